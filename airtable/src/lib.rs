@@ -14,7 +14,14 @@
  *     let airtable = Airtable::new_from_env();
  *
  *     // Get the current records from a table.
- *     let mut records: Vec<Record<SomeFormat>> = airtable.list_records("Table Name", "Grid view", vec!["the", "fields", "you", "want", "to", "return"]).await.unwrap();
+ *     let mut records: Vec<Record<SomeFormat>> = airtable
+ *         .list_records(
+ *             "Table Name",
+ *             "Grid view",
+ *             vec!["the", "fields", "you", "want", "to", "return"],
+ *         )
+ *         .await
+ *         .unwrap();
  *
  *     // Iterate over the records.
  *     for (i, record) in records.clone().iter().enumerate() {
@@ -37,7 +44,7 @@ use std::sync::Arc;
 
 use chrono::offset::Utc;
 use chrono::DateTime;
-use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest::{header, Client, Method, Request, Response, StatusCode, Url};
 use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
 use serde::de::{MapAccess, SeqAccess, Visitor};
@@ -51,6 +58,8 @@ pub struct Airtable {
     key: String,
     base_id: String,
     enterprise_account_id: String,
+    endpoint: String,
+    on_request: Option<fn(&Request)>,
 
     client: Arc<Client>,
 }
@@ -78,6 +87,8 @@ impl Airtable {
                 key: key.to_string(),
                 base_id: base_id.to_string(),
                 enterprise_account_id: enterprise_account_id.to_string(),
+                endpoint: ENDPOINT.to_string(),
+                on_request: None,
 
                 client: Arc::new(c),
             },
@@ -96,6 +107,23 @@ impl Airtable {
         Airtable::new(api_key_from_env(), base_id, enterprise_account_id)
     }
 
+    /// Send requests to a different endpoint than the Airtable API, ie. a mock server
+    /// for tests. The endpoint must end with a `/`, like `http://127.0.0.1:8080/v0/`.
+    pub fn with_endpoint<E>(mut self, endpoint: E) -> Self
+    where
+        E: ToString,
+    {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Call a function with every request before it is sent, ie. to count the
+    /// requests we make. A paginated list calls it once for each page.
+    pub fn with_request_hook(mut self, hook: fn(&Request)) -> Self {
+        self.on_request = Some(hook);
+        self
+    }
+
     /// Get the currently set API key.
     pub fn get_key(&self) -> &str {
         &self.key
@@ -105,7 +133,7 @@ impl Airtable {
     where
         B: Serialize,
     {
-        let base = Url::parse(ENDPOINT).unwrap();
+        let base = Url::parse(&self.endpoint).unwrap();
         let url = base.join(&(self.base_id.to_string() + "/" + &path)).unwrap();
 
         let bt = format!("Bearer {}", self.key);
@@ -134,17 +162,36 @@ impl Airtable {
         rb.build().unwrap()
     }
 
+    /// Send a request, calling the request hook first if there is one.
+    async fn execute(&self, request: Request) -> Result<Response, reqwest::Error> {
+        if let Some(hook) = self.on_request {
+            hook(&request);
+        }
+
+        self.client.execute(request).await
+    }
+
     /// List records in a table for a particular view.
     pub async fn list_records<T: DeserializeOwned>(&self, table: &str, view: &str, fields: Vec<&str>) -> Result<Vec<Record<T>>, APIError> {
+        self.list_records_with_formula(table, view, fields, "").await
+    }
+
+    /// List the records in a table for a particular view that match a formula,
+    /// ie. `IS_AFTER(LAST_MODIFIED_TIME(), '2021-05-04T09:00:00Z')`. An empty formula
+    /// lists every record.
+    pub async fn list_records_with_formula<T: DeserializeOwned>(&self, table: &str, view: &str, fields: Vec<&str>, formula: &str) -> Result<Vec<Record<T>>, APIError> {
         let mut params = vec![("pageSize", "100".to_string()), ("view", view.to_string())];
         for field in fields {
             params.push(("fields", field.to_string()));
         }
+        if !formula.is_empty() {
+            params.push(("filterByFormula", formula.to_string()));
+        }
 
         // Build the request.
-        let mut request = self.request(Method::GET, table.to_string(), (), Some(params));
+        let mut request = self.request(Method::GET, table.to_string(), (), Some(params.clone()));
 
-        let mut resp = self.client.execute(request).await.unwrap();
+        let mut resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -165,14 +212,12 @@ impl Airtable {
         // Paginate if we should.
         // TODO: make this more DRY
         while !offset.is_empty() {
-            request = self.request(
-                Method::GET,
-                table.to_string(),
-                (),
-                Some(vec![("pageSize", "100".to_string()), ("view", view.to_string()), ("offset", offset)]),
-            );
-
-            resp = self.client.execute(request).await.unwrap();
+            // Keep the fields and formula for every page.
+            let mut page_params = params.clone();
+            page_params.push(("offset", offset));
+            request = self.request(Method::GET, table.to_string(), (), Some(page_params));
+
+            resp = self.execute(request).await.unwrap();
             match resp.status() {
                 StatusCode::OK => (),
                 s => {
@@ -199,7 +244,7 @@ impl Airtable {
         // Build the request.
         let request = self.request(Method::GET, format!("{}/{}", table, record_id), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -221,7 +266,7 @@ impl Airtable {
         // Build the request.
         let request = self.request(Method::DELETE, table.to_string(), (), Some(vec![("records[]", record_id.to_string())]));
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -252,7 +297,7 @@ impl Airtable {
             None,
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -286,7 +331,7 @@ impl Airtable {
             None,
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -327,7 +372,7 @@ impl Airtable {
             Some(vec![("state", "provisioned".to_string())]),
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -365,7 +410,7 @@ impl Airtable {
             Some(vec![("email", email.to_string())]),
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -0,0 +1,4 @@
+ALTER TABLE software_vendors DROP COLUMN security_questionnaire_status;
+ALTER TABLE software_vendors DROP COLUMN data_classification;
+ALTER TABLE software_vendors DROP COLUMN dpa_signed;
+ALTER TABLE software_vendors DROP COLUMN security_review_date;
@@ -0,0 +1,8 @@
+-- The airtable_record_id column has to remain the last column in the table
+-- for the db macro, so we drop it and add it back after the new columns.
+ALTER TABLE software_vendors DROP COLUMN airtable_record_id;
+ALTER TABLE software_vendors ADD COLUMN security_questionnaire_status VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE software_vendors ADD COLUMN data_classification VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE software_vendors ADD COLUMN dpa_signed BOOLEAN NOT NULL DEFAULT 'f';
+ALTER TABLE software_vendors ADD COLUMN security_review_date DATE NOT NULL DEFAULT '1970-01-01';
+ALTER TABLE software_vendors ADD COLUMN airtable_record_id VARCHAR NOT NULL DEFAULT '';
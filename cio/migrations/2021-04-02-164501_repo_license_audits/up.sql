@@ -0,0 +1,11 @@
+CREATE TABLE repo_license_audits (
+    id SERIAL PRIMARY KEY,
+    repo VARCHAR NOT NULL UNIQUE,
+    manifests TEXT [] NOT NULL,
+    dependencies_count INTEGER NOT NULL DEFAULT 0,
+    licenses TEXT [] NOT NULL,
+    copyleft_dependencies TEXT [] NOT NULL,
+    unknown_dependencies TEXT [] NOT NULL,
+    audited_at TIMESTAMPTZ NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+)
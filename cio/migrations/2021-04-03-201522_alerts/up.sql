@@ -0,0 +1,16 @@
+CREATE TABLE alerts (
+    id SERIAL PRIMARY KEY,
+    alert_id VARCHAR NOT NULL UNIQUE,
+    repo VARCHAR NOT NULL,
+    source VARCHAR NOT NULL,
+    number INTEGER NOT NULL DEFAULT 0,
+    state VARCHAR NOT NULL,
+    severity VARCHAR NOT NULL,
+    package VARCHAR NOT NULL,
+    summary VARCHAR NOT NULL,
+    html_url VARCHAR NOT NULL,
+    owner_team VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+)
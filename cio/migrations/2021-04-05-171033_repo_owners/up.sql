@@ -0,0 +1,7 @@
+CREATE TABLE repo_owners (
+    id SERIAL PRIMARY KEY,
+    repo VARCHAR NOT NULL UNIQUE,
+    team VARCHAR NOT NULL,
+    owners TEXT [] NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+)
@@ -0,0 +1,13 @@
+CREATE TABLE github_actions_usages (
+    id SERIAL PRIMARY KEY,
+    repo VARCHAR NOT NULL,
+    date DATE NOT NULL,
+    runs INTEGER NOT NULL DEFAULT 0,
+    ubuntu_minutes INTEGER NOT NULL DEFAULT 0,
+    macos_minutes INTEGER NOT NULL DEFAULT 0,
+    windows_minutes INTEGER NOT NULL DEFAULT 0,
+    billable_minutes INTEGER NOT NULL DEFAULT 0,
+    cost REAL NOT NULL DEFAULT 0,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    UNIQUE (repo, date)
+)
@@ -0,0 +1 @@
+DROP TABLE cloud_costs
@@ -0,0 +1,12 @@
+CREATE TABLE cloud_costs (
+    id SERIAL PRIMARY KEY,
+    cost_id VARCHAR NOT NULL UNIQUE,
+    provider VARCHAR NOT NULL,
+    month DATE NOT NULL,
+    project VARCHAR NOT NULL,
+    service VARCHAR NOT NULL,
+    owner_group VARCHAR NOT NULL,
+    cost REAL NOT NULL DEFAULT 0,
+    currency VARCHAR NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT ''
+)
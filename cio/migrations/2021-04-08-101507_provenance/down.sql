@@ -0,0 +1,27 @@
+ALTER TABLE alerts DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE applicant_interviews DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE applicant_reviewers DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE applicants DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE auth_users DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE auth_user_logins DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE buildings DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE certificates DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE cloud_costs DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE conference_rooms DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE github_actions_usages DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE github_repos DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE groups DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE inbound_shipments DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE journal_club_meetings DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE journal_club_papers DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE links DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE mailing_list_subscribers DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE page_views DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE recorded_meetings DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE repo_license_audits DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE repo_owners DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE rfds DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE software_vendors DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+ALTER TABLE users DROP COLUMN source, DROP COLUMN last_synced_from, DROP COLUMN last_synced_at;
+
+ALTER TABLE alerts RENAME COLUMN tool TO source;
@@ -0,0 +1,27 @@
+ALTER TABLE alerts RENAME COLUMN source TO tool;
+
+ALTER TABLE alerts ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE applicant_interviews ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE applicant_reviewers ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE applicants ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE auth_users ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE auth_user_logins ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE buildings ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE certificates ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE cloud_costs ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE conference_rooms ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE github_actions_usages ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE github_repos ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE groups ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE inbound_shipments ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE journal_club_meetings ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE journal_club_papers ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE links ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE mailing_list_subscribers ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE page_views ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE recorded_meetings ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE repo_license_audits ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE repo_owners ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE rfds ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE software_vendors ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN source VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '', ADD COLUMN last_synced_at TIMESTAMPTZ;
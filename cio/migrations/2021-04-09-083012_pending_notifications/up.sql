@@ -0,0 +1,7 @@
+CREATE TABLE pending_notifications (
+    id SERIAL PRIMARY KEY,
+    channel VARCHAR NOT NULL,
+    event VARCHAR NOT NULL,
+    message JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
@@ -0,0 +1,6 @@
+CREATE TABLE slack_user_ids (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL UNIQUE,
+    slack_user_id VARCHAR NOT NULL DEFAULT '',
+    resolved_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
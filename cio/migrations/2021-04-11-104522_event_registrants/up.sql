@@ -0,0 +1,21 @@
+CREATE TABLE event_registrants (
+    id SERIAL PRIMARY KEY,
+    registrant_id VARCHAR NOT NULL UNIQUE,
+    platform VARCHAR NOT NULL,
+    event_id VARCHAR NOT NULL,
+    event_name VARCHAR NOT NULL,
+    event_start TIMESTAMPTZ,
+    event_url VARCHAR NOT NULL DEFAULT '',
+    email VARCHAR NOT NULL,
+    first_name VARCHAR NOT NULL DEFAULT '',
+    last_name VARCHAR NOT NULL DEFAULT '',
+    name VARCHAR NOT NULL DEFAULT '',
+    company VARCHAR NOT NULL DEFAULT '',
+    registered_at TIMESTAMPTZ NOT NULL,
+    existing_contact BOOLEAN NOT NULL DEFAULT false,
+    link_to_mailing_list_signups TEXT [] NOT NULL DEFAULT '{}',
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
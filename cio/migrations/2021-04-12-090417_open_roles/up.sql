@@ -0,0 +1,15 @@
+CREATE TABLE open_roles (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    team VARCHAR NOT NULL DEFAULT '',
+    location VARCHAR NOT NULL DEFAULT '',
+    description TEXT NOT NULL DEFAULT '',
+    job_description_url VARCHAR NOT NULL DEFAULT '',
+    application_form_url VARCHAR NOT NULL DEFAULT '',
+    sheet_id VARCHAR NOT NULL DEFAULT '',
+    open BOOLEAN NOT NULL DEFAULT true,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
@@ -0,0 +1,7 @@
+CREATE TABLE webhook_deliveries (
+    id SERIAL PRIMARY KEY,
+    source VARCHAR NOT NULL,
+    delivery_id VARCHAR NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (source, delivery_id)
+)
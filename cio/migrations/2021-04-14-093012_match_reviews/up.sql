@@ -0,0 +1,13 @@
+CREATE TABLE match_reviews (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR NOT NULL,
+    name VARCHAR NOT NULL,
+    candidate VARCHAR NOT NULL,
+    score REAL NOT NULL DEFAULT 0,
+    status VARCHAR NOT NULL DEFAULT 'pending',
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ,
+    UNIQUE (kind, name, candidate)
+)
@@ -0,0 +1,22 @@
+-- The airtable_record_id and provenance columns have to remain the last columns in
+-- the table for the db macro, so we move them after the new column, keeping their data.
+ALTER TABLE users RENAME COLUMN airtable_record_id TO old_airtable_record_id;
+ALTER TABLE users RENAME COLUMN source TO old_source;
+ALTER TABLE users RENAME COLUMN last_synced_from TO old_last_synced_from;
+ALTER TABLE users RENAME COLUMN last_synced_at TO old_last_synced_at;
+ALTER TABLE users
+    ADD COLUMN timezone VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN source VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN last_synced_at TIMESTAMPTZ;
+UPDATE users SET
+    airtable_record_id = old_airtable_record_id,
+    source = old_source,
+    last_synced_from = old_last_synced_from,
+    last_synced_at = old_last_synced_at;
+ALTER TABLE users
+    DROP COLUMN old_airtable_record_id,
+    DROP COLUMN old_source,
+    DROP COLUMN old_last_synced_from,
+    DROP COLUMN old_last_synced_at;
@@ -0,0 +1,7 @@
+CREATE TABLE scheduled_messages (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL,
+    message JSONB NOT NULL,
+    deliver_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
@@ -0,0 +1,15 @@
+CREATE TABLE bucket_audits (
+    id SERIAL PRIMARY KEY,
+    bucket VARCHAR NOT NULL UNIQUE,
+    project VARCHAR NOT NULL DEFAULT '',
+    public BOOLEAN NOT NULL DEFAULT 'f',
+    bindings TEXT [] NOT NULL,
+    lifecycle_delete_age INTEGER NOT NULL DEFAULT 0,
+    retention_days INTEGER NOT NULL DEFAULT 0,
+    findings TEXT [] NOT NULL,
+    audited_at TIMESTAMPTZ NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
@@ -0,0 +1,12 @@
+CREATE TABLE profiles (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL UNIQUE,
+    name VARCHAR NOT NULL DEFAULT '',
+    skills TEXT [] NOT NULL,
+    interests TEXT [] NOT NULL,
+    available_to_mentor BOOLEAN NOT NULL DEFAULT 'f',
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
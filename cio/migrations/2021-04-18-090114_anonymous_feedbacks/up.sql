@@ -0,0 +1,12 @@
+CREATE TABLE anonymous_feedbacks (
+    id SERIAL PRIMARY KEY,
+    topic VARCHAR NOT NULL DEFAULT '',
+    feedback TEXT NOT NULL DEFAULT '',
+    sender_hash VARCHAR NOT NULL DEFAULT '',
+    submitted_at TIMESTAMPTZ NOT NULL,
+    digested BOOLEAN NOT NULL DEFAULT 'f',
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
@@ -0,0 +1 @@
+DROP TABLE engineering_metrics
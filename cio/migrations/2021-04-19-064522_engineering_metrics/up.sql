@@ -0,0 +1,15 @@
+CREATE TABLE engineering_metrics (
+    id SERIAL PRIMARY KEY,
+    team VARCHAR NOT NULL,
+    week DATE NOT NULL,
+    prs_opened INTEGER NOT NULL DEFAULT 0,
+    prs_merged INTEGER NOT NULL DEFAULT 0,
+    median_review_hours REAL NOT NULL DEFAULT 0,
+    median_merge_hours REAL NOT NULL DEFAULT 0,
+    deploys INTEGER NOT NULL DEFAULT 0,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ,
+    UNIQUE (team, week)
+)
@@ -0,0 +1,6 @@
+CREATE TABLE rfd_references (
+    id SERIAL PRIMARY KEY,
+    from_number INTEGER NOT NULL,
+    to_number INTEGER NOT NULL,
+    UNIQUE (from_number, to_number)
+)
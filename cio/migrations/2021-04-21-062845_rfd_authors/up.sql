@@ -0,0 +1,11 @@
+CREATE TABLE rfd_authors (
+    id SERIAL PRIMARY KEY,
+    rfd_number INTEGER NOT NULL,
+    name VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    username VARCHAR NOT NULL,
+    github VARCHAR NOT NULL,
+    in_frontmatter BOOLEAN NOT NULL DEFAULT false,
+    commits INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (rfd_number, name)
+)
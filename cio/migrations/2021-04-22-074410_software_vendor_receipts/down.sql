@@ -0,0 +1 @@
+ALTER TABLE software_vendors DROP COLUMN receipts
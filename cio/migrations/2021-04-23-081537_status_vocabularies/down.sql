@@ -0,0 +1,4 @@
+-- The statuses we mapped to an option can not be told apart from the ones that were
+-- already that option, and every option was a valid value before too, so there is
+-- nothing to undo.
+SELECT 1;
@@ -0,0 +1,30 @@
+-- Statuses are stored as one of the options of their controlled vocabulary now, so
+-- map every other value to an option, the same way `Status::from_str` reads the raw
+-- statuses in the applications spreadsheet, and give the ones that were never set the
+-- default.
+UPDATE applicants SET status = CASE
+        WHEN lower(status) LIKE '%next steps%' THEN 'Next steps'
+        WHEN lower(status) LIKE '%deferred%' THEN 'Deferred'
+        WHEN lower(status) LIKE '%declined%' THEN 'Declined'
+        WHEN lower(status) LIKE '%hired%' THEN 'Hired'
+        WHEN lower(status) LIKE '%interviewing%' THEN 'Interviewing'
+        WHEN lower(status) LIKE '%onboarding%' THEN 'Onboarding'
+        WHEN lower(status) LIKE '%giving offer%' THEN 'Giving offer'
+        WHEN lower(status) LIKE '%contractor%' OR lower(status) LIKE '%consulting%' THEN 'Contractor'
+        WHEN lower(status) LIKE '%keeping warm%' THEN 'Keeping warm'
+        ELSE 'Needs to be triaged'
+    END
+    WHERE status NOT IN ('Hired', 'Onboarding', 'Giving offer', 'Interviewing', 'Deferred', 'Next steps', 'Declined', 'Needs to be triaged', 'Contractor', 'Keeping warm');
+
+UPDATE software_vendors SET status = CASE
+        WHEN lower(status) LIKE '%cancel%' THEN 'Cancelled'
+        WHEN lower(status) LIKE '%trial%' THEN 'Trial'
+        ELSE 'Active'
+    END
+    WHERE status NOT IN ('Active', 'Trial', 'Cancelled');
+
+UPDATE software_vendors SET security_questionnaire_status = CASE
+        WHEN lower(trim(security_questionnaire_status)) IN ('sent', 'received', 'approved') THEN lower(trim(security_questionnaire_status))
+        ELSE 'not started'
+    END
+    WHERE security_questionnaire_status NOT IN ('not started', 'sent', 'received', 'approved');
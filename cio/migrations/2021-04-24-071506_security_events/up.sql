@@ -0,0 +1,21 @@
+CREATE TABLE security_events (
+    id SERIAL PRIMARY KEY,
+    event_id VARCHAR NOT NULL UNIQUE,
+    provider VARCHAR NOT NULL,
+    category VARCHAR NOT NULL,
+    event_type VARCHAR NOT NULL,
+    name VARCHAR NOT NULL DEFAULT '',
+    actor_email VARCHAR NOT NULL DEFAULT '',
+    target VARCHAR NOT NULL DEFAULT '',
+    ip_address VARCHAR NOT NULL DEFAULT '',
+    outcome VARCHAR NOT NULL DEFAULT '',
+    details TEXT [] NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+);
+
+CREATE INDEX security_events_actor_email_occurred_at ON security_events (actor_email, occurred_at);
+CREATE INDEX security_events_target_occurred_at ON security_events (target, occurred_at);
@@ -0,0 +1,18 @@
+CREATE TABLE security_postures (
+    id SERIAL PRIMARY KEY,
+    username VARCHAR NOT NULL UNIQUE,
+    email VARCHAR NOT NULL,
+    has_okta BOOLEAN NOT NULL DEFAULT 'f',
+    okta_factors TEXT [] NOT NULL,
+    password_changed_at TIMESTAMPTZ,
+    has_gsuite BOOLEAN NOT NULL DEFAULT 'f',
+    gsuite_2sv_enrolled BOOLEAN NOT NULL DEFAULT 'f',
+    admin_roles TEXT [] NOT NULL,
+    score INTEGER NOT NULL DEFAULT 0,
+    violations TEXT [] NOT NULL,
+    checked_at TIMESTAMPTZ NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
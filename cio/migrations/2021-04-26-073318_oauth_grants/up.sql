@@ -0,0 +1,15 @@
+CREATE TABLE oauth_grants (
+    id SERIAL PRIMARY KEY,
+    grant_id VARCHAR NOT NULL UNIQUE,
+    email VARCHAR NOT NULL,
+    client_id VARCHAR NOT NULL,
+    app_name VARCHAR NOT NULL DEFAULT '',
+    scopes TEXT [] NOT NULL,
+    high_risk_scopes TEXT [] NOT NULL,
+    native_app BOOLEAN NOT NULL DEFAULT 'f',
+    first_seen_at TIMESTAMPTZ NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
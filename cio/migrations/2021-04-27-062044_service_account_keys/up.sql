@@ -0,0 +1,18 @@
+CREATE TABLE service_account_keys (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    service_account VARCHAR NOT NULL,
+    key_id VARCHAR NOT NULL DEFAULT '',
+    key_created_at TIMESTAMPTZ,
+    age_days INTEGER NOT NULL DEFAULT 0,
+    max_age_days INTEGER NOT NULL DEFAULT 0,
+    secret VARCHAR NOT NULL DEFAULT '',
+    rotated_at TIMESTAMPTZ,
+    status VARCHAR NOT NULL DEFAULT '',
+    error VARCHAR NOT NULL DEFAULT '',
+    checked_at TIMESTAMPTZ NOT NULL,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
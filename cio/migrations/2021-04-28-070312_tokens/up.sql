@@ -0,0 +1,15 @@
+CREATE TABLE tokens (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    service VARCHAR NOT NULL,
+    owner VARCHAR NOT NULL,
+    env_var VARCHAR NOT NULL DEFAULT '',
+    scopes TEXT [] NOT NULL,
+    created DATE,
+    expires DATE,
+    last_reminded_at TIMESTAMPTZ,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
@@ -0,0 +1 @@
+DROP TABLE tailscale_devices
@@ -0,0 +1,19 @@
+CREATE TABLE tailscale_devices (
+    id SERIAL PRIMARY KEY,
+    device_id VARCHAR NOT NULL UNIQUE,
+    name VARCHAR NOT NULL,
+    hostname VARCHAR NOT NULL DEFAULT '',
+    os VARCHAR NOT NULL DEFAULT '',
+    login VARCHAR NOT NULL DEFAULT '',
+    username VARCHAR NOT NULL DEFAULT '',
+    tags TEXT [] NOT NULL,
+    client_version VARCHAR NOT NULL DEFAULT '',
+    status VARCHAR NOT NULL DEFAULT '',
+    created TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    expires TIMESTAMPTZ,
+    airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    source VARCHAR NOT NULL DEFAULT '',
+    last_synced_from VARCHAR NOT NULL DEFAULT '',
+    last_synced_at TIMESTAMPTZ
+)
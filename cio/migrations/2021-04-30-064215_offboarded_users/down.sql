@@ -0,0 +1 @@
+DROP TABLE offboarded_users
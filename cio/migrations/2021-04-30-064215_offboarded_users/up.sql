@@ -0,0 +1,8 @@
+CREATE TABLE offboarded_users (
+    id SERIAL PRIMARY KEY,
+    username VARCHAR NOT NULL UNIQUE,
+    email VARCHAR NOT NULL,
+    first_name VARCHAR NOT NULL DEFAULT '',
+    last_name VARCHAR NOT NULL DEFAULT '',
+    offboarded_at TIMESTAMPTZ NOT NULL
+)
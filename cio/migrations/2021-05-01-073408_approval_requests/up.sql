@@ -0,0 +1,15 @@
+CREATE TABLE approval_requests (
+    id SERIAL PRIMARY KEY,
+    operation VARCHAR NOT NULL,
+    subject VARCHAR NOT NULL,
+    description VARCHAR NOT NULL DEFAULT '',
+    approvers TEXT [] NOT NULL,
+    required INTEGER NOT NULL,
+    ordered BOOLEAN NOT NULL DEFAULT false,
+    approved_by TEXT [] NOT NULL,
+    rejected_by VARCHAR NOT NULL DEFAULT '',
+    status VARCHAR NOT NULL,
+    requested_at TIMESTAMPTZ NOT NULL,
+    decided_at TIMESTAMPTZ,
+    UNIQUE (operation, subject)
+)
@@ -0,0 +1,9 @@
+CREATE TABLE maintenance_windows (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    reason VARCHAR NOT NULL DEFAULT '',
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    jobs TEXT [] NOT NULL,
+    keep_notifications BOOLEAN NOT NULL DEFAULT false
+)
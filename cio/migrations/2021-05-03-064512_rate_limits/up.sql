@@ -0,0 +1,11 @@
+CREATE TABLE rate_limits (
+    id SERIAL PRIMARY KEY,
+    service VARCHAR NOT NULL UNIQUE,
+    request_limit INTEGER NOT NULL DEFAULT 0,
+    remaining INTEGER NOT NULL DEFAULT 0,
+    resets_at TIMESTAMPTZ,
+    throttled INTEGER NOT NULL DEFAULT 0,
+    last_throttled_at TIMESTAMPTZ,
+    retry_after_secs INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL
+)
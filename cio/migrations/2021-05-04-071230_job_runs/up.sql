@@ -0,0 +1,15 @@
+CREATE TABLE job_runs (
+    id SERIAL PRIMARY KEY,
+    job VARCHAR NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    duration_ms BIGINT NOT NULL DEFAULT 0,
+    succeeded BOOLEAN NOT NULL DEFAULT 't',
+    error VARCHAR NOT NULL DEFAULT '',
+    airtable_calls INTEGER NOT NULL DEFAULT 0,
+    github_calls INTEGER NOT NULL DEFAULT 0,
+    gsuite_calls INTEGER NOT NULL DEFAULT 0,
+    slack_calls INTEGER NOT NULL DEFAULT 0,
+    other_calls INTEGER NOT NULL DEFAULT 0,
+    db_queries INTEGER NOT NULL DEFAULT 0,
+    rows_written INTEGER NOT NULL DEFAULT 0
+)
@@ -0,0 +1,38 @@
+ALTER TABLE alerts DROP COLUMN extra;
+ALTER TABLE anonymous_feedbacks DROP COLUMN extra;
+ALTER TABLE applicant_interviews DROP COLUMN extra;
+ALTER TABLE applicant_reviewers DROP COLUMN extra;
+ALTER TABLE applicants DROP COLUMN extra;
+ALTER TABLE auth_user_logins DROP COLUMN extra;
+ALTER TABLE auth_users DROP COLUMN extra;
+ALTER TABLE bucket_audits DROP COLUMN extra;
+ALTER TABLE buildings DROP COLUMN extra;
+ALTER TABLE certificates DROP COLUMN extra;
+ALTER TABLE cloud_costs DROP COLUMN extra;
+ALTER TABLE conference_rooms DROP COLUMN extra;
+ALTER TABLE engineering_metrics DROP COLUMN extra;
+ALTER TABLE event_registrants DROP COLUMN extra;
+ALTER TABLE github_actions_usages DROP COLUMN extra;
+ALTER TABLE github_repos DROP COLUMN extra;
+ALTER TABLE groups DROP COLUMN extra;
+ALTER TABLE inbound_shipments DROP COLUMN extra;
+ALTER TABLE journal_club_meetings DROP COLUMN extra;
+ALTER TABLE journal_club_papers DROP COLUMN extra;
+ALTER TABLE links DROP COLUMN extra;
+ALTER TABLE mailing_list_subscribers DROP COLUMN extra;
+ALTER TABLE match_reviews DROP COLUMN extra;
+ALTER TABLE oauth_grants DROP COLUMN extra;
+ALTER TABLE open_roles DROP COLUMN extra;
+ALTER TABLE page_views DROP COLUMN extra;
+ALTER TABLE profiles DROP COLUMN extra;
+ALTER TABLE recorded_meetings DROP COLUMN extra;
+ALTER TABLE repo_license_audits DROP COLUMN extra;
+ALTER TABLE repo_owners DROP COLUMN extra;
+ALTER TABLE rfds DROP COLUMN extra;
+ALTER TABLE security_events DROP COLUMN extra;
+ALTER TABLE security_postures DROP COLUMN extra;
+ALTER TABLE service_account_keys DROP COLUMN extra;
+ALTER TABLE software_vendors DROP COLUMN extra;
+ALTER TABLE tailscale_devices DROP COLUMN extra;
+ALTER TABLE tokens DROP COLUMN extra;
+ALTER TABLE users DROP COLUMN extra;
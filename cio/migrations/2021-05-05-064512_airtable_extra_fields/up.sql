@@ -0,0 +1,38 @@
+ALTER TABLE alerts ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE anonymous_feedbacks ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE applicant_interviews ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE applicant_reviewers ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE applicants ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE auth_user_logins ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE auth_users ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE bucket_audits ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE buildings ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE certificates ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE cloud_costs ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE conference_rooms ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE engineering_metrics ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE event_registrants ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE github_actions_usages ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE github_repos ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE groups ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE inbound_shipments ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE journal_club_meetings ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE journal_club_papers ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE links ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE mailing_list_subscribers ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE match_reviews ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE oauth_grants ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE open_roles ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE page_views ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE profiles ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE recorded_meetings ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE repo_license_audits ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE repo_owners ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE rfds ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE security_events ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE security_postures ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE service_account_keys ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE software_vendors ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE tailscale_devices ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE tokens ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE users ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1 @@
+DROP TABLE welcome_messages
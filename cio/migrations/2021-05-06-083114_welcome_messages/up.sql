@@ -0,0 +1,11 @@
+CREATE TABLE welcome_messages (
+    id SERIAL PRIMARY KEY,
+    slack_user_id VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    first_name VARCHAR NOT NULL,
+    step VARCHAR NOT NULL,
+    deliver_at TIMESTAMPTZ NOT NULL,
+    sent_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (slack_user_id, step)
+)
@@ -0,0 +1 @@
+DROP TABLE docusign_envelopes
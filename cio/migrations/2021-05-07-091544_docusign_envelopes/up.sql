@@ -0,0 +1,15 @@
+CREATE TABLE docusign_envelopes (
+    id SERIAL PRIMARY KEY,
+    envelope_id VARCHAR NOT NULL UNIQUE,
+    template VARCHAR NOT NULL,
+    applicant_id INTEGER,
+    software_vendor_id INTEGER,
+    signer_name VARCHAR NOT NULL,
+    signer_email VARCHAR NOT NULL,
+    status VARCHAR NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL,
+    status_changed_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ,
+    reminded_at TIMESTAMPTZ,
+    reminders INTEGER NOT NULL DEFAULT 0
+)
@@ -0,0 +1,2 @@
+DROP TABLE background_checks;
+ALTER TABLE open_roles DROP COLUMN hiring_manager;
@@ -0,0 +1,41 @@
+CREATE TABLE background_checks (
+    id SERIAL PRIMARY KEY,
+    applicant_id INTEGER NOT NULL,
+    email VARCHAR NOT NULL,
+    candidate_id VARCHAR NOT NULL,
+    package VARCHAR NOT NULL,
+    report_id VARCHAR NOT NULL DEFAULT '',
+    status VARCHAR NOT NULL,
+    result VARCHAR NOT NULL DEFAULT '',
+    adjudication VARCHAR NOT NULL DEFAULT '',
+    requested_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ,
+    notified_at TIMESTAMPTZ,
+    UNIQUE (applicant_id, package)
+);
+-- The airtable_record_id, provenance, and extra columns have to remain the last columns
+-- in the table for the db macro, so we move them after the new column, keeping their data.
+ALTER TABLE open_roles RENAME COLUMN airtable_record_id TO old_airtable_record_id;
+ALTER TABLE open_roles RENAME COLUMN source TO old_source;
+ALTER TABLE open_roles RENAME COLUMN last_synced_from TO old_last_synced_from;
+ALTER TABLE open_roles RENAME COLUMN last_synced_at TO old_last_synced_at;
+ALTER TABLE open_roles RENAME COLUMN extra TO old_extra;
+ALTER TABLE open_roles
+    ADD COLUMN hiring_manager VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN source VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN last_synced_at TIMESTAMPTZ,
+    ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+UPDATE open_roles SET
+    airtable_record_id = old_airtable_record_id,
+    source = old_source,
+    last_synced_from = old_last_synced_from,
+    last_synced_at = old_last_synced_at,
+    extra = old_extra;
+ALTER TABLE open_roles
+    DROP COLUMN old_airtable_record_id,
+    DROP COLUMN old_source,
+    DROP COLUMN old_last_synced_from,
+    DROP COLUMN old_last_synced_at,
+    DROP COLUMN old_extra;
@@ -0,0 +1,2 @@
+DROP TABLE referrals;
+ALTER TABLE applicants DROP COLUMN referred_by;
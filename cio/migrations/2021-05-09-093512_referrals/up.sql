@@ -0,0 +1,38 @@
+CREATE TABLE referrals (
+    id SERIAL PRIMARY KEY,
+    applicant_id INTEGER NOT NULL,
+    email VARCHAR NOT NULL UNIQUE,
+    referrer VARCHAR NOT NULL,
+    source VARCHAR NOT NULL,
+    referred_at TIMESTAMPTZ NOT NULL,
+    applied_directly_at TIMESTAMPTZ,
+    hired_at TIMESTAMPTZ,
+    bonus REAL NOT NULL DEFAULT 0,
+    bonus_paid_at TIMESTAMPTZ
+);
+-- The airtable_record_id, provenance, and extra columns have to remain the last columns
+-- in the table for the db macro, so we move them after the new column, keeping their data.
+ALTER TABLE applicants RENAME COLUMN airtable_record_id TO old_airtable_record_id;
+ALTER TABLE applicants RENAME COLUMN source TO old_source;
+ALTER TABLE applicants RENAME COLUMN last_synced_from TO old_last_synced_from;
+ALTER TABLE applicants RENAME COLUMN last_synced_at TO old_last_synced_at;
+ALTER TABLE applicants RENAME COLUMN extra TO old_extra;
+ALTER TABLE applicants
+    ADD COLUMN referred_by VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN source VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN last_synced_at TIMESTAMPTZ,
+    ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+UPDATE applicants SET
+    airtable_record_id = old_airtable_record_id,
+    source = old_source,
+    last_synced_from = old_last_synced_from,
+    last_synced_at = old_last_synced_at,
+    extra = old_extra;
+ALTER TABLE applicants
+    DROP COLUMN old_airtable_record_id,
+    DROP COLUMN old_source,
+    DROP COLUMN old_last_synced_from,
+    DROP COLUMN old_last_synced_at,
+    DROP COLUMN old_extra;
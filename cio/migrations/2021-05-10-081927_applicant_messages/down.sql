@@ -0,0 +1,2 @@
+DROP TABLE applicant_messages;
+DROP TABLE applicant_threads;
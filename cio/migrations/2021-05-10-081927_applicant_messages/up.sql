@@ -0,0 +1,25 @@
+CREATE TABLE applicant_threads (
+    id SERIAL PRIMARY KEY,
+    applicant_id INTEGER NOT NULL,
+    mailbox VARCHAR NOT NULL,
+    thread_id VARCHAR NOT NULL,
+    linked_by VARCHAR NOT NULL,
+    linked_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (mailbox, thread_id)
+);
+CREATE TABLE applicant_messages (
+    id SERIAL PRIMARY KEY,
+    applicant_id INTEGER NOT NULL,
+    mailbox VARCHAR NOT NULL,
+    thread_id VARCHAR NOT NULL,
+    gmail_id VARCHAR NOT NULL,
+    message_id VARCHAR NOT NULL DEFAULT '',
+    from_email VARCHAR NOT NULL,
+    recipients TEXT[] NOT NULL DEFAULT '{}',
+    subject VARCHAR NOT NULL DEFAULT '',
+    snippet VARCHAR NOT NULL DEFAULT '',
+    direction VARCHAR NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (mailbox, gmail_id)
+);
+CREATE INDEX applicant_messages_applicant_id ON applicant_messages (applicant_id);
@@ -0,0 +1,2 @@
+DROP TABLE applicant_emails;
+DROP TABLE applicant_stage_changes;
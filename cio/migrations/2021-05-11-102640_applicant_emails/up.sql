@@ -0,0 +1,22 @@
+CREATE TABLE applicant_stage_changes (
+    id SERIAL PRIMARY KEY,
+    applicant_id INTEGER NOT NULL,
+    from_status VARCHAR NOT NULL,
+    to_status VARCHAR NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL,
+    processed_at TIMESTAMPTZ
+);
+CREATE TABLE applicant_emails (
+    id SERIAL PRIMARY KEY,
+    applicant_id INTEGER NOT NULL,
+    template VARCHAR NOT NULL,
+    to_email VARCHAR NOT NULL,
+    subject VARCHAR NOT NULL DEFAULT '',
+    status VARCHAR NOT NULL,
+    gmail_id VARCHAR NOT NULL DEFAULT '',
+    thread_id VARCHAR NOT NULL DEFAULT '',
+    error VARCHAR NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL,
+    sent_at TIMESTAMPTZ,
+    UNIQUE (applicant_id, template)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE applicants DROP COLUMN take_home_score;
+ALTER TABLE applicants DROP COLUMN take_home_repo;
+DROP TABLE take_home_grades;
+DROP TABLE take_home_assignments;
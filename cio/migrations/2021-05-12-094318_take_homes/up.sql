@@ -0,0 +1,48 @@
+CREATE TABLE take_home_assignments (
+    id SERIAL PRIMARY KEY,
+    applicant_id INTEGER NOT NULL,
+    assignment VARCHAR NOT NULL,
+    repo VARCHAR NOT NULL,
+    github VARCHAR NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL,
+    due_at TIMESTAMPTZ NOT NULL,
+    submitted_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    UNIQUE (applicant_id, assignment)
+);
+CREATE TABLE take_home_grades (
+    id SERIAL PRIMARY KEY,
+    assignment_id INTEGER NOT NULL,
+    grader VARCHAR NOT NULL,
+    score INTEGER NOT NULL,
+    notes TEXT NOT NULL DEFAULT '',
+    graded_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (assignment_id, grader)
+);
+-- The airtable_record_id, provenance, and extra columns have to remain the last columns
+-- in the table for the db macro, so we move them after the new columns, keeping their data.
+ALTER TABLE applicants RENAME COLUMN airtable_record_id TO old_airtable_record_id;
+ALTER TABLE applicants RENAME COLUMN source TO old_source;
+ALTER TABLE applicants RENAME COLUMN last_synced_from TO old_last_synced_from;
+ALTER TABLE applicants RENAME COLUMN last_synced_at TO old_last_synced_at;
+ALTER TABLE applicants RENAME COLUMN extra TO old_extra;
+ALTER TABLE applicants
+    ADD COLUMN take_home_repo VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN take_home_score REAL NOT NULL DEFAULT 0,
+    ADD COLUMN airtable_record_id VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN source VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN last_synced_from VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN last_synced_at TIMESTAMPTZ,
+    ADD COLUMN extra JSONB NOT NULL DEFAULT '{}';
+UPDATE applicants SET
+    airtable_record_id = old_airtable_record_id,
+    source = old_source,
+    last_synced_from = old_last_synced_from,
+    last_synced_at = old_last_synced_at,
+    extra = old_extra;
+ALTER TABLE applicants
+    DROP COLUMN old_airtable_record_id,
+    DROP COLUMN old_source,
+    DROP COLUMN old_last_synced_from,
+    DROP COLUMN old_last_synced_at,
+    DROP COLUMN old_extra;
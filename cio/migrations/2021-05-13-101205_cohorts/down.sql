@@ -0,0 +1,2 @@
+DROP TABLE cohort_events;
+DROP TABLE cohort_members;
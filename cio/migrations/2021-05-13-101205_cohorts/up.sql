@@ -0,0 +1,19 @@
+CREATE TABLE cohort_members (
+    id SERIAL PRIMARY KEY,
+    cohort VARCHAR NOT NULL,
+    applicant_id INTEGER NOT NULL,
+    source VARCHAR NOT NULL,
+    start_date DATE,
+    added_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (cohort, applicant_id)
+);
+CREATE TABLE cohort_events (
+    id SERIAL PRIMARY KEY,
+    cohort VARCHAR NOT NULL,
+    milestone VARCHAR NOT NULL,
+    date DATE NOT NULL,
+    calendar_id VARCHAR NOT NULL,
+    event_id VARCHAR NOT NULL,
+    synced_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (cohort, milestone)
+);
@@ -0,0 +1,15 @@
+CREATE TABLE offers (
+    id SERIAL PRIMARY KEY,
+    applicant_id INTEGER NOT NULL,
+    level VARCHAR NOT NULL,
+    location VARCHAR NOT NULL,
+    salary BIGINT NOT NULL,
+    currency VARCHAR NOT NULL,
+    start_date DATE,
+    band_min BIGINT NOT NULL,
+    band_max BIGINT NOT NULL,
+    in_band BOOLEAN NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX offers_applicant_id ON offers (applicant_id);
@@ -0,0 +1 @@
+DROP TABLE headcount_plans;
@@ -0,0 +1,7 @@
+CREATE TABLE headcount_plans (
+    id SERIAL PRIMARY KEY,
+    team VARCHAR NOT NULL,
+    quarter VARCHAR NOT NULL,
+    planned INTEGER NOT NULL,
+    UNIQUE (team, quarter)
+);
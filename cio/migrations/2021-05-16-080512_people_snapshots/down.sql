@@ -0,0 +1,3 @@
+DROP TABLE retention_cohorts;
+DROP TABLE people_snapshots;
+ALTER TABLE offboarded_users DROP COLUMN start_date;
@@ -0,0 +1,23 @@
+ALTER TABLE offboarded_users ADD COLUMN start_date DATE;
+
+CREATE TABLE people_snapshots (
+    id SERIAL PRIMARY KEY,
+    taken_on DATE NOT NULL UNIQUE,
+    headcount INTEGER NOT NULL,
+    median_tenure_days INTEGER NOT NULL,
+    tenure_under_1y INTEGER NOT NULL,
+    tenure_1y_to_2y INTEGER NOT NULL,
+    tenure_2y_to_4y INTEGER NOT NULL,
+    tenure_over_4y INTEGER NOT NULL,
+    departures_12m INTEGER NOT NULL,
+    attrition_12m REAL NOT NULL
+);
+
+CREATE TABLE retention_cohorts (
+    id SERIAL PRIMARY KEY,
+    taken_on DATE NOT NULL,
+    cohort INTEGER NOT NULL,
+    started INTEGER NOT NULL,
+    retained INTEGER [] NOT NULL,
+    UNIQUE (taken_on, cohort)
+);
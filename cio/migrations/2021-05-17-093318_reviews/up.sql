@@ -0,0 +1,23 @@
+CREATE TABLE review_cycles (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    opened_by VARCHAR NOT NULL,
+    opened_at TIMESTAMPTZ NOT NULL,
+    closes_on DATE NOT NULL,
+    closed_at TIMESTAMPTZ,
+    archive_url VARCHAR NOT NULL DEFAULT ''
+);
+
+CREATE TABLE review_assignments (
+    id SERIAL PRIMARY KEY,
+    cycle_id INTEGER NOT NULL,
+    kind VARCHAR NOT NULL,
+    reviewer VARCHAR NOT NULL,
+    subject VARCHAR NOT NULL,
+    response_id VARCHAR NOT NULL DEFAULT '',
+    answers JSONB NOT NULL DEFAULT '{}',
+    completed_at TIMESTAMPTZ,
+    reminders INTEGER NOT NULL DEFAULT 0,
+    reminded_on DATE,
+    UNIQUE (cycle_id, kind, reviewer, subject)
+);
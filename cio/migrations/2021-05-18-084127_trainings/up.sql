@@ -0,0 +1,10 @@
+CREATE TABLE training_completions (
+    id SERIAL PRIMARY KEY,
+    training VARCHAR NOT NULL,
+    username VARCHAR NOT NULL,
+    course VARCHAR NOT NULL,
+    completed_at TIMESTAMPTZ NOT NULL,
+    source VARCHAR NOT NULL,
+    imported_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (training, username, completed_at)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE policy_acknowledgments (
+    id SERIAL PRIMARY KEY,
+    policy VARCHAR NOT NULL,
+    version VARCHAR NOT NULL,
+    username VARCHAR NOT NULL,
+    requested_at TIMESTAMPTZ NOT NULL,
+    acknowledged_at TIMESTAMPTZ,
+    UNIQUE (policy, version, username)
+);
@@ -0,0 +1,13 @@
+CREATE TABLE visitors (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    email VARCHAR NOT NULL,
+    company VARCHAR NOT NULL DEFAULT '',
+    host VARCHAR NOT NULL,
+    visit_date DATE NOT NULL,
+    registered_by VARCHAR NOT NULL,
+    nda_envelope_id VARCHAR NOT NULL DEFAULT '',
+    arrived_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (email, visit_date)
+);
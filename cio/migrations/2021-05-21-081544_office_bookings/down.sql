@@ -0,0 +1 @@
+DROP TABLE office_bookings;
@@ -0,0 +1,11 @@
+CREATE TABLE office_bookings (
+    id SERIAL PRIMARY KEY,
+    resource VARCHAR NOT NULL,
+    building VARCHAR NOT NULL,
+    desk BOOLEAN NOT NULL,
+    username VARCHAR NOT NULL,
+    day DATE NOT NULL,
+    booked_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (resource, day)
+);
+CREATE UNIQUE INDEX office_bookings_one_desk_per_day ON office_bookings (username, day) WHERE desk;
@@ -0,0 +1,2 @@
+DROP TABLE lunch_orders;
+DROP TABLE lunch_days;
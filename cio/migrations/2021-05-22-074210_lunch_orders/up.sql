@@ -0,0 +1,17 @@
+CREATE TABLE lunch_days (
+    id SERIAL PRIMARY KEY,
+    day DATE NOT NULL UNIQUE,
+    posted_at TIMESTAMPTZ NOT NULL,
+    sent_at TIMESTAMPTZ,
+    orders INTEGER NOT NULL DEFAULT 0,
+    total_cost REAL NOT NULL DEFAULT 0
+);
+CREATE TABLE lunch_orders (
+    id SERIAL PRIMARY KEY,
+    day DATE NOT NULL,
+    username VARCHAR NOT NULL,
+    item VARCHAR NOT NULL,
+    price REAL NOT NULL,
+    ordered_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (day, username)
+);
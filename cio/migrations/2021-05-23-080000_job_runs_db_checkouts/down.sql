@@ -0,0 +1 @@
+ALTER TABLE job_runs RENAME COLUMN db_checkouts TO db_queries;
@@ -0,0 +1 @@
+ALTER TABLE job_runs RENAME COLUMN db_queries TO db_checkouts;
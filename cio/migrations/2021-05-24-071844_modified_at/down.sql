@@ -0,0 +1,38 @@
+ALTER TABLE alerts DROP COLUMN modified_at;
+ALTER TABLE anonymous_feedbacks DROP COLUMN modified_at;
+ALTER TABLE applicant_interviews DROP COLUMN modified_at;
+ALTER TABLE applicant_reviewers DROP COLUMN modified_at;
+ALTER TABLE applicants DROP COLUMN modified_at;
+ALTER TABLE auth_user_logins DROP COLUMN modified_at;
+ALTER TABLE auth_users DROP COLUMN modified_at;
+ALTER TABLE bucket_audits DROP COLUMN modified_at;
+ALTER TABLE buildings DROP COLUMN modified_at;
+ALTER TABLE certificates DROP COLUMN modified_at;
+ALTER TABLE cloud_costs DROP COLUMN modified_at;
+ALTER TABLE conference_rooms DROP COLUMN modified_at;
+ALTER TABLE engineering_metrics DROP COLUMN modified_at;
+ALTER TABLE event_registrants DROP COLUMN modified_at;
+ALTER TABLE github_actions_usages DROP COLUMN modified_at;
+ALTER TABLE github_repos DROP COLUMN modified_at;
+ALTER TABLE groups DROP COLUMN modified_at;
+ALTER TABLE inbound_shipments DROP COLUMN modified_at;
+ALTER TABLE journal_club_meetings DROP COLUMN modified_at;
+ALTER TABLE journal_club_papers DROP COLUMN modified_at;
+ALTER TABLE links DROP COLUMN modified_at;
+ALTER TABLE mailing_list_subscribers DROP COLUMN modified_at;
+ALTER TABLE match_reviews DROP COLUMN modified_at;
+ALTER TABLE oauth_grants DROP COLUMN modified_at;
+ALTER TABLE open_roles DROP COLUMN modified_at;
+ALTER TABLE page_views DROP COLUMN modified_at;
+ALTER TABLE profiles DROP COLUMN modified_at;
+ALTER TABLE recorded_meetings DROP COLUMN modified_at;
+ALTER TABLE repo_license_audits DROP COLUMN modified_at;
+ALTER TABLE repo_owners DROP COLUMN modified_at;
+ALTER TABLE rfds DROP COLUMN modified_at;
+ALTER TABLE security_events DROP COLUMN modified_at;
+ALTER TABLE security_postures DROP COLUMN modified_at;
+ALTER TABLE service_account_keys DROP COLUMN modified_at;
+ALTER TABLE software_vendors DROP COLUMN modified_at;
+ALTER TABLE tailscale_devices DROP COLUMN modified_at;
+ALTER TABLE tokens DROP COLUMN modified_at;
+ALTER TABLE users DROP COLUMN modified_at;
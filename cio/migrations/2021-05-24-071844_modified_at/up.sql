@@ -0,0 +1,40 @@
+-- When each record last changed in the database, by a sync or anything else, so we
+-- know which records to push to Airtable between full syncs.
+ALTER TABLE alerts ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE anonymous_feedbacks ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE applicant_interviews ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE applicant_reviewers ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE applicants ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE auth_user_logins ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE auth_users ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE bucket_audits ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE buildings ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE certificates ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE cloud_costs ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE conference_rooms ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE engineering_metrics ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE event_registrants ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE github_actions_usages ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE github_repos ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE groups ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE inbound_shipments ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE journal_club_meetings ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE journal_club_papers ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE links ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE mailing_list_subscribers ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE match_reviews ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE oauth_grants ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE open_roles ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE page_views ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE profiles ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE recorded_meetings ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE repo_license_audits ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE repo_owners ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE rfds ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE security_events ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE security_postures ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE service_account_keys ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE software_vendors ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE tailscale_devices ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE tokens ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE users ADD COLUMN modified_at TIMESTAMPTZ DEFAULT now();
@@ -0,0 +1,298 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::naive::NaiveDate;
+use chrono::{Datelike, Duration, Utc};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_FINANCE, AIRTABLE_GITHUB_ACTIONS_USAGE_TABLE};
+use crate::chat::ChatChannel;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::format::{format_compact, format_percent_change, format_usd};
+use crate::messages::render_message;
+use crate::models::GithubRepos;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::github_actions_usages;
+use crate::utils::{github_api_get, github_org};
+
+/// The cost of a minute on a Linux runner in dollars.
+/// FROM: https://docs.github.com/en/github/setting-up-and-managing-billing-and-payments-on-github/about-billing-for-github-actions
+pub const LINUX_COST_PER_MINUTE: f32 = 0.008;
+/// Minutes on Windows runners are billed at twice the rate of Linux runners.
+pub const WINDOWS_MINUTES_MULTIPLIER: i32 = 2;
+/// Minutes on macOS runners are billed at ten times the rate of Linux runners.
+pub const MACOS_MINUTES_MULTIPLIER: i32 = 10;
+
+/// The week over week increase in billable minutes for a repo that we alert on.
+pub const USAGE_SPIKE_THRESHOLD: f32 = 0.5;
+
+/// The data type for the GitHub Actions minutes a repository consumed in a day.
+#[db {
+    new_struct_name = "GithubActionsUsage",
+    airtable_base_id = "AIRTABLE_BASE_ID_FINANCE",
+    airtable_table = "AIRTABLE_GITHUB_ACTIONS_USAGE_TABLE",
+    source = "github",
+    match_on = {
+        "repo" = "String",
+        "date" = "NaiveDate",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "github_actions_usages"]
+pub struct NewGithubActionsUsage {
+    pub repo: String,
+    #[serde(serialize_with = "crate::configs::null_date_format::serialize")]
+    pub date: NaiveDate,
+    /// The number of workflow runs created on the date.
+    #[serde(default)]
+    pub runs: i32,
+    #[serde(default)]
+    pub ubuntu_minutes: i32,
+    #[serde(default)]
+    pub macos_minutes: i32,
+    #[serde(default)]
+    pub windows_minutes: i32,
+    /// The minutes consumed with the multipliers for each operating system applied.
+    #[serde(default)]
+    pub billable_minutes: i32,
+    /// The cost in dollars of the billable minutes.
+    #[serde(default)]
+    pub cost: f32,
+}
+
+impl NewGithubActionsUsage {
+    /// Add the billable time from a workflow run's timing to the usage.
+    /// FROM: https://docs.github.com/en/rest/reference/actions#get-workflow-run-usage
+    #[instrument]
+    #[inline]
+    pub fn add_run_timing(&mut self, timing: &Value) {
+        self.runs += 1;
+        self.ubuntu_minutes += ms_to_minutes(&timing["billable"]["UBUNTU"]["total_ms"]);
+        self.macos_minutes += ms_to_minutes(&timing["billable"]["MACOS"]["total_ms"]);
+        self.windows_minutes += ms_to_minutes(&timing["billable"]["WINDOWS"]["total_ms"]);
+
+        self.billable_minutes = self.ubuntu_minutes + self.macos_minutes * MACOS_MINUTES_MULTIPLIER + self.windows_minutes * WINDOWS_MINUTES_MULTIPLIER;
+        self.cost = self.billable_minutes as f32 * LINUX_COST_PER_MINUTE;
+    }
+}
+
+/// Implement updating the Airtable record for a GithubActionsUsage.
+#[async_trait]
+impl UpdateAirtableRecord<GithubActionsUsage> for GithubActionsUsage {
+    async fn update_airtable_record(&mut self, _record: GithubActionsUsage) {}
+}
+
+/// Convert a duration in milliseconds to minutes, rounding up like GitHub does for billing.
+fn ms_to_minutes(v: &Value) -> i32 {
+    let ms = v.as_i64().unwrap_or_default();
+    ((ms + 59_999) / 60_000) as i32
+}
+
+/// Get the IDs of the workflow runs created in a repository on a date.
+#[instrument]
+#[inline]
+async fn list_workflow_run_ids(repo: &str, date: NaiveDate) -> Vec<i64> {
+    let mut ids: Vec<i64> = Default::default();
+    let mut page = 1;
+    loop {
+        // This endpoint returns an object rather than a list so we can't use `github_api_list`.
+        let resp: Value = match github_api_get(&format!("/repos/{}/{}/actions/runs?created={}&per_page=100&page={}", github_org(), repo, date.format("%Y-%m-%d"), page)).await {
+            Ok(r) => r,
+            Err(e) => {
+                println!("[actions] listing workflow runs for {} failed: {}", repo, e);
+                break;
+            }
+        };
+
+        let runs = resp["workflow_runs"].as_array().cloned().unwrap_or_default();
+        for run in runs.iter() {
+            ids.push(run["id"].as_i64().unwrap_or_default());
+        }
+
+        if runs.len() < 100 {
+            break;
+        }
+        page += 1;
+    }
+
+    ids
+}
+
+/// Sync the GitHub Actions minutes consumed yesterday by each repository in our org.
+/// We only sync complete days so the numbers don't change once they are recorded.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_github_actions_usage(db: &Database) {
+    let date = Utc::now().date().naive_utc() - Duration::days(1);
+
+    for repo in GithubRepos::get_from_db(db) {
+        if repo.archived {
+            continue;
+        }
+
+        let run_ids = list_workflow_run_ids(&repo.name, date).await;
+        if run_ids.is_empty() {
+            continue;
+        }
+
+        let mut usage = NewGithubActionsUsage {
+            repo: repo.name.to_string(),
+            date,
+            runs: 0,
+            ubuntu_minutes: 0,
+            macos_minutes: 0,
+            windows_minutes: 0,
+            billable_minutes: 0,
+            cost: 0.0,
+        };
+        for id in run_ids {
+            match github_api_get::<Value>(&format!("/repos/{}/{}/actions/runs/{}/timing", github_org(), repo.name, id)).await {
+                Ok(timing) => usage.add_run_timing(&timing),
+                Err(e) => println!("[actions] getting timing for run {} in {} failed: {}", id, repo.name, e),
+            }
+        }
+
+        usage.upsert(db).await;
+    }
+}
+
+/// Get the total cost of GitHub Actions per repository for the month containing the given date.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_github_actions_cost_for_month(db: &Database, month: NaiveDate) -> BTreeMap<String, f32> {
+    let mut costs: BTreeMap<String, f32> = Default::default();
+    for usage in GithubActionsUsages::get_from_db(db) {
+        if usage.date.year() == month.year() && usage.date.month() == month.month() {
+            *costs.entry(usage.repo.to_string()).or_default() += usage.cost;
+        }
+    }
+
+    costs
+}
+
+/// Returns the fractional increase from `previous` to `current`, or `None` if there
+/// was no previous usage to compare against.
+#[instrument]
+#[inline]
+pub fn usage_increase(previous: i32, current: i32) -> Option<f32> {
+    if previous <= 0 {
+        return None;
+    }
+
+    Some((current - previous) as f32 / previous as f32)
+}
+
+/// Alert in the #engineering channel on repositories whose billable minutes over the
+/// last week increased by more than `USAGE_SPIKE_THRESHOLD` compared to the week before.
+#[instrument(skip(db))]
+#[inline]
+pub async fn check_github_actions_usage_spikes(db: &Database) {
+    let today = Utc::now().date().naive_utc();
+    let this_week_start = today - Duration::days(7);
+    let last_week_start = today - Duration::days(14);
+
+    // Map of repo to the (last week, this week) billable minutes.
+    let mut weeks: BTreeMap<String, (i32, i32)> = Default::default();
+    for usage in GithubActionsUsages::get_from_db(db) {
+        let entry = weeks.entry(usage.repo.to_string()).or_default();
+        if usage.date >= this_week_start && usage.date < today {
+            entry.1 += usage.billable_minutes;
+        } else if usage.date >= last_week_start && usage.date < this_week_start {
+            entry.0 += usage.billable_minutes;
+        }
+    }
+
+    let mut spikes: Vec<String> = Default::default();
+    for (repo, (previous, current)) in weeks {
+        if let Some(increase) = usage_increase(previous, current) {
+            if increase > USAGE_SPIKE_THRESHOLD {
+                spikes.push(format!(
+                    "• `{}` used {} billable minutes ({}) this week, {} from {} last week",
+                    repo,
+                    format_compact(current),
+                    format_usd(current as f32 * LINUX_COST_PER_MINUTE),
+                    format_percent_change(increase as f64),
+                    format_compact(previous)
+                ));
+            }
+        }
+    }
+
+    if spikes.is_empty() {
+        println!("[actions] no repositories had a spike in GitHub Actions usage");
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Engineering,
+        "actions_usage.spikes",
+        json!({
+            "text": render_message("actions_usage.spikes", &json!({ "lines": spikes })),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+
+    use crate::actions_usage::{check_github_actions_usage_spikes, refresh_github_actions_usage, usage_increase, GithubActionsUsages, NewGithubActionsUsage};
+    use crate::db::Database;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_github_actions_usage() {
+        let db = Database::new();
+
+        refresh_github_actions_usage(&db).await;
+
+        GithubActionsUsages::get_from_db(&db).update_airtable().await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_monday_cron_github_actions_usage_spikes() {
+        let db = Database::new();
+
+        check_github_actions_usage_spikes(&db).await;
+    }
+
+    #[test]
+    fn test_github_actions_usage_billing() {
+        let mut usage = NewGithubActionsUsage {
+            repo: "cio".to_string(),
+            date: NaiveDate::from_ymd(2021, 4, 5),
+            runs: 0,
+            ubuntu_minutes: 0,
+            macos_minutes: 0,
+            windows_minutes: 0,
+            billable_minutes: 0,
+            cost: 0.0,
+        };
+
+        usage.add_run_timing(&serde_json::json!({
+            "billable": {
+                "UBUNTU": {"total_ms": 90_000},
+                "MACOS": {"total_ms": 60_000}
+            }
+        }));
+
+        assert_eq!(usage.runs, 1);
+        assert_eq!(usage.ubuntu_minutes, 2);
+        assert_eq!(usage.macos_minutes, 1);
+        assert_eq!(usage.billable_minutes, 12);
+        assert!((usage.cost - 0.096).abs() < 0.0001);
+
+        assert_eq!(usage_increase(0, 10), None);
+        assert_eq!(usage_increase(100, 160), Some(0.6));
+    }
+}
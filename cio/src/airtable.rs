@@ -1,9 +1,15 @@
+use airtable_api::{api_key_from_env, Airtable};
+
+use crate::job_runs::count_api_call;
+use crate::load_test::airtable_endpoint;
+
 pub static AIRTABLE_BASE_ID_CUSTOMER_LEADS: &str = "appr7imQLcR3pWaNa";
 pub static AIRTABLE_MAILING_LIST_SIGNUPS_TABLE: &str = "Mailing List Signups";
 pub static AIRTABLE_CUSTOMER_INTERACTIONS_TABLE: &str = "Interactions";
 pub static AIRTABLE_AUTH_USERS_TABLE: &str = "Auth Users";
 pub static AIRTABLE_AUTH_USER_LOGINS_TABLE: &str = "Auth User Logins";
 pub static AIRTABLE_PAGE_VIEWS_TABLE: &str = "Page Views";
+pub static AIRTABLE_EVENT_REGISTRANTS_TABLE: &str = "Event Registrants";
 
 pub static AIRTABLE_BASE_ID_DIRECTORY: &str = "appzV7RV5yJH6VFbL";
 pub static AIRTABLE_EMPLOYEES_TABLE: &str = "Employees";
@@ -11,6 +17,8 @@ pub static AIRTABLE_GROUPS_TABLE: &str = "Groups";
 pub static AIRTABLE_BUILDINGS_TABLE: &str = "Buildings";
 pub static AIRTABLE_CONFERENCE_ROOMS_TABLE: &str = "Conference Rooms";
 pub static AIRTABLE_LINKS_TABLE: &str = "Links";
+pub static AIRTABLE_REPO_OWNERS_TABLE: &str = "Repo Owners";
+pub static AIRTABLE_PROFILES_TABLE: &str = "Profiles";
 
 pub static AIRTABLE_BASE_ID_MISC: &str = "appfWJK9kBNwnavG2";
 pub static AIRTABLE_CERTIFICATES_TABLE: &str = "Certificates";
@@ -18,6 +26,18 @@ pub static AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE: &str = "Journal Club Meetings";
 pub static AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE: &str = "Journal Club Papers";
 pub static AIRTABLE_GITHUB_REPOS_TABLE: &str = "GitHub Repos";
 pub static AIRTABLE_RECORDED_MEETINGS_TABLE: &str = "Recorded Meetings";
+pub static AIRTABLE_DEPENDENCY_LICENSES_TABLE: &str = "Dependency Licenses";
+pub static AIRTABLE_SECURITY_ALERTS_TABLE: &str = "Security Alerts";
+pub static AIRTABLE_MATCH_REVIEWS_TABLE: &str = "Match Reviews";
+pub static AIRTABLE_BUCKET_AUDITS_TABLE: &str = "Bucket Audits";
+pub static AIRTABLE_ANONYMOUS_FEEDBACK_TABLE: &str = "Anonymous Feedback";
+pub static AIRTABLE_ENGINEERING_METRICS_TABLE: &str = "Engineering Metrics";
+pub static AIRTABLE_SECURITY_EVENTS_TABLE: &str = "Security Events";
+pub static AIRTABLE_SECURITY_POSTURE_TABLE: &str = "Security Posture";
+pub static AIRTABLE_OAUTH_GRANTS_TABLE: &str = "OAuth Grants";
+pub static AIRTABLE_SERVICE_ACCOUNT_KEYS_TABLE: &str = "Service Account Keys";
+pub static AIRTABLE_API_TOKENS_TABLE: &str = "API Tokens";
+pub static AIRTABLE_TAILSCALE_DEVICES_TABLE: &str = "Tailscale Devices";
 
 pub static AIRTABLE_BASE_ID_RACK_ROADMAP: &str = "appvAEzcMvB2QNboC";
 pub static AIRTABLE_RFD_TABLE: &str = "RFDs";
@@ -26,6 +46,7 @@ pub static AIRTABLE_BASE_ID_RECURITING_APPLICATIONS: &str = "appIw5FNBqWTXFTeV";
 pub static AIRTABLE_APPLICATIONS_TABLE: &str = "Applicants";
 pub static AIRTABLE_INTERVIEWS_TABLE: &str = "Interviews";
 pub static AIRTABLE_REVIEWER_LEADERBOARD_TABLE: &str = "Reviewer Leaderboard";
+pub static AIRTABLE_OPEN_ROLES_TABLE: &str = "Open Roles";
 
 pub static AIRTABLE_BASE_ID_PRODUCT_HUDDLE: &str = "appbQqnE3nykcnkbx";
 pub static AIRTABLE_DISCUSSION_TOPICS_TABLE: &str = "Discussion topics";
@@ -37,5 +58,67 @@ pub static AIRTABLE_INBOUND_TABLE: &str = "Inbound";
 
 pub static AIRTABLE_BASE_ID_FINANCE: &str = "appduLHDVQ332gKyf";
 pub static AIRTABLE_SOFTWARE_VENDORS_TABLE: &str = "Software Vendors";
+pub static AIRTABLE_GITHUB_ACTIONS_USAGE_TABLE: &str = "GitHub Actions Usage";
+pub static AIRTABLE_CLOUD_COSTS_TABLE: &str = "Cloud Costs";
 
 pub static AIRTABLE_GRID_VIEW: &str = "Grid view";
+
+/// Returns every base and table we sync to, so we can back them all up.
+pub fn airtable_tables() -> Vec<(&'static str, &'static str)> {
+    vec![
+        (AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_MAILING_LIST_SIGNUPS_TABLE),
+        (AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_CUSTOMER_INTERACTIONS_TABLE),
+        (AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_AUTH_USERS_TABLE),
+        (AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_AUTH_USER_LOGINS_TABLE),
+        (AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_PAGE_VIEWS_TABLE),
+        (AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_EVENT_REGISTRANTS_TABLE),
+        (AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_EMPLOYEES_TABLE),
+        (AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_GROUPS_TABLE),
+        (AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_BUILDINGS_TABLE),
+        (AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_CONFERENCE_ROOMS_TABLE),
+        (AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_LINKS_TABLE),
+        (AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_REPO_OWNERS_TABLE),
+        (AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_PROFILES_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_CERTIFICATES_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_GITHUB_REPOS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_RECORDED_MEETINGS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_DEPENDENCY_LICENSES_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_SECURITY_ALERTS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_MATCH_REVIEWS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_BUCKET_AUDITS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_ANONYMOUS_FEEDBACK_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_ENGINEERING_METRICS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_SECURITY_EVENTS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_SECURITY_POSTURE_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_OAUTH_GRANTS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_SERVICE_ACCOUNT_KEYS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_API_TOKENS_TABLE),
+        (AIRTABLE_BASE_ID_MISC, AIRTABLE_TAILSCALE_DEVICES_TABLE),
+        (AIRTABLE_BASE_ID_RACK_ROADMAP, AIRTABLE_RFD_TABLE),
+        (AIRTABLE_BASE_ID_RECURITING_APPLICATIONS, AIRTABLE_APPLICATIONS_TABLE),
+        (AIRTABLE_BASE_ID_RECURITING_APPLICATIONS, AIRTABLE_INTERVIEWS_TABLE),
+        (AIRTABLE_BASE_ID_RECURITING_APPLICATIONS, AIRTABLE_REVIEWER_LEADERBOARD_TABLE),
+        (AIRTABLE_BASE_ID_RECURITING_APPLICATIONS, AIRTABLE_OPEN_ROLES_TABLE),
+        (AIRTABLE_BASE_ID_PRODUCT_HUDDLE, AIRTABLE_DISCUSSION_TOPICS_TABLE),
+        (AIRTABLE_BASE_ID_PRODUCT_HUDDLE, AIRTABLE_MEETING_SCHEDULE_TABLE),
+        (AIRTABLE_BASE_ID_SHIPMENTS, AIRTABLE_OUTBOUND_TABLE),
+        (AIRTABLE_BASE_ID_SHIPMENTS, AIRTABLE_INBOUND_TABLE),
+        (AIRTABLE_BASE_ID_FINANCE, AIRTABLE_SOFTWARE_VENDORS_TABLE),
+        (AIRTABLE_BASE_ID_FINANCE, AIRTABLE_GITHUB_ACTIONS_USAGE_TABLE),
+        (AIRTABLE_BASE_ID_FINANCE, AIRTABLE_CLOUD_COSTS_TABLE),
+    ]
+}
+
+/// Create an Airtable client for a base. Every request it sends is counted as a call
+/// to Airtable for the job that is running, and the load test sends them to its mock
+/// server instead.
+#[inline]
+pub fn airtable_client(base_id: &str) -> Airtable {
+    let airtable = Airtable::new(api_key_from_env(), base_id, "").with_request_hook(|_| count_api_call("airtable"));
+    match airtable_endpoint() {
+        Some(endpoint) => airtable.with_endpoint(endpoint),
+        None => airtable,
+    }
+}
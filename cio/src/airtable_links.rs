@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use tracing::instrument;
+
+/// The records the linked-record fields of a model link to, by the Airtable table of the
+/// model they link to. We keep the key of each record in the database, ie. the name of a
+/// group, next to its Airtable record ID, so links can be stored as keys in the database
+/// and sent to Airtable as record IDs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AirtableLinks(BTreeMap<String, Vec<(String, String)>>);
+
+impl AirtableLinks {
+    /// Add the records in a table, as pairs of their Airtable record ID and their key.
+    #[instrument(skip(records))]
+    #[inline]
+    pub fn insert(&mut self, table: &str, records: Vec<(String, String)>) {
+        self.0.insert(table.to_string(), records);
+    }
+
+    /// Resolve the Airtable record IDs of linked records to their keys. Records we do not
+    /// know about keep their ID, so the link is not lost.
+    #[instrument]
+    #[inline]
+    pub fn keys(&self, table: &str, ids: &[String]) -> Vec<String> {
+        let records = self.0.get(table).map(|r| r.as_slice()).unwrap_or_default();
+
+        ids.iter()
+            .map(|id| records.iter().find(|(record_id, _)| record_id == id).map(|(_, key)| key.to_string()).unwrap_or_else(|| id.to_string()))
+            .collect()
+    }
+
+    /// Look up the Airtable record IDs of linked records from their keys, or their record
+    /// IDs if they were never resolved. Airtable rejects links to records that do not
+    /// exist, so the ones we can not find are left out.
+    #[instrument]
+    #[inline]
+    pub fn ids(&self, table: &str, keys: &[String]) -> Vec<String> {
+        let records = self.0.get(table).map(|r| r.as_slice()).unwrap_or_default();
+
+        keys.iter()
+            .filter_map(|key| match records.iter().find(|(id, k)| k == key || id == key) {
+                Some((id, _)) => Some(id.to_string()),
+                None => {
+                    println!("[airtable] there is no record `{}` in {} to link to", key, table);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::airtable_links::AirtableLinks;
+
+    #[test]
+    fn test_airtable_links() {
+        let mut links = AirtableLinks::default();
+        links.insert("Groups", vec![("recAAA".to_string(), "eng".to_string()), ("recBBB".to_string(), "all".to_string())]);
+
+        assert_eq!(links.keys("Groups", &["recBBB".to_string(), "recZZZ".to_string()]), vec!["all".to_string(), "recZZZ".to_string()]);
+        assert_eq!(
+            links.ids("Groups", &["eng".to_string(), "recBBB".to_string(), "nope".to_string()]),
+            vec!["recAAA".to_string(), "recBBB".to_string()]
+        );
+        assert_eq!(links.keys("Buildings", &["recAAA".to_string()]), vec!["recAAA".to_string()]);
+        assert!(links.ids("Buildings", &["eng".to_string()]).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use tracing::instrument;
+
+/// How often we still list every record in a table when pushing it to Airtable. In
+/// between we only fetch the records modified since the last push, but that misses
+/// records deleted on either side, so those are cleaned up by the full sync.
+pub const AIRTABLE_FULL_SYNC_HOURS: i64 = 24;
+
+/// How far before the last push we look for modified records, so a record changed
+/// while we were pushing, or with a clock slightly off from Airtable's, is not missed.
+pub const AIRTABLE_SYNC_OVERLAP_MINUTES: i64 = 5;
+
+/// When we last pushed a table to Airtable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AirtableSync {
+    pub last_sync: DateTime<Utc>,
+    pub last_full_sync: DateTime<Utc>,
+    /// The Airtable record ID for the database ID of each record in the table, so we
+    /// can update a record that only changed on our side without listing the table.
+    pub record_ids: BTreeMap<i32, String>,
+}
+
+/// The last push of each table, by base and table. This lives in memory, so the first
+/// push of each table after a restart, and every push from `cio run`, is a full sync.
+static AIRTABLE_SYNCS: Mutex<BTreeMap<String, AirtableSync>> = Mutex::new(BTreeMap::new());
+
+fn sync_key(base_id: &str, table: &str) -> String {
+    format!("{}/{}", base_id, table)
+}
+
+/// Get the time to push the changes to a table since, with the Airtable record IDs of
+/// its records, or `None` if it is time for a full sync.
+#[instrument]
+#[inline]
+pub fn delta_sync_since(base_id: &str, table: &str, now: DateTime<Utc>) -> Option<(DateTime<Utc>, BTreeMap<i32, String>)> {
+    AIRTABLE_SYNCS
+        .lock()
+        .unwrap()
+        .get(&sync_key(base_id, table))
+        .and_then(|s| s.delta_since(now).map(|since| (since, s.record_ids.clone())))
+}
+
+/// Record a push to a table that started at `started_at`, with the Airtable record IDs
+/// of its records.
+#[instrument(skip(record_ids))]
+#[inline]
+pub fn record_airtable_sync(base_id: &str, table: &str, started_at: DateTime<Utc>, record_ids: BTreeMap<i32, String>, full: bool) {
+    let mut syncs = AIRTABLE_SYNCS.lock().unwrap();
+    let key = sync_key(base_id, table);
+    let last_full_sync = match syncs.get(&key) {
+        Some(s) if !full => s.last_full_sync,
+        _ => started_at,
+    };
+    syncs.insert(
+        key,
+        AirtableSync {
+            last_sync: started_at,
+            last_full_sync,
+            record_ids,
+        },
+    );
+}
+
+impl AirtableSync {
+    /// Get the time to push the changes since, or `None` if it is time for a full sync.
+    #[instrument]
+    #[inline]
+    pub fn delta_since(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if now - self.last_full_sync >= Duration::hours(AIRTABLE_FULL_SYNC_HOURS) {
+            return None;
+        }
+
+        Some(self.last_sync - Duration::minutes(AIRTABLE_SYNC_OVERLAP_MINUTES))
+    }
+}
+
+/// The Airtable formula for the records modified since a time.
+#[instrument]
+#[inline]
+pub fn modified_since_formula(since: DateTime<Utc>) -> String {
+    format!("IS_AFTER(LAST_MODIFIED_TIME(), '{}')", since.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// Returns if a record in the database changed since a time, from when it was last
+/// modified. Records without a modified time count as changed.
+#[instrument]
+#[inline]
+pub fn changed_since(modified_at: Option<DateTime<Utc>>, since: DateTime<Utc>) -> bool {
+    modified_at.map(|t| t >= since).unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::airtable_syncs::{changed_since, delta_sync_since, modified_since_formula, record_airtable_sync};
+
+    #[test]
+    fn test_airtable_syncs() {
+        let now = Utc.ymd(2021, 5, 5).and_hms(9, 0, 0);
+        let (base, table) = ("appTest", "Delta Test");
+
+        // Nothing has been pushed yet, so we need a full sync.
+        assert_eq!(delta_sync_since(base, table, now), None);
+
+        let ids: BTreeMap<i32, String> = vec![(1, "rec1".to_string())].into_iter().collect();
+        record_airtable_sync(base, table, now, ids.clone(), true);
+        record_airtable_sync(base, table, now + Duration::hours(2), ids.clone(), false);
+        assert_eq!(delta_sync_since(base, table, now + Duration::hours(3)), Some((now + Duration::hours(2) - Duration::minutes(5), ids)));
+        assert_eq!(delta_sync_since("appOther", table, now + Duration::hours(3)), None);
+
+        // A day after the last full sync, we need another.
+        assert_eq!(delta_sync_since(base, table, now + Duration::hours(24)), None);
+
+        assert_eq!(modified_since_formula(now), "IS_AFTER(LAST_MODIFIED_TIME(), '2021-05-05T09:00:00Z')");
+        assert!(changed_since(None, now));
+        assert!(changed_since(Some(now), now));
+        assert!(!changed_since(Some(now - Duration::seconds(1)), now));
+    }
+}
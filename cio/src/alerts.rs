@@ -0,0 +1,367 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_SECURITY_ALERTS_TABLE};
+use crate::business_days::BusinessCalendar;
+use crate::chat::{notify_channel, ChatChannel};
+use crate::configs::RepoOwner;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::models::GithubRepos;
+use crate::notifications::{notify, NotificationPriority};
+use crate::reactions::message_ref;
+use crate::schema::alerts;
+use crate::utils::{github_api_list, github_org};
+
+/// How many business days someone has to claim a security alert before the digest
+/// calls it out as past the triage SLA.
+pub const ALERT_TRIAGE_SLA_BUSINESS_DAYS: u32 = 2;
+
+/// The data type for a security alert from GitHub, either from Dependabot or
+/// code scanning.
+#[db {
+    new_struct_name = "Alert",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_SECURITY_ALERTS_TABLE",
+    source = "github",
+    match_on = {
+        "alert_id" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "alerts"]
+pub struct NewAlert {
+    /// A unique identifier for the alert formatted as `{repo}/{tool}/{number}`.
+    /// We use this to dedupe alerts between syncs.
+    pub alert_id: String,
+    pub repo: String,
+    /// The tool that raised the alert, either `dependabot` or `code-scanning`.
+    pub tool: String,
+    #[serde(default)]
+    pub number: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub state: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub severity: String,
+    /// The vulnerable package for Dependabot alerts or the rule for code scanning alerts.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub package: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub summary: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub html_url: String,
+    /// The GitHub team that owns the repository.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub owner_team: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// The username of the person who claimed the alert in Slack.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub assignee: String,
+}
+
+/// Implement updating the Airtable record for an Alert.
+#[async_trait]
+impl UpdateAirtableRecord<Alert> for Alert {
+    async fn update_airtable_record(&mut self, _record: Alert) {}
+}
+
+impl NewAlert {
+    /// Parse a Dependabot alert from the GitHub API.
+    #[instrument]
+    #[inline]
+    pub fn from_dependabot(repo: &str, owner_team: &str, a: &Value) -> Self {
+        let number = a["number"].as_i64().unwrap_or_default() as i32;
+        NewAlert {
+            alert_id: format!("{}/dependabot/{}", repo, number),
+            repo: repo.to_string(),
+            tool: "dependabot".to_string(),
+            number,
+            state: a["state"].as_str().unwrap_or_default().to_string(),
+            severity: a["security_advisory"]["severity"].as_str().unwrap_or_default().to_lowercase(),
+            package: a["dependency"]["package"]["name"].as_str().unwrap_or_default().to_string(),
+            summary: a["security_advisory"]["summary"].as_str().unwrap_or_default().to_string(),
+            html_url: a["html_url"].as_str().unwrap_or_default().to_string(),
+            owner_team: owner_team.to_string(),
+            assignee: Default::default(),
+            created_at: parse_time(&a["created_at"]),
+            updated_at: parse_time(&a["updated_at"]),
+        }
+    }
+
+    /// Parse a code scanning alert from the GitHub API.
+    #[instrument]
+    #[inline]
+    pub fn from_code_scanning(repo: &str, owner_team: &str, a: &Value) -> Self {
+        let number = a["number"].as_i64().unwrap_or_default() as i32;
+        // Prefer the security severity since the rule severity is about code quality.
+        let mut severity = a["rule"]["security_severity_level"].as_str().unwrap_or_default().to_lowercase();
+        if severity.is_empty() {
+            severity = a["rule"]["severity"].as_str().unwrap_or_default().to_lowercase();
+        }
+
+        let mut updated_at = parse_time(&a["updated_at"]);
+        if a["updated_at"].is_null() {
+            updated_at = parse_time(&a["created_at"]);
+        }
+
+        NewAlert {
+            alert_id: format!("{}/code-scanning/{}", repo, number),
+            repo: repo.to_string(),
+            tool: "code-scanning".to_string(),
+            number,
+            state: a["state"].as_str().unwrap_or_default().to_string(),
+            severity,
+            package: a["rule"]["id"].as_str().unwrap_or_default().to_string(),
+            summary: a["rule"]["description"].as_str().unwrap_or_default().to_string(),
+            html_url: a["html_url"].as_str().unwrap_or_default().to_string(),
+            owner_team: owner_team.to_string(),
+            assignee: Default::default(),
+            created_at: parse_time(&a["created_at"]),
+            updated_at,
+        }
+    }
+}
+
+impl Alert {
+    /// Returns if the alert is still open.
+    #[instrument]
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.state == "open"
+    }
+
+    /// Get the message we post to triage a new alert. The first block is tagged
+    /// so reactions to the message can find the alert again.
+    #[instrument]
+    #[inline]
+    pub fn as_triage_slack_msg(&self) -> Value {
+        let mut owner = self.owner_team.to_string();
+        if owner.is_empty() {
+            owner = "no owner".to_string();
+        }
+
+        json!({
+            "blocks": [
+                {
+                    "type": "section",
+                    "block_id": message_ref("alert", &self.alert_id),
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": format!("New *{}* {} alert in `{}`: <{}|{}> {}", self.severity, self.tool, self.repo, self.html_url, self.package, self.summary),
+                    },
+                },
+                {
+                    "type": "context",
+                    "elements": [{ "type": "mrkdwn", "text": format!("owned by {} | react to claim or resolve it", owner) }],
+                },
+            ],
+        })
+    }
+}
+
+/// Upsert an alert from GitHub, keeping who claimed it, and post new severe alerts
+/// to the security channel for triage.
+#[instrument(skip(db))]
+#[inline]
+async fn upsert_alert(db: &Database, mut alert: NewAlert) {
+    let existing = Alert::get_from_db(db, alert.alert_id.to_string());
+    if let Some(e) = &existing {
+        alert.assignee = e.assignee.to_string();
+    }
+
+    let alert = alert.upsert(db).await;
+    if existing.is_none() && alert.is_open() && severity_priority(&alert.severity) <= TRIAGE_SEVERITY_PRIORITY {
+        notify(db, NotificationPriority::Urgent, ChatChannel::Security, "alerts.triage", alert.as_triage_slack_msg()).await;
+    }
+}
+
+/// Parse a timestamp from the GitHub API, defaulting to now.
+fn parse_time(v: &Value) -> DateTime<Utc> {
+    v.as_str().and_then(|s| DateTime::parse_from_rfc3339(s).ok()).map(|t| t.with_timezone(&Utc)).unwrap_or_else(Utc::now)
+}
+
+/// New alerts at this priority or more important are posted for triage as soon as we see them.
+pub const TRIAGE_SEVERITY_PRIORITY: i32 = 1;
+
+/// Return the priority for a severity, lower is more important.
+#[instrument]
+#[inline]
+pub fn severity_priority(severity: &str) -> i32 {
+    match severity.to_lowercase().as_str() {
+        "critical" => 0,
+        "high" | "error" => 1,
+        "medium" | "moderate" | "warning" => 2,
+        "low" | "note" => 3,
+        _ => 4,
+    }
+}
+
+/// Get the slug of the team that owns a repository, based on the teams with access to it.
+/// Teams with admin access take precedence over teams with push access.
+#[instrument]
+#[inline]
+async fn get_repo_owner_team(repo: &str) -> String {
+    let teams: Vec<Value> = github_api_list(&format!("/repos/{}/{}/teams", github_org(), repo)).await.unwrap_or_default();
+
+    let mut owner = "".to_string();
+    for team in teams {
+        let slug = team["slug"].as_str().unwrap_or_default().to_string();
+        let permission = team["permission"].as_str().unwrap_or_default();
+        if permission == "admin" {
+            return slug;
+        }
+        if owner.is_empty() && permission == "push" {
+            owner = slug;
+        }
+    }
+
+    owner
+}
+
+/// Get when an alert created at a time needs to be claimed by, counting business days
+/// in Pacific time.
+#[instrument(skip(calendar))]
+#[inline]
+pub fn alert_triage_deadline(calendar: &BusinessCalendar, created_at: DateTime<Utc>) -> DateTime<Utc> {
+    calendar.business_deadline(created_at, ALERT_TRIAGE_SLA_BUSINESS_DAYS, &chrono_tz::US::Pacific)
+}
+
+/// Sync the Dependabot and code scanning alerts for all the repositories in our org.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_security_alerts(db: &Database) {
+    for repo in GithubRepos::get_from_db(db) {
+        if repo.archived {
+            continue;
+        }
+
+        // Use the owner from our configs if we have one, otherwise fall back to
+        // the teams with access to the repository.
+        let owner_team = match RepoOwner::get_from_db(db, repo.name.to_string()) {
+            Some(owner) if !owner.team.is_empty() => owner.team,
+            _ => get_repo_owner_team(&repo.name).await,
+        };
+
+        // The APIs return an error if the alerts are not enabled for the repository,
+        // so we ignore failures here.
+        let dependabot: Vec<Value> = github_api_list(&format!("/repos/{}/{}/dependabot/alerts", github_org(), repo.name)).await.unwrap_or_default();
+        for a in dependabot.iter() {
+            upsert_alert(db, NewAlert::from_dependabot(&repo.name, &owner_team, a)).await;
+        }
+
+        let code_scanning: Vec<Value> = github_api_list(&format!("/repos/{}/{}/code-scanning/alerts", github_org(), repo.name)).await.unwrap_or_default();
+        for a in code_scanning.iter() {
+            upsert_alert(db, NewAlert::from_code_scanning(&repo.name, &owner_team, a)).await;
+        }
+    }
+}
+
+/// Post a digest of the open security alerts to Slack, grouped by the team that
+/// owns the repository and then by repository, with the most severe alerts first.
+/// Alerts no one has claimed within `ALERT_TRIAGE_SLA_BUSINESS_DAYS` are called out.
+#[instrument(skip(db, calendar))]
+#[inline]
+pub async fn send_security_alerts_digest(db: &Database, calendar: &BusinessCalendar) {
+    let now = Utc::now();
+    let mut alerts: Vec<Alert> = Alerts::get_from_db(db).into_iter().filter(|a| a.is_open()).collect();
+    if alerts.is_empty() {
+        println!("[alerts] there are no open security alerts");
+        return;
+    }
+
+    alerts.sort_by(|a, b| severity_priority(&a.severity).cmp(&severity_priority(&b.severity)).then(a.created_at.cmp(&b.created_at)));
+
+    // Group by team, then repo. The alerts stay in priority order within each group.
+    let mut grouped: BTreeMap<String, BTreeMap<String, Vec<Alert>>> = Default::default();
+    for alert in alerts.iter() {
+        let mut team = alert.owner_team.to_string();
+        if team.is_empty() {
+            team = "no owner".to_string();
+        }
+        grouped.entry(team).or_default().entry(alert.repo.to_string()).or_default().push(alert.clone());
+    }
+
+    let mut text = format!("*{} open security alerts this week*\n", alerts.len());
+    for (team, repos) in grouped {
+        text += &format!("\n*{}*\n", team);
+        for (repo, repo_alerts) in repos {
+            text += &format!("`{}`\n", repo);
+            for a in repo_alerts {
+                text += &format!("• [{}] <{}|{}> {}", a.severity, a.html_url, a.package, a.summary);
+                if !a.assignee.is_empty() {
+                    text += &format!(" _claimed by {}_", a.assignee);
+                } else if alert_triage_deadline(calendar, a.created_at) < now {
+                    text += " *unclaimed past the triage SLA*";
+                }
+                text += "\n";
+            }
+        }
+    }
+
+    notify_channel(ChatChannel::Security, json!({ "text": text })).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::alerts::{alert_triage_deadline, refresh_security_alerts, send_security_alerts_digest, severity_priority, Alerts, NewAlert};
+    use crate::business_days::BusinessCalendar;
+    use crate::db::Database;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_security_alerts() {
+        let db = Database::new();
+
+        refresh_security_alerts(&db).await;
+
+        Alerts::get_from_db(&db).update_airtable().await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_monday_cron_security_alerts_digest() {
+        let db = Database::new();
+
+        send_security_alerts_digest(&db, &BusinessCalendar::default()).await;
+    }
+
+    #[test]
+    fn test_parse_dependabot_alert() {
+        let v = serde_json::json!({
+            "number": 3,
+            "state": "open",
+            "dependency": {"package": {"name": "openssl"}},
+            "security_advisory": {"severity": "HIGH", "summary": "Use after free"},
+            "html_url": "https://github.com/oxidecomputer/cio/security/dependabot/3",
+            "created_at": "2021-04-01T10:00:00Z",
+            "updated_at": "2021-04-02T10:00:00Z"
+        });
+
+        let alert = NewAlert::from_dependabot("cio", "eng", &v);
+        assert_eq!(alert.alert_id, "cio/dependabot/3");
+        assert_eq!(alert.severity, "high");
+        assert_eq!(alert.package, "openssl");
+        assert!(severity_priority(&alert.severity) < severity_priority("moderate"));
+    }
+
+    #[test]
+    fn test_alert_triage_deadline() {
+        let calendar = BusinessCalendar::default();
+
+        // An alert from Friday afternoon in Pacific time is due Tuesday afternoon.
+        let created_at = Utc.ymd(2021, 4, 16).and_hms(22, 0, 0);
+        assert_eq!(alert_triage_deadline(&calendar, created_at), Utc.ymd(2021, 4, 20).and_hms(22, 0, 0));
+    }
+}
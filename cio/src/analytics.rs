@@ -15,6 +15,7 @@ use crate::schema::page_views;
     new_struct_name = "PageView",
     airtable_base_id = "AIRTABLE_BASE_ID_CUSTOMER_LEADS",
     airtable_table = "AIRTABLE_PAGE_VIEWS_TABLE",
+    source = "webhooks",
     match_on = {
         "time" = "DateTime<Utc>",
         "user_email" = "String",
@@ -0,0 +1,262 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration, NaiveDate};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_API_TOKENS_TABLE, AIRTABLE_BASE_ID_MISC};
+use crate::chat::ChatChannel;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, notify_user, NotificationPriority};
+use crate::schema::tokens;
+use crate::utils::GSUITE_DOMAIN;
+
+/// The days before a token expires we start reminding its owner to rotate it. We remind
+/// them weekly until the last week, and then daily.
+pub static TOKEN_REMINDER_DAYS: i64 = 14;
+
+/// An API credential we rely on, keyed by its name in the `tokens` section of our configs,
+/// so we know who to ask to rotate it before it expires.
+///
+/// ```toml
+/// [tokens.github]
+/// service = "GitHub"
+/// owner = "jess"
+/// env_var = "GITHUB_TOKEN"
+/// scopes = ["repo", "read:org"]
+/// created = "2021-02-01"
+/// expires = "2021-05-01"
+/// ```
+#[db {
+    new_struct_name = "ApiToken",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_API_TOKENS_TABLE",
+    source = "configs",
+    match_on = {
+        "name" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "tokens"]
+pub struct ApiTokenConfig {
+    /// name will not be used in config files.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub service: String,
+    /// The username of the person who rotates the token.
+    pub owner: String,
+    /// The environment variable the token is in, if any.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub env_var: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub created: Option<NaiveDate>,
+    /// When the token expires, empty if it does not.
+    #[serde(default)]
+    pub expires: Option<NaiveDate>,
+    /// last_reminded_at will not be used in config files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_reminded_at: Option<DateTime<Utc>>,
+}
+
+/// Implement updating the Airtable record for an ApiToken.
+#[async_trait]
+impl UpdateAirtableRecord<ApiToken> for ApiToken {
+    async fn update_airtable_record(&mut self, _record: ApiToken) {}
+}
+
+impl ApiToken {
+    /// The days until the token expires, negative if it already has, or None if it does not expire.
+    #[instrument]
+    #[inline]
+    pub fn days_left(&self, today: NaiveDate) -> Option<i64> {
+        self.expires.map(|e| (e - today).num_days())
+    }
+
+    /// Returns if we should remind the owner to rotate the token.
+    #[instrument]
+    #[inline]
+    pub fn needs_reminder(&self, now: DateTime<Utc>) -> bool {
+        let days_left = match self.days_left(now.date().naive_utc()) {
+            Some(d) => d,
+            None => return false,
+        };
+        if days_left > TOKEN_REMINDER_DAYS {
+            return false;
+        }
+
+        let every = if days_left > 7 { Duration::days(7) } else { Duration::days(1) };
+        match self.last_reminded_at {
+            // Leave an hour of slack so a daily job that runs a little early still reminds.
+            Some(last) => now - last >= every - Duration::hours(1),
+            None => true,
+        }
+    }
+}
+
+/// Format when a token expires for a reminder, ie. `expires in 3 days`.
+#[instrument]
+#[inline]
+pub fn format_expiry(days_left: i64) -> String {
+    match days_left {
+        d if d < -1 => format!("expired {} days ago", -d),
+        -1 => "expired yesterday".to_string(),
+        0 => "expires today".to_string(),
+        1 => "expires tomorrow".to_string(),
+        d => format!("expires in {} days", d),
+    }
+}
+
+/// Sync the tokens in our configs with our database and then update Airtable from the database.
+#[instrument(skip(db))]
+#[inline]
+pub async fn sync_api_tokens(db: &Database, tokens: BTreeMap<String, ApiTokenConfig>) {
+    let mut token_map: BTreeMap<String, ApiToken> = ApiTokens::get_from_db(db).into_iter().map(|t| (t.name.to_string(), t)).collect();
+
+    for (name, mut token) in tokens {
+        token.name = name.to_string();
+        // Keep when we last reminded the owner, the config does not have it.
+        if let Some(existing) = token_map.remove(&name) {
+            token.last_reminded_at = existing.last_reminded_at;
+        }
+
+        token.upsert(db).await;
+    }
+
+    // Remove any tokens that are no longer in our configs.
+    for (_, token) in token_map {
+        token.delete(db).await;
+    }
+
+    ApiTokens::get_from_db(db).update_airtable().await;
+}
+
+/// Remind the owners of the tokens that expire in the next `TOKEN_REMINDER_DAYS` to rotate
+/// them, and post the tokens to the #security channel. Tokens that already expired are
+/// posted as urgent.
+#[instrument(skip(db))]
+#[inline]
+pub async fn send_api_token_reminders(db: &Database) {
+    let now = Utc::now();
+    let today = now.date().naive_utc();
+
+    let mut lines: Vec<String> = Default::default();
+    let mut expired = false;
+    for token in ApiTokens::get_from_db(db) {
+        if !token.needs_reminder(now) {
+            continue;
+        }
+
+        let days_left = token.days_left(today).unwrap_or_default();
+        expired = expired || days_left < 0;
+        let when = format_expiry(days_left);
+        let email = format!("{}@{}", token.owner, GSUITE_DOMAIN);
+
+        notify_user(
+            db,
+            &email,
+            json!({
+                "text": render_message(
+                    "api_tokens.rotate",
+                    &json!({ "name": token.name, "service": token.service, "env_var": token.env_var, "when": when }),
+                ),
+            }),
+        )
+        .await;
+        lines.push(format!("• *{}* {} token owned by {} {}", token.name, token.service, token.owner, when));
+
+        let mut token = token;
+        token.last_reminded_at = Some(now);
+        token.update_in_db(db);
+    }
+
+    if lines.is_empty() {
+        println!("[api_tokens] no tokens expire in the next {} days", TOKEN_REMINDER_DAYS);
+        return;
+    }
+
+    let priority = if expired { NotificationPriority::Urgent } else { NotificationPriority::Low };
+    notify(
+        db,
+        priority,
+        ChatChannel::Security,
+        "api_tokens.expiring",
+        json!({
+            "text": render_message("api_tokens.expiring", &json!({ "lines": lines })),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+
+    use crate::api_tokens::{format_expiry, send_api_token_reminders, ApiToken};
+    use crate::db::Database;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_api_token_reminders() {
+        let db = Database::new();
+
+        send_api_token_reminders(&db).await;
+    }
+
+    #[test]
+    fn test_api_token_reminders() {
+        let now = Utc.ymd(2021, 4, 27).and_hms(9, 0, 0);
+        let mut token = ApiToken {
+            id: 1,
+            name: "github".to_string(),
+            service: "GitHub".to_string(),
+            owner: "jess".to_string(),
+            env_var: "GITHUB_TOKEN".to_string(),
+            scopes: vec!["repo".to_string()],
+            created: Some(NaiveDate::from_ymd(2021, 2, 1)),
+            expires: None,
+            last_reminded_at: None,
+            airtable_record_id: Default::default(),
+            source: Default::default(),
+            last_synced_from: Default::default(),
+            last_synced_at: None,
+            extra: Default::default(),
+            modified_at: None,
+        };
+        assert_eq!(token.days_left(now.date().naive_utc()), None);
+        assert!(!token.needs_reminder(now));
+
+        token.expires = Some(NaiveDate::from_ymd(2021, 5, 21));
+        assert_eq!(token.days_left(now.date().naive_utc()), Some(24));
+        assert!(!token.needs_reminder(now));
+
+        // Ten days out we remind weekly.
+        token.expires = Some(NaiveDate::from_ymd(2021, 5, 7));
+        assert!(token.needs_reminder(now));
+        token.last_reminded_at = Some(now - Duration::days(3));
+        assert!(!token.needs_reminder(now));
+        token.last_reminded_at = Some(now - Duration::days(7) + Duration::minutes(30));
+        assert!(token.needs_reminder(now));
+
+        // In the last week we remind daily, including once it expired.
+        token.expires = Some(NaiveDate::from_ymd(2021, 4, 25));
+        token.last_reminded_at = Some(now - Duration::hours(12));
+        assert!(!token.needs_reminder(now));
+        token.last_reminded_at = Some(now - Duration::days(1));
+        assert!(token.needs_reminder(now));
+
+        assert_eq!(format_expiry(3), "expires in 3 days");
+        assert_eq!(format_expiry(1), "expires tomorrow");
+        assert_eq!(format_expiry(0), "expires today");
+        assert_eq!(format_expiry(-1), "expired yesterday");
+        assert_eq!(format_expiry(-2), "expired 2 days ago");
+    }
+}
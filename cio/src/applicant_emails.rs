@@ -0,0 +1,576 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::Client;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tera::{Context, Tera};
+use tracing::instrument;
+
+use crate::applicant_status::Status;
+use crate::applicants::Applicant;
+use crate::configs::Config;
+use crate::db::Database;
+use crate::format::format_time;
+use crate::interviews::ApplicantInterview;
+use crate::rate_limits::check_throttled;
+use crate::schema::{applicant_emails, applicant_interviews, applicant_stage_changes, applicants};
+use crate::utils::{get_gmail_token, DOMAIN, GMAIL_SEND_SCOPE, GSUITE_DOMAIN};
+use crate::vcr;
+
+/// The mailbox we send mail to applicants from, if the `applicant_emails` section of
+/// our configs does not say.
+const DEFAULT_APPLICANT_EMAIL_MAILBOX: &str = "careers";
+
+/// The templates for the mail we send applicants, by name, as
+/// `(name, stages, raw status contains, subject, body)`. Templates in our configs with
+/// the same name replace these.
+pub static DEFAULT_APPLICANT_EMAIL_TEMPLATES: &[(&str, &[&str], &str, &str, &str)] = &[
+    (
+        "availability_request",
+        &[],
+        "",
+        "Next steps with Oxide Computer Company",
+        "Dear {{ name }},
+
+Thank you again for applying to Oxide! We would love to talk with you more about
+the {{ role }} role. Could you reply with a few times over the next two weeks that
+work for you, along with your timezone?
+
+Sincerely,
+  The Oxide Team",
+    ),
+    (
+        "interview_confirmation",
+        &[],
+        "",
+        "Your interviews with Oxide Computer Company",
+        "Dear {{ name }},
+
+We are looking forward to talking with you! Here are your interviews:
+{% for interview in interviews %}
+  - {{ interview }}{% endfor %}
+
+If any of these times no longer work for you, reply to this email and we will
+find a new time.
+
+Sincerely,
+  The Oxide Team",
+    ),
+    (
+        "rejection_materials",
+        &["Declined", "Deferred"],
+        "did not do materials",
+        "Thank you for your application, {{ name }}",
+        "Dear {{ name }},
+
+Unfortunately, we cannot accept it at this time since you failed to provide the
+requested materials.
+
+All the best,
+The Oxide Team",
+    ),
+    (
+        "rejection_timing",
+        &["Declined", "Deferred"],
+        "",
+        "Thank you for your application, {{ name }}",
+        "Dear {{ name }},
+
+We are so humbled by your application to join Oxide Computer Company. At this
+stage of the company we are hyper-focused on certain areas of the stack and
+when we need specific domain space experience such as yours, please engage
+with us. Our roles will be updated as we need them.
+
+We are grateful you took the time to apply and put so much thought into the
+candidate materials, we loved reading them. We would absolutely love to work
+with you in the future and cannot wait for that stage of the company!
+
+All the best,
+The Oxide Team",
+    ),
+];
+
+/// A template for mail to applicants. The subject and body are Tera templates with the
+/// `name`, `first_name`, `email`, and `role` of the applicant, and their upcoming
+/// `interviews`.
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ApplicantEmailTemplate {
+    /// The stages that send the email when an applicant moves to them, ie. `Declined`.
+    /// Templates without stages are only sent by hand with `cio applicants email`.
+    #[serde(default)]
+    pub stages: Vec<String>,
+    /// Only send the email when moving to a stage if the raw status of the applicant
+    /// contains this, ie. `did not do materials`. Templates with this set win over those
+    /// without it for the same stage.
+    #[serde(default)]
+    pub raw_status_contains: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// How we send mail to applicants, from the `applicant_emails` section of our configs.
+///
+/// ```toml
+/// [applicant_emails]
+/// mailbox = "careers"
+/// from = "careers@oxide.computer"
+/// from_name = "Oxide Careers"
+///
+/// [applicant_emails.templates.availability_request]
+/// stages = ["Next steps"]
+/// subject = "Next steps with Oxide Computer Company"
+/// body = """Dear {{ first_name }}, ..."""
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ApplicantEmailsConfig {
+    /// The username of the mailbox we send from. Defaults to `careers`.
+    #[serde(default)]
+    pub mailbox: String,
+    /// The shared alias we send as, which must be a "send mail as" address of the
+    /// mailbox. Defaults to `careers@oxide.computer`.
+    #[serde(default)]
+    pub from: String,
+    #[serde(default)]
+    pub from_name: String,
+    /// Templates by name, replacing the default template with the same name.
+    #[serde(default)]
+    pub templates: BTreeMap<String, ApplicantEmailTemplate>,
+}
+
+impl ApplicantEmailsConfig {
+    /// Get the username of the mailbox we send from.
+    #[instrument]
+    #[inline]
+    pub fn mailbox(&self) -> String {
+        if self.mailbox.is_empty() {
+            return DEFAULT_APPLICANT_EMAIL_MAILBOX.to_string();
+        }
+
+        self.mailbox.to_string()
+    }
+
+    /// Get the address we send as, with the name if we have one.
+    #[instrument]
+    #[inline]
+    pub fn from_header(&self) -> String {
+        let from = if self.from.is_empty() {
+            format!("{}@{}", DEFAULT_APPLICANT_EMAIL_MAILBOX, DOMAIN)
+        } else {
+            self.from.to_string()
+        };
+        if self.from_name.is_empty() {
+            return from;
+        }
+
+        format!("{} <{}>", encode_header(&self.from_name), from)
+    }
+
+    /// Get every template, the defaults with the ones from our configs on top.
+    #[instrument]
+    #[inline]
+    pub fn all_templates(&self) -> BTreeMap<String, ApplicantEmailTemplate> {
+        let mut templates: BTreeMap<String, ApplicantEmailTemplate> = DEFAULT_APPLICANT_EMAIL_TEMPLATES
+            .iter()
+            .map(|(name, stages, raw_status_contains, subject, body)| {
+                (
+                    name.to_string(),
+                    ApplicantEmailTemplate {
+                        stages: stages.iter().map(|s| s.to_string()).collect(),
+                        raw_status_contains: raw_status_contains.to_string(),
+                        subject: subject.to_string(),
+                        body: body.to_string(),
+                    },
+                )
+            })
+            .collect();
+        for (name, template) in self.templates.iter() {
+            templates.insert(name.to_string(), template.clone());
+        }
+
+        templates
+    }
+
+    /// Get the template to send an applicant when they move to a stage, if any.
+    #[instrument]
+    #[inline]
+    pub fn template_for_stage(&self, status: Status, raw_status: &str) -> Option<(String, ApplicantEmailTemplate)> {
+        let raw_status = raw_status.to_lowercase();
+        let mut matching: Vec<(String, ApplicantEmailTemplate)> = self
+            .all_templates()
+            .into_iter()
+            .filter(|(_, t)| t.stages.iter().any(|s| Status::from_str(s) == Ok(status)))
+            .filter(|(_, t)| raw_status.contains(&t.raw_status_contains.to_lowercase()))
+            .collect();
+        // Templates that need something in the raw status are more specific.
+        matching.sort_by_key(|(name, t)| (t.raw_status_contains.is_empty(), name.to_string()));
+
+        matching.into_iter().next()
+    }
+}
+
+/// An applicant moving to a different stage of our hiring process, which may send them
+/// an email. We record these when they happen since the templates are in our configs,
+/// which only the jobs have.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "applicant_stage_changes"]
+pub struct NewApplicantStageChange {
+    pub applicant_id: i32,
+    pub from_status: Status,
+    pub to_status: Status,
+    pub changed_at: DateTime<Utc>,
+    /// When the `applicant_emails` job looked at the change.
+    pub processed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct ApplicantStageChange {
+    pub id: i32,
+    pub applicant_id: i32,
+    pub from_status: Status,
+    pub to_status: Status,
+    pub changed_at: DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>,
+}
+
+/// The log of a template we sent an applicant. There is one per applicant and template,
+/// which is what keeps us from sending the same email twice: the row is claimed before
+/// sending, and only failed sends are tried again.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "applicant_emails"]
+pub struct NewApplicantEmail {
+    pub applicant_id: i32,
+    pub template: String,
+    pub to_email: String,
+    pub subject: String,
+    /// `sending`, `sent`, or `failed`. An email left `sending` means we do not know if
+    /// it went out, so it is not sent again.
+    pub status: String,
+    pub gmail_id: String,
+    pub thread_id: String,
+    pub error: String,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, AsChangeset)]
+#[table_name = "applicant_emails"]
+pub struct ApplicantEmail {
+    pub id: i32,
+    pub applicant_id: i32,
+    pub template: String,
+    pub to_email: String,
+    pub subject: String,
+    pub status: String,
+    pub gmail_id: String,
+    pub thread_id: String,
+    pub error: String,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// Record an applicant moving to a different stage, for the `applicant_emails` job.
+#[instrument(skip(db))]
+#[inline]
+pub fn record_stage_change(db: &Database, applicant_id: i32, from: Status, to: Status) {
+    diesel::insert_into(applicant_stage_changes::table)
+        .values(&NewApplicantStageChange {
+            applicant_id,
+            from_status: from,
+            to_status: to,
+            changed_at: Utc::now(),
+            processed_at: None,
+        })
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("recording the stage change of applicant {} failed: {}", applicant_id, e));
+}
+
+/// Render the subject and body of a template for an applicant.
+#[instrument(skip(context))]
+#[inline]
+pub fn render_applicant_email(template: &ApplicantEmailTemplate, context: &Value) -> Result<(String, String), String> {
+    let context = Context::from_serialize(context).map_err(|e| format!("serializing the context failed: {:?}", e))?;
+    let subject = Tera::one_off(&template.subject, &context, false).map_err(|e| format!("rendering the subject failed: {:?}", e))?;
+    let body = Tera::one_off(&template.body, &context, false).map_err(|e| format!("rendering the body failed: {:?}", e))?;
+
+    Ok((subject.trim().to_string(), body))
+}
+
+/// Encode a header value as UTF-8 if it is not plain ASCII.
+/// FROM: https://tools.ietf.org/html/rfc2047
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+
+    format!("=?UTF-8?B?{}?=", base64::encode(value))
+}
+
+/// Build a plain text email as the Gmail API wants it, base64url encoded.
+#[instrument(skip(body))]
+#[inline]
+pub fn build_raw_email(from: &str, to: &str, subject: &str, body: &str) -> String {
+    let message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=\"UTF-8\"\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}\r\n",
+        from,
+        to,
+        encode_header(subject),
+        body.replace("\r\n", "\n").replace('\n', "\r\n")
+    );
+
+    base64::encode_config(message, base64::URL_SAFE)
+}
+
+/// Send a raw email from a mailbox. Returns the ID of the message and its thread.
+#[instrument(skip(raw))]
+#[inline]
+async fn send_gmail_message(mailbox: &str, raw: String) -> Result<(String, String), String> {
+    let token = get_gmail_token(&format!("{}@{}", mailbox, GSUITE_DOMAIN), GMAIL_SEND_SCOPE).await;
+    let client = Client::new();
+    let req = client
+        .post("https://gmail.googleapis.com/gmail/v1/users/me/messages/send")
+        .bearer_auth(token.as_str())
+        .json(&json!({ "raw": raw }));
+    let resp = vcr::send(&client, req).await.map_err(|e| e.to_string())?;
+    check_throttled("gsuite", &resp)?;
+
+    let status = resp.status();
+    let body: Value = resp.json().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(format!("sending mail from {} failed: {} {}", mailbox, status, body["error"]["message"].as_str().unwrap_or_default()));
+    }
+
+    Ok((body["id"].as_str().unwrap_or_default().to_string(), body["threadId"].as_str().unwrap_or_default().to_string()))
+}
+
+/// Claim sending a template to an applicant. Returns the log to fill in, or nothing if
+/// the email was already sent or is being sent.
+#[instrument(skip(db))]
+#[inline]
+fn claim_applicant_email(db: &Database, applicant: &Applicant, template: &str) -> Result<Option<ApplicantEmail>, String> {
+    let inserted = diesel::insert_into(applicant_emails::table)
+        .values(&NewApplicantEmail {
+            applicant_id: applicant.id,
+            template: template.to_string(),
+            to_email: applicant.email.to_string(),
+            subject: Default::default(),
+            status: "sending".to_string(),
+            gmail_id: Default::default(),
+            thread_id: Default::default(),
+            error: Default::default(),
+            created_at: Utc::now(),
+            sent_at: None,
+        })
+        .on_conflict((applicant_emails::applicant_id, applicant_emails::template))
+        .do_nothing()
+        .execute(&db.conn())
+        .map_err(|e| format!("logging the {} email to {} failed: {}", template, applicant.email, e))?;
+    if inserted == 0 {
+        // Only one of us gets to retry a failed send.
+        let retried = diesel::update(applicant_emails::table)
+            .filter(applicant_emails::applicant_id.eq(applicant.id))
+            .filter(applicant_emails::template.eq(template.to_string()))
+            .filter(applicant_emails::status.eq("failed"))
+            .set((applicant_emails::status.eq("sending"), applicant_emails::error.eq("")))
+            .execute(&db.conn())
+            .map_err(|e| format!("retrying the {} email to {} failed: {}", template, applicant.email, e))?;
+        if retried == 0 {
+            return Ok(None);
+        }
+    }
+
+    applicant_emails::table
+        .filter(applicant_emails::applicant_id.eq(applicant.id))
+        .filter(applicant_emails::template.eq(template.to_string()))
+        .first(&db.conn())
+        .map(Some)
+        .map_err(|e| format!("getting the {} email to {} failed: {}", template, applicant.email, e))
+}
+
+/// Send an applicant one of our templates from the shared alias, unless we already
+/// have. Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_applicant_email(db: &Database, config: &Config, applicant: &Applicant, template: &str) -> Result<String, String> {
+    let templates = config.applicant_emails.all_templates();
+    let t = templates.get(template).ok_or_else(|| {
+        format!(
+            "`{}` is not one of our applicant email templates, which are {}",
+            template,
+            templates.keys().cloned().collect::<Vec<_>>().join(", ")
+        )
+    })?;
+
+    let mut log = match claim_applicant_email(db, applicant, template)? {
+        Some(l) => l,
+        None => return Ok(format!("already sent the {} email to {}", template, applicant.email)),
+    };
+
+    let interviews: Vec<String> = applicant_interviews::dsl::applicant_interviews
+        .filter(applicant_interviews::dsl::email.eq(applicant.email.to_string()))
+        .filter(applicant_interviews::dsl::start_time.gt(Utc::now()))
+        .order_by(applicant_interviews::dsl::start_time)
+        .load::<ApplicantInterview>(&db.conn())
+        .unwrap_or_default()
+        .iter()
+        .map(|i| format!("{} with {}", format_time(i.start_time), i.interviewers.join(", ")))
+        .collect();
+    let context = json!({
+        "name": applicant.name,
+        "first_name": applicant.name.split_whitespace().next().unwrap_or_default(),
+        "email": applicant.email,
+        "role": applicant.role,
+        "interviews": interviews,
+    });
+
+    let result = match render_applicant_email(t, &context) {
+        Ok((subject, body)) => {
+            log.subject = subject.to_string();
+            let raw = build_raw_email(&config.applicant_emails.from_header(), &applicant.email, &subject, &body);
+            send_gmail_message(&config.applicant_emails.mailbox(), raw).await
+        }
+        Err(e) => Err(format!("the {} template: {}", template, e)),
+    };
+    match &result {
+        Ok((gmail_id, thread_id)) => {
+            log.status = "sent".to_string();
+            log.gmail_id = gmail_id.to_string();
+            log.thread_id = thread_id.to_string();
+            log.sent_at = Some(Utc::now());
+        }
+        Err(e) => {
+            log.status = "failed".to_string();
+            log.error = e.to_string();
+        }
+    }
+    diesel::update(applicant_emails::table.find(log.id))
+        .set(&log)
+        .execute(&db.conn())
+        .map_err(|e| format!("updating the log of the {} email to {} failed: {}", template, applicant.email, e))?;
+
+    result.map(|_| format!("sent the {} email to {}", template, applicant.email))
+}
+
+/// Send a template to the applicant with an email, for their most recent application.
+/// This is for the templates that are not sent by stage, ie. after scheduling
+/// interviews. Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_applicant_email_by_hand(db: &Database, config: &Config, email: &str, template: &str) -> Result<String, String> {
+    let applicant = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.trim().to_lowercase()))
+        .order_by(applicants::dsl::submitted_time.desc())
+        .first::<Applicant>(&db.conn())
+        .map_err(|_| format!("{} has not applied", email))?;
+
+    send_applicant_email(db, config, &applicant, template).await
+}
+
+/// Send the emails for the stages applicants moved to. Changes the applicant has since
+/// moved on from are skipped, so someone moved through a stage by mistake does not get
+/// its email.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_applicant_stage_emails(db: &Database, config: &Config) {
+    let changes: Vec<ApplicantStageChange> = applicant_stage_changes::table
+        .filter(applicant_stage_changes::processed_at.is_null())
+        .order_by(applicant_stage_changes::changed_at)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the applicant stage changes failed: {}", e));
+
+    for change in changes {
+        match applicants::dsl::applicants.find(change.applicant_id).first::<Applicant>(&db.conn()) {
+            Ok(applicant) if applicant.status == change.to_status => {
+                if let Some((template, _)) = config.applicant_emails.template_for_stage(applicant.status, &applicant.raw_status) {
+                    match send_applicant_email(db, config, &applicant, &template).await {
+                        Ok(done) => println!("[applicant_emails] {}", done),
+                        Err(e) => println!("[applicant_emails] {}", e),
+                    }
+                }
+            }
+            Ok(applicant) => println!(
+                "[applicant_emails] skipping the move of {} to {:?} since they are now {:?}",
+                applicant.email, change.to_status, applicant.status
+            ),
+            Err(e) => println!("[applicant_emails] applicant {} does not exist: {}", change.applicant_id, e),
+        }
+
+        diesel::update(applicant_stage_changes::table.find(change.id))
+            .set(applicant_stage_changes::processed_at.eq(Some(Utc::now())))
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("updating applicant stage change {} failed: {}", change.id, e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use crate::applicant_emails::{build_raw_email, render_applicant_email, ApplicantEmailTemplate, ApplicantEmailsConfig};
+    use crate::applicant_status::Status;
+
+    #[test]
+    fn test_template_for_stage() {
+        let mut config = ApplicantEmailsConfig::default();
+        let name = |status: Status, raw_status: &str, config: &ApplicantEmailsConfig| config.template_for_stage(status, raw_status).map(|(n, _)| n);
+
+        assert_eq!(name(Status::Declined, "Declined", &config), Some("rejection_timing".to_string()));
+        assert_eq!(name(Status::Deferred, "Deferred - did not do materials", &config), Some("rejection_materials".to_string()));
+        assert_eq!(name(Status::NextSteps, "Next steps", &config), None);
+
+        config.templates.insert(
+            "availability_request".to_string(),
+            ApplicantEmailTemplate {
+                stages: vec!["Next steps".to_string()],
+                raw_status_contains: "".to_string(),
+                subject: "Next steps".to_string(),
+                body: "Hi {{ first_name }}".to_string(),
+            },
+        );
+        assert_eq!(name(Status::NextSteps, "Next steps", &config), Some("availability_request".to_string()));
+        assert_eq!(config.all_templates().len(), 4);
+    }
+
+    #[test]
+    fn test_render_applicant_email() {
+        let templates: BTreeMap<String, ApplicantEmailTemplate> = ApplicantEmailsConfig::default().all_templates();
+        let context = json!({
+            "name": "Jane Doe",
+            "first_name": "Jane",
+            "email": "jane@example.com",
+            "role": "Engineering",
+            "interviews": ["2021-05-12 17:00 UTC with sam"],
+        });
+        for (name, template) in templates.iter() {
+            assert!(render_applicant_email(template, &context).is_ok(), "{}", name);
+        }
+
+        let (subject, body) = render_applicant_email(&templates["interview_confirmation"], &context).unwrap();
+        assert_eq!(subject, "Your interviews with Oxide Computer Company");
+        assert!(body.contains("\n  - 2021-05-12 17:00 UTC with sam\n"), "{}", body);
+    }
+
+    #[test]
+    fn test_build_raw_email() {
+        let config = ApplicantEmailsConfig {
+            from: "careers@oxide.computer".to_string(),
+            from_name: "Oxide Careers".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.from_header(), "Oxide Careers <careers@oxide.computer>");
+        assert_eq!(ApplicantEmailsConfig::default().from_header(), "careers@oxide.computer");
+        assert_eq!(ApplicantEmailsConfig::default().mailbox(), "careers");
+
+        let raw = build_raw_email(&config.from_header(), "jane@example.com", "Merci, Zoë", "Hi\nthere");
+        let message = String::from_utf8(base64::decode_config(raw, base64::URL_SAFE).unwrap()).unwrap();
+        assert_eq!(
+            message,
+            "From: Oxide Careers <careers@oxide.computer>\r\nTo: jane@example.com\r\nSubject: =?UTF-8?B?TWVyY2ksIFpvw6s=?=\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=\"UTF-8\"\r\nContent-Transfer-Encoding: 8bit\r\n\r\nHi\r\nthere\r\n"
+        );
+    }
+}
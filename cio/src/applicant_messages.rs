@@ -0,0 +1,620 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::configs::Config;
+use crate::db::Database;
+use crate::format::format_time;
+use crate::schema::{applicant_messages, applicant_threads, applicants};
+use crate::utils::{get_gmail_token, google_api, DOMAIN, GMAIL_READONLY_SCOPE, GSUITE_DOMAIN};
+
+/// The headers of each message we keep for the timeline.
+static GMAIL_METADATA_HEADERS: &[&str] = &["From", "To", "Cc", "Delivered-To", "Subject", "Message-ID"];
+
+/// How far back to look through a mailbox the first time.
+const APPLICANT_MESSAGES_INITIAL_DAYS: i64 = 90;
+
+/// A thread with mail to or from the applicant's email.
+pub static LINKED_BY_ADDRESS: &str = "address";
+
+/// A thread someone cc'd the applicant's plus addressed alias on.
+pub static LINKED_BY_ALIAS: &str = "alias";
+
+/// A thread someone linked by hand by the Message-ID of one of its messages.
+pub static LINKED_BY_MESSAGE_ID: &str = "message_id";
+
+/// Where we look for mail with applicants, from the `applicant_messages` section of
+/// our configs.
+///
+/// ```toml
+/// [applicant_messages]
+/// mailboxes = ["careers"]
+/// alias = "careers"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ApplicantMessagesConfig {
+    /// The usernames of the mailboxes we look through for mail with applicants.
+    #[serde(default)]
+    pub mailboxes: Vec<String>,
+    /// The alias we plus address for each applicant, ie. `careers` for
+    /// `careers+42@oxide.computer`. Cc'ing it on mail from any inbox links the thread to
+    /// the applicant, as long as the alias delivers to one of the mailboxes.
+    #[serde(default)]
+    pub alias: String,
+}
+
+impl ApplicantMessagesConfig {
+    /// Get the plus addressed alias for an applicant.
+    #[instrument]
+    #[inline]
+    pub fn applicant_alias(&self, applicant_id: i32) -> Option<String> {
+        if self.alias.is_empty() {
+            return None;
+        }
+
+        Some(format!("{}+{}@{}", self.alias, applicant_id, DOMAIN))
+    }
+
+    /// Get the applicant a plus addressed alias is for, ie. `42` for
+    /// `careers+42@oxide.computer`.
+    #[instrument]
+    #[inline]
+    pub fn parse_applicant_alias(&self, address: &str) -> Option<i32> {
+        if self.alias.is_empty() {
+            return None;
+        }
+        let (local, domain) = address.rsplit_once('@')?;
+        if domain != DOMAIN && domain != GSUITE_DOMAIN {
+            return None;
+        }
+
+        local.strip_prefix(&format!("{}+", self.alias))?.parse().ok()
+    }
+}
+
+/// A thread in one of our mailboxes linked to an applicant. Every message in it is part
+/// of the applicant's timeline.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "applicant_threads"]
+pub struct NewApplicantThread {
+    pub applicant_id: i32,
+    /// The username of the mailbox the thread is in.
+    pub mailbox: String,
+    pub thread_id: String,
+    /// How we linked the thread, `address`, `alias`, or `message_id`.
+    pub linked_by: String,
+    pub linked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct ApplicantThread {
+    pub id: i32,
+    pub applicant_id: i32,
+    pub mailbox: String,
+    pub thread_id: String,
+    pub linked_by: String,
+    pub linked_at: DateTime<Utc>,
+}
+
+/// A message in the timeline of mail with an applicant. We only keep the headers and
+/// the snippet Gmail gives us, not the body.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "applicant_messages"]
+pub struct NewApplicantMessage {
+    pub applicant_id: i32,
+    pub mailbox: String,
+    pub thread_id: String,
+    /// The ID of the message in the mailbox in Gmail.
+    pub gmail_id: String,
+    /// The `Message-ID` header, which is the same in every mailbox the message is in.
+    pub message_id: String,
+    pub from_email: String,
+    /// Everyone the message was to, cc'd, or delivered to.
+    pub recipients: Vec<String>,
+    pub subject: String,
+    pub snippet: String,
+    /// `outbound` if one of us sent it, otherwise `inbound`.
+    pub direction: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct ApplicantMessage {
+    pub id: i32,
+    pub applicant_id: i32,
+    pub mailbox: String,
+    pub thread_id: String,
+    pub gmail_id: String,
+    pub message_id: String,
+    pub from_email: String,
+    pub recipients: Vec<String>,
+    pub subject: String,
+    pub snippet: String,
+    pub direction: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+/// A message from the Gmail API, fetched with the `metadata` format.
+/// FROM: https://developers.google.com/gmail/api/reference/rest/v1/users.messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct GmailMessage {
+    pub id: String,
+    pub thread_id: String,
+    pub message_id: String,
+    pub from: String,
+    pub recipients: Vec<String>,
+    pub subject: String,
+    pub snippet: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+impl GmailMessage {
+    /// Parse a message from the Gmail API.
+    #[instrument(skip(message))]
+    #[inline]
+    pub fn from_api(message: &Value) -> Option<Self> {
+        let headers = message["payload"]["headers"].as_array().cloned().unwrap_or_default();
+        let header = |name: &str| -> String {
+            headers
+                .iter()
+                .filter(|h| h["name"].as_str().unwrap_or_default().eq_ignore_ascii_case(name))
+                .filter_map(|h| h["value"].as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut recipients: Vec<String> = ["To", "Cc", "Delivered-To"].iter().flat_map(|h| parse_addresses(&header(*h))).collect();
+        recipients.sort();
+        recipients.dedup();
+
+        Some(GmailMessage {
+            id: message["id"].as_str()?.to_string(),
+            thread_id: message["threadId"].as_str()?.to_string(),
+            message_id: header("Message-ID").trim().trim_start_matches('<').trim_end_matches('>').to_string(),
+            from: parse_addresses(&header("From")).into_iter().next().unwrap_or_default(),
+            recipients,
+            subject: header("Subject"),
+            snippet: unescape_snippet(message["snippet"].as_str().unwrap_or_default()),
+            sent_at: Utc.timestamp_millis(message["internalDate"].as_str()?.parse().ok()?),
+        })
+    }
+
+    /// Get if one of us sent the message.
+    #[instrument]
+    #[inline]
+    pub fn direction(&self) -> &'static str {
+        if self.from.ends_with(&format!("@{}", DOMAIN)) || self.from.ends_with(&format!("@{}", GSUITE_DOMAIN)) {
+            "outbound"
+        } else {
+            "inbound"
+        }
+    }
+
+    /// Find the applicant the message is with, by their alias or their email, from the
+    /// most recent application for each email. Returns the applicant and how we found them.
+    #[instrument(skip(config, applicants))]
+    #[inline]
+    pub fn match_applicant(&self, config: &ApplicantMessagesConfig, applicants: &HashMap<String, i32>) -> Option<(i32, &'static str)> {
+        let mut addresses = vec![&self.from];
+        addresses.extend(self.recipients.iter());
+
+        if let Some(id) = addresses.iter().filter_map(|a| config.parse_applicant_alias(a)).find(|id| applicants.values().any(|a| a == id)) {
+            return Some((id, LINKED_BY_ALIAS));
+        }
+
+        addresses.iter().find_map(|a| applicants.get(*a)).map(|id| (*id, LINKED_BY_ADDRESS))
+    }
+
+    /// Get the message for the timeline of an applicant.
+    #[instrument]
+    #[inline]
+    pub fn to_applicant_message(&self, applicant_id: i32, mailbox: &str) -> NewApplicantMessage {
+        NewApplicantMessage {
+            applicant_id,
+            mailbox: mailbox.to_string(),
+            thread_id: self.thread_id.to_string(),
+            gmail_id: self.id.to_string(),
+            message_id: self.message_id.to_string(),
+            from_email: self.from.to_string(),
+            recipients: self.recipients.clone(),
+            subject: self.subject.to_string(),
+            snippet: self.snippet.to_string(),
+            direction: self.direction().to_string(),
+            sent_at: self.sent_at,
+        }
+    }
+}
+
+/// Parse the email addresses out of an address header, ie.
+/// `"Doe, Jane" <jane@example.com>, sam@example.com`. Addresses are lowercased.
+#[instrument]
+#[inline]
+pub fn parse_addresses(header: &str) -> Vec<String> {
+    let mut parts: Vec<String> = Default::default();
+    let mut part = String::new();
+    let mut quoted = false;
+    for c in header.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => parts.push(std::mem::take(&mut part)),
+            _ => part.push(c),
+        }
+    }
+    parts.push(part);
+
+    parts
+        .iter()
+        .filter_map(|p| {
+            let address = match (p.rfind('<'), p.rfind('>')) {
+                (Some(start), Some(end)) if start < end => &p[start + 1..end],
+                _ => p.as_str(),
+            };
+            let address = address.trim().to_lowercase();
+            if address.contains('@') {
+                Some(address)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Gmail escapes the snippets of messages as HTML.
+fn unescape_snippet(snippet: &str) -> String {
+    snippet.replace("&#39;", "'").replace("&quot;", "\"").replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// Get the username of a mailbox from a username or an email.
+fn mailbox_username(mailbox: &str) -> String {
+    mailbox.trim_end_matches(&format!("@{}", GSUITE_DOMAIN)).to_string()
+}
+
+/// Send a request to the Gmail API for the mailbox the token is for.
+#[instrument(skip(token))]
+#[inline]
+async fn gmail_api(token: &str, path: &str, query: &[(&str, &str)]) -> Result<Value, String> {
+    let query: Vec<(&str, String)> = query.iter().map(|(k, v)| (*k, v.to_string())).collect();
+
+    google_api(token, Method::GET, &format!("https://gmail.googleapis.com/gmail/v1/users/me{}", path), &query, None).await
+}
+
+/// List the IDs of the messages in a mailbox matching a Gmail search.
+#[instrument(skip(token))]
+#[inline]
+async fn list_gmail_messages(token: &str, q: &str) -> Result<Vec<String>, String> {
+    let mut ids: Vec<String> = Default::default();
+    let mut page_token = String::new();
+    loop {
+        let mut query = vec![("q", q), ("maxResults", "500")];
+        if !page_token.is_empty() {
+            query.push(("pageToken", &page_token));
+        }
+        let resp = gmail_api(token, "/messages", &query).await?;
+        ids.extend(resp["messages"].as_array().cloned().unwrap_or_default().iter().filter_map(|m| m["id"].as_str().map(|s| s.to_string())));
+
+        page_token = resp["nextPageToken"].as_str().unwrap_or_default().to_string();
+        if page_token.is_empty() {
+            return Ok(ids);
+        }
+    }
+}
+
+/// Get the query for fetching messages or threads with only the headers we keep.
+fn metadata_query() -> Vec<(&'static str, &'static str)> {
+    let mut query = vec![("format", "metadata")];
+    query.extend(GMAIL_METADATA_HEADERS.iter().map(|h| ("metadataHeaders", *h)));
+    query
+}
+
+/// Get a message in a mailbox.
+#[instrument(skip(token))]
+#[inline]
+async fn get_gmail_message(token: &str, id: &str) -> Result<GmailMessage, String> {
+    let message = gmail_api(token, &format!("/messages/{}", id), &metadata_query()).await?;
+    GmailMessage::from_api(&message).ok_or_else(|| format!("gmail message {} is missing its ID, thread, or date", id))
+}
+
+/// Get the messages of a thread in a mailbox.
+#[instrument(skip(token))]
+#[inline]
+async fn get_gmail_thread(token: &str, thread_id: &str) -> Result<Vec<GmailMessage>, String> {
+    let thread = gmail_api(token, &format!("/threads/{}", thread_id), &metadata_query()).await?;
+    Ok(thread["messages"].as_array().cloned().unwrap_or_default().iter().filter_map(GmailMessage::from_api).collect())
+}
+
+/// Link a thread to an applicant, unless it is linked already. Returns the link, which
+/// may be to a different applicant if someone got there first.
+#[instrument(skip(db))]
+#[inline]
+fn link_thread(db: &Database, applicant_id: i32, mailbox: &str, thread_id: &str, linked_by: &str) -> Result<ApplicantThread, String> {
+    diesel::insert_into(applicant_threads::table)
+        .values(&NewApplicantThread {
+            applicant_id,
+            mailbox: mailbox.to_string(),
+            thread_id: thread_id.to_string(),
+            linked_by: linked_by.to_string(),
+            linked_at: Utc::now(),
+        })
+        .on_conflict((applicant_threads::mailbox, applicant_threads::thread_id))
+        .do_nothing()
+        .execute(&db.conn())
+        .map_err(|e| format!("linking thread {} in {} to applicant {} failed: {}", thread_id, mailbox, applicant_id, e))?;
+
+    applicant_threads::table
+        .filter(applicant_threads::mailbox.eq(mailbox.to_string()))
+        .filter(applicant_threads::thread_id.eq(thread_id.to_string()))
+        .first(&db.conn())
+        .map_err(|e| format!("getting thread {} in {} failed: {}", thread_id, mailbox, e))
+}
+
+/// Save messages to the timeline of an applicant, skipping those we have. Returns how
+/// many were new.
+#[instrument(skip(db, messages))]
+#[inline]
+fn save_messages(db: &Database, applicant_id: i32, mailbox: &str, messages: &[GmailMessage]) -> usize {
+    let rows: Vec<NewApplicantMessage> = messages.iter().map(|m| m.to_applicant_message(applicant_id, mailbox)).collect();
+    diesel::insert_into(applicant_messages::table)
+        .values(&rows)
+        .on_conflict((applicant_messages::mailbox, applicant_messages::gmail_id))
+        .do_nothing()
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("saving the messages for applicant {} failed: {}", applicant_id, e))
+}
+
+/// Get the most recent application for each applicant email.
+#[instrument(skip(db))]
+#[inline]
+fn applicants_by_email(db: &Database) -> HashMap<String, i32> {
+    let rows: Vec<(i32, String)> = applicants::dsl::applicants
+        .select((applicants::dsl::id, applicants::dsl::email))
+        .order_by(applicants::dsl::submitted_time)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the applicants failed: {}", e));
+
+    // Later applications replace earlier ones.
+    rows.into_iter().map(|(id, email)| (email.trim().to_lowercase(), id)).collect()
+}
+
+/// Sync the mail with applicants into the database, so anyone picking up a candidate
+/// can see what we already said to them. New mail in the mailboxes in our configs is
+/// linked to an applicant by the thread it is in, their alias, or their email. Threads
+/// linked by hand in other mailboxes are fetched again in full.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn refresh_applicant_messages(db: &Database, config: &Config) {
+    let applicants = applicants_by_email(db);
+    let threads: Vec<ApplicantThread> = applicant_threads::table.load(&db.conn()).unwrap_or_else(|e| panic!("getting the applicant threads failed: {}", e));
+
+    for mailbox in config.applicant_messages.mailboxes.iter() {
+        let token = get_gmail_token(&format!("{}@{}", mailbox, GSUITE_DOMAIN), GMAIL_READONLY_SCOPE).await;
+        let since = applicant_messages::table
+            .filter(applicant_messages::mailbox.eq(mailbox.to_string()))
+            .select(diesel::dsl::max(applicant_messages::sent_at))
+            .first::<Option<DateTime<Utc>>>(&db.conn())
+            .unwrap_or_else(|e| panic!("getting the last message in {} failed: {}", mailbox, e))
+            .map(|t| t - Duration::days(1))
+            .unwrap_or_else(|| Utc::now() - Duration::days(APPLICANT_MESSAGES_INITIAL_DAYS));
+
+        let ids = match list_gmail_messages(token.as_str(), &format!("after:{}", since.timestamp())).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                println!("[applicant_messages] listing the mail in {} failed: {}", mailbox, e);
+                continue;
+            }
+        };
+        let seen: HashSet<String> = applicant_messages::table
+            .filter(applicant_messages::mailbox.eq(mailbox.to_string()))
+            .filter(applicant_messages::sent_at.ge(since))
+            .select(applicant_messages::gmail_id)
+            .load::<String>(&db.conn())
+            .unwrap_or_else(|e| panic!("getting the messages in {} failed: {}", mailbox, e))
+            .into_iter()
+            .collect();
+        let mut linked: HashMap<String, i32> = threads.iter().filter(|t| t.mailbox == *mailbox).map(|t| (t.thread_id.to_string(), t.applicant_id)).collect();
+
+        let mut saved = 0;
+        for id in ids.iter().filter(|id| !seen.contains(*id)) {
+            let message = match get_gmail_message(token.as_str(), id).await {
+                Ok(m) => m,
+                Err(e) => {
+                    println!("[applicant_messages] {}", e);
+                    continue;
+                }
+            };
+
+            let applicant_id = match linked.get(&message.thread_id) {
+                Some(applicant_id) => *applicant_id,
+                None => {
+                    let (applicant_id, linked_by) = match message.match_applicant(&config.applicant_messages, &applicants) {
+                        Some(m) => m,
+                        None => continue,
+                    };
+                    match link_thread(db, applicant_id, mailbox, &message.thread_id, linked_by) {
+                        Ok(thread) => {
+                            linked.insert(thread.thread_id.to_string(), thread.applicant_id);
+                            thread.applicant_id
+                        }
+                        Err(e) => {
+                            println!("[applicant_messages] {}", e);
+                            continue;
+                        }
+                    }
+                }
+            };
+            saved += save_messages(db, applicant_id, mailbox, &[message]);
+        }
+        println!("[applicant_messages] saved {} messages with applicants from {} since {}", saved, mailbox, format_time(since));
+    }
+
+    let mut other: BTreeMap<String, Vec<&ApplicantThread>> = Default::default();
+    for thread in threads.iter().filter(|t| !config.applicant_messages.mailboxes.contains(&t.mailbox)) {
+        other.entry(thread.mailbox.to_string()).or_default().push(thread);
+    }
+    for (mailbox, threads) in other {
+        let token = get_gmail_token(&format!("{}@{}", mailbox, GSUITE_DOMAIN), GMAIL_READONLY_SCOPE).await;
+        for thread in threads {
+            match get_gmail_thread(token.as_str(), &thread.thread_id).await {
+                Ok(messages) => {
+                    save_messages(db, thread.applicant_id, &mailbox, &messages);
+                }
+                Err(e) => println!("[applicant_messages] getting thread {} in {} failed: {}", thread.thread_id, mailbox, e),
+            }
+        }
+    }
+}
+
+/// Link the thread a message is in to an applicant's most recent application, by the
+/// `Message-ID` header of the message. This works for any mailbox, ie. for mail someone
+/// sent from their own inbox without cc'ing the applicant's alias. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn link_applicant_thread(db: &Database, email: &str, mailbox: &str, message_id: &str) -> Result<String, String> {
+    let applicant_id = *applicants_by_email(db).get(&email.trim().to_lowercase()).ok_or_else(|| format!("{} has not applied", email))?;
+    let mailbox = mailbox_username(mailbox);
+    let message_id = message_id.trim().trim_start_matches('<').trim_end_matches('>');
+
+    let token = get_gmail_token(&format!("{}@{}", mailbox, GSUITE_DOMAIN), GMAIL_READONLY_SCOPE).await;
+    let id = list_gmail_messages(token.as_str(), &format!("rfc822msgid:{}", message_id))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("there is no message with Message-ID {} in the mailbox of {}", message_id, mailbox))?;
+    let message = get_gmail_message(token.as_str(), &id).await?;
+
+    let thread = link_thread(db, applicant_id, &mailbox, &message.thread_id, LINKED_BY_MESSAGE_ID)?;
+    if thread.applicant_id != applicant_id {
+        return Err(format!("the thread \"{}\" is already linked to applicant {}", message.subject, thread.applicant_id));
+    }
+    let messages = get_gmail_thread(token.as_str(), &thread.thread_id).await?;
+    let saved = save_messages(db, applicant_id, &mailbox, &messages);
+
+    Ok(format!(
+        "linked the thread \"{}\" in the mailbox of {} to {}, with {} new messages",
+        message.subject, mailbox, email, saved
+    ))
+}
+
+/// Get the mail with an applicant across all their applications, oldest first. Messages
+/// that are in more than one mailbox are only included once.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_applicant_timeline(db: &Database, email: &str) -> Vec<ApplicantMessage> {
+    let ids: Vec<i32> = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.trim().to_lowercase()))
+        .select(applicants::dsl::id)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the applications for {} failed: {}", email, e));
+    let mut messages: Vec<ApplicantMessage> = applicant_messages::table
+        .filter(applicant_messages::applicant_id.eq_any(ids))
+        .order_by(applicant_messages::sent_at)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the messages for {} failed: {}", email, e));
+
+    let mut seen: HashSet<String> = Default::default();
+    messages.retain(|m| m.message_id.is_empty() || seen.insert(m.message_id.to_string()));
+    messages
+}
+
+/// Format the mail with an applicant as a timeline, a message per entry.
+#[instrument(skip(messages))]
+#[inline]
+pub fn format_applicant_timeline(email: &str, alias: Option<String>, messages: &[ApplicantMessage]) -> String {
+    let mut text = String::new();
+    if messages.is_empty() {
+        text += &format!("There is no mail with {} yet.\n", email);
+    }
+    for m in messages {
+        text += &format!("{}  {:<8} {} to {}: {}\n", format_time(m.sent_at), m.direction, m.from_email, m.recipients.join(", "), m.subject);
+        if !m.snippet.is_empty() {
+            text += &format!("    {}\n", m.snippet);
+        }
+    }
+    if let Some(alias) = alias {
+        text += &format!("Cc {} on mail from your own inbox to add it here.\n", alias);
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::applicant_messages::{format_applicant_timeline, parse_addresses, ApplicantMessagesConfig, GmailMessage, LINKED_BY_ADDRESS, LINKED_BY_ALIAS};
+
+    #[test]
+    fn test_parse_addresses() {
+        assert_eq!(
+            parse_addresses(r#""Doe, Jane" <Jane@Example.com>, sam@example.com, Oxide Careers <careers@oxide.computer>"#),
+            vec!["jane@example.com", "sam@example.com", "careers@oxide.computer"]
+        );
+        assert_eq!(parse_addresses("undisclosed-recipients:;"), Vec::<String>::new());
+        assert_eq!(parse_addresses(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_applicant_alias() {
+        let config = ApplicantMessagesConfig {
+            mailboxes: vec!["careers".to_string()],
+            alias: "careers".to_string(),
+        };
+        assert_eq!(config.applicant_alias(42), Some("careers+42@oxide.computer".to_string()));
+        assert_eq!(config.parse_applicant_alias("careers+42@oxide.computer"), Some(42));
+        assert_eq!(config.parse_applicant_alias("careers+42@oxidecomputer.com"), Some(42));
+        assert_eq!(config.parse_applicant_alias("careers+42@example.com"), None);
+        assert_eq!(config.parse_applicant_alias("careers@oxide.computer"), None);
+        assert_eq!(ApplicantMessagesConfig::default().applicant_alias(42), None);
+    }
+
+    #[test]
+    fn test_gmail_message() {
+        let message = GmailMessage::from_api(&json!({
+            "id": "179438f0c2b1a5e3",
+            "threadId": "179438f0c2b1a5e3",
+            "snippet": "Thanks for getting back to me! I&#39;m free on Thursday.",
+            "internalDate": "1620061200000",
+            "payload": {
+                "headers": [
+                    { "name": "From", "value": "Sam Smith <Sam@Example.com>" },
+                    { "name": "To", "value": "Jane Doe <jane@oxidecomputer.com>" },
+                    { "name": "Cc", "value": "careers+42@oxide.computer" },
+                    { "name": "Subject", "value": "Re: Your application" },
+                    { "name": "Message-ID", "value": "<CAF=abc123@mail.gmail.com>" }
+                ]
+            }
+        }))
+        .unwrap();
+        assert_eq!(message.from, "sam@example.com");
+        assert_eq!(message.recipients, vec!["careers+42@oxide.computer", "jane@oxidecomputer.com"]);
+        assert_eq!(message.message_id, "CAF=abc123@mail.gmail.com");
+        assert_eq!(message.snippet, "Thanks for getting back to me! I'm free on Thursday.");
+        assert_eq!(message.sent_at, Utc.ymd(2021, 5, 3).and_hms(17, 0, 0));
+        assert_eq!(message.direction(), "inbound");
+
+        let config = ApplicantMessagesConfig {
+            mailboxes: vec!["careers".to_string()],
+            alias: "careers".to_string(),
+        };
+        let mut applicants = HashMap::new();
+        applicants.insert("sam@example.com".to_string(), 41);
+        assert_eq!(message.match_applicant(&config, &applicants), Some((41, LINKED_BY_ADDRESS)));
+        applicants.insert("sam.smith@example.com".to_string(), 42);
+        assert_eq!(message.match_applicant(&config, &applicants), Some((42, LINKED_BY_ALIAS)));
+        assert_eq!(message.match_applicant(&config, &HashMap::new()), None);
+
+        let timeline = format_applicant_timeline("sam@example.com", config.applicant_alias(42), &[]);
+        assert_eq!(
+            timeline,
+            "There is no mail with sam@example.com yet.\nCc careers+42@oxide.computer on mail from your own inbox to add it here.\n"
+        );
+    }
+}
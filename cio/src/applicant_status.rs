@@ -1,9 +1,15 @@
 use std::str::FromStr;
 
+use diesel::sql_types::Text;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-/// The various different statuses that an applicant can be in.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+/// The various different statuses that an applicant can be in. These are the options of
+/// the status column in Airtable, and applicants with any other status are rejected when
+/// we read them from Airtable or the database.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, JsonSchema, FromSqlRow, AsExpression, Deserialize, Serialize)]
+#[sql_type = "Text"]
 pub enum Status {
     /// The applicant has been hired.
     Hired,
@@ -13,6 +19,7 @@ pub enum Status {
     Onboarding,
 
     /// We are giving an offer to the applicant.
+    #[serde(rename = "Giving offer")]
     GivingOffer,
 
     /// We are in the process of interviewing the applicant.
@@ -23,18 +30,21 @@ pub enum Status {
 
     /// We are taking next steps with the applicant. This usually includes
     /// talking to them a bit more but not going into full fledged interviews yet.
+    #[serde(rename = "Next steps")]
     NextSteps,
 
     /// The applicant has been declined.
     Declined,
 
     /// The applicant needs to be triaged.
+    #[serde(rename = "Needs to be triaged")]
     NeedsToBeTriaged,
 
     /// The applicant has been hired as a contractor.
     Contractor,
 
     /// We are keeping the applicant warm.
+    #[serde(rename = "Keeping warm")]
     KeepingWarm,
 }
 
@@ -46,6 +56,8 @@ impl Default for Status {
     }
 }
 
+/// Parse a status from the raw status people write in the applications spreadsheet, ie.
+/// "Declined - timing". Anything we do not recognize needs to be triaged.
 impl FromStr for Status {
     type Err = &'static str;
 
@@ -78,21 +90,21 @@ impl FromStr for Status {
     }
 }
 
-impl ToString for Status {
+crate::text_enum!(Status);
+
+impl Status {
+    /// Returns the next stage in our hiring process, or `None` if the applicant is not
+    /// moving through the stages, ie. they were declined or already hired.
     #[instrument]
     #[inline]
-    fn to_string(&self) -> String {
+    pub fn next(&self) -> Option<Status> {
         match self {
-            Status::NextSteps => "Next steps".to_string(),
-            Status::Deferred => "Deferred".to_string(),
-            Status::Declined => "Declined".to_string(),
-            Status::Hired => "Hired".to_string(),
-            Status::GivingOffer => "Giving offer".to_string(),
-            Status::Contractor => "Contractor".to_string(),
-            Status::KeepingWarm => "Keeping warm".to_string(),
-            Status::NeedsToBeTriaged => "Needs to be triaged".to_string(),
-            Status::Interviewing => "Interviewing".to_string(),
-            Status::Onboarding => "Onboarding".to_string(),
+            Status::NeedsToBeTriaged => Some(Status::NextSteps),
+            Status::NextSteps => Some(Status::Interviewing),
+            Status::Interviewing => Some(Status::GivingOffer),
+            Status::GivingOffer => Some(Status::Onboarding),
+            Status::Onboarding => Some(Status::Hired),
+            _ => None,
         }
     }
 }
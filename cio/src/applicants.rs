@@ -11,6 +11,7 @@ use checkr::Checkr;
 use chrono::offset::Utc;
 use chrono::{DateTime, Duration};
 use chrono_humanize::HumanTime;
+use futures_util::stream::TryStreamExt;
 use google_drive::GoogleDrive;
 use html2text::from_read;
 use hubcaps::comments::CommentOptions;
@@ -31,13 +32,23 @@ use tracing::instrument;
 use walkdir::WalkDir;
 
 use crate::airtable::{AIRTABLE_APPLICATIONS_TABLE, AIRTABLE_BASE_ID_RECURITING_APPLICATIONS, AIRTABLE_REVIEWER_LEADERBOARD_TABLE};
-use crate::configs::User;
+use crate::applicant_emails::record_stage_change;
+use crate::applicant_status::Status;
+use crate::background_checks::{record_background_check_report, start_background_check};
+use crate::charts::{attach_chart, cumulative_counts, render_bar_chart, render_line_chart, ChartSeries};
+use crate::chat::{notify_channel, ChatChannel};
+use crate::configs::{User, Users};
 use crate::core::UpdateAirtableRecord;
 use crate::db::Database;
+use crate::event_bus::emit_event;
+use crate::filter::RecordFilter;
+use crate::messages::render_message;
 use crate::models::get_value;
+use crate::notifications::{notify, notify_user, NotificationPriority};
+use crate::reactions::message_ref;
+use crate::referrals::record_referral_from_application;
 use crate::schema::{applicant_reviewers, applicants};
-use crate::slack::{get_hiring_channel_post_url, post_to_channel};
-use crate::utils::{authenticate_github_jwt, check_if_github_issue_exists, get_gsuite_token, github_org, DOMAIN, GSUITE_DOMAIN};
+use crate::utils::{authenticate_github_jwt, check_if_github_issue_exists, drive_client, get_gsuite_token, github_org, sheets_client, DOMAIN, GSUITE_DOMAIN};
 
 // The line breaks that get parsed are weird thats why we have the random asterisks here.
 static QUESTION_TECHNICALLY_CHALLENGING: &str = r"W(?s:.*)at work(?s:.*)ave you found mos(?s:.*)challenging(?s:.*)caree(?s:.*)wh(?s:.*)\?";
@@ -55,6 +66,7 @@ static QUESTION_WHY_OXIDE: &str = r"W(?s:.*)y do you want to work for Oxide\?";
     new_struct_name = "Applicant",
     airtable_base_id = "AIRTABLE_BASE_ID_RECURITING_APPLICATIONS",
     airtable_table = "AIRTABLE_APPLICATIONS_TABLE",
+    source = "google_sheets",
     match_on = {
         "email" = "String",
         "sheet_id" = "String",
@@ -67,7 +79,8 @@ pub struct NewApplicant {
     pub role: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub sheet_id: String,
-    pub status: String,
+    #[serde(default)]
+    pub status: Status,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub raw_status: String,
     pub submitted_time: DateTime<Utc>,
@@ -183,6 +196,17 @@ pub struct NewApplicant {
     // This field is used by Airtable for mapping the location data.
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub geocode_cache: String,
+    /// Who the applicant said referred them on the application form, as they wrote it.
+    /// The referral itself is in the `referrals` table, linked to the user.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub referred_by: String,
+    /// The repository we made for the take-home assignment of the applicant.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub take_home_repo: String,
+    /// The average of the scores the graders gave the take-home assignment, from the
+    /// `take_home_grades` table.
+    #[serde(default)]
+    pub take_home_score: f32,
 }
 
 impl NewApplicant {
@@ -210,7 +234,8 @@ impl NewApplicant {
             website: get_value(values, "Website"),
             resume: get_value(values, "Submit your resume (or PDF export of LinkedIn profile)"),
             materials: get_value(values, "Submit your Oxide candidate materials"),
-            status: crate::applicant_status::Status::NeedsToBeTriaged.to_string(),
+            referred_by: get_value(values, "Who referred you?"),
+            status: Status::NeedsToBeTriaged,
             raw_status: get_value(values, "Status"),
             sent_email_received: false,
             sent_email_follow_up: false,
@@ -248,6 +273,8 @@ impl NewApplicant {
             scoring_inapplicable_experience_count: Default::default(),
             scoring_job_function_yet_needed_count: Default::default(),
             scoring_underwhelming_materials_count: Default::default(),
+            take_home_repo: Default::default(),
+            take_home_score: Default::default(),
             request_background_check: Default::default(),
             criminal_background_check_status: Default::default(),
             motor_vehicle_background_check_status: Default::default(),
@@ -285,70 +312,6 @@ Sincerely,
             .await;
     }
 
-    /// Send an email to the applicant that they did not provide materials.
-    #[instrument]
-    #[inline]
-    pub async fn send_email_rejection_did_not_provide_materials(&self) {
-        // Initialize the SendGrid client.
-        let sendgrid_client = SendGrid::new_from_env();
-
-        // Send the message.
-        sendgrid_client
-            .send_mail(
-                format!("Thank you for your application, {}", self.name),
-                format!(
-                    "Dear {},
-
-Unfortunately, we cannot accept it at this time since you failed to provide the
-requested materials.
-
-All the best,
-The Oxide Team",
-                    self.name
-                ),
-                vec![self.email.to_string()],
-                vec![format!("careers@{}", DOMAIN)],
-                vec![],
-                format!("careers@{}", DOMAIN),
-            )
-            .await;
-    }
-
-    /// Send an email to the applicant about timing.
-    #[instrument]
-    #[inline]
-    pub async fn send_email_rejection_timing(&self) {
-        // Initialize the SendGrid client.
-        let sendgrid_client = SendGrid::new_from_env();
-
-        // Send the message.
-        sendgrid_client
-            .send_mail(
-                format!("Thank you for your application, {}", self.name),
-                format!(
-                    "Dear {},
-
-We are so humbled by your application to join Oxide Computer Company. At this
-stage of the company we are hyper-focused on certain areas of the stack and
-when we need specific domain space experience such as yours, please engage
-with us. Our roles will be updated as we need them.
-
-We are grateful you took the time to apply and put so much thought into the
-candidate materials, we loved reading them. We would absolutely love to work
-with you in the future and cannot wait for that stage of the company!
-
-All the best,
-The Oxide Team",
-                    self.name
-                ),
-                vec![self.email.to_string()],
-                vec![format!("careers@{}", DOMAIN)],
-                vec![],
-                format!("careers@{}", DOMAIN),
-            )
-            .await;
-    }
-
     /// Send an email internally that we have a new application.
     #[instrument]
     #[inline]
@@ -389,6 +352,14 @@ The Oxide Team",
             "".to_string()
         };
 
+        // If the length of the row is greater than the referred by column
+        // then we have a referrer.
+        let referred_by = if row.len() > columns.referred_by && columns.referred_by != 0 {
+            row[columns.referred_by].trim().to_string()
+        } else {
+            "".to_string()
+        };
+
         // If the length of the row is greater than the portfolio column
         // then we have a portfolio.
         let portfolio = if row.len() > columns.portfolio && columns.portfolio != 0 {
@@ -488,6 +459,9 @@ The Oxide Team",
         let mut scoring_job_function_yet_needed_count = 0;
         let mut scoring_underwhelming_materials_count = 0;
 
+        let mut take_home_repo = "".to_string();
+        let mut take_home_score = 0.0;
+
         let mut request_background_check = false;
         let mut criminal_background_check_status = "".to_string();
         let mut motor_vehicle_background_check_status = "".to_string();
@@ -566,6 +540,10 @@ The Oxide Team",
             if !a.motor_vehicle_background_check_status.is_empty() {
                 motor_vehicle_background_check_status = a.criminal_background_check_status.to_string();
             }
+
+            // The take-home is set by the `take_homes` module, not the sheet.
+            take_home_repo = a.take_home_repo.to_string();
+            take_home_score = a.take_home_score;
         }
 
         // If we know they have more than 1 interview AND their current status is "next steps",
@@ -588,7 +566,8 @@ The Oxide Team",
             website,
             resume,
             materials,
-            status: status.to_string(),
+            referred_by,
+            status,
             raw_status,
             sent_email_received,
             sent_email_follow_up,
@@ -628,6 +607,8 @@ The Oxide Team",
             scoring_inapplicable_experience_count,
             scoring_job_function_yet_needed_count,
             scoring_underwhelming_materials_count,
+            take_home_repo,
+            take_home_score,
             request_background_check,
             criminal_background_check_status,
             motor_vehicle_background_check_status,
@@ -703,30 +684,10 @@ The Oxide Team",
             let mut colmn = "ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars();
             let rng = format!("{}{}", colmn.nth(sent_email_follow_up_index).unwrap().to_string(), row_index);
 
-            let status = crate::applicant_status::Status::from_str(&self.status).unwrap_or_default();
-            if status == crate::applicant_status::Status::Declined || status == crate::applicant_status::Status::Deferred {
-                // Check if we have sent the follow up email to them.unwrap_or_default().
-                if self.raw_status.contains("did not do materials") {
-                    // Send the email.
-                    self.send_email_rejection_did_not_provide_materials().await;
-
-                    println!("[applicant] sent email to {} tell them they did not do the materials", self.email);
-                } else {
-                    // Send the email.
-                    self.send_email_rejection_timing().await;
-
-                    println!("[applicant] sent email to {} tell them about timing", self.email);
-                }
-
-                // Update the cell in the google sheet so we know we sent the email.
-                // Mark the column as true not false.
-                sheets_client.update_values(&self.sheet_id, &rng, "TRUE".to_string()).await.unwrap();
-
-                self.sent_email_follow_up = true;
-            } else if status != crate::applicant_status::Status::NeedsToBeTriaged {
-                // Just set that we have sent the email so that we don't do it again if we move to
-                // next steps then interviews etc.
-                // Only when it's not in "NeedsToBeTriaged".
+            // The follow up emails, like rejections, are sent by the `applicant_emails` job
+            // when the applicant moves to a stage, so this only marks that we moved past
+            // triage and should not email them from the sheet again.
+            if self.status != Status::NeedsToBeTriaged {
                 // Update the cell in the google sheet so we know we sent the email.
                 // Mark the column as true not false.
                 sheets_client.update_values(&self.sheet_id, &rng, "TRUE".to_string()).await.unwrap();
@@ -939,9 +900,7 @@ The Oxide Team",
         let time = self.human_duration();
 
         let mut status_msg = format!("<https://docs.google.com/spreadsheets/d/{}|{}> Applicant | applied {}", self.sheet_id, self.role, time);
-        if !self.status.is_empty() {
-            status_msg += &format!(" | status: *{}*", self.status);
-        }
+        status_msg += &format!(" | status: *{}*", self.status);
 
         let mut values_msg = "".to_string();
         if !self.value_reflected.is_empty() {
@@ -961,10 +920,7 @@ The Oxide Team",
             values_msg = "values not yet populated".to_string();
         }
 
-        let mut intro_msg = format!("*{}*  <mailto:{}|{}>", self.name, self.email, self.email,);
-        if !self.location.is_empty() {
-            intro_msg += &format!("  {}", self.location);
-        }
+        let intro_msg = render_message("applicant.new", self);
 
         let mut info_msg = format!("<{}|resume> | <{}|materials>", self.resume, self.materials,);
         if !self.phone.is_empty() {
@@ -998,7 +954,8 @@ The Oxide Team",
                     }),
                     elements: Default::default(),
                     accessory: Default::default(),
-                    block_id: Default::default(),
+                    // Tag the message so reactions to it can find the applicant again.
+                    block_id: message_ref("applicant", &format!("{}/{}", self.sheet_id, self.email)),
                     fields: Default::default(),
                 },
                 MessageBlock {
@@ -1093,6 +1050,43 @@ The applicants Airtable is at: https://airtable-applicants.corp.oxide.computer
 }
 
 impl Applicant {
+    /// Tell the tools that subscribe to our events that the applicant moved to a
+    /// different stage of our hiring process.
+    #[instrument]
+    #[inline]
+    pub async fn emit_stage_changed(&self, from: Status) {
+        emit_event(
+            "applicant.stage_changed",
+            json!({
+                "name": self.name,
+                "email": self.email,
+                "role": self.role,
+                "sheet_id": self.sheet_id,
+                "from": from,
+                "to": self.status,
+            }),
+        )
+        .await;
+    }
+
+    /// Handle the applicant moving to a different stage of our hiring process. Once we
+    /// are giving them an offer, we start their background check. The email for the
+    /// stage, if any, is sent by the `applicant_emails` job.
+    #[instrument(skip(db))]
+    #[inline]
+    pub async fn stage_changed(&self, db: &Database, from: Status) {
+        self.emit_stage_changed(from).await;
+        record_stage_change(db, self.id, from, self.status);
+
+        if self.status == Status::GivingOffer {
+            let mut applicant = self.clone();
+            match start_background_check(db, &mut applicant).await {
+                Ok(done) => println!("[applicant] {}", done),
+                Err(e) => println!("[applicant] {}", e),
+            }
+        }
+    }
+
     /// Get the human duration of time since the application was submitted.
     #[instrument]
     #[inline]
@@ -1109,44 +1103,10 @@ impl Applicant {
     #[instrument(skip(db))]
     #[inline]
     pub async fn send_background_check_invitation(&mut self, db: &Database) {
-        // Initialize the Checker client.
-        let checkr = Checkr::new_from_env();
-
-        // Check if we already sent them an invitation.
-        let candidates = checkr.list_candidates().await.unwrap();
-        for candidate in candidates {
-            if candidate.email == self.email {
-                // Check if we already have sent their invitation.
-                if self.criminal_background_check_status.is_empty() {
-                    // Create an invitation for the candidate.
-                    checkr.create_invitation(&candidate.id, "premium_criminal").await.unwrap();
-
-                    // Update the database.
-                    self.request_background_check = true;
-                    self.criminal_background_check_status = "requested".to_string();
-
-                    self.update(db).await;
-
-                    println!("[applicant] sent background check invitation to: {}", self.email);
-                }
-                // We can return early they already exist as a candidate.
-                return;
-            }
+        match start_background_check(db, self).await {
+            Ok(done) => println!("[applicant] {}", done),
+            Err(e) => println!("[applicant] {}", e),
         }
-
-        // Create a new candidate for the applicant in checkr.
-        let candidate = checkr.create_candidate(&self.email).await.unwrap();
-
-        // Create an invitation for the candidate.
-        checkr.create_invitation(&candidate.id, "premium_criminal").await.unwrap();
-
-        // Update the database.
-        self.request_background_check = true;
-        self.criminal_background_check_status = "requested".to_string();
-
-        self.update(db).await;
-
-        println!("[applicant] sent background check invitation to: {}", self.email);
     }
 
     /// Convert the applicant into JSON for a Slack message.
@@ -1156,9 +1116,7 @@ impl Applicant {
         let time = self.human_duration();
 
         let mut status_msg = format!("<https://docs.google.com/spreadsheets/d/{}|{}> Applicant | applied {}", self.sheet_id, self.role, time);
-        if !self.status.is_empty() {
-            status_msg += &format!(" | status: *{}*", self.status);
-        }
+        status_msg += &format!(" | status: *{}*", self.status);
 
         let mut values_msg = "".to_string();
         if !self.value_reflected.is_empty() {
@@ -1178,10 +1136,7 @@ impl Applicant {
             values_msg = "values not yet populated".to_string();
         }
 
-        let mut intro_msg = format!("*{}*  <mailto:{}|{}>", self.name, self.email, self.email,);
-        if !self.location.is_empty() {
-            intro_msg += &format!("  {}", self.location);
-        }
+        let intro_msg = render_message("applicant.new", self);
 
         let mut info_msg = format!("<{}|resume> | <{}|materials>", self.resume, self.materials,);
         if !self.phone.is_empty() {
@@ -1215,7 +1170,8 @@ impl Applicant {
                     }),
                     elements: Default::default(),
                     accessory: Default::default(),
-                    block_id: Default::default(),
+                    // Tag the message so reactions to it can find the applicant again.
+                    block_id: message_ref("applicant", &format!("{}/{}", self.sheet_id, self.email)),
                     fields: Default::default(),
                 },
                 MessageBlock {
@@ -1275,6 +1231,19 @@ impl Applicant {
             .await;
     }
 
+    /// Get the message we send a scorer in Slack when they have been assigned to
+    /// score the applicant.
+    #[instrument]
+    #[inline]
+    pub fn as_scorer_slack_msg(&self) -> Value {
+        json!({
+            "text": format!(
+                "You have been assigned to review *{}* for {}. Their <{}|resume> and <{}|materials> are ready, submit your review with the <{}|scoring form>.",
+                self.name, self.role, self.resume, self.materials, self.scoring_form_url
+            ),
+        })
+    }
+
     /// Get the applicant's information in the form of the body of an email for a
     /// scorer email that they have been assigned to score the applicant.
     #[instrument]
@@ -1341,7 +1310,7 @@ The applicants Airtable is at: https://airtable-applicants.corp.oxide.computer
         let issue = check_if_github_issue_exists(&configs_issues, &self.name);
 
         // Check if their status is not onboarding, we only care about onboarding applicants.
-        if !self.status.contains("Onboarding") {
+        if self.status != Status::Onboarding {
             // If the issue exists and is opened, we need to close it.
             if let Some(i) = issue {
                 if i.state != "open" {
@@ -1470,6 +1439,7 @@ pub struct ApplicantSheetColumns {
     pub linkedin: usize,
     pub resume: usize,
     pub materials: usize,
+    pub referred_by: usize,
     pub status: usize,
     pub sent_email_received: usize,
     pub sent_email_follow_up: usize,
@@ -1527,6 +1497,9 @@ impl ApplicantSheetColumns {
             if c.contains("materials") {
                 columns.materials = index;
             }
+            if c.contains("referred you") {
+                columns.referred_by = index;
+            }
             if c.contains("status") {
                 columns.status = index;
             }
@@ -1779,10 +1752,10 @@ pub async fn get_raw_applicants() -> Vec<NewApplicant> {
     let token = get_gsuite_token("").await;
 
     // Initialize the GSuite sheets client.
-    let sheets_client = Sheets::new(token.clone());
+    let sheets_client = sheets_client(token.clone());
 
     // Initialize the GSuite sheets client.
-    let drive_client = GoogleDrive::new(token.clone());
+    let drive_client = drive_client(token.clone());
 
     // Iterate over the Google sheets and create or update GitHub issues
     // depending on the application status.
@@ -1819,7 +1792,7 @@ pub async fn get_raw_applicants() -> Vec<NewApplicant> {
 
             if !applicant.sent_email_received {
                 // Post to Slack.
-                post_to_channel(get_hiring_channel_post_url(), applicant.as_slack_msg()).await;
+                notify_channel(ChatChannel::Hiring, applicant.as_slack_msg()).await;
 
                 // Send a company-wide email.
                 applicant.send_email_internally().await;
@@ -1832,11 +1805,48 @@ pub async fn get_raw_applicants() -> Vec<NewApplicant> {
     applicants
 }
 
-// Sync the applicants with our database.
+/// Set the status for an applicant in their row of the Google Sheet for their role,
+/// since the sheet is the source of truth for statuses.
+#[instrument]
+#[inline]
+pub async fn update_applicant_status_in_sheet(sheet_id: &str, email: &str, status: &str) -> Result<(), String> {
+    // Get the GSuite token.
+    let token = get_gsuite_token("").await;
+
+    // Initialize the GSuite sheets client.
+    let sheets_client = sheets_client(token);
+
+    let sheet_values = sheets_client
+        .get_values(sheet_id, "Form Responses 1!A1:S1000".to_string())
+        .await
+        .map_err(|e| format!("getting the values in Google sheet {} failed: {:?}", sheet_id, e))?;
+    let values = sheet_values.values.unwrap_or_default();
+    if values.is_empty() {
+        return Err(format!("unable to retrieve any data values from Google sheet {}", sheet_id));
+    }
+
+    let columns = ApplicantSheetColumns::parse(&values);
+    let row_index = match values.iter().position(|row| row.get(columns.email).map(|e| e.trim()) == Some(email)) {
+        Some(i) => i,
+        None => return Err(format!("applicant {} is not in Google sheet {}", email, sheet_id)),
+    };
+
+    let mut colmn = "ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars();
+    let rng = format!("{}{}", colmn.nth(columns.status).unwrap().to_string(), row_index + 1);
+    sheets_client
+        .update_values(sheet_id, &rng, status.to_string())
+        .await
+        .map_err(|e| format!("updating {} in Google sheet {} failed: {:?}", rng, sheet_id, e))?;
+
+    Ok(())
+}
+
+// Sync the applicants with our database. Only the applicants matching the filter,
+// who applied between the dates of the filter, are synced.
 #[instrument(skip(db))]
 #[inline]
-pub async fn refresh_db_applicants(db: &Database) {
-    let applicants = get_raw_applicants().await;
+pub async fn refresh_db_applicants(db: &Database, filter: &RecordFilter) {
+    let applicants: Vec<NewApplicant> = get_raw_applicants().await.into_iter().filter(|a| filter.matches(a) && filter.matches_date(a.submitted_time)).collect();
 
     let github = authenticate_github_jwt();
 
@@ -1844,13 +1854,33 @@ pub async fn refresh_db_applicants(db: &Database) {
     let configs_issues = github
         .repo(github_org(), "configs")
         .issues()
-        .list(&IssueListOptions::builder().per_page(100).state(State::All).labels(vec!["hiring"]).build())
+        .iter(&IssueListOptions::builder().per_page(100).state(State::All).labels(vec!["hiring"]).build())
+        .try_collect::<Vec<Issue>>()
         .await
         .unwrap();
 
     // Sync applicants.
     for applicant in applicants {
+        // Get the stage they were at before we update them.
+        let old_status = applicants::dsl::applicants
+            .filter(applicants::dsl::email.eq(applicant.email.to_string()))
+            .filter(applicants::dsl::sheet_id.eq(applicant.sheet_id.to_string()))
+            .first::<Applicant>(&db.conn())
+            .map(|a| a.status)
+            .ok();
+
         let new_applicant = applicant.upsert(db).await;
+        if let Some(old_status) = old_status {
+            if old_status != new_applicant.status {
+                new_applicant.stage_changed(db, old_status).await;
+            }
+        }
+        if !new_applicant.referred_by.is_empty() {
+            match record_referral_from_application(db, &new_applicant).await {
+                Ok(done) => println!("[applicant] {}", done),
+                Err(e) => println!("[applicant] {}", e),
+            }
+        }
 
         new_applicant.create_github_onboarding_issue(&github, &configs_issues).await;
     }
@@ -1888,7 +1918,7 @@ async fn get_reviewer_pool(sheet_id: &str) -> Vec<String> {
     let token = get_gsuite_token("").await;
 
     // Initialize the GSuite sheets client.
-    let sheets_client = Sheets::new(token.clone());
+    let sheets_client = sheets_client(token.clone());
 
     // Get the values in the sheet.
     let sheet_values = sheets_client.get_values(&sheet_id, "Reviewer pool!A1:G1000".to_string()).await.unwrap();
@@ -1919,7 +1949,7 @@ pub async fn update_applications_with_scoring_forms(db: &Database) {
     let token = get_gsuite_token("").await;
 
     // Initialize the GSuite sheets client.
-    let sheets_client = Sheets::new(token.clone());
+    let sheets_client = sheets_client(token.clone());
     for sheet_id in get_tracking_sheets() {
         // Get the values in the sheet.
         let sheet_values = sheets_client.get_values(&sheet_id, "Applicants to review!A1:G1000".to_string()).await.unwrap();
@@ -1962,8 +1992,7 @@ pub async fn update_applications_with_scoring_forms(db: &Database) {
                     .first::<Applicant>(&db.conn())
                 {
                     // Make sure the status is "Needs to be triaged".
-                    let status = crate::applicant_status::Status::from_str(&applicant.status);
-                    if status != Ok(crate::applicant_status::Status::NeedsToBeTriaged) {
+                    if applicant.status != Status::NeedsToBeTriaged {
                         // Continue we don't care.
                         continue;
                     }
@@ -1978,12 +2007,11 @@ pub async fn update_applications_with_scoring_forms(db: &Database) {
                         // Choose next five reviewers.
                         applicant.scorers = reviewer_pool.by_ref().take(5).collect();
 
-                        // Send emails to the scorers.
-                        // We don't need to do this since we will use airtable
-                        // for the emails.
-                        //for s in &applicant.scorers {
-                        //applicant.send_email_to_scorer(&s).await;
-                        //}
+                        // Let the scorers know in Slack. We don't need to
+                        // email them since airtable sends the emails.
+                        for s in &applicant.scorers {
+                            notify_user(db, s, applicant.as_scorer_slack_msg()).await;
+                        }
                     }
 
                     // Update the applicant in the database.
@@ -2001,7 +2029,7 @@ pub async fn update_applications_with_scoring_results(db: &Database) {
     let token = get_gsuite_token("").await;
 
     // Initialize the GSuite sheets client.
-    let sheets_client = Sheets::new(token.clone());
+    let sheets_client = sheets_client(token.clone());
     for sheet_id in get_tracking_sheets() {
         // Get the values in the sheet.
         let sheet_values = sheets_client.get_values(&sheet_id, "Responses!A1:R1000".to_string()).await.unwrap();
@@ -2084,6 +2112,7 @@ pub async fn update_applications_with_scoring_results(db: &Database) {
                     applicant.value_reflected = value_reflected.to_string();
                     applicant.value_violated = value_violated.to_string();
                     applicant.values_in_tension = values_in_tension.clone();
+                    applicant.set_synced_from("google_sheets");
 
                     // Update the applicant in the database.
                     applicant.update(db).await;
@@ -2117,7 +2146,7 @@ pub async fn refresh_background_checks(db: &Database) {
         // Try to match the candidate based on their email.
         // Try for all the sheet_ids.
         for (_, sheet_id) in get_sheets_map() {
-            if let Ok(mut applicant) = applicants::dsl::applicants
+            if let Ok(applicant) = applicants::dsl::applicants
                 .filter(applicants::dsl::email.eq(candidate.email.to_string()))
                 .filter(applicants::dsl::sheet_id.eq(sheet_id.to_string()))
                 .first::<Applicant>(&db.conn())
@@ -2125,18 +2154,11 @@ pub async fn refresh_background_checks(db: &Database) {
                 for report_id in &candidate.report_ids {
                     // Get the report for the candidate.
                     let report = checkr.get_report(&report_id).await.unwrap();
-                    println!("report: {:?}", report);
 
-                    // Set the status for the report.
-                    if report.package.contains("premium_criminal") {
-                        applicant.criminal_background_check_status = report.status.to_string();
-                    }
-                    if report.package.contains("motor_vehicle") {
-                        applicant.motor_vehicle_background_check_status = report.status.to_string();
+                    match record_background_check_report(db, &applicant, &candidate.id, &report).await {
+                        Ok(done) => println!("[checkr] {}", done),
+                        Err(e) => println!("[checkr] {}", e),
                     }
-
-                    // Update the applicant.
-                    applicant.update(db).await;
                 }
             } else {
                 println!("[checkr] could not find applicant with email {} in sheet_id {}", candidate.email, sheet_id);
@@ -2150,6 +2172,7 @@ pub async fn refresh_background_checks(db: &Database) {
     new_struct_name = "ApplicantReviewer",
     airtable_base_id = "AIRTABLE_BASE_ID_RECURITING_APPLICATIONS",
     airtable_table = "AIRTABLE_REVIEWER_LEADERBOARD_TABLE",
+    source = "google_sheets",
     match_on = {
         "email" = "String",
     },
@@ -2193,7 +2216,7 @@ pub async fn update_applicant_reviewers(db: &Database) {
     let token = get_gsuite_token("").await;
 
     // Initialize the GSuite sheets client.
-    let sheets_client = Sheets::new(token.clone());
+    let sheets_client = sheets_client(token.clone());
     let sheet_id = "1BOeZTdSNixkJsVHwf3Z0LMVlaXsc_0J8Fsy9BkCa7XM";
 
     // Get the values in the sheet.
@@ -2243,12 +2266,80 @@ pub async fn update_applicant_reviewers(db: &Database) {
     }
 }
 
+/// The stages of our hiring funnel, in order.
+pub static FUNNEL_STAGES: &[&str] = &["applied", "next steps", "interviewing", "giving offer", "hired"];
+
+/// Count how many applicants reached each stage of the hiring funnel. Applicants who
+/// were declined, deferred, or are being kept warm only count as having applied since
+/// we do not know how far they got.
+#[instrument(skip(statuses))]
+#[inline]
+pub fn applicant_funnel(statuses: &[Status]) -> Vec<(String, f64)> {
+    let mut counts = vec![0.0; FUNNEL_STAGES.len()];
+    for status in statuses {
+        let reached = match status {
+            Status::Hired | Status::Onboarding | Status::Contractor => 4,
+            Status::GivingOffer => 3,
+            Status::Interviewing => 2,
+            Status::NextSteps => 1,
+            _ => 0,
+        };
+        for c in counts.iter_mut().take(reached + 1) {
+            *c += 1.0;
+        }
+    }
+
+    FUNNEL_STAGES.iter().zip(counts).map(|(s, c)| (s.to_string(), c)).collect()
+}
+
+/// Post a weekly report to the #hiring channel with charts of the applicant funnel
+/// and our headcount over time.
+#[instrument(skip(db))]
+#[inline]
+pub async fn send_hiring_report(db: &Database, filter: &RecordFilter) {
+    let replica = db.read_only();
+    let applicants = Applicants::get_from_db(&replica);
+    // New applicants are counted for the last week, unless the filter has dates.
+    let period = RecordFilter {
+        since: Some(filter.since.unwrap_or_else(|| Utc::now() - Duration::weeks(1))),
+        before: filter.before,
+        ..Default::default()
+    };
+    let new_applicants = applicants.0.iter().filter(|a| period.matches_date(a.submitted_time)).count();
+    // The funnel is for everyone, unless the filter has dates.
+    let statuses: Vec<Status> = applicants.0.iter().filter(|a| filter.matches_date(a.submitted_time)).map(|a| a.status).collect();
+    let interviewing = statuses.iter().filter(|s| **s == Status::Interviewing).count();
+
+    // People who left are removed from our configs, so this is the headcount of the
+    // people here now by when they started.
+    let start_dates: Vec<_> = Users::get_from_db(&replica).into_iter().filter(|u| !u.is_system_account()).map(|u| u.start_date).collect();
+    let headcount = start_dates.len();
+
+    let mut msg = json!({
+        "text": render_message(
+            "hiring.weekly",
+            &json!({ "new_applicants": new_applicants, "period": filter.describe_dates("this week"), "interviewing": interviewing, "headcount": headcount }),
+        ),
+    });
+    attach_chart(&mut msg, "Applicant funnel", render_bar_chart("Applicant funnel", &applicant_funnel(&statuses)));
+    let series = vec![ChartSeries {
+        name: "headcount".to_string(),
+        points: cumulative_counts(&start_dates),
+    }];
+    attach_chart(&mut msg, "Headcount", render_line_chart("Headcount", "people", &series));
+
+    notify(db, NotificationPriority::Low, ChatChannel::Hiring, "hiring.weekly", msg).await;
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::applicant_status::Status;
     use crate::applicants::{
-        refresh_background_checks, refresh_db_applicants, update_applicant_reviewers, update_applications_with_scoring_forms, update_applications_with_scoring_results, Applicant, Applicants,
+        applicant_funnel, refresh_background_checks, refresh_db_applicants, update_applicant_reviewers, update_applications_with_scoring_forms, update_applications_with_scoring_results, Applicant,
+        Applicants,
     };
     use crate::db::Database;
+    use crate::filter::RecordFilter;
     use crate::schema::applicants;
 
     use diesel::prelude::*;
@@ -2290,7 +2381,7 @@ mod tests {
     #[tokio::test(threaded_scheduler)]
     async fn test_applicants() {
         let db = Database::new();
-        refresh_db_applicants(&db).await;
+        refresh_db_applicants(&db, &RecordFilter::default()).await;
 
         // Update Airtable.
         Applicants::get_from_db(&db).update_airtable().await;
@@ -2301,4 +2392,19 @@ mod tests {
 
         update_applications_with_scoring_results(&db).await;
     }
+
+    #[test]
+    fn test_applicant_funnel() {
+        let statuses = vec![Status::NeedsToBeTriaged, Status::Declined, Status::NextSteps, Status::Interviewing, Status::Interviewing, Status::Hired];
+        assert_eq!(
+            applicant_funnel(&statuses),
+            vec![
+                ("applied".to_string(), 6.0),
+                ("next steps".to_string(), 4.0),
+                ("interviewing".to_string(), 3.0),
+                ("giving offer".to_string(), 1.0),
+                ("hired".to_string(), 1.0),
+            ]
+        );
+    }
 }
@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use google_drive::GoogleDrive;
+use reqwest::Client;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::applicants::{Applicant, ApplicantSheetColumns, NewApplicant};
+use crate::chat::{notify_channel, ChatChannel};
+use crate::configs::{OpenRole, OpenRoles};
+use crate::db::Database;
+use crate::schema::applicants;
+use crate::utils::{drive_client, get_gsuite_token, sheets_client};
+
+/// The largest resume or materials file we accept, 10MB.
+pub const MAX_APPLICATION_FILE_BYTES: usize = 10 * 1024 * 1024;
+
+/// The folder in the "Automated Documents" shared drive that uploaded resumes and
+/// materials go in.
+pub const APPLICATION_FILES_FOLDER: &str = "application_materials";
+
+/// A file attached to an application.
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct ApplicationFile {
+    pub name: String,
+    /// The contents of the file, base64 encoded.
+    pub data: String,
+}
+
+impl ApplicationFile {
+    /// Decode the file, making sure it is a PDF that is not too big.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn decode(&self, field: &str) -> Result<Vec<u8>, String> {
+        let bytes = base64::decode(&self.data).map_err(|e| format!("{} is not base64 encoded: {}", field, e))?;
+        if bytes.is_empty() {
+            return Err(format!("{} is empty", field));
+        }
+        if bytes.len() > MAX_APPLICATION_FILE_BYTES {
+            return Err(format!("{} is larger than {}MB", field, MAX_APPLICATION_FILE_BYTES / 1024 / 1024));
+        }
+        if !bytes.starts_with(b"%PDF") {
+            return Err(format!("{} must be a PDF", field));
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// A submission of the application form on the careers page.
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct ApplicationSubmission {
+    /// The name of the open role, as in `configs/roles.toml`.
+    pub role: String,
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub phone: String,
+    #[serde(default)]
+    pub location: String,
+    #[serde(default)]
+    pub github: String,
+    #[serde(default)]
+    pub linkedin: String,
+    #[serde(default)]
+    pub portfolio: String,
+    #[serde(default)]
+    pub website: String,
+    pub resume: ApplicationFile,
+    pub materials: ApplicationFile,
+    /// The response token from the hCaptcha widget on the form.
+    #[serde(default)]
+    pub hcaptcha_token: String,
+}
+
+impl ApplicationSubmission {
+    /// Check the submission is for one of our open roles and has everything we need,
+    /// returning the role.
+    #[instrument(skip(self, roles))]
+    #[inline]
+    pub fn validate(&self, roles: &[OpenRole]) -> Result<OpenRole, String> {
+        let role = match roles.iter().find(|r| r.open && r.name == self.role.trim()) {
+            Some(r) => r.clone(),
+            None => return Err(format!("{} is not one of our open roles", self.role)),
+        };
+        if role.sheet_id.is_empty() {
+            return Err(format!("{} is not accepting applications", role.name));
+        }
+
+        if self.name.trim().is_empty() {
+            return Err("name is required".to_string());
+        }
+        let email = self.email.trim();
+        if email.len() < 3 || !email.contains('@') || email.contains(char::is_whitespace) {
+            return Err(format!("{} is not a valid email", self.email));
+        }
+
+        self.resume.decode("resume")?;
+        self.materials.decode("materials")?;
+
+        Ok(role)
+    }
+
+    /// The row for the submission in the sheet for the role, in the same format as
+    /// the Google Form for the role would have written it.
+    #[instrument(skip(self, columns))]
+    #[inline]
+    pub fn sheet_row(&self, columns: &ApplicantSheetColumns, submitted_at: DateTime<Utc>, resume_url: &str, materials_url: &str) -> Vec<String> {
+        let cells = vec![
+            // The timestamps in the sheets are in Pacific time, see `NewApplicant::parse_timestamp`.
+            (columns.timestamp, submitted_at.with_timezone(&FixedOffset::west(8 * 3600)).format("%m/%d/%Y %H:%M:%S").to_string()),
+            (columns.name, self.name.trim().to_string()),
+            (columns.email, self.email.trim().to_string()),
+            (columns.location, self.location.trim().to_string()),
+            (columns.phone, self.phone.trim().to_string()),
+            (columns.github, self.github.trim().to_string()),
+            (columns.linkedin, self.linkedin.trim().to_string()),
+            (columns.portfolio, self.portfolio.trim().to_string()),
+            (columns.website, self.website.trim().to_string()),
+            (columns.resume, resume_url.to_string()),
+            (columns.materials, materials_url.to_string()),
+        ];
+
+        let width = cells
+            .iter()
+            .map(|(i, _)| *i)
+            .chain(vec![columns.status, columns.sent_email_received, columns.sent_email_follow_up])
+            .max()
+            .unwrap_or_default()
+            + 1;
+        let mut row = vec!["".to_string(); width];
+        for (index, value) in cells {
+            // A column the sheet does not have parses as the first column, do not
+            // overwrite the timestamp with it.
+            if index == 0 && !row[0].is_empty() {
+                continue;
+            }
+            row[index] = value;
+        }
+
+        row
+    }
+}
+
+/// Limit how many submissions we take from a single client in a window of time.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max: usize,
+    window: Duration,
+    hits: Mutex<HashMap<String, Vec<DateTime<Utc>>>>,
+}
+
+impl RateLimiter {
+    #[instrument]
+    #[inline]
+    pub fn new(max: usize, window: Duration) -> Self {
+        RateLimiter {
+            max,
+            window,
+            hits: Default::default(),
+        }
+    }
+
+    /// Record a request from the client, returning false if they are over the limit.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn check(&self, key: &str, now: DateTime<Utc>) -> bool {
+        let mut hits = self.hits.lock().unwrap();
+        // Forget about everyone outside the window so this does not grow forever.
+        hits.retain(|_, times| {
+            times.retain(|t| now - *t < self.window);
+            !times.is_empty()
+        });
+
+        let times = hits.entry(key.to_string()).or_default();
+        if times.len() >= self.max {
+            return false;
+        }
+        times.push(now);
+
+        true
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct HCaptchaResponse {
+    #[serde(default)]
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+/// Verify the hCaptcha token from the form with the secret in the `HCAPTCHA_SECRET`
+/// environment variable.
+#[instrument(skip(token))]
+#[inline]
+pub async fn verify_hcaptcha(token: &str, remote_ip: &str) -> Result<(), String> {
+    if token.trim().is_empty() {
+        return Err("the captcha was not completed".to_string());
+    }
+
+    let secret = env::var("HCAPTCHA_SECRET").map_err(|_| "the HCAPTCHA_SECRET env variable must be set".to_string())?;
+    let mut form = vec![("secret", secret.as_str()), ("response", token)];
+    if !remote_ip.is_empty() {
+        form.push(("remoteip", remote_ip));
+    }
+
+    let resp = Client::new()
+        .post("https://hcaptcha.com/siteverify")
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("verifying the captcha failed: {}", e))?;
+    let result: HCaptchaResponse = resp.json().await.map_err(|e| format!("decoding the captcha verification failed: {}", e))?;
+    if !result.success {
+        return Err(format!("the captcha is not valid: {}", result.error_codes.join(", ")));
+    }
+
+    Ok(())
+}
+
+/// Upload a file to our application materials folder in Google Drive, returning its URL.
+/// Every upload is a new file, so applicants with the same name applying for the same
+/// role do not overwrite each other's files.
+#[instrument(skip(drive_client, bytes))]
+#[inline]
+async fn upload_application_file(drive_client: &GoogleDrive, filename: &str, bytes: &[u8]) -> Result<String, String> {
+    let shared_drive = drive_client
+        .get_drive_by_name("Automated Documents")
+        .await
+        .map_err(|e| format!("getting the shared drive `Automated Documents` failed: {}", e))?;
+    let drive_id = shared_drive.id.to_string();
+    let folder = drive_client
+        .get_file_by_name(&drive_id, APPLICATION_FILES_FOLDER)
+        .await
+        .map_err(|e| format!("getting the {} folder failed: {}", APPLICATION_FILES_FOLDER, e))?;
+    let parent_id = match folder.get(0) {
+        Some(f) => f.id.to_string(),
+        None => return Err(format!("the {} folder does not exist in the `Automated Documents` shared drive", APPLICATION_FILES_FOLDER)),
+    };
+
+    let file = drive_client
+        .create_file(&drive_id, &parent_id, filename, "application/pdf", bytes)
+        .await
+        .map_err(|e| format!("uploading {} failed: {}", filename, e))?;
+
+    Ok(format!("https://drive.google.com/open?id={}", file.id))
+}
+
+/// Get the name for a file attached to an application, with a random suffix so it is
+/// unique, ie. `Jane Doe - Resume - Hardware Engineer - 5f2c9a1b7e3d4c60.pdf`.
+#[instrument]
+#[inline]
+pub fn application_filename(name: &str, kind: &str, role: &str, suffix: u64) -> String {
+    format!("{} - {} - {} - {:016x}.pdf", name, kind, role, suffix)
+}
+
+/// Get the row that values were appended to from the range the Sheets API says it
+/// updated, ie. `'Form Responses 1'!A1002:S1002` is row 1002.
+#[instrument]
+#[inline]
+pub fn appended_row(updated_range: &str) -> Option<usize> {
+    let cells = updated_range.rsplit('!').next()?;
+    let first = cells.split(':').next()?;
+    first.trim_start_matches(|c: char| c.is_ascii_alphabetic()).parse::<usize>().ok()
+}
+
+/// Get the address of the client from the `X-Forwarded-For` header. The client can put
+/// anything in the header, so this is the last address, which is the one our load
+/// balancer appends for who connected to it.
+#[instrument]
+#[inline]
+pub fn client_ip(forwarded_for: &str) -> String {
+    forwarded_for.rsplit(',').next().unwrap_or_default().trim().to_string()
+}
+
+/// Why a submission of the application form was not taken.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApplicationError {
+    /// Something is wrong with the submission, which the applicant can fix.
+    Invalid(String),
+    /// Saving the submission failed on our side.
+    Failed(String),
+}
+
+impl fmt::Display for ApplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApplicationError::Invalid(e) => write!(f, "{}", e),
+            ApplicationError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Take a submission of the application form. The applicant is added to the Google
+/// Sheet for the role, since that is the source of truth for applicants, and then
+/// handled the same as a response to the Google Form for the role.
+#[instrument(skip(db, submission))]
+#[inline]
+pub async fn submit_application(db: &Database, submission: &ApplicationSubmission) -> Result<Applicant, ApplicationError> {
+    let roles: Vec<OpenRole> = OpenRoles::get_from_db(db).into();
+    let role = submission.validate(&roles).map_err(ApplicationError::Invalid)?;
+
+    let email = submission.email.trim().to_string();
+    if applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.to_string()))
+        .filter(applicants::dsl::sheet_id.eq(role.sheet_id.to_string()))
+        .first::<Applicant>(&db.conn())
+        .is_ok()
+    {
+        return Err(ApplicationError::Invalid(format!("{} has already applied for {}", email, role.name)));
+    }
+
+    add_application(db, submission, &role).await.map_err(ApplicationError::Failed)
+}
+
+/// Add a valid submission to the Google Sheet for the role and then to the database.
+#[instrument(skip(db, submission))]
+#[inline]
+async fn add_application(db: &Database, submission: &ApplicationSubmission, role: &OpenRole) -> Result<Applicant, String> {
+    // Get the GSuite token.
+    let token = get_gsuite_token("").await;
+    // Initialize the GSuite sheets client.
+    let sheets_client = sheets_client(token.clone());
+    // Initialize the Google Drive client.
+    let drive_client = drive_client(token);
+
+    // We only need the header to know which column is which.
+    let sheet_values = sheets_client
+        .get_values(&role.sheet_id, "Form Responses 1!A1:S1".to_string())
+        .await
+        .map_err(|e| format!("getting the values in Google sheet {} failed: {:?}", role.sheet_id, e))?;
+    let values = sheet_values.values.unwrap_or_default();
+    if values.is_empty() {
+        return Err(format!("unable to retrieve any data values from Google sheet {}", role.sheet_id));
+    }
+    let columns = ApplicantSheetColumns::parse(&values);
+
+    let name = submission.name.trim();
+    let resume_url = upload_application_file(
+        &drive_client,
+        &application_filename(name, "Resume", &role.name, rand::random::<u64>()),
+        &submission.resume.decode("resume")?,
+    )
+    .await?;
+    let materials_url = upload_application_file(
+        &drive_client,
+        &application_filename(name, "Materials", &role.name, rand::random::<u64>()),
+        &submission.materials.decode("materials")?,
+    )
+    .await?;
+
+    // Append the whole row at once, so applications at the same time each get a row.
+    let row = submission.sheet_row(&columns, Utc::now(), &resume_url, &materials_url);
+    let appended = sheets_client
+        .append_values(&role.sheet_id, "Form Responses 1!A1:S1", vec![row.clone()])
+        .await
+        .map_err(|e| format!("appending to Google sheet {} failed: {:?}", role.sheet_id, e))?;
+    let updated_range = appended.updates.and_then(|u| u.updated_range).unwrap_or_default();
+    let row_index = appended_row(&updated_range).ok_or_else(|| format!("Google sheet {} did not say which row it appended to: `{}`", role.sheet_id, updated_range))?;
+
+    let mut applicant = NewApplicant::parse_from_row_with_columns(&role.name, &role.sheet_id, &columns, &row).await;
+    applicant
+        .expand(&drive_client, &sheets_client, columns.sent_email_received, columns.sent_email_follow_up, row_index)
+        .await;
+
+    // Post to Slack.
+    notify_channel(ChatChannel::Hiring, applicant.as_slack_msg()).await;
+
+    // Send a company-wide email.
+    applicant.send_email_internally().await;
+
+    applicant.sent_email_received = true;
+
+    // Send the applicant to the database and Airtable.
+    let a = applicant.upsert(db).await;
+    println!("[application_form] {} applied for {}", a.email, a.role);
+
+    Ok(a)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::applicants::ApplicantSheetColumns;
+    use crate::application_form::{appended_row, application_filename, client_ip, ApplicationFile, ApplicationSubmission, RateLimiter};
+    use crate::configs::OpenRole;
+
+    fn role(name: &str, open: bool) -> OpenRole {
+        OpenRole {
+            id: 1,
+            name: name.to_string(),
+            team: "hardware".to_string(),
+            location: "Remote".to_string(),
+            description: "".to_string(),
+            job_description_url: "".to_string(),
+            application_form_url: "".to_string(),
+            sheet_id: "1FHA".to_string(),
+            open,
+            airtable_record_id: "".to_string(),
+            source: "configs".to_string(),
+            last_synced_from: "".to_string(),
+            last_synced_at: None,
+            extra: Default::default(),
+            modified_at: None,
+        }
+    }
+
+    fn pdf() -> ApplicationFile {
+        ApplicationFile {
+            name: "resume.pdf".to_string(),
+            data: base64::encode(b"%PDF-1.4 resume"),
+        }
+    }
+
+    #[test]
+    fn test_validate_application() {
+        let roles = vec![role("Hardware Engineer", true), role("Product Manager", false)];
+        let mut submission = ApplicationSubmission {
+            role: "Hardware Engineer".to_string(),
+            name: "Jane Doe".to_string(),
+            email: "jane@example.com".to_string(),
+            resume: pdf(),
+            materials: pdf(),
+            ..Default::default()
+        };
+        assert_eq!(submission.validate(&roles).unwrap().name, "Hardware Engineer");
+
+        submission.role = "Product Manager".to_string();
+        assert!(submission.validate(&roles).is_err());
+        submission.role = "Hardware Engineer".to_string();
+
+        submission.email = "jane".to_string();
+        assert!(submission.validate(&roles).is_err());
+        submission.email = "jane@example.com".to_string();
+
+        submission.materials.data = base64::encode(b"<html></html>");
+        assert_eq!(submission.validate(&roles).unwrap_err(), "materials must be a PDF");
+    }
+
+    #[test]
+    fn test_application_sheet_row() {
+        let columns = ApplicantSheetColumns {
+            timestamp: 0,
+            name: 1,
+            email: 2,
+            resume: 3,
+            materials: 4,
+            status: 5,
+            sent_email_received: 6,
+            sent_email_follow_up: 7,
+            ..Default::default()
+        };
+        let submission = ApplicationSubmission {
+            name: " Jane Doe ".to_string(),
+            email: "jane@example.com".to_string(),
+            ..Default::default()
+        };
+
+        let row = submission.sheet_row(&columns, Utc.ymd(2021, 4, 13).and_hms(17, 30, 0), "https://drive/r", "https://drive/m");
+        assert_eq!(row, vec!["04/13/2021 09:30:00", "Jane Doe", "jane@example.com", "https://drive/r", "https://drive/m", "", "", ""]);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2, Duration::hours(1));
+        let now = Utc.ymd(2021, 4, 13).and_hms(9, 0, 0);
+        assert!(limiter.check("10.0.0.1", now));
+        assert!(limiter.check("10.0.0.1", now + Duration::minutes(1)));
+        assert!(!limiter.check("10.0.0.1", now + Duration::minutes(2)));
+        assert!(limiter.check("10.0.0.2", now + Duration::minutes(2)));
+        assert!(limiter.check("10.0.0.1", now + Duration::minutes(61)));
+    }
+
+    #[test]
+    fn test_application_submission_helpers() {
+        // Whatever the client puts in the header comes before what our load balancer appends.
+        assert_eq!(client_ip("1.2.3.4, 10.0.0.1"), "10.0.0.1");
+        assert_eq!(client_ip("10.0.0.1"), "10.0.0.1");
+        assert_eq!(client_ip(""), "");
+
+        assert_eq!(appended_row("'Form Responses 1'!A1002:S1002"), Some(1002));
+        assert_eq!(appended_row("Sheet1!A7"), Some(7));
+        assert_eq!(appended_row(""), None);
+
+        assert_eq!(
+            application_filename("Jane O'Brien", "Resume", "Hardware Engineer", 255),
+            "Jane O'Brien - Resume - Hardware Engineer - 00000000000000ff.pdf"
+        );
+    }
+}
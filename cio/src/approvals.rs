@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::chat::{notify_channel, ChatChannel};
+use crate::configs::{User, Users};
+use crate::db::Database;
+use crate::notifications::notify_user;
+use crate::reactions::message_ref;
+use crate::schema::approval_requests;
+use crate::utils::GSUITE_DOMAIN;
+
+/// The operations we can ask for approval before doing, keyed in the `approvals`
+/// section of our configs.
+pub static APPROVAL_OPERATIONS: &[&str] = &["budget_overage", "offboarding", "offer_out_of_band"];
+
+/// The status of an approval request still waiting on approvers.
+pub const APPROVAL_PENDING: &str = "pending";
+/// The status of an approval request every approver it needs approved.
+pub const APPROVAL_APPROVED: &str = "approved";
+/// The status of an approval request one of the approvers rejected.
+pub const APPROVAL_REJECTED: &str = "rejected";
+
+/// Who has to approve an operation before we do it, keyed by the operation in the
+/// `approvals` section of our configs. Either everyone in `approvers` in order, or any
+/// `required` people in `group`.
+///
+/// ```toml
+/// [approvals.offboarding]
+/// approvers = ["jess", "steve"]
+///
+/// [approvals.budget_overage]
+/// group = "finance"
+/// required = 2
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct ApprovalChainConfig {
+    /// The usernames of the people who approve, in the order they approve in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approvers: Vec<String>,
+    /// The group whose members approve, in any order.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub group: String,
+    /// How many people in the group have to approve, 1 if not set.
+    #[serde(default)]
+    pub required: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[table_name = "approval_requests"]
+pub struct NewApprovalRequest {
+    pub operation: String,
+    /// What the operation is on, ie. the username for offboarding.
+    pub subject: String,
+    pub description: String,
+    /// The usernames of the people who can approve. We keep them on the request so
+    /// changes to the chain in our configs do not change requests already waiting.
+    pub approvers: Vec<String>,
+    pub required: i32,
+    /// If the approvers have to approve in order.
+    pub ordered: bool,
+    pub approved_by: Vec<String>,
+    pub rejected_by: String,
+    /// One of `pending`, `approved`, or `rejected`.
+    pub status: String,
+    pub requested_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+}
+
+impl NewApprovalRequest {
+    /// Create a request for an operation from its approval chain.
+    #[instrument(skip(users))]
+    #[inline]
+    pub fn new(chain: &ApprovalChainConfig, operation: &str, subject: &str, description: &str, users: &[User]) -> Self {
+        let (approvers, required, ordered) = if !chain.approvers.is_empty() {
+            (chain.approvers.clone(), chain.approvers.len(), true)
+        } else {
+            let members: Vec<String> = users.iter().filter(|u| u.groups.contains(&chain.group)).map(|u| u.username.to_string()).collect();
+            (members, chain.required.max(1), false)
+        };
+
+        NewApprovalRequest {
+            operation: operation.to_string(),
+            subject: subject.to_string(),
+            description: description.to_string(),
+            approvers,
+            required: required as i32,
+            ordered,
+            approved_by: Default::default(),
+            rejected_by: Default::default(),
+            status: APPROVAL_PENDING.to_string(),
+            requested_at: Utc::now(),
+            decided_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, AsChangeset)]
+#[table_name = "approval_requests"]
+pub struct ApprovalRequest {
+    pub id: i32,
+    pub operation: String,
+    pub subject: String,
+    pub description: String,
+    pub approvers: Vec<String>,
+    pub required: i32,
+    pub ordered: bool,
+    pub approved_by: Vec<String>,
+    pub rejected_by: String,
+    pub status: String,
+    pub requested_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+}
+
+impl ApprovalRequest {
+    /// The people who can approve the request next. For an ordered chain this is only
+    /// the first approver who has not approved yet.
+    #[instrument]
+    #[inline]
+    pub fn next_approvers(&self) -> Vec<String> {
+        if self.status != APPROVAL_PENDING {
+            return Default::default();
+        }
+
+        let waiting = self.approvers.iter().filter(|a| !self.approved_by.contains(a)).cloned();
+        if self.ordered {
+            waiting.take(1).collect()
+        } else {
+            waiting.collect()
+        }
+    }
+
+    /// Approve the request as a user.
+    #[instrument]
+    #[inline]
+    pub fn approve(&mut self, username: &str, now: DateTime<Utc>) -> Result<(), String> {
+        self.check_pending(username)?;
+        if self.approved_by.iter().any(|a| a == username) {
+            return Err(format!("{} already approved this", username));
+        }
+        if !self.next_approvers().iter().any(|a| a == username) {
+            return Err(format!("{} cannot approve this yet, it is waiting on {}", username, self.next_approvers().join(", ")));
+        }
+
+        self.approved_by.push(username.to_string());
+        if self.approved_by.len() >= self.required as usize {
+            self.status = APPROVAL_APPROVED.to_string();
+            self.decided_at = Some(now);
+        }
+
+        Ok(())
+    }
+
+    /// Reject the request as a user. Any of the approvers can reject it, whether it is
+    /// their turn or not.
+    #[instrument]
+    #[inline]
+    pub fn reject(&mut self, username: &str, now: DateTime<Utc>) -> Result<(), String> {
+        self.check_pending(username)?;
+
+        self.rejected_by = username.to_string();
+        self.status = APPROVAL_REJECTED.to_string();
+        self.decided_at = Some(now);
+
+        Ok(())
+    }
+
+    fn check_pending(&self, username: &str) -> Result<(), String> {
+        if self.status != APPROVAL_PENDING {
+            return Err(format!("this was already {}", self.status));
+        }
+        if !self.approvers.iter().any(|a| a == username) {
+            return Err(format!("{} is not one of the approvers for {}", username, self.operation));
+        }
+
+        Ok(())
+    }
+
+    /// Get the message we post to ask for approval. The first block is tagged so
+    /// reactions to the message can find the request again.
+    #[instrument]
+    #[inline]
+    pub fn as_slack_msg(&self) -> Value {
+        let who = if self.ordered {
+            format!("{} in order", self.approvers.join(", then "))
+        } else {
+            format!("{} of {}", self.required, self.approvers.join(", "))
+        };
+
+        json!({
+            "blocks": [
+                {
+                    "type": "section",
+                    "block_id": message_ref("approval", &self.id.to_string()),
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": format!(":raised_hand: *{}* needs approval: {}", self.operation.replace('_', " "), self.description),
+                    },
+                },
+                {
+                    "type": "context",
+                    "elements": [{ "type": "mrkdwn", "text": format!("needs {} | react to approve or reject it", who) }],
+                },
+            ],
+        })
+    }
+}
+
+/// The channel we ask for approval of an operation in.
+#[instrument]
+#[inline]
+pub fn approval_channel(operation: &str) -> ChatChannel {
+    match operation {
+        "budget_overage" | "offer_out_of_band" => ChatChannel::Finance,
+        _ => ChatChannel::Security,
+    }
+}
+
+/// Get the request for an operation on a subject, if we asked for one.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_approval_request(db: &Database, operation: &str, subject: &str) -> Option<ApprovalRequest> {
+    approval_requests::table
+        .filter(approval_requests::operation.eq(operation))
+        .filter(approval_requests::subject.eq(subject))
+        .first::<ApprovalRequest>(&db.conn())
+        .ok()
+}
+
+/// Returns if an operation on a subject is approved. Operations without an approval
+/// chain in our configs are always approved. Otherwise we ask for approval the first
+/// time, and the operation is approved once everyone in the chain approved it.
+#[instrument(skip(db, approvals))]
+#[inline]
+pub async fn request_approval(db: &Database, approvals: &BTreeMap<String, ApprovalChainConfig>, operation: &str, subject: &str, description: &str) -> bool {
+    let chain = match approvals.get(operation) {
+        Some(c) => c,
+        None => return true,
+    };
+
+    if let Some(request) = get_approval_request(db, operation, subject) {
+        return request.status == APPROVAL_APPROVED;
+    }
+
+    let users: Vec<User> = Users::get_from_db(db).into_iter().collect();
+    let request: ApprovalRequest = diesel::insert_into(approval_requests::table)
+        .values(&NewApprovalRequest::new(chain, operation, subject, description, &users))
+        .get_result(&db.conn())
+        .unwrap_or_else(|e| panic!("creating the approval request for {} `{}` failed: {}", operation, subject, e));
+    println!("[approvals] asked for approval of {} `{}`", operation, subject);
+
+    notify_channel(approval_channel(operation), request.as_slack_msg()).await;
+    notify_next_approvers(db, &request).await;
+
+    false
+}
+
+/// Forget the request for an operation on a subject, so we ask again the next time,
+/// ie. for someone who was added back to our configs before they were offboarded.
+#[instrument(skip(db))]
+#[inline]
+pub fn clear_approval_request(db: &Database, operation: &str, subject: &str) {
+    diesel::delete(
+        approval_requests::table
+            .filter(approval_requests::operation.eq(operation))
+            .filter(approval_requests::subject.eq(subject)),
+    )
+    .execute(&db.conn())
+    .unwrap_or_else(|e| panic!("clearing the approval request for {} `{}` failed: {}", operation, subject, e));
+}
+
+/// Let the people who can approve a request next know it is waiting on them.
+#[instrument(skip(db))]
+#[inline]
+async fn notify_next_approvers(db: &Database, request: &ApprovalRequest) {
+    for approver in request.next_approvers() {
+        notify_user(
+            db,
+            &format!("{}@{}", approver, GSUITE_DOMAIN),
+            json!({
+                "text": format!("*{}* is waiting on your approval: {}", request.operation.replace('_', " "), request.description),
+            }),
+        )
+        .await;
+    }
+}
+
+/// Approve or reject a request from a reaction to its message. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn decide_approval_request(db: &Database, id: &str, user: &User, approve: bool) -> Result<String, String> {
+    let id: i32 = id.parse().map_err(|_| format!("{} is not an approval request", id))?;
+    let mut request = approval_requests::table
+        .find(id)
+        .first::<ApprovalRequest>(&db.conn())
+        .map_err(|e| format!("approval request {} does not exist: {}", id, e))?;
+
+    if approve {
+        request.approve(&user.username, Utc::now())?;
+    } else {
+        request.reject(&user.username, Utc::now())?;
+    }
+    diesel::update(approval_requests::table.find(id))
+        .set(&request)
+        .execute(&db.conn())
+        .map_err(|e| format!("updating approval request {} failed: {}", id, e))?;
+
+    Ok(match request.status.as_str() {
+        APPROVAL_APPROVED => format!("{} approved this, it will happen the next time the job runs", user.full_name()),
+        APPROVAL_REJECTED => format!("{} rejected this", user.full_name()),
+        _ => {
+            notify_next_approvers(db, &request).await;
+            format!(
+                "{} approved this, {} of {} approvals, waiting on {}",
+                user.full_name(),
+                request.approved_by.len(),
+                request.required,
+                request.next_approvers().join(", ")
+            )
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::approvals::{ApprovalChainConfig, ApprovalRequest, NewApprovalRequest, APPROVAL_APPROVED, APPROVAL_PENDING, APPROVAL_REJECTED};
+
+    fn request(chain: &ApprovalChainConfig) -> ApprovalRequest {
+        let r = NewApprovalRequest::new(chain, "offboarding", "jane", "offboard Jane", &[]);
+        ApprovalRequest {
+            id: 1,
+            operation: r.operation,
+            subject: r.subject,
+            description: r.description,
+            approvers: r.approvers,
+            required: r.required,
+            ordered: r.ordered,
+            approved_by: r.approved_by,
+            rejected_by: r.rejected_by,
+            status: r.status,
+            requested_at: r.requested_at,
+            decided_at: r.decided_at,
+        }
+    }
+
+    #[test]
+    fn test_ordered_approval_chain() {
+        let chain = ApprovalChainConfig {
+            approvers: vec!["jess".to_string(), "steve".to_string()],
+            ..Default::default()
+        };
+        let mut r = request(&chain);
+        assert!(r.ordered);
+        assert_eq!(r.required, 2);
+        assert_eq!(r.next_approvers(), vec!["jess".to_string()]);
+
+        // Approvers go in order.
+        assert!(r.approve("steve", Utc::now()).is_err());
+        assert!(r.approve("sam", Utc::now()).is_err());
+        r.approve("jess", Utc::now()).unwrap();
+        assert!(r.approve("jess", Utc::now()).is_err());
+        assert_eq!(r.status, APPROVAL_PENDING);
+        assert_eq!(r.next_approvers(), vec!["steve".to_string()]);
+
+        r.approve("steve", Utc::now()).unwrap();
+        assert_eq!(r.status, APPROVAL_APPROVED);
+        assert!(r.decided_at.is_some());
+        assert!(r.next_approvers().is_empty());
+        assert!(r.reject("steve", Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_group_approval_chain() {
+        let chain = ApprovalChainConfig {
+            group: "finance".to_string(),
+            required: 2,
+            ..Default::default()
+        };
+        let mut r = request(&chain);
+        assert!(!r.ordered);
+        r.approvers = vec!["jess".to_string(), "steve".to_string(), "sam".to_string()];
+
+        // Any of the group can approve in any order.
+        r.approve("sam", Utc::now()).unwrap();
+        assert_eq!(r.next_approvers(), vec!["jess".to_string(), "steve".to_string()]);
+        assert_eq!(r.status, APPROVAL_PENDING);
+
+        // Any of them can reject.
+        let mut rejected = r.clone();
+        rejected.reject("steve", Utc::now()).unwrap();
+        assert_eq!(rejected.status, APPROVAL_REJECTED);
+        assert_eq!(rejected.rejected_by, "steve");
+
+        r.approve("jess", Utc::now()).unwrap();
+        assert_eq!(r.status, APPROVAL_APPROVED);
+    }
+}
@@ -0,0 +1,226 @@
+use std::env;
+use std::io::Write;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Jsonb;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::backups::{list_gcs_objects, upload_to_gcs};
+
+/// An attachment in an Airtable attachment column, ie. a receipt or a resume.
+///
+/// The URLs Airtable gives us for attachments expire, so the sync mirrors attachments to
+/// the bucket in the `AIRTABLE_ATTACHMENTS_BUCKET` environment variable and points `url`
+/// at the mirror. When we send an attachment to Airtable that Airtable does not have yet,
+/// ie. one without an `id`, Airtable downloads it from `url`.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct AirtableAttachment {
+    /// The ID of the attachment in Airtable, empty if Airtable does not have it yet.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub size: i64,
+    #[serde(default, skip_serializing_if = "String::is_empty", rename = "type")]
+    pub content_type: String,
+}
+
+fn is_zero(size: &i64) -> bool {
+    *size == 0
+}
+
+/// The attachments in an Airtable attachment column. Models generated by the `db` macro
+/// with a field of this type mirror the attachments whenever they read them from Airtable.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, FromSqlRow, AsExpression, Deserialize, Serialize)]
+#[sql_type = "Jsonb"]
+#[serde(transparent)]
+pub struct AirtableAttachments(pub Vec<AirtableAttachment>);
+
+impl FromSql<Jsonb, Pg> for AirtableAttachments {
+    #[instrument]
+    #[inline]
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value = <serde_json::Value as FromSql<Jsonb, Pg>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(value).unwrap())
+    }
+}
+
+impl ToSql<Jsonb, Pg> for AirtableAttachments {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        let value = serde_json::to_value(self).unwrap();
+        <serde_json::Value as ToSql<Jsonb, Pg>>::to_sql(&value, out)
+    }
+}
+
+/// The base URL of the mirrored attachments, the CDN in the `AIRTABLE_ATTACHMENTS_URL`
+/// environment variable or the public URL of the bucket if it is not set.
+fn attachments_base_url(bucket: &str) -> String {
+    let base_url = env::var("AIRTABLE_ATTACHMENTS_URL").unwrap_or_default();
+    if base_url.is_empty() {
+        return format!("https://storage.googleapis.com/{}", bucket);
+    }
+
+    base_url.trim_end_matches('/').to_string()
+}
+
+/// The name of the object we mirror an attachment to, ie.
+/// `airtable/software-vendors/attXXXX/receipt.pdf`. Attachments never change in Airtable,
+/// only get added or removed, so the ID of the attachment is enough to make it unique.
+#[instrument]
+#[inline]
+pub fn attachment_object_name(table: &str, id: &str, filename: &str) -> String {
+    let table: Vec<String> = table.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(|w| w.to_string()).collect();
+    let filename = filename.replace('/', "-");
+
+    format!("airtable/{}/{}/{}", table.join("-"), id, if filename.is_empty() { "attachment" } else { filename.as_str() })
+}
+
+/// Download the contents of an attachment.
+async fn download_attachment(url: &str) -> Result<Vec<u8>, String> {
+    let resp = reqwest::get(url).await.map_err(|e| format!("downloading {} failed: {}", url, e))?;
+    if !resp.status().is_success() {
+        return Err(format!("downloading {} failed with status {}", url, resp.status()));
+    }
+
+    resp.bytes().await.map(|b| b.to_vec()).map_err(|e| format!("reading {} failed: {}", url, e))
+}
+
+impl AirtableAttachment {
+    /// Upload the contents of a new attachment, so it can be sent to Airtable, which
+    /// downloads it from the mirror.
+    #[instrument(skip(body))]
+    #[inline]
+    pub async fn upload(table: &str, filename: &str, content_type: &str, body: Vec<u8>) -> Result<Self, String> {
+        let bucket = env::var("AIRTABLE_ATTACHMENTS_BUCKET").unwrap_or_default();
+        if bucket.is_empty() {
+            return Err("AIRTABLE_ATTACHMENTS_BUCKET is not set, so we can not upload attachments".to_string());
+        }
+
+        // New attachments do not have an ID yet, so we name them after their contents.
+        let checksum: String = openssl::sha::sha256(&body).iter().map(|b| format!("{:02x}", b)).collect();
+        let name = attachment_object_name(table, &checksum, filename);
+        let size = body.len() as i64;
+        upload_to_gcs(&bucket, &name, content_type, body).await?;
+
+        Ok(AirtableAttachment {
+            id: Default::default(),
+            url: format!("{}/{}", attachments_base_url(&bucket), name.replace(' ', "%20")),
+            filename: filename.to_string(),
+            size,
+            content_type: content_type.to_string(),
+        })
+    }
+}
+
+impl AirtableAttachments {
+    #[instrument]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Mirror the attachments that are still on Airtable to the bucket in the
+    /// `AIRTABLE_ATTACHMENTS_BUCKET` environment variable, and point them at the mirror.
+    /// Attachments we mirrored before are not downloaded again. This does nothing if the
+    /// bucket is not set.
+    #[instrument]
+    #[inline]
+    pub async fn mirror(&mut self, table: &str) {
+        let bucket = env::var("AIRTABLE_ATTACHMENTS_BUCKET").unwrap_or_default();
+        if bucket.is_empty() {
+            return;
+        }
+        let base_url = attachments_base_url(&bucket);
+
+        for attachment in self.0.iter_mut() {
+            if attachment.id.is_empty() || attachment.url.starts_with(&base_url) {
+                continue;
+            }
+
+            let name = attachment_object_name(table, &attachment.id, &attachment.filename);
+            let url = format!("{}/{}", base_url, name.replace(' ', "%20"));
+            match list_gcs_objects(&bucket, &name).await {
+                Ok(existing) if existing.contains(&name) => {
+                    attachment.url = url;
+                    continue;
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    println!("[airtable] checking for the mirror of attachment {} failed: {}", attachment.id, e);
+                    continue;
+                }
+            }
+
+            let content_type = if attachment.content_type.is_empty() {
+                "application/octet-stream"
+            } else {
+                attachment.content_type.as_str()
+            };
+            match download_attachment(&attachment.url).await {
+                Ok(body) => match upload_to_gcs(&bucket, &name, content_type, body).await {
+                    Ok(()) => attachment.url = url,
+                    Err(e) => println!("[airtable] mirroring attachment {} failed: {}", attachment.id, e),
+                },
+                Err(e) => println!("[airtable] mirroring attachment {} failed: {}", attachment.id, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attachments::{attachment_object_name, AirtableAttachment, AirtableAttachments};
+
+    #[test]
+    fn test_attachment_object_name() {
+        assert_eq!(
+            attachment_object_name("Software Vendors", "attXYZ", "receipt 2021.pdf"),
+            "airtable/software-vendors/attXYZ/receipt 2021.pdf"
+        );
+        assert_eq!(attachment_object_name("Applicants", "attABC", "a/b.pdf"), "airtable/applicants/attABC/a-b.pdf");
+        assert_eq!(attachment_object_name("Applicants", "attABC", ""), "airtable/applicants/attABC/attachment");
+    }
+
+    #[test]
+    fn test_airtable_attachments_serde() {
+        let attachments: AirtableAttachments = serde_json::from_str(
+            r#"[{
+                "id": "attXYZ",
+                "url": "https://dl.airtable.com/.attachments/abc/receipt.pdf",
+                "filename": "receipt.pdf",
+                "size": 1024,
+                "type": "application/pdf",
+                "thumbnails": { "small": { "url": "https://dl.airtable.com/small.png", "width": 36, "height": 36 } }
+            }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            attachments,
+            AirtableAttachments(vec![AirtableAttachment {
+                id: "attXYZ".to_string(),
+                url: "https://dl.airtable.com/.attachments/abc/receipt.pdf".to_string(),
+                filename: "receipt.pdf".to_string(),
+                size: 1024,
+                content_type: "application/pdf".to_string(),
+            }])
+        );
+
+        let new = AirtableAttachments(vec![AirtableAttachment {
+            url: "https://storage.googleapis.com/attachments/receipt.pdf".to_string(),
+            filename: "receipt.pdf".to_string(),
+            ..Default::default()
+        }]);
+        assert_eq!(
+            serde_json::to_value(&new).unwrap(),
+            serde_json::json!([{ "url": "https://storage.googleapis.com/attachments/receipt.pdf", "filename": "receipt.pdf" }])
+        );
+        assert!(AirtableAttachments::default().is_empty());
+    }
+}
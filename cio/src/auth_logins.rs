@@ -25,6 +25,7 @@ use crate::utils::{DOMAIN, GSUITE_DOMAIN};
     new_struct_name = "AuthUser",
     airtable_base_id = "AIRTABLE_BASE_ID_CUSTOMER_LEADS",
     airtable_table = "AIRTABLE_AUTH_USERS_TABLE",
+    source = "auth0",
     custom_partial_eq = true,
     match_on = {
         "user_id" = "String",
@@ -108,6 +109,7 @@ impl PartialEq for AuthUser {
     new_struct_name = "AuthUserLogin",
     airtable_base_id = "AIRTABLE_BASE_ID_CUSTOMER_LEADS",
     airtable_table = "AIRTABLE_AUTH_USER_LOGINS_TABLE",
+    source = "auth0",
     match_on = {
         "user_id" = "String",
         "date" = "DateTime<Utc>",
@@ -339,11 +341,16 @@ pub async fn get_auth_users(domain: String, db: &Database) -> Vec<NewAuthUser> {
         // https://auth0.com/docs/policies/rate-limit-policy/management-api-endpoint-rate-limits
         thread::sleep(rate_limit_sleep);
 
-        // Update our database with all the auth_user_logins.
-        for mut auth_user_login in auth_user_logins {
-            auth_user_login.email = user.email.to_string();
-            auth_user_login.upsert(db).await;
-        }
+        // Update our database with all the auth_user_logins, they are pushed to Airtable
+        // with the rest of the table by the job.
+        let auth_user_logins: Vec<NewAuthUserLogin> = auth_user_logins
+            .into_iter()
+            .map(|mut l| {
+                l.email = user.email.to_string();
+                l
+            })
+            .collect();
+        NewAuthUserLogin::upsert_all(db, &auth_user_logins);
     }
 
     auth_users
@@ -0,0 +1,352 @@
+use std::env;
+
+use checkr::{Checkr, Report};
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::applicants::Applicant;
+use crate::chat::ChatChannel;
+use crate::configs::OpenRole;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, notify_user, NotificationPriority};
+use crate::schema::{applicants, background_checks, open_roles};
+use crate::utils::GSUITE_DOMAIN;
+
+/// The Checkr package we run for everyone we give an offer to.
+pub static BACKGROUND_CHECK_PACKAGE: &str = "premium_criminal";
+
+/// A background check for an applicant. The result is only kept here, never in
+/// Airtable, and only the hiring manager for the role is told it. The applicant in
+/// Airtable only has the status of the check.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "background_checks"]
+pub struct NewBackgroundCheck {
+    pub applicant_id: i32,
+    pub email: String,
+    pub candidate_id: String,
+    /// The Checkr package, ie. `premium_criminal`.
+    pub package: String,
+    pub report_id: String,
+    /// The status of the report in Checkr, or `requested` until the applicant fills out
+    /// the invitation.
+    pub status: String,
+    /// The result of the report once it is complete, `clear` or `consider`.
+    pub result: String,
+    pub adjudication: String,
+    pub requested_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// When we told the hiring manager the result.
+    pub notified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, AsChangeset)]
+#[table_name = "background_checks"]
+pub struct BackgroundCheck {
+    pub id: i32,
+    pub applicant_id: i32,
+    pub email: String,
+    pub candidate_id: String,
+    pub package: String,
+    pub report_id: String,
+    pub status: String,
+    pub result: String,
+    pub adjudication: String,
+    pub requested_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub notified_at: Option<DateTime<Utc>>,
+}
+
+impl BackgroundCheck {
+    /// Update the check from its report in Checkr. Returns if the check is complete and
+    /// we have not told the hiring manager yet.
+    #[instrument(skip(report))]
+    #[inline]
+    pub fn apply_report(&mut self, report: &Report) -> bool {
+        self.report_id = report.id.to_string();
+        self.status = report.status.to_string();
+        self.result = report.result.to_string();
+        self.adjudication = report.adjudication.to_string();
+        if self.status == "complete" && self.completed_at.is_none() {
+            self.completed_at = Some(report.completed_at.unwrap_or_else(Utc::now));
+        }
+
+        self.status == "complete" && self.notified_at.is_none()
+    }
+}
+
+/// An event from a Checkr webhook.
+/// FROM: https://docs.checkr.com/#section/Webhooks
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CheckrEvent {
+    #[serde(default)]
+    pub id: String,
+    /// The type of event, ie. `report.completed` or `invitation.created`.
+    #[serde(default, rename = "type")]
+    pub event_type: String,
+    #[serde(default)]
+    pub data: CheckrEventData,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CheckrEventData {
+    /// The object the event is about, a report for `report.*` events.
+    #[serde(default)]
+    pub object: Value,
+}
+
+/// Set the status of a background check on an applicant, by the package of the report.
+/// Returns if it changed.
+#[instrument(skip(applicant))]
+#[inline]
+pub fn set_applicant_background_check_status(applicant: &mut Applicant, package: &str, status: &str) -> bool {
+    let field = if package.contains("premium_criminal") {
+        &mut applicant.criminal_background_check_status
+    } else if package.contains("motor_vehicle") {
+        &mut applicant.motor_vehicle_background_check_status
+    } else {
+        return false;
+    };
+    if *field == status {
+        return false;
+    }
+
+    *field = status.to_string();
+    true
+}
+
+/// Start a background check for an applicant by sending them an invitation from Checkr
+/// to fill out. Applicants who already have a check are left alone. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn start_background_check(db: &Database, applicant: &mut Applicant) -> Result<String, String> {
+    if let Ok(check) = background_checks::table
+        .filter(background_checks::applicant_id.eq(applicant.id))
+        .filter(background_checks::package.eq(BACKGROUND_CHECK_PACKAGE))
+        .first::<BackgroundCheck>(&db.conn())
+    {
+        return Ok(format!("{} already has a background check, it is {}", applicant.email, check.status));
+    }
+    // Checks started by hand in Checkr before we tracked them.
+    if !applicant.criminal_background_check_status.is_empty() {
+        return Ok(format!("{} already has a background check, it is {}", applicant.email, applicant.criminal_background_check_status));
+    }
+    if env::var("CHECKR_API_KEY").unwrap_or_default().is_empty() {
+        return Err(format!("CHECKR_API_KEY is not set, not starting a background check for {}", applicant.email));
+    }
+
+    let checkr = Checkr::new_from_env();
+    let candidates = checkr.list_candidates().await.map_err(|e| format!("listing the checkr candidates failed: {}", e))?;
+    let candidate = match candidates.into_iter().find(|c| c.email == applicant.email) {
+        Some(c) => c,
+        None => checkr
+            .create_candidate(&applicant.email)
+            .await
+            .map_err(|e| format!("creating the checkr candidate for {} failed: {}", applicant.email, e))?,
+    };
+    checkr
+        .create_invitation(&candidate.id, BACKGROUND_CHECK_PACKAGE)
+        .await
+        .map_err(|e| format!("inviting {} to a background check failed: {}", applicant.email, e))?;
+
+    diesel::insert_into(background_checks::table)
+        .values(&NewBackgroundCheck {
+            applicant_id: applicant.id,
+            email: applicant.email.to_string(),
+            candidate_id: candidate.id.to_string(),
+            package: BACKGROUND_CHECK_PACKAGE.to_string(),
+            report_id: Default::default(),
+            status: "requested".to_string(),
+            result: Default::default(),
+            adjudication: Default::default(),
+            requested_at: Utc::now(),
+            completed_at: None,
+            notified_at: None,
+        })
+        .execute(&db.conn())
+        .map_err(|e| format!("saving the background check for {} failed: {}", applicant.email, e))?;
+
+    applicant.request_background_check = true;
+    applicant.criminal_background_check_status = "requested".to_string();
+    applicant.set_synced_from("checkr");
+    applicant.update(db).await;
+
+    Ok(format!("sent background check invitation to {}", applicant.email))
+}
+
+/// Tell the hiring manager for the applicant's role the result of their background
+/// check. Roles without a hiring manager get a note in the hiring channel, without
+/// the result, so someone sets one.
+#[instrument(skip(db))]
+#[inline]
+async fn notify_hiring_manager(db: &Database, applicant: &Applicant, check: &BackgroundCheck) {
+    let hiring_manager = open_roles::table
+        .filter(open_roles::name.eq(applicant.role.to_string()))
+        .first::<OpenRole>(&db.conn())
+        .map(|r| r.hiring_manager)
+        .unwrap_or_default();
+    if hiring_manager.is_empty() {
+        notify(
+            db,
+            NotificationPriority::Low,
+            ChatChannel::Hiring,
+            "background_checks.no_hiring_manager",
+            json!({
+                "text": render_message("background_checks.no_hiring_manager", &json!({ "name": applicant.name, "role": applicant.role })),
+            }),
+        )
+        .await;
+        return;
+    }
+
+    notify_user(
+        db,
+        &format!("{}@{}", hiring_manager, GSUITE_DOMAIN),
+        json!({
+            "text": render_message(
+                "background_checks.completed",
+                &json!({
+                    "name": applicant.name,
+                    "role": applicant.role,
+                    "result": check.result,
+                    "url": format!("https://dashboard.checkr.com/reports/{}", check.report_id),
+                })
+            ),
+        }),
+    )
+    .await;
+}
+
+/// Record a report from Checkr for an applicant, telling the hiring manager once it is
+/// complete. Returns what was done.
+#[instrument(skip(db, report))]
+#[inline]
+pub async fn record_background_check_report(db: &Database, applicant: &Applicant, candidate_id: &str, report: &Report) -> Result<String, String> {
+    let mut check = match background_checks::table
+        .filter(background_checks::applicant_id.eq(applicant.id))
+        .filter(background_checks::package.eq(report.package.to_string()))
+        .first::<BackgroundCheck>(&db.conn())
+    {
+        Ok(c) => c,
+        Err(_) => diesel::insert_into(background_checks::table)
+            .values(&NewBackgroundCheck {
+                applicant_id: applicant.id,
+                email: applicant.email.to_string(),
+                candidate_id: candidate_id.to_string(),
+                package: report.package.to_string(),
+                report_id: report.id.to_string(),
+                status: report.status.to_string(),
+                result: Default::default(),
+                adjudication: Default::default(),
+                requested_at: report.created_at,
+                completed_at: None,
+                notified_at: None,
+            })
+            .get_result(&db.conn())
+            .map_err(|e| format!("saving the background check for {} failed: {}", applicant.email, e))?,
+    };
+
+    if check.apply_report(report) {
+        notify_hiring_manager(db, applicant, &check).await;
+        check.notified_at = Some(Utc::now());
+    }
+    diesel::update(background_checks::table.find(check.id))
+        .set(&check)
+        .execute(&db.conn())
+        .map_err(|e| format!("updating the background check for {} failed: {}", applicant.email, e))?;
+
+    // Only the status goes on the applicant, since everyone with access to the
+    // applications in Airtable can see it.
+    let mut applicant = applicant.clone();
+    if set_applicant_background_check_status(&mut applicant, &report.package, &report.status) {
+        applicant.set_synced_from("checkr");
+        applicant.update(db).await;
+    }
+
+    Ok(format!("the {} background check for {} is {}", report.package, applicant.email, report.status))
+}
+
+/// Handle an event from a Checkr webhook. Only report events change anything, for
+/// candidates we started a background check for. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn handle_checkr_event(db: &Database, event: &CheckrEvent) -> Result<String, String> {
+    if !event.event_type.starts_with("report.") {
+        return Ok(format!("ignoring {} event", event.event_type));
+    }
+
+    let report: Report = serde_json::from_value(event.data.object.clone()).map_err(|e| format!("decoding the report in checkr event {} failed: {}", event.id, e))?;
+    let check = background_checks::table
+        .filter(background_checks::candidate_id.eq(report.candidate_id.to_string()))
+        .first::<BackgroundCheck>(&db.conn())
+        .map_err(|_| format!("checkr candidate {} is not one we started a background check for", report.candidate_id))?;
+    let applicant = applicants::dsl::applicants
+        .find(check.applicant_id)
+        .first::<Applicant>(&db.conn())
+        .map_err(|e| format!("applicant {} for checkr candidate {} does not exist: {}", check.applicant_id, report.candidate_id, e))?;
+
+    record_background_check_report(db, &applicant, &report.candidate_id, &report).await
+}
+
+#[cfg(test)]
+mod tests {
+    use checkr::Report;
+    use chrono::{TimeZone, Utc};
+
+    use crate::background_checks::{BackgroundCheck, CheckrEvent};
+
+    #[test]
+    fn test_apply_report() {
+        let event: CheckrEvent = serde_json::from_str(
+            r#"{
+  "id": "5f4d8b1c2a7e3d0012a4c6b9",
+  "object": "event",
+  "type": "report.completed",
+  "created_at": "2021-05-07T19:02:11Z",
+  "data": {
+    "object": {
+      "id": "4722c07dd9a10c3985ae432a",
+      "object": "report",
+      "status": "complete",
+      "result": "clear",
+      "adjudication": null,
+      "package": "premium_criminal",
+      "candidate_id": "e44aa283528e6fde7d542194",
+      "created_at": "2021-05-04T16:20:00Z",
+      "completed_at": "2021-05-07T19:02:10Z"
+    }
+  }
+}"#,
+        )
+        .unwrap();
+        assert_eq!(event.event_type, "report.completed");
+        let report: Report = serde_json::from_value(event.data.object).unwrap();
+
+        let mut check = BackgroundCheck {
+            id: 1,
+            applicant_id: 42,
+            email: "jane@example.com".to_string(),
+            candidate_id: "e44aa283528e6fde7d542194".to_string(),
+            package: "premium_criminal".to_string(),
+            report_id: "".to_string(),
+            status: "requested".to_string(),
+            result: "".to_string(),
+            adjudication: "".to_string(),
+            requested_at: Utc.ymd(2021, 5, 3).and_hms(18, 0, 0),
+            completed_at: None,
+            notified_at: None,
+        };
+        assert!(check.apply_report(&report));
+        assert_eq!(check.status, "complete");
+        assert_eq!(check.result, "clear");
+        assert_eq!(check.report_id, "4722c07dd9a10c3985ae432a");
+        assert_eq!(check.completed_at, Some(Utc.ymd(2021, 5, 7).and_hms(19, 2, 10)));
+
+        // The hiring manager is only told once.
+        check.notified_at = Some(Utc::now());
+        assert!(!check.apply_report(&report));
+    }
+}
@@ -0,0 +1,673 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use airtable_api::Record;
+use chrono::naive::NaiveDateTime;
+use chrono::offset::Utc;
+use chrono::{DateTime, Datelike, Duration};
+use diesel::pg::PgConnection;
+use diesel::sql_types::{BigInt, Text};
+use diesel::{sql_query, Connection, RunQueryDsl};
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{airtable_client, airtable_tables, AIRTABLE_GRID_VIEW};
+use crate::chat::{notify_channel, ChatChannel};
+use crate::utils::get_gcp_token;
+
+/// The scope we need to read and write backups in Google Cloud Storage.
+static GCS_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+
+/// A snapshot of every record in an Airtable table at a point in time.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AirtableSnapshot {
+    pub base_id: String,
+    pub table: String,
+    pub taken_at: DateTime<Utc>,
+    /// The hex encoded SHA-256 of the JSON encoded records, so we can tell if a
+    /// snapshot was truncated or modified before we restore from it.
+    pub checksum: String,
+    pub records: Vec<Record<Value>>,
+}
+
+impl AirtableSnapshot {
+    /// Create a new snapshot for the records and compute its checksum.
+    #[instrument(skip(records))]
+    #[inline]
+    pub fn new(base_id: &str, table: &str, taken_at: DateTime<Utc>, records: Vec<Record<Value>>) -> Self {
+        AirtableSnapshot {
+            base_id: base_id.to_string(),
+            table: table.to_string(),
+            taken_at,
+            checksum: checksum_records(&records),
+            records,
+        }
+    }
+
+    /// Returns if the checksum matches the records in the snapshot.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn verify(&self) -> bool {
+        self.checksum == checksum_records(&self.records)
+    }
+
+    /// The name of the object in the backups bucket for the snapshot, ie.
+    /// `airtable/2021-04-08T060000Z/appduLHDVQ332gKyf/Software Vendors.json`.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn object_name(&self) -> String {
+        format!("airtable/{}/{}/{}.json", self.taken_at.format("%Y-%m-%dT%H%M%SZ"), self.base_id, self.table)
+    }
+}
+
+/// Compute the hex encoded SHA-256 of the JSON encoded records.
+#[instrument(skip(records))]
+#[inline]
+pub fn checksum_records(records: &[Record<Value>]) -> String {
+    let body = serde_json::to_vec(records).unwrap();
+    openssl::sha::sha256(&body).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Get the bucket we store backups in.
+fn get_backup_bucket() -> String {
+    env::var("CIO_BACKUP_BUCKET").expect("the CIO_BACKUP_BUCKET env variable must be set")
+}
+
+/// Upload an object to Google Cloud Storage.
+#[instrument(skip(body))]
+#[inline]
+pub async fn upload_to_gcs(bucket: &str, name: &str, content_type: &str, body: Vec<u8>) -> Result<(), String> {
+    let token = get_gcp_token(&[GCS_SCOPE]).await;
+    let mut url = Url::parse("https://storage.googleapis.com/upload/storage/v1/b").unwrap();
+    url.path_segments_mut().unwrap().push(bucket).push("o");
+    url.query_pairs_mut().append_pair("uploadType", "media").append_pair("name", name);
+
+    let resp = Client::new()
+        .post(url)
+        .bearer_auth(token.as_str())
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match resp.status() {
+        StatusCode::OK => Ok(()),
+        s => Err(format!("uploading {} to gs://{} failed with status {}: {}", name, bucket, s, resp.text().await.unwrap_or_default())),
+    }
+}
+
+/// Download an object from Google Cloud Storage.
+#[instrument]
+#[inline]
+pub async fn download_from_gcs(bucket: &str, name: &str) -> Result<Vec<u8>, String> {
+    let token = get_gcp_token(&[GCS_SCOPE]).await;
+    let mut url = Url::parse("https://storage.googleapis.com/storage/v1/b").unwrap();
+    url.path_segments_mut().unwrap().push(bucket).push("o").push(name);
+    url.query_pairs_mut().append_pair("alt", "media");
+
+    let resp = Client::new().get(url).bearer_auth(token.as_str()).send().await.map_err(|e| e.to_string())?;
+    match resp.status() {
+        StatusCode::OK => Ok(resp.bytes().await.map_err(|e| e.to_string())?.to_vec()),
+        s => Err(format!("downloading gs://{}/{} failed with status {}: {}", bucket, name, s, resp.text().await.unwrap_or_default())),
+    }
+}
+
+/// List the names of the objects in Google Cloud Storage with a prefix.
+#[instrument]
+#[inline]
+pub async fn list_gcs_objects(bucket: &str, prefix: &str) -> Result<Vec<String>, String> {
+    let token = get_gcp_token(&[GCS_SCOPE]).await;
+    let mut url = Url::parse("https://storage.googleapis.com/storage/v1/b").unwrap();
+    url.path_segments_mut().unwrap().push(bucket).push("o");
+
+    let mut names: Vec<String> = Default::default();
+    let mut page_token = String::new();
+    loop {
+        let mut u = url.clone();
+        u.query_pairs_mut().append_pair("prefix", prefix);
+        if !page_token.is_empty() {
+            u.query_pairs_mut().append_pair("pageToken", &page_token);
+        }
+
+        let resp: Value = Client::new()
+            .get(u)
+            .bearer_auth(token.as_str())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp["error"].is_null() {
+            return Err(format!("listing gs://{}/{} failed: {}", bucket, prefix, resp["error"]));
+        }
+
+        for item in resp["items"].as_array().cloned().unwrap_or_default() {
+            names.push(item["name"].as_str().unwrap_or_default().to_string());
+        }
+
+        page_token = resp["nextPageToken"].as_str().unwrap_or_default().to_string();
+        if page_token.is_empty() {
+            break;
+        }
+    }
+
+    Ok(names)
+}
+
+/// Delete an object from Google Cloud Storage.
+#[instrument]
+#[inline]
+pub async fn delete_from_gcs(bucket: &str, name: &str) -> Result<(), String> {
+    let token = get_gcp_token(&[GCS_SCOPE]).await;
+    let mut url = Url::parse("https://storage.googleapis.com/storage/v1/b").unwrap();
+    url.path_segments_mut().unwrap().push(bucket).push("o").push(name);
+
+    let resp = Client::new().delete(url).bearer_auth(token.as_str()).send().await.map_err(|e| e.to_string())?;
+    match resp.status() {
+        StatusCode::NO_CONTENT | StatusCode::OK => Ok(()),
+        s => Err(format!("deleting gs://{}/{} failed with status {}: {}", bucket, name, s, resp.text().await.unwrap_or_default())),
+    }
+}
+
+/// Snapshot every table in every Airtable base we sync to and upload the snapshots
+/// to the backups bucket. All the snapshots from a run share the same timestamp.
+#[instrument]
+#[inline]
+pub async fn backup_airtable() {
+    let bucket = get_backup_bucket();
+    let taken_at = Utc::now();
+
+    for (base_id, table) in airtable_tables() {
+        let airtable = airtable_client(base_id);
+        let records: Vec<Record<Value>> = match airtable.list_records(table, AIRTABLE_GRID_VIEW, vec![]).await {
+            Ok(r) => r,
+            Err(e) => {
+                println!("[backups] listing the records in {}/{} failed: {:?}", base_id, table, e);
+                continue;
+            }
+        };
+
+        let snapshot = AirtableSnapshot::new(base_id, table, taken_at, records);
+        let name = snapshot.object_name();
+        match upload_to_gcs(&bucket, &name, "application/json", serde_json::to_vec_pretty(&snapshot).unwrap()).await {
+            Ok(_) => println!("[backups] backed up {} records to gs://{}/{}", snapshot.records.len(), bucket, name),
+            Err(e) => println!("[backups] {}", e),
+        }
+    }
+}
+
+/// Strip the fields we should not write back to Airtable, like formulas and lookups,
+/// which Airtable refuses to let us set.
+#[instrument(skip(fields))]
+#[inline]
+pub fn strip_fields(fields: &Value, skip_fields: &[&str]) -> Value {
+    let mut fields = fields.clone();
+    if let Value::Object(o) = &mut fields {
+        for field in skip_fields {
+            o.remove(*field);
+        }
+    }
+
+    fields
+}
+
+/// Repopulate a table from a snapshot in the backups bucket. Records that still exist
+/// are updated in place, records that were deleted are created again. Records created
+/// again get new IDs, so links to them from other tables will need to be fixed by hand.
+/// Records added since the snapshot are left as is.
+#[instrument]
+#[inline]
+pub async fn restore_airtable_snapshot(name: &str, skip_fields: &[&str]) -> Result<(), String> {
+    let bucket = get_backup_bucket();
+    let body = download_from_gcs(&bucket, name).await?;
+    let snapshot: AirtableSnapshot = serde_json::from_slice(&body).map_err(|e| format!("decoding the snapshot failed: {}", e))?;
+    if !snapshot.verify() {
+        return Err(format!("the checksum for gs://{}/{} does not match its records, refusing to restore from it", bucket, name));
+    }
+
+    let airtable = airtable_client(&snapshot.base_id);
+    let existing: BTreeSet<String> = airtable
+        .list_records::<Value>(&snapshot.table, AIRTABLE_GRID_VIEW, vec![])
+        .await
+        .map_err(|e| format!("listing the records in {}/{} failed: {:?}", snapshot.base_id, snapshot.table, e))?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+
+    let mut updates: Vec<Record<Value>> = Default::default();
+    let mut creates: Vec<Record<Value>> = Default::default();
+    for record in snapshot.records.iter() {
+        let fields = strip_fields(&record.fields, skip_fields);
+        if existing.contains(&record.id) {
+            updates.push(Record {
+                id: record.id.to_string(),
+                fields,
+                created_time: None,
+            });
+        } else {
+            creates.push(Record {
+                id: "".to_string(),
+                fields,
+                created_time: None,
+            });
+        }
+    }
+
+    // Airtable only lets us update or create 10 records at a time.
+    for chunk in updates.chunks(10) {
+        airtable
+            .update_records(&snapshot.table, chunk.to_vec())
+            .await
+            .map_err(|e| format!("updating records in {}/{} failed: {:?}", snapshot.base_id, snapshot.table, e))?;
+    }
+    for chunk in creates.chunks(10) {
+        airtable
+            .create_records(&snapshot.table, chunk.to_vec())
+            .await
+            .map_err(|e| format!("creating records in {}/{} failed: {:?}", snapshot.base_id, snapshot.table, e))?;
+    }
+
+    println!(
+        "[backups] restored {}/{} from {}: updated {} records, created {} records",
+        snapshot.base_id,
+        snapshot.table,
+        snapshot.taken_at,
+        updates.len(),
+        creates.len()
+    );
+
+    Ok(())
+}
+
+/// The format of the timestamps in the names of our database backups.
+static DB_BACKUP_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H%M%SZ";
+
+/// How many days we keep every database backup for.
+pub const DB_BACKUP_DAILY_RETENTION_DAYS: i64 = 30;
+
+/// How many months we keep the first database backup of each month for.
+pub const DB_BACKUP_MONTHLY_RETENTION_MONTHS: i32 = 12;
+
+/// The name of the prefix in the backups bucket for a database backup.
+/// A backup is made up of the encrypted dump and the row counts for each table
+/// at the time of the dump, ie. `db/2021-04-08T060000Z/cio.dump.enc` and
+/// `db/2021-04-08T060000Z/row_counts.json`.
+#[instrument]
+#[inline]
+pub fn db_backup_prefix(taken_at: DateTime<Utc>) -> String {
+    format!("db/{}/", taken_at.format(DB_BACKUP_TIMESTAMP_FORMAT))
+}
+
+/// Parse the time a database backup was taken from the name of one of its objects.
+#[instrument]
+#[inline]
+pub fn parse_db_backup_time(name: &str) -> Option<DateTime<Utc>> {
+    let ts = name.trim_start_matches("db/").split('/').next()?;
+    NaiveDateTime::parse_from_str(ts, DB_BACKUP_TIMESTAMP_FORMAT).ok().map(|t| DateTime::<Utc>::from_utc(t, Utc))
+}
+
+/// Returns the database backups we no longer need to keep. We keep every backup
+/// from the last `DB_BACKUP_DAILY_RETENTION_DAYS` days and the first backup of each
+/// of the last `DB_BACKUP_MONTHLY_RETENTION_MONTHS` months.
+#[instrument]
+#[inline]
+pub fn db_backups_to_prune(backups: &[DateTime<Utc>], now: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut sorted = backups.to_vec();
+    sorted.sort();
+    sorted.dedup();
+
+    let months = |t: &DateTime<Utc>| t.year() * 12 + t.month() as i32;
+    let mut kept_months: BTreeSet<i32> = Default::default();
+    let mut prune: Vec<DateTime<Utc>> = Default::default();
+    for t in sorted {
+        if now - t <= Duration::days(DB_BACKUP_DAILY_RETENTION_DAYS) {
+            continue;
+        }
+
+        let month = months(&t);
+        if months(&now) - month < DB_BACKUP_MONTHLY_RETENTION_MONTHS && kept_months.insert(month) {
+            // This is the first backup of the month.
+            continue;
+        }
+
+        prune.push(t);
+    }
+
+    prune
+}
+
+/// Get the key we encrypt database backups with. It is a base64 encoded 256 bit key.
+fn get_db_backup_key() -> Vec<u8> {
+    let key = base64::decode(env::var("CIO_BACKUP_ENCRYPTION_KEY").expect("the CIO_BACKUP_ENCRYPTION_KEY env variable must be set")).unwrap();
+    if key.len() != 32 {
+        panic!("the CIO_BACKUP_ENCRYPTION_KEY must be a base64 encoded 256 bit key");
+    }
+
+    key
+}
+
+/// Encrypt a backup with AES-256-GCM. The output is the nonce, followed by the
+/// authentication tag, followed by the ciphertext.
+#[instrument(skip(key, plaintext))]
+#[inline]
+pub fn encrypt_backup(key: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut nonce = [0u8; 12];
+    rand_bytes(&mut nonce).unwrap();
+    let mut tag = [0u8; 16];
+    let ciphertext = encrypt_aead(Cipher::aes_256_gcm(), key, Some(&nonce), &[], plaintext, &mut tag).unwrap();
+
+    let mut out = Vec::with_capacity(nonce.len() + tag.len() + ciphertext.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&tag);
+    out.extend_from_slice(&ciphertext);
+    out
+}
+
+/// Decrypt a backup encrypted with `encrypt_backup`. This fails if the backup was modified.
+#[instrument(skip(key, data))]
+#[inline]
+pub fn decrypt_backup(key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 28 {
+        return Err("the backup is too short to be valid".to_string());
+    }
+
+    let (nonce, rest) = data.split_at(12);
+    let (tag, ciphertext) = rest.split_at(16);
+    decrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), &[], ciphertext, tag).map_err(|e| format!("decrypting the backup failed: {}", e))
+}
+
+#[derive(QueryableByName)]
+struct TableName {
+    #[sql_type = "Text"]
+    tablename: String,
+}
+
+#[derive(QueryableByName)]
+struct RowCount {
+    #[sql_type = "BigInt"]
+    count: i64,
+}
+
+#[derive(QueryableByName)]
+struct ExportedSnapshot {
+    #[sql_type = "Text"]
+    pg_export_snapshot: String,
+}
+
+/// Count the rows in every table in the public schema.
+#[instrument(skip(conn))]
+#[inline]
+pub fn count_rows(conn: &PgConnection) -> Result<BTreeMap<String, i64>, diesel::result::Error> {
+    let tables: Vec<TableName> = sql_query("SELECT tablename FROM pg_tables WHERE schemaname = 'public'").load(conn)?;
+
+    let mut counts: BTreeMap<String, i64> = Default::default();
+    for table in tables {
+        let rows: Vec<RowCount> = sql_query(format!("SELECT count(*) AS count FROM \"{}\"", table.tablename)).load(conn)?;
+        counts.insert(table.tablename, rows.first().map(|r| r.count).unwrap_or_default());
+    }
+
+    Ok(counts)
+}
+
+/// Compare the row counts from a restored backup to the row counts at the time
+/// of the dump, returning a line for each table that does not match.
+#[instrument]
+#[inline]
+pub fn compare_row_counts(expected: &BTreeMap<String, i64>, actual: &BTreeMap<String, i64>) -> Vec<String> {
+    let mut mismatches: Vec<String> = Default::default();
+    for (table, count) in expected {
+        match actual.get(table) {
+            Some(c) if c == count => (),
+            Some(c) => mismatches.push(format!("{}: expected {} rows, restored {}", table, count, c)),
+            None => mismatches.push(format!("{}: expected {} rows, the table is missing", table, count)),
+        }
+    }
+    for table in actual.keys().filter(|t| !expected.contains_key(*t)) {
+        mismatches.push(format!("{}: the table was not in the dump", table));
+    }
+
+    mismatches
+}
+
+/// Dump the database with `pg_dump`, returning the dump and the row counts for each table.
+/// We count the rows and dump the database from the same snapshot of the database, so the
+/// counts match the dump exactly, even while our jobs are writing to the database.
+#[instrument]
+#[inline]
+fn dump_db(database_url: &str, path: &Path) -> Result<BTreeMap<String, i64>, String> {
+    let conn = PgConnection::establish(database_url).map_err(|e| format!("connecting to the database failed: {}", e))?;
+
+    conn.build_transaction()
+        .repeatable_read()
+        .read_only()
+        .run::<_, diesel::result::Error, _>(|| {
+            let snapshots: Vec<ExportedSnapshot> = sql_query("SELECT pg_export_snapshot()").load(&conn)?;
+            let snapshot = snapshots.first().map(|s| s.pg_export_snapshot.to_string()).unwrap_or_default();
+            let counts = count_rows(&conn)?;
+
+            let output = Command::new("pg_dump")
+                .args(&[
+                    "--format=custom",
+                    "--no-owner",
+                    "--no-privileges",
+                    "--snapshot",
+                    &snapshot,
+                    "--file",
+                    path.to_str().unwrap(),
+                    database_url,
+                ])
+                .output()
+                .unwrap();
+            if !output.status.success() {
+                println!("[backups] pg_dump failed: {}", String::from_utf8_lossy(&output.stderr));
+                return Err(diesel::result::Error::RollbackTransaction);
+            }
+
+            Ok(counts)
+        })
+        .map_err(|e| format!("dumping the database failed: {}", e))
+}
+
+/// Take a backup of the database, encrypt it, and upload it to the backups bucket.
+/// Then delete the backups we no longer need to keep. Returns the prefix of the new backup.
+#[instrument]
+#[inline]
+pub async fn backup_db() -> Result<String, String> {
+    let bucket = get_backup_bucket();
+    let key = get_db_backup_key();
+    let database_url = env::var("CIO_DATABASE_URL").expect("CIO_DATABASE_URL must be set");
+    let taken_at = Utc::now();
+    let prefix = db_backup_prefix(taken_at);
+
+    let mut path = env::temp_dir();
+    path.push(format!("cio-{}.dump", taken_at.format(DB_BACKUP_TIMESTAMP_FORMAT)));
+    let counts = dump_db(&database_url, &path)?;
+    let dump = fs::read(&path).map_err(|e| e.to_string())?;
+    fs::remove_file(&path).unwrap_or_default();
+
+    upload_to_gcs(&bucket, &format!("{}cio.dump.enc", prefix), "application/octet-stream", encrypt_backup(&key, &dump)).await?;
+    upload_to_gcs(&bucket, &format!("{}row_counts.json", prefix), "application/json", serde_json::to_vec_pretty(&counts).unwrap()).await?;
+    println!("[backups] backed up {} tables to gs://{}/{}", counts.len(), bucket, prefix);
+
+    // Delete the old backups we no longer need.
+    let names = list_gcs_objects(&bucket, "db/").await?;
+    let backups: Vec<DateTime<Utc>> = names.iter().filter_map(|n| parse_db_backup_time(n)).collect();
+    let prune = db_backups_to_prune(&backups, taken_at);
+    for name in names.iter().filter(|n| parse_db_backup_time(n).map(|t| prune.contains(&t)).unwrap_or(false)) {
+        delete_from_gcs(&bucket, name).await?;
+        println!("[backups] deleted old backup gs://{}/{}", bucket, name);
+    }
+
+    Ok(prefix)
+}
+
+/// Verify a database backup by restoring it into a scratch database and checking
+/// the row counts for each table match the counts at the time of the dump.
+/// The scratch database is wiped, so it must never be our real database.
+#[instrument(skip(scratch_database_url))]
+#[inline]
+pub async fn verify_db_backup(prefix: &str, scratch_database_url: &str) -> Result<(), String> {
+    if env::var("CIO_DATABASE_URL").unwrap_or_default() == scratch_database_url {
+        return Err("the scratch database cannot be the same as CIO_DATABASE_URL".to_string());
+    }
+
+    let bucket = get_backup_bucket();
+    let prefix = format!("{}/", prefix.trim_end_matches('/'));
+    let dump = decrypt_backup(&get_db_backup_key(), &download_from_gcs(&bucket, &format!("{}cio.dump.enc", prefix)).await?)?;
+    let expected: BTreeMap<String, i64> =
+        serde_json::from_slice(&download_from_gcs(&bucket, &format!("{}row_counts.json", prefix)).await?).map_err(|e| format!("decoding the row counts failed: {}", e))?;
+
+    let mut path = env::temp_dir();
+    path.push("cio-verify.dump");
+    fs::write(&path, dump).map_err(|e| e.to_string())?;
+    let output = Command::new("pg_restore")
+        .args(&["--clean", "--if-exists", "--no-owner", "--no-privileges", "--dbname", scratch_database_url, path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap_or_default();
+    if !output.status.success() {
+        return Err(format!("pg_restore failed: {}", String::from_utf8_lossy(&output.stderr)));
+    }
+
+    let conn = PgConnection::establish(scratch_database_url).map_err(|e| format!("connecting to the scratch database failed: {}", e))?;
+    let actual = count_rows(&conn).map_err(|e| format!("counting the rows in the scratch database failed: {}", e))?;
+    let mismatches = compare_row_counts(&expected, &actual);
+    if !mismatches.is_empty() {
+        return Err(format!(
+            "the backup gs://{}/{} does not match the database at the time of the dump:\n{}",
+            bucket,
+            prefix,
+            mismatches.join("\n")
+        ));
+    }
+
+    println!("[backups] verified gs://{}/{}: the row counts for {} tables match", bucket, prefix, actual.len());
+
+    Ok(())
+}
+
+/// Take a backup of the database and, if we have a scratch database, verify it.
+#[instrument]
+#[inline]
+pub async fn backup_and_verify_db() {
+    let prefix = match backup_db().await {
+        Ok(p) => p,
+        Err(e) => {
+            println!("[backups] {}", e);
+            notify_channel(ChatChannel::Engineering, json!({ "text": format!("The database backup failed: {}", e) })).await;
+            return;
+        }
+    };
+
+    if let Some(scratch_database_url) = env::var("CIO_SCRATCH_DATABASE_URL").ok().filter(|u| !u.is_empty()) {
+        if let Err(e) = verify_db_backup(&prefix, &scratch_database_url).await {
+            println!("[backups] {}", e);
+            notify_channel(ChatChannel::Engineering, json!({ "text": format!("Verifying the database backup `{}` failed: {}", prefix, e) })).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use airtable_api::Record;
+    use chrono::{Duration, TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::backups::{
+        backup_airtable, backup_and_verify_db, compare_row_counts, db_backup_prefix, db_backups_to_prune, decrypt_backup, encrypt_backup, parse_db_backup_time, strip_fields, AirtableSnapshot,
+    };
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_airtable_backup() {
+        backup_airtable().await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_db_backup() {
+        backup_and_verify_db().await;
+    }
+
+    #[test]
+    fn test_airtable_snapshot_checksum() {
+        let records = vec![Record {
+            id: "rec123".to_string(),
+            fields: json!({"name": "Okta", "users": 10, "total": 100}),
+            created_time: None,
+        }];
+
+        let mut snapshot = AirtableSnapshot::new("appduLHDVQ332gKyf", "Software Vendors", Utc.ymd(2021, 4, 8).and_hms(6, 0, 0), records);
+        assert!(snapshot.verify());
+        assert_eq!(snapshot.object_name(), "airtable/2021-04-08T060000Z/appduLHDVQ332gKyf/Software Vendors.json");
+
+        // Round trip it through JSON like we do when we upload it.
+        let decoded: AirtableSnapshot = serde_json::from_slice(&serde_json::to_vec_pretty(&snapshot).unwrap()).unwrap();
+        assert!(decoded.verify());
+
+        snapshot.records[0].fields["users"] = json!(11);
+        assert!(!snapshot.verify());
+
+        assert_eq!(strip_fields(&snapshot.records[0].fields, &["total"]), json!({"name": "Okta", "users": 11}));
+    }
+
+    #[test]
+    fn test_db_backup_encryption() {
+        let key = [7u8; 32];
+        let dump = b"PGDMP some dump".to_vec();
+
+        let mut encrypted = encrypt_backup(&key, &dump);
+        assert_ne!(encrypted[28..].to_vec(), dump);
+        assert_eq!(decrypt_backup(&key, &encrypted).unwrap(), dump);
+        assert!(decrypt_backup(&[8u8; 32], &encrypted).is_err());
+
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(decrypt_backup(&key, &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_db_backup_retention() {
+        let now = Utc.ymd(2021, 4, 8).and_hms(6, 0, 0);
+        assert_eq!(parse_db_backup_time(&format!("{}cio.dump.enc", db_backup_prefix(now))), Some(now));
+
+        let mut backups = vec![];
+        for days in 0..500 {
+            backups.push(now - Duration::days(days));
+        }
+        let prune = db_backups_to_prune(&backups, now);
+
+        // We keep the last 30 days plus the first backup of each of the last 12 months.
+        let kept: Vec<_> = backups.iter().filter(|t| !prune.contains(t)).collect();
+        assert_eq!(kept.len(), 31 + 11);
+        assert!(!prune.contains(&Utc.ymd(2020, 5, 1).and_hms(6, 0, 0)));
+        assert!(prune.contains(&Utc.ymd(2020, 5, 2).and_hms(6, 0, 0)));
+        assert!(prune.contains(&Utc.ymd(2020, 4, 8).and_hms(6, 0, 0)));
+    }
+
+    #[test]
+    fn test_compare_row_counts() {
+        let mut expected = BTreeMap::new();
+        expected.insert("users".to_string(), 10);
+        expected.insert("groups".to_string(), 4);
+        let mut actual = expected.clone();
+        assert!(compare_row_counts(&expected, &actual).is_empty());
+
+        actual.insert("users".to_string(), 9);
+        actual.remove("groups");
+        assert_eq!(
+            compare_row_counts(&expected, &actual),
+            vec!["groups: expected 4 rows, the table is missing".to_string(), "users: expected 10 rows, restored 9".to_string()]
+        );
+    }
+}
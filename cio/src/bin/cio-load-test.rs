@@ -0,0 +1,95 @@
+use std::env;
+use std::process;
+
+use clap::{App, Arg};
+
+use cio_api::db::Database;
+use cio_api::load_test::{format_load_test_report, run_load_test, slower_than, start_mock_airtable, CountingAllocator};
+use cio_api::seed::{check_local_database_url, generate_seed_data, SeedCounts};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[tokio::main]
+async fn main() {
+    let matches = App::new("cio-load-test")
+        .about("Measure the throughput and allocations of the database layer and the Airtable sync engine against seeded data, with a mock Airtable")
+        .arg(
+            Arg::with_name("employees")
+                .long("employees")
+                .takes_value(true)
+                .value_name("COUNT")
+                .default_value("50")
+                .help("How many fake employees to sync, with three applicants for each"),
+        )
+        .arg(
+            Arg::with_name("vendors")
+                .long("vendors")
+                .takes_value(true)
+                .value_name("COUNT")
+                .default_value("30")
+                .help("How many fake software vendors to sync"),
+        )
+        .arg(
+            Arg::with_name("rfds")
+                .long("rfds")
+                .takes_value(true)
+                .value_name("COUNT")
+                .default_value("200")
+                .help("How many fake RFDs to sync"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .value_name("SEED")
+                .default_value("1")
+                .help("The seed for the fake data"),
+        )
+        .arg(
+            Arg::with_name("min-records-per-sec")
+                .long("min-records-per-sec")
+                .takes_value(true)
+                .value_name("RATE")
+                .help("Exit with an error if any phase syncs fewer records a second than this, to catch regressions before deploying"),
+        )
+        .get_matches();
+
+    // The load test writes the fake records to the database, so it must be a local one.
+    let database_url = env::var("CIO_DATABASE_URL").expect("CIO_DATABASE_URL must be set");
+    if let Err(e) = check_local_database_url(&database_url) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    // Nothing reaches Airtable, but the client still wants a key.
+    if env::var("AIRTABLE_API_KEY").is_err() {
+        env::set_var("AIRTABLE_API_KEY", "keyLoadTest");
+    }
+
+    let count = |name: &str| matches.value_of(name).unwrap().parse::<usize>().unwrap_or_else(|e| panic!("--{} must be a number: {}", name, e));
+    let counts = SeedCounts {
+        employees: count("employees"),
+        vendors: count("vendors"),
+        rfds: count("rfds"),
+        ..Default::default()
+    };
+    let seed = matches.value_of("seed").unwrap().parse::<u64>().unwrap_or_else(|e| panic!("--seed must be a number: {}", e));
+
+    let endpoint = start_mock_airtable().await;
+    println!("[load_test] sending Airtable requests to {}", endpoint);
+
+    let db = Database::new();
+    let results = run_load_test(&db, &generate_seed_data(seed, &counts)).await;
+    print!("{}", format_load_test_report(&results));
+
+    if let Some(min) = matches.value_of("min-records-per-sec") {
+        let min = min.parse::<f64>().unwrap_or_else(|e| panic!("--min-records-per-sec must be a number: {}", e));
+        let slow = slower_than(&results, min);
+        if !slow.is_empty() {
+            for r in slow {
+                eprintln!("{} synced {:.1} records a second, below {}", r.phase, r.records_per_sec(), min);
+            }
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,1161 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+use chrono::{NaiveDate, Utc};
+use clap::{App, AppSettings, Arg, SubCommand};
+
+use cio_api::applicant_emails::send_applicant_email_by_hand;
+use cio_api::applicant_messages::{format_applicant_timeline, get_applicant_timeline, link_applicant_thread};
+use cio_api::backups::{backup_airtable, backup_db, restore_airtable_snapshot, verify_db_backup};
+use cio_api::board_pack::generate_board_pack;
+use cio_api::cohorts::{add_cohort_member, format_cohort, set_cohort_start_date};
+use cio_api::config_check::{check_configs, format_config_check};
+use cio_api::configs::{config_json_schema, get_configs_from_repo};
+use cio_api::db::Database;
+use cio_api::diff::{diff_groups, diff_users, diff_vendors, format_record_diffs};
+use cio_api::doctor::{format_doctor_report, run_doctor};
+use cio_api::docusign::{send_envelope_to_applicant, send_envelope_to_vendor};
+use cio_api::filter::{parse_human_date, RecordFilter};
+use cio_api::headcount::{format_headcount, get_headcount_comparisons, quarter_days, quarter_of};
+use cio_api::import::{format_import_report, import_csv, ImportMapping, IMPORT_TABLES};
+use cio_api::job_runs::{format_job_usage_report, get_job_runs_since, record_job_run, start_job_usage, summarize_job_runs};
+use cio_api::jobs::{run_job, DATE_FILTERABLE_JOBS, FILTERABLE_JOBS, JOBS, REPLAYABLE_JOBS};
+use cio_api::oauth_grants::revoke_oauth_grants;
+use cio_api::offers::{create_offer, format_offers};
+use cio_api::policies::{format_acknowledgment_evidence, format_policy_acknowledgments, get_policy_acknowledgments};
+use cio_api::progress::enable_progress_bars;
+use cio_api::referrals::{add_referral, mark_referral_bonus_paid};
+use cio_api::reviews::{add_peer_reviewers, close_review_cycle, format_review_cycle, open_review_cycle};
+use cio_api::scheduler::{run_scheduler, SyncConfig};
+use cio_api::security_events::{format_account_activity, get_account_activity};
+use cio_api::seed::{check_local_database_url, format_seed_report, generate_seed_data, seed_db, SeedCounts};
+use cio_api::take_homes::{format_take_homes, get_take_homes, grade_take_home, send_take_home, submit_take_home};
+use cio_api::trainings::{format_training_statuses, get_training_statuses, import_training_completions};
+use cio_api::utils::authenticate_github_jwt;
+use cio_api::vcr::{finish_recording, start_recording, start_replaying};
+use cio_api::visitors::{add_visitor, format_visitors, get_visitors_on};
+
+#[tokio::main]
+async fn main() {
+    let matches = App::new("cio")
+        .about("Run the jobs that sync our data between our services, the database, and Airtable")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("scheduler")
+                .about("Run all the jobs on their schedules")
+                .arg(
+                    Arg::with_name("sync-config")
+                        .long("sync-config")
+                        .takes_value(true)
+                        .default_value("sync.toml")
+                        .help("The file with the schedule for each job"),
+                )
+                .arg(Arg::with_name("skip-doctor").long("skip-doctor").help("Start even if the checks from `cio doctor` fail")),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a single job once")
+                .arg(Arg::with_name("job").required(true).possible_values(&JOBS.iter().map(|(name, _)| *name).collect::<Vec<&str>>()))
+                .arg(
+                    Arg::with_name("filter")
+                        .long("filter")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Only sync the records matching `field=value`, ie. `name=Okta` or `number=123`"),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("DATE")
+                        .help("Only include the records from on or after a date, ie. `last monday`, `2 weeks ago`, or `2021-04-01`"),
+                )
+                .arg(
+                    Arg::with_name("before")
+                        .long("before")
+                        .takes_value(true)
+                        .value_name("DATE")
+                        .help("Only include the records from before a date, ie. `yesterday`, `1 month ago`, or `2021-04-01`"),
+                )
+                .arg(
+                    Arg::with_name("plain")
+                        .long("plain")
+                        .help("Log progress as plain lines rather than drawing progress bars, which are only drawn on a terminal"),
+                )
+                .arg(
+                    Arg::with_name("sync-config")
+                        .long("sync-config")
+                        .takes_value(true)
+                        .default_value("sync.toml")
+                        .help("The file with the settings for the jobs, if there is one"),
+                )
+                .arg(
+                    Arg::with_name("record")
+                        .long("record")
+                        .takes_value(true)
+                        .value_name("CASSETTE")
+                        .conflicts_with("replay")
+                        .help("Record the requests the job sends and the responses, with secrets redacted, to a file to replay later. Only some jobs can be recorded"),
+                )
+                .arg(
+                    Arg::with_name("replay")
+                        .long("replay")
+                        .takes_value(true)
+                        .value_name("CASSETTE")
+                        .help("Answer the requests the job sends from a file recorded with `--record` rather than sending them. Only some jobs can be replayed"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Show the field level differences for records between Airtable, the database, and our configs")
+                .arg(Arg::with_name("table").required(true).possible_values(&["groups", "users", "vendors"])),
+        )
+        .subcommand(
+            SubCommand::with_name("activity")
+                .about("Show what an account did, or had done to it, in the audit logs of our identity providers")
+                .arg(Arg::with_name("email").required(true).help("The email of the account"))
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("DATE")
+                        .default_value("yesterday")
+                        .help("Only include the events from on or after a date, ie. `last monday`, `2 weeks ago`, or `2021-04-01`"),
+                )
+                .arg(
+                    Arg::with_name("before")
+                        .long("before")
+                        .takes_value(true)
+                        .value_name("DATE")
+                        .default_value("now")
+                        .help("Only include the events from before a date, ie. the day someone was offboarded"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("usage")
+                .about("Show the time, API calls, database connection checkouts, and rows written by each job, with the heaviest Airtable users first")
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("DATE")
+                        .default_value("last week")
+                        .help("Only include the runs from on or after a date, ie. `yesterday`, `2 weeks ago`, or `2021-04-01`"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("doctor").about("Check our credentials, database, Airtable bases, GitHub permissions, and Slack webhooks before running anything"))
+        .subcommand(
+            SubCommand::with_name("backup")
+                .about("Back up our data to the backups bucket")
+                .arg(Arg::with_name("target").required(true).possible_values(&["airtable", "db"]))
+                .arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .takes_value(true)
+                        .value_name("SCRATCH_DATABASE_URL")
+                        .help("For `db`, restore the backup into this scratch database and check the row counts for each table match. The scratch database is wiped"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Restore a table from a snapshot in the backups bucket")
+                .arg(Arg::with_name("target").required(true).possible_values(&["airtable"]))
+                .arg(
+                    Arg::with_name("snapshot")
+                        .required(true)
+                        .help("The name of the snapshot in the backups bucket, ie. `airtable/2021-04-08T060000Z/appduLHDVQ332gKyf/Software Vendors.json`"),
+                )
+                .arg(
+                    Arg::with_name("skip-field")
+                        .long("skip-field")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("A field to leave out when restoring, ie. formula or lookup fields that Airtable will not let us set"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("revoke-app")
+                .about("Revoke a third-party app's access to everyone's Google account, or only some people's")
+                .arg(Arg::with_name("client-id").required(true).help("The OAuth client ID of the app, from the OAuth Grants table"))
+                .arg(
+                    Arg::with_name("user")
+                        .long("user")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("EMAIL")
+                        .help("Only revoke the app for this person"),
+                )
+                .arg(Arg::with_name("dry-run").long("dry-run").help("Show who the app would be revoked for without revoking anything")),
+        )
+        .subcommand(
+            SubCommand::with_name("docusign")
+                .about("Work with DocuSign envelopes")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("send")
+                        .about("Send an envelope from one of the templates in the docusign section of our configs to an applicant or someone at a software vendor")
+                        .arg(Arg::with_name("template").required(true).help("The name of the template, ie. offer_letter"))
+                        .arg(
+                            Arg::with_name("applicant")
+                                .long("applicant")
+                                .takes_value(true)
+                                .value_name("EMAIL")
+                                .required_unless("vendor")
+                                .conflicts_with("vendor")
+                                .help("Send the envelope to the applicant with this email"),
+                        )
+                        .arg(
+                            Arg::with_name("vendor")
+                                .long("vendor")
+                                .takes_value(true)
+                                .value_name("NAME")
+                                .requires_all(&["signer-name", "signer-email"])
+                                .help("Link the envelope to the software vendor with this name"),
+                        )
+                        .arg(
+                            Arg::with_name("signer-name")
+                                .long("signer-name")
+                                .takes_value(true)
+                                .help("The name of the person signing for the vendor"),
+                        )
+                        .arg(
+                            Arg::with_name("signer-email")
+                                .long("signer-email")
+                                .takes_value(true)
+                                .help("The email of the person signing for the vendor"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("headcount")
+                .about("Compare the headcount plan for a quarter against who joined each team and the roles that are open")
+                .arg(Arg::with_name("quarter").long("quarter").takes_value(true).help("The quarter, ie. 2021-Q2, defaults to this quarter")),
+        )
+        .subcommand(
+            SubCommand::with_name("board-pack")
+                .about("Generate the board pack of headcount, burn, hiring, and engineering metrics for a quarter and save it to Drive as slides and a CSV")
+                .arg(
+                    Arg::with_name("quarter")
+                        .long("quarter")
+                        .takes_value(true)
+                        .help("The quarter, ie. 2021-Q2, defaults to the last quarter"),
+                )
+                .arg(Arg::with_name("dry-run").long("dry-run").help("Print the board pack without saving it to Drive")),
+        )
+        .subcommand(
+            SubCommand::with_name("referrals")
+                .about("Work with referrals of applicants")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Record that someone referred an applicant, for referrals the applicant did not tell us about on the application form")
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant"))
+                        .arg(Arg::with_name("referrer").required(true).help("The username of the person who referred them"))
+                        .arg(
+                            Arg::with_name("role")
+                                .long("role")
+                                .takes_value(true)
+                                .help("The role of the application the referral is for, defaults to their first application"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("paid")
+                        .about("Mark the bonus for referring an applicant as paid")
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cohorts")
+                .about("Work with internship and returnship cohorts")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Show where the members of a cohort are in our hiring process")
+                        .arg(Arg::with_name("cohort").required(true).help("The name of the cohort in our configs")),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add an applicant to a cohort, ie. someone who applied for another role")
+                        .arg(Arg::with_name("cohort").required(true).help("The name of the cohort in our configs"))
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant")),
+                )
+                .subcommand(
+                    SubCommand::with_name("start-date")
+                        .about("Set when a member of a cohort starts, if it is not the start date of the cohort")
+                        .arg(Arg::with_name("cohort").required(true).help("The name of the cohort in our configs"))
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant"))
+                        .arg(Arg::with_name("date").required(true).help("The day they start, ie. 2021-06-07")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("offers")
+                .about("Make offers to applicants, checked against our compensation bands")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Make an offer, which needs approval before the offer letter is sent if it is outside of its band")
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant"))
+                        .arg(Arg::with_name("level").long("level").takes_value(true).required(true).help("The level of the offer, ie. L3"))
+                        .arg(Arg::with_name("location").long("location").takes_value(true).required(true).help("Where they will work from, ie. US"))
+                        .arg(Arg::with_name("salary").long("salary").takes_value(true).required(true).help("The base salary, ie. 160000"))
+                        .arg(Arg::with_name("start-date").long("start-date").takes_value(true).help("The day they start, ie. 2021-06-07"))
+                        .arg(Arg::with_name("by").long("by").takes_value(true).value_name("USERNAME").required(true).help("Your username")),
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Show the offers for an applicant and if they are approved")
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("policies")
+                .about("Track who has acknowledged our policies")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("status").about("Show who has not acknowledged the current version of each policy"))
+                .subcommand(
+                    SubCommand::with_name("evidence")
+                        .about("Print who was sent the current version of a policy and when they acknowledged it as a CSV, for audits")
+                        .arg(Arg::with_name("policy").required(true).help("The name of the policy in our configs")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reviews")
+                .about("Run performance review cycles")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("open")
+                        .about("Open a review cycle with a self review for everyone and a manager review for everyone with a manager")
+                        .arg(Arg::with_name("cycle").required(true).help("The name of the cycle, ie. 2021-h1"))
+                        .arg(
+                            Arg::with_name("closes")
+                                .long("closes")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The last day to write reviews, ie. 2021-06-15"),
+                        )
+                        .arg(Arg::with_name("by").long("by").takes_value(true).value_name("USERNAME").required(true).help("Your username")),
+                )
+                .subcommand(
+                    SubCommand::with_name("peers")
+                        .about("Add the peers who review someone in a cycle")
+                        .arg(Arg::with_name("cycle").required(true).help("The name of the cycle"))
+                        .arg(Arg::with_name("subject").required(true).help("The username of who is reviewed"))
+                        .arg(Arg::with_name("peer").required(true).multiple(true).help("The usernames of their peers")),
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Show who has written their reviews in a cycle")
+                        .arg(Arg::with_name("cycle").required(true).help("The name of the cycle")),
+                )
+                .subcommand(
+                    SubCommand::with_name("close")
+                        .about("Close a cycle before its last day and archive its reviews to Drive")
+                        .arg(Arg::with_name("cycle").required(true).help("The name of the cycle")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trainings")
+                .about("Track the trainings everyone has to take")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Show who has not taken each training")
+                        .arg(Arg::with_name("training").long("training").takes_value(true).help("Only show one training, by its name in our configs")),
+                )
+                .subcommand(
+                    SubCommand::with_name("import")
+                        .about("Import the completions from a report exported from the training vendor")
+                        .arg(Arg::with_name("file").required(true).help("The CSV of the report, with a header row"))
+                        .arg(
+                            Arg::with_name("map")
+                                .long("map")
+                                .takes_value(true)
+                                .value_name("MAPPING")
+                                .help("A TOML file mapping `email`, `course`, and `completed_at` to the columns of the CSV, defaults to the mapping in our configs"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("visitors")
+                .about("Register the visitors we expect at the office")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Register a visitor and send them our NDA to sign before they come in")
+                        .arg(Arg::with_name("name").required(true).help("The full name of the visitor"))
+                        .arg(Arg::with_name("email").required(true).help("The email of the visitor"))
+                        .arg(Arg::with_name("date").long("date").takes_value(true).required(true).help("The day of the visit, ie. 2021-05-20"))
+                        .arg(
+                            Arg::with_name("host")
+                                .long("host")
+                                .takes_value(true)
+                                .value_name("USERNAME")
+                                .required(true)
+                                .help("The username of who they are visiting"),
+                        )
+                        .arg(Arg::with_name("company").long("company").takes_value(true).help("The company of the visitor"))
+                        .arg(Arg::with_name("by").long("by").takes_value(true).value_name("USERNAME").required(true).help("Your username")),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the visitors on a day and their NDAs")
+                        .arg(Arg::with_name("date").long("date").takes_value(true).help("The day to list, defaults to today")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("take-homes")
+                .about("Send take-home assignments to candidates and grade them")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").about("List the take-homes we sent and their grades"))
+                .subcommand(
+                    SubCommand::with_name("send")
+                        .about("Make a candidate a private repository from a take-home and invite them to it until the deadline")
+                        .arg(Arg::with_name("assignment").required(true).help("The name of the take-home in our configs"))
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant"))
+                        .arg(
+                            Arg::with_name("days")
+                                .long("days")
+                                .takes_value(true)
+                                .help("How many days they have, defaults to the days of the take-home in our configs"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("submit")
+                        .about("Close the take-home of a candidate who told us they are done before the deadline")
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant")),
+                )
+                .subcommand(
+                    SubCommand::with_name("grade")
+                        .about("Record your score for the take-home of a candidate, replacing your earlier score")
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant"))
+                        .arg(Arg::with_name("score").required(true).help("The score, from 1 to 5"))
+                        .arg(Arg::with_name("grader").long("grader").takes_value(true).value_name("USERNAME").required(true).help("Your username"))
+                        .arg(Arg::with_name("notes").long("notes").takes_value(true).help("Notes on the score for the hiring team")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("applicants")
+                .about("Work with applicants")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("email")
+                        .about("Send an applicant one of the applicant email templates, unless it was already sent to them")
+                        .arg(Arg::with_name("template").required(true).help("The name of the template, ie. `interview_confirmation`"))
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant")),
+                )
+                .subcommand(
+                    SubCommand::with_name("timeline")
+                        .about("Show the mail we have sent and received from an applicant, oldest first")
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant")),
+                )
+                .subcommand(
+                    SubCommand::with_name("link-thread")
+                        .about("Add a thread from someone's inbox to the timeline of an applicant, by the Message-ID of one of its messages")
+                        .arg(Arg::with_name("email").required(true).help("The email of the applicant"))
+                        .arg(
+                            Arg::with_name("message-id")
+                                .required(true)
+                                .help("The Message-ID header of a message in the thread, from `Show original` in Gmail"),
+                        )
+                        .arg(
+                            Arg::with_name("mailbox")
+                                .long("mailbox")
+                                .takes_value(true)
+                                .value_name("USERNAME")
+                                .required(true)
+                                .help("The username of the person whose inbox the thread is in"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Import the rows of a CSV into a table in the database and Airtable")
+                .arg(Arg::with_name("table").required(true).possible_values(IMPORT_TABLES))
+                .arg(Arg::with_name("file").required(true).help("The CSV to import, with a header row"))
+                .arg(
+                    Arg::with_name("map")
+                        .long("map")
+                        .takes_value(true)
+                        .value_name("MAPPING")
+                        .help("A TOML file mapping the fields of the table to the columns of the CSV. Without one, columns named after fields are imported"),
+                )
+                .arg(
+                    Arg::with_name("upsert")
+                        .long("upsert")
+                        .help("Update the existing records that match a row rather than reporting them as errors"),
+                )
+                .arg(
+                    Arg::with_name("plain")
+                        .long("plain")
+                        .help("Log progress as plain lines rather than drawing progress bars, which are only drawn on a terminal"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Work with the config files in the configs repo")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("schema").about("Print the JSON Schema for a config file, for editors and for checking the configs before merging"))
+                .subcommand(
+                    SubCommand::with_name("check")
+                        .about("Decode and validate the configs at a ref and show what syncing them would change in the database, without syncing anything")
+                        .arg(
+                            Arg::with_name("against")
+                                .long("against")
+                                .takes_value(true)
+                                .value_name("REF")
+                                .required(true)
+                                .help("The branch, tag, or commit of the configs repo to check, ie. the head of a pull request"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("db").about("Work with the database").setting(AppSettings::SubcommandRequiredElseHelp).subcommand(
+                SubCommand::with_name("seed")
+                    .about("Fill a database on this machine with fake, but realistic, users, groups, buildings, vendors, RFDs, and applicants for local development and load testing")
+                    .arg(
+                        Arg::with_name("employees")
+                            .long("employees")
+                            .takes_value(true)
+                            .value_name("COUNT")
+                            .default_value("50")
+                            .help("How many fake employees to create, with three applicants for each"),
+                    )
+                    .arg(
+                        Arg::with_name("vendors")
+                            .long("vendors")
+                            .takes_value(true)
+                            .value_name("COUNT")
+                            .default_value("30")
+                            .help("How many fake software vendors to create"),
+                    )
+                    .arg(
+                        Arg::with_name("rfds")
+                            .long("rfds")
+                            .takes_value(true)
+                            .value_name("COUNT")
+                            .default_value("200")
+                            .help("How many fake RFDs to create"),
+                    )
+                    .arg(
+                        Arg::with_name("seed")
+                            .long("seed")
+                            .takes_value(true)
+                            .value_name("SEED")
+                            .default_value("1")
+                            .help("The seed for the fake data. Seeding again with the same seed updates the same records"),
+                    ),
+            ),
+        )
+        .get_matches();
+
+    // The scheduler runs jobs side by side, so only jobs run by hand get progress bars.
+    if let ("run", Some(m)) | ("import", Some(m)) = matches.subcommand() {
+        if !m.is_present("plain") {
+            enable_progress_bars();
+        }
+    }
+
+    match matches.subcommand() {
+        ("scheduler", Some(m)) => {
+            // Fail fast if the deployment is misconfigured rather than failing
+            // job by job once the scheduler is running.
+            if !m.is_present("skip-doctor") {
+                let checks = run_doctor().await;
+                if checks.iter().any(|c| !c.passed) {
+                    eprint!("{}", format_doctor_report(&checks));
+                    process::exit(1);
+                }
+            }
+
+            run_scheduler(m.value_of("sync-config").unwrap()).await
+        }
+        ("run", Some(m)) => {
+            let job = m.value_of("job").unwrap();
+            let pairs: Vec<&str> = m.values_of("filter").map(|v| v.collect()).unwrap_or_default();
+            let filter = match RecordFilter::parse(&pairs).and_then(|f| f.with_dates(m.value_of("since"), m.value_of("before"), Utc::now())) {
+                Ok(f) => f,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+            if !filter.fields.is_empty() && !FILTERABLE_JOBS.contains(&job) {
+                eprintln!("job `{}` does not support filters, only {} do", job, FILTERABLE_JOBS.join(", "));
+                process::exit(1);
+            }
+            if filter.has_dates() && !DATE_FILTERABLE_JOBS.contains(&job) {
+                eprintln!("job `{}` does not support `--since` or `--before`, only {} do", job, DATE_FILTERABLE_JOBS.join(", "));
+                process::exit(1);
+            }
+
+            let replaying = m.is_present("replay");
+            if (replaying || m.is_present("record")) && !REPLAYABLE_JOBS.contains(&job) {
+                eprintln!(
+                    "job `{}` can not be recorded or replayed, since it sends requests we can not answer from a cassette, only {} can",
+                    job,
+                    REPLAYABLE_JOBS.join(", ")
+                );
+                process::exit(1);
+            }
+            if let Some(cassette) = m.value_of("record") {
+                start_recording(cassette, job);
+            }
+            if let Some(cassette) = m.value_of("replay") {
+                if let Err(e) = start_replaying(cassette) {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+
+            let db = Database::new();
+            let github = authenticate_github_jwt();
+            // The jobs we can replay do not use the configs, and getting them from GitHub
+            // would send requests that are not in the cassette.
+            let configs = if replaying { Default::default() } else { get_configs_from_repo(&github).await };
+            // Outside of the scheduler the sync config is optional, the jobs have defaults
+            // for everything in it.
+            let sync_config_file = m.value_of("sync-config").unwrap();
+            let sync_config = if Path::new(sync_config_file).exists() {
+                SyncConfig::read(sync_config_file)
+            } else {
+                Default::default()
+            };
+            let started_at = Utc::now();
+            let start = Instant::now();
+            start_job_usage();
+            let ok = run_job(&db, &github, &configs, &sync_config, job, &filter).await;
+            record_job_run(&db, job, started_at, start.elapsed(), &Ok(()));
+            if let Err(e) = finish_recording() {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            if !ok {
+                process::exit(1);
+            }
+        }
+        ("diff", Some(m)) => {
+            let db = Database::new();
+            let diffs = match m.value_of("table").unwrap() {
+                "vendors" => diff_vendors(&db).await,
+                table => {
+                    let github = authenticate_github_jwt();
+                    let configs = get_configs_from_repo(&github).await;
+                    if table == "users" {
+                        diff_users(&db, &configs).await
+                    } else {
+                        diff_groups(&db, &configs).await
+                    }
+                }
+            };
+
+            print!("{}", format_record_diffs(&diffs));
+        }
+        ("activity", Some(m)) => {
+            let now = Utc::now();
+            let (since, before) = match parse_human_date(m.value_of("since").unwrap(), now).and_then(|s| parse_human_date(m.value_of("before").unwrap(), now).map(|b| (s, b))) {
+                Ok(dates) => dates,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            let db = Database::new();
+            let email = m.value_of("email").unwrap();
+            print!("{}", format_account_activity(email, &get_account_activity(&db, email, since, before)));
+        }
+        ("usage", Some(m)) => {
+            let since = match parse_human_date(m.value_of("since").unwrap(), Utc::now()) {
+                Ok(since) => since,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            let db = Database::new();
+            print!("{}", format_job_usage_report(&summarize_job_runs(&get_job_runs_since(&db, since))));
+        }
+        ("doctor", Some(_)) => {
+            let checks = run_doctor().await;
+            print!("{}", format_doctor_report(&checks));
+            if checks.iter().any(|c| !c.passed) {
+                process::exit(1);
+            }
+        }
+        ("backup", Some(m)) => match m.value_of("target").unwrap() {
+            "db" => {
+                let result = match backup_db().await {
+                    Ok(prefix) => match m.value_of("verify") {
+                        Some(scratch_database_url) => verify_db_backup(&prefix, scratch_database_url).await,
+                        None => Ok(()),
+                    },
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            _ => backup_airtable().await,
+        },
+        ("restore", Some(m)) => {
+            let skip_fields: Vec<&str> = m.values_of("skip-field").map(|v| v.collect()).unwrap_or_default();
+            if let Err(e) = restore_airtable_snapshot(m.value_of("snapshot").unwrap(), &skip_fields).await {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        ("revoke-app", Some(m)) => {
+            let db = Database::new();
+            let client_id = m.value_of("client-id").unwrap();
+            let emails: Vec<String> = m.values_of("user").map(|v| v.map(|e| e.to_lowercase()).collect()).unwrap_or_default();
+            let dry_run = m.is_present("dry-run");
+
+            let (revoked, errors) = revoke_oauth_grants(&db, client_id, &emails, dry_run).await;
+            for email in &revoked {
+                if dry_run {
+                    println!("would revoke {} for {}", client_id, email);
+                } else {
+                    println!("revoked {} for {}", client_id, email);
+                }
+            }
+            for e in &errors {
+                eprintln!("{}", e);
+            }
+            if !errors.is_empty() {
+                process::exit(1);
+            }
+        }
+        ("docusign", Some(m)) => match m.subcommand() {
+            ("send", Some(m)) => {
+                let db = Database::new();
+                let github = authenticate_github_jwt();
+                let configs = get_configs_from_repo(&github).await;
+                let template = m.value_of("template").unwrap();
+                let result = match m.value_of("applicant") {
+                    Some(email) => send_envelope_to_applicant(&db, &configs, template, email).await,
+                    None => {
+                        send_envelope_to_vendor(
+                            &db,
+                            &configs,
+                            template,
+                            m.value_of("vendor").unwrap(),
+                            m.value_of("signer-name").unwrap(),
+                            m.value_of("signer-email").unwrap(),
+                        )
+                        .await
+                    }
+                };
+                match result {
+                    Ok(envelope) => println!("sent the {} to {} as envelope {}", envelope.template, envelope.signer_email, envelope.envelope_id),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        },
+        ("headcount", Some(m)) => {
+            let db = Database::new();
+            let today = Utc::now().date().naive_utc();
+            let as_of = match m.value_of("quarter") {
+                Some(q) => match quarter_days(q) {
+                    Some((_, last)) => last.min(today),
+                    None => {
+                        eprintln!("{} is not a quarter like 2021-Q2", q);
+                        process::exit(1);
+                    }
+                },
+                None => today,
+            };
+            let text = format_headcount(&get_headcount_comparisons(&db, as_of));
+            if text.is_empty() {
+                println!("There is no headcount plan for {}.", quarter_of(as_of));
+            } else {
+                println!("{}", text.trim_end());
+            }
+        }
+        ("board-pack", Some(m)) => {
+            let db = Database::new();
+            let github = authenticate_github_jwt();
+            let configs = get_configs_from_repo(&github).await;
+            let today = Utc::now().date().naive_utc();
+            match generate_board_pack(&db, &configs, m.value_of("quarter"), today, m.is_present("dry-run")).await {
+                Ok(done) => println!("{}", done.trim_end()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("referrals", Some(m)) => {
+            let db = Database::new();
+            let result = match m.subcommand() {
+                ("add", Some(m)) => add_referral(&db, m.value_of("email").unwrap(), m.value_of("referrer").unwrap(), m.value_of("role")).await,
+                ("paid", Some(m)) => mark_referral_bonus_paid(&db, m.value_of("email").unwrap()).await,
+                _ => unreachable!(),
+            };
+            match result {
+                Ok(done) => println!("{}", done),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("cohorts", Some(m)) => {
+            let db = Database::new();
+            let github = authenticate_github_jwt();
+            let configs = get_configs_from_repo(&github).await;
+            let result = match m.subcommand() {
+                ("show", Some(m)) => {
+                    let name = m.value_of("cohort").unwrap();
+                    match configs.cohorts.get(name) {
+                        Some(cohort) => Ok(format_cohort(&db, name, cohort)),
+                        None => Err(format!("`{}` is not one of our cohorts", name)),
+                    }
+                }
+                ("add", Some(m)) => add_cohort_member(&db, &configs, m.value_of("cohort").unwrap(), m.value_of("email").unwrap()),
+                ("start-date", Some(m)) => {
+                    let date = m.value_of("date").unwrap();
+                    match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                        Ok(d) => set_cohort_start_date(&db, m.value_of("cohort").unwrap(), m.value_of("email").unwrap(), d),
+                        Err(_) => Err(format!("{} is not a date like 2021-06-07", date)),
+                    }
+                }
+                _ => unreachable!(),
+            };
+            match result {
+                Ok(done) => println!("{}", done.trim_end()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("offers", Some(m)) => {
+            let db = Database::new();
+            let result = match m.subcommand() {
+                ("create", Some(m)) => {
+                    let github = authenticate_github_jwt();
+                    let configs = get_configs_from_repo(&github).await;
+                    let salary = m.value_of("salary").unwrap().replace(',', "").parse::<i64>().unwrap_or_else(|_| {
+                        eprintln!("--salary must be a whole number, ie. 160000");
+                        process::exit(1);
+                    });
+                    let start_date = m.value_of("start-date").map(|d| {
+                        NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap_or_else(|_| {
+                            eprintln!("{} is not a date like 2021-06-07", d);
+                            process::exit(1);
+                        })
+                    });
+                    create_offer(
+                        &db,
+                        &configs,
+                        m.value_of("email").unwrap(),
+                        m.value_of("level").unwrap(),
+                        m.value_of("location").unwrap(),
+                        salary,
+                        start_date,
+                        m.value_of("by").unwrap(),
+                    )
+                    .await
+                }
+                ("status", Some(m)) => format_offers(&db, m.value_of("email").unwrap()),
+                _ => unreachable!(),
+            };
+            match result {
+                Ok(done) => println!("{}", done.trim_end()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("policies", Some(m)) => {
+            let db = Database::new();
+            let github = authenticate_github_jwt();
+            let configs = get_configs_from_repo(&github).await;
+            let result = match m.subcommand() {
+                ("status", Some(_)) => Ok(format_policy_acknowledgments(
+                    &configs.policies,
+                    &configs
+                        .policies
+                        .iter()
+                        .map(|(name, p)| (name.to_string(), get_policy_acknowledgments(&db, name, &p.version())))
+                        .collect(),
+                )),
+                ("evidence", Some(m)) => {
+                    let name = m.value_of("policy").unwrap();
+                    match configs.policies.get(name) {
+                        Some(p) => format_acknowledgment_evidence(&get_policy_acknowledgments(&db, name, &p.version())),
+                        None => Err(format!("`{}` is not one of our policies", name)),
+                    }
+                }
+                _ => unreachable!(),
+            };
+            match result {
+                Ok(done) => println!("{}", done.trim_end()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("reviews", Some(m)) => {
+            let db = Database::new();
+            let github = authenticate_github_jwt();
+            let configs = get_configs_from_repo(&github).await;
+            let result = match m.subcommand() {
+                ("open", Some(m)) => {
+                    let closes = m.value_of("closes").unwrap();
+                    match NaiveDate::parse_from_str(closes, "%Y-%m-%d") {
+                        Ok(d) => open_review_cycle(&db, &configs, m.value_of("cycle").unwrap(), d, m.value_of("by").unwrap()),
+                        Err(_) => Err(format!("{} is not a date like 2021-06-15", closes)),
+                    }
+                }
+                ("peers", Some(m)) => {
+                    let peers: Vec<String> = m.values_of("peer").unwrap().map(|p| p.to_string()).collect();
+                    add_peer_reviewers(&db, &configs, m.value_of("cycle").unwrap(), m.value_of("subject").unwrap(), &peers)
+                }
+                ("status", Some(m)) => format_review_cycle(&db, m.value_of("cycle").unwrap()),
+                ("close", Some(m)) => close_review_cycle(&db, &configs, m.value_of("cycle").unwrap()).await,
+                _ => unreachable!(),
+            };
+            match result {
+                Ok(done) => println!("{}", done.trim_end()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("take-homes", Some(m)) => {
+            let db = Database::new();
+            let github = authenticate_github_jwt();
+            let configs = get_configs_from_repo(&github).await;
+            let result = match m.subcommand() {
+                ("list", Some(_)) => Ok(format_take_homes(&get_take_homes(&db))),
+                ("send", Some(m)) => {
+                    let days = m.value_of("days").map(|d| {
+                        d.parse::<i64>().unwrap_or_else(|_| {
+                            eprintln!("--days must be a number of days, not {}", d);
+                            process::exit(1);
+                        })
+                    });
+                    send_take_home(&db, &github, &configs, m.value_of("assignment").unwrap(), m.value_of("email").unwrap(), days).await
+                }
+                ("submit", Some(m)) => submit_take_home(&db, &configs, m.value_of("email").unwrap()).await,
+                ("grade", Some(m)) => {
+                    let score = m.value_of("score").unwrap().parse::<i32>().unwrap_or_else(|_| {
+                        eprintln!("the score must be a number");
+                        process::exit(1);
+                    });
+                    grade_take_home(
+                        &db,
+                        &configs,
+                        m.value_of("email").unwrap(),
+                        m.value_of("grader").unwrap(),
+                        score,
+                        m.value_of("notes").unwrap_or_default(),
+                    )
+                    .await
+                }
+                _ => unreachable!(),
+            };
+            match result {
+                Ok(done) => println!("{}", done.trim_end()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("trainings", Some(m)) => {
+            let db = Database::new();
+            let github = authenticate_github_jwt();
+            let configs = get_configs_from_repo(&github).await;
+            match m.subcommand() {
+                ("status", Some(m)) => {
+                    let mut statuses = get_training_statuses(&db, &configs);
+                    if let Some(training) = m.value_of("training") {
+                        statuses.retain(|s| s.training == training);
+                    }
+                    print!("{}", format_training_statuses(&statuses, Utc::now().date().naive_utc()));
+                }
+                ("import", Some(m)) => {
+                    let file = m.value_of("file").unwrap();
+                    let csv = fs::File::open(file).unwrap_or_else(|e| {
+                        eprintln!("reading {} failed: {}", file, e);
+                        process::exit(1);
+                    });
+                    let mapping = match m.value_of("map") {
+                        Some(map) => ImportMapping::read(map).unwrap_or_else(|e| {
+                            eprintln!("{}", e);
+                            process::exit(1);
+                        }),
+                        None => configs.trainings.mapping.clone(),
+                    };
+                    match import_training_completions(&db, &configs, csv, &mapping, "csv") {
+                        Ok(report) => {
+                            print!("{}", format_import_report(&report));
+                            if report.failed() > 0 {
+                                process::exit(1);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            process::exit(1);
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        ("visitors", Some(m)) => {
+            let db = Database::new();
+            let github = authenticate_github_jwt();
+            let configs = get_configs_from_repo(&github).await;
+            let parse_date = |date: &str| {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap_or_else(|_| {
+                    eprintln!("{} is not a date like 2021-05-20", date);
+                    process::exit(1);
+                })
+            };
+            let result = match m.subcommand() {
+                ("add", Some(m)) => {
+                    add_visitor(
+                        &db,
+                        &configs,
+                        m.value_of("name").unwrap(),
+                        m.value_of("email").unwrap(),
+                        m.value_of("company").unwrap_or_default(),
+                        m.value_of("host").unwrap(),
+                        parse_date(m.value_of("date").unwrap()),
+                        m.value_of("by").unwrap(),
+                    )
+                    .await
+                }
+                ("list", Some(m)) => {
+                    let day = m.value_of("date").map(parse_date).unwrap_or_else(|| Utc::now().date().naive_utc());
+                    Ok(format_visitors(&db, &configs, &get_visitors_on(&db, day)).join("\n"))
+                }
+                _ => unreachable!(),
+            };
+            match result {
+                Ok(done) => println!("{}", done.trim_end()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("applicants", Some(m)) => match m.subcommand() {
+            ("email", Some(m)) => {
+                let db = Database::new();
+                let github = authenticate_github_jwt();
+                let configs = get_configs_from_repo(&github).await;
+                match send_applicant_email_by_hand(&db, &configs, m.value_of("email").unwrap(), m.value_of("template").unwrap()).await {
+                    Ok(done) => println!("{}", done),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+            ("timeline", Some(m)) => {
+                let db = Database::new();
+                let github = authenticate_github_jwt();
+                let configs = get_configs_from_repo(&github).await;
+                let email = m.value_of("email").unwrap();
+                let messages = get_applicant_timeline(&db, email);
+                let alias = messages.last().and_then(|m| configs.applicant_messages.applicant_alias(m.applicant_id));
+                print!("{}", format_applicant_timeline(email, alias, &messages));
+            }
+            ("link-thread", Some(m)) => {
+                let db = Database::new();
+                match link_applicant_thread(&db, m.value_of("email").unwrap(), m.value_of("mailbox").unwrap(), m.value_of("message-id").unwrap()).await {
+                    Ok(done) => println!("{}", done),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        },
+        ("import", Some(m)) => {
+            // Stream through the CSV rather than reading it into memory, since it can be
+            // hundreds of megabytes.
+            let file = m.value_of("file").unwrap();
+            let (csv, size) = fs::File::open(file).and_then(|f| f.metadata().map(|m| (f, m.len()))).unwrap_or_else(|e| {
+                eprintln!("reading {} failed: {}", file, e);
+                process::exit(1);
+            });
+            let mapping = match m.value_of("map") {
+                Some(map) => ImportMapping::read(map).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }),
+                None => Default::default(),
+            };
+
+            let db = Database::new();
+            match import_csv(&db, m.value_of("table").unwrap(), csv, size, &mapping, m.is_present("upsert")).await {
+                Ok(report) => {
+                    print!("{}", format_import_report(&report));
+                    if report.failed() > 0 {
+                        process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ("config", Some(m)) => match m.subcommand() {
+            ("schema", Some(_)) => println!("{}", serde_json::to_string_pretty(&config_json_schema()).unwrap()),
+            ("check", Some(m)) => {
+                let db = Database::new();
+                let github = authenticate_github_jwt();
+                let check = check_configs(&db, &github, m.value_of("against").unwrap()).await;
+                print!("{}", format_config_check(&check));
+                if !check.passed() {
+                    process::exit(1);
+                }
+            }
+            _ => unreachable!(),
+        },
+        ("db", Some(m)) => match m.subcommand() {
+            ("seed", Some(m)) => {
+                let database_url = env::var("CIO_DATABASE_URL").expect("CIO_DATABASE_URL must be set");
+                if let Err(e) = check_local_database_url(&database_url) {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+
+                let count = |name: &str| m.value_of(name).unwrap().parse::<usize>().unwrap_or_else(|e| panic!("--{} must be a number: {}", name, e));
+                let counts = SeedCounts {
+                    employees: count("employees"),
+                    vendors: count("vendors"),
+                    rfds: count("rfds"),
+                    ..Default::default()
+                };
+                let seed = m.value_of("seed").unwrap().parse::<u64>().unwrap_or_else(|e| panic!("--seed must be a number: {}", e));
+                let db = Database::new();
+                print!("{}", format_seed_report(&seed_db(&db, &generate_seed_data(seed, &counts))));
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
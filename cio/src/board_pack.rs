@@ -0,0 +1,495 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::Datelike;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::instrument;
+
+use crate::actions_usage::get_github_actions_cost_for_month;
+use crate::applicant_status::Status;
+use crate::applicants::{applicant_funnel, Applicants};
+use crate::cloud_costs::get_cloud_costs_by_group_for_month;
+use crate::configs::{Config, Users};
+use crate::db::Database;
+use crate::drive_permissions::{drive_api, get_folder_id};
+use crate::engineering_metrics::{median, EngineeringMetrics, NewEngineeringMetric};
+use crate::finance::SoftwareVendors;
+use crate::format::{format_date, format_usd};
+use crate::headcount::{get_headcount_comparisons, quarter_days, quarter_of, HeadcountComparison};
+use crate::lunch::get_lunch_cost_for_month;
+use crate::retention::PeopleSnapshot;
+use crate::schema::people_snapshots;
+use crate::utils::{drive_client, get_gsuite_token, google_api};
+
+static SLIDES_API: &str = "https://slides.googleapis.com/v1";
+
+/// Where we save the board pack for each quarter, from the `board_pack` section of our
+/// configs.
+///
+/// ```toml
+/// [board_pack]
+/// drive = "Finance"
+/// folder = "Board/Packs"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct BoardPackConfig {
+    /// The shared drive and the folder in it we save the slides and the CSV to.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub drive: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub folder: String,
+}
+
+/// A section of the board pack, which is a slide in the presentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoardPackSection {
+    pub title: String,
+    /// The metrics in the section, as their label and their formatted value.
+    pub rows: Vec<(String, String)>,
+}
+
+/// The key metrics for a quarter that we take to the board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoardPack {
+    pub quarter: String,
+    /// The last day the metrics are for, the last day of the quarter unless it is not
+    /// over yet.
+    pub as_of: NaiveDate,
+    pub sections: Vec<BoardPackSection>,
+}
+
+impl BoardPack {
+    /// The title of the pack, which is also the name of its files in Drive.
+    #[instrument]
+    #[inline]
+    pub fn title(&self) -> String {
+        format!("Board pack {}", self.quarter)
+    }
+}
+
+/// The numbers for a quarter that we build the board pack from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BoardPackData {
+    /// The latest people snapshot taken in or before the quarter.
+    pub people: Option<PeopleSnapshot>,
+    pub headcount: Vec<HeadcountComparison>,
+    /// What we spent in the quarter by category, ie. `Cloud`.
+    pub burn: BTreeMap<String, f64>,
+    /// How many applicants who applied in the quarter reached each stage of the funnel.
+    pub funnel: Vec<(String, f64)>,
+    /// The people who started in the quarter.
+    pub hires: usize,
+    /// The weekly engineering metrics for the weeks that start in the quarter.
+    pub engineering: Vec<NewEngineeringMetric>,
+}
+
+/// Build the sections of the board pack for a quarter from its numbers.
+#[instrument(skip(data))]
+#[inline]
+pub fn build_board_pack(quarter: &str, as_of: NaiveDate, data: &BoardPackData) -> BoardPack {
+    let mut sections: Vec<BoardPackSection> = Default::default();
+
+    let mut rows: Vec<(String, String)> = Default::default();
+    if let Some(people) = &data.people {
+        rows.push(("Headcount".to_string(), people.headcount.to_string()));
+        rows.push(("Median tenure".to_string(), format!("{:.1} years", people.median_tenure_days as f64 / 365.0)));
+        rows.push(("Departures (last 12 months)".to_string(), people.departures_12m.to_string()));
+        rows.push(("Attrition (last 12 months)".to_string(), format!("{:.1}%", people.attrition_12m)));
+    }
+    if !data.headcount.is_empty() {
+        let planned: i32 = data.headcount.iter().map(|c| c.planned).sum();
+        let hired: i32 = data.headcount.iter().map(|c| c.hired).sum();
+        rows.push(("Hires against plan".to_string(), format!("{} of {}", hired, planned)));
+        for c in data.headcount.iter() {
+            rows.push((format!("Hires for {}", c.team), format!("{} of {}, {}", c.hired, c.planned, c.status())));
+        }
+    }
+    sections.push(BoardPackSection { title: "Headcount".to_string(), rows });
+
+    let mut rows: Vec<(String, String)> = data.burn.iter().map(|(category, cost)| (category.to_string(), format_usd(*cost))).collect();
+    rows.push(("Total".to_string(), format_usd(data.burn.values().sum::<f64>())));
+    sections.push(BoardPackSection {
+        title: "Burn by category".to_string(),
+        rows,
+    });
+
+    let mut rows: Vec<(String, String)> = data
+        .funnel
+        .iter()
+        .map(|(stage, count)| (format!("Applicants who reached {}", stage), format!("{:.0}", count)))
+        .collect();
+    rows.push(("People who started".to_string(), data.hires.to_string()));
+    sections.push(BoardPackSection {
+        title: "Hiring funnel".to_string(),
+        rows,
+    });
+
+    let mut rows: Vec<(String, String)> = vec![
+        ("Pull requests opened".to_string(), data.engineering.iter().map(|m| m.prs_opened).sum::<i32>().to_string()),
+        ("Pull requests merged".to_string(), data.engineering.iter().map(|m| m.prs_merged).sum::<i32>().to_string()),
+        ("Deploys".to_string(), data.engineering.iter().map(|m| m.deploys).sum::<i32>().to_string()),
+        (
+            "Median hours to first review".to_string(),
+            format!(
+                "{:.1}",
+                median(data.engineering.iter().filter(|m| m.median_review_hours > 0.0).map(|m| m.median_review_hours as f64).collect())
+            ),
+        ),
+        (
+            "Median hours to merge".to_string(),
+            format!(
+                "{:.1}",
+                median(data.engineering.iter().filter(|m| m.median_merge_hours > 0.0).map(|m| m.median_merge_hours as f64).collect())
+            ),
+        ),
+    ];
+    let mut merged_by_team: BTreeMap<&str, i32> = Default::default();
+    for m in data.engineering.iter() {
+        *merged_by_team.entry(&m.team).or_default() += m.prs_merged;
+    }
+    for (team, merged) in merged_by_team {
+        rows.push((format!("Pull requests merged by {}", team), merged.to_string()));
+    }
+    sections.push(BoardPackSection {
+        title: "Engineering throughput".to_string(),
+        rows,
+    });
+
+    BoardPack {
+        quarter: quarter.to_string(),
+        as_of,
+        sections,
+    }
+}
+
+/// Format the board pack as a CSV of its sections, metrics, and values, for whoever
+/// wants to chart them in a spreadsheet.
+#[instrument(skip(pack))]
+#[inline]
+pub fn format_board_pack_csv(pack: &BoardPack) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(&["section", "metric", "value"]).map_err(|e| e.to_string())?;
+    for section in pack.sections.iter() {
+        for (label, value) in section.rows.iter() {
+            writer.write_record(&[&section.title, label, value]).map_err(|e| e.to_string())?;
+        }
+    }
+
+    String::from_utf8(writer.into_inner().map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+}
+
+/// Format the board pack as plain text, for printing it without saving it to Drive.
+#[instrument(skip(pack))]
+#[inline]
+pub fn format_board_pack(pack: &BoardPack) -> String {
+    let mut text = format!("{} (as of {})\n", pack.title(), format_date(pack.as_of));
+    for section in pack.sections.iter() {
+        text += &format!("\n{}\n", section.title);
+        for (label, value) in section.rows.iter() {
+            text += &format!("• {}: {}\n", label, value);
+        }
+    }
+
+    text
+}
+
+/// Get the Slides API requests that replace the slides in a presentation with a title
+/// slide and a slide for each section of the board pack.
+#[instrument(skip(pack))]
+#[inline]
+pub fn board_pack_slides_requests(pack: &BoardPack, existing_slides: &[String]) -> Vec<Value> {
+    let mut requests: Vec<Value> = existing_slides.iter().map(|id| json!({ "deleteObject": { "objectId": id } })).collect();
+
+    requests.push(json!({
+        "createSlide": {
+            "objectId": "board_pack_title",
+            "slideLayoutReference": { "predefinedLayout": "TITLE" },
+            "placeholderIdMappings": [
+                { "layoutPlaceholder": { "type": "CENTERED_TITLE" }, "objectId": "board_pack_title_text" },
+                { "layoutPlaceholder": { "type": "SUBTITLE" }, "objectId": "board_pack_subtitle_text" },
+            ],
+        }
+    }));
+    requests.push(json!({ "insertText": { "objectId": "board_pack_title_text", "text": pack.title() } }));
+    requests.push(json!({ "insertText": { "objectId": "board_pack_subtitle_text", "text": format!("As of {}", format_date(pack.as_of)) } }));
+
+    for (i, section) in pack.sections.iter().enumerate() {
+        let slide = format!("board_pack_section_{}", i);
+        requests.push(json!({
+            "createSlide": {
+                "objectId": slide,
+                "slideLayoutReference": { "predefinedLayout": "TITLE_AND_BODY" },
+                "placeholderIdMappings": [
+                    { "layoutPlaceholder": { "type": "TITLE" }, "objectId": format!("{}_title", slide) },
+                    { "layoutPlaceholder": { "type": "BODY" }, "objectId": format!("{}_body", slide) },
+                ],
+            }
+        }));
+        requests.push(json!({ "insertText": { "objectId": format!("{}_title", slide), "text": section.title } }));
+
+        let body: Vec<String> = section.rows.iter().map(|(label, value)| format!("{}: {}", label, value)).collect();
+        if !body.is_empty() {
+            requests.push(json!({ "insertText": { "objectId": format!("{}_body", slide), "text": body.join("\n") } }));
+        }
+    }
+
+    requests
+}
+
+/// Make a request to the Google Slides API.
+#[instrument(skip(token, body))]
+#[inline]
+async fn slides_api(token: &str, method: Method, path: &str, body: Option<Value>) -> Result<Value, String> {
+    google_api(token, method, &format!("{}{}", SLIDES_API, path), &[], body).await
+}
+
+/// Get the latest people snapshot taken on or before a day.
+#[instrument(skip(db))]
+#[inline]
+fn get_people_snapshot_as_of(db: &Database, as_of: NaiveDate) -> Option<PeopleSnapshot> {
+    people_snapshots::table
+        .filter(people_snapshots::taken_on.le(as_of))
+        .order_by(people_snapshots::taken_on.desc())
+        .first::<PeopleSnapshot>(&db.conn())
+        .ok()
+}
+
+/// Get the numbers for the board pack for a quarter from our tables, up to a day in it.
+/// Software vendors are counted at what they cost each month now, since we only keep
+/// their current cost.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_board_pack_data(db: &Database, quarter: &str, as_of: NaiveDate) -> BoardPackData {
+    let (first, _) = quarter_days(quarter).unwrap_or_else(|| panic!("`{}` is not a quarter, it should be formatted like `2021-Q2`", quarter));
+
+    let mut months: Vec<NaiveDate> = Default::default();
+    let mut month = first;
+    while month <= as_of {
+        months.push(month);
+        month = if month.month() == 12 {
+            NaiveDate::from_ymd(month.year() + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd(month.year(), month.month() + 1, 1)
+        };
+    }
+
+    let mut burn: BTreeMap<String, f64> = Default::default();
+    let software: f32 = SoftwareVendors::get_from_db(db).into_iter().map(|v| v.total_cost_per_month).sum();
+    burn.insert("Software vendors".to_string(), software as f64 * months.len() as f64);
+    for month in months.iter() {
+        *burn.entry("Cloud".to_string()).or_default() += get_cloud_costs_by_group_for_month(db, *month).values().map(|c| *c as f64).sum::<f64>();
+        *burn.entry("CI (GitHub Actions)".to_string()).or_default() += get_github_actions_cost_for_month(db, *month).values().map(|c| *c as f64).sum::<f64>();
+        *burn.entry("Office lunches".to_string()).or_default() += get_lunch_cost_for_month(db, *month) as f64;
+    }
+
+    let statuses: Vec<Status> = Applicants::get_from_db(db)
+        .0
+        .iter()
+        .filter(|a| {
+            let day = a.submitted_time.date().naive_utc();
+            first <= day && day <= as_of
+        })
+        .map(|a| a.status)
+        .collect();
+
+    let hires = Users::get_from_db(db)
+        .into_iter()
+        .filter(|u| !u.is_system_account() && first <= u.start_date && u.start_date <= as_of)
+        .count();
+
+    let engineering: Vec<NewEngineeringMetric> = EngineeringMetrics::get_from_db(db)
+        .into_iter()
+        .filter(|m| first <= m.week && m.week <= as_of)
+        .map(|m| m.into())
+        .collect();
+
+    BoardPackData {
+        people: get_people_snapshot_as_of(db, as_of),
+        headcount: get_headcount_comparisons(db, as_of),
+        burn,
+        funnel: applicant_funnel(&statuses),
+        hires,
+        engineering,
+    }
+}
+
+/// Save the board pack to the folder in our configs as a Google Slides presentation and
+/// a CSV of the same metrics. Returns the link to the presentation.
+#[instrument(skip(config, pack))]
+#[inline]
+async fn save_board_pack(config: &Config, pack: &BoardPack) -> Result<String, String> {
+    let token = get_gsuite_token("").await;
+    let drive_client = drive_client(token.clone());
+    let token = token.as_str();
+
+    let drive_id = drive_client
+        .get_drive_by_name(&config.board_pack.drive)
+        .await
+        .map_err(|e| format!("getting the shared drive `{}` failed: {}", config.board_pack.drive, e))?
+        .id;
+    let parent_id = get_folder_id(token, &drive_id, &config.board_pack.folder).await?;
+
+    let file = drive_api(
+        token,
+        Method::POST,
+        "/files",
+        &[("fields", "id,webViewLink".to_string())],
+        Some(json!({ "name": pack.title(), "mimeType": "application/vnd.google-apps.presentation", "parents": [parent_id] })),
+    )
+    .await?;
+    let id = file["id"].as_str().unwrap_or_default().to_string();
+
+    // New presentations start with an empty title slide, which we replace with our own.
+    let presentation = slides_api(token, Method::GET, &format!("/presentations/{}", id), None).await?;
+    let existing: Vec<String> = presentation["slides"]
+        .as_array()
+        .map(|slides| slides.iter().filter_map(|s| s["objectId"].as_str()).map(|s| s.to_string()).collect())
+        .unwrap_or_default();
+    slides_api(
+        token,
+        Method::POST,
+        &format!("/presentations/{}:batchUpdate", id),
+        Some(json!({ "requests": board_pack_slides_requests(pack, &existing) })),
+    )
+    .await?;
+
+    let filename = format!("{}.csv", pack.title());
+    let csv = format_board_pack_csv(pack)?;
+    drive_client
+        .create_or_upload_file(&drive_id, &parent_id, &filename, "text/csv", csv.as_bytes())
+        .await
+        .map_err(|e| format!("uploading {} failed: {}", filename, e))?;
+
+    Ok(file["webViewLink"]
+        .as_str()
+        .map(|l| l.to_string())
+        .unwrap_or_else(|| format!("https://docs.google.com/presentation/d/{}", id)))
+}
+
+/// Generate the board pack for a quarter, by default the last quarter that is over, and
+/// save it to Drive. With `dry_run` the pack is only returned as text.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn generate_board_pack(db: &Database, config: &Config, quarter: Option<&str>, today: NaiveDate, dry_run: bool) -> Result<String, String> {
+    let quarter = match quarter {
+        Some(q) => q.trim().to_string(),
+        None => quarter_of(quarter_days(&quarter_of(today)).unwrap().0.pred()),
+    };
+    let (first, last) = quarter_days(&quarter).ok_or_else(|| format!("`{}` is not a quarter, it should be formatted like `2021-Q2`", quarter))?;
+    if first > today {
+        return Err(format!("{} has not started yet", quarter));
+    }
+    let as_of = last.min(today);
+
+    // The pack only reads, so it reads from the replica.
+    let data = get_board_pack_data(&db.read_only(), &quarter, as_of);
+    let pack = build_board_pack(&quarter, as_of, &data);
+    if dry_run {
+        return Ok(format_board_pack(&pack));
+    }
+
+    if config.board_pack.drive.is_empty() || config.board_pack.folder.is_empty() {
+        return Err("the `board_pack` section of our configs needs a `drive` and a `folder` to save to".to_string());
+    }
+    let url = save_board_pack(config, &pack).await?;
+
+    Ok(format!("saved the board pack for {} to {}", quarter, url))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+
+    use crate::board_pack::{board_pack_slides_requests, build_board_pack, format_board_pack_csv, BoardPackData};
+    use crate::engineering_metrics::NewEngineeringMetric;
+    use crate::headcount::HeadcountComparison;
+
+    fn metric(team: &str, week: NaiveDate, merged: i32, review_hours: f32) -> NewEngineeringMetric {
+        NewEngineeringMetric {
+            team: team.to_string(),
+            week,
+            prs_opened: merged + 1,
+            prs_merged: merged,
+            median_review_hours: review_hours,
+            median_merge_hours: review_hours * 2.0,
+            deploys: 3,
+        }
+    }
+
+    #[test]
+    fn test_build_board_pack() {
+        let mut data = BoardPackData {
+            headcount: vec![HeadcountComparison {
+                team: "eng".to_string(),
+                quarter: "2021-Q2".to_string(),
+                planned: 3,
+                hired: 1,
+                open_roles: 2,
+            }],
+            funnel: vec![("applied".to_string(), 40.0), ("hired".to_string(), 2.0)],
+            hires: 2,
+            engineering: vec![
+                metric("eng", NaiveDate::from_ymd(2021, 4, 5), 10, 4.0),
+                metric("eng", NaiveDate::from_ymd(2021, 4, 12), 6, 0.0),
+                metric("hw", NaiveDate::from_ymd(2021, 4, 12), 2, 8.0),
+            ],
+            ..Default::default()
+        };
+        data.burn.insert("Cloud".to_string(), 12000.0);
+        data.burn.insert("Software vendors".to_string(), 3000.5);
+
+        let pack = build_board_pack("2021-Q2", NaiveDate::from_ymd(2021, 6, 30), &data);
+        assert_eq!(pack.title(), "Board pack 2021-Q2");
+        assert_eq!(
+            pack.sections.iter().map(|s| s.title.as_str()).collect::<Vec<&str>>(),
+            vec!["Headcount", "Burn by category", "Hiring funnel", "Engineering throughput"]
+        );
+        assert_eq!(
+            pack.sections[0].rows,
+            vec![
+                ("Hires against plan".to_string(), "1 of 3".to_string()),
+                ("Hires for eng".to_string(), "1 of 3, behind plan by 2".to_string()),
+            ]
+        );
+        assert_eq!(pack.sections[1].rows.last().unwrap(), &("Total".to_string(), "$15,000.50".to_string()));
+        assert_eq!(pack.sections[2].rows[0], ("Applicants who reached applied".to_string(), "40".to_string()));
+        assert_eq!(pack.sections[2].rows[2], ("People who started".to_string(), "2".to_string()));
+        assert_eq!(
+            pack.sections[3].rows,
+            vec![
+                ("Pull requests opened".to_string(), "21".to_string()),
+                ("Pull requests merged".to_string(), "18".to_string()),
+                ("Deploys".to_string(), "9".to_string()),
+                // Weeks without any reviews do not count towards the median.
+                ("Median hours to first review".to_string(), "6.0".to_string()),
+                ("Median hours to merge".to_string(), "12.0".to_string()),
+                ("Pull requests merged by eng".to_string(), "16".to_string()),
+                ("Pull requests merged by hw".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_board_pack() {
+        let mut data = BoardPackData::default();
+        data.burn.insert("Cloud".to_string(), 1200.0);
+        let pack = build_board_pack("2021-Q2", NaiveDate::from_ymd(2021, 5, 31), &data);
+
+        let csv = format_board_pack_csv(&pack).unwrap();
+        assert!(csv.starts_with("section,metric,value\nBurn by category,Cloud,\"$1,200.00\"\nBurn by category,Total,\"$1,200.00\"\n"));
+        assert!(csv.contains("Engineering throughput,Median hours to first review,0.0\n"));
+
+        let requests = board_pack_slides_requests(&pack, &["p".to_string()]);
+        assert_eq!(requests[0]["deleteObject"]["objectId"], "p");
+        assert_eq!(requests[3]["insertText"]["text"], "As of 2021-05-31");
+        // The headcount slide has no rows, so it only gets its title.
+        assert_eq!(requests[4]["createSlide"]["objectId"], "board_pack_section_0");
+        assert_eq!(requests[5]["insertText"]["objectId"], "board_pack_section_0_title");
+        assert_eq!(requests[7]["insertText"]["objectId"], "board_pack_section_1_title");
+        assert_eq!(requests[8]["insertText"]["text"], "Cloud: $1,200.00\nTotal: $1,200.00");
+    }
+}
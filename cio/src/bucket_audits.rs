@@ -0,0 +1,378 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+use std::env;
+
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use macros::db;
+use reqwest::{Client, Url};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_BUCKET_AUDITS_TABLE};
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::bucket_audits;
+use crate::utils::{get_gcp_token, GSUITE_DOMAIN};
+
+static GCS_READ_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform.read-only";
+
+/// The members that make a bucket public.
+static PUBLIC_MEMBERS: &[&str] = &["allUsers", "allAuthenticatedUsers"];
+
+/// What we expect of a Google Cloud Storage bucket, keyed by the bucket name in the
+/// `buckets` section of our configs. Buckets that are not in our configs are reported.
+///
+/// ```toml
+/// [buckets.oxide-cio-backups]
+/// groups = { ops = "roles/storage.admin" }
+/// delete_after_days = 90
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct BucketConfig {
+    /// The IAM role for each group. Bindings for other groups, users, or domains are
+    /// reported, bindings for service accounts and the project roles are not.
+    #[serde(default)]
+    pub groups: BTreeMap<String, String>,
+    /// If the bucket is meant to be readable by anyone, ie. for a website.
+    #[serde(default)]
+    pub public: bool,
+    /// The age in days a lifecycle rule should delete objects at, 0 if objects are kept.
+    #[serde(default)]
+    pub delete_after_days: i32,
+    /// The minimum days the retention policy should keep objects for, 0 for no policy.
+    #[serde(default)]
+    pub retention_days: i32,
+}
+
+/// The audit of the access and lifecycle rules for a Google Cloud Storage bucket.
+#[db {
+    new_struct_name = "BucketAudit",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_BUCKET_AUDITS_TABLE",
+    source = "gcp",
+    match_on = {
+        "bucket" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "bucket_audits"]
+pub struct NewBucketAudit {
+    pub bucket: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub project: String,
+    /// If anyone on the internet can read from the bucket.
+    #[serde(default)]
+    pub public: bool,
+    /// The IAM bindings for the bucket, formatted as `{role} {member}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bindings: Vec<String>,
+    /// The age in days the lifecycle rules delete objects at, 0 if they are never deleted.
+    #[serde(default)]
+    pub lifecycle_delete_age: i32,
+    #[serde(default)]
+    pub retention_days: i32,
+    /// How the bucket differs from what we expect in our configs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<String>,
+    pub audited_at: DateTime<Utc>,
+}
+
+/// Implement updating the Airtable record for a BucketAudit.
+#[async_trait]
+impl UpdateAirtableRecord<BucketAudit> for BucketAudit {
+    async fn update_airtable_record(&mut self, _record: BucketAudit) {}
+}
+
+/// Audit a bucket from its metadata and IAM policy against what we expect of it.
+/// FROM: https://cloud.google.com/storage/docs/json_api/v1/buckets
+#[instrument(skip(metadata, policy))]
+#[inline]
+pub fn audit_bucket(project: &str, metadata: &Value, policy: &Value, expected: Option<&BucketConfig>) -> NewBucketAudit {
+    let bucket = metadata["name"].as_str().unwrap_or_default().to_string();
+
+    let mut bindings: Vec<String> = Default::default();
+    for binding in policy["bindings"].as_array().cloned().unwrap_or_default() {
+        let role = binding["role"].as_str().unwrap_or_default();
+        for member in binding["members"].as_array().cloned().unwrap_or_default() {
+            bindings.push(format!("{} {}", role, member.as_str().unwrap_or_default()));
+        }
+    }
+    bindings.sort();
+    let public = bindings.iter().any(|b| PUBLIC_MEMBERS.iter().any(|m| b.ends_with(&format!(" {}", m))));
+
+    let mut lifecycle_delete_age = 0;
+    for rule in metadata["lifecycle"]["rule"].as_array().cloned().unwrap_or_default() {
+        if rule["action"]["type"] == "Delete" {
+            if let Some(age) = rule["condition"]["age"].as_i64() {
+                if lifecycle_delete_age == 0 || (age as i32) < lifecycle_delete_age {
+                    lifecycle_delete_age = age as i32;
+                }
+            }
+        }
+    }
+
+    // The retention period is in seconds, as a string.
+    let retention_days = metadata["retentionPolicy"]["retentionPeriod"].as_str().and_then(|s| s.parse::<i64>().ok()).unwrap_or_default() / 86400;
+
+    let mut findings: Vec<String> = Default::default();
+    let default_config = BucketConfig::default();
+    let config = match expected {
+        Some(c) => c,
+        None => {
+            findings.push("the bucket is not in the `buckets` section of our configs".to_string());
+            &default_config
+        }
+    };
+
+    if public && !config.public {
+        findings.push("anyone on the internet can read from the bucket".to_string());
+    }
+
+    for (group, role) in &config.groups {
+        let binding = format!("{} group:{}@{}", role, group, GSUITE_DOMAIN);
+        if !bindings.contains(&binding) {
+            findings.push(format!("group `{}` does not have `{}`", group, role));
+        }
+    }
+    for binding in &bindings {
+        let (role, member) = binding.split_once(' ').unwrap_or_default();
+        let expected_group = config.groups.iter().any(|(g, r)| r == role && member == format!("group:{}@{}", g, GSUITE_DOMAIN));
+        let managed = member.starts_with("group:") || member.starts_with("user:") || member.starts_with("domain:");
+        if managed && !expected_group {
+            findings.push(format!("`{}` has `{}` which is not in our configs", member, role));
+        }
+    }
+
+    if config.delete_after_days > 0 && (lifecycle_delete_age == 0 || lifecycle_delete_age > config.delete_after_days) {
+        findings.push(format!(
+            "objects should be deleted after {} days but the lifecycle rules delete them after {}",
+            config.delete_after_days,
+            days(lifecycle_delete_age)
+        ));
+    }
+    if config.retention_days > 0 && retention_days < config.retention_days as i64 {
+        findings.push(format!(
+            "objects should be retained for {} days but the retention policy keeps them for {}",
+            config.retention_days,
+            days(retention_days as i32)
+        ));
+    }
+
+    NewBucketAudit {
+        bucket,
+        project: project.to_string(),
+        public,
+        bindings,
+        lifecycle_delete_age,
+        retention_days: retention_days as i32,
+        findings,
+        audited_at: Utc::now(),
+    }
+}
+
+/// Format a number of days for a finding, 0 means never.
+fn days(d: i32) -> String {
+    if d == 0 {
+        return "never".to_string();
+    }
+
+    format!("{} days", d)
+}
+
+/// Get a JSON object from the Google Cloud Storage API.
+#[instrument(skip(token))]
+#[inline]
+async fn gcs_api_get(token: &str, url: Url) -> Result<Value, String> {
+    let resp: Value = Client::new()
+        .get(url.clone())
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp["error"].is_null() {
+        return Err(format!("getting {} failed: {}", url, resp["error"]));
+    }
+
+    Ok(resp)
+}
+
+/// List the buckets in a Google Cloud project.
+#[instrument(skip(token))]
+#[inline]
+async fn list_buckets(token: &str, project: &str) -> Result<Vec<Value>, String> {
+    let mut buckets: Vec<Value> = Default::default();
+    let mut page_token = String::new();
+    loop {
+        let mut url = Url::parse("https://storage.googleapis.com/storage/v1/b").unwrap();
+        url.query_pairs_mut().append_pair("project", project).append_pair("projection", "full");
+        if !page_token.is_empty() {
+            url.query_pairs_mut().append_pair("pageToken", &page_token);
+        }
+
+        let resp = gcs_api_get(token, url).await?;
+        buckets.extend(resp["items"].as_array().cloned().unwrap_or_default());
+
+        page_token = resp["nextPageToken"].as_str().unwrap_or_default().to_string();
+        if page_token.is_empty() {
+            return Ok(buckets);
+        }
+    }
+}
+
+/// Audit the buckets in the Google Cloud projects in the `GCP_PROJECTS` environment
+/// variable, a comma separated list, against the `buckets` in our configs. The audits
+/// are saved so the findings can be tracked over time, and buckets that became
+/// public since the last audit are posted to the #security channel right away.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn refresh_bucket_audits(db: &Database, config: &Config) {
+    let projects = env::var("GCP_PROJECTS").unwrap_or_default();
+    if projects.is_empty() {
+        println!("[buckets] GCP_PROJECTS is not set, there are no buckets to audit");
+        return;
+    }
+
+    let token = get_gcp_token(&[GCS_READ_SCOPE]).await;
+
+    let mut newly_public: Vec<String> = Default::default();
+    for project in projects.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let buckets = match list_buckets(token.as_str(), project).await {
+            Ok(b) => b,
+            Err(e) => {
+                println!("[buckets] listing the buckets in {} failed: {}", project, e);
+                continue;
+            }
+        };
+
+        for metadata in buckets {
+            let name = metadata["name"].as_str().unwrap_or_default().to_string();
+            let mut url = Url::parse("https://storage.googleapis.com/storage/v1/b").unwrap();
+            url.path_segments_mut().unwrap().push(&name).push("iam");
+            let policy = match gcs_api_get(token.as_str(), url).await {
+                Ok(p) => p,
+                Err(e) => {
+                    println!("[buckets] getting the iam policy for {} failed: {}", name, e);
+                    continue;
+                }
+            };
+
+            let audit = audit_bucket(project, &metadata, &policy, config.buckets.get(&name));
+
+            // Record the drift from the last audit.
+            let existing = BucketAudit::get_from_db(db, name.to_string());
+            for finding in &audit.findings {
+                if !existing.as_ref().map(|e| e.findings.contains(finding)).unwrap_or(false) {
+                    println!("[buckets] {}: new finding: {}", name, finding);
+                }
+            }
+            if audit.public && !existing.map(|e| e.public).unwrap_or(false) {
+                let mut line = format!("• `gs://{}` in `{}`", name, project);
+                if config.buckets.get(&name).map(|c| c.public).unwrap_or(false) {
+                    line += " (expected to be public in our configs)";
+                }
+                newly_public.push(line);
+            }
+
+            audit.upsert(db).await;
+        }
+    }
+
+    if newly_public.is_empty() {
+        println!("[buckets] no buckets became public since the last audit");
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Urgent,
+        ChatChannel::Security,
+        "buckets.newly_public",
+        json!({
+            "text": render_message("buckets.newly_public", &json!({ "lines": newly_public })),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use crate::bucket_audits::{audit_bucket, refresh_bucket_audits, BucketAudits, BucketConfig};
+    use crate::configs::get_configs_from_repo;
+    use crate::db::Database;
+    use crate::utils::authenticate_github_jwt;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_bucket_audits() {
+        let db = Database::new();
+        let github = authenticate_github_jwt();
+        let config = get_configs_from_repo(&github).await;
+
+        refresh_bucket_audits(&db, &config).await;
+
+        BucketAudits::get_from_db(&db).update_airtable().await;
+    }
+
+    #[test]
+    fn test_audit_bucket() {
+        let metadata = json!({
+            "name": "oxide-cio-backups",
+            "lifecycle": {"rule": [
+                {"action": {"type": "SetStorageClass"}, "condition": {"age": 30}},
+                {"action": {"type": "Delete"}, "condition": {"age": 365}},
+            ]},
+            "retentionPolicy": {"retentionPeriod": "2592000"},
+        });
+        let policy = json!({"bindings": [
+            {"role": "roles/storage.admin", "members": ["group:ops@oxidecomputer.com", "projectOwner:oxide-cio"]},
+            {"role": "roles/storage.objectViewer", "members": ["user:jane@oxidecomputer.com", "allUsers"]},
+            {"role": "roles/storage.objectCreator", "members": ["serviceAccount:cio@oxide-cio.iam.gserviceaccount.com"]},
+        ]});
+
+        let mut groups = BTreeMap::new();
+        groups.insert("ops".to_string(), "roles/storage.admin".to_string());
+        groups.insert("eng".to_string(), "roles/storage.objectViewer".to_string());
+        let config = BucketConfig {
+            groups,
+            public: false,
+            delete_after_days: 90,
+            retention_days: 30,
+        };
+
+        let audit = audit_bucket("oxide-cio", &metadata, &policy, Some(&config));
+        assert_eq!(audit.bucket, "oxide-cio-backups");
+        assert!(audit.public);
+        assert_eq!(audit.lifecycle_delete_age, 365);
+        assert_eq!(audit.retention_days, 30);
+        assert_eq!(audit.bindings.len(), 5);
+        assert_eq!(
+            audit.findings,
+            vec![
+                "anyone on the internet can read from the bucket",
+                "group `eng` does not have `roles/storage.objectViewer`",
+                "`user:jane@oxidecomputer.com` has `roles/storage.objectViewer` which is not in our configs",
+                "objects should be deleted after 90 days but the lifecycle rules delete them after 365 days",
+            ]
+        );
+
+        // A bucket we do not know about.
+        let audit = audit_bucket("oxide-cio", &json!({"name": "scratch"}), &json!({}), None);
+        assert!(!audit.public);
+        assert_eq!(audit.findings, vec!["the bucket is not in the `buckets` section of our configs"]);
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::schedule::CronSchedule;
+
+/// A company holiday, keyed by its name in the `holidays` section of our configs.
+///
+/// ```toml
+/// [holidays.thanksgiving-2021]
+/// date = "2021-11-25"
+/// end_date = "2021-11-26"
+/// ```
+#[derive(Debug, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct HolidayConfig {
+    pub date: NaiveDate,
+    /// The last day of the holiday for holidays longer than a day, ie. the winter break.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_date: Option<NaiveDate>,
+}
+
+/// The days we work: Monday through Friday, except company holidays.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct BusinessCalendar {
+    holidays: BTreeSet<NaiveDate>,
+}
+
+impl BusinessCalendar {
+    /// Build the calendar from the holidays in our configs.
+    #[instrument]
+    #[inline]
+    pub fn new(holidays: &BTreeMap<String, HolidayConfig>) -> Self {
+        let mut days = BTreeSet::new();
+        for h in holidays.values() {
+            let mut d = h.date;
+            while d <= h.end_date.unwrap_or(h.date) {
+                days.insert(d);
+                d = d.succ();
+            }
+        }
+
+        BusinessCalendar { holidays: days }
+    }
+
+    /// Returns if the date is a weekday that is not a holiday.
+    #[instrument]
+    #[inline]
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        date.weekday() != Weekday::Sat && date.weekday() != Weekday::Sun && !self.holidays.contains(&date)
+    }
+
+    /// Get the date, or the first business day after it if it is not one.
+    #[instrument]
+    #[inline]
+    pub fn next_business_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut d = date;
+        while !self.is_business_day(d) {
+            d = d.succ();
+        }
+
+        d
+    }
+
+    /// Get the date `n` business days after the date, ie. 2 business days after a
+    /// Thursday is the Monday after.
+    #[instrument]
+    #[inline]
+    pub fn add_business_days(&self, date: NaiveDate, n: u32) -> NaiveDate {
+        let mut d = date;
+        let mut left = n;
+        while left > 0 {
+            d = d.succ();
+            if self.is_business_day(d) {
+                left -= 1;
+            }
+        }
+
+        d
+    }
+
+    /// Count the business days after `start` up to and including `end`, so the count
+    /// from a Friday to the Monday after is 1.
+    #[instrument]
+    #[inline]
+    pub fn business_days_between(&self, start: NaiveDate, end: NaiveDate) -> i64 {
+        let mut count = 0;
+        let mut d = start.succ();
+        while d <= end {
+            if self.is_business_day(d) {
+                count += 1;
+            }
+            d = d.succ();
+        }
+
+        count
+    }
+
+    /// Get the deadline `n` business days after a time, at the same time of day in the
+    /// timezone. Something that comes in on a day off counts from the next business day,
+    /// ie. a request on Saturday due in 1 business day is due at the start of Tuesday.
+    #[instrument(skip(tz))]
+    #[inline]
+    pub fn business_deadline<T: TimeZone>(&self, start: DateTime<Utc>, n: u32, tz: &T) -> DateTime<Utc> {
+        let local = start.with_timezone(tz).naive_local();
+        let (date, time) = if self.is_business_day(local.date()) {
+            (local.date(), local.time())
+        } else {
+            (self.next_business_day(local.date()), chrono::NaiveTime::from_hms(0, 0, 0))
+        };
+
+        let due = self.add_business_days(date, n).and_time(time);
+        // The deadline can land in a daylight saving time gap, in which case we use the
+        // time an hour later.
+        tz.from_local_datetime(&due)
+            .earliest()
+            .or_else(|| tz.from_local_datetime(&(due + Duration::hours(1))).earliest())
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&due))
+    }
+
+    /// Get the next time after `after` the schedule fires on a business day in the
+    /// timezone, so reminders skip weekends and holidays.
+    #[instrument(skip(tz))]
+    #[inline]
+    pub fn next_business_fire<T: TimeZone>(&self, schedule: &CronSchedule, after: DateTime<Utc>, tz: &T) -> Option<DateTime<Utc>> {
+        let mut t = after;
+        // A schedule can only fire on days off for as long as the longest break.
+        for _ in 0..366 {
+            t = schedule.next_after(t, tz)?;
+            if self.is_business_day(t.with_timezone(tz).naive_local().date()) {
+                return Some(t);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::business_days::{BusinessCalendar, HolidayConfig};
+    use crate::schedule::{parse_timezone, CronSchedule};
+
+    fn calendar() -> BusinessCalendar {
+        let mut holidays: BTreeMap<String, HolidayConfig> = Default::default();
+        holidays.insert(
+            "memorial-day-2021".to_string(),
+            HolidayConfig {
+                date: NaiveDate::from_ymd(2021, 5, 31),
+                end_date: None,
+            },
+        );
+        holidays.insert(
+            "winter-break-2021".to_string(),
+            HolidayConfig {
+                date: NaiveDate::from_ymd(2021, 12, 24),
+                end_date: Some(NaiveDate::from_ymd(2021, 12, 31)),
+            },
+        );
+
+        BusinessCalendar::new(&holidays)
+    }
+
+    #[test]
+    fn test_business_days() {
+        let cal = calendar();
+        let d = |m: u32, day: u32| NaiveDate::from_ymd(2021, m, day);
+
+        assert!(cal.is_business_day(d(5, 28)));
+        assert!(!cal.is_business_day(d(5, 29)));
+        assert!(!cal.is_business_day(d(5, 31)));
+        assert!(!cal.is_business_day(d(12, 27)));
+
+        // Friday before Memorial Day plus 1 business day skips the weekend and the holiday.
+        assert_eq!(cal.add_business_days(d(5, 28), 1), d(6, 1));
+        assert_eq!(cal.add_business_days(d(5, 27), 2), d(6, 1));
+        assert_eq!(cal.add_business_days(d(5, 27), 0), d(5, 27));
+        assert_eq!(cal.next_business_day(d(12, 24)), NaiveDate::from_ymd(2022, 1, 3));
+
+        assert_eq!(cal.business_days_between(d(5, 28), d(6, 1)), 1);
+        assert_eq!(cal.business_days_between(d(6, 1), d(6, 8)), 5);
+        assert_eq!(cal.business_days_between(d(6, 8), d(6, 1)), 0);
+    }
+
+    #[test]
+    fn test_business_deadline() {
+        let cal = calendar();
+        let tz = parse_timezone("America/Los_Angeles").unwrap();
+
+        // Thursday, May 27th 2021 at 10:00 in Los Angeles, due in 2 business days is
+        // Tuesday at 10:00 since Monday is Memorial Day.
+        let start = Utc.ymd(2021, 5, 27).and_hms(17, 0, 0);
+        assert_eq!(cal.business_deadline(start, 2, &tz), Utc.ymd(2021, 6, 1).and_hms(17, 0, 0));
+
+        // Saturday counts from the start of Tuesday.
+        let start = Utc.ymd(2021, 5, 29).and_hms(17, 0, 0);
+        assert_eq!(cal.business_deadline(start, 1, &tz), Utc.ymd(2021, 6, 2).and_hms(7, 0, 0));
+    }
+
+    #[test]
+    fn test_next_business_fire() {
+        let cal = calendar();
+        let tz = parse_timezone("America/Los_Angeles").unwrap();
+        let schedule = CronSchedule::parse("0 9 * * *").unwrap();
+
+        // After 09:00 on Friday before Memorial Day the next business day is Tuesday.
+        let after = Utc.ymd(2021, 5, 28).and_hms(17, 0, 0);
+        assert_eq!(cal.next_business_fire(&schedule, after, &tz), Some(Utc.ymd(2021, 6, 1).and_hms(16, 0, 0)));
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::cloud_costs::{CloudCosts, NewCloudCost};
+use crate::configs::Config;
+use crate::db::Database;
+use crate::headcount::{quarter_days, quarter_of};
+
+/// The spend-based factor we estimate the emissions of cloud spend with when our configs
+/// do not set one for the provider, in kg CO2e per US dollar. It is in line with the
+/// factors for data processing and hosting services in environmentally extended
+/// input-output models, which is what the GHG Protocol suggests for scope 3 when we do
+/// not have usage data from the provider.
+pub const DEFAULT_CLOUD_KG_CO2E_PER_USD: f64 = 0.12;
+
+/// The cloud providers we have spend for.
+pub static CLOUD_PROVIDERS: &[&str] = &["aws", "gcp"];
+
+/// The configuration for estimating our carbon footprint.
+///
+/// ```toml
+/// [carbon.cloud_kg_co2e_per_usd]
+/// gcp = 0.05
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct CarbonConfig {
+    /// The kg CO2e per US dollar of spend for each cloud provider, ie. worked out from
+    /// the carbon footprint reports of the provider, overriding the default factor.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cloud_kg_co2e_per_usd: BTreeMap<String, f64>,
+}
+
+impl CarbonConfig {
+    /// Get the kg CO2e per US dollar of spend for a cloud provider.
+    #[instrument]
+    #[inline]
+    pub fn cloud_factor(&self, provider: &str) -> f64 {
+        self.cloud_kg_co2e_per_usd.get(provider).cloned().unwrap_or(DEFAULT_CLOUD_KG_CO2E_PER_USD)
+    }
+}
+
+/// Estimate the emissions of our cloud spend, in kg CO2e, by quarter and then by provider.
+#[instrument(skip(config, costs))]
+#[inline]
+pub fn estimate_cloud_emissions(config: &CarbonConfig, costs: &[NewCloudCost]) -> BTreeMap<String, BTreeMap<String, f64>> {
+    let mut quarters: BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
+    for cost in costs {
+        *quarters.entry(quarter_of(cost.month)).or_default().entry(cost.provider.to_string()).or_default() += cost.cost as f64 * config.cloud_factor(&cost.provider);
+    }
+
+    quarters
+}
+
+/// Get the estimated emissions of our cloud spend, in kg CO2e, by quarter and then by
+/// provider.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn get_cloud_emissions(db: &Database, config: &Config) -> BTreeMap<String, BTreeMap<String, f64>> {
+    let costs: Vec<NewCloudCost> = CloudCosts::get_from_db(db).into_iter().map(|c| c.into()).collect();
+
+    estimate_cloud_emissions(&config.carbon, &costs)
+}
+
+/// Format an amount of CO2e, ie. `850 kg CO2e` or `12.4 t CO2e`.
+#[instrument]
+#[inline]
+pub fn format_co2e(kg: f64) -> String {
+    if kg < 1000.0 {
+        return format!("{:.0} kg CO2e", kg);
+    }
+
+    format!("{:.1} t CO2e", kg / 1000.0)
+}
+
+/// Format the estimated emissions of a quarter and the quarter before it for the exec
+/// report, saying when the quarter is not over yet on the given day.
+#[instrument(skip(emissions))]
+#[inline]
+pub fn format_carbon_footprint(emissions: &BTreeMap<String, BTreeMap<String, f64>>, day: NaiveDate) -> String {
+    let quarter = quarter_of(day);
+    let (first, last) = quarter_days(&quarter).unwrap();
+    let previous = quarter_of(first.pred());
+
+    let mut text = "*Carbon footprint (estimated from cloud spend):*\n".to_string();
+    for (q, label) in &[
+        (quarter.to_string(), if day < last { format!("{} so far", quarter) } else { quarter.to_string() }),
+        (previous.to_string(), previous),
+    ] {
+        let providers = match emissions.get(q) {
+            Some(p) => p,
+            None => {
+                text += &format!("• {}: no cloud spend\n", label);
+                continue;
+            }
+        };
+        let total: f64 = providers.values().sum();
+        let by_provider: Vec<String> = providers.iter().map(|(p, kg)| format!("{} {}", p, format_co2e(*kg))).collect();
+        text += &format!("• {}: {} ({})\n", label, format_co2e(total), by_provider.join(", "));
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::naive::NaiveDate;
+
+    use crate::carbon::{estimate_cloud_emissions, format_carbon_footprint, format_co2e, CarbonConfig};
+    use crate::cloud_costs::NewCloudCost;
+
+    #[test]
+    fn test_estimate_cloud_emissions() {
+        let config: CarbonConfig = toml::from_str(
+            r#"[cloud_kg_co2e_per_usd]
+gcp = 0.05
+"#,
+        )
+        .unwrap();
+        let costs = vec![
+            NewCloudCost::new("gcp", NaiveDate::from_ymd(2021, 4, 1), "cio", "BigQuery", "eng", 10000.0, "USD"),
+            NewCloudCost::new("gcp", NaiveDate::from_ymd(2021, 5, 1), "cio", "BigQuery", "eng", 10000.0, "USD"),
+            NewCloudCost::new("aws", NaiveDate::from_ymd(2021, 5, 1), "1234", "Amazon Simple Storage Service", "", 5000.0, "USD"),
+            NewCloudCost::new("aws", NaiveDate::from_ymd(2021, 3, 1), "1234", "Amazon Simple Storage Service", "", 2500.0, "USD"),
+        ];
+
+        let emissions = estimate_cloud_emissions(&config, &costs);
+        assert_eq!(emissions.keys().cloned().collect::<Vec<String>>(), vec!["2021-Q1".to_string(), "2021-Q2".to_string()]);
+        assert!((emissions["2021-Q2"]["gcp"] - 1000.0).abs() < 0.01);
+        assert!((emissions["2021-Q2"]["aws"] - 600.0).abs() < 0.01);
+        assert!((emissions["2021-Q1"]["aws"] - 300.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_format_carbon_footprint() {
+        assert_eq!(format_co2e(849.6), "850 kg CO2e");
+        assert_eq!(format_co2e(12_400.0), "12.4 t CO2e");
+
+        let mut emissions: BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
+        emissions.entry("2021-Q2".to_string()).or_default().insert("aws".to_string(), 600.0);
+        emissions.entry("2021-Q2".to_string()).or_default().insert("gcp".to_string(), 1000.0);
+
+        assert_eq!(
+            format_carbon_footprint(&emissions, NaiveDate::from_ymd(2021, 5, 31)),
+            "*Carbon footprint (estimated from cloud spend):*\n• 2021-Q2 so far: 1.6 t CO2e (aws 600 kg CO2e, gcp 1.0 t CO2e)\n• 2021-Q1: no cloud spend\n"
+        );
+        assert_eq!(
+            format_carbon_footprint(&emissions, NaiveDate::from_ymd(2021, 6, 30)),
+            "*Carbon footprint (estimated from cloud spend):*\n• 2021-Q2: 1.6 t CO2e (aws 600 kg CO2e, gcp 1.0 t CO2e)\n• 2021-Q1: no cloud spend\n"
+        );
+    }
+}
@@ -200,6 +200,7 @@ pub async fn create_ssl_certificate(domain: &str) -> NewCertificate {
     new_struct_name = "Certificate",
     airtable_base_id = "AIRTABLE_BASE_ID_MISC",
     airtable_table = "AIRTABLE_CERTIFICATES_TABLE",
+    source = "configs",
     match_on = {
         "domain" = "String",
     },
@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+use chrono::naive::NaiveDate;
+use chrono::offset::Utc;
+use chrono::{Date, Duration};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::chat::ChatChannel;
+use crate::slack::upload_slack_file;
+
+/// The size of the charts we render, in pixels.
+const CHART_SIZE: (u32, u32) = (900, 450);
+
+/// A line on a chart, ie. the spend of one group over time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartSeries {
+    pub name: String,
+    pub points: Vec<(NaiveDate, f64)>,
+}
+
+/// Render a chart to a PNG. plotters only encodes images to files, so we render to a
+/// temporary file and read it back.
+fn render_png<F>(draw: F) -> Result<Vec<u8>, String>
+where
+    F: FnOnce(&DrawingArea<BitMapBackend, Shift>) -> Result<(), String>,
+{
+    let path = env::temp_dir().join(format!("cio-chart-{}.png", Utc::now().timestamp_nanos()));
+    {
+        let root = BitMapBackend::new(&path, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| e.to_string())?;
+        draw(&root)?;
+        root.present().map_err(|e| e.to_string())?;
+    }
+
+    let png = fs::read(&path).map_err(|e| format!("reading the rendered chart {} failed: {}", path.display(), e));
+    fs::remove_file(&path).unwrap_or_default();
+    png
+}
+
+/// Render a line chart of values over time as a PNG, with a line for each series.
+#[instrument(skip(series))]
+#[inline]
+pub fn render_line_chart(title: &str, y_label: &str, series: &[ChartSeries]) -> Result<Vec<u8>, String> {
+    let dates: Vec<NaiveDate> = series.iter().flat_map(|s| s.points.iter().map(|(d, _)| *d)).collect();
+    let (first, mut last) = match (dates.iter().min(), dates.iter().max()) {
+        (Some(f), Some(l)) => (*f, *l),
+        _ => return Err(format!("there is no data for the chart `{}`", title)),
+    };
+    // plotters can not draw an empty range.
+    if last == first {
+        last = first + Duration::days(1);
+    }
+    let mut max = series.iter().flat_map(|s| s.points.iter().map(|(_, v)| *v)).fold(0.0, f64::max) * 1.1;
+    if max <= 0.0 {
+        max = 1.0;
+    }
+
+    render_png(|root| {
+        let mut chart = ChartBuilder::on(root)
+            .caption(title, ("sans-serif", 24))
+            .margin(15)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d(Date::<Utc>::from_utc(first, Utc)..Date::<Utc>::from_utc(last, Utc), 0f64..max)
+            .map_err(|e| e.to_string())?;
+        chart
+            .configure_mesh()
+            .y_desc(y_label)
+            .x_label_formatter(&|d: &Date<Utc>| d.format("%b %-d").to_string())
+            .draw()
+            .map_err(|e| e.to_string())?;
+
+        for (i, s) in series.iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            chart
+                .draw_series(LineSeries::new(s.points.iter().map(|(d, v)| (Date::<Utc>::from_utc(*d, Utc), *v)), &color))
+                .map_err(|e| e.to_string())?
+                .label(s.name.as_str())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+        }
+        if series.len() > 1 {
+            chart.configure_series_labels().background_style(&WHITE).border_style(&BLACK).draw().map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Render a bar chart of labelled values as a PNG, ie. the stages of a funnel.
+#[instrument]
+#[inline]
+pub fn render_bar_chart(title: &str, bars: &[(String, f64)]) -> Result<Vec<u8>, String> {
+    if bars.is_empty() {
+        return Err(format!("there is no data for the chart `{}`", title));
+    }
+    let mut max = bars.iter().map(|(_, v)| *v).fold(0.0, f64::max) * 1.1;
+    if max <= 0.0 {
+        max = 1.0;
+    }
+
+    render_png(|root| {
+        let mut chart = ChartBuilder::on(root)
+            .caption(title, ("sans-serif", 24))
+            .margin(15)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d((0..bars.len()).into_segmented(), 0f64..max)
+            .map_err(|e| e.to_string())?;
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_labels(bars.len())
+            .x_label_formatter(&|v: &SegmentValue<usize>| match v {
+                SegmentValue::CenterOf(i) => bars.get(*i).map(|(label, _)| label.to_string()).unwrap_or_default(),
+                _ => String::new(),
+            })
+            .draw()
+            .map_err(|e| e.to_string())?;
+
+        chart
+            .draw_series(
+                Histogram::vertical(&chart)
+                    .style(BLUE.mix(0.7).filled())
+                    .margin(20)
+                    .data(bars.iter().enumerate().map(|(i, (_, v))| (i, *v))),
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    })
+}
+
+/// Count how many things there were on each date, from the dates they started, ie.
+/// headcount from start dates. Returns a point for each distinct date.
+#[instrument(skip(dates))]
+#[inline]
+pub fn cumulative_counts(dates: &[NaiveDate]) -> Vec<(NaiveDate, f64)> {
+    let mut per_date: BTreeMap<NaiveDate, usize> = Default::default();
+    for d in dates {
+        *per_date.entry(*d).or_default() += 1;
+    }
+
+    let mut total = 0;
+    per_date
+        .into_iter()
+        .map(|(d, n)| {
+            total += n;
+            (d, total as f64)
+        })
+        .collect()
+}
+
+/// Attach a rendered chart to a message, in the `charts` field of the message. The charts
+/// are uploaded after the message is posted, including when it is held for a digest.
+/// Charts that failed to render are left out.
+#[instrument(skip(msg, png))]
+#[inline]
+pub fn attach_chart(msg: &mut Value, title: &str, png: Result<Vec<u8>, String>) {
+    let png = match png {
+        Ok(p) => p,
+        Err(e) => {
+            println!("[charts] rendering `{}` failed: {}", title, e);
+            return;
+        }
+    };
+
+    if !msg["charts"].is_array() {
+        msg["charts"] = json!([]);
+    }
+    msg["charts"].as_array_mut().unwrap().push(json!({
+        "title": title,
+        "png": base64::encode(&png),
+    }));
+}
+
+/// Take the charts out of a message, so the rest of the message can be posted as is.
+#[instrument(skip(msg))]
+#[inline]
+pub fn take_charts(msg: &mut Value) -> Vec<Value> {
+    match msg.as_object_mut().and_then(|m| m.remove("charts")) {
+        Some(Value::Array(charts)) => charts,
+        _ => vec![],
+    }
+}
+
+/// The file name to upload a chart as, from its title.
+fn chart_filename(title: &str) -> String {
+    let slug: Vec<String> = title.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(|w| w.to_string()).collect();
+
+    format!("{}.png", slug.join("-"))
+}
+
+/// Upload the charts taken from a message to a channel. Files can only be uploaded to
+/// Slack, to the channel ID in the `SLACK_{CHANNEL}_CHANNEL_ID` environment variable.
+#[instrument(skip(charts))]
+#[inline]
+pub async fn post_charts(channel: ChatChannel, charts: Vec<Value>) {
+    if charts.is_empty() {
+        return;
+    }
+
+    let backend = env::var("CHAT_BACKEND").unwrap_or_default().to_lowercase();
+    if !backend.is_empty() && backend != "slack" {
+        println!("[charts] charts can only be posted to slack, not {}", backend);
+        return;
+    }
+    let channel_id = env::var(format!("SLACK_{}_CHANNEL_ID", channel.env_name())).unwrap_or_default();
+    if channel_id.is_empty() {
+        println!("[charts] SLACK_{}_CHANNEL_ID is not set, not posting {} charts", channel.env_name(), charts.len());
+        return;
+    }
+
+    for chart in charts {
+        let title = chart["title"].as_str().unwrap_or_default();
+        let png = match base64::decode(chart["png"].as_str().unwrap_or_default()) {
+            Ok(p) => p,
+            Err(e) => {
+                println!("[charts] decoding `{}` failed: {}", title, e);
+                continue;
+            }
+        };
+
+        if let Err(e) = upload_slack_file(&channel_id, &chart_filename(title), title, png).await {
+            println!("[charts] {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+    use serde_json::json;
+
+    use crate::charts::{attach_chart, chart_filename, cumulative_counts, take_charts};
+
+    #[test]
+    fn test_cumulative_counts() {
+        let dates = vec![NaiveDate::from_ymd(2020, 3, 1), NaiveDate::from_ymd(2019, 9, 1), NaiveDate::from_ymd(2020, 3, 1)];
+        assert_eq!(cumulative_counts(&dates), vec![(NaiveDate::from_ymd(2019, 9, 1), 1.0), (NaiveDate::from_ymd(2020, 3, 1), 3.0)]);
+        assert!(cumulative_counts(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_attach_chart() {
+        let mut msg = json!({ "text": "Cloud spend this month" });
+        attach_chart(&mut msg, "Cloud spend by group", Ok(vec![137, 80, 78, 71]));
+        attach_chart(&mut msg, "Broken", Err("no data".to_string()));
+        assert_eq!(msg["charts"], json!([{ "title": "Cloud spend by group", "png": "iVBORw==" }]));
+
+        let charts = take_charts(&mut msg);
+        assert_eq!(charts.len(), 1);
+        assert_eq!(msg, json!({ "text": "Cloud spend this month" }));
+        assert!(take_charts(&mut msg).is_empty());
+
+        assert_eq!(chart_filename("Cloud spend by group (USD)"), "cloud-spend-by-group-usd.png");
+    }
+}
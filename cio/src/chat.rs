@@ -0,0 +1,259 @@
+use std::env;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use regex::Regex;
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::slack::{
+    get_engineering_channel_post_url, get_finance_channel_post_url, get_hiring_channel_post_url, get_office_channel_post_url, get_public_relations_channel_post_url, get_security_channel_post_url,
+    post_to_channel,
+};
+use crate::vcr;
+
+/// The channels our jobs post notifications to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChatChannel {
+    Engineering,
+    Finance,
+    Hiring,
+    Office,
+    PublicRelations,
+    Security,
+}
+
+/// Every channel we post to.
+pub static CHAT_CHANNELS: &[ChatChannel] = &[
+    ChatChannel::Engineering,
+    ChatChannel::Finance,
+    ChatChannel::Hiring,
+    ChatChannel::Office,
+    ChatChannel::PublicRelations,
+    ChatChannel::Security,
+];
+
+impl ChatChannel {
+    /// The name of the channel as used in environment variables, ie. `PUBLIC_RELATIONS`.
+    #[instrument]
+    #[inline]
+    pub fn env_name(&self) -> &'static str {
+        match self {
+            ChatChannel::Engineering => "ENGINEERING",
+            ChatChannel::Finance => "FINANCE",
+            ChatChannel::Hiring => "HIRING",
+            ChatChannel::Office => "OFFICE",
+            ChatChannel::PublicRelations => "PUBLIC_RELATIONS",
+            ChatChannel::Security => "SECURITY",
+        }
+    }
+}
+
+/// A chat service we can post notifications to. Messages are always built in the
+/// Slack message format, with either `text` or `blocks`, and each backend converts
+/// them to what its service expects.
+#[async_trait]
+pub trait ChatNotifier: Send + Sync {
+    /// Post a message to a channel.
+    async fn post(&self, channel: ChatChannel, msg: Value);
+}
+
+/// Post to Slack through the incoming webhook for each channel.
+pub struct SlackNotifier;
+
+#[async_trait]
+impl ChatNotifier for SlackNotifier {
+    async fn post(&self, channel: ChatChannel, msg: Value) {
+        let url = match channel {
+            ChatChannel::Engineering => get_engineering_channel_post_url(),
+            ChatChannel::Finance => get_finance_channel_post_url(),
+            ChatChannel::Hiring => get_hiring_channel_post_url(),
+            ChatChannel::Office => get_office_channel_post_url(),
+            ChatChannel::PublicRelations => get_public_relations_channel_post_url(),
+            ChatChannel::Security => get_security_channel_post_url(),
+        };
+
+        post_to_channel(url, msg).await;
+    }
+}
+
+/// Post to Discord through the webhook for each channel, set in the
+/// `DISCORD_{CHANNEL}_CHANNEL_WEBHOOK_URL` environment variables.
+pub struct DiscordNotifier;
+
+/// Discord rejects messages longer than this.
+const DISCORD_MAX_MESSAGE_LENGTH: usize = 2000;
+
+#[async_trait]
+impl ChatNotifier for DiscordNotifier {
+    async fn post(&self, channel: ChatChannel, msg: Value) {
+        let url = env::var(format!("DISCORD_{}_CHANNEL_WEBHOOK_URL", channel.env_name())).unwrap();
+
+        let mut content = slack_message_to_markdown(&msg);
+        if content.chars().count() > DISCORD_MAX_MESSAGE_LENGTH {
+            content = content.chars().take(DISCORD_MAX_MESSAGE_LENGTH - 1).collect::<String>() + "…";
+        }
+
+        let client = Client::new();
+        let resp = vcr::send(&client, client.post(&url).json(&json!({ "content": content }))).await.unwrap();
+        match resp.status() {
+            StatusCode::OK | StatusCode::NO_CONTENT => (),
+            s => {
+                println!("posting to discord webhook for {:?} failed, status: {} | resp: {}", channel, s, resp.text().await.unwrap());
+            }
+        };
+    }
+}
+
+/// Post to Matrix as the user for the `MATRIX_ACCESS_TOKEN` on the `MATRIX_HOMESERVER_URL`,
+/// in the room for each channel set in the `MATRIX_{CHANNEL}_ROOM_ID` environment variables.
+pub struct MatrixNotifier;
+
+#[async_trait]
+impl ChatNotifier for MatrixNotifier {
+    async fn post(&self, channel: ChatChannel, msg: Value) {
+        let homeserver = env::var("MATRIX_HOMESERVER_URL").unwrap();
+        let token = env::var("MATRIX_ACCESS_TOKEN").unwrap();
+        let room_id = env::var(format!("MATRIX_{}_ROOM_ID", channel.env_name())).unwrap();
+
+        // The transaction ID makes retries of the same message idempotent, so it only
+        // needs to be unique per message.
+        let txn_id = Utc::now().timestamp_nanos().to_string();
+        let mut url = reqwest::Url::parse(&homeserver).unwrap();
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .extend(&["_matrix", "client", "r0", "rooms", room_id.as_str(), "send", "m.room.message", txn_id.as_str()]);
+
+        let client = Client::new();
+        let resp = vcr::send(
+            &client,
+            client.put(url).bearer_auth(&token).json(&json!({
+                "msgtype": "m.text",
+                "body": slack_message_to_markdown(&msg),
+            })),
+        )
+        .await
+        .unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                println!("posting to matrix room for {:?} failed, status: {} | resp: {}", channel, s, resp.text().await.unwrap());
+            }
+        };
+    }
+}
+
+/// Get the chat backend from the `CHAT_BACKEND` environment variable, either
+/// `slack`, `discord`, or `matrix`. Defaults to Slack.
+#[instrument]
+#[inline]
+pub fn chat_notifier() -> Box<dyn ChatNotifier> {
+    match env::var("CHAT_BACKEND").unwrap_or_default().to_lowercase().as_str() {
+        "discord" => Box::new(DiscordNotifier),
+        "matrix" => Box::new(MatrixNotifier),
+        _ => Box::new(SlackNotifier),
+    }
+}
+
+/// Get the environment variables the configured chat backend needs.
+#[instrument]
+#[inline]
+pub fn chat_backend_env_vars() -> Vec<String> {
+    match env::var("CHAT_BACKEND").unwrap_or_default().to_lowercase().as_str() {
+        "discord" => CHAT_CHANNELS.iter().map(|c| format!("DISCORD_{}_CHANNEL_WEBHOOK_URL", c.env_name())).collect(),
+        "matrix" => {
+            let mut vars = vec!["MATRIX_HOMESERVER_URL".to_string(), "MATRIX_ACCESS_TOKEN".to_string()];
+            vars.extend(CHAT_CHANNELS.iter().map(|c| format!("MATRIX_{}_ROOM_ID", c.env_name())));
+            vars
+        }
+        _ => CHAT_CHANNELS.iter().map(|c| format!("SLACK_{}_CHANNEL_POST_URL", c.env_name())).collect(),
+    }
+}
+
+/// Post a message to a channel with the configured chat backend.
+#[instrument(skip(msg))]
+#[inline]
+pub async fn notify_channel(channel: ChatChannel, msg: Value) {
+    chat_notifier().post(channel, msg).await;
+}
+
+/// Get the text of a message in the Slack message format as mrkdwn, flattening any
+/// blocks into lines.
+#[instrument]
+#[inline]
+pub fn slack_message_text(msg: &Value) -> String {
+    let mut lines: Vec<String> = Default::default();
+    for block in msg["blocks"].as_array().cloned().unwrap_or_default() {
+        if let Some(text) = block["text"]["text"].as_str() {
+            lines.push(text.to_string());
+        }
+        for f in block["fields"].as_array().cloned().unwrap_or_default() {
+            if let Some(text) = f["text"].as_str() {
+                lines.push(text.to_string());
+            }
+        }
+        // Context blocks have their text in elements.
+        for e in block["elements"].as_array().cloned().unwrap_or_default() {
+            if let Some(text) = e["text"].as_str() {
+                lines.push(text.to_string());
+            }
+        }
+    }
+    if lines.is_empty() {
+        lines.push(msg["text"].as_str().unwrap_or_default().to_string());
+    }
+
+    lines.join("\n")
+}
+
+/// Convert a message in the Slack message format to Markdown for services that
+/// do not understand Slack blocks or mrkdwn.
+#[instrument]
+#[inline]
+pub fn slack_message_to_markdown(msg: &Value) -> String {
+    slack_mrkdwn_to_markdown(&slack_message_text(msg))
+}
+
+/// Convert Slack mrkdwn links and bold text to Markdown.
+#[instrument]
+#[inline]
+pub fn slack_mrkdwn_to_markdown(text: &str) -> String {
+    let labeled_link = Regex::new(r"<([^|>]+)\|([^>]+)>").unwrap();
+    let link = Regex::new(r"<([^>]+)>").unwrap();
+    let bold = Regex::new(r"\*([^*\n]+)\*").unwrap();
+
+    let text = labeled_link.replace_all(text, "[$2]($1)");
+    let text = link.replace_all(&text, "$1");
+    bold.replace_all(&text, "**$1**").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::chat::{slack_message_to_markdown, slack_mrkdwn_to_markdown};
+
+    #[test]
+    fn test_slack_message_to_markdown() {
+        assert_eq!(
+            slack_mrkdwn_to_markdown("*Jane Doe*  <mailto:jane@example.com|jane@example.com> | <https://example.com>"),
+            "**Jane Doe**  [jane@example.com](mailto:jane@example.com) | https://example.com"
+        );
+
+        assert_eq!(
+            slack_message_to_markdown(&json!({"text": "Cloud spend this month:\n*eng*: $10"})),
+            "Cloud spend this month:\n**eng**: $10"
+        );
+
+        let msg = json!({
+            "blocks": [
+                {"type": "section", "text": {"type": "mrkdwn", "text": "*Jane Doe*"}},
+                {"type": "context", "elements": [{"type": "mrkdwn", "text": "<https://example.com|resume>"}]},
+                {"type": "divider"}
+            ]
+        });
+        assert_eq!(slack_message_to_markdown(&msg), "**Jane Doe**\n[resume](https://example.com)");
+    }
+}
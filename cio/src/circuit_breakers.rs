@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+use tracing::instrument;
+
+use crate::chat::ChatChannel;
+use crate::db::Database;
+use crate::doctor::{catch_panic, check_airtable_tables, check_github, DoctorCheck};
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::utils::get_gsuite_token;
+
+/// The external services our jobs depend on that have a circuit breaker.
+pub static BREAKER_SERVICES: &[&str] = &["airtable", "github", "gsuite"];
+
+/// How many jobs that depend on a service fail in a row before its breaker opens.
+pub const BREAKER_FAILURE_THRESHOLD: u32 = 3;
+
+/// How long we wait after a breaker opens before probing the service. This doubles
+/// after each failed probe, up to `BREAKER_MAX_PROBE_MINUTES`.
+pub const BREAKER_PROBE_MINUTES: i64 = 5;
+pub const BREAKER_MAX_PROBE_MINUTES: i64 = 60;
+
+/// A circuit breaker for a service. Once the jobs that depend on a service fail
+/// `BREAKER_FAILURE_THRESHOLD` times in a row the breaker opens, and those jobs are
+/// skipped rather than each failing slowly in turn. While open, the service is probed
+/// with a cheap request and the breaker closes once a probe succeeds.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CircuitBreaker {
+    /// How many jobs that depend on the service failed in a row.
+    pub failures: u32,
+    pub opened_at: Option<DateTime<Utc>>,
+    pub next_probe: Option<DateTime<Utc>>,
+    /// How many probes failed since the breaker opened.
+    pub failed_probes: u32,
+    pub last_error: String,
+}
+
+impl CircuitBreaker {
+    /// Returns if jobs that depend on the service are being skipped.
+    #[instrument]
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.opened_at.is_some()
+    }
+
+    /// Record a job that depends on the service succeeding, which closes the breaker.
+    #[instrument]
+    #[inline]
+    pub fn record_success(&mut self) {
+        *self = Default::default();
+    }
+
+    /// Record a job that depends on the service failing. Returns true if this opened
+    /// the breaker.
+    #[instrument]
+    #[inline]
+    pub fn record_failure(&mut self, error: &str, now: DateTime<Utc>) -> bool {
+        self.failures += 1;
+        self.last_error = error.to_string();
+        if self.is_open() || self.failures < BREAKER_FAILURE_THRESHOLD {
+            return false;
+        }
+
+        self.opened_at = Some(now);
+        self.next_probe = Some(now + Duration::minutes(BREAKER_PROBE_MINUTES));
+        true
+    }
+
+    /// Returns if the breaker is open and it is time to probe the service.
+    #[instrument]
+    #[inline]
+    pub fn probe_due(&self, now: DateTime<Utc>) -> bool {
+        self.is_open() && self.next_probe.map(|p| p <= now).unwrap_or(true)
+    }
+
+    /// Record a probe of the service failing, backing off before the next one.
+    #[instrument]
+    #[inline]
+    pub fn record_failed_probe(&mut self, error: &str, now: DateTime<Utc>) {
+        self.failed_probes += 1;
+        self.last_error = error.to_string();
+        let wait = (BREAKER_PROBE_MINUTES << self.failed_probes.min(8)).min(BREAKER_MAX_PROBE_MINUTES);
+        self.next_probe = Some(now + Duration::minutes(wait));
+    }
+}
+
+/// The circuit breakers for each service, kept by the scheduler.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CircuitBreakers(pub BTreeMap<String, CircuitBreaker>);
+
+impl CircuitBreakers {
+    /// Get the services with an open breaker out of the ones a job depends on.
+    #[instrument]
+    #[inline]
+    pub fn open_for(&self, services: &[&str]) -> Vec<String> {
+        services.iter().filter(|s| self.0.get(**s).map(|b| b.is_open()).unwrap_or(false)).map(|s| s.to_string()).collect()
+    }
+
+    /// Record the result of a job against the services it depends on. Returns the
+    /// services whose breaker this opened.
+    #[instrument]
+    #[inline]
+    pub fn record_job(&mut self, services: &[&str], result: &Result<(), String>, now: DateTime<Utc>) -> Vec<String> {
+        let mut opened: Vec<String> = Default::default();
+        for service in services {
+            let breaker = self.0.entry(service.to_string()).or_default();
+            match result {
+                Ok(()) => breaker.record_success(),
+                Err(e) => {
+                    if breaker.record_failure(e, now) {
+                        opened.push(service.to_string());
+                    }
+                }
+            }
+        }
+
+        opened
+    }
+
+    /// Probe the services whose breaker is open and due for a probe, closing the
+    /// breakers of the ones that recovered. Returns the services that recovered.
+    #[instrument(skip(db))]
+    #[inline]
+    pub async fn probe(&mut self, db: &Database, now: DateTime<Utc>) -> Vec<String> {
+        let mut recovered: Vec<String> = Default::default();
+        for (service, breaker) in self.0.iter_mut().filter(|(_, b)| b.probe_due(now)) {
+            match probe_service(service).await {
+                Ok(()) => {
+                    println!("[circuit_breakers] {} recovered, closing its breaker", service);
+                    breaker.record_success();
+                    notify_service(db, "services.recovered", service, "", 0).await;
+                    recovered.push(service.to_string());
+                }
+                Err(e) => {
+                    breaker.record_failed_probe(&e, now);
+                    println!("[circuit_breakers] probing {} failed, trying again at {:?}: {}", service, breaker.next_probe, e);
+                }
+            }
+        }
+
+        recovered
+    }
+
+    /// Tell engineering a service looks to be down.
+    #[instrument(skip(db))]
+    #[inline]
+    pub async fn notify_opened(&self, db: &Database, service: &str) {
+        if let Some(breaker) = self.0.get(service) {
+            notify_service(db, "services.unavailable", service, &breaker.last_error, breaker.failures).await;
+        }
+    }
+}
+
+async fn notify_service(db: &Database, event: &str, service: &str, error: &str, count: u32) {
+    notify(
+        db,
+        NotificationPriority::Urgent,
+        ChatChannel::Engineering,
+        event,
+        json!({ "text": render_message(event, &json!({ "service": service, "error": error, "count": count })) }),
+    )
+    .await;
+}
+
+/// The result for a job skipped because a service it depends on is down.
+#[instrument]
+#[inline]
+pub fn skipped_result(services: &[String]) -> String {
+    format!("skipped: {} unavailable", services.join(", "))
+}
+
+/// Make a cheap request to a service to see if it is back, reusing the checks from
+/// `cio doctor`.
+#[instrument]
+#[inline]
+pub async fn probe_service(service: &str) -> Result<(), String> {
+    let checks: Vec<DoctorCheck> = match service {
+        "airtable" => check_airtable_tables().await,
+        "github" => check_github().await,
+        "gsuite" => return catch_panic(async { get_gsuite_token("").await }).await.map(|_| ()),
+        _ => return Ok(()),
+    };
+
+    let failed: Vec<String> = checks.into_iter().filter(|c| !c.passed).map(|c| format!("{}: {}", c.name, c.detail)).collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(failed.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::circuit_breakers::{skipped_result, CircuitBreakers, BREAKER_FAILURE_THRESHOLD};
+
+    #[test]
+    fn test_circuit_breakers() {
+        let now = Utc.ymd(2021, 5, 4).and_hms(9, 0, 0);
+        let mut breakers = CircuitBreakers::default();
+
+        let failed = Err("airtable returned 503".to_string());
+        for _ in 1..BREAKER_FAILURE_THRESHOLD {
+            assert!(breakers.record_job(&["airtable", "gsuite"], &failed, now).is_empty());
+        }
+        // A job that only needs GSuite succeeding means GSuite is fine.
+        breakers.record_job(&["gsuite"], &Ok(()), now);
+        assert_eq!(breakers.record_job(&["airtable", "gsuite"], &failed, now), vec!["airtable".to_string()]);
+        assert_eq!(breakers.open_for(&["airtable", "github", "gsuite"]), vec!["airtable".to_string()]);
+        assert_eq!(skipped_result(&breakers.open_for(&["airtable"])), "skipped: airtable unavailable");
+
+        // More failures while open do not open it again.
+        assert!(breakers.record_job(&["airtable"], &failed, now).is_empty());
+
+        let airtable = breakers.0.get_mut("airtable").unwrap();
+        assert!(!airtable.probe_due(now + Duration::minutes(4)));
+        assert!(airtable.probe_due(now + Duration::minutes(5)));
+
+        // Failed probes back off.
+        airtable.record_failed_probe("still down", now + Duration::minutes(5));
+        assert_eq!(airtable.next_probe, Some(now + Duration::minutes(15)));
+        airtable.record_failed_probe("still down", now + Duration::minutes(15));
+        assert_eq!(airtable.next_probe, Some(now + Duration::minutes(35)));
+        for _ in 0..5 {
+            airtable.record_failed_probe("still down", now);
+        }
+        assert_eq!(airtable.next_probe, Some(now + Duration::minutes(60)));
+
+        airtable.record_success();
+        assert!(breakers.open_for(&["airtable"]).is_empty());
+    }
+}
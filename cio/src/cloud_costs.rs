@@ -0,0 +1,483 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use async_trait::async_trait;
+use chrono::naive::NaiveDate;
+use chrono::{Datelike, Utc};
+use macros::db;
+use reqwest::Client;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_FINANCE, AIRTABLE_CLOUD_COSTS_TABLE};
+use crate::approvals::{get_approval_request, request_approval};
+use crate::charts::{attach_chart, render_line_chart, ChartSeries};
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::format::format_usd;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::cloud_costs;
+use crate::utils::get_gcp_token;
+
+/// The label or tag on a cloud project or resource that names the group that owns it.
+pub static OWNER_LABEL: &str = "owner";
+
+/// The data type for the spend on a service in a cloud project for a month.
+#[db {
+    new_struct_name = "CloudCost",
+    airtable_base_id = "AIRTABLE_BASE_ID_FINANCE",
+    airtable_table = "AIRTABLE_CLOUD_COSTS_TABLE",
+    source = "cloud_billing",
+    match_on = {
+        "cost_id" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "cloud_costs"]
+pub struct NewCloudCost {
+    /// A unique identifier for the cost formatted as
+    /// `{provider}/{month}/{project}/{service}/{owner_group}`.
+    pub cost_id: String,
+    /// The cloud provider, either `gcp` or `aws`.
+    pub provider: String,
+    /// The first day of the month the spend is for.
+    #[serde(serialize_with = "crate::configs::null_date_format::serialize")]
+    pub month: NaiveDate,
+    /// The GCP project ID or the AWS account ID.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub project: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub service: String,
+    /// The group that owns the project, from the `owner` label or tag.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub owner_group: String,
+    #[serde(default)]
+    pub cost: f32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub currency: String,
+}
+
+impl NewCloudCost {
+    /// Create a new cost with the `cost_id` set from the other fields.
+    #[instrument]
+    #[inline]
+    pub fn new(provider: &str, month: NaiveDate, project: &str, service: &str, owner_group: &str, cost: f32, currency: &str) -> Self {
+        NewCloudCost {
+            cost_id: format!("{}/{}/{}/{}/{}", provider, month.format("%Y-%m"), project, service, owner_group),
+            provider: provider.to_string(),
+            month,
+            project: project.to_string(),
+            service: service.to_string(),
+            owner_group: owner_group.to_string(),
+            cost,
+            currency: currency.to_string(),
+        }
+    }
+}
+
+/// Implement updating the Airtable record for a CloudCost.
+#[async_trait]
+impl UpdateAirtableRecord<CloudCost> for CloudCost {
+    async fn update_airtable_record(&mut self, _record: CloudCost) {}
+}
+
+/// The monthly budget for the cloud spend of a group, keyed by the group name in
+/// the `cloud_budgets` section of our configs.
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct CloudBudgetConfig {
+    #[serde(default)]
+    pub monthly: f32,
+}
+
+/// Returns the first day of the month for a date.
+fn month_start(date: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd(date.year(), date.month(), 1)
+}
+
+/// Get the GCP spend for a month from the BigQuery billing export. The table is set in
+/// the `GCP_BILLING_EXPORT_TABLE` environment variable as `{project}.{dataset}.{table}`.
+/// FROM: https://cloud.google.com/billing/docs/how-to/export-data-bigquery-tables
+#[instrument]
+#[inline]
+pub async fn get_gcp_costs(month: NaiveDate) -> Vec<NewCloudCost> {
+    let table = env::var("GCP_BILLING_EXPORT_TABLE").unwrap();
+    let project = table.split('.').next().unwrap_or_default().to_string();
+
+    // Credits are negative amounts so we add them to get the net cost.
+    let query = format!(
+        "SELECT project.id, service.description, \
+         IFNULL((SELECT value FROM UNNEST(project.labels) WHERE key = '{}'), ''), \
+         SUM(cost) + SUM(IFNULL((SELECT SUM(c.amount) FROM UNNEST(credits) c), 0)), currency \
+         FROM `{}` WHERE invoice.month = '{}' GROUP BY 1, 2, 3, 5",
+        OWNER_LABEL,
+        table,
+        month.format("%Y%m")
+    );
+
+    let token = get_gcp_token(&["https://www.googleapis.com/auth/bigquery.readonly"]).await;
+    let resp: Value = Client::new()
+        .post(&format!("https://bigquery.googleapis.com/bigquery/v2/projects/{}/queries", project))
+        .bearer_auth(token.as_str())
+        .json(&json!({
+            "query": query,
+            "useLegacySql": false,
+            "timeoutMs": 60_000,
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    if !resp["error"].is_null() {
+        println!("[cloud_costs] querying the gcp billing export failed: {}", resp["error"]);
+        return Default::default();
+    }
+
+    parse_bigquery_rows(&resp, month)
+}
+
+/// Parse the rows of the billing export query into costs.
+fn parse_bigquery_rows(resp: &Value, month: NaiveDate) -> Vec<NewCloudCost> {
+    let mut costs: Vec<NewCloudCost> = Default::default();
+    for row in resp["rows"].as_array().cloned().unwrap_or_default() {
+        let f = |i: usize| row["f"][i]["v"].as_str().unwrap_or_default().to_string();
+        let cost = f(3).parse::<f32>().unwrap_or_default();
+        costs.push(NewCloudCost::new("gcp", month, &f(0), &f(1), &f(2), cost, &f(4)));
+    }
+
+    costs
+}
+
+/// Aggregate the line items in an AWS Cost and Usage Report CSV by account, service,
+/// and the owner tag for the given month.
+/// FROM: https://docs.aws.amazon.com/cur/latest/userguide/data-dictionary.html
+#[instrument(skip(r))]
+#[inline]
+pub fn aggregate_aws_cur<R: Read>(r: R, month: NaiveDate) -> Result<Vec<NewCloudCost>, String> {
+    let mut reader = csv::Reader::from_reader(r);
+    let headers = reader.headers().map_err(|e| format!("reading the aws cur headers failed: {}", e))?.clone();
+    let column = |name: &str| headers.iter().position(|h| h == name);
+
+    let period = column("bill/BillingPeriodStartDate");
+    let account = column("lineItem/UsageAccountId");
+    let service = column("product/ProductName").or_else(|| column("lineItem/ProductCode"));
+    let cost = column("lineItem/UnblendedCost");
+    let currency = column("lineItem/CurrencyCode");
+    let owner = column(&format!("resourceTags/user:{}", OWNER_LABEL));
+
+    let mut totals: BTreeMap<(String, String, String), (f32, String)> = Default::default();
+    for record in reader.records() {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                println!("[cloud_costs] reading aws cur record failed: {}", e);
+                continue;
+            }
+        };
+        let get = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or_default().to_string();
+
+        // The billing period is formatted as `2021-03-01T00:00:00Z`.
+        if !get(period).starts_with(&month.format("%Y-%m").to_string()) {
+            continue;
+        }
+
+        let entry = totals.entry((get(account), get(service), get(owner))).or_insert((0.0, get(currency)));
+        entry.0 += get(cost).parse::<f32>().unwrap_or_default();
+    }
+
+    Ok(totals
+        .into_iter()
+        .map(|((account, service, owner), (cost, currency))| NewCloudCost::new("aws", month, &account, &service, &owner, cost, &currency))
+        .collect())
+}
+
+/// Get the files of the latest version of a Cost and Usage Report from the manifest of its
+/// billing period, as `{assemblyId}/{file}`. AWS writes a new version of the report, with a
+/// new assembly ID, every time it updates it during the month, so the older versions have
+/// to be skipped or we would count the same costs again.
+/// FROM: https://docs.aws.amazon.com/cur/latest/userguide/understanding-report-versions.html
+#[instrument(skip(manifest))]
+#[inline]
+pub fn aws_cur_report_files(manifest: &Value) -> Vec<String> {
+    manifest["reportKeys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|key| {
+            let mut parts = key.as_str()?.rsplitn(3, '/');
+            let file = parts.next()?;
+            let assembly = parts.next()?;
+            Some(format!("{}/{}", assembly, file))
+        })
+        .collect()
+}
+
+/// Get the AWS spend for a month from the Cost and Usage Report CSV files in the
+/// directory set in the `AWS_CUR_PATH` environment variable. The files are synced
+/// from the report's S3 bucket, and unzipped, before we run.
+#[instrument]
+#[inline]
+pub fn get_aws_costs(month: NaiveDate) -> Vec<NewCloudCost> {
+    let dir = env::var("AWS_CUR_PATH").unwrap();
+
+    // Each billing period has a directory like `20210301-20210401`, with the manifest of
+    // the latest version of the report in it. The versions have their own directories
+    // with a copy of their manifest, so we only take the one right in the period's.
+    let next_month = if month.month() == 12 {
+        NaiveDate::from_ymd(month.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd(month.year(), month.month() + 1, 1)
+    };
+    let period = format!("{}-{}", month.format("%Y%m%d"), next_month.format("%Y%m%d"));
+    let manifest_path = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .find(|path| path.to_str().unwrap_or_default().ends_with("-Manifest.json") && path.parent().and_then(Path::file_name).and_then(|n| n.to_str()) == Some(period.as_str()));
+    let manifest_path = match manifest_path {
+        Some(path) => path,
+        None => {
+            println!("[cloud_costs] there is no aws cur manifest for the billing period {}", period);
+            return Default::default();
+        }
+    };
+    let manifest: Value = match fs::read_to_string(&manifest_path)
+        .map_err(|e| e.to_string())
+        .and_then(|m| serde_json::from_str(&m).map_err(|e| e.to_string()))
+    {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            println!("[cloud_costs] reading the aws cur manifest {} failed: {}", manifest_path.display(), e);
+            return Default::default();
+        }
+    };
+    let period_dir = manifest_path.parent().unwrap();
+
+    // A report can be split across multiple files so we merge the costs by their ID.
+    let mut costs: BTreeMap<String, NewCloudCost> = Default::default();
+    for file in aws_cur_report_files(&manifest) {
+        let path = period_dir.join(file.trim_end_matches(".gz"));
+        // Rather than record part of the spend, we skip AWS until we can read all of it.
+        let aggregated = fs::File::open(&path).map_err(|e| e.to_string()).and_then(|f| aggregate_aws_cur(f, month));
+        let aggregated = match aggregated {
+            Ok(aggregated) => aggregated,
+            Err(e) => {
+                println!("[cloud_costs] reading the aws cur file {} failed: {}", path.display(), e);
+                return Default::default();
+            }
+        };
+
+        for cost in aggregated {
+            match costs.get_mut(&cost.cost_id) {
+                Some(existing) => existing.cost += cost.cost,
+                None => {
+                    costs.insert(cost.cost_id.to_string(), cost);
+                }
+            }
+        }
+    }
+
+    costs.into_iter().map(|(_, cost)| cost).collect()
+}
+
+/// Sync the cloud spend for the current month from GCP and AWS.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_cloud_costs(db: &Database) {
+    let month = month_start(Utc::now().date().naive_utc());
+
+    let mut costs = get_gcp_costs(month).await;
+    costs.append(&mut get_aws_costs(month));
+
+    for cost in costs {
+        // Record which provider the cost came from rather than the default for the table.
+        cost.upsert_from(db, &cost.provider).await;
+    }
+}
+
+/// Get the total cloud spend per owner group for the month containing the given date.
+/// Spend without an owner is grouped under an empty string.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_cloud_costs_by_group_for_month(db: &Database, month: NaiveDate) -> BTreeMap<String, f32> {
+    let month = month_start(month);
+
+    let mut totals: BTreeMap<String, f32> = Default::default();
+    for cost in CloudCosts::get_from_db(db) {
+        if cost.month == month {
+            *totals.entry(cost.owner_group.to_string()).or_default() += cost.cost;
+        }
+    }
+
+    totals
+}
+
+/// How many months of spend the chart on the budget alert shows, including this month.
+const SPEND_CHART_MONTHS: i32 = 6;
+
+/// Get the spend of each group for each month from `since`, for charting.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_cloud_cost_series(db: &Database, since: NaiveDate) -> Vec<ChartSeries> {
+    let mut totals: BTreeMap<String, BTreeMap<NaiveDate, f64>> = Default::default();
+    for cost in CloudCosts::get_from_db(db) {
+        if cost.month < since {
+            continue;
+        }
+
+        let group = if cost.owner_group.is_empty() { "unowned".to_string() } else { cost.owner_group.to_string() };
+        *totals.entry(group).or_default().entry(cost.month).or_default() += cost.cost as f64;
+    }
+
+    totals
+        .into_iter()
+        .map(|(name, months)| ChartSeries {
+            name,
+            points: months.into_iter().collect(),
+        })
+        .collect()
+}
+
+/// Alert in the #finance channel on groups whose cloud spend so far this month
+/// is over their budget in our configs. If overages need approval in our configs,
+/// we ask for approval of each group's overage once a month.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn check_cloud_budgets(db: &Database, config: &Config) {
+    let today = Utc::now().date().naive_utc();
+    let spend = get_cloud_costs_by_group_for_month(db, today);
+
+    let mut over: Vec<String> = Default::default();
+    for (group, budget) in config.cloud_budgets.iter() {
+        let total = spend.get(group).cloned().unwrap_or_default();
+        if budget.monthly > 0.0 && total > budget.monthly {
+            let mut line = format!("• *{}* has spent {} of their {} monthly budget", group, format_usd(total), format_usd(budget.monthly));
+
+            if config.approvals.contains_key("budget_overage") {
+                let subject = format!("{}/{}", group, today.format("%Y-%m"));
+                let description = format!("*{}* is over their {} cloud budget for {}", group, format_usd(budget.monthly), today.format("%B %Y"));
+                request_approval(db, &config.approvals, "budget_overage", &subject, &description).await;
+                if let Some(request) = get_approval_request(db, "budget_overage", &subject) {
+                    line += &format!(" | overage {}", request.status);
+                }
+            }
+
+            over.push(line);
+        }
+    }
+
+    if let Some(unowned) = spend.get("") {
+        over.push(format!("• {} of spend has no `{}` label so it is not counted against any budget", format_usd(*unowned), OWNER_LABEL));
+    }
+
+    if over.is_empty() {
+        println!("[cloud_costs] all groups are within their cloud budgets");
+        return;
+    }
+
+    let mut msg = json!({
+        "text": render_message("cloud_costs.over_budget", &json!({ "lines": over })),
+    });
+
+    let first_month = today.year() * 12 + today.month0() as i32 - (SPEND_CHART_MONTHS - 1);
+    let since = NaiveDate::from_ymd(first_month / 12, (first_month % 12) as u32 + 1, 1);
+    attach_chart(
+        &mut msg,
+        "Cloud spend by group (USD)",
+        render_line_chart("Cloud spend by group", "USD", &get_cloud_cost_series(db, since)),
+    );
+
+    notify(db, NotificationPriority::Low, ChatChannel::Finance, "cloud_costs.over_budget", msg).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+
+    use crate::cloud_costs::{aggregate_aws_cur, aws_cur_report_files, check_cloud_budgets, parse_bigquery_rows, refresh_cloud_costs, CloudCosts};
+    use crate::configs::get_configs_from_repo;
+    use crate::db::Database;
+    use crate::utils::authenticate_github_jwt;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_cloud_costs() {
+        let db = Database::new();
+
+        refresh_cloud_costs(&db).await;
+
+        CloudCosts::get_from_db(&db).update_airtable().await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_monday_cron_cloud_budgets() {
+        let db = Database::new();
+        let github = authenticate_github_jwt();
+        let config = get_configs_from_repo(&github).await;
+
+        check_cloud_budgets(&db, &config).await;
+    }
+
+    #[test]
+    fn test_aggregate_aws_cur() {
+        let csv = "bill/BillingPeriodStartDate,lineItem/UsageAccountId,product/ProductName,lineItem/UnblendedCost,lineItem/CurrencyCode,resourceTags/user:owner
+2021-03-01T00:00:00Z,1234,Amazon Elastic Compute Cloud,10.5,USD,eng
+2021-03-01T00:00:00Z,1234,Amazon Elastic Compute Cloud,2.5,USD,eng
+2021-03-01T00:00:00Z,1234,Amazon Simple Storage Service,1.0,USD,
+2021-02-01T00:00:00Z,1234,Amazon Elastic Compute Cloud,100.0,USD,eng
+";
+
+        let costs = aggregate_aws_cur(csv.as_bytes(), NaiveDate::from_ymd(2021, 3, 1)).unwrap();
+        assert_eq!(costs.len(), 2);
+        assert_eq!(costs[0].cost_id, "aws/2021-03/1234/Amazon Elastic Compute Cloud/eng");
+        assert!((costs[0].cost - 13.0).abs() < 0.001);
+        assert_eq!(costs[1].owner_group, "");
+
+        // The headers are not UTF-8.
+        assert!(aggregate_aws_cur(&b"\xff,cost\n"[..], NaiveDate::from_ymd(2021, 3, 1)).is_err());
+    }
+
+    #[test]
+    fn test_aws_cur_report_files() {
+        let manifest = serde_json::json!({
+            "assemblyId": "6ad3c7a5-2b0e-4e25-a1b3-1c7a0f1f2a4d",
+            "reportKeys": [
+                "cur/oxide/20210301-20210401/6ad3c7a5-2b0e-4e25-a1b3-1c7a0f1f2a4d/oxide-1.csv.gz",
+                "cur/oxide/20210301-20210401/6ad3c7a5-2b0e-4e25-a1b3-1c7a0f1f2a4d/oxide-2.csv.gz"
+            ]
+        });
+
+        assert_eq!(
+            aws_cur_report_files(&manifest),
+            vec!["6ad3c7a5-2b0e-4e25-a1b3-1c7a0f1f2a4d/oxide-1.csv.gz", "6ad3c7a5-2b0e-4e25-a1b3-1c7a0f1f2a4d/oxide-2.csv.gz"]
+        );
+        assert!(aws_cur_report_files(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_parse_bigquery_rows() {
+        let resp = serde_json::json!({
+            "rows": [
+                {"f": [{"v": "cio-prod"}, {"v": "Cloud Run"}, {"v": "eng"}, {"v": "42.1"}, {"v": "USD"}]}
+            ]
+        });
+
+        let costs = parse_bigquery_rows(&resp, NaiveDate::from_ymd(2021, 3, 1));
+        assert_eq!(costs.len(), 1);
+        assert_eq!(costs[0].provider, "gcp");
+        assert_eq!(costs[0].project, "cio-prod");
+        assert_eq!(costs[0].owner_group, "eng");
+    }
+}
@@ -0,0 +1,559 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::{Client, Method, Url};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::applicant_status::Status;
+use crate::applicants::Applicant;
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::db::Database;
+use crate::format::format_date;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::rate_limits::check_throttled;
+use crate::schema::{applicants, cohort_events, cohort_members};
+use crate::utils::get_gsuite_token;
+use crate::vcr;
+
+/// The programs we run cohorts for.
+pub static COHORT_PROGRAMS: &[&str] = &["internship", "returnship"];
+
+/// How a member joined a cohort: by applying for one of its roles while applications
+/// were open, or added by hand.
+pub const COHORT_SOURCE_ROLE: &str = "role";
+pub const COHORT_SOURCE_MANUAL: &str = "manual";
+
+/// A cohort of a program, from the `cohorts` section of our configs, by name. Dates are
+/// quoted, ie. `"2021-06-07"`.
+///
+/// ```toml
+/// [cohorts.internship-summer-2021]
+/// program = "internship"
+/// season = "Summer 2021"
+/// roles = ["Engineering Intern"]
+/// applications_open = "2021-01-04"
+/// applications_close = "2021-03-01"
+/// start_date = "2021-06-07"
+/// calendar = "c_hiring@group.calendar.google.com"
+///
+/// [cohorts.internship-summer-2021.milestones]
+/// "Offers out" = "2021-03-29"
+/// "First day" = "2021-06-07"
+/// "Demo day" = "2021-08-20"
+/// ```
+#[derive(Debug, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct CohortConfig {
+    /// One of `COHORT_PROGRAMS`.
+    pub program: String,
+    pub season: String,
+    /// The roles applicants apply for to join the cohort.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// Only applications for the roles from this day on are in the cohort.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applications_open: Option<NaiveDate>,
+    /// Only applications for the roles up to and including this day are in the cohort.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applications_close: Option<NaiveDate>,
+    /// When the cohort starts, unless a member has their own start date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<NaiveDate>,
+    /// The Google Calendar we add the milestones to as all-day events.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub calendar: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub milestones: BTreeMap<String, NaiveDate>,
+}
+
+impl CohortConfig {
+    /// Get the name of the cohort for people, ie. `Internship Summer 2021`.
+    #[instrument]
+    #[inline]
+    pub fn title(&self) -> String {
+        let mut program = self.program.to_string();
+        if let Some(first) = program.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+
+        format!("{} {}", program, self.season).trim().to_string()
+    }
+
+    /// Returns if an application for a role is part of the cohort.
+    #[instrument]
+    #[inline]
+    pub fn includes_application(&self, role: &str, submitted: DateTime<Utc>) -> bool {
+        let day = submitted.date().naive_utc();
+        self.roles.iter().any(|r| r == role) && self.applications_open.map(|d| d <= day).unwrap_or(true) && self.applications_close.map(|d| day <= d).unwrap_or(true)
+    }
+
+    /// Returns if the cohort is still going on, so it is in the weekly report. A cohort
+    /// is done a week after its last milestone or its start date.
+    #[instrument]
+    #[inline]
+    pub fn is_active(&self, today: NaiveDate) -> bool {
+        match self.milestones.values().chain(self.start_date.iter()).max() {
+            Some(last) => today <= *last + Duration::weeks(1),
+            None => true,
+        }
+    }
+}
+
+/// An applicant or hire in a cohort.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "cohort_members"]
+pub struct NewCohortMember {
+    /// The name of the cohort in our configs.
+    pub cohort: String,
+    pub applicant_id: i32,
+    /// Either `COHORT_SOURCE_ROLE` or `COHORT_SOURCE_MANUAL`.
+    pub source: String,
+    /// When the member starts, if it is not the start date of the cohort.
+    pub start_date: Option<NaiveDate>,
+    pub added_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct CohortMember {
+    pub id: i32,
+    pub cohort: String,
+    pub applicant_id: i32,
+    pub source: String,
+    pub start_date: Option<NaiveDate>,
+    pub added_at: DateTime<Utc>,
+}
+
+/// The calendar event we made for a milestone of a cohort, so we update it rather than
+/// making another when the milestone moves.
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[table_name = "cohort_events"]
+pub struct NewCohortEvent {
+    pub cohort: String,
+    pub milestone: String,
+    pub date: NaiveDate,
+    pub calendar_id: String,
+    pub event_id: String,
+    pub synced_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct CohortEvent {
+    pub id: i32,
+    pub cohort: String,
+    pub milestone: String,
+    pub date: NaiveDate,
+    pub calendar_id: String,
+    pub event_id: String,
+    pub synced_at: DateTime<Utc>,
+}
+
+/// Where the members of a cohort are in our hiring process.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CohortReport {
+    pub members: usize,
+    pub interviewing: usize,
+    /// Members we are giving an offer to and have not heard back from.
+    pub offers_out: usize,
+    /// Members who signed their offer or started, as employees or contractors.
+    pub accepted: usize,
+    /// How many accepted members start on each day.
+    pub start_dates: BTreeMap<NaiveDate, usize>,
+}
+
+impl CohortReport {
+    /// Count the statuses and start dates of the members of a cohort. Members without
+    /// their own start date start with the cohort.
+    #[instrument]
+    #[inline]
+    pub fn new(members: &[(Status, Option<NaiveDate>)], cohort_start: Option<NaiveDate>) -> Self {
+        let mut report = CohortReport {
+            members: members.len(),
+            ..Default::default()
+        };
+        for (status, start_date) in members {
+            match status {
+                Status::Interviewing | Status::NextSteps => report.interviewing += 1,
+                Status::GivingOffer => report.offers_out += 1,
+                Status::Onboarding | Status::Hired | Status::Contractor => {
+                    report.accepted += 1;
+                    if let Some(d) = start_date.or(cohort_start) {
+                        *report.start_dates.entry(d).or_insert(0) += 1;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        report
+    }
+}
+
+/// Get the members of a cohort with their applications.
+fn get_cohort_members(db: &Database, cohort: &str) -> Vec<(CohortMember, Applicant)> {
+    let members: Vec<CohortMember> = cohort_members::table
+        .filter(cohort_members::cohort.eq(cohort.to_string()))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the members of cohort {} failed: {}", cohort, e));
+    let ids: Vec<i32> = members.iter().map(|m| m.applicant_id).collect();
+    let applicants: BTreeMap<i32, Applicant> = applicants::dsl::applicants
+        .filter(applicants::dsl::id.eq_any(ids))
+        .load::<Applicant>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the applicants in cohort {} failed: {}", cohort, e))
+        .into_iter()
+        .map(|a| (a.id, a))
+        .collect();
+
+    members.into_iter().filter_map(|m| applicants.get(&m.applicant_id).cloned().map(|a| (m, a))).collect()
+}
+
+/// Add the applicants for the roles of each cohort, while its applications were open.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn sync_cohort_members(db: &Database, config: &Config) {
+    let all: Vec<Applicant> = applicants::dsl::applicants.load(&db.conn()).unwrap_or_else(|e| panic!("getting the applicants failed: {}", e));
+
+    for (name, cohort) in config.cohorts.iter() {
+        let members: Vec<NewCohortMember> = all
+            .iter()
+            .filter(|a| cohort.includes_application(&a.role, a.submitted_time))
+            .map(|a| NewCohortMember {
+                cohort: name.to_string(),
+                applicant_id: a.id,
+                source: COHORT_SOURCE_ROLE.to_string(),
+                start_date: None,
+                added_at: Utc::now(),
+            })
+            .collect();
+        let added = diesel::insert_into(cohort_members::table)
+            .values(&members)
+            .on_conflict((cohort_members::cohort, cohort_members::applicant_id))
+            .do_nothing()
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("adding the members of cohort {} failed: {}", name, e));
+        if added > 0 {
+            println!("[cohorts] added {} applicants to {}", added, name);
+        }
+    }
+}
+
+/// Send a request to the Google Calendar API for the events of a calendar.
+#[instrument(skip(token, body))]
+#[inline]
+async fn calendar_events_api(token: &str, method: Method, calendar: &str, event_id: &str, body: Option<Value>) -> Result<Value, String> {
+    let mut url = Url::parse("https://www.googleapis.com/calendar/v3/calendars").unwrap();
+    {
+        let mut segments = url.path_segments_mut().unwrap();
+        segments.push(calendar).push("events");
+        if !event_id.is_empty() {
+            segments.push(event_id);
+        }
+    }
+
+    let client = Client::new();
+    let mut req = client.request(method.clone(), url).bearer_auth(token);
+    if let Some(body) = body {
+        req = req.json(&body);
+    }
+    let resp = vcr::send(&client, req).await.map_err(|e| e.to_string())?;
+    check_throttled("gsuite", &resp)?;
+
+    let status = resp.status();
+    let body: Value = resp.json().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(format!(
+            "{} calendar event in {} failed: {} {}",
+            method,
+            calendar,
+            status,
+            body["error"]["message"].as_str().unwrap_or_default()
+        ));
+    }
+
+    Ok(body)
+}
+
+/// Get the all-day calendar event for a milestone of a cohort.
+#[instrument]
+#[inline]
+pub fn milestone_event(cohort: &CohortConfig, milestone: &str, date: NaiveDate) -> Value {
+    json!({
+        "summary": format!("{}: {}", cohort.title(), milestone),
+        "description": format!("A milestone of the {} cohort, from our configs.", cohort.title()),
+        "start": { "date": date.format("%Y-%m-%d").to_string() },
+        "end": { "date": date.succ().format("%Y-%m-%d").to_string() },
+        "transparency": "transparent",
+    })
+}
+
+/// Keep a calendar event for each milestone of each cohort: make events for new
+/// milestones, move the ones whose date changed, and delete the ones that were removed.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn sync_cohort_events(db: &Database, config: &Config) {
+    let existing: Vec<CohortEvent> = cohort_events::table.load(&db.conn()).unwrap_or_else(|e| panic!("getting the cohort events failed: {}", e));
+    if existing.is_empty() && config.cohorts.values().all(|c| c.milestones.is_empty()) {
+        return;
+    }
+    let token = get_gsuite_token("").await;
+
+    for event in existing.iter() {
+        let wanted = config.cohorts.get(&event.cohort).and_then(|c| c.milestones.get(&event.milestone).map(|_| &c.calendar));
+        if wanted == Some(&event.calendar_id) {
+            continue;
+        }
+
+        // The milestone was removed or moved to another calendar. If the event is
+        // already gone from the calendar we still forget about it.
+        if let Err(e) = calendar_events_api(token.as_str(), Method::DELETE, &event.calendar_id, &event.event_id, None).await {
+            println!("[cohorts] deleting the event for {} of {} failed: {}", event.milestone, event.cohort, e);
+        }
+        diesel::delete(cohort_events::table.find(event.id))
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("deleting cohort event {} failed: {}", event.id, e));
+        println!("[cohorts] removed the event for {} of {}", event.milestone, event.cohort);
+    }
+
+    for (name, cohort) in config.cohorts.iter() {
+        if cohort.calendar.is_empty() {
+            continue;
+        }
+
+        for (milestone, date) in cohort.milestones.iter() {
+            let current = existing.iter().find(|e| e.cohort == *name && e.milestone == *milestone && e.calendar_id == cohort.calendar);
+            let body = milestone_event(cohort, milestone, *date);
+            let result = match current {
+                Some(e) if e.date == *date => continue,
+                Some(e) => calendar_events_api(token.as_str(), Method::PATCH, &cohort.calendar, &e.event_id, Some(body)).await,
+                None => calendar_events_api(token.as_str(), Method::POST, &cohort.calendar, "", Some(body)).await,
+            };
+            let created = match result {
+                Ok(c) => c,
+                Err(e) => {
+                    println!("[cohorts] syncing the event for {} of {} failed: {}", milestone, name, e);
+                    continue;
+                }
+            };
+
+            let event = NewCohortEvent {
+                cohort: name.to_string(),
+                milestone: milestone.to_string(),
+                date: *date,
+                calendar_id: cohort.calendar.to_string(),
+                event_id: created["id"].as_str().unwrap_or_default().to_string(),
+                synced_at: Utc::now(),
+            };
+            diesel::insert_into(cohort_events::table)
+                .values(&event)
+                .on_conflict((cohort_events::cohort, cohort_events::milestone))
+                .do_update()
+                .set(&event)
+                .execute(&db.conn())
+                .unwrap_or_else(|e| panic!("saving the event for {} of {} failed: {}", milestone, name, e));
+            println!("[cohorts] synced the event for {} of {} on {}", milestone, name, format_date(*date));
+        }
+    }
+}
+
+/// Get the report for a cohort.
+#[instrument(skip(db, cohort))]
+#[inline]
+pub fn get_cohort_report(db: &Database, name: &str, cohort: &CohortConfig) -> CohortReport {
+    let members: Vec<(Status, Option<NaiveDate>)> = get_cohort_members(db, name).into_iter().map(|(m, a)| (a.status, m.start_date)).collect();
+
+    CohortReport::new(&members, cohort.start_date)
+}
+
+/// Get the lines about when the accepted members of a cohort start.
+#[instrument]
+#[inline]
+pub fn format_start_dates(report: &CohortReport) -> Vec<String> {
+    report.start_dates.iter().map(|(d, count)| format!("• {} starting {}", count, format_date(*d))).collect()
+}
+
+/// Post where each cohort that is still going on stands to the hiring channel.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_cohort_reports(db: &Database, config: &Config) {
+    let today = Utc::now().date().naive_utc();
+    for (name, cohort) in config.cohorts.iter().filter(|(_, c)| c.is_active(today)) {
+        let report = get_cohort_report(db, name, cohort);
+        notify(
+            db,
+            NotificationPriority::Low,
+            ChatChannel::Hiring,
+            "cohorts.report",
+            json!({
+                "text": render_message(
+                    "cohorts.report",
+                    &json!({
+                        "title": cohort.title(),
+                        "count": report.members,
+                        "interviewing": report.interviewing,
+                        "offers": report.offers_out,
+                        "accepted": report.accepted,
+                        "lines": format_start_dates(&report),
+                    }),
+                ),
+            }),
+        )
+        .await;
+    }
+}
+
+/// Add an applicant to a cohort by hand, ie. someone who applied for another role.
+/// Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn add_cohort_member(db: &Database, config: &Config, cohort: &str, email: &str) -> Result<String, String> {
+    if !config.cohorts.contains_key(cohort) {
+        return Err(format!(
+            "`{}` is not one of our cohorts, which are {}",
+            cohort,
+            config.cohorts.keys().cloned().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    let applicant = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.trim().to_lowercase()))
+        .order_by(applicants::dsl::submitted_time.desc())
+        .first::<Applicant>(&db.conn())
+        .map_err(|_| format!("{} has not applied", email))?;
+
+    let added = diesel::insert_into(cohort_members::table)
+        .values(&NewCohortMember {
+            cohort: cohort.to_string(),
+            applicant_id: applicant.id,
+            source: COHORT_SOURCE_MANUAL.to_string(),
+            start_date: None,
+            added_at: Utc::now(),
+        })
+        .on_conflict((cohort_members::cohort, cohort_members::applicant_id))
+        .do_nothing()
+        .execute(&db.conn())
+        .map_err(|e| format!("adding {} to {} failed: {}", email, cohort, e))?;
+    if added == 0 {
+        return Ok(format!("{} is already in {}", email, cohort));
+    }
+
+    Ok(format!("added {} to {}", email, cohort))
+}
+
+/// Set when a member of a cohort starts, if it is not the start date of the cohort.
+/// Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub fn set_cohort_start_date(db: &Database, cohort: &str, email: &str, start_date: NaiveDate) -> Result<String, String> {
+    let member = get_cohort_members(db, cohort)
+        .into_iter()
+        .find(|(_, a)| a.email == email.trim().to_lowercase())
+        .map(|(m, _)| m)
+        .ok_or_else(|| format!("{} is not in {}", email, cohort))?;
+
+    diesel::update(cohort_members::table.find(member.id))
+        .set(cohort_members::start_date.eq(Some(start_date)))
+        .execute(&db.conn())
+        .map_err(|e| format!("setting the start date of {} failed: {}", email, e))?;
+
+    Ok(format!("{} starts with {} on {}", email, cohort, format_date(start_date)))
+}
+
+/// Format the report for a cohort for the command line, with its members.
+#[instrument(skip(db, cohort))]
+#[inline]
+pub fn format_cohort(db: &Database, name: &str, cohort: &CohortConfig) -> String {
+    let members = get_cohort_members(db, name);
+    let report = get_cohort_report(db, name, cohort);
+
+    let mut text = format!(
+        "{}: {} members, {} interviewing, {} offers out, {} accepted\n",
+        cohort.title(),
+        report.members,
+        report.interviewing,
+        report.offers_out,
+        report.accepted
+    );
+    for line in format_start_dates(&report) {
+        text += &format!("{}\n", line);
+    }
+    for (member, applicant) in members {
+        let start = member.start_date.map(|d| format!(", starts {}", format_date(d))).unwrap_or_default();
+        text += &format!("    {:<30} {:<20} {}{}\n", applicant.email, applicant.status, member.source, start);
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+    use chrono::{TimeZone, Utc};
+
+    use crate::applicant_status::Status;
+    use crate::cohorts::{milestone_event, CohortConfig, CohortReport};
+
+    fn cohort() -> CohortConfig {
+        toml::from_str(
+            r#"program = "internship"
+season = "Summer 2021"
+roles = ["Engineering Intern"]
+applications_open = "2021-01-04"
+applications_close = "2021-03-01"
+start_date = "2021-06-07"
+calendar = "c_hiring@group.calendar.google.com"
+
+[milestones]
+"Offers out" = "2021-03-29"
+"Demo day" = "2021-08-20"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_includes_application() {
+        let cohort = cohort();
+        assert_eq!(cohort.title(), "Internship Summer 2021");
+        assert!(cohort.includes_application("Engineering Intern", Utc.ymd(2021, 3, 1).and_hms(23, 0, 0)));
+        assert!(!cohort.includes_application("Engineering Intern", Utc.ymd(2021, 3, 2).and_hms(0, 0, 0)));
+        assert!(!cohort.includes_application("Engineering Intern", Utc.ymd(2021, 1, 3).and_hms(12, 0, 0)));
+        assert!(!cohort.includes_application("Firmware Engineer", Utc.ymd(2021, 2, 1).and_hms(12, 0, 0)));
+
+        assert!(cohort.is_active(NaiveDate::from_ymd(2021, 8, 27)));
+        assert!(!cohort.is_active(NaiveDate::from_ymd(2021, 8, 28)));
+    }
+
+    #[test]
+    fn test_cohort_report() {
+        let start = NaiveDate::from_ymd(2021, 6, 7);
+        let late = NaiveDate::from_ymd(2021, 6, 21);
+        let report = CohortReport::new(
+            &[
+                (Status::Hired, None),
+                (Status::Onboarding, Some(late)),
+                (Status::Onboarding, None),
+                (Status::GivingOffer, None),
+                (Status::Interviewing, None),
+                (Status::Declined, None),
+            ],
+            Some(start),
+        );
+        assert_eq!(report.members, 6);
+        assert_eq!(report.interviewing, 1);
+        assert_eq!(report.offers_out, 1);
+        assert_eq!(report.accepted, 3);
+        assert_eq!(report.start_dates.into_iter().collect::<Vec<_>>(), vec![(start, 2), (late, 1)]);
+    }
+
+    #[test]
+    fn test_milestone_event() {
+        let event = milestone_event(&cohort(), "Demo day", NaiveDate::from_ymd(2021, 8, 20));
+        assert_eq!(event["summary"], "Internship Summer 2021: Demo day");
+        assert_eq!(event["start"]["date"], "2021-08-20");
+        assert_eq!(event["end"]["date"], "2021-08-21");
+    }
+}
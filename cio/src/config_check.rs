@@ -0,0 +1,193 @@
+use hubcaps::comments::CommentOptions;
+use hubcaps::statuses::{State, StatusOptions};
+use hubcaps::Github;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::configs::{placeholder_config_variable, try_get_configs_from_repo_at};
+use crate::db::Database;
+use crate::diff::{diff_configs_with_db, format_record_diffs, RecordDiff};
+use crate::utils::{github_api_list, github_api_patch, github_org};
+
+/// The context for the commit status we set on pull requests to the configs repo.
+pub static CONFIG_CHECK_STATUS_CONTEXT: &str = "cio/config-check";
+
+/// Marks our comment on a pull request so we update it rather than adding a new
+/// comment for every push.
+static CONFIG_CHECK_COMMENT_MARKER: &str = "<!-- cio config check -->";
+
+/// The result of checking a version of our configs before it is merged.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigCheck {
+    pub git_ref: String,
+    /// The errors decoding or validating the configs.
+    pub errors: Vec<String>,
+    /// The changes syncing the configs would make to the database, for each table.
+    pub changes: Vec<(String, Vec<RecordDiff>)>,
+}
+
+impl ConfigCheck {
+    /// Returns if the configs can be merged.
+    #[instrument]
+    #[inline]
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// A one line summary for the commit status, ie. `valid, changes 2 users and 1 groups`.
+    #[instrument]
+    #[inline]
+    pub fn summary(&self) -> String {
+        if !self.passed() {
+            return format!("{} errors in the configs", self.errors.len());
+        }
+
+        let changes: Vec<String> = self.changes.iter().filter(|(_, d)| !d.is_empty()).map(|(table, d)| format!("{} {}", d.len(), table)).collect();
+        if changes.is_empty() {
+            return "valid, no changes to sync".to_string();
+        }
+
+        format!("valid, changes {}", changes.join(" and "))
+    }
+}
+
+/// Check our configs at a branch, tag, or commit: decode and validate them, then diff
+/// them with the database to show what merging them would change. Nothing is synced.
+/// The configs are not ours yet, so their `${NAME}` are checked but left unreplaced,
+/// which keeps our environment and secrets out of the comment we post.
+#[instrument(skip(db))]
+#[inline]
+pub async fn check_configs(db: &Database, github: &Github, git_ref: &str) -> ConfigCheck {
+    let mut check = ConfigCheck {
+        git_ref: git_ref.to_string(),
+        ..Default::default()
+    };
+
+    let config = match try_get_configs_from_repo_at(github, git_ref, placeholder_config_variable).await {
+        Ok(c) => c,
+        Err(errors) => {
+            check.errors = errors;
+            return check;
+        }
+    };
+
+    check.errors = config.validate();
+    if check.passed() {
+        check.changes = diff_configs_with_db(db, &config);
+    }
+
+    check
+}
+
+/// Format the result of a config check as markdown, for a pull request comment or
+/// the terminal.
+#[instrument]
+#[inline]
+pub fn format_config_check(check: &ConfigCheck) -> String {
+    let mut text = format!("### Config check for `{}`\n\n", check.git_ref);
+    if !check.passed() {
+        text += "The configs are invalid:\n\n";
+        for e in &check.errors {
+            text += &format!("- {}\n", e);
+        }
+
+        return text;
+    }
+
+    text += &format!("The configs are {}.\n", check.summary());
+    for (table, diffs) in &check.changes {
+        if diffs.is_empty() {
+            continue;
+        }
+
+        text += &format!(
+            "\n<details><summary>{} changes to {}</summary>\n\n```\n{}```\n</details>\n",
+            diffs.len(),
+            table,
+            format_record_diffs(diffs)
+        );
+    }
+
+    text
+}
+
+/// Post the result of a config check to a pull request to the configs repo, as a
+/// commit status on the head of the pull request and a comment on the pull request.
+#[instrument(skip(github))]
+#[inline]
+pub async fn post_config_check(github: &Github, pull_number: u64, sha: &str, check: &ConfigCheck) {
+    let repo = github.repo(github_org(), "configs");
+
+    let state = if check.passed() { State::Success } else { State::Failure };
+    let mut description = check.summary();
+    // GitHub limits the description of a status to 140 characters.
+    description.truncate(140);
+    if let Err(e) = repo
+        .statuses()
+        .create(sha, &StatusOptions::builder(state).context(CONFIG_CHECK_STATUS_CONTEXT.to_string()).description(description).build())
+        .await
+    {
+        println!("[config check] setting the status for {} failed: {}", sha, e);
+    }
+
+    let body = format!("{}\n{}", CONFIG_CHECK_COMMENT_MARKER, format_config_check(check));
+
+    // Update our comment from the last push if there is one.
+    let comments: Vec<Value> = github_api_list(&format!("/repos/{}/configs/issues/{}/comments", github_org(), pull_number)).await.unwrap_or_default();
+    let existing = comments.iter().find(|c| c["body"].as_str().unwrap_or_default().starts_with(CONFIG_CHECK_COMMENT_MARKER));
+    match existing {
+        Some(c) => {
+            let path = format!("/repos/{}/configs/issues/comments/{}", github_org(), c["id"]);
+            if let Err(e) = github_api_patch::<Value>(&path, &json!({ "body": body })).await {
+                println!("[config check] updating the comment on pull request {} failed: {}", pull_number, e);
+            }
+        }
+        None => {
+            if let Err(e) = repo.issue(pull_number).comments().create(&CommentOptions { body }).await {
+                println!("[config check] commenting on pull request {} failed: {}", pull_number, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::config_check::{format_config_check, ConfigCheck};
+    use crate::diff::{FieldDiff, RecordDiff};
+
+    #[test]
+    fn test_format_config_check() {
+        let mut check = ConfigCheck {
+            git_ref: "abc123".to_string(),
+            errors: vec!["user jane is in the group nope, which does not exist".to_string()],
+            changes: vec![],
+        };
+        assert_eq!(check.summary(), "1 errors in the configs");
+        assert_eq!(
+            format_config_check(&check),
+            "### Config check for `abc123`\n\nThe configs are invalid:\n\n- user jane is in the group nope, which does not exist\n"
+        );
+
+        check.errors = vec![];
+        check.changes = vec![("users".to_string(), vec![]), ("groups".to_string(), vec![])];
+        assert_eq!(check.summary(), "valid, no changes to sync");
+
+        check.changes[0].1.push(RecordDiff {
+            key: "jane".to_string(),
+            missing: vec![],
+            fields: vec![FieldDiff {
+                field: "github".to_string(),
+                values: vec![("configs".to_string(), json!("janedoe")), ("db".to_string(), json!("jane"))],
+                winner: "configs".to_string(),
+            }],
+        });
+        assert_eq!(check.summary(), "valid, changes 1 users");
+        let text = format_config_check(&check);
+        assert!(text.contains("The configs are valid, changes 1 users.\n"));
+        assert!(text.contains("<summary>1 changes to users</summary>"));
+        assert!(text.contains("* configs   \"janedoe\""));
+        assert!(!text.contains("changes to groups"));
+    }
+}
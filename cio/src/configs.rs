@@ -2,48 +2,195 @@
 use std::collections::BTreeMap;
 use std::env;
 use std::fs;
-use std::str::from_utf8;
+use std::path::Path;
+use std::str::{from_utf8, FromStr};
 
 use async_trait::async_trait;
 use chrono::naive::NaiveDate;
+use chrono::Weekday;
+use chrono_tz::Tz;
 use clap::ArgMatches;
+use futures_util::future::join_all;
 use futures_util::stream::TryStreamExt;
-use gsuite_api::{Building as GSuiteBuilding, CalendarResource as GSuiteCalendarResource, GSuite, Group as GSuiteGroup};
+use gsuite_api::{Building as GSuiteBuilding, CalendarResource as GSuiteCalendarResource, Group as GSuiteGroup};
 use hubcaps::collaborators::Permissions;
 use hubcaps::Github;
 use macros::db;
+use schemars::schema::{ArrayValidation, InstanceType, Metadata, RootSchema, Schema, SchemaObject};
 use schemars::JsonSchema;
 use sendgrid_api::SendGrid;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tracing::{event, instrument, Level};
 
-use crate::airtable::{AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_BUILDINGS_TABLE, AIRTABLE_CONFERENCE_ROOMS_TABLE, AIRTABLE_EMPLOYEES_TABLE, AIRTABLE_GROUPS_TABLE, AIRTABLE_LINKS_TABLE};
+use crate::airtable::{
+    AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_BASE_ID_RECURITING_APPLICATIONS, AIRTABLE_BUILDINGS_TABLE, AIRTABLE_CONFERENCE_ROOMS_TABLE, AIRTABLE_EMPLOYEES_TABLE, AIRTABLE_GROUPS_TABLE,
+    AIRTABLE_LINKS_TABLE, AIRTABLE_OPEN_ROLES_TABLE, AIRTABLE_REPO_OWNERS_TABLE,
+};
+use crate::api_tokens::{sync_api_tokens, ApiTokenConfig};
+use crate::applicant_emails::ApplicantEmailsConfig;
+use crate::applicant_messages::ApplicantMessagesConfig;
+use crate::applicant_status::Status;
+use crate::applicants::get_sheets_map;
+use crate::approvals::{clear_approval_request, request_approval, ApprovalChainConfig, APPROVAL_OPERATIONS};
+use crate::board_pack::BoardPackConfig;
+use crate::bucket_audits::BucketConfig;
+use crate::business_days::HolidayConfig;
+use crate::carbon::{CarbonConfig, CLOUD_PROVIDERS};
 use crate::certs::{Certificate, Certificates, NewCertificate};
+use crate::chat::{ChatChannel, CHAT_CHANNELS};
+use crate::cloud_costs::CloudBudgetConfig;
+use crate::cohorts::{CohortConfig, COHORT_PROGRAMS};
 use crate::core::UpdateAirtableRecord;
 use crate::db::Database;
+use crate::desks::DesksConfig;
+use crate::docusign::DocuSignConfig;
+use crate::drive_permissions::{DrivePermissionsConfig, DRIVE_ROLES};
+use crate::event_bus::emit_event;
+use crate::forms::FormConfig;
 use crate::gsuite::{update_google_group_settings, update_group_aliases, update_gsuite_building, update_gsuite_calendar_resource};
-use crate::schema::{buildings, conference_rooms, groups, links, users};
-use crate::templates::{generate_terraform_files_for_aws_and_github, generate_terraform_files_for_okta};
-use crate::utils::{get_github_user_public_ssh_keys, get_gsuite_token, github_org, DOMAIN, GSUITE_DOMAIN};
+use crate::headcount::{parse_quarter, sync_headcount_plans, HeadcountPlanConfig};
+use crate::jobs::JOBS;
+use crate::key_rotation::ServiceAccountKeyConfig;
+use crate::lunch::LunchConfig;
+use crate::maintenance::{sync_maintenance_windows, MaintenanceWindowConfig};
+use crate::messages::render_message;
+use crate::models::GithubRepos;
+use crate::notifications::{notify, notify_user, NotificationPriority};
+use crate::offers::{CompBandsConfig, OFFER_OUT_OF_BAND};
+use crate::password_manager::PasswordManagerConfig;
+use crate::policies::PolicyConfig;
+use crate::posture::PosturePolicy;
+use crate::referrals::ReferralConfig;
+use crate::reviews::ReviewsConfig;
+use crate::schedule::parse_timezone;
+use crate::schema::{buildings, conference_rooms, groups, links, open_roles, repo_owners, users};
+use crate::scim::{clear_offboarded_user, record_offboarded_user};
+use crate::slack_channels::{check_slack_channel_name, SlackChannelConfig};
+use crate::slack_user_groups::SlackUserGroupsConfig;
+use crate::take_homes::TakeHomeConfig;
+use crate::templates::{generate_careers_page, generate_codeowners_for_repos, generate_terraform_files_for_aws_and_github, generate_terraform_files_for_okta};
+use crate::trainings::TrainingsConfig;
+use crate::utils::{get_github_user_public_ssh_keys, get_gsuite_token, github_api_get, github_org, gsuite_client, DOMAIN, GSUITE_DOMAIN};
+use crate::welcome::{WelcomeConfig, WELCOME_STEPS};
 
 /// The data type for our configuration files.
 #[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
 pub struct Config {
+    #[serde(default)]
     pub users: BTreeMap<String, UserConfig>,
+    #[serde(default)]
     pub groups: BTreeMap<String, GroupConfig>,
 
+    #[serde(default)]
     pub buildings: BTreeMap<String, BuildingConfig>,
+    #[serde(default)]
     pub resources: BTreeMap<String, ResourceConfig>,
 
+    #[serde(default)]
     pub links: BTreeMap<String, LinkConfig>,
 
-    #[serde(alias = "github-outside-collaborators")]
+    #[serde(default, alias = "github-outside-collaborators")]
     pub github_outside_collaborators: BTreeMap<String, GitHubOutsideCollaboratorsConfig>,
 
+    #[serde(default)]
     pub huddles: BTreeMap<String, HuddleConfig>,
 
     #[serde(default)]
     pub certificates: BTreeMap<String, NewCertificate>,
+
+    #[serde(default)]
+    pub repos: BTreeMap<String, RepoOwnerConfig>,
+
+    #[serde(default)]
+    pub cloud_budgets: BTreeMap<String, CloudBudgetConfig>,
+
+    #[serde(default)]
+    pub roles: BTreeMap<String, OpenRoleConfig>,
+
+    #[serde(default)]
+    pub holidays: BTreeMap<String, HolidayConfig>,
+
+    #[serde(default)]
+    pub drive_permissions: BTreeMap<String, DrivePermissionsConfig>,
+
+    #[serde(default)]
+    pub buckets: BTreeMap<String, BucketConfig>,
+
+    #[serde(default)]
+    pub posture: PosturePolicy,
+
+    #[serde(default)]
+    pub service_account_keys: BTreeMap<String, ServiceAccountKeyConfig>,
+
+    #[serde(default)]
+    pub tokens: BTreeMap<String, ApiTokenConfig>,
+
+    #[serde(default)]
+    pub password_manager: PasswordManagerConfig,
+
+    #[serde(default)]
+    pub slack_user_groups: SlackUserGroupsConfig,
+
+    #[serde(default)]
+    pub slack_channels: BTreeMap<String, SlackChannelConfig>,
+
+    #[serde(default)]
+    pub welcome: WelcomeConfig,
+
+    #[serde(default)]
+    pub forms: BTreeMap<String, FormConfig>,
+
+    #[serde(default)]
+    pub docusign: DocuSignConfig,
+
+    #[serde(default)]
+    pub referrals: ReferralConfig,
+
+    #[serde(default)]
+    pub applicant_messages: ApplicantMessagesConfig,
+
+    #[serde(default)]
+    pub applicant_emails: ApplicantEmailsConfig,
+
+    #[serde(default)]
+    pub take_homes: BTreeMap<String, TakeHomeConfig>,
+
+    #[serde(default)]
+    pub cohorts: BTreeMap<String, CohortConfig>,
+
+    #[serde(default)]
+    pub comp_bands: CompBandsConfig,
+
+    #[serde(default)]
+    pub headcount_plan: HeadcountPlanConfig,
+
+    #[serde(default)]
+    pub reviews: ReviewsConfig,
+
+    #[serde(default)]
+    pub trainings: TrainingsConfig,
+
+    #[serde(default)]
+    pub policies: BTreeMap<String, PolicyConfig>,
+
+    #[serde(default)]
+    pub desks: DesksConfig,
+
+    #[serde(default)]
+    pub lunch: LunchConfig,
+
+    #[serde(default)]
+    pub carbon: CarbonConfig,
+
+    #[serde(default)]
+    pub board_pack: BoardPackConfig,
+
+    #[serde(default)]
+    pub approvals: BTreeMap<String, ApprovalChainConfig>,
+
+    #[serde(default)]
+    pub maintenance: BTreeMap<String, MaintenanceWindowConfig>,
 }
 
 impl Config {
@@ -59,21 +206,435 @@ impl Config {
             }
         };
 
-        let mut contents = String::new();
-        for file in files.iter() {
+        let mut contents: Vec<(String, String)> = Default::default();
+        let mut errors: Vec<String> = Default::default();
+        let mut queue = files;
+        while !queue.is_empty() {
+            let file = queue.remove(0);
+            if contents.iter().any(|(f, _)| *f == file) {
+                // We already read the file, it was included more than once.
+                continue;
+            }
             println!("decoding {}", file);
 
             // Read the file.
-            let body = fs::read_to_string(file).expect("reading the file failed");
+            let body = fs::read_to_string(&file).expect("reading the file failed");
+
+            // Queue the files it includes, which are relative to its directory.
+            let parent = Path::new(&file).parent().map(|p| p.to_path_buf()).unwrap_or_default();
+            for pattern in config_file_includes(&body) {
+                let (dir, name) = match split_config_include(&pattern) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        errors.push(format!("{}: {}", file, e));
+                        continue;
+                    }
+                };
+
+                let mut matched: Vec<String> = fs::read_dir(parent.join(&dir))
+                    .map(|entries| {
+                        entries
+                            .filter_map(|e| e.ok())
+                            .filter(|e| e.path().is_file() && glob_match(&name, &e.file_name().to_string_lossy()))
+                            .map(|e| e.path().to_string_lossy().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if matched.is_empty() {
+                    errors.push(format!("{}: include `{}` does not match any files", file, pattern));
+                }
+                matched.sort();
+                queue.append(&mut matched);
+            }
+
+            contents.push((file, body));
+        }
 
-            // Append the body of the file to the rest of the contents.
-            contents.push_str(&body);
+        if !errors.is_empty() {
+            panic!("decoding the configs failed:\n{}", errors.join("\n"));
         }
 
         // Decode the contents.
-        let config: Config = toml::from_str(&contents).unwrap();
+        match decode_config_files(&contents, lookup_config_variable) {
+            Ok(config) => config,
+            Err(errors) => panic!("decoding the configs failed:\n{}", errors.join("\n")),
+        }
+    }
+
+    /// Validate the references between the different sections of the config.
+    /// Returns a list of the problems, which is empty if the config is valid.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors: Vec<String> = Default::default();
+
+        for (username, user) in self.users.iter() {
+            for group in user.groups.iter() {
+                if !self.groups.contains_key(group) {
+                    errors.push(format!("user `{}` is in group `{}` which does not exist", username, group));
+                }
+            }
+            if let Err(e) = parse_timezone(&user.timezone) {
+                errors.push(format!("user `{}`: {}", username, e));
+            }
+        }
+
+        for (name, resource) in self.resources.iter() {
+            if !resource.building.is_empty() && !self.buildings.contains_key(&resource.building) {
+                errors.push(format!("resource `{}` is in building `{}` which does not exist", name, resource.building));
+            }
+        }
+
+        for (repo, owner) in self.repos.iter() {
+            if !owner.team.is_empty() && !self.groups.contains_key(&owner.team) {
+                errors.push(format!("repo `{}` is owned by team `{}` which does not exist", repo, owner.team));
+            }
+        }
+
+        for group in self.cloud_budgets.keys() {
+            if !self.groups.contains_key(group) {
+                errors.push(format!("cloud budget for group `{}` which does not exist", group));
+            }
+        }
+
+        for (name, holiday) in self.holidays.iter() {
+            if holiday.end_date.map(|end| end < holiday.date).unwrap_or(false) {
+                errors.push(format!("holiday `{}` ends before it starts", name));
+            }
+        }
+
+        for (name, permissions) in self.drive_permissions.iter() {
+            if permissions.drive.is_empty() {
+                errors.push(format!("drive permissions `{}` do not have a shared drive", name));
+            }
+            for (group, role) in permissions.groups.iter() {
+                if !self.groups.contains_key(group) {
+                    errors.push(format!("drive permissions `{}` are for group `{}` which does not exist", name, group));
+                }
+                if !DRIVE_ROLES.contains(&role.as_str()) {
+                    errors.push(format!(
+                        "drive permissions `{}` give group `{}` the role `{}`, which is not one of {}",
+                        name,
+                        group,
+                        role,
+                        DRIVE_ROLES.join(", ")
+                    ));
+                }
+            }
+        }
+
+        for (bucket, expected) in self.buckets.iter() {
+            for group in expected.groups.keys() {
+                if !self.groups.contains_key(group) {
+                    errors.push(format!("bucket `{}` is shared with group `{}` which does not exist", bucket, group));
+                }
+            }
+        }
+
+        for group in self.posture.admin_groups.iter() {
+            if !self.groups.contains_key(group) {
+                errors.push(format!("admin group `{}` in the posture policy does not exist", group));
+            }
+        }
+
+        for group in self.password_manager.groups.keys() {
+            if !self.groups.contains_key(group) {
+                errors.push(format!("group `{}` in the password manager groups does not exist", group));
+            }
+        }
+
+        let mut handles: BTreeMap<&str, &str> = Default::default();
+        for (group, handle) in self.slack_user_groups.groups.iter() {
+            if !self.groups.contains_key(group) {
+                errors.push(format!("group `{}` in the slack user groups does not exist", group));
+            }
+            if let Some(other) = handles.insert(handle, group) {
+                errors.push(format!("groups `{}` and `{}` are both synced to the slack user group `@{}`", other, group, handle));
+            }
+        }
+
+        for name in self.slack_channels.keys() {
+            if let Err(e) = check_slack_channel_name(name) {
+                errors.push(e);
+            }
+        }
+
+        for (step, links) in self.welcome.links.iter() {
+            if !WELCOME_STEPS.iter().any(|(s, _)| s == step) {
+                errors.push(format!(
+                    "welcome links for `{}` which is not one of {}",
+                    step,
+                    WELCOME_STEPS.iter().map(|(s, _)| *s).collect::<Vec<_>>().join(", ")
+                ));
+            }
+            for link in links.iter() {
+                if !self.links.contains_key(link) {
+                    errors.push(format!("welcome links for `{}` include `{}` which is not a link", step, link));
+                }
+            }
+        }
+
+        for (name, form) in self.forms.iter() {
+            if form.chat_channel().is_none() {
+                errors.push(format!(
+                    "form `{}` posts to `{}` which is not one of {}",
+                    name,
+                    form.channel,
+                    CHAT_CHANNELS.iter().map(|c| c.env_name().to_lowercase()).collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+
+        for (name, template_id) in self.docusign.templates.iter() {
+            if template_id.trim().is_empty() {
+                errors.push(format!("docusign template `{}` does not have a template ID", name));
+            }
+        }
+        if self.docusign.remind_after_days < 0 {
+            errors.push(format!("docusign `remind_after_days` is {}, it cannot be negative", self.docusign.remind_after_days));
+        }
+
+        if self.referrals.bonus < 0.0 {
+            errors.push(format!("referrals `bonus` is {}, it cannot be negative", self.referrals.bonus));
+        }
+        if self.referrals.pay_after_days < 0 {
+            errors.push(format!("referrals `pay_after_days` is {}, it cannot be negative", self.referrals.pay_after_days));
+        }
+
+        for mailbox in self.applicant_messages.mailboxes.iter() {
+            if !self.users.contains_key(mailbox) {
+                errors.push(format!("applicant messages mailbox `{}` is not a user", mailbox));
+            }
+        }
+        if self.applicant_messages.alias.contains('@') || self.applicant_messages.alias.contains('+') {
+            errors.push(format!(
+                "applicant messages `alias` is `{}`, it should only be the part before the `@`, ie. `careers`",
+                self.applicant_messages.alias
+            ));
+        }
+
+        if !self.applicant_emails.mailbox.is_empty() && !self.users.contains_key(&self.applicant_emails.mailbox) {
+            errors.push(format!("applicant emails mailbox `{}` is not a user", self.applicant_emails.mailbox));
+        }
+        for (name, template) in self.applicant_emails.templates.iter() {
+            for stage in template.stages.iter() {
+                if Status::from_str(stage).is_err() {
+                    errors.push(format!("applicant email template `{}` is sent at stage `{}` which is not a status", name, stage));
+                }
+            }
+            for (part, value) in vec![("subject", &template.subject), ("body", &template.body)] {
+                if tera::Tera::default().add_raw_template(part, value).is_err() {
+                    errors.push(format!("applicant email template `{}` has a {} that is not a valid template", name, part));
+                }
+            }
+        }
+
+        for (name, take_home) in self.take_homes.iter() {
+            if take_home.repo.is_empty() {
+                errors.push(format!("take-home `{}` does not have a `repo`", name));
+            }
+            if take_home.days < 0 {
+                errors.push(format!("take-home `{}` has `days` of {}, it cannot be negative", name, take_home.days));
+            }
+            for grader in take_home.graders.iter() {
+                if !self.users.contains_key(grader) {
+                    errors.push(format!("take-home `{}` is graded by `{}` who is not a user", name, grader));
+                }
+            }
+        }
+
+        for (name, cohort) in self.cohorts.iter() {
+            if !COHORT_PROGRAMS.contains(&cohort.program.as_str()) {
+                errors.push(format!("cohort `{}` is for program `{}` which is not one of {}", name, cohort.program, COHORT_PROGRAMS.join(", ")));
+            }
+            for role in cohort.roles.iter() {
+                if !self.roles.contains_key(role) {
+                    errors.push(format!("cohort `{}` is for role `{}` which does not exist", name, role));
+                }
+            }
+            if let (Some(open), Some(close)) = (cohort.applications_open, cohort.applications_close) {
+                if close < open {
+                    errors.push(format!("cohort `{}` closes applications before it opens them", name));
+                }
+            }
+            if !cohort.milestones.is_empty() && cohort.calendar.is_empty() {
+                errors.push(format!("cohort `{}` has milestones but no `calendar` to add them to", name));
+            }
+        }
+
+        if !self.comp_bands.secret.is_empty() {
+            let parts: Vec<&str> = self.comp_bands.secret.split('/').collect();
+            if parts.len() != 4 || parts[0] != "projects" || parts[2] != "secrets" {
+                errors.push(format!("comp bands secret `{}` is not formatted as `projects/{{project}}/secrets/{{secret}}`", self.comp_bands.secret));
+            }
+            if !self.approvals.contains_key(OFFER_OUT_OF_BAND) {
+                errors.push(format!("comp bands need approvals for `{}` to approve offers outside of their band", OFFER_OUT_OF_BAND));
+            }
+        }
+
+        for (team, quarters) in self.headcount_plan.iter() {
+            if !self.groups.contains_key(team) {
+                errors.push(format!("headcount plan for team `{}` which does not exist", team));
+            }
+            for (quarter, planned) in quarters.iter() {
+                if parse_quarter(quarter).is_none() {
+                    errors.push(format!("headcount plan for `{}` has quarter `{}` which is not formatted like `2021-Q2`", team, quarter));
+                }
+                if *planned < 0 {
+                    errors.push(format!("headcount plan for `{}` in `{}` is {}, it cannot be negative", team, quarter, planned));
+                }
+            }
+        }
+
+        for (person, manager) in self.reviews.managers.iter() {
+            if !self.users.contains_key(person) {
+                errors.push(format!("reviews manager for `{}` who is not a user", person));
+            }
+            if !self.users.contains_key(manager) {
+                errors.push(format!("reviews for `{}` are by manager `{}` who is not a user", person, manager));
+            }
+        }
+        if self.reviews.drive.is_empty() != self.reviews.folder.is_empty() {
+            errors.push("reviews need both a `drive` and a `folder` to archive to".to_string());
+        }
+        for days in self.reviews.reminder_days.iter().filter(|d| **d < 0) {
+            errors.push(format!("reviews `reminder_days` has {}, it cannot be negative", days));
+        }
+
+        for (name, training) in self.trainings.required.iter() {
+            if training.courses.is_empty() {
+                errors.push(format!("training `{}` has no `courses` to find it in the vendor's reports by", name));
+            }
+            for group in training.groups.iter() {
+                if !self.groups.contains_key(group) {
+                    errors.push(format!("training `{}` is for group `{}` which does not exist", name, group));
+                }
+            }
+            if training.due_days < 0 {
+                errors.push(format!("training `{}` has `due_days` of {}, it cannot be negative", name, training.due_days));
+            }
+            if let Some(every) = training.every_days.filter(|d| *d <= 0) {
+                errors.push(format!("training `{}` has `every_days` of {}, it must be at least 1", name, every));
+            }
+        }
+
+        for (name, policy) in self.policies.iter() {
+            if policy.file_id.is_empty() {
+                errors.push(format!("policy `{}` does not have a `file_id` for its document in Drive", name));
+            }
+            for group in policy.groups.iter() {
+                if !self.groups.contains_key(group) {
+                    errors.push(format!("policy `{}` is for group `{}` which does not exist", name, group));
+                }
+            }
+        }
+
+        for (building, capacity) in self.desks.capacity.iter() {
+            if !self.buildings.values().any(|b| b.name == *building) {
+                errors.push(format!("desk capacity for building `{}` which does not exist", building));
+            }
+            if *capacity < 0 {
+                errors.push(format!("desk capacity for `{}` is {}, it cannot be negative", building, capacity));
+            }
+        }
+
+        for day in self.lunch.office_days.iter().filter(|d| d.parse::<Weekday>().is_err()) {
+            errors.push(format!("lunch `office_days` has `{}` which is not a day of the week", day));
+        }
+        if !self.lunch.office_days.is_empty() && self.lunch.caterer.is_empty() {
+            errors.push("lunch has `office_days` but no `caterer` to email the orders to".to_string());
+        }
+        if self.lunch.close_hour() > 23 {
+            errors.push(format!("lunch `close_hour` is {}, it must be an hour of the day from 0 to 23", self.lunch.close_hour()));
+        }
+        for (name, item) in self.lunch.menu.iter().filter(|(_, i)| i.price < 0.0) {
+            errors.push(format!("lunch menu item `{}` has a price of {}, it cannot be negative", name, item.price));
+        }
+
+        for (provider, factor) in self.carbon.cloud_kg_co2e_per_usd.iter() {
+            if !CLOUD_PROVIDERS.contains(&provider.as_str()) {
+                errors.push(format!("carbon factor for cloud provider `{}` which is not one of {}", provider, CLOUD_PROVIDERS.join(", ")));
+            }
+            if *factor < 0.0 {
+                errors.push(format!("carbon factor for `{}` is {}, it cannot be negative", provider, factor));
+            }
+        }
+
+        if self.board_pack.drive.is_empty() != self.board_pack.folder.is_empty() {
+            errors.push("board pack needs both a `drive` and a `folder` to save to".to_string());
+        }
+
+        for (name, token) in self.tokens.iter() {
+            if !self.users.contains_key(&token.owner) {
+                errors.push(format!("token `{}` is owned by `{}` who is not a user", name, token.owner));
+            }
+        }
+
+        for (operation, chain) in self.approvals.iter() {
+            if !APPROVAL_OPERATIONS.contains(&operation.as_str()) {
+                errors.push(format!("approvals for `{}` which is not one of {}", operation, APPROVAL_OPERATIONS.join(", ")));
+            }
+            if chain.approvers.is_empty() == chain.group.is_empty() {
+                errors.push(format!("approvals for `{}` must have either `approvers` or a `group`", operation));
+            }
+            for approver in chain.approvers.iter() {
+                if !self.users.contains_key(approver) {
+                    errors.push(format!("approvals for `{}` need `{}` who is not a user", operation, approver));
+                }
+            }
+            if !chain.group.is_empty() {
+                let members = self.users.values().filter(|u| u.groups.contains(&chain.group)).count();
+                if !self.groups.contains_key(&chain.group) {
+                    errors.push(format!("approvals for `{}` are from group `{}` which does not exist", operation, chain.group));
+                } else if chain.required.max(1) > members {
+                    errors.push(format!(
+                        "approvals for `{}` need {} people but group `{}` only has {}",
+                        operation,
+                        chain.required.max(1),
+                        chain.group,
+                        members
+                    ));
+                }
+            }
+        }
+
+        for (name, window) in self.maintenance.iter() {
+            if window.end <= window.start {
+                errors.push(format!("maintenance window `{}` ends before it starts", name));
+            }
+            for job in window.jobs.iter() {
+                if !JOBS.iter().any(|(j, _)| j == job) {
+                    errors.push(format!("maintenance window `{}` pauses job `{}` which does not exist", name, job));
+                }
+            }
+        }
+
+        for (name, key) in self.service_account_keys.iter() {
+            let parts: Vec<&str> = key.secret.split('/').collect();
+            if parts.len() != 4 || parts[0] != "projects" || parts[2] != "secrets" {
+                errors.push(format!(
+                    "service account key `{}` has secret `{}` which is not formatted as `projects/{{project}}/secrets/{{secret}}`",
+                    name, key.secret
+                ));
+            }
+        }
+
+        let sheets = get_sheets_map();
+        for (name, role) in self.roles.iter() {
+            if !role.team.is_empty() && !self.groups.contains_key(&role.team) {
+                errors.push(format!("role `{}` is on team `{}` which does not exist", name, role.team));
+            }
+            if !role.hiring_manager.is_empty() && !self.users.contains_key(&role.hiring_manager) {
+                errors.push(format!("role `{}` has hiring manager `{}` who is not a user", name, role.hiring_manager));
+            }
+            // We only sync applicants from the sheets we know about, so applications
+            // for the role would never show up.
+            if role.open && !sheets.values().any(|id| *id == role.sheet_id) {
+                errors.push(format!("role `{}` collects applications in sheet `{}` which we do not sync applicants from", name, role.sheet_id));
+            }
+        }
 
-        config
+        errors
     }
 }
 
@@ -82,6 +643,7 @@ impl Config {
     new_struct_name = "User",
     airtable_base_id = "AIRTABLE_BASE_ID_DIRECTORY",
     airtable_table = "AIRTABLE_EMPLOYEES_TABLE",
+    source = "configs",
     match_on = {
         "username" = "String",
     },
@@ -155,6 +717,11 @@ pub struct UserConfig {
 
     #[serde(default, rename = "type", skip_serializing_if = "String::is_empty")]
     pub typev: String,
+
+    /// The timezone the user works in, ie. `America/Los_Angeles`, so the reminders
+    /// we send them arrive during their day. Defaults to UTC.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub timezone: String,
 }
 
 pub mod null_date_format {
@@ -297,6 +864,13 @@ impl User {
         format!("{} {}", self.first_name, self.last_name)
     }
 
+    /// The timezone the user works in, UTC if it is not set or invalid.
+    #[instrument]
+    #[inline]
+    pub fn local_timezone(&self) -> Tz {
+        parse_timezone(&self.timezone).unwrap_or(Tz::UTC)
+    }
+
     #[instrument]
     #[inline]
     pub fn is_system_account(&self) -> bool {
@@ -370,6 +944,25 @@ xoxo,
             .await;
     }
 
+    /// Get the message we send the admin in Slack with the onboarding steps that are
+    /// left for a new user after their account is created.
+    #[instrument]
+    #[inline]
+    pub fn as_onboarding_slack_msg(&self) -> Value {
+        let mut steps = vec![format!("• Make sure they got the email about their account at {}", self.recovery_email)];
+        if self.github.is_empty() {
+            steps.push("• Get their GitHub handle and add it to `configs/users.toml`".to_string());
+        }
+        steps.push("• Add them to the matrix chat".to_string());
+        if !self.is_consultant() {
+            steps.push(format!("• Check their start date of {} is right", self.start_date.format("%B %-d, %Y")));
+        }
+
+        json!({
+            "text": format!("Created the account for *{} {}* ({}). Left to do:\n{}", self.first_name, self.last_name, self.email(), steps.join("\n")),
+        })
+    }
+
     /// Send an email to the new user about their account.
     #[instrument]
     #[inline]
@@ -521,6 +1114,7 @@ impl UpdateAirtableRecord<User> for User {
     new_struct_name = "Group",
     airtable_base_id = "AIRTABLE_BASE_ID_DIRECTORY",
     airtable_table = "AIRTABLE_GROUPS_TABLE",
+    source = "configs",
     match_on = {
         "name" = "String",
     },
@@ -680,6 +1274,7 @@ impl UpdateAirtableRecord<Group> for Group {
     new_struct_name = "Building",
     airtable_base_id = "AIRTABLE_BASE_ID_DIRECTORY",
     airtable_table = "AIRTABLE_BUILDINGS_TABLE",
+    source = "configs",
     match_on = {
         "name" = "String",
     },
@@ -738,6 +1333,7 @@ impl UpdateAirtableRecord<Building> for Building {
     new_struct_name = "ConferenceRoom",
     airtable_base_id = "AIRTABLE_BASE_ID_DIRECTORY",
     airtable_table = "AIRTABLE_CONFERENCE_ROOMS_TABLE",
+    source = "configs",
     match_on = {
         "name" = "String",
     },
@@ -789,6 +1385,7 @@ impl UpdateAirtableRecord<ConferenceRoom> for ConferenceRoom {
     new_struct_name = "Link",
     airtable_base_id = "AIRTABLE_BASE_ID_DIRECTORY",
     airtable_table = "AIRTABLE_LINKS_TABLE",
+    source = "configs",
     match_on = {
         "name" = "String",
     },
@@ -815,13 +1412,102 @@ impl UpdateAirtableRecord<Link> for Link {
     async fn update_airtable_record(&mut self, _record: Link) {}
 }
 
-/// The data type for GitHub outside collaborators to repositories.
-#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
-pub struct GitHubOutsideCollaboratorsConfig {
-    pub description: String,
-    pub users: Vec<String>,
-    pub repos: Vec<String>,
-    pub perm: String,
+/// The data type for the owners of a GitHub repository. These get turned into
+/// `CODEOWNERS` files in each repository.
+#[db {
+    new_struct_name = "RepoOwner",
+    airtable_base_id = "AIRTABLE_BASE_ID_DIRECTORY",
+    airtable_table = "AIRTABLE_REPO_OWNERS_TABLE",
+    source = "configs",
+    match_on = {
+        "repo" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "repo_owners"]
+pub struct RepoOwnerConfig {
+    /// repo will not be used in config files, it is the key of the table.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub repo: String,
+    /// The group that owns the repository. This is also the name of the GitHub team.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub team: String,
+    /// Additional GitHub handles of individual owners.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub owners: Vec<String>,
+}
+
+/// Implement updating the Airtable record for a RepoOwner.
+#[async_trait]
+impl UpdateAirtableRecord<RepoOwner> for RepoOwner {
+    #[instrument]
+    #[inline]
+    async fn update_airtable_record(&mut self, _record: RepoOwner) {}
+}
+
+/// The data type for a role we are hiring for. These get turned into the careers
+/// page on the website.
+#[db {
+    new_struct_name = "OpenRole",
+    airtable_base_id = "AIRTABLE_BASE_ID_RECURITING_APPLICATIONS",
+    airtable_table = "AIRTABLE_OPEN_ROLES_TABLE",
+    source = "configs",
+    match_on = {
+        "name" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "open_roles"]
+pub struct OpenRoleConfig {
+    /// name will not be used in config files, it is the key of the table.
+    /// This is also the role applicants are shown as applying for.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// The group the role is on.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub team: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub location: String,
+    /// A short description of the role in markdown.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub job_description_url: String,
+    /// The Google Form applicants fill out for the role.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub application_form_url: String,
+    /// The Google Sheet the responses to the application form go to.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sheet_id: String,
+    /// Closed roles are kept so the applicants for them still have a role, but
+    /// are left off the careers page.
+    #[serde(default = "default_open")]
+    pub open: bool,
+    /// The username of the person who hires for the role. They are the only ones told
+    /// the results of background checks for applicants to the role.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hiring_manager: String,
+}
+
+fn default_open() -> bool {
+    true
+}
+
+/// Implement updating the Airtable record for an OpenRole.
+#[async_trait]
+impl UpdateAirtableRecord<OpenRole> for OpenRole {
+    #[instrument]
+    #[inline]
+    async fn update_airtable_record(&mut self, _record: OpenRole) {}
+}
+
+/// The data type for GitHub outside collaborators to repositories.
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct GitHubOutsideCollaboratorsConfig {
+    pub description: String,
+    pub users: Vec<String>,
+    pub repos: Vec<String>,
+    pub perm: String,
 }
 
 /// The data type for a huddle meeting that syncs with Airtable and notes in GitHub.
@@ -844,28 +1530,432 @@ pub struct HuddleConfig {
 #[instrument]
 #[inline]
 pub async fn get_configs_from_repo(github: &Github) -> Config {
+    match try_get_configs_from_repo(github).await {
+        Ok(config) => config,
+        Err(errors) => panic!("decoding the configs failed:\n{}", errors.join("\n")),
+    }
+}
+
+/// Get the configs from the GitHub repo, returning the errors for each file if they
+/// fail to decode. The files at the top of the `configs` directory are read along with
+/// the files they include, which are relative to the `configs` directory.
+#[instrument]
+#[inline]
+pub async fn try_get_configs_from_repo(github: &Github) -> Result<Config, Vec<String>> {
+    let r = github.repo(github_org(), "configs").get().await.unwrap();
+
+    try_get_configs_from_repo_at(github, &r.default_branch, lookup_config_variable).await
+}
+
+/// Get the configs from the GitHub repo at a branch, tag, or commit, ie. the head of a
+/// pull request, returning the errors for each file if they fail to decode. The
+/// `${NAME}` in the configs are replaced with what `lookup` returns for them.
+#[instrument(skip(lookup))]
+#[inline]
+pub async fn try_get_configs_from_repo_at(github: &Github, git_ref: &str, lookup: fn(&str) -> Result<String, String>) -> Result<Config, Vec<String>> {
     let repo = github.repo(github_org(), "configs");
-    let r = repo.get().await.unwrap();
     let repo_contents = repo.content();
 
-    let files = repo_contents.iter("/configs/", &r.default_branch).try_collect::<Vec<hubcaps::content::DirectoryItem>>().await.unwrap();
+    let files = repo_contents
+        .iter("/configs/", git_ref)
+        .try_collect::<Vec<hubcaps::content::DirectoryItem>>()
+        .await
+        .map_err(|e| vec![format!("listing the configs at `{}` failed: {}", git_ref, e)])?;
+    let mut paths: Vec<String> = files.into_iter().filter(|f| f._type == "file").map(|f| f.path).collect();
+
+    let mut file_contents: Vec<(String, String)> = Default::default();
+    let mut errors: Vec<String> = Default::default();
+    while !paths.is_empty() {
+        // Get the contents of the files at the same time.
+        let contents = join_all(paths.iter().map(|path| repo_contents.file(&format!("/{}", path), git_ref))).await;
+
+        let mut included: Vec<String> = Default::default();
+        for (path, contents) in paths.iter().zip(contents) {
+            let name = path.trim_start_matches("configs/").to_string();
+            println!("decoding {}", name);
+            let decoded = from_utf8(&contents.unwrap().content).unwrap().to_string();
+
+            for pattern in config_file_includes(&decoded) {
+                let (dir, glob) = match split_config_include(&pattern) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        errors.push(format!("{}: {}", name, e));
+                        continue;
+                    }
+                };
+
+                let items = repo_contents
+                    .iter(&format!("/configs/{}", dir), git_ref)
+                    .try_collect::<Vec<hubcaps::content::DirectoryItem>>()
+                    .await
+                    .unwrap_or_default();
+                let mut matched: Vec<String> = items.into_iter().filter(|i| i._type == "file" && glob_match(&glob, &i.name)).map(|i| i.path).collect();
+                if matched.is_empty() {
+                    errors.push(format!("{}: include `{}` does not match any files", name, pattern));
+                }
+                included.append(&mut matched);
+            }
+
+            file_contents.push((name, decoded));
+        }
 
-    let mut file_contents = String::new();
-    for file in files {
-        println!("decoding {}", file.name);
-        // Get the contents of the file.
-        let contents = repo_contents.file(&format!("/{}", file.path), &r.default_branch).await.unwrap();
+        // Files included more than once are only read once.
+        included.sort();
+        included.dedup();
+        paths = included.into_iter().filter(|p| !file_contents.iter().any(|(f, _)| p.trim_start_matches("configs/") == f)).collect();
+    }
 
-        let decoded = from_utf8(&contents.content).unwrap().trim().to_string();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    decode_config_files(&file_contents, lookup)
+}
 
-        // Append the body of the file to the rest of the contents.
-        file_contents.push_str(&"\n");
-        file_contents.push_str(&decoded);
+/// Generate the JSON Schema for a config file, for editors and for checking the configs
+/// repo before merging. Unlike decoding, which ignores fields it does not know, the
+/// schema does not allow them so typos in field names are caught.
+#[instrument]
+#[inline]
+pub fn config_json_schema() -> RootSchema {
+    let mut schema = schemars::schema_for!(Config);
+
+    // The sections we handle outside of serde.
+    let root = schema.schema.object();
+    if let Some(collaborators) = root.properties.get("github_outside_collaborators").cloned() {
+        root.properties.insert("github-outside-collaborators".to_string(), collaborators);
+    }
+    root.properties.insert(
+        "include".to_string(),
+        Schema::Object(SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some("The other config files to read, relative to the configs directory, ie. `groups/*.toml`.".to_string()),
+                ..Default::default()
+            })),
+            instance_type: Some(InstanceType::Array.into()),
+            array: Some(Box::new(ArrayValidation {
+                items: Some(Schema::Object(InstanceType::String.into()).into()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }),
+    );
+
+    // Objects with fixed fields do not allow any others. Maps already say what
+    // their values are with `additionalProperties`.
+    let objects = std::iter::once(&mut schema.schema).chain(schema.definitions.values_mut().filter_map(|d| match d {
+        Schema::Object(o) => Some(o),
+        _ => None,
+    }));
+    for o in objects {
+        if let Some(object) = &mut o.object {
+            if !object.properties.is_empty() && object.additional_properties.is_none() {
+                object.additional_properties = Some(Box::new(Schema::Bool(false)));
+            }
+        }
     }
 
-    let config: Config = toml::from_str(&file_contents).unwrap();
+    schema
+}
 
-    config
+/// Get the patterns in the `include` list of a config file, ie. `include = ["groups/*.toml"]`.
+/// A file that fails to parse has no includes, the error is returned when it is decoded.
+#[instrument(skip(body))]
+#[inline]
+pub fn config_file_includes(body: &str) -> Vec<String> {
+    match toml::from_str::<toml::value::Table>(body) {
+        Ok(t) => t
+            .get("include")
+            .and_then(|i| i.as_array())
+            .map(|i| i.iter().filter_map(|p| p.as_str()).map(|p| p.to_string()).collect())
+            .unwrap_or_default(),
+        Err(_) => vec![],
+    }
+}
+
+/// Split an include into its directory and the pattern for the file names in the
+/// directory, ie. `groups/*.toml` into `groups` and `*.toml`. Wildcards are only
+/// supported in file names.
+#[instrument]
+#[inline]
+pub fn split_config_include(pattern: &str) -> Result<(String, String), String> {
+    let (dir, name) = pattern.rsplit_once('/').unwrap_or(("", pattern));
+    if dir.contains('*') || dir.contains('?') {
+        return Err(format!("include `{}` has a wildcard in a directory, wildcards are only supported in file names", pattern));
+    }
+    if dir.split('/').any(|d| d == "..") || pattern.starts_with('/') {
+        return Err(format!("include `{}` must be inside the configs directory", pattern));
+    }
+
+    Ok((dir.to_string(), name.to_string()))
+}
+
+/// Match a file name against a pattern where `*` matches any number of characters
+/// and `?` matches one character.
+#[instrument]
+#[inline]
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+
+    // The positions to go back to if the rest does not match after a `*`.
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// The prefix of the environment variables a config can use, so a config can not read
+/// the credentials we run with, ie. `${AIRTABLE_API_KEY}`.
+pub static CONFIG_VARIABLE_PREFIX: &str = "CIO_CONFIG_";
+
+/// The sections of the configs that we publish, as short links and on the careers page.
+/// These can not use `${NAME}` at all.
+pub static PUBLISHED_CONFIG_SECTIONS: &[&str] = &["links", "roles"];
+
+/// Check the name in a `${NAME}` is one a config may use: an environment variable that
+/// starts with `CIO_CONFIG_`, or for `${secret:NAME}` a file directly in `CIO_SECRETS_DIR`.
+#[instrument]
+#[inline]
+pub fn check_config_variable(name: &str) -> Result<(), String> {
+    match name.strip_prefix("secret:") {
+        Some(secret) => {
+            if secret.is_empty() || secret.starts_with('.') || !secret.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.') {
+                return Err(format!("`{}` is not the name of a secret, use letters, numbers, `_`, `-`, and `.`", secret));
+            }
+        }
+        None => {
+            if !name.starts_with(CONFIG_VARIABLE_PREFIX) {
+                return Err(format!(
+                    "the environment variable `{}` can not be used in the configs, only ones starting with `{}` can",
+                    name, CONFIG_VARIABLE_PREFIX
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Look up the value for a `${NAME}` in a config, which is the environment variable,
+/// or for `${secret:NAME}` the contents of the file `NAME` in `CIO_SECRETS_DIR`
+/// (`/var/run/secrets/cio` by default), so secrets are not committed to the configs.
+#[instrument]
+#[inline]
+pub fn lookup_config_variable(name: &str) -> Result<String, String> {
+    match name.strip_prefix("secret:") {
+        Some(secret) => {
+            let dir = env::var("CIO_SECRETS_DIR").unwrap_or_else(|_| "/var/run/secrets/cio".to_string());
+            fs::read_to_string(Path::new(&dir).join(secret))
+                .map(|s| s.trim_end().to_string())
+                .map_err(|_| format!("the secret `{}` does not exist", secret))
+        }
+        None => env::var(name).map_err(|_| format!("the environment variable `{}` is not set", name)),
+    }
+}
+
+/// Leave a `${NAME}` in a config as it is. This is for checking configs we do not trust,
+/// ie. from a pull request, so the names are still checked but the values of our
+/// environment variables and secrets are never read into them.
+#[instrument]
+#[inline]
+pub fn placeholder_config_variable(name: &str) -> Result<String, String> {
+    Ok(format!("${{{}}}", name))
+}
+
+/// Replace the `${NAME}` and `${secret:NAME}` in a string from a config. Use `$${` for
+/// a literal `${`. Names a config may not use are errors, whatever `lookup` returns.
+#[instrument(skip(lookup))]
+#[inline]
+pub fn interpolate_config_string(s: &str, lookup: &dyn Fn(&str) -> Result<String, String>) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find("${") {
+        if rest[..i].ends_with('$') {
+            result.push_str(&rest[..i - 1]);
+            result.push_str("${");
+            rest = &rest[i + 2..];
+            continue;
+        }
+
+        result.push_str(&rest[..i]);
+        let end = rest[i..].find('}').ok_or_else(|| format!("`{}` has a `${{` without a closing `}}`", s))?;
+        let name = &rest[i + 2..i + end];
+        check_config_variable(name)?;
+        result.push_str(&lookup(name)?);
+        rest = &rest[i + end + 1..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+/// Interpolate every string in a value from a config, collecting an error with the key
+/// for each string that fails.
+#[instrument(skip(value, lookup))]
+#[inline]
+fn interpolate_config_value(value: &mut toml::Value, key: &str, lookup: &dyn Fn(&str) -> Result<String, String>, errors: &mut Vec<String>) {
+    match value {
+        toml::Value::String(s) => match interpolate_config_string(s, lookup) {
+            Ok(i) => *s = i,
+            Err(e) => errors.push(format!("{}: {}", key, e)),
+        },
+        toml::Value::Array(a) => {
+            for (i, v) in a.iter_mut().enumerate() {
+                interpolate_config_value(v, &format!("{}[{}]", key, i), lookup, errors);
+            }
+        }
+        toml::Value::Table(t) => {
+            for (k, v) in t.iter_mut() {
+                interpolate_config_value(v, &format!("{}.{}", key, k), lookup, errors);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Parse a single config file into its sections, ie. `users` and `groups`, replacing
+/// any `${NAME}` in its strings with what `lookup` returns, which the published sections
+/// can not use. Each entry in a section is decoded on its own so errors name the file
+/// and the key of the entry, ie. `users.toml: users.jess: missing field `username``.
+#[instrument(skip(body, lookup))]
+#[inline]
+pub fn parse_config_file(file: &str, body: &str, lookup: fn(&str) -> Result<String, String>) -> Result<toml::value::Table, Vec<String>> {
+    let mut sections: toml::value::Table = toml::from_str(body).map_err(|e| vec![format!("{}: {}", file, e)])?;
+
+    let mut errors: Vec<String> = Default::default();
+
+    // The includes were read with the file.
+    if let Some(include) = sections.remove("include") {
+        if !include.as_array().map(|i| i.iter().all(|p| p.is_str())).unwrap_or(false) {
+            errors.push(format!("{}: include: must be a list of paths, ie. `include = [\"groups/*.toml\"]`", file));
+        }
+    }
+
+    for (section, entries) in sections.iter_mut() {
+        let mut interpolate_errors: Vec<String> = Default::default();
+        if PUBLISHED_CONFIG_SECTIONS.contains(&section.as_str()) {
+            let published = |name: &str| Err(format!("`${{{}}}` can not be used in `{}`, since we publish them", name, section));
+            interpolate_config_value(entries, section, &published, &mut interpolate_errors);
+        } else {
+            interpolate_config_value(entries, section, &lookup, &mut interpolate_errors);
+        }
+        errors.extend(interpolate_errors.into_iter().map(|e| format!("{}: {}", file, e)));
+    }
+
+    for (section, entries) in &sections {
+        let entries = match entries.as_table() {
+            Some(e) => e,
+            None => {
+                errors.push(format!("{}: {}: must be a table of entries, ie. `[{}.name]`", file, section, section));
+                continue;
+            }
+        };
+
+        for (key, value) in entries {
+            // Decode the entry into an otherwise empty config.
+            let mut entry = toml::value::Table::new();
+            entry.insert(key.to_string(), value.clone());
+            let mut config = toml::value::Table::new();
+            config.insert(section.to_string(), toml::Value::Table(entry));
+
+            if let Err(e) = toml::Value::Table(config).try_into::<Config>() {
+                errors.push(format!("{}: {}.{}: {}", file, section, key, e));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(sections)
+}
+
+/// Merge the sections of the config files. A section can be split across files, but
+/// an entry can only be defined in one file, so defining `users.jess` in two files is
+/// an error rather than one silently replacing the other.
+#[instrument(skip(files))]
+#[inline]
+pub fn merge_config_files(files: Vec<(String, toml::value::Table)>) -> Result<toml::value::Table, Vec<String>> {
+    let mut merged = toml::value::Table::new();
+    let mut defined_in: BTreeMap<String, String> = Default::default();
+    let mut errors: Vec<String> = Default::default();
+
+    for (file, sections) in files {
+        for (section, entries) in sections {
+            let merged_entries = merged.entry(section.to_string()).or_insert_with(|| toml::Value::Table(Default::default())).as_table_mut().unwrap();
+
+            // We checked the sections are tables when parsing the file.
+            for (key, value) in entries.as_table().cloned().unwrap_or_default() {
+                let path = format!("{}.{}", section, key);
+                if let Some(other) = defined_in.get(&path) {
+                    errors.push(format!("{}: {}: already defined in {}", file, path, other));
+                    continue;
+                }
+
+                defined_in.insert(path, file.to_string());
+                merged_entries.insert(key, value);
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(merged)
+}
+
+/// Decode our config from the name and contents of each config file. The files are
+/// parsed on their own and then merged, so the errors for every file are returned
+/// at once with the file and key they are for.
+#[instrument(skip(files, lookup))]
+#[inline]
+pub fn decode_config_files(files: &[(String, String)], lookup: fn(&str) -> Result<String, String>) -> Result<Config, Vec<String>> {
+    let mut parsed: Vec<(String, toml::value::Table)> = Default::default();
+    let mut errors: Vec<String> = Default::default();
+    for (file, body) in files {
+        match parse_config_file(file, body, lookup) {
+            Ok(sections) => parsed.push((file.to_string(), sections)),
+            Err(e) => errors.extend(e),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    toml::Value::Table(merge_config_files(parsed)?).try_into().map_err(|e| vec![format!("configs: {}", e)])
+}
+
+/// Get the SHA of the latest commit on the default branch of the configs repo.
+/// This is cheaper than getting the configs so we can poll it for changes.
+#[instrument]
+#[inline]
+pub async fn get_configs_sha() -> String {
+    match github_api_get::<Value>(&format!("/repos/{}/configs/commits/HEAD", github_org())).await {
+        Ok(commit) => commit["sha"].as_str().unwrap_or_default().to_string(),
+        Err(e) => {
+            println!("getting the latest commit for the configs repo failed: {}", e);
+            String::new()
+        }
+    }
 }
 
 /// Sync GitHub outside collaborators with our configs.
@@ -906,10 +1996,41 @@ pub async fn sync_github_outside_collaborators(github: &Github, outside_collabor
     }
 }
 
+/// Sync a single user from our configs with our database. If the user is new,
+/// we send them an email about setting up their account.
+#[instrument(skip(db))]
+#[inline]
+pub async fn sync_user(db: &Database, mut user: UserConfig) {
+    user.expand().await;
+
+    // Check if we already have the new user in the database.
+    let existing = User::get_from_db(db, user.username.to_string());
+
+    // Update or create the user in the database.
+    let new_user = user.upsert(db).await;
+
+    if existing.is_none() {
+        // The user did not already exist in the database.
+        // We should send them an email about setting up their account.
+        println!("sending email to new user: {}", new_user.username);
+        if new_user.is_consultant() {
+            new_user.send_email_new_consultant().await;
+        } else {
+            new_user.send_email_new_user().await;
+        }
+
+        // Let the admin know what is left to do for onboarding.
+        let admin = env::var("GADMIN_SUBJECT").unwrap_or_default();
+        if !admin.is_empty() {
+            notify_user(db, &admin, new_user.as_onboarding_slack_msg()).await;
+        }
+    }
+}
+
 /// Sync our users with our database and then update Airtable from the database.
 #[instrument(skip(db))]
 #[inline]
-pub async fn sync_users(db: &Database, github: &Github, users: BTreeMap<String, UserConfig>) {
+pub async fn sync_users(db: &Database, github: &Github, users: BTreeMap<String, UserConfig>, approvals: &BTreeMap<String, ApprovalChainConfig>) {
     // Generate the terraform files for teams.
     generate_terraform_files_for_aws_and_github(github, users.clone()).await;
 
@@ -921,37 +2042,40 @@ pub async fn sync_users(db: &Database, github: &Github, users: BTreeMap<String,
         user_map.insert(u.username.to_string(), u);
     }
     // Sync users.
-    for (_, mut user) in users {
-        user.expand().await;
-
-        // Check if we already have the new user in the database.
-        let existing = User::get_from_db(db, user.username.to_string());
-
-        // Update or create the user in the database.
-        let new_user = user.upsert(db).await;
-
-        if existing.is_none() {
-            // The user did not already exist in the database.
-            // We should send them an email about setting up their account.
-            println!("sending email to new user: {}", new_user.username);
-            if new_user.is_consultant() {
-                new_user.send_email_new_consultant().await;
-            } else {
-                new_user.send_email_new_user().await;
-            }
-        }
+    for (_, user) in users {
+        let username = user.username.to_string();
+        sync_user(db, user).await;
+        clear_offboarded_user(db, &username);
+        // Ask again if they are ever removed from our configs.
+        clear_approval_request(db, "offboarding", &username);
 
         // Remove the user from the BTreeMap.
-        user_map.remove(&user.username);
+        user_map.remove(&username);
     }
     // Remove any users that should no longer be in the database.
     // This is found by the remaining users that are in the map since we removed
     // the existing repos from the map above.
     for (username, user) in user_map {
+        if !request_approval(db, approvals, "offboarding", &username, &format!("offboard {} ({})", user.full_name(), user.email())).await {
+            println!("not deleting user {} from the database until offboarding them is approved", username);
+            continue;
+        }
         println!("deleting user {} from the database", username);
 
-        // Delete the user from the database and Airtable.
+        // Keep them as deactivated for SCIM clients, then delete the user from the
+        // database and Airtable.
+        record_offboarded_user(db, &user);
         user.delete(db).await;
+
+        emit_event(
+            "user.offboarded",
+            json!({
+                "username": user.username,
+                "email": user.email(),
+                "name": user.full_name(),
+            }),
+        )
+        .await;
     }
     event!(Level::INFO, "updated configs users in the database");
 
@@ -967,7 +2091,7 @@ pub async fn sync_buildings(db: &Database, buildings: BTreeMap<String, BuildingC
     // Initialize the GSuite client.
     let gsuite_customer = env::var("GADMIN_ACCOUNT_ID").unwrap();
     let token = get_gsuite_token("").await;
-    let gsuite = GSuite::new(&gsuite_customer, GSUITE_DOMAIN, token);
+    let gsuite = gsuite_client(&gsuite_customer, token);
 
     // Get the existing google buildings.
     let gsuite_buildings = gsuite.list_buildings().await.unwrap();
@@ -1065,7 +2189,7 @@ pub async fn sync_conference_rooms(db: &Database, conference_rooms: BTreeMap<Str
     // Initialize the GSuite client.
     let gsuite_customer = env::var("GADMIN_ACCOUNT_ID").unwrap();
     let token = get_gsuite_token("").await;
-    let gsuite = GSuite::new(&gsuite_customer, GSUITE_DOMAIN, token);
+    let gsuite = gsuite_client(&gsuite_customer, token);
 
     // Get the existing GSuite calendar resources.
     let g_suite_calendar_resources = gsuite.list_calendar_resources().await.unwrap();
@@ -1165,7 +2289,7 @@ pub async fn sync_groups(db: &Database, groups: BTreeMap<String, GroupConfig>) {
     // Initialize the GSuite client.
     let gsuite_customer = env::var("GADMIN_ACCOUNT_ID").unwrap();
     let token = get_gsuite_token("").await;
-    let gsuite = GSuite::new(&gsuite_customer, GSUITE_DOMAIN, token);
+    let gsuite = gsuite_client(&gsuite_customer, token);
 
     // Get the GSuite groups.
     let gsuite_groups = gsuite.list_groups().await.unwrap();
@@ -1365,37 +2489,170 @@ pub async fn sync_certificates(db: &Database, github: &Github, certificates: BTr
     Certificates::get_from_db(db).update_airtable().await;
 }
 
-#[instrument]
+/// Sync our repo owners with our database and then update Airtable from the database.
+#[instrument(skip(db))]
+#[inline]
+pub async fn sync_repo_owners(db: &Database, github: &Github, repos: BTreeMap<String, RepoOwnerConfig>) {
+    // Get all the repo owners.
+    let db_repo_owners = RepoOwners::get_from_db(db);
+    // Create a BTreeMap
+    let mut repo_owner_map: BTreeMap<String, RepoOwner> = Default::default();
+    for r in db_repo_owners {
+        repo_owner_map.insert(r.repo.to_string(), r);
+    }
+    // Sync repo owners.
+    for (repo, mut repo_owner) in repos {
+        repo_owner.repo = repo.to_string();
+
+        repo_owner.upsert(db).await;
+
+        // Remove the repo owner from the BTreeMap.
+        repo_owner_map.remove(&repo_owner.repo);
+    }
+    // Remove any repo owners that should no longer be in the database.
+    // This is found by the remaining repo owners that are in the map since we removed
+    // the existing ones from the map above.
+    for (_, repo_owner) in repo_owner_map {
+        repo_owner.delete(db).await;
+    }
+    event!(Level::INFO, "updated configs repo owners in the database");
+
+    // Update repo owners in airtable.
+    RepoOwners::get_from_db(db).update_airtable().await;
+
+    // Generate the CODEOWNERS files for the repos.
+    generate_codeowners_for_repos(github, db).await;
+
+    // Let folks know about any repos without a real owner.
+    check_repo_ownership(db).await;
+}
+
+/// Sync our roles with our database and then update Airtable from the database.
+#[instrument(skip(db))]
+#[inline]
+pub async fn sync_open_roles(db: &Database, github: &Github, roles: BTreeMap<String, OpenRoleConfig>) {
+    // Get all the roles.
+    let db_roles = OpenRoles::get_from_db(db);
+    // Create a BTreeMap
+    let mut role_map: BTreeMap<String, OpenRole> = Default::default();
+    for r in db_roles {
+        role_map.insert(r.name.to_string(), r);
+    }
+    // Sync roles.
+    for (name, mut role) in roles {
+        role.name = name.to_string();
+
+        role.upsert(db).await;
+
+        // Remove the role from the BTreeMap.
+        role_map.remove(&role.name);
+    }
+    // Remove any roles that should no longer be in the database.
+    // This is found by the remaining roles that are in the map since we removed
+    // the existing ones from the map above.
+    for (_, role) in role_map {
+        role.delete(db).await;
+    }
+    event!(Level::INFO, "updated configs roles in the database");
+
+    // Update roles in airtable.
+    OpenRoles::get_from_db(db).update_airtable().await;
+
+    // Generate the careers page for the website.
+    generate_careers_page(github, db).await;
+}
+
+/// Alert in Slack when a repository has no owner or when the team that owns it
+/// has no active members.
+#[instrument(skip(db))]
 #[inline]
-pub async fn refresh_db_configs_and_airtable(github: &Github) {
-    let configs = get_configs_from_repo(github).await;
+pub async fn check_repo_ownership(db: &Database) {
+    let users: Vec<User> = Users::get_from_db(db).into();
+
+    let mut problems: Vec<String> = Default::default();
+    for repo in GithubRepos::get_from_db(db) {
+        if repo.archived || repo.fork {
+            continue;
+        }
+
+        match RepoOwner::get_from_db(db, repo.name.to_string()) {
+            None => problems.push(format!("• `{}` has no owner", repo.name)),
+            Some(owner) => {
+                if owner.team.is_empty() {
+                    if owner.owners.is_empty() {
+                        problems.push(format!("• `{}` has no owner", repo.name));
+                    }
+                    continue;
+                }
 
-    // Initialize our database.
-    let db = Database::new();
+                let has_members = users.iter().any(|u| !u.is_system_account() && u.groups.contains(&owner.team));
+                if !has_members {
+                    problems.push(format!("• `{}` is owned by {}@ which has no active members", repo.name, owner.team));
+                }
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Engineering,
+        "repo_owners.missing",
+        json!({
+            "text": render_message("repo_owners.missing", &json!({ "lines": problems })),
+        }),
+    )
+    .await;
+}
 
+/// Sync everything in our configs to the database and Airtable. The configs are the
+/// ones the caller already decoded and validated, so a sync never reads them again.
+#[instrument(skip(db, configs))]
+#[inline]
+pub async fn refresh_db_configs_and_airtable(db: &Database, github: &Github, configs: Config) {
     // Sync buildings.
     // Syncing buildings must happen before we sync conference rooms.
-    sync_buildings(&db, configs.buildings).await;
+    sync_buildings(db, configs.buildings).await;
 
     // Sync conference rooms.
-    sync_conference_rooms(&db, configs.resources).await;
+    sync_conference_rooms(db, configs.resources).await;
 
     // Sync groups.
     // Syncing groups must happen before we sync the users.
-    sync_groups(&db, configs.groups).await;
+    sync_groups(db, configs.groups).await;
 
     // Sync users.
-    sync_users(&db, github, configs.users).await;
+    sync_users(db, github, configs.users, &configs.approvals).await;
 
     // Sync okta users and group from the database.
     // Do this after we update the users and groups in the database.
-    generate_terraform_files_for_okta(github, &db).await;
+    generate_terraform_files_for_okta(github, db).await;
 
     // Sync links.
-    sync_links(&db, configs.links).await;
+    sync_links(db, configs.links).await;
 
     // Sync certificates.
-    sync_certificates(&db, github, configs.certificates).await;
+    sync_certificates(db, github, configs.certificates).await;
+
+    // Sync the API tokens we rely on.
+    sync_api_tokens(db, configs.tokens).await;
+
+    // Sync the maintenance windows, so webhooky knows about them too.
+    sync_maintenance_windows(db, &configs.maintenance);
+
+    // Sync repo owners.
+    // Syncing repo owners must happen after we sync the users and groups.
+    sync_repo_owners(db, github, configs.repos).await;
+
+    // Sync the headcount plan, for the monthly finance rollup.
+    sync_headcount_plans(db, &configs.headcount_plan);
+
+    // Sync roles.
+    sync_open_roles(db, github, configs.roles).await;
 
     // Sync github outside collaborators.
     sync_github_outside_collaborators(github, configs.github_outside_collaborators).await;
@@ -1403,13 +2660,421 @@ pub async fn refresh_db_configs_and_airtable(github: &Github) {
 
 #[cfg(test)]
 mod tests {
-    use crate::configs::refresh_db_configs_and_airtable;
+    use crate::api_tokens::ApiTokenConfig;
+    use crate::applicant_emails::ApplicantEmailTemplate;
+    use crate::approvals::ApprovalChainConfig;
+    use crate::configs::{
+        config_file_includes, config_json_schema, decode_config_files, get_configs_from_repo, glob_match, interpolate_config_string, lookup_config_variable, placeholder_config_variable,
+        refresh_db_configs_and_airtable, split_config_include, Config, GroupConfig, OpenRoleConfig, RepoOwnerConfig, UserConfig,
+    };
+    use crate::db::Database;
+    use crate::forms::FormConfig;
+    use crate::key_rotation::ServiceAccountKeyConfig;
+    use crate::take_homes::TakeHomeConfig;
     use crate::utils::authenticate_github_jwt;
 
     #[ignore]
     #[tokio::test(threaded_scheduler)]
     async fn test_cron_configs() {
         let github = authenticate_github_jwt();
-        refresh_db_configs_and_airtable(&github).await;
+        let configs = get_configs_from_repo(&github).await;
+        refresh_db_configs_and_airtable(&Database::new(), &github, configs).await;
+    }
+
+    #[test]
+    fn test_decode_config_files() {
+        let users = r#"[users.jane]
+first_name = "Jane"
+last_name = "Doe"
+username = "jane"
+"#;
+        let groups = r#"[groups.eng]
+name = "eng"
+"#;
+        let files = vec![("users.toml".to_string(), users.to_string()), ("groups.toml".to_string(), groups.to_string())];
+        let config = decode_config_files(&files, lookup_config_variable).unwrap();
+        assert_eq!(config.users["jane"].first_name, "Jane");
+        assert_eq!(config.groups["eng"].name, "eng");
+
+        // Errors name the file and the entry, and every file is checked.
+        let files = vec![
+            ("users.toml".to_string(), users.replace("username = \"jane\"\n", "")),
+            ("groups.toml".to_string(), "[groups.eng]\nname = \n".to_string()),
+        ];
+        let errors = decode_config_files(&files, lookup_config_variable).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("users.toml: users.jane: missing field `username`"), "{}", errors[0]);
+        assert!(errors[1].starts_with("groups.toml: ") && errors[1].contains("line 2"), "{}", errors[1]);
+
+        // An entry can only be defined in one file.
+        let files = vec![
+            ("users.toml".to_string(), users.to_string()),
+            ("more-users.toml".to_string(), users.to_string()),
+            ("groups.toml".to_string(), groups.to_string()),
+        ];
+        assert_eq!(
+            decode_config_files(&files, lookup_config_variable).unwrap_err(),
+            vec!["more-users.toml: users.jane: already defined in users.toml".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_config_json_schema() {
+        let schema = serde_json::to_value(config_json_schema()).unwrap();
+
+        // Every section is optional in a file and the keys we handle outside of serde are allowed.
+        assert!(schema.get("required").is_none());
+        assert_eq!(schema["properties"]["include"]["type"], "array");
+        assert_eq!(schema["properties"]["github-outside-collaborators"], schema["properties"]["github_outside_collaborators"]);
+
+        // Typos in fields are not allowed, but any key for the entries of a section is.
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(schema["definitions"]["UserConfig"]["additionalProperties"], false);
+        assert_eq!(schema["properties"]["users"]["additionalProperties"]["$ref"], "#/definitions/UserConfig");
+    }
+
+    #[test]
+    fn test_config_includes() {
+        assert_eq!(
+            config_file_includes("include = [\"groups/*.toml\", \"huddles.toml\"]\n[users]\n"),
+            vec!["groups/*.toml", "huddles.toml"]
+        );
+        assert!(config_file_includes("[users]\n").is_empty());
+
+        assert_eq!(split_config_include("groups/*.toml").unwrap(), ("groups".to_string(), "*.toml".to_string()));
+        assert_eq!(split_config_include("huddles.toml").unwrap(), ("".to_string(), "huddles.toml".to_string()));
+        assert!(split_config_include("*/eng.toml").is_err());
+        assert!(split_config_include("../secrets.toml").is_err());
+
+        assert!(glob_match("*.toml", "eng.toml"));
+        assert!(glob_match("eng-*.toml", "eng-hardware.toml"));
+        assert!(glob_match("team?.toml", "team1.toml"));
+        assert!(glob_match("*", "anything"));
+        assert!(!glob_match("*.toml", "eng.toml.bak"));
+        assert!(!glob_match("team?.toml", "team12.toml"));
+    }
+
+    #[test]
+    fn test_interpolate_config_string() {
+        let lookup = |name: &str| match name {
+            "CIO_CONFIG_DOMAIN" | "AIRTABLE_API_KEY" => Ok("oxide.computer".to_string()),
+            "secret:zoom_key" => Ok("hunter2".to_string()),
+            _ => Err(format!("`{}` is not set", name)),
+        };
+
+        assert_eq!(interpolate_config_string("jane@${CIO_CONFIG_DOMAIN}", &lookup).unwrap(), "jane@oxide.computer");
+        assert_eq!(interpolate_config_string("${secret:zoom_key}", &lookup).unwrap(), "hunter2");
+        assert_eq!(interpolate_config_string("costs $${CIO_CONFIG_DOMAIN} and $5", &lookup).unwrap(), "costs ${CIO_CONFIG_DOMAIN} and $5");
+        assert_eq!(interpolate_config_string("${CIO_CONFIG_NOPE}", &lookup).unwrap_err(), "`CIO_CONFIG_NOPE` is not set");
+        assert!(interpolate_config_string("${CIO_CONFIG_DOMAIN", &lookup).is_err());
+
+        // Only our own variables and plain secret names can be used.
+        assert_eq!(
+            interpolate_config_string("${AIRTABLE_API_KEY}", &lookup).unwrap_err(),
+            "the environment variable `AIRTABLE_API_KEY` can not be used in the configs, only ones starting with `CIO_CONFIG_` can"
+        );
+        assert!(interpolate_config_string("${secret:../github/key.pem}", &lookup).is_err());
+        assert!(interpolate_config_string("${secret:}", &lookup).is_err());
+
+        // The sections we publish can not use them at all.
+        let files = vec![(
+            "links.toml".to_string(),
+            "[links.zoom]\nname = \"zoom\"\nlink = \"${secret:zoom_key}\"\ndescription = \"Zoom\"\n".to_string(),
+        )];
+        assert_eq!(
+            decode_config_files(&files, lookup_config_variable).unwrap_err(),
+            vec!["links.toml: links.zoom.link: `${secret:zoom_key}` can not be used in `links`, since we publish them".to_string()]
+        );
+
+        // Checking a pull request leaves the values out, but still checks the names.
+        let files = vec![(
+            "users.toml".to_string(),
+            "[users.jane]\nfirst_name = \"Jane\"\nlast_name = \"Doe\"\nusername = \"jane\"\nrecovery_email = \"${CIO_CONFIG_RECOVERY_EMAIL}\"\n".to_string(),
+        )];
+        std::env::set_var("CIO_CONFIG_RECOVERY_EMAIL", "jane@example.com");
+        let config = decode_config_files(&files, placeholder_config_variable).unwrap();
+        assert_eq!(config.users["jane"].recovery_email, "${CIO_CONFIG_RECOVERY_EMAIL}");
+        let files = vec![("users.toml".to_string(), files[0].1.replace("CIO_CONFIG_RECOVERY_EMAIL", "AIRTABLE_API_KEY"))];
+        assert!(decode_config_files(&files, placeholder_config_variable).is_err());
+    }
+
+    #[test]
+    fn test_config_validate() {
+        let mut config = Config::default();
+        let user: UserConfig = toml::from_str(
+            r#"first_name = "Jane"
+last_name = "Doe"
+username = "jane"
+groups = ["eng", "nope"]
+"#,
+        )
+        .unwrap();
+        config.users.insert(user.username.to_string(), user);
+        let group: GroupConfig = toml::from_str(r#"name = "eng""#).unwrap();
+        config.groups.insert(group.name.to_string(), group);
+        config.repos.insert(
+            "cio".to_string(),
+            RepoOwnerConfig {
+                repo: "cio".to_string(),
+                team: "eng".to_string(),
+                owners: vec![],
+            },
+        );
+
+        let role: OpenRoleConfig = toml::from_str(
+            r#"team = "eng"
+sheet_id = "1FHA-otHCGwe5fCRpcl89MWI7GHiFfN3EWjO6K943rYA"
+"#,
+        )
+        .unwrap();
+        assert!(role.open);
+        config.roles.insert("Engineering".to_string(), role.clone());
+        config.roles.insert(
+            "Firmware".to_string(),
+            OpenRoleConfig {
+                sheet_id: "1nope".to_string(),
+                ..role.clone()
+            },
+        );
+        config.roles.insert(
+            "Closed".to_string(),
+            OpenRoleConfig {
+                sheet_id: "1nope".to_string(),
+                open: false,
+                hiring_manager: "nobody".to_string(),
+                ..role
+            },
+        );
+
+        config.posture.admin_groups = vec!["eng".to_string(), "it".to_string()];
+        config.password_manager.groups.insert("eng".to_string(), vec!["Engineering".to_string()]);
+        config.password_manager.groups.insert("finance".to_string(), vec!["Finance".to_string()]);
+        config.slack_user_groups.groups.insert("eng".to_string(), "eng".to_string());
+        config.slack_user_groups.groups.insert("oncall".to_string(), "eng".to_string());
+        config.slack_channels.insert("eng".to_string(), Default::default());
+        config.slack_channels.insert("Eng Oncall".to_string(), Default::default());
+        config.welcome.links.insert("tools".to_string(), vec!["rfd".to_string()]);
+        config.welcome.links.insert("day_2".to_string(), vec![]);
+        config.forms.insert(
+            "laptop-request".to_string(),
+            FormConfig {
+                form_id: "1FAIpQLSlaptop".to_string(),
+                channel: "it".to_string(),
+            },
+        );
+        config.docusign.templates.insert("offer_letter".to_string(), " ".to_string());
+        config.docusign.remind_after_days = -1;
+        config.referrals.pay_after_days = -30;
+        config.applicant_messages.mailboxes = vec!["jane".to_string(), "careers".to_string()];
+        config.applicant_messages.alias = "careers@oxide.computer".to_string();
+        config.applicant_emails.mailbox = "careers".to_string();
+        config.applicant_emails.templates.insert(
+            "availability_request".to_string(),
+            ApplicantEmailTemplate {
+                stages: vec!["Maybe".to_string()],
+                subject: "Next steps".to_string(),
+                body: "Dear {{ name".to_string(),
+                ..Default::default()
+            },
+        );
+        config.take_homes.insert(
+            "firmware".to_string(),
+            TakeHomeConfig {
+                repo: "".to_string(),
+                days: 7,
+                graders: vec!["jane".to_string(), "nobody".to_string()],
+            },
+        );
+        config.cohorts = toml::from_str(
+            r#"[internship-summer-2021]
+program = "internships"
+season = "Summer 2021"
+roles = ["Intern"]
+applications_open = "2021-03-01"
+applications_close = "2021-01-04"
+
+[internship-summer-2021.milestones]
+"First day" = "2021-06-07"
+"#,
+        )
+        .unwrap();
+        config.service_account_keys.insert(
+            "gadmin".to_string(),
+            ServiceAccountKeyConfig {
+                secret: "projects/oxide-cio/secrets/gadmin-credentials".to_string(),
+                ..Default::default()
+            },
+        );
+        config.service_account_keys.insert(
+            "backups".to_string(),
+            ServiceAccountKeyConfig {
+                secret: "gadmin-credentials".to_string(),
+                ..Default::default()
+            },
+        );
+        config.tokens.insert(
+            "github".to_string(),
+            ApiTokenConfig {
+                service: "GitHub".to_string(),
+                owner: "jane".to_string(),
+                ..Default::default()
+            },
+        );
+        config.tokens.insert(
+            "slack".to_string(),
+            ApiTokenConfig {
+                service: "Slack".to_string(),
+                owner: "nobody".to_string(),
+                ..Default::default()
+            },
+        );
+
+        config.approvals.insert(
+            "offboarding".to_string(),
+            ApprovalChainConfig {
+                approvers: vec!["jane".to_string(), "sam".to_string()],
+                ..Default::default()
+            },
+        );
+        config.approvals.insert(
+            "budget_overage".to_string(),
+            ApprovalChainConfig {
+                group: "eng".to_string(),
+                required: 2,
+                ..Default::default()
+            },
+        );
+        config.approvals.insert("dns".to_string(), Default::default());
+        config.comp_bands.secret = "comp-bands".to_string();
+        config.reviews = toml::from_str(
+            r#"drive = "People"
+reminder_days = [7, -1]
+
+[managers]
+jane = "nobody"
+"#,
+        )
+        .unwrap();
+        config.trainings = toml::from_str(
+            r#"[required.security_awareness]
+title = "Security awareness"
+groups = ["design"]
+due_days = -1
+every_days = 0
+"#,
+        )
+        .unwrap();
+        config.policies = toml::from_str(
+            r#"[acceptable_use]
+title = "Acceptable use policy"
+file_id = ""
+groups = ["design"]
+"#,
+        )
+        .unwrap();
+        config.desks = toml::from_str(
+            r#"[capacity]
+SF = -1
+"#,
+        )
+        .unwrap();
+        config.lunch = toml::from_str(
+            r#"office_days = ["tuesday", "thursdays"]
+close_hour = 24
+
+[menu.salad]
+name = "Salad"
+price = -11
+"#,
+        )
+        .unwrap();
+        config.carbon = toml::from_str(
+            r#"[cloud_kg_co2e_per_usd]
+azure = -0.5
+"#,
+        )
+        .unwrap();
+        config.board_pack.folder = "Board/Packs".to_string();
+        config.headcount_plan = toml::from_str(
+            r#"[eng]
+"2021-Q2" = 3
+"Q3" = -1
+
+[design]
+"2021-Q2" = 1
+"#,
+        )
+        .unwrap();
+        config.maintenance = toml::from_str(
+            r#"[okta_migration]
+start = "2021-05-08T20:00:00Z"
+end = "2021-05-08T16:00:00Z"
+jobs = ["configs", "okta"]
+"#,
+        )
+        .unwrap();
+
+        let errors = config.validate();
+        assert_eq!(
+            errors,
+            vec![
+                "user `jane` is in group `nope` which does not exist".to_string(),
+                "admin group `it` in the posture policy does not exist".to_string(),
+                "group `finance` in the password manager groups does not exist".to_string(),
+                "group `oncall` in the slack user groups does not exist".to_string(),
+                "groups `eng` and `oncall` are both synced to the slack user group `@eng`".to_string(),
+                "slack channel `Eng Oncall` can only have lowercase letters, numbers, hyphens, and underscores in its name".to_string(),
+                "welcome links for `day_2` which is not one of welcome, tools, check_in".to_string(),
+                "welcome links for `tools` include `rfd` which is not a link".to_string(),
+                "form `laptop-request` posts to `it` which is not one of engineering, finance, hiring, office, public_relations, security".to_string(),
+                "docusign template `offer_letter` does not have a template ID".to_string(),
+                "docusign `remind_after_days` is -1, it cannot be negative".to_string(),
+                "referrals `pay_after_days` is -30, it cannot be negative".to_string(),
+                "applicant messages mailbox `careers` is not a user".to_string(),
+                "applicant messages `alias` is `careers@oxide.computer`, it should only be the part before the `@`, ie. `careers`".to_string(),
+                "applicant emails mailbox `careers` is not a user".to_string(),
+                "applicant email template `availability_request` is sent at stage `Maybe` which is not a status".to_string(),
+                "applicant email template `availability_request` has a body that is not a valid template".to_string(),
+                "take-home `firmware` does not have a `repo`".to_string(),
+                "take-home `firmware` is graded by `nobody` who is not a user".to_string(),
+                "cohort `internship-summer-2021` is for program `internships` which is not one of internship, returnship".to_string(),
+                "cohort `internship-summer-2021` is for role `Intern` which does not exist".to_string(),
+                "cohort `internship-summer-2021` closes applications before it opens them".to_string(),
+                "cohort `internship-summer-2021` has milestones but no `calendar` to add them to".to_string(),
+                "comp bands secret `comp-bands` is not formatted as `projects/{project}/secrets/{secret}`".to_string(),
+                "comp bands need approvals for `offer_out_of_band` to approve offers outside of their band".to_string(),
+                "headcount plan for team `design` which does not exist".to_string(),
+                "headcount plan for `eng` has quarter `Q3` which is not formatted like `2021-Q2`".to_string(),
+                "headcount plan for `eng` in `Q3` is -1, it cannot be negative".to_string(),
+                "reviews for `jane` are by manager `nobody` who is not a user".to_string(),
+                "reviews need both a `drive` and a `folder` to archive to".to_string(),
+                "reviews `reminder_days` has -1, it cannot be negative".to_string(),
+                "training `security_awareness` has no `courses` to find it in the vendor's reports by".to_string(),
+                "training `security_awareness` is for group `design` which does not exist".to_string(),
+                "training `security_awareness` has `due_days` of -1, it cannot be negative".to_string(),
+                "training `security_awareness` has `every_days` of 0, it must be at least 1".to_string(),
+                "policy `acceptable_use` does not have a `file_id` for its document in Drive".to_string(),
+                "policy `acceptable_use` is for group `design` which does not exist".to_string(),
+                "desk capacity for building `SF` which does not exist".to_string(),
+                "desk capacity for `SF` is -1, it cannot be negative".to_string(),
+                "lunch `office_days` has `thursdays` which is not a day of the week".to_string(),
+                "lunch has `office_days` but no `caterer` to email the orders to".to_string(),
+                "lunch `close_hour` is 24, it must be an hour of the day from 0 to 23".to_string(),
+                "lunch menu item `salad` has a price of -11, it cannot be negative".to_string(),
+                "carbon factor for cloud provider `azure` which is not one of aws, gcp".to_string(),
+                "carbon factor for `azure` is -0.5, it cannot be negative".to_string(),
+                "board pack needs both a `drive` and a `folder` to save to".to_string(),
+                "token `slack` is owned by `nobody` who is not a user".to_string(),
+                "approvals for `budget_overage` need 2 people but group `eng` only has 1".to_string(),
+                "approvals for `dns` which is not one of budget_overage, offboarding, offer_out_of_band".to_string(),
+                "approvals for `dns` must have either `approvers` or a `group`".to_string(),
+                "approvals for `offboarding` need `sam` who is not a user".to_string(),
+                "maintenance window `okta_migration` ends before it starts".to_string(),
+                "maintenance window `okta_migration` pauses job `okta` which does not exist".to_string(),
+                "service account key `backups` has secret `gadmin-credentials` which is not formatted as `projects/{project}/secrets/{secret}`".to_string(),
+                "role `Closed` has hiring manager `nobody` who is not a user".to_string(),
+                "role `Firmware` collects applications in sheet `1nope` which we do not sync applicants from".to_string()
+            ]
+        );
     }
 }
@@ -0,0 +1,422 @@
+use std::env;
+
+use chrono::{DateTime, Utc};
+use handlebars::Handlebars;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::actions_usage::get_github_actions_cost_for_month;
+use crate::backups::upload_to_gcs;
+use crate::cloud_costs::get_cloud_costs_by_group_for_month;
+use crate::configs::{OpenRole, OpenRoles, Users};
+use crate::db::Database;
+use crate::finance::SoftwareVendors;
+use crate::format::{format_date, format_time, format_usd};
+use crate::models::RFDs;
+use crate::rate_limits::{rate_limit_statuses, RateLimitStatus};
+use crate::retention::{get_latest_people_snapshot, PeopleSnapshot, RetentionCohort};
+
+/// How many of the most recently changed RFDs the dashboard shows.
+const DASHBOARD_RECENT_RFDS: usize = 10;
+
+/// The status of a sync job, from the scheduler. The times are empty until the job runs
+/// for the first time since the daemon started.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DashboardJob {
+    pub name: String,
+    /// When the job runs, ie. `every 6h`, `0 9 * * mon (America/Los_Angeles)`, or
+    /// `disabled`.
+    pub schedule: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_run: String,
+    /// How long the last run took.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub duration: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub next_run: String,
+    /// How the last run went, ie. `ok`, `failed: ...`, or `skipped: airtable unavailable`
+    /// when a service the job depends on is down.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub result: String,
+}
+
+/// Our spend so far this month.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DashboardSpend {
+    pub software_vendors: String,
+    pub ci: String,
+    pub cloud: String,
+    pub total: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DashboardRole {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub team: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub location: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DashboardRFD {
+    pub number_string: String,
+    pub title: String,
+    pub state: String,
+    pub link: String,
+    /// The date of the last commit to the RFD.
+    pub updated: String,
+}
+
+/// How many people have been here for a range of time.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DashboardTenure {
+    pub label: String,
+    pub people: i32,
+}
+
+/// What share of the people who started in a year were still here after each full year.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DashboardRetention {
+    pub cohort: i32,
+    pub started: i32,
+    pub retained: Vec<String>,
+}
+
+/// Our tenure and retention numbers, from the most recent people snapshot.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DashboardPeople {
+    pub as_of: String,
+    pub median_tenure: String,
+    pub tenure: Vec<DashboardTenure>,
+    /// ie. `8.3% (2 people left in the last 12 months)`
+    pub attrition: String,
+    pub retention: Vec<DashboardRetention>,
+}
+
+/// The internal dashboard, everything on it is formatted for people.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct Dashboard {
+    pub generated_at: String,
+    pub jobs: Vec<DashboardJob>,
+    pub spend: DashboardSpend,
+    pub headcount: usize,
+    /// This is empty until the `people_snapshots` job runs for the first time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub people: Option<DashboardPeople>,
+    pub open_roles: Vec<DashboardRole>,
+    pub recent_rfds: Vec<DashboardRFD>,
+    /// How close we are to the rate limit for each API we use.
+    pub rate_limits: Vec<RateLimitStatus>,
+}
+
+/// Get the roles we are hiring for, sorted by name.
+#[instrument(skip(roles))]
+#[inline]
+pub fn dashboard_roles(roles: Vec<OpenRole>) -> Vec<DashboardRole> {
+    let mut open: Vec<DashboardRole> = roles
+        .into_iter()
+        .filter(|r| r.open)
+        .map(|r| DashboardRole {
+            name: r.name,
+            team: r.team,
+            location: r.location,
+        })
+        .collect();
+    open.sort_by(|a, b| a.name.cmp(&b.name));
+
+    open
+}
+
+/// Format a people snapshot and the retention of each cohort on its day for the dashboard.
+#[instrument(skip(cohorts))]
+#[inline]
+pub fn dashboard_people(snapshot: &PeopleSnapshot, cohorts: &[RetentionCohort]) -> DashboardPeople {
+    let people = if snapshot.departures_12m == 1 { "person" } else { "people" };
+
+    DashboardPeople {
+        as_of: format_date(snapshot.taken_on),
+        median_tenure: format!("{:.1} years", snapshot.median_tenure_days as f32 / 365.0),
+        tenure: vec![
+            DashboardTenure {
+                label: "Under 1 year".to_string(),
+                people: snapshot.tenure_under_1y,
+            },
+            DashboardTenure {
+                label: "1 to 2 years".to_string(),
+                people: snapshot.tenure_1y_to_2y,
+            },
+            DashboardTenure {
+                label: "2 to 4 years".to_string(),
+                people: snapshot.tenure_2y_to_4y,
+            },
+            DashboardTenure {
+                label: "4 years or more".to_string(),
+                people: snapshot.tenure_over_4y,
+            },
+        ],
+        attrition: format!("{:.1}% ({} {} left in the last 12 months)", snapshot.attrition_12m, snapshot.departures_12m, people),
+        retention: cohorts
+            .iter()
+            .filter(|c| c.started > 0)
+            .map(|c| DashboardRetention {
+                cohort: c.cohort,
+                started: c.started,
+                retained: c.retained.iter().map(|r| format!("{:.0}%", *r as f32 / c.started as f32 * 100.0)).collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Build the dashboard from the database, with the status of the jobs from the scheduler.
+#[instrument(skip(db, jobs))]
+#[inline]
+pub fn build_dashboard(db: &Database, jobs: Vec<DashboardJob>, now: DateTime<Utc>) -> Dashboard {
+    let month = now.date().naive_utc();
+    let vendors: f32 = SoftwareVendors::get_from_db(db).into_iter().map(|v| v.total_cost_per_month).sum();
+    let ci: f32 = get_github_actions_cost_for_month(db, month).values().sum();
+    let cloud: f32 = get_cloud_costs_by_group_for_month(db, month).values().sum();
+
+    // People who left are removed from our configs, so these are the people here now.
+    let headcount = Users::get_from_db(db).into_iter().filter(|u| !u.is_system_account()).count();
+
+    let mut rfds: Vec<_> = RFDs::get_from_db(db).into_iter().collect();
+    rfds.sort_by(|a, b| b.commit_date.cmp(&a.commit_date));
+    let recent_rfds = rfds
+        .into_iter()
+        .take(DASHBOARD_RECENT_RFDS)
+        .map(|r| DashboardRFD {
+            number_string: r.number_string,
+            title: r.title,
+            state: r.state,
+            link: r.link,
+            updated: format_date(r.commit_date.date().naive_utc()),
+        })
+        .collect();
+
+    Dashboard {
+        generated_at: format_time(now),
+        jobs,
+        spend: DashboardSpend {
+            software_vendors: format_usd(vendors),
+            ci: format_usd(ci),
+            cloud: format_usd(cloud),
+            total: format_usd(vendors + ci + cloud),
+        },
+        headcount,
+        people: get_latest_people_snapshot(db).map(|(snapshot, cohorts)| dashboard_people(&snapshot, &cohorts)),
+        open_roles: dashboard_roles(OpenRoles::get_from_db(db).into()),
+        recent_rfds,
+        rate_limits: rate_limit_statuses(db, now),
+    }
+}
+
+/// Render the dashboard as a single HTML page.
+#[instrument]
+#[inline]
+pub fn render_dashboard(dashboard: &Dashboard) -> String {
+    Handlebars::new().render_template(TEMPLATE_DASHBOARD, dashboard).unwrap()
+}
+
+/// Publish the dashboard, as HTML and JSON, to the bucket in the `CIO_DASHBOARD_BUCKET`
+/// environment variable. The bucket is private and served behind IAP, so only we can
+/// see it. This does nothing if the bucket is not set.
+#[instrument(skip(db, jobs))]
+#[inline]
+pub async fn publish_dashboard(db: &Database, jobs: Vec<DashboardJob>) {
+    let bucket = env::var("CIO_DASHBOARD_BUCKET").unwrap_or_default();
+    if bucket.is_empty() {
+        return;
+    }
+
+    let dashboard = build_dashboard(db, jobs, Utc::now());
+    let json = serde_json::to_vec_pretty(&dashboard).unwrap();
+    if let Err(e) = upload_to_gcs(&bucket, "dashboard.json", "application/json", json).await {
+        println!("[dashboard] publishing the dashboard failed: {}", e);
+        return;
+    }
+    if let Err(e) = upload_to_gcs(&bucket, "index.html", "text/html; charset=utf-8", render_dashboard(&dashboard).into_bytes()).await {
+        println!("[dashboard] publishing the dashboard failed: {}", e);
+    }
+}
+
+/// Template for the internal dashboard.
+pub static TEMPLATE_DASHBOARD: &str = r#"<!-- THIS FILE HAS BEEN GENERATED BY THE CIO REPO AND SHOULD NEVER BE EDITED BY HAND!! -->
+<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>CIO dashboard</title>
+  <style>
+    body { font-family: sans-serif; margin: 2em; color: #222; }
+    table { border-collapse: collapse; margin-bottom: 2em; }
+    th, td { text-align: left; padding: 0.25em 1em 0.25em 0; }
+    th { border-bottom: 1px solid #ccc; }
+    .muted { color: #888; }
+    .low { color: #b00; }
+  </style>
+</head>
+<body>
+  <h1>CIO dashboard</h1>
+  <p class="muted">Generated {{generated_at}}</p>
+
+  <h2>Spend this month</h2>
+  <table>
+    <tr><td>Software vendors</td><td>{{spend.software_vendors}}</td></tr>
+    <tr><td>CI (GitHub Actions)</td><td>{{spend.ci}}</td></tr>
+    <tr><td>Cloud (GCP and AWS)</td><td>{{spend.cloud}}</td></tr>
+    <tr><th>Total</th><th>{{spend.total}}</th></tr>
+  </table>
+
+  <h2>Headcount</h2>
+  <p>{{headcount}} people</p>
+{{#if people}}  <p class="muted">Tenure and retention as of {{people.as_of}}</p>
+  <table>
+{{#each people.tenure}}    <tr><td>{{this.label}}</td><td>{{this.people}}</td></tr>
+{{/each}}    <tr><th>Median tenure</th><th>{{people.median_tenure}}</th></tr>
+    <tr><th>Attrition</th><th>{{people.attrition}}</th></tr>
+  </table>
+  <table>
+    <tr><th>Started in</th><th>People</th><th>Still here after each year</th></tr>
+{{#each people.retention}}    <tr><td>{{this.cohort}}</td><td>{{this.started}}</td><td>{{#each this.retained}}{{this}} {{/each}}</td></tr>
+{{/each}}  </table>
+{{/if}}
+  <h2>Open roles</h2>
+  {{#if open_roles}}<ul>
+{{#each open_roles}}    <li>{{this.name}}{{#if this.team}} | {{this.team}}{{/if}}{{#if this.location}} | {{this.location}}{{/if}}</li>
+{{/each}}  </ul>{{else}}<p class="muted">We are not hiring for any roles right now.</p>{{/if}}
+
+  <h2>Recent RFDs</h2>
+  <table>
+    <tr><th>RFD</th><th>Title</th><th>State</th><th>Updated</th></tr>
+{{#each recent_rfds}}    <tr><td><a href="{{this.link}}">{{this.number_string}}</a></td><td>{{this.title}}</td><td>{{this.state}}</td><td>{{this.updated}}</td></tr>
+{{/each}}  </table>
+
+  <h2>Sync jobs</h2>
+  <table>
+    <tr><th>Job</th><th>Schedule</th><th>Last run</th><th>Took</th><th>Next run</th><th>Result</th></tr>
+{{#each jobs}}    <tr><td>{{this.name}}</td><td>{{this.schedule}}</td><td>{{#if this.last_run}}{{this.last_run}}{{else}}<span class="muted">not since the daemon started</span>{{/if}}</td><td>{{this.duration}}</td><td>{{this.next_run}}</td><td>{{this.result}}</td></tr>
+{{/each}}  </table>
+
+  <h2>API rate limits</h2>
+  <p class="muted">Low priority jobs wait while an API is low on headroom.</p>
+  <table>
+    <tr><th>API</th><th>Headroom</th></tr>
+{{#each rate_limits}}    <tr><td>{{this.service}}</td><td{{#if this.low}} class="low"{{/if}}>{{this.headroom}}{{#if this.low}} (low){{/if}}</td></tr>
+{{/each}}  </table>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+
+    use crate::dashboard::{dashboard_people, publish_dashboard, render_dashboard, Dashboard, DashboardJob, DashboardRFD, DashboardRole};
+    use crate::db::Database;
+    use crate::rate_limits::RateLimitStatus;
+    use crate::retention::{PeopleSnapshot, RetentionCohort};
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_dashboard() {
+        let db = Database::new();
+
+        // Outside of the daemon we do not know the status of the jobs.
+        publish_dashboard(&db, vec![]).await;
+    }
+
+    #[test]
+    fn test_render_dashboard() {
+        let dashboard = Dashboard {
+            generated_at: "2021-04-23 09:00 UTC".to_string(),
+            headcount: 42,
+            open_roles: vec![DashboardRole {
+                name: "Hardware <Engineer>".to_string(),
+                ..Default::default()
+            }],
+            recent_rfds: vec![DashboardRFD {
+                number_string: "0042".to_string(),
+                title: "The answer".to_string(),
+                state: "published".to_string(),
+                link: "https://github.com/oxidecomputer/rfd/tree/0042/rfd/0042".to_string(),
+                updated: "2021-04-22".to_string(),
+            }],
+            jobs: vec![
+                DashboardJob {
+                    name: "rfds".to_string(),
+                    schedule: "every 6h".to_string(),
+                    last_run: "2021-04-23 08:00 UTC".to_string(),
+                    duration: "3m 20s".to_string(),
+                    next_run: "2021-04-23 14:00 UTC".to_string(),
+                    result: "skipped: airtable unavailable".to_string(),
+                },
+                DashboardJob {
+                    name: "software_vendors".to_string(),
+                    schedule: "disabled".to_string(),
+                    ..Default::default()
+                },
+            ],
+            rate_limits: vec![
+                RateLimitStatus {
+                    service: "github".to_string(),
+                    low: true,
+                    headroom: "120 of 5000 left until 2021-04-23 09:30 UTC".to_string(),
+                },
+                RateLimitStatus {
+                    service: "slack".to_string(),
+                    low: false,
+                    headroom: "not throttled today".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+
+        let html = render_dashboard(&dashboard);
+        assert!(html.contains("<p>42 people</p>"));
+        assert!(html.contains("<li>Hardware &lt;Engineer&gt;</li>"));
+        assert!(html.contains("<a href=\"https://github.com/oxidecomputer/rfd/tree/0042/rfd/0042\">0042</a>"));
+        assert!(html.contains("<td>rfds</td><td>every 6h</td><td>2021-04-23 08:00 UTC</td><td>3m 20s</td><td>2021-04-23 14:00 UTC</td><td>skipped: airtable unavailable</td>"));
+        assert!(html.contains("<td>software_vendors</td><td>disabled</td><td><span class=\"muted\">not since the daemon started</span></td>"));
+        assert!(html.contains("<td>github</td><td class=\"low\">120 of 5000 left until 2021-04-23 09:30 UTC (low)</td>"));
+        assert!(html.contains("<td>slack</td><td>not throttled today</td>"));
+        assert!(!html.contains("Median tenure"));
+    }
+
+    #[test]
+    fn test_dashboard_people() {
+        let taken_on = NaiveDate::from_ymd(2021, 5, 17);
+        let snapshot = PeopleSnapshot {
+            id: 1,
+            taken_on,
+            headcount: 4,
+            median_tenure_days: 593,
+            tenure_under_1y: 1,
+            tenure_1y_to_2y: 2,
+            tenure_2y_to_4y: 0,
+            tenure_over_4y: 1,
+            departures_12m: 2,
+            attrition_12m: 50.0,
+        };
+        let cohorts = vec![RetentionCohort {
+            id: 1,
+            taken_on,
+            cohort: 2019,
+            started: 3,
+            retained: vec![3, 2],
+        }];
+
+        let people = dashboard_people(&snapshot, &cohorts);
+        assert_eq!(people.median_tenure, "1.6 years");
+        assert_eq!(people.attrition, "50.0% (2 people left in the last 12 months)");
+        assert_eq!(people.retention[0].retained, vec!["100%".to_string(), "67%".to_string()]);
+
+        let html = render_dashboard(&Dashboard {
+            people: Some(people),
+            ..Default::default()
+        });
+        assert!(html.contains("<tr><td>1 to 2 years</td><td>2</td></tr>"));
+        assert!(html.contains("<tr><td>2019</td><td>3</td><td>100% 67% </td></tr>"));
+    }
+}
@@ -1,21 +1,83 @@
 use std::env;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use diesel::pg::PgConnection;
 use diesel::r2d2;
 
+/// How many records we write in one statement with `upsert_all`. Postgres takes at most
+/// 65535 bind parameters in a statement and our widest tables are under 128 columns.
+pub const BATCH_SIZE: usize = 250;
+
+/// How long we wait for a connection to the read replica before reading from the
+/// primary instead.
+pub const REPLICA_CONNECT_TIMEOUT_SECS: u64 = 2;
+
+/// How long we read from the primary after the read replica was unavailable, before
+/// trying the replica again.
+pub const REPLICA_RETRY_SECS: u64 = 60;
+
+#[derive(Clone)]
 pub struct Database {
     pool: Arc<r2d2::Pool<r2d2::ConnectionManager<PgConnection>>>,
+    /// The read replica from `CIO_DATABASE_REPLICA_URL`, if we have one.
+    replica: Option<Arc<Replica>>,
+    /// If this handle reads from the replica, see `read_only`.
+    read_only: bool,
+    /// The SQLite database the synced tables are kept in instead, when
+    /// `CIO_DATABASE_URL` is a `sqlite://` URL.
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<Arc<crate::sqlite::SqliteStore>>,
+}
+
+struct Replica {
+    pool: r2d2::Pool<r2d2::ConnectionManager<PgConnection>>,
+    /// When we go back to trying the replica after it was unavailable.
+    unavailable_until: Mutex<Option<Instant>>,
 }
 
 impl Default for Database {
     fn default() -> Self {
         let database_url = env::var("CIO_DATABASE_URL").expect("CIO_DATABASE_URL must be set");
 
+        #[cfg(feature = "sqlite")]
+        {
+            if let Some(path) = database_url.strip_prefix("sqlite://") {
+                let store = crate::sqlite::SqliteStore::open(path).unwrap_or_else(|e| panic!("{}", e));
+                // There is no Postgres to connect to, see `conn`.
+                let pool = r2d2::Pool::builder().max_size(1).build_unchecked(r2d2::ConnectionManager::new(&database_url));
+                return Database {
+                    pool: Arc::new(pool),
+                    replica: None,
+                    read_only: false,
+                    sqlite: Some(Arc::new(store)),
+                };
+            }
+        }
+
         let manager = r2d2::ConnectionManager::new(&database_url);
         let pool = r2d2::Pool::builder().max_size(15).build(manager).unwrap();
 
-        Database { pool: Arc::new(pool) }
+        // The replica may be down when we start, so we do not connect to it until the
+        // first read.
+        let replica = env::var("CIO_DATABASE_REPLICA_URL").ok().filter(|url| !url.is_empty()).map(|url| {
+            let pool = r2d2::Pool::builder()
+                .max_size(15)
+                .connection_timeout(Duration::from_secs(REPLICA_CONNECT_TIMEOUT_SECS))
+                .build_unchecked(r2d2::ConnectionManager::new(&url));
+            Arc::new(Replica {
+                pool,
+                unavailable_until: Mutex::new(None),
+            })
+        });
+
+        Database {
+            pool: Arc::new(pool),
+            replica,
+            read_only: false,
+            #[cfg(feature = "sqlite")]
+            sqlite: None,
+        }
     }
 }
 
@@ -25,8 +87,95 @@ impl Database {
         Default::default()
     }
 
-    /// Returns a connection from the pool.
+    /// Returns a connection from the pool. Each connection is counted as a checkout
+    /// against the job that is running. For a handle from `read_only` this is a
+    /// connection to the read replica when it is available.
     pub fn conn(&self) -> r2d2::PooledConnection<r2d2::ConnectionManager<PgConnection>> {
+        if self.is_sqlite() {
+            panic!("only the tables synced to Airtable are kept in SQLite, run this against Postgres");
+        }
+
+        crate::job_runs::count_db_checkout();
+        if self.read_only {
+            if let Some(conn) = self.replica_conn() {
+                return conn;
+            }
+        }
+
         self.pool.get().unwrap_or_else(|e| panic!("getting a connection from the pool failed: {}", e))
     }
+
+    /// Returns a handle that reads from the read replica, for the API server and reports,
+    /// so they do not load the primary. Without a replica, or while it is unavailable,
+    /// this reads from the primary. Writes would fail on the replica, so only read with it.
+    pub fn read_only(&self) -> Database {
+        Database { read_only: true, ..self.clone() }
+    }
+
+    /// Returns if this handle reads from the read replica.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns if the synced tables are kept in SQLite, see the `sqlite` feature.
+    pub fn is_sqlite(&self) -> bool {
+        #[cfg(feature = "sqlite")]
+        {
+            self.sqlite.is_some()
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            false
+        }
+    }
+
+    /// Returns the SQLite database the synced tables are kept in, if they are.
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(&self) -> Option<&crate::sqlite::SqliteStore> {
+        self.sqlite.as_deref()
+    }
+
+    fn replica_conn(&self) -> Option<r2d2::PooledConnection<r2d2::ConnectionManager<PgConnection>>> {
+        let replica = self.replica.as_ref()?;
+        if replica.unavailable_until.lock().unwrap().map(|t| Instant::now() < t).unwrap_or(false) {
+            return None;
+        }
+
+        match replica.pool.get() {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                println!("[db] the read replica is unavailable, reading from the primary for the next {}s: {}", REPLICA_RETRY_SECS, e);
+                *replica.unavailable_until.lock().unwrap() = Some(Instant::now() + Duration::from_secs(REPLICA_RETRY_SECS));
+                None
+            }
+        }
+    }
+}
+
+/// What `upsert_all` did with each record in a batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpsertedRecords<T> {
+    pub created: Vec<T>,
+    pub updated: Vec<T>,
+    pub unchanged: Vec<T>,
+}
+
+impl<T> Default for UpsertedRecords<T> {
+    fn default() -> Self {
+        UpsertedRecords {
+            created: Default::default(),
+            updated: Default::default(),
+            unchanged: Default::default(),
+        }
+    }
+}
+
+impl<T> UpsertedRecords<T> {
+    /// Every record in the batch as it is now in the database.
+    pub fn into_all(self) -> Vec<T> {
+        let mut all = self.created;
+        all.extend(self.updated);
+        all.extend(self.unchanged);
+        all
+    }
 }
@@ -0,0 +1,457 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use diesel::pg::PgConnection;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::BigInt;
+use diesel::{Connection, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::chat::ChatChannel;
+use crate::configs::{Config, ResourceConfig};
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::office_bookings;
+
+/// The type of the resources in our configs that are desks. Everything else, ie.
+/// conference rooms, is booked as a room and does not count towards the capacity of
+/// the office.
+pub const DESK_RESOURCE_TYPE: &str = "desk";
+
+/// The header a client of the bookings API sends the shared secret in, which must
+/// match the `OFFICE_BOOKINGS_SECRET` environment variable.
+pub static OFFICE_BOOKINGS_SECRET_HEADER: &str = "X-CIO-Office-Secret";
+
+/// The configuration for booking desks in our buildings.
+///
+/// ```toml
+/// [desks.capacity]
+/// "SF Office" = 12
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct DesksConfig {
+    /// The most people who can be in a building on a day, by the name of the building.
+    /// Buildings without one can fill every desk.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub capacity: BTreeMap<String, i64>,
+}
+
+impl DesksConfig {
+    /// Get the most people who can be in a building on a day.
+    #[instrument(skip(resources))]
+    #[inline]
+    pub fn capacity_for(&self, building: &str, resources: &BTreeMap<String, ResourceConfig>) -> i64 {
+        match self.capacity.get(building) {
+            Some(capacity) => *capacity,
+            None => resources.values().filter(|r| is_desk(r) && r.building == building).count() as i64,
+        }
+    }
+}
+
+/// Returns if a resource is a desk rather than a room.
+#[instrument]
+#[inline]
+pub fn is_desk(resource: &ResourceConfig) -> bool {
+    resource.typev.eq_ignore_ascii_case(DESK_RESOURCE_TYPE)
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "office_bookings"]
+pub struct NewOfficeBooking {
+    /// The name of the resource in our configs.
+    pub resource: String,
+    pub building: String,
+    pub desk: bool,
+    pub username: String,
+    pub day: NaiveDate,
+    pub booked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct OfficeBooking {
+    pub id: i32,
+    pub resource: String,
+    pub building: String,
+    pub desk: bool,
+    pub username: String,
+    pub day: NaiveDate,
+    pub booked_at: DateTime<Utc>,
+}
+
+/// A request to the bookings API to book a desk or a room, or to cancel someone's
+/// bookings on a day.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct OfficeBookingRequest {
+    pub username: String,
+    /// The day to book, ie. `2021-05-20`, `today`, `tomorrow`, or `friday`.
+    pub day: String,
+    /// The resource to book, by its name. Defaults to a free desk in their building.
+    #[serde(default)]
+    pub resource: String,
+    #[serde(default)]
+    pub cancel: bool,
+}
+
+/// Parse the day someone wants to book: `today`, `tomorrow`, a weekday, which is the
+/// next one after today, or a date like `2021-05-20`.
+#[instrument]
+#[inline]
+pub fn parse_booking_day(input: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let input = input.trim().to_lowercase();
+    match input.as_str() {
+        "" | "today" => return Ok(today),
+        "tomorrow" => return Ok(today + Duration::days(1)),
+        _ => (),
+    }
+    if let Ok(weekday) = input.parse::<Weekday>() {
+        let mut days = (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+        if days == 0 {
+            days = 7;
+        }
+        return Ok(today + Duration::days(days as i64));
+    }
+
+    NaiveDate::parse_from_str(&input, "%Y-%m-%d").map_err(|_| format!("could not understand the day `{}`, try `today`, `tomorrow`, `friday`, or `2021-05-20`", input))
+}
+
+/// Pick the resource to book for someone on a day given the bookings we already have
+/// that day, and make sure the building has room for them. Without a resource we pick
+/// the first free desk, in their building if they have one.
+#[instrument(skip(config, bookings))]
+#[inline]
+pub fn plan_booking(config: &Config, bookings: &[OfficeBooking], username: &str, resource: &str) -> Result<ResourceConfig, String> {
+    let user = config
+        .users
+        .get(username)
+        .filter(|u| !u.is_system_account())
+        .ok_or_else(|| format!("`{}` is not anyone in our directory", username))?;
+
+    let chosen = if resource.trim().is_empty() {
+        if let Some(b) = bookings.iter().find(|b| b.desk && b.username == username) {
+            return Err(format!("you already have desk `{}` that day", b.resource));
+        }
+        let mut desks: Vec<&ResourceConfig> = config.resources.values().filter(|r| is_desk(r) && !bookings.iter().any(|b| b.resource == r.name)).collect();
+        desks.sort_by_key(|r| (r.building != user.building, r.building.to_string(), r.name.to_string()));
+        desks.first().cloned().ok_or_else(|| "every desk is booked that day".to_string())?
+    } else {
+        let r = config
+            .resources
+            .values()
+            .find(|r| r.name.eq_ignore_ascii_case(resource.trim()))
+            .ok_or_else(|| format!("`{}` is not a desk or room we have", resource.trim()))?;
+        if let Some(b) = bookings.iter().find(|b| b.resource == r.name) {
+            if b.username == username {
+                return Err(format!("you already booked `{}` that day", r.name));
+            }
+            return Err(format!("`{}` is already booked by {} that day", r.name, b.username));
+        }
+        r
+    };
+
+    if is_desk(chosen) {
+        let capacity = config.desks.capacity_for(&chosen.building, &config.resources);
+        let people = bookings.iter().filter(|b| b.desk && b.building == chosen.building && b.username != username).count() as i64;
+        if people >= capacity {
+            return Err(format!("the {} office is full that day, it only fits {}", chosen.building, capacity));
+        }
+    }
+
+    Ok(chosen.clone())
+}
+
+/// Get the bookings on a day.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_bookings_on(db: &Database, day: NaiveDate) -> Vec<OfficeBooking> {
+    load_bookings_on(&db.conn(), day).unwrap_or_else(|e| panic!("getting the office bookings on {} failed: {}", day, e))
+}
+
+fn load_bookings_on(conn: &PgConnection, day: NaiveDate) -> QueryResult<Vec<OfficeBooking>> {
+    office_bookings::table.filter(office_bookings::day.eq(day)).order_by(office_bookings::resource).load(conn)
+}
+
+/// Book a desk or a room for someone for a day. Booking another desk moves them to it.
+/// Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn book_office(db: &Database, config: &Config, username: &str, day: NaiveDate, resource: &str) -> Result<String, String> {
+    if day < Utc::now().date().naive_utc() {
+        return Err(format!("{} has already passed", day.format("%A, %B %-d")));
+    }
+
+    // Check the bookings and book in one transaction, so moving someone from their desk
+    // is undone if the new booking fails. Bookings for the same day wait on each other
+    // here, so two people can not both take the last desk in an office.
+    let conn = db.conn();
+    let chosen = conn
+        .transaction::<_, DieselError, _>(|| {
+            diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+                .bind::<BigInt, _>(i64::from(day.num_days_from_ce()))
+                .execute(&conn)?;
+            let bookings = load_bookings_on(&conn, day)?;
+            let chosen = match plan_booking(config, &bookings, username, resource) {
+                Ok(chosen) => chosen,
+                Err(e) => return Ok(Err(e)),
+            };
+
+            if is_desk(&chosen) {
+                diesel::delete(
+                    office_bookings::table
+                        .filter(office_bookings::day.eq(day))
+                        .filter(office_bookings::username.eq(username))
+                        .filter(office_bookings::desk.eq(true)),
+                )
+                .execute(&conn)?;
+            }
+
+            diesel::insert_into(office_bookings::table)
+                .values(&NewOfficeBooking {
+                    resource: chosen.name.to_string(),
+                    building: chosen.building.to_string(),
+                    desk: is_desk(&chosen),
+                    username: username.to_string(),
+                    day,
+                    booked_at: Utc::now(),
+                })
+                .execute(&conn)?;
+
+            Ok(Ok(chosen))
+        })
+        .map_err(|e| match e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => "that was just booked by someone else, try again".to_string(),
+            e => format!("booking for {} on {} failed: {}", username, day, e),
+        })??;
+    let desk = is_desk(&chosen);
+
+    Ok(format!(
+        "booked {} `{}` in {} for {} on {}",
+        if desk { "desk" } else { "room" },
+        chosen.name,
+        chosen.building,
+        username,
+        day.format("%A, %B %-d")
+    ))
+}
+
+/// Cancel all of someone's bookings on a day. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub fn cancel_office_bookings(db: &Database, username: &str, day: NaiveDate) -> Result<String, String> {
+    let cancelled = diesel::delete(office_bookings::table.filter(office_bookings::day.eq(day)).filter(office_bookings::username.eq(username)))
+        .execute(&db.conn())
+        .map_err(|e| format!("cancelling the bookings of {} failed: {}", username, e))?;
+    if cancelled == 0 {
+        return Err(format!("{} has nothing booked on {}", username, day.format("%A, %B %-d")));
+    }
+
+    Ok(format!("cancelled {} booking(s) of {} on {}", cancelled, username, day.format("%A, %B %-d")))
+}
+
+/// Format who is in each building on a day and the rooms they booked, as the lines of
+/// a list.
+#[instrument(skip(config, bookings))]
+#[inline]
+pub fn format_office_day(config: &Config, bookings: &[OfficeBooking]) -> Vec<String> {
+    let name = |username: &str| -> String { config.users.get(username).map(|u| format!("{} {}", u.first_name, u.last_name)).unwrap_or_else(|| username.to_string()) };
+
+    let mut buildings: BTreeMap<&str, Vec<&OfficeBooking>> = BTreeMap::new();
+    for b in bookings {
+        buildings.entry(b.building.as_str()).or_default().push(b);
+    }
+
+    let mut lines = Vec::new();
+    for (building, bookings) in buildings {
+        let desks: Vec<String> = bookings.iter().filter(|b| b.desk).map(|b| format!("{} ({})", name(&b.username), b.resource)).collect();
+        let capacity = config.desks.capacity_for(building, &config.resources);
+        lines.push(format!("• *{}*, {} of {}: {}", building, desks.len(), capacity, desks.join(", ")));
+        for b in bookings.iter().filter(|b| !b.desk) {
+            lines.push(format!("    ◦ {} has booked {} for the day", name(&b.username), b.resource));
+        }
+    }
+
+    lines
+}
+
+/// Post who is in the office today to the office channel each morning.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn post_office_summary(db: &Database, config: &Config) {
+    let bookings = get_bookings_on(db, Utc::now().date().naive_utc());
+    if bookings.is_empty() {
+        return;
+    }
+
+    let people = bookings.iter().filter(|b| b.desk).count();
+    notify(
+        db,
+        NotificationPriority::Urgent,
+        ChatChannel::Office,
+        "office.summary",
+        json!({
+            "text": render_message("office.summary", &json!({ "count": people, "lines": format_office_day(config, &bookings) })),
+        }),
+    )
+    .await;
+}
+
+/// Answer the `/desk` Slack command for someone, by their username:
+/// - `/desk`, `/desk tomorrow`, or `/desk friday` books a free desk
+/// - `/desk friday Fishbowl` books a desk or a room by its name
+/// - `/desk cancel friday` cancels what they booked
+/// - `/desk who friday` lists who is in the office
+#[instrument(skip(db, config))]
+#[inline]
+pub fn handle_desk_command(db: &Database, config: &Config, username: &str, text: &str) -> String {
+    let today = Utc::now().date().naive_utc();
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    let action = match words.first().map(|w| w.to_lowercase()) {
+        Some(w) if w == "cancel" || w == "who" => {
+            words.remove(0);
+            w
+        }
+        _ => "book".to_string(),
+    };
+    let day = match parse_booking_day(words.first().cloned().unwrap_or_default(), today) {
+        Ok(day) => {
+            if !words.is_empty() {
+                words.remove(0);
+            }
+            day
+        }
+        // Without a day, everything is the name of what to book today.
+        Err(e) => {
+            if action != "book" {
+                return format!("Sorry, {}.", e);
+            }
+            today
+        }
+    };
+
+    let result = match action.as_str() {
+        "cancel" => cancel_office_bookings(db, username, day),
+        "who" => {
+            let lines = format_office_day(config, &get_bookings_on(db, day));
+            if lines.is_empty() {
+                Ok(format!("Nobody has booked anything on {}.", day.format("%A, %B %-d")))
+            } else {
+                Ok(lines.join("\n"))
+            }
+        }
+        _ => book_office(db, config, username, day, &words.join(" ")),
+    };
+
+    result.unwrap_or_else(|e| format!("Sorry, {}.", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+    use chrono::{TimeZone, Utc};
+
+    use crate::configs::Config;
+    use crate::desks::{parse_booking_day, plan_booking, OfficeBooking};
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.users = toml::from_str(
+            r#"[jane]
+first_name = "Jane"
+last_name = "Doe"
+username = "jane"
+building = "SF"
+
+[sam]
+first_name = "Sam"
+last_name = "Smith"
+username = "sam"
+"#,
+        )
+        .unwrap();
+        config.resources = toml::from_str(
+            r#"[desk-1]
+name = "Desk 1"
+type = "desk"
+building = "SF"
+capacity = 1
+
+[desk-2]
+name = "Desk 2"
+type = "desk"
+building = "SF"
+capacity = 1
+
+[desk-9]
+name = "Desk 9"
+type = "desk"
+building = "Emeryville"
+capacity = 1
+
+[fishbowl]
+name = "Fishbowl"
+type = "Conference Room"
+building = "SF"
+capacity = 6
+"#,
+        )
+        .unwrap();
+        config.desks = toml::from_str(
+            r#"[capacity]
+SF = 1
+"#,
+        )
+        .unwrap();
+
+        config
+    }
+
+    fn booking(resource: &str, building: &str, desk: bool, username: &str) -> OfficeBooking {
+        OfficeBooking {
+            id: 1,
+            resource: resource.to_string(),
+            building: building.to_string(),
+            desk,
+            username: username.to_string(),
+            day: NaiveDate::from_ymd(2021, 5, 20),
+            booked_at: Utc.ymd(2021, 5, 19).and_hms(9, 0, 0),
+        }
+    }
+
+    #[test]
+    fn test_plan_booking() {
+        let config = config();
+
+        // Jane gets a desk in her own building first.
+        assert_eq!(plan_booking(&config, &[], "jane", "").unwrap().name, "Desk 1");
+        assert_eq!(plan_booking(&config, &[], "jane", "fishbowl").unwrap().name, "Fishbowl");
+        assert_eq!(plan_booking(&config, &[], "nobody", "").unwrap_err(), "`nobody` is not anyone in our directory");
+        assert_eq!(plan_booking(&config, &[], "jane", "Desk 7").unwrap_err(), "`Desk 7` is not a desk or room we have");
+
+        // SF only fits one person, so Sam gets the desk in Emeryville.
+        let bookings = vec![booking("Desk 1", "SF", true, "jane"), booking("Fishbowl", "SF", false, "jane")];
+        assert_eq!(plan_booking(&config, &bookings, "sam", "Desk 2").unwrap_err(), "the SF office is full that day, it only fits 1");
+        assert_eq!(plan_booking(&config, &bookings, "sam", "").unwrap_err(), "the SF office is full that day, it only fits 1");
+        assert_eq!(plan_booking(&config, &bookings, "sam", "Desk 9").unwrap().name, "Desk 9");
+        assert_eq!(plan_booking(&config, &bookings, "sam", "fishbowl").unwrap_err(), "`Fishbowl` is already booked by jane that day");
+
+        // Jane can move desks, but needs to say which one.
+        assert_eq!(plan_booking(&config, &bookings, "jane", "").unwrap_err(), "you already have desk `Desk 1` that day");
+        assert_eq!(plan_booking(&config, &bookings, "jane", "Desk 2").unwrap().name, "Desk 2");
+        assert_eq!(config.desks.capacity_for("Emeryville", &config.resources), 1);
+    }
+
+    #[test]
+    fn test_parse_booking_day() {
+        // A Wednesday.
+        let today = NaiveDate::from_ymd(2021, 5, 19);
+        assert_eq!(parse_booking_day("", today).unwrap(), today);
+        assert_eq!(parse_booking_day("Tomorrow", today).unwrap(), NaiveDate::from_ymd(2021, 5, 20));
+        assert_eq!(parse_booking_day("friday", today).unwrap(), NaiveDate::from_ymd(2021, 5, 21));
+        assert_eq!(parse_booking_day("wednesday", today).unwrap(), NaiveDate::from_ymd(2021, 5, 26));
+        assert_eq!(parse_booking_day("2021-06-01", today).unwrap(), NaiveDate::from_ymd(2021, 6, 1));
+        assert!(parse_booking_day("fishbowl", today).is_err());
+    }
+}
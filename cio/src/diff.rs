@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::configs::{Config, Groups, Users};
+use crate::db::Database;
+use crate::finance::SoftwareVendors;
+
+/// The fields that are specific to where a record is stored, or when it was synced or modified, so we never diff them.
+static IGNORED_FIELDS: &[&str] = &["id", "airtable_record_id", "source", "last_synced_from", "last_synced_at", "modified_at"];
+
+/// A field that has a different value in at least two sources.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDiff {
+    pub field: String,
+    /// The value of the field in each source that has it, in order of precedence.
+    pub values: Vec<(String, Value)>,
+    /// The source whose value would win on the next sync.
+    pub winner: String,
+}
+
+/// The differences for a single record between our sources.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordDiff {
+    pub key: String,
+    /// The sources that do not have the record at all.
+    pub missing: Vec<String>,
+    pub fields: Vec<FieldDiff>,
+}
+
+impl RecordDiff {
+    /// Returns if the record is the same in every source.
+    #[instrument]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.fields.is_empty()
+    }
+}
+
+/// Returns if a value is empty. Airtable leaves empty fields out of records, and we skip
+/// serializing empty fields, so an empty value is the same as not having the field.
+fn is_empty_value(v: &Value) -> bool {
+    match v {
+        Value::Null => true,
+        Value::Bool(b) => !b,
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        Value::Number(_) => false,
+    }
+}
+
+/// Diff the fields of a record between sources. The sources are given in order of
+/// precedence under our sync rules, so the first source wins. We compare every field
+/// any source has, and a source that does not have a field has it as empty, since
+/// syncing from that source would clear the field.
+#[instrument]
+#[inline]
+pub fn diff_fields(sources: &[(&str, Value)]) -> Vec<FieldDiff> {
+    let mut fields: BTreeSet<String> = Default::default();
+    for (_, v) in sources {
+        if let Value::Object(o) = v {
+            fields.extend(o.keys().cloned());
+        }
+    }
+
+    let mut diffs: Vec<FieldDiff> = Default::default();
+    for field in fields {
+        if IGNORED_FIELDS.contains(&field.as_str()) {
+            continue;
+        }
+
+        let values: Vec<(String, Value)> = sources.iter().map(|(source, v)| (source.to_string(), v.get(&field).cloned().unwrap_or(Value::Null))).collect();
+        if values.windows(2).all(|w| w[0].1 == w[1].1 || (is_empty_value(&w[0].1) && is_empty_value(&w[1].1))) {
+            continue;
+        }
+
+        diffs.push(FieldDiff {
+            field,
+            winner: values[0].0.to_string(),
+            values,
+        });
+    }
+
+    diffs
+}
+
+/// Diff records keyed the same way from each source. The sources are given in order of precedence.
+#[instrument(skip(sources))]
+#[inline]
+pub fn diff_records(sources: Vec<(&str, BTreeMap<String, Value>)>) -> Vec<RecordDiff> {
+    let mut keys: BTreeSet<String> = Default::default();
+    for (_, records) in sources.iter() {
+        keys.extend(records.keys().cloned());
+    }
+
+    let mut diffs: Vec<RecordDiff> = Default::default();
+    for key in keys {
+        let mut missing: Vec<String> = Default::default();
+        let mut present: Vec<(&str, Value)> = Default::default();
+        for (source, records) in sources.iter() {
+            match records.get(&key) {
+                Some(v) => present.push((*source, v.clone())),
+                None => missing.push(source.to_string()),
+            }
+        }
+
+        let diff = RecordDiff {
+            key,
+            missing,
+            fields: diff_fields(&present),
+        };
+        if !diff.is_empty() {
+            diffs.push(diff);
+        }
+    }
+
+    diffs
+}
+
+/// Serialize records into a map keyed by the given field.
+fn to_map<T: Serialize>(records: impl IntoIterator<Item = T>, key: &str) -> BTreeMap<String, Value> {
+    let mut map: BTreeMap<String, Value> = Default::default();
+    for record in records {
+        let v = serde_json::to_value(record).unwrap_or_default();
+        let k = match &v[key] {
+            Value::String(s) => s.to_string(),
+            other => other.to_string(),
+        };
+        map.insert(k, v);
+    }
+
+    map
+}
+
+/// Diff the software vendors between Airtable and the database.
+/// Airtable is the source of truth for vendors.
+#[instrument(skip(db))]
+#[inline]
+pub async fn diff_vendors(db: &Database) -> Vec<RecordDiff> {
+    let airtable = to_map(SoftwareVendors::get_from_airtable().await.into_iter().map(|(_, r)| r.fields), "name");
+    let db_records = to_map(SoftwareVendors::get_from_db(db), "name");
+
+    diff_records(vec![("airtable", airtable), ("db", db_records)])
+}
+
+/// Diff the users between our configs, the database, and Airtable.
+/// Configs are the source of truth, then the database is pushed to Airtable.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn diff_users(db: &Database, config: &Config) -> Vec<RecordDiff> {
+    let configs = to_map(config.users.values(), "username");
+    let db_records = to_map(Users::get_from_db(db), "username");
+    let airtable = to_map(Users::get_from_airtable().await.into_iter().map(|(_, r)| r.fields), "username");
+
+    diff_records(vec![("configs", configs), ("db", db_records), ("airtable", airtable)])
+}
+
+/// Diff the groups between our configs, the database, and Airtable.
+/// Configs are the source of truth, then the database is pushed to Airtable.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn diff_groups(db: &Database, config: &Config) -> Vec<RecordDiff> {
+    let configs = to_map(config.groups.values(), "name");
+    let db_records = to_map(Groups::get_from_db(db), "name");
+    let airtable = to_map(Groups::get_from_airtable().await.into_iter().map(|(_, r)| r.fields), "name");
+
+    diff_records(vec![("configs", configs), ("db", db_records), ("airtable", airtable)])
+}
+
+/// Diff the users and groups in a version of our configs, ie. the head of a pull request
+/// to the configs repo, with the database to show what syncing it would change.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn diff_configs_with_db(db: &Database, config: &Config) -> Vec<(String, Vec<RecordDiff>)> {
+    vec![
+        (
+            "users".to_string(),
+            diff_records(vec![("configs", to_map(config.users.values(), "username")), ("db", to_map(Users::get_from_db(db), "username"))]),
+        ),
+        (
+            "groups".to_string(),
+            diff_records(vec![("configs", to_map(config.groups.values(), "name")), ("db", to_map(Groups::get_from_db(db), "name"))]),
+        ),
+    ]
+}
+
+/// Format the diffs to print in a terminal.
+#[instrument]
+#[inline]
+pub fn format_record_diffs(diffs: &[RecordDiff]) -> String {
+    if diffs.is_empty() {
+        return "no differences\n".to_string();
+    }
+
+    let mut text = String::new();
+    for diff in diffs {
+        text += &format!("{}\n", diff.key);
+        for source in diff.missing.iter() {
+            text += &format!("  missing from {}\n", source);
+        }
+        for field in diff.fields.iter() {
+            text += &format!("  {}:\n", field.field);
+            for (source, value) in field.values.iter() {
+                let mut marker = " ";
+                if *source == field.winner {
+                    marker = "*";
+                }
+                text += &format!("    {} {:<9} {}\n", marker, source, value);
+            }
+        }
+    }
+    text += "\n* marks the source that wins on the next sync\n";
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use crate::diff::{diff_records, format_record_diffs};
+
+    #[test]
+    fn test_diff_records() {
+        let mut configs = BTreeMap::new();
+        configs.insert("jane".to_string(), json!({"username": "jane", "github": "jane"}));
+        configs.insert("joe".to_string(), json!({"username": "joe"}));
+        configs.insert("sam".to_string(), json!({"username": "sam", "github": "sam"}));
+        let mut db = BTreeMap::new();
+        db.insert("jane".to_string(), json!({"id": 1, "username": "jane", "github": "jane-old", "aliases": ["j"]}));
+        db.insert("joe".to_string(), json!({"id": 2, "username": "joe"}));
+        // Missing and empty fields are the same.
+        db.insert("sam".to_string(), json!({"id": 3, "username": "sam", "github": "sam", "aliases": [], "is_admin": false}));
+        let mut airtable = BTreeMap::new();
+        airtable.insert("jane".to_string(), json!({"id": 1, "username": "jane", "github": "jane-old", "aliases": []}));
+        airtable.insert("sam".to_string(), json!({"id": 3, "username": "sam"}));
+
+        let diffs = diff_records(vec![("configs", configs), ("db", db), ("airtable", airtable)]);
+        assert_eq!(diffs.len(), 2);
+
+        assert_eq!(diffs[0].key, "jane");
+        assert!(diffs[0].missing.is_empty());
+        assert_eq!(diffs[0].fields.len(), 2);
+        assert_eq!(diffs[0].fields[0].field, "aliases");
+        // Configs don't have the field, so syncing them clears it.
+        assert_eq!(diffs[0].fields[0].winner, "configs");
+        assert_eq!(diffs[0].fields[0].values[0], ("configs".to_string(), serde_json::Value::Null));
+        assert_eq!(diffs[0].fields[1].field, "github");
+        assert_eq!(diffs[0].fields[1].winner, "configs");
+
+        assert_eq!(diffs[1].key, "joe");
+        assert_eq!(diffs[1].missing, vec!["airtable".to_string()]);
+
+        assert!(format_record_diffs(&diffs).contains("missing from airtable"));
+    }
+}
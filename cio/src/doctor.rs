@@ -0,0 +1,453 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::future::Future;
+use std::panic;
+
+use chrono::Utc;
+use diesel::pg::PgConnection;
+use diesel::{sql_query, Connection, RunQueryDsl};
+use reqwest::Client;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::airtable_tables;
+use crate::api_tokens::{format_expiry, ApiToken, TOKEN_REMINDER_DAYS};
+use crate::backups::list_gcs_objects;
+use crate::chat::chat_backend_env_vars;
+use crate::messages::{messages_config_file, MessageTemplates, MessagesConfig};
+use crate::reactions::ReactionAction;
+use crate::schema::tokens;
+use crate::schema_drift::{check_schema_drift, get_airtable_base_fields};
+use crate::utils::{authenticate_github_jwt, get_gcp_token, get_gsuite_token};
+
+/// The environment variables we need and what needs them.
+pub static REQUIRED_ENV_VARS: &[(&str, &str)] = &[
+    ("AIRTABLE_API_KEY", "airtable"),
+    ("ANONYMOUS_FEEDBACK_SALT", "anonymous feedback"),
+    ("CIO_BACKUP_BUCKET", "backups"),
+    ("CIO_BACKUP_ENCRYPTION_KEY", "database backups"),
+    ("CIO_DATABASE_URL", "the database"),
+    ("GADMIN_ACCOUNT_ID", "google workspace"),
+    ("GADMIN_SUBJECT", "google workspace"),
+    ("GH_APP_ID", "the github app"),
+    ("GH_INSTALLATION_ID", "the github app"),
+    ("GH_PRIVATE_KEY", "the github app"),
+    ("GITHUB_ORG", "github"),
+    ("GITHUB_TOKEN", "the github rest api"),
+    ("SENDGRID_API_KEY", "email"),
+    ("SLACK_TOKEN", "slack"),
+];
+
+/// The scopes the `GITHUB_TOKEN` needs. Each entry is satisfied by any one of its scopes.
+pub static GITHUB_TOKEN_SCOPES: &[&[&str]] = &[&["repo"], &["read:org", "admin:org"]];
+
+/// The result of a single check run by `cio doctor`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub passed: bool,
+    /// If the check passed but needs attention soon, ie. a token that is about to expire.
+    pub warning: bool,
+    /// What was wrong if the check failed, or anything worth knowing if it passed.
+    pub detail: String,
+}
+
+impl DoctorCheck {
+    /// Create a passing check.
+    #[instrument]
+    #[inline]
+    pub fn pass(name: &str, detail: &str) -> Self {
+        DoctorCheck {
+            name: name.to_string(),
+            passed: true,
+            warning: false,
+            detail: detail.to_string(),
+        }
+    }
+
+    /// Create a check that passed but needs attention soon.
+    #[instrument]
+    #[inline]
+    pub fn warn(name: &str, detail: &str) -> Self {
+        DoctorCheck {
+            name: name.to_string(),
+            passed: true,
+            warning: true,
+            detail: detail.to_string(),
+        }
+    }
+
+    /// Create a failing check.
+    #[instrument]
+    #[inline]
+    pub fn fail(name: &str, detail: &str) -> Self {
+        DoctorCheck {
+            name: name.to_string(),
+            passed: false,
+            warning: false,
+            detail: detail.to_string(),
+        }
+    }
+}
+
+/// Check the fields of every model we sync match the fields of its Airtable table.
+/// Fields in code that Airtable does not have fail the check since Airtable rejects
+/// records with unknown fields. Fields only in Airtable are reported but pass.
+#[instrument]
+#[inline]
+pub async fn check_airtable_schemas() -> Vec<DoctorCheck> {
+    let drifts = match check_schema_drift().await {
+        Ok(d) => d,
+        Err(e) => return vec![DoctorCheck::fail("airtable schemas", &e)],
+    };
+
+    let mut checks: Vec<DoctorCheck> = Default::default();
+    for drift in drifts {
+        let name = format!("airtable schema for {} ({})", drift.model, drift.table);
+        if drift.table_missing {
+            checks.push(DoctorCheck::fail(&name, &format!("table does not exist in base {}", drift.base_id)));
+            continue;
+        }
+
+        let mut detail: Vec<String> = Default::default();
+        if !drift.missing_in_airtable.is_empty() {
+            detail.push(format!("missing in airtable: {}", drift.missing_in_airtable.join(", ")));
+        }
+        if !drift.missing_in_code.is_empty() {
+            detail.push(format!("only in airtable: {}", drift.missing_in_code.join(", ")));
+        }
+
+        if drift.is_breaking() {
+            checks.push(DoctorCheck::fail(&name, &detail.join("; ")));
+        } else {
+            checks.push(DoctorCheck::pass(&name, &detail.join("; ")));
+        }
+    }
+
+    checks
+}
+
+/// Run a future that panics when it fails, like most of our helpers for getting
+/// clients, and return the panic as an error instead.
+#[instrument(skip(f))]
+#[inline]
+pub async fn catch_panic<T: Send + 'static>(f: impl Future<Output = T> + Send + 'static) -> Result<T, String> {
+    tokio::spawn(f).await.map_err(|e| format!("{}, see the error above", e))
+}
+
+/// Check the environment variables we need are set and are valid where we can tell.
+#[instrument]
+#[inline]
+pub fn check_env_vars() -> Vec<DoctorCheck> {
+    let mut checks: Vec<DoctorCheck> = Default::default();
+    for (var, needed_by) in REQUIRED_ENV_VARS {
+        let name = format!("env {}", var);
+        if env::var(var).unwrap_or_default().is_empty() {
+            checks.push(DoctorCheck::fail(&name, &format!("not set, it is needed for {}", needed_by)));
+        } else {
+            checks.push(DoctorCheck::pass(&name, ""));
+        }
+    }
+
+    if env::var("GADMIN_CREDENTIAL_FILE").unwrap_or_default().is_empty() && env::var("GSUITE_KEY_ENCODED").unwrap_or_default().is_empty() {
+        checks.push(DoctorCheck::fail("env GADMIN_CREDENTIAL_FILE", "neither GADMIN_CREDENTIAL_FILE or GSUITE_KEY_ENCODED is set"));
+    } else {
+        checks.push(DoctorCheck::pass("env GADMIN_CREDENTIAL_FILE", ""));
+    }
+
+    if let Ok(key) = env::var("CIO_BACKUP_ENCRYPTION_KEY") {
+        match base64::decode(&key) {
+            Ok(k) if k.len() == 32 => (),
+            _ => checks.push(DoctorCheck::fail("backup encryption key", "CIO_BACKUP_ENCRYPTION_KEY must be a base64 encoded 256 bit key")),
+        }
+    }
+
+    for var in chat_backend_env_vars() {
+        let name = format!("chat {}", var);
+        let value = env::var(&var).unwrap_or_default();
+        if value.is_empty() {
+            checks.push(DoctorCheck::fail(&name, "not set"));
+        } else if var.starts_with("SLACK_") && !value.starts_with("https://hooks.slack.com/") {
+            checks.push(DoctorCheck::fail(&name, "not a slack incoming webhook url"));
+        } else if var.starts_with("DISCORD_") && !value.starts_with("https://discord.com/api/webhooks/") {
+            checks.push(DoctorCheck::fail(&name, "not a discord webhook url"));
+        } else {
+            checks.push(DoctorCheck::pass(&name, ""));
+        }
+    }
+
+    checks
+}
+
+/// Check we can connect to and query the database.
+#[instrument]
+#[inline]
+pub fn check_database() -> DoctorCheck {
+    let url = env::var("CIO_DATABASE_URL").unwrap_or_default();
+    let conn = match PgConnection::establish(&url) {
+        Ok(c) => c,
+        Err(e) => return DoctorCheck::fail("database", &format!("connecting failed: {}", e)),
+    };
+
+    match sql_query("SELECT 1").execute(&conn) {
+        Ok(_) => DoctorCheck::pass("database", ""),
+        Err(e) => DoctorCheck::fail("database", &format!("querying failed: {}", e)),
+    }
+}
+
+/// Check none of the API tokens in our inventory have expired or expire in the next
+/// `TOKEN_REMINDER_DAYS`. Tokens about to expire are warnings, expired tokens fail.
+#[instrument]
+#[inline]
+pub fn check_api_tokens() -> Vec<DoctorCheck> {
+    let url = env::var("CIO_DATABASE_URL").unwrap_or_default();
+    let api_tokens = match PgConnection::establish(&url)
+        .map_err(|e| e.to_string())
+        .and_then(|c| tokens::dsl::tokens.load::<ApiToken>(&c).map_err(|e| e.to_string()))
+    {
+        Ok(t) => t,
+        Err(e) => return vec![DoctorCheck::fail("api tokens", &format!("reading the inventory failed: {}", e))],
+    };
+
+    let today = Utc::now().date().naive_utc();
+    let mut checks: Vec<DoctorCheck> = Default::default();
+    for token in api_tokens.iter() {
+        let name = format!("api token {}", token.name);
+        let detail = format!("the {} token owned by {}", token.service, token.owner);
+        match token.days_left(today) {
+            Some(d) if d < 0 => checks.push(DoctorCheck::fail(&name, &format!("{} {}", detail, format_expiry(d)))),
+            Some(d) if d <= TOKEN_REMINDER_DAYS => checks.push(DoctorCheck::warn(&name, &format!("{} {}, rotate it", detail, format_expiry(d)))),
+            _ => (),
+        }
+    }
+
+    if checks.is_empty() {
+        checks.push(DoctorCheck::pass(
+            "api tokens",
+            &format!("none of the {} tokens expire in the next {} days", api_tokens.len(), TOKEN_REMINDER_DAYS),
+        ));
+    }
+
+    checks
+}
+
+/// Check every Airtable base we use exists and has the tables we sync to.
+#[instrument]
+#[inline]
+pub async fn check_airtable_tables() -> Vec<DoctorCheck> {
+    let mut bases: BTreeMap<&str, Vec<&str>> = Default::default();
+    for (base_id, table) in airtable_tables() {
+        bases.entry(base_id).or_default().push(table);
+    }
+
+    let mut checks: Vec<DoctorCheck> = Default::default();
+    for (base_id, tables) in bases {
+        let fields = match get_airtable_base_fields(base_id).await {
+            Ok(f) => f,
+            Err(e) => {
+                checks.push(DoctorCheck::fail(&format!("airtable base {}", base_id), &e));
+                continue;
+            }
+        };
+
+        for table in tables {
+            let name = format!("airtable table {}/{}", base_id, table);
+            if fields.contains_key(table) {
+                checks.push(DoctorCheck::pass(&name, ""));
+            } else {
+                checks.push(DoctorCheck::fail(&name, "table does not exist"));
+            }
+        }
+    }
+
+    checks
+}
+
+/// Check the GitHub app can authenticate and read our org and configs repo, and the
+/// `GITHUB_TOKEN` has the scopes we need.
+#[instrument]
+#[inline]
+pub async fn check_github() -> Vec<DoctorCheck> {
+    let mut checks: Vec<DoctorCheck> = Default::default();
+    let org = env::var("GITHUB_ORG").unwrap_or_default();
+
+    match panic::catch_unwind(authenticate_github_jwt) {
+        Ok(github) => {
+            match github.org(&org).get().await {
+                Ok(_) => checks.push(DoctorCheck::pass("github app org access", "")),
+                Err(e) => checks.push(DoctorCheck::fail("github app org access", &e.to_string())),
+            }
+            match github.repo(&org, "configs").get().await {
+                Ok(_) => checks.push(DoctorCheck::pass("github app configs repo access", "")),
+                Err(e) => checks.push(DoctorCheck::fail("github app configs repo access", &e.to_string())),
+            }
+        }
+        Err(_) => checks.push(DoctorCheck::fail("github app", "authenticating failed, see the error above")),
+    }
+
+    let resp = Client::new()
+        .get(&format!("https://api.github.com/orgs/{}", org))
+        .header(reqwest::header::USER_AGENT, concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .bearer_auth(env::var("GITHUB_TOKEN").unwrap_or_default())
+        .send()
+        .await;
+    match resp {
+        Ok(r) if r.status().is_success() => match r.headers().get("x-oauth-scopes").and_then(|h| h.to_str().ok()) {
+            Some(scopes) => {
+                let scopes: Vec<&str> = scopes.split(',').map(|s| s.trim()).collect();
+                let missing: Vec<String> = GITHUB_TOKEN_SCOPES.iter().filter(|any| !any.iter().any(|s| scopes.contains(s))).map(|any| any.join(" or ")).collect();
+                if missing.is_empty() {
+                    checks.push(DoctorCheck::pass("github token scopes", ""));
+                } else {
+                    checks.push(DoctorCheck::fail("github token scopes", &format!("missing scopes: {}", missing.join(", "))));
+                }
+            }
+            // Fine-grained tokens do not tell us their permissions.
+            None => checks.push(DoctorCheck::pass("github token scopes", "the token does not report its scopes")),
+        },
+        Ok(r) => checks.push(DoctorCheck::fail("github token scopes", &format!("reading the org failed with status {}", r.status()))),
+        Err(e) => checks.push(DoctorCheck::fail("github token scopes", &e.to_string())),
+    }
+
+    checks
+}
+
+/// Check we can get tokens for Google Workspace and GCP with the scopes we need,
+/// and that we can use the backups bucket.
+#[instrument]
+#[inline]
+pub async fn check_google() -> Vec<DoctorCheck> {
+    let mut checks: Vec<DoctorCheck> = Default::default();
+
+    match catch_panic(async { get_gsuite_token("").await }).await {
+        Ok(_) => checks.push(DoctorCheck::pass("google workspace token", "")),
+        Err(e) => checks.push(DoctorCheck::fail("google workspace token", &e)),
+    }
+
+    match catch_panic(async { get_gcp_token(&["https://www.googleapis.com/auth/bigquery.readonly"]).await }).await {
+        Ok(_) => checks.push(DoctorCheck::pass("gcp bigquery token", "")),
+        Err(e) => checks.push(DoctorCheck::fail("gcp bigquery token", &e)),
+    }
+
+    match catch_panic(async { list_gcs_objects(&env::var("CIO_BACKUP_BUCKET").unwrap_or_default(), "db/").await }).await {
+        Ok(Ok(_)) => checks.push(DoctorCheck::pass("backups bucket", "")),
+        Ok(Err(e)) | Err(e) => checks.push(DoctorCheck::fail("backups bucket", &e)),
+    }
+
+    checks
+}
+
+/// Check the Slack token is valid.
+#[instrument]
+#[inline]
+pub async fn check_slack() -> DoctorCheck {
+    let resp = Client::new()
+        .post("https://slack.com/api/auth.test")
+        .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+        .send()
+        .await;
+    let body: Value = match resp {
+        Ok(r) => r.json().await.unwrap_or_default(),
+        Err(e) => return DoctorCheck::fail("slack token", &e.to_string()),
+    };
+
+    if body["ok"].as_bool().unwrap_or(false) {
+        DoctorCheck::pass("slack token", &format!("authenticated to {}", body["team"].as_str().unwrap_or_default()))
+    } else {
+        DoctorCheck::fail("slack token", body["error"].as_str().unwrap_or("authenticating failed"))
+    }
+}
+
+/// Check the overrides in the messages config parse and are for event types we have,
+/// and the reactions are for actions we have.
+#[instrument]
+#[inline]
+pub fn check_message_templates() -> DoctorCheck {
+    let file = messages_config_file();
+    if let Err(e) = MessageTemplates::from_config_file(&file) {
+        return DoctorCheck::fail("message templates", &e);
+    }
+
+    let config = MessagesConfig::read(&file).unwrap_or_default();
+    for action in config.reactions.values() {
+        if let Err(e) = action.parse::<ReactionAction>() {
+            return DoctorCheck::fail("message templates", &e);
+        }
+    }
+
+    DoctorCheck::pass("message templates", &file)
+}
+
+/// Run all the checks.
+#[instrument]
+#[inline]
+pub async fn run_doctor() -> Vec<DoctorCheck> {
+    let mut checks = check_env_vars();
+    checks.push(check_database());
+    checks.append(&mut check_api_tokens());
+    checks.append(&mut check_airtable_tables().await);
+    checks.append(&mut check_airtable_schemas().await);
+    checks.append(&mut check_github().await);
+    checks.append(&mut check_google().await);
+    checks.push(check_slack().await);
+    checks.push(check_message_templates());
+
+    checks
+}
+
+/// Format the checks as a table to print in a terminal.
+#[instrument]
+#[inline]
+pub fn format_doctor_report(checks: &[DoctorCheck]) -> String {
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or_default();
+
+    let mut text = String::new();
+    for check in checks {
+        let status = if !check.passed {
+            "FAIL"
+        } else if check.warning {
+            "WARN"
+        } else {
+            "PASS"
+        };
+        text += format!("{}  {:<width$}  {}", status, check.name, check.detail, width = width).trim_end();
+        text += "\n";
+    }
+
+    let failed = checks.iter().filter(|c| !c.passed).count();
+    let warnings = checks.iter().filter(|c| c.warning).count();
+    if warnings > 0 {
+        text += &format!("\n{} checks, {} failed, {} warnings\n", checks.len(), failed, warnings);
+    } else {
+        text += &format!("\n{} checks, {} failed\n", checks.len(), failed);
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doctor::{format_doctor_report, DoctorCheck};
+
+    #[test]
+    fn test_format_doctor_report() {
+        let checks = vec![
+            DoctorCheck::pass("database", ""),
+            DoctorCheck::fail("airtable schema for User (Employees)", "missing in airtable: timezone"),
+        ];
+
+        assert_eq!(
+            format_doctor_report(&checks),
+            "PASS  database\nFAIL  airtable schema for User (Employees)  missing in airtable: timezone\n\n2 checks, 1 failed\n"
+        );
+
+        let checks = vec![
+            DoctorCheck::pass("database", ""),
+            DoctorCheck::warn("api token github", "the GitHub token owned by jess expires in 3 days, rotate it"),
+        ];
+        assert_eq!(
+            format_doctor_report(&checks),
+            "PASS  database\nWARN  api token github  the GitHub token owned by jess expires in 3 days, rotate it\n\n2 checks, 0 failed, 1 warnings\n"
+        );
+    }
+}
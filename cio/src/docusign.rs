@@ -0,0 +1,497 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::{Client, Method};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::applicants::Applicant;
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::db::Database;
+use crate::finance::SoftwareVendor;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::offers::{get_offer_for_letter, OFFER_LETTER_TEMPLATE};
+use crate::rate_limits::check_throttled;
+use crate::schema::{applicants, docusign_envelopes, software_vendors};
+use crate::vcr;
+use crate::visitors::VISITOR_NDA_TEMPLATE;
+
+/// The statuses of an envelope that is still waiting on its signer.
+pub static ENVELOPE_OPEN_STATUSES: &[&str] = &["sent", "delivered"];
+
+/// The statuses of an envelope the signer is done with, one way or another.
+pub static ENVELOPE_CLOSED_STATUSES: &[&str] = &["completed", "declined", "voided"];
+
+/// How many days we wait for a signer before reminding them, if the `docusign` section
+/// of our configs does not say.
+const DEFAULT_REMIND_AFTER_DAYS: i64 = 3;
+
+/// The DocuSign templates we send envelopes from, from the `docusign` section of our
+/// configs, keyed by the name we send them by.
+///
+/// ```toml
+/// [docusign]
+/// remind_after_days = 3
+///
+/// [docusign.templates]
+/// offer_letter = "8f3e2a1c-6b0d-4c8e-9f4a-2d7b5e1c9a30"
+/// nda = "1b9d6e4f-3a2c-4e7d-8b5f-0c6a9d2e7f14"
+/// vendor_dpa = "5c7a0e2b-9d4f-4b1e-a6c3-8e2f1d9b4a67"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct DocuSignConfig {
+    /// The ID of the template in DocuSign for each template name.
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+    /// How many days to wait for a signer before reminding them, and between reminders.
+    /// Defaults to 3.
+    #[serde(default)]
+    pub remind_after_days: i64,
+}
+
+impl DocuSignConfig {
+    /// Get how long to wait for a signer before reminding them.
+    #[instrument]
+    #[inline]
+    pub fn remind_after(&self) -> Duration {
+        if self.remind_after_days > 0 {
+            Duration::days(self.remind_after_days)
+        } else {
+            Duration::days(DEFAULT_REMIND_AFTER_DAYS)
+        }
+    }
+}
+
+/// An envelope we sent from a template, linked to the applicant or software vendor it
+/// is for, if any.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "docusign_envelopes"]
+pub struct NewDocuSignEnvelope {
+    pub envelope_id: String,
+    /// The name of the template in the `docusign` section of our configs.
+    pub template: String,
+    pub applicant_id: Option<i32>,
+    pub software_vendor_id: Option<i32>,
+    pub signer_name: String,
+    pub signer_email: String,
+    /// The status of the envelope in DocuSign, ie. `sent` or `completed`.
+    pub status: String,
+    pub sent_at: DateTime<Utc>,
+    pub status_changed_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub reminded_at: Option<DateTime<Utc>>,
+    pub reminders: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, AsChangeset)]
+#[table_name = "docusign_envelopes"]
+pub struct DocuSignEnvelope {
+    pub id: i32,
+    pub envelope_id: String,
+    pub template: String,
+    pub applicant_id: Option<i32>,
+    pub software_vendor_id: Option<i32>,
+    pub signer_name: String,
+    pub signer_email: String,
+    pub status: String,
+    pub sent_at: DateTime<Utc>,
+    pub status_changed_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub reminded_at: Option<DateTime<Utc>>,
+    pub reminders: i32,
+}
+
+impl DocuSignEnvelope {
+    /// Set the status of the envelope from a Connect event. DocuSign does not promise
+    /// to deliver events in order, so events older than the status we have are ignored.
+    /// Returns if the status changed.
+    #[instrument]
+    #[inline]
+    pub fn apply_status(&mut self, status: &str, at: DateTime<Utc>) -> bool {
+        if at < self.status_changed_at || self.status == status {
+            return false;
+        }
+
+        self.status = status.to_string();
+        self.status_changed_at = at;
+        if status == "completed" {
+            self.completed_at = Some(at);
+        }
+
+        true
+    }
+
+    /// Returns if the signer needs a reminder, which is when the envelope has been
+    /// waiting on them for longer than `remind_after` since it was sent or since we last
+    /// reminded them.
+    #[instrument]
+    #[inline]
+    pub fn needs_reminder(&self, now: DateTime<Utc>, remind_after: Duration) -> bool {
+        if !ENVELOPE_OPEN_STATUSES.contains(&self.status.as_str()) {
+            return false;
+        }
+
+        now - self.reminded_at.unwrap_or(self.sent_at) >= remind_after
+    }
+
+    /// The channel we post about the envelope to, hiring for applicants, the office for
+    /// visitor NDAs, and finance for everything else.
+    #[instrument]
+    #[inline]
+    pub fn chat_channel(&self) -> ChatChannel {
+        if self.applicant_id.is_some() {
+            ChatChannel::Hiring
+        } else if self.template == VISITOR_NDA_TEMPLATE {
+            ChatChannel::Office
+        } else {
+            ChatChannel::Finance
+        }
+    }
+}
+
+/// An event from DocuSign Connect, sent in the JSON format with the envelope ID only.
+/// FROM: https://developers.docusign.com/platform/webhooks/connect/json-sim-event-model/
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocuSignConnectEvent {
+    /// The type of event, ie. `envelope-completed` or `recipient-delivered`.
+    pub event: String,
+    pub generated_date_time: DateTime<Utc>,
+    #[serde(default)]
+    pub data: DocuSignConnectEventData,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocuSignConnectEventData {
+    #[serde(default)]
+    pub envelope_id: String,
+}
+
+impl DocuSignConnectEvent {
+    /// Get the status of the envelope the event is for, ie. `completed` for
+    /// `envelope-completed`. Events for recipients or that do not change the status,
+    /// like `envelope-resent`, return `None`.
+    #[instrument]
+    #[inline]
+    pub fn envelope_status(&self) -> Option<&str> {
+        let status = self.event.strip_prefix("envelope-")?;
+        if ENVELOPE_OPEN_STATUSES.contains(&status) || ENVELOPE_CLOSED_STATUSES.contains(&status) {
+            Some(status)
+        } else {
+            None
+        }
+    }
+
+    /// The ID we dedupe deliveries of the event by, since Connect retries events and
+    /// does not send an ID of its own.
+    #[instrument]
+    #[inline]
+    pub fn delivery_id(&self) -> String {
+        format!("{}:{}:{}", self.data.envelope_id, self.event, self.generated_date_time.to_rfc3339())
+    }
+}
+
+/// Send a request to the DocuSign eSignature API for our account. This uses the
+/// `DOCUSIGN_BASE_URI`, `DOCUSIGN_ACCOUNT_ID`, and `DOCUSIGN_ACCESS_TOKEN` environment
+/// variables.
+#[instrument(skip(body))]
+#[inline]
+async fn docusign_api(method: Method, path: &str, body: Value) -> Result<Value, String> {
+    let url = format!(
+        "{}/restapi/v2.1/accounts/{}{}",
+        env::var("DOCUSIGN_BASE_URI").unwrap_or_default().trim_end_matches('/'),
+        env::var("DOCUSIGN_ACCOUNT_ID").unwrap_or_default(),
+        path
+    );
+    let client = Client::new();
+    let req = client.request(method, &url).bearer_auth(env::var("DOCUSIGN_ACCESS_TOKEN").unwrap_or_default()).json(&body);
+    let resp = vcr::send(&client, req).await.map_err(|e| e.to_string())?;
+    check_throttled("docusign", &resp)?;
+
+    let status = resp.status();
+    let body: Value = resp.json().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(format!("docusign {} failed: {} {}", path, status, body["message"].as_str().unwrap_or_default()));
+    }
+
+    Ok(body)
+}
+
+/// Send an envelope from one of the templates in our configs to a signer, linked to
+/// the applicant or software vendor it is for. The signer is the `signer` role of the
+/// template.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_envelope(
+    db: &Database,
+    config: &Config,
+    template: &str,
+    signer_name: &str,
+    signer_email: &str,
+    applicant_id: Option<i32>,
+    software_vendor_id: Option<i32>,
+) -> Result<DocuSignEnvelope, String> {
+    let template_id = config.docusign.templates.get(template).ok_or_else(|| {
+        format!(
+            "`{}` is not one of our docusign templates, which are {}",
+            template,
+            config.docusign.templates.keys().cloned().collect::<Vec<_>>().join(", ")
+        )
+    })?;
+
+    let mut role = json!({ "roleName": "signer", "name": signer_name, "email": signer_email });
+    if template == OFFER_LETTER_TEMPLATE {
+        // We only generate offer letters for offers in their band, or that were approved.
+        let applicant_id = applicant_id.ok_or_else(|| format!("the {} can only be sent to an applicant", template))?;
+        let offer = get_offer_for_letter(db, applicant_id)?;
+        let tabs: Vec<Value> = offer.letter_fields().into_iter().map(|(label, value)| json!({ "tabLabel": label, "value": value })).collect();
+        role["tabs"] = json!({ "textTabs": tabs });
+    }
+
+    let resp = docusign_api(
+        Method::POST,
+        "/envelopes",
+        json!({
+            "templateId": template_id,
+            "templateRoles": [role],
+            "status": "sent",
+        }),
+    )
+    .await?;
+    let envelope_id = resp["envelopeId"].as_str().unwrap_or_default();
+    if envelope_id.is_empty() {
+        return Err(format!("docusign did not return an envelope ID for the {} to {}", template, signer_email));
+    }
+
+    let now = Utc::now();
+    diesel::insert_into(docusign_envelopes::table)
+        .values(&NewDocuSignEnvelope {
+            envelope_id: envelope_id.to_string(),
+            template: template.to_string(),
+            applicant_id,
+            software_vendor_id,
+            signer_name: signer_name.to_string(),
+            signer_email: signer_email.to_string(),
+            status: resp["status"].as_str().unwrap_or("sent").to_string(),
+            sent_at: now,
+            status_changed_at: now,
+            completed_at: None,
+            reminded_at: None,
+            reminders: 0,
+        })
+        .get_result(&db.conn())
+        .map_err(|e| format!("saving envelope {} failed: {}", envelope_id, e))
+}
+
+/// Send an envelope to an applicant, by the email they applied with.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_envelope_to_applicant(db: &Database, config: &Config, template: &str, email: &str) -> Result<DocuSignEnvelope, String> {
+    let applicant = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.to_string()))
+        .order_by(applicants::dsl::submitted_time.desc())
+        .first::<Applicant>(&db.conn())
+        .map_err(|_| format!("there is no applicant with the email {}", email))?;
+
+    send_envelope(db, config, template, &applicant.name, &applicant.email, Some(applicant.id), None).await
+}
+
+/// Send an envelope to someone at a software vendor, by the name of the vendor.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_envelope_to_vendor(db: &Database, config: &Config, template: &str, vendor: &str, signer_name: &str, signer_email: &str) -> Result<DocuSignEnvelope, String> {
+    let vendor = software_vendors::dsl::software_vendors
+        .filter(software_vendors::dsl::name.eq(vendor.to_string()))
+        .first::<SoftwareVendor>(&db.conn())
+        .map_err(|_| format!("there is no software vendor named {}", vendor))?;
+
+    send_envelope(db, config, template, signer_name, signer_email, None, Some(vendor.id)).await
+}
+
+/// Get the name of the applicant or software vendor an envelope is for, for messages.
+#[instrument(skip(db))]
+#[inline]
+fn envelope_record_name(db: &Database, envelope: &DocuSignEnvelope) -> String {
+    if let Some(id) = envelope.applicant_id {
+        if let Ok(a) = applicants::dsl::applicants.find(id).first::<Applicant>(&db.conn()) {
+            return format!("{} ({})", a.name, a.role);
+        }
+    }
+    if let Some(id) = envelope.software_vendor_id {
+        if let Ok(v) = software_vendors::dsl::software_vendors.find(id).first::<SoftwareVendor>(&db.conn()) {
+            return v.name;
+        }
+    }
+
+    envelope.signer_name.to_string()
+}
+
+/// Update the status of an envelope from a DocuSign Connect event, posting when the
+/// signer completes, declines, or the envelope is voided. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn handle_docusign_event(db: &Database, event: &DocuSignConnectEvent) -> Result<String, String> {
+    let status = match event.envelope_status() {
+        Some(s) => s,
+        None => return Ok(format!("ignoring {} event", event.event)),
+    };
+
+    let mut envelope = docusign_envelopes::table
+        .filter(docusign_envelopes::envelope_id.eq(event.data.envelope_id.to_string()))
+        .first::<DocuSignEnvelope>(&db.conn())
+        .map_err(|_| format!("envelope {} is not one we sent", event.data.envelope_id))?;
+    if !envelope.apply_status(status, event.generated_date_time) {
+        return Ok(format!("envelope {} is already {}", envelope.envelope_id, envelope.status));
+    }
+
+    diesel::update(docusign_envelopes::table.find(envelope.id))
+        .set(&envelope)
+        .execute(&db.conn())
+        .map_err(|e| format!("updating envelope {} failed: {}", envelope.envelope_id, e))?;
+
+    if ENVELOPE_CLOSED_STATUSES.contains(&status) {
+        notify(
+            db,
+            NotificationPriority::Low,
+            envelope.chat_channel(),
+            "docusign.status",
+            json!({
+                "text": render_message(
+                    "docusign.status",
+                    &json!({
+                        "template": envelope.template.replace('_', " "),
+                        "name": envelope_record_name(db, &envelope),
+                        "email": envelope.signer_email,
+                        "status": status,
+                    })
+                ),
+            }),
+        )
+        .await;
+    }
+
+    Ok(format!("envelope {} is now {}", envelope.envelope_id, status))
+}
+
+/// Remind the signers of envelopes that have been waiting on them for longer than
+/// `remind_after_days` in the `docusign` section of our configs, by having DocuSign
+/// send them the envelope again.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn remind_docusign_signers(db: &Database, config: &Config) {
+    let now = Utc::now();
+    let open: Vec<DocuSignEnvelope> = docusign_envelopes::table
+        .filter(docusign_envelopes::status.eq_any(ENVELOPE_OPEN_STATUSES.iter().map(|s| s.to_string()).collect::<Vec<String>>()))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the open docusign envelopes failed: {}", e));
+
+    for mut envelope in open.into_iter().filter(|e| e.needs_reminder(now, config.docusign.remind_after())) {
+        if let Err(e) = docusign_api(Method::PUT, &format!("/envelopes/{}?resend_envelope=true", envelope.envelope_id), json!({})).await {
+            println!("[docusign] reminding {} about envelope {} failed: {}", envelope.signer_email, envelope.envelope_id, e);
+            continue;
+        }
+
+        envelope.reminded_at = Some(now);
+        envelope.reminders += 1;
+        diesel::update(docusign_envelopes::table.find(envelope.id))
+            .set(&envelope)
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("updating envelope {} failed: {}", envelope.envelope_id, e));
+        println!("[docusign] reminded {} about the {} for the {} time", envelope.signer_email, envelope.template, envelope.reminders);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::chat::ChatChannel;
+    use crate::docusign::{DocuSignConfig, DocuSignConnectEvent, DocuSignEnvelope};
+    use crate::visitors::VISITOR_NDA_TEMPLATE;
+
+    fn envelope() -> DocuSignEnvelope {
+        let sent_at = Utc.ymd(2021, 5, 3).and_hms(17, 0, 0);
+        DocuSignEnvelope {
+            id: 1,
+            envelope_id: "93be49ab-cd8d-4d16-9a8e-0b6a2b8f5e1d".to_string(),
+            template: "offer_letter".to_string(),
+            applicant_id: Some(42),
+            software_vendor_id: None,
+            signer_name: "Jane Doe".to_string(),
+            signer_email: "jane@example.com".to_string(),
+            status: "sent".to_string(),
+            sent_at,
+            status_changed_at: sent_at,
+            completed_at: None,
+            reminded_at: None,
+            reminders: 0,
+        }
+    }
+
+    #[test]
+    fn test_envelope_status_from_connect_event() {
+        let event: DocuSignConnectEvent = serde_json::from_str(
+            r#"{
+  "event": "envelope-completed",
+  "apiVersion": "v2.1",
+  "uri": "/restapi/v2.1/accounts/1a2b/envelopes/93be49ab-cd8d-4d16-9a8e-0b6a2b8f5e1d",
+  "retryCount": 0,
+  "configurationId": 10418,
+  "generatedDateTime": "2021-05-04T18:30:12.4410000Z",
+  "data": {
+    "accountId": "1a2b",
+    "envelopeId": "93be49ab-cd8d-4d16-9a8e-0b6a2b8f5e1d"
+  }
+}"#,
+        )
+        .unwrap();
+        assert_eq!(event.envelope_status(), Some("completed"));
+        assert_eq!(event.delivery_id(), "93be49ab-cd8d-4d16-9a8e-0b6a2b8f5e1d:envelope-completed:2021-05-04T18:30:12.441+00:00");
+
+        let mut e = envelope();
+        assert!(e.apply_status("completed", event.generated_date_time));
+        assert_eq!(e.completed_at, Some(event.generated_date_time));
+        // An older event delivered late does not undo it.
+        assert!(!e.apply_status("delivered", Utc.ymd(2021, 5, 4).and_hms(9, 0, 0)));
+        assert_eq!(e.status, "completed");
+        assert_eq!(e.chat_channel(), ChatChannel::Hiring);
+        e.applicant_id = None;
+        assert_eq!(e.chat_channel(), ChatChannel::Finance);
+        e.template = VISITOR_NDA_TEMPLATE.to_string();
+        assert_eq!(e.chat_channel(), ChatChannel::Office);
+
+        for (name, status) in &[("envelope-resent", None), ("recipient-completed", None), ("envelope-declined", Some("declined"))] {
+            let event = DocuSignConnectEvent {
+                event: name.to_string(),
+                ..event.clone()
+            };
+            assert_eq!(event.envelope_status(), *status, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_envelope_needs_reminder() {
+        let config = DocuSignConfig::default();
+        assert_eq!(config.remind_after(), Duration::days(3));
+
+        let mut e = envelope();
+        assert!(!e.needs_reminder(e.sent_at + Duration::days(2), config.remind_after()));
+        assert!(e.needs_reminder(e.sent_at + Duration::days(3), config.remind_after()));
+
+        // Reminders are spaced out from the last one.
+        e.reminded_at = Some(e.sent_at + Duration::days(3));
+        assert!(!e.needs_reminder(e.sent_at + Duration::days(5), config.remind_after()));
+        assert!(e.needs_reminder(e.sent_at + Duration::days(6), config.remind_after()));
+
+        e.status = "completed".to_string();
+        assert!(!e.needs_reminder(e.sent_at + Duration::days(30), config.remind_after()));
+    }
+}
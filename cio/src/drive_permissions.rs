@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::utils::{drive_client, get_gsuite_token, google_api, GSUITE_DOMAIN};
+
+/// The roles a group can have on a shared drive or a folder in one.
+/// FROM: https://developers.google.com/drive/api/v3/ref-roles
+pub static DRIVE_ROLES: &[&str] = &["organizer", "fileOrganizer", "writer", "commenter", "reader"];
+
+static DRIVE_API: &str = "https://www.googleapis.com/drive/v3";
+
+/// The permissions for a shared drive, or a folder in one, keyed by a name in the
+/// `drive_permissions` section of our configs.
+///
+/// ```toml
+/// [drive_permissions.payroll]
+/// drive = "Finance"
+/// folder = "Payroll/2021"
+/// sensitive = true
+/// groups = { finance = "organizer", ops = "reader" }
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct DrivePermissionsConfig {
+    /// The name of the shared drive.
+    pub drive: String,
+    /// The path to the folder in the shared drive, empty for the shared drive itself.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub folder: String,
+    /// The role for each group, the groups not listed lose their access.
+    #[serde(default)]
+    pub groups: BTreeMap<String, String>,
+    /// If we report the files in the folder that are shared with anyone with the link.
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+/// A permission on a shared drive, folder, or file.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct DrivePermission {
+    pub id: String,
+    /// The type of the permission, `user`, `group`, `domain`, or `anyone`.
+    pub kind: String,
+    pub role: String,
+    pub email_address: String,
+    /// If the permission is from the shared drive or a parent folder, these can only be
+    /// changed where they are set.
+    pub inherited: bool,
+}
+
+/// A change to the permissions of a shared drive or folder to make them match our configs.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DrivePermissionChange {
+    Add { email_address: String, role: String },
+    Update { id: String, email_address: String, role: String },
+    Remove { id: String, email_address: String },
+}
+
+/// Parse the permissions from a Drive API permissions list.
+#[instrument]
+#[inline]
+pub fn parse_drive_permissions(permissions: &[Value]) -> Vec<DrivePermission> {
+    permissions
+        .iter()
+        .map(|p| {
+            let details = p["permissionDetails"].as_array().cloned().unwrap_or_default();
+            DrivePermission {
+                id: p["id"].as_str().unwrap_or_default().to_string(),
+                kind: p["type"].as_str().unwrap_or_default().to_string(),
+                role: p["role"].as_str().unwrap_or_default().to_string(),
+                email_address: p["emailAddress"].as_str().unwrap_or_default().to_lowercase(),
+                inherited: !details.is_empty() && details.iter().all(|d| d["inherited"].as_bool().unwrap_or_default()),
+            }
+        })
+        .collect()
+}
+
+/// Plan the changes to make the group permissions match the role for each group email.
+/// Permissions for users, the domain, and anyone are left alone, as are inherited
+/// permissions that already give a group its role.
+#[instrument]
+#[inline]
+pub fn plan_drive_permission_changes(groups: &BTreeMap<String, String>, actual: &[DrivePermission]) -> Vec<DrivePermissionChange> {
+    let mut changes: Vec<DrivePermissionChange> = Default::default();
+    for (email, role) in groups {
+        let email = email.to_lowercase();
+        let direct = actual.iter().find(|p| p.kind == "group" && !p.inherited && p.email_address == email);
+        match direct {
+            Some(p) if p.role == *role => (),
+            Some(p) => changes.push(DrivePermissionChange::Update {
+                id: p.id.to_string(),
+                email_address: email,
+                role: role.to_string(),
+            }),
+            None => {
+                if actual.iter().any(|p| p.kind == "group" && p.inherited && p.email_address == email && p.role == *role) {
+                    continue;
+                }
+
+                changes.push(DrivePermissionChange::Add {
+                    email_address: email,
+                    role: role.to_string(),
+                });
+            }
+        }
+    }
+
+    for p in actual {
+        if p.kind == "group" && !p.inherited && !groups.keys().any(|e| e.to_lowercase() == p.email_address) {
+            changes.push(DrivePermissionChange::Remove {
+                id: p.id.to_string(),
+                email_address: p.email_address.to_string(),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Escape a value for a Drive API search query.
+fn escape_query(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// Make a request to the Drive API for what the `google_drive` crate does not support yet.
+#[instrument(skip(token, body))]
+#[inline]
+pub async fn drive_api(token: &str, method: Method, path: &str, query: &[(&str, String)], body: Option<Value>) -> Result<Value, String> {
+    let mut q = vec![("supportsAllDrives", "true".to_string())];
+    q.extend_from_slice(query);
+
+    google_api(token, method, &format!("{}{}", DRIVE_API, path), &q, body).await
+}
+
+/// Get all the pages of a Drive API list, ie. the `files` or `permissions`.
+#[instrument(skip(token))]
+#[inline]
+async fn drive_api_list(token: &str, path: &str, query: &[(&str, String)], key: &str) -> Result<Vec<Value>, String> {
+    let mut results: Vec<Value> = Default::default();
+    let mut page_token = String::new();
+    loop {
+        let mut q = query.to_vec();
+        q.push(("pageSize", "100".to_string()));
+        if !page_token.is_empty() {
+            q.push(("pageToken", page_token.to_string()));
+        }
+
+        let resp = drive_api(token, Method::GET, path, &q, None).await?;
+        results.extend(resp[key].as_array().cloned().unwrap_or_default());
+
+        page_token = resp["nextPageToken"].as_str().unwrap_or_default().to_string();
+        if page_token.is_empty() {
+            return Ok(results);
+        }
+    }
+}
+
+/// List the files in a shared drive matching a search query.
+#[instrument(skip(token))]
+#[inline]
+async fn search_drive(token: &str, drive_id: &str, q: &str) -> Result<Vec<Value>, String> {
+    let query = vec![
+        ("corpora", "drive".to_string()),
+        ("driveId", drive_id.to_string()),
+        ("includeItemsFromAllDrives", "true".to_string()),
+        ("q", format!("{} and trashed = false", q)),
+        ("fields", "nextPageToken,files(id,name,parents,webViewLink)".to_string()),
+    ];
+
+    drive_api_list(token, "/files", &query, "files").await
+}
+
+/// Get the ID of a folder in a shared drive from its path in the shared drive.
+#[instrument(skip(token))]
+#[inline]
+pub async fn get_folder_id(token: &str, drive_id: &str, path: &str) -> Result<String, String> {
+    let mut id = drive_id.to_string();
+    for name in path.split('/').filter(|n| !n.is_empty()) {
+        let q = format!(
+            "name = '{}' and '{}' in parents and mimeType = 'application/vnd.google-apps.folder'",
+            escape_query(name),
+            escape_query(&id)
+        );
+        id = match search_drive(token, drive_id, &q).await?.first() {
+            Some(f) => f["id"].as_str().unwrap_or_default().to_string(),
+            None => return Err(format!("the folder `{}` does not exist", path)),
+        };
+    }
+
+    Ok(id)
+}
+
+/// Get the files in a folder of a shared drive, including the files in its subfolders,
+/// that anyone with the link can open. Returns the name and link for each file.
+#[instrument(skip(token))]
+#[inline]
+async fn get_over_shared_files(token: &str, drive_id: &str, folder_id: &str) -> Result<Vec<(String, String)>, String> {
+    // Find all the folders under the folder.
+    let mut folders: BTreeSet<String> = Default::default();
+    let mut queue = vec![folder_id.to_string()];
+    while let Some(id) = queue.pop() {
+        let q = format!("'{}' in parents and mimeType = 'application/vnd.google-apps.folder'", escape_query(&id));
+        for f in search_drive(token, drive_id, &q).await? {
+            queue.push(f["id"].as_str().unwrap_or_default().to_string());
+        }
+        folders.insert(id);
+    }
+
+    let shared = search_drive(token, drive_id, "(visibility = 'anyoneWithLink' or visibility = 'anyoneCanFind')").await?;
+    Ok(shared
+        .iter()
+        .filter(|f| drive_id == folder_id || f["parents"].as_array().map(|p| p.iter().any(|p| folders.contains(p.as_str().unwrap_or_default()))).unwrap_or(false))
+        .map(|f| (f["name"].as_str().unwrap_or_default().to_string(), f["webViewLink"].as_str().unwrap_or_default().to_string()))
+        .collect())
+}
+
+/// Make the permissions of the shared drives and folders in our configs match the roles
+/// for each group, and report the files in sensitive folders that are shared with
+/// anyone with the link in the #security channel.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn reconcile_drive_permissions(db: &Database, config: &Config) {
+    let token = get_gsuite_token("").await;
+    let drive_client = drive_client(token.clone());
+    let token = token.as_str();
+
+    let mut over_shared: Vec<String> = Default::default();
+    for (name, permissions) in &config.drive_permissions {
+        let drive_id = match drive_client.get_drive_by_name(&permissions.drive).await {
+            Ok(d) => d.id,
+            Err(e) => {
+                println!("[drive_permissions] {}: getting the shared drive `{}` failed: {}", name, permissions.drive, e);
+                continue;
+            }
+        };
+        let folder_id = match get_folder_id(token, &drive_id, &permissions.folder).await {
+            Ok(id) => id,
+            Err(e) => {
+                println!("[drive_permissions] {}: {}", name, e);
+                continue;
+            }
+        };
+
+        let query = vec![("fields", "nextPageToken,permissions(id,type,role,emailAddress,permissionDetails)".to_string())];
+        let actual = match drive_api_list(token, &format!("/files/{}/permissions", folder_id), &query, "permissions").await {
+            Ok(p) => parse_drive_permissions(&p),
+            Err(e) => {
+                println!("[drive_permissions] {}: listing the permissions failed: {}", name, e);
+                continue;
+            }
+        };
+
+        let groups: BTreeMap<String, String> = permissions.groups.iter().map(|(g, role)| (format!("{}@{}", g, GSUITE_DOMAIN), role.to_string())).collect();
+        for change in plan_drive_permission_changes(&groups, &actual) {
+            let path = format!("/files/{}/permissions", folder_id);
+            let result = match &change {
+                DrivePermissionChange::Add { email_address, role } => {
+                    let body = json!({ "type": "group", "role": role, "emailAddress": email_address });
+                    drive_api(token, Method::POST, &path, &[("sendNotificationEmail", "false".to_string())], Some(body)).await
+                }
+                DrivePermissionChange::Update { id, role, .. } => drive_api(token, Method::PATCH, &format!("{}/{}", path, id), &[], Some(json!({ "role": role }))).await,
+                DrivePermissionChange::Remove { id, .. } => drive_api(token, Method::DELETE, &format!("{}/{}", path, id), &[], None).await,
+            };
+
+            match result {
+                Ok(_) => println!("[drive_permissions] {}: {:?}", name, change),
+                Err(e) => println!("[drive_permissions] {}: {:?} failed: {}", name, change, e),
+            }
+        }
+
+        if !permissions.sensitive {
+            continue;
+        }
+
+        match get_over_shared_files(token, &drive_id, &folder_id).await {
+            Ok(files) => {
+                for (file, link) in files {
+                    over_shared.push(format!("• <{}|{}> in *{}*", link, file, name));
+                }
+            }
+            Err(e) => println!("[drive_permissions] {}: finding the files shared with anyone with the link failed: {}", name, e),
+        }
+    }
+
+    if over_shared.is_empty() {
+        println!("[drive_permissions] no files in sensitive folders are shared with anyone with the link");
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Security,
+        "drive.over_shared",
+        json!({
+            "text": render_message("drive.over_shared", &json!({ "lines": over_shared })),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use crate::drive_permissions::{parse_drive_permissions, plan_drive_permission_changes, DrivePermissionChange};
+
+    #[test]
+    fn test_plan_drive_permission_changes() {
+        let actual = parse_drive_permissions(&[
+            json!({"id": "1", "type": "group", "role": "organizer", "emailAddress": "Finance@oxidecomputer.com"}),
+            json!({"id": "2", "type": "group", "role": "writer", "emailAddress": "ops@oxidecomputer.com"}),
+            json!({"id": "3", "type": "group", "role": "reader", "emailAddress": "all@oxidecomputer.com"}),
+            json!({"id": "4", "type": "group", "role": "reader", "emailAddress": "eng@oxidecomputer.com", "permissionDetails": [{"inherited": true}]}),
+            json!({"id": "5", "type": "user", "role": "organizer", "emailAddress": "jane@oxidecomputer.com"}),
+            json!({"id": "6", "type": "anyone", "role": "reader"}),
+        ]);
+        assert_eq!(actual[0].email_address, "finance@oxidecomputer.com");
+        assert!(actual[3].inherited);
+
+        let mut groups = BTreeMap::new();
+        groups.insert("finance@oxidecomputer.com".to_string(), "organizer".to_string());
+        groups.insert("ops@oxidecomputer.com".to_string(), "reader".to_string());
+        groups.insert("eng@oxidecomputer.com".to_string(), "reader".to_string());
+        groups.insert("legal@oxidecomputer.com".to_string(), "commenter".to_string());
+
+        assert_eq!(
+            plan_drive_permission_changes(&groups, &actual),
+            vec![
+                DrivePermissionChange::Add {
+                    email_address: "legal@oxidecomputer.com".to_string(),
+                    role: "commenter".to_string(),
+                },
+                DrivePermissionChange::Update {
+                    id: "2".to_string(),
+                    email_address: "ops@oxidecomputer.com".to_string(),
+                    role: "reader".to_string(),
+                },
+                DrivePermissionChange::Remove {
+                    id: "3".to_string(),
+                    email_address: "all@oxidecomputer.com".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,435 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::naive::NaiveDate;
+use chrono::offset::Utc;
+use chrono::{DateTime, Datelike, Duration};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_ENGINEERING_METRICS_TABLE};
+use crate::charts::{attach_chart, render_line_chart, ChartSeries};
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::engineering_metrics;
+use crate::utils::{github_api_get, github_org};
+
+/// How many weeks of trends the monthly report shows.
+pub const REPORT_WEEKS: usize = 8;
+
+/// The characters for the bars in a sparkline, from lowest to highest.
+static SPARK_BARS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A weekly snapshot of how a team's pull requests and deploys moved, across the
+/// repositories the team owns in `repos.toml`.
+#[db {
+    new_struct_name = "EngineeringMetric",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_ENGINEERING_METRICS_TABLE",
+    source = "github",
+    match_on = {
+        "team" = "String",
+        "week" = "NaiveDate",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "engineering_metrics"]
+pub struct NewEngineeringMetric {
+    pub team: String,
+    /// The Monday the week starts on.
+    #[serde(serialize_with = "crate::configs::null_date_format::serialize")]
+    pub week: NaiveDate,
+    #[serde(default)]
+    pub prs_opened: i32,
+    #[serde(default)]
+    pub prs_merged: i32,
+    /// The median hours from a pull request being opened to its first review by someone
+    /// other than the author, for pull requests opened in the week that were reviewed.
+    #[serde(default)]
+    pub median_review_hours: f32,
+    /// The median hours from a pull request being opened to it being merged, for pull
+    /// requests merged in the week.
+    #[serde(default)]
+    pub median_merge_hours: f32,
+    /// The number of deployments created in the week.
+    #[serde(default)]
+    pub deploys: i32,
+}
+
+/// Implement updating the Airtable record for an EngineeringMetric.
+#[async_trait]
+impl UpdateAirtableRecord<EngineeringMetric> for EngineeringMetric {
+    async fn update_airtable_record(&mut self, _record: EngineeringMetric) {}
+}
+
+/// What we need to know about a pull request to measure it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PullRequestActivity {
+    pub created_at: DateTime<Utc>,
+    pub merged_at: Option<DateTime<Utc>>,
+    /// When someone other than the author first reviewed it.
+    pub first_review_at: Option<DateTime<Utc>>,
+}
+
+/// Get the Monday that starts the week containing the date.
+#[instrument]
+#[inline]
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// The median of the values, 0 if there are none.
+#[instrument]
+#[inline]
+pub fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        return (values[mid - 1] + values[mid]) / 2.0;
+    }
+
+    values[mid]
+}
+
+fn hours_between(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+    (end - start).num_minutes() as f64 / 60.0
+}
+
+/// Measure a team's week from the pull requests opened or merged in the repositories
+/// they own and the number of deployments.
+#[instrument(skip(prs))]
+#[inline]
+pub fn summarize_week(team: &str, week: NaiveDate, prs: &[PullRequestActivity], deploys: i32) -> NewEngineeringMetric {
+    let start = DateTime::<Utc>::from_utc(week.and_hms(0, 0, 0), Utc);
+    let end = start + Duration::weeks(1);
+    let in_week = |t: &DateTime<Utc>| *t >= start && *t < end;
+
+    let opened: Vec<&PullRequestActivity> = prs.iter().filter(|pr| in_week(&pr.created_at)).collect();
+    let merged: Vec<&PullRequestActivity> = prs.iter().filter(|pr| pr.merged_at.as_ref().map(in_week).unwrap_or(false)).collect();
+
+    let review_hours = opened.iter().filter_map(|pr| pr.first_review_at.map(|r| hours_between(pr.created_at, r))).collect();
+    let merge_hours = merged.iter().map(|pr| hours_between(pr.created_at, pr.merged_at.unwrap())).collect();
+
+    NewEngineeringMetric {
+        team: team.to_string(),
+        week,
+        prs_opened: opened.len() as i32,
+        prs_merged: merged.len() as i32,
+        median_review_hours: median(review_hours) as f32,
+        median_merge_hours: median(merge_hours) as f32,
+        deploys,
+    }
+}
+
+/// Draw the values as a sparkline, scaled from the lowest to the highest value.
+#[instrument]
+#[inline]
+pub fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+
+    values
+        .iter()
+        .map(|v| {
+            if max <= min {
+                return SPARK_BARS[0];
+            }
+            let i = ((v - min) / (max - min) * (SPARK_BARS.len() - 1) as f64).round() as usize;
+            SPARK_BARS[i]
+        })
+        .collect()
+}
+
+/// Format a line of the monthly report for each team, with the trend over the weeks
+/// in the metrics. The metrics for each team should be sorted by week.
+#[instrument(skip(metrics))]
+#[inline]
+pub fn format_metrics_report(metrics: &BTreeMap<String, Vec<NewEngineeringMetric>>) -> Vec<String> {
+    let mut lines: Vec<String> = Default::default();
+    for (team, weeks) in metrics {
+        let last = match weeks.last() {
+            Some(l) => l,
+            None => continue,
+        };
+
+        let merged: Vec<f64> = weeks.iter().map(|m| m.prs_merged as f64).collect();
+        let review: Vec<f64> = weeks.iter().map(|m| m.median_review_hours as f64).collect();
+        let deploys: Vec<f64> = weeks.iter().map(|m| m.deploys as f64).collect();
+        lines.push(format!(
+            "• *{}*  merged `{}` {}/wk  first review `{}` {:.1}h  deploys `{}` {}/wk",
+            team,
+            sparkline(&merged),
+            last.prs_merged,
+            sparkline(&review),
+            last.median_review_hours,
+            sparkline(&deploys),
+            last.deploys
+        ));
+    }
+
+    lines
+}
+
+/// Get the pages of a GitHub list sorted newest first, until the items are older than
+/// `since` by the `field` timestamp.
+#[instrument]
+#[inline]
+async fn list_github_since(path: &str, field: &str, since: DateTime<Utc>) -> Vec<Value> {
+    let mut items: Vec<Value> = Default::default();
+    let mut page = 1;
+    loop {
+        let separator = if path.contains('?') { "&" } else { "?" };
+        let resp: Vec<Value> = match github_api_get(&format!("{}{}per_page=100&page={}", path, separator, page)).await {
+            Ok(r) => r,
+            Err(e) => {
+                println!("[metrics] listing {} failed: {}", path, e);
+                break;
+            }
+        };
+
+        let done = resp.len() < 100;
+        let mut older = false;
+        for item in resp {
+            match item[field].as_str().and_then(|t| DateTime::parse_from_rfc3339(t).ok()) {
+                Some(t) if t.with_timezone(&Utc) < since => older = true,
+                _ => items.push(item),
+            }
+        }
+
+        if done || older {
+            break;
+        }
+        page += 1;
+    }
+
+    items
+}
+
+fn parse_time(v: &Value) -> Option<DateTime<Utc>> {
+    v.as_str().and_then(|t| DateTime::parse_from_rfc3339(t).ok()).map(|t| t.with_timezone(&Utc))
+}
+
+/// Get the pull requests in a repository that were updated since the start of the week,
+/// with when each was first reviewed by someone other than the author.
+#[instrument]
+#[inline]
+async fn get_pull_request_activity(repo: &str, since: DateTime<Utc>) -> Vec<PullRequestActivity> {
+    let pulls = list_github_since(&format!("/repos/{}/{}/pulls?state=all&sort=updated&direction=desc", github_org(), repo), "updated_at", since).await;
+
+    let mut prs: Vec<PullRequestActivity> = Default::default();
+    for pr in pulls {
+        let created_at = match parse_time(&pr["created_at"]) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let mut first_review_at = None;
+        if created_at >= since {
+            let reviews: Vec<Value> = github_api_get(&format!("/repos/{}/{}/pulls/{}/reviews?per_page=100", github_org(), repo, pr["number"]))
+                .await
+                .unwrap_or_default();
+            first_review_at = reviews
+                .iter()
+                .filter(|r| r["user"]["login"] != pr["user"]["login"])
+                .filter_map(|r| parse_time(&r["submitted_at"]))
+                .min();
+        }
+
+        prs.push(PullRequestActivity {
+            created_at,
+            merged_at: parse_time(&pr["merged_at"]),
+            first_review_at,
+        });
+    }
+
+    prs
+}
+
+/// Snapshot last week's pull request throughput, review latency, and deploy frequency
+/// for each team, from the repositories they own. We only snapshot complete weeks so
+/// the numbers don't change once they are recorded.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn refresh_engineering_metrics(db: &Database, config: &Config) {
+    let week = week_start(Utc::now().date().naive_utc()) - Duration::weeks(1);
+    let since = DateTime::<Utc>::from_utc(week.and_hms(0, 0, 0), Utc);
+
+    // Map of team to the repositories they own.
+    let mut teams: BTreeMap<String, Vec<String>> = Default::default();
+    for (repo, owner) in &config.repos {
+        if !owner.team.is_empty() {
+            teams.entry(owner.team.to_string()).or_default().push(repo.to_string());
+        }
+    }
+
+    for (team, repos) in teams {
+        let mut prs: Vec<PullRequestActivity> = Default::default();
+        let mut deploys = 0;
+        for repo in repos {
+            prs.append(&mut get_pull_request_activity(&repo, since).await);
+
+            let deployments = list_github_since(&format!("/repos/{}/{}/deployments", github_org(), repo), "created_at", since).await;
+            deploys += deployments
+                .iter()
+                .filter(|d| parse_time(&d["created_at"]).map(|t| t < since + Duration::weeks(1)).unwrap_or(false))
+                .count() as i32;
+        }
+
+        let metric = summarize_week(&team, week, &prs, deploys);
+        println!(
+            "[metrics] {} the week of {}: {} opened, {} merged, {} deploys",
+            team, week, metric.prs_opened, metric.prs_merged, metric.deploys
+        );
+        metric.upsert(db).await;
+    }
+}
+
+/// Post the trends in each team's metrics over the last `REPORT_WEEKS` weeks to the
+/// #engineering channel.
+#[instrument(skip(db))]
+#[inline]
+pub async fn send_engineering_metrics_report(db: &Database) {
+    let first_week = week_start(Utc::now().date().naive_utc()) - Duration::weeks(REPORT_WEEKS as i64);
+
+    let mut metrics: BTreeMap<String, Vec<NewEngineeringMetric>> = Default::default();
+    for m in EngineeringMetrics::get_from_db(&db.read_only()) {
+        if m.week >= first_week {
+            metrics.entry(m.team.to_string()).or_default().push(m.into());
+        }
+    }
+    for weeks in metrics.values_mut() {
+        weeks.sort_by_key(|m| m.week);
+    }
+
+    let lines = format_metrics_report(&metrics);
+    if lines.is_empty() {
+        println!("[metrics] there are no engineering metrics to report");
+        return;
+    }
+
+    let mut msg = json!({
+        "text": render_message("engineering_metrics.report", &json!({ "weeks": REPORT_WEEKS, "lines": lines })),
+    });
+    let merged: Vec<ChartSeries> = metrics
+        .iter()
+        .map(|(team, weeks)| ChartSeries {
+            name: team.to_string(),
+            points: weeks.iter().map(|m| (m.week, m.prs_merged as f64)).collect(),
+        })
+        .collect();
+    attach_chart(&mut msg, "Pull requests merged per week", render_line_chart("Pull requests merged per week", "pull requests", &merged));
+
+    notify(db, NotificationPriority::Low, ChatChannel::Engineering, "engineering_metrics.report", msg).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::naive::NaiveDate;
+    use chrono::{TimeZone, Utc};
+
+    use crate::configs::get_configs_from_repo;
+    use crate::db::Database;
+    use crate::engineering_metrics::{
+        format_metrics_report, median, refresh_engineering_metrics, send_engineering_metrics_report, sparkline, summarize_week, week_start, EngineeringMetrics, PullRequestActivity,
+    };
+    use crate::utils::authenticate_github_jwt;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_engineering_metrics() {
+        let db = Database::new();
+        let github = authenticate_github_jwt();
+        let config = get_configs_from_repo(&github).await;
+
+        refresh_engineering_metrics(&db, &config).await;
+
+        EngineeringMetrics::get_from_db(&db).update_airtable().await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_monthly_cron_engineering_metrics_report() {
+        let db = Database::new();
+
+        send_engineering_metrics_report(&db).await;
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(vec![]), 0.0);
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(vec![4.0, 1.0, 2.0, 3.0]), 2.5);
+    }
+
+    #[test]
+    fn test_summarize_week() {
+        let week = NaiveDate::from_ymd(2021, 4, 5);
+        assert_eq!(week_start(NaiveDate::from_ymd(2021, 4, 11)), week);
+        assert_eq!(week_start(week), week);
+
+        let prs = vec![
+            // Opened and merged in the week, reviewed after 2 hours.
+            PullRequestActivity {
+                created_at: Utc.ymd(2021, 4, 5).and_hms(9, 0, 0),
+                merged_at: Some(Utc.ymd(2021, 4, 6).and_hms(9, 0, 0)),
+                first_review_at: Some(Utc.ymd(2021, 4, 5).and_hms(11, 0, 0)),
+            },
+            // Opened the week before and merged in the week.
+            PullRequestActivity {
+                created_at: Utc.ymd(2021, 4, 1).and_hms(9, 0, 0),
+                merged_at: Some(Utc.ymd(2021, 4, 7).and_hms(9, 0, 0)),
+                first_review_at: None,
+            },
+            // Opened in the week, reviewed after 6 hours, not merged yet.
+            PullRequestActivity {
+                created_at: Utc.ymd(2021, 4, 11).and_hms(12, 0, 0),
+                merged_at: None,
+                first_review_at: Some(Utc.ymd(2021, 4, 11).and_hms(18, 0, 0)),
+            },
+            // Merged the week after.
+            PullRequestActivity {
+                created_at: Utc.ymd(2021, 4, 11).and_hms(23, 0, 0),
+                merged_at: Some(Utc.ymd(2021, 4, 12).and_hms(1, 0, 0)),
+                first_review_at: None,
+            },
+        ];
+
+        let metric = summarize_week("eng", week, &prs, 3);
+        assert_eq!(metric.prs_opened, 3);
+        assert_eq!(metric.prs_merged, 2);
+        assert_eq!(metric.median_review_hours, 4.0);
+        assert_eq!(metric.median_merge_hours, 84.0);
+        assert_eq!(metric.deploys, 3);
+    }
+
+    #[test]
+    fn test_format_metrics_report() {
+        assert_eq!(sparkline(&[0.0, 7.0, 3.5, 7.0]), "▁█▅█");
+        assert_eq!(sparkline(&[2.0, 2.0]), "▁▁");
+
+        let mut metrics = BTreeMap::new();
+        metrics.insert(
+            "eng".to_string(),
+            vec![
+                summarize_week("eng", NaiveDate::from_ymd(2021, 3, 29), &[], 1),
+                summarize_week("eng", NaiveDate::from_ymd(2021, 4, 5), &[], 5),
+            ],
+        );
+        metrics.insert("web".to_string(), vec![]);
+        assert_eq!(format_metrics_report(&metrics), vec!["• *eng*  merged `▁▁` 0/wk  first review `▁▁` 0.0h  deploys `▁█` 5/wk"]);
+    }
+}
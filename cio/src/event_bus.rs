@@ -0,0 +1,114 @@
+use std::env;
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::verify::hmac_sha256_signature;
+
+/// The types of events we send to subscribers.
+pub static EVENT_TYPES: &[&str] = &["applicant.stage_changed", "rfd.published", "user.offboarded"];
+
+/// An event about something that changed in our data, sent to the internal tools that
+/// subscribe to them so they do not need to poll our database.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct CioEvent {
+    /// A unique ID for the event, subscribers can use this to skip events they
+    /// already handled.
+    pub id: String,
+    /// One of `EVENT_TYPES`, ie. `rfd.published`.
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub data: Value,
+}
+
+impl CioEvent {
+    #[instrument(skip(data))]
+    #[inline]
+    pub fn new(event_type: &str, data: Value, occurred_at: DateTime<Utc>) -> Self {
+        CioEvent {
+            id: format!("evt_{:016x}", rand::random::<u64>()),
+            event_type: event_type.to_string(),
+            occurred_at,
+            data,
+        }
+    }
+}
+
+/// Get the URLs that subscribe to our events from the comma separated list in the
+/// `CIO_EVENT_WEBHOOK_URLS` environment variable.
+#[instrument]
+#[inline]
+pub fn event_subscribers(urls: &str) -> Vec<String> {
+    urls.split(',').map(|u| u.trim().to_string()).filter(|u| !u.is_empty()).collect()
+}
+
+/// Send an event to each of our subscribers as a JSON `POST`. The type of the event is
+/// in the `X-CIO-Event` header so subscribers can route it without decoding the body,
+/// and if `CIO_EVENT_WEBHOOK_SECRET` is set the body is signed in the
+/// `X-CIO-Signature-256` header the same way GitHub signs webhooks. A subscriber that
+/// is down does not stop us from syncing, so failures are logged and not retried.
+#[instrument(skip(data))]
+#[inline]
+pub async fn emit_event(event_type: &str, data: Value) {
+    let subscribers = event_subscribers(&env::var("CIO_EVENT_WEBHOOK_URLS").unwrap_or_default());
+    if subscribers.is_empty() {
+        return;
+    }
+
+    let event = CioEvent::new(event_type, data, Utc::now());
+    let body = serde_json::to_vec(&event).unwrap();
+    let secret = env::var("CIO_EVENT_WEBHOOK_SECRET").unwrap_or_default();
+
+    let client = Client::builder().timeout(std::time::Duration::from_secs(10)).build().unwrap();
+    for url in subscribers {
+        let mut req = client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("X-CIO-Event", event_type)
+            .header("X-CIO-Event-Id", &event.id)
+            .body(body.clone());
+        if !secret.is_empty() {
+            req = req.header("X-CIO-Signature-256", hmac_sha256_signature(&secret, &body));
+        }
+
+        match req.send().await {
+            Ok(resp) if resp.status().is_success() => println!("[event_bus] sent {} {} to {}", event_type, event.id, url),
+            Ok(resp) => println!("[event_bus] sending {} {} to {} failed: {}", event_type, event.id, url, resp.status()),
+            Err(e) => println!("[event_bus] sending {} {} to {} failed: {}", event_type, event.id, url, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::event_bus::{event_subscribers, CioEvent};
+
+    #[test]
+    fn test_event_subscribers() {
+        assert_eq!(
+            event_subscribers(" https://a.internal/events, ,https://b.internal/hooks/cio "),
+            vec!["https://a.internal/events", "https://b.internal/hooks/cio"]
+        );
+        assert!(event_subscribers("").is_empty());
+    }
+
+    #[test]
+    fn test_cio_event_json() {
+        let mut event = CioEvent::new("rfd.published", json!({"number": 123}), Utc.ymd(2021, 4, 14).and_hms(10, 0, 0));
+        assert!(event.id.starts_with("evt_"));
+
+        event.id = "evt_1".to_string();
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"id": "evt_1", "type": "rfd.published", "occurred_at": "2021-04-14T10:00:00Z", "data": {"number": 123}})
+        );
+    }
+}
@@ -0,0 +1,512 @@
+#![allow(clippy::from_over_into)]
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use macros::db;
+use reqwest::Client;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_EVENT_REGISTRANTS_TABLE};
+use crate::chat::ChatChannel;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::mailing_list::{MailingListSubscriber, MailingListSubscribers};
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::event_registrants;
+
+/// The registration counts we post about when an upcoming event passes them.
+pub static REGISTRATION_MILESTONES: &[usize] = &[25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// The data type for a person registered for one of our webinars or events.
+#[db {
+    new_struct_name = "EventRegistrant",
+    airtable_base_id = "AIRTABLE_BASE_ID_CUSTOMER_LEADS",
+    airtable_table = "AIRTABLE_EVENT_REGISTRANTS_TABLE",
+    source = "zoom",
+    match_on = {
+        "registrant_id" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "event_registrants"]
+pub struct NewEventRegistrant {
+    /// A unique identifier for the registration formatted as `{platform}/{event_id}/{email}`,
+    /// so someone who registers more than once for an event is only counted once.
+    pub registrant_id: String,
+    /// The platform the event is on, either `zoom` or `eventbrite`.
+    pub platform: String,
+    pub event_id: String,
+    pub event_name: String,
+    /// Recurring webinars without a fixed time do not have a start.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_start: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub event_url: String,
+    pub email: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub first_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_name: String,
+    /// (generated) name is a combination of first_name and last_name.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub company: String,
+    pub registered_at: DateTime<Utc>,
+    /// If the person was already on our mailing list, so we can tell how many new
+    /// contacts an event brought in.
+    #[serde(default)]
+    pub existing_contact: bool,
+    /// link to another table in Airtable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub link_to_mailing_list_signups: Vec<String>,
+}
+
+impl NewEventRegistrant {
+    /// Create a new registrant with the `registrant_id` and `name` set from the other fields.
+    #[instrument]
+    #[inline]
+    pub fn new(platform: &str, event: &Event, email: &str, first_name: &str, last_name: &str, company: &str, registered_at: DateTime<Utc>) -> Self {
+        let email = email.trim().to_lowercase();
+        NewEventRegistrant {
+            registrant_id: format!("{}/{}/{}", platform, event.id, email),
+            platform: platform.to_string(),
+            event_id: event.id.to_string(),
+            event_name: event.name.to_string(),
+            event_start: event.start,
+            event_url: event.url.to_string(),
+            email,
+            first_name: first_name.trim().to_string(),
+            last_name: last_name.trim().to_string(),
+            name: format!("{} {}", first_name.trim(), last_name.trim()).trim().to_string(),
+            company: company.trim().to_string(),
+            registered_at,
+            existing_contact: false,
+            link_to_mailing_list_signups: Default::default(),
+        }
+    }
+
+    /// Link the registrant to their record on our mailing list if they already have one,
+    /// rather than counting them as a new contact.
+    #[instrument(skip(contacts))]
+    #[inline]
+    pub fn link_to_contact(&mut self, contacts: &HashMap<String, MailingListSubscriber>) {
+        self.existing_contact = false;
+        self.link_to_mailing_list_signups = Default::default();
+        if let Some(c) = contacts.get(&self.email) {
+            self.existing_contact = true;
+            if !c.airtable_record_id.is_empty() {
+                self.link_to_mailing_list_signups = vec![c.airtable_record_id.to_string()];
+            }
+        }
+    }
+}
+
+/// Implement updating the Airtable record for an EventRegistrant.
+#[async_trait]
+impl UpdateAirtableRecord<EventRegistrant> for EventRegistrant {
+    async fn update_airtable_record(&mut self, _record: EventRegistrant) {}
+}
+
+/// A webinar or event people register for, from any platform.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Event {
+    pub id: String,
+    pub name: String,
+    pub start: Option<DateTime<Utc>>,
+    pub url: String,
+}
+
+/// Returns the highest milestone an event passed going from `before` to `after` registrations.
+#[instrument]
+#[inline]
+pub fn passed_milestone(before: usize, after: usize) -> Option<usize> {
+    REGISTRATION_MILESTONES.iter().rev().find(|m| before < **m && after >= **m).copied()
+}
+
+/// Get a token for the Zoom API from the JWT app credentials in the `ZOOM_API_KEY`
+/// and `ZOOM_API_SECRET` environment variables.
+#[instrument]
+#[inline]
+fn get_zoom_token() -> String {
+    #[derive(Serialize)]
+    struct Claims {
+        iss: String,
+        exp: i64,
+    }
+
+    let claims = Claims {
+        iss: env::var("ZOOM_API_KEY").unwrap(),
+        exp: (Utc::now() + Duration::hours(1)).timestamp(),
+    };
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some("signing_key".to_string());
+
+    encode(&header, &claims, &EncodingKey::from_secret(env::var("ZOOM_API_SECRET").unwrap().as_bytes())).unwrap()
+}
+
+/// Get the registrants for the upcoming webinars hosted by the Zoom user in the
+/// `ZOOM_WEBINAR_HOST` environment variable.
+/// FROM: https://marketplace.zoom.us/docs/api-reference/zoom-api/webinars/webinarregistrants
+#[instrument]
+#[inline]
+pub async fn get_zoom_webinar_registrants() -> Vec<NewEventRegistrant> {
+    let client = Client::new();
+    let token = get_zoom_token();
+
+    let resp = client
+        .get(&format!("https://api.zoom.us/v2/users/{}/webinars", env::var("ZOOM_WEBINAR_HOST").unwrap()))
+        .bearer_auth(&token)
+        .query(&[("page_size", "300")])
+        .send()
+        .await
+        .unwrap();
+    let webinars: ZoomWebinarsResponse = resp.json().await.unwrap();
+
+    let mut registrants: Vec<NewEventRegistrant> = Default::default();
+    for webinar in webinars.webinars {
+        let event = Event {
+            id: webinar.id.to_string(),
+            name: webinar.topic,
+            start: webinar.start_time,
+            url: webinar.join_url,
+        };
+
+        let mut next_page_token = String::new();
+        loop {
+            let resp = client
+                .get(&format!("https://api.zoom.us/v2/webinars/{}/registrants", webinar.id))
+                .bearer_auth(&token)
+                .query(&[("page_size", "300"), ("status", "approved"), ("next_page_token", next_page_token.as_str())])
+                .send()
+                .await
+                .unwrap();
+            let r: ZoomWebinarRegistrantsResponse = resp.json().await.unwrap();
+
+            for registrant in r.registrants {
+                registrants.push(NewEventRegistrant::new(
+                    "zoom",
+                    &event,
+                    &registrant.email,
+                    &registrant.first_name,
+                    &registrant.last_name,
+                    &registrant.org,
+                    registrant.create_time,
+                ));
+            }
+
+            if r.next_page_token.is_empty() {
+                break;
+            }
+            next_page_token = r.next_page_token;
+        }
+    }
+
+    registrants
+}
+
+/// Get the attendees for the live events of the Eventbrite organization in the
+/// `EVENTBRITE_ORGANIZATION_ID` environment variable. Eventbrite calls everyone
+/// with a ticket an attendee, whether or not the event has happened.
+/// FROM: https://www.eventbrite.com/platform/api#/reference/attendee/list/list-attendees-by-event
+#[instrument]
+#[inline]
+pub async fn get_eventbrite_registrants() -> Vec<NewEventRegistrant> {
+    let client = Client::new();
+    let token = env::var("EVENTBRITE_TOKEN").unwrap();
+
+    let mut events: Vec<EventbriteEvent> = Default::default();
+    let mut continuation = String::new();
+    loop {
+        let mut query = vec![("status", "live")];
+        if !continuation.is_empty() {
+            query.push(("continuation", continuation.as_str()));
+        }
+        let resp = client
+            .get(&format!("https://www.eventbriteapi.com/v3/organizations/{}/events/", env::var("EVENTBRITE_ORGANIZATION_ID").unwrap()))
+            .bearer_auth(&token)
+            .query(&query)
+            .send()
+            .await
+            .unwrap();
+        let mut r: EventbriteEventsResponse = resp.json().await.unwrap();
+        events.append(&mut r.events);
+
+        if !r.pagination.has_more_items {
+            break;
+        }
+        continuation = r.pagination.continuation;
+    }
+
+    let mut registrants: Vec<NewEventRegistrant> = Default::default();
+    for e in events {
+        let event = Event {
+            id: e.id.to_string(),
+            name: e.name.text,
+            start: e.start.utc,
+            url: e.url,
+        };
+
+        let mut continuation = String::new();
+        loop {
+            let mut query = vec![("status", "attending")];
+            if !continuation.is_empty() {
+                query.push(("continuation", continuation.as_str()));
+            }
+            let resp = client
+                .get(&format!("https://www.eventbriteapi.com/v3/events/{}/attendees/", e.id))
+                .bearer_auth(&token)
+                .query(&query)
+                .send()
+                .await
+                .unwrap();
+            let r: EventbriteAttendeesResponse = resp.json().await.unwrap();
+
+            for attendee in r.attendees {
+                if attendee.cancelled || attendee.refunded || attendee.profile.email.is_empty() {
+                    continue;
+                }
+
+                registrants.push(NewEventRegistrant::new(
+                    "eventbrite",
+                    &event,
+                    &attendee.profile.email,
+                    &attendee.profile.first_name,
+                    &attendee.profile.last_name,
+                    &attendee.profile.company,
+                    attendee.created,
+                ));
+            }
+
+            if !r.pagination.has_more_items {
+                break;
+            }
+            continuation = r.pagination.continuation;
+        }
+    }
+
+    registrants
+}
+
+/// Count the registrants for each event, keyed by `{platform}/{event_id}`.
+#[instrument(skip(registrants))]
+#[inline]
+fn count_registrants_by_event(registrants: &[EventRegistrant]) -> BTreeMap<String, usize> {
+    let mut counts: BTreeMap<String, usize> = Default::default();
+    for r in registrants {
+        *counts.entry(format!("{}/{}", r.platform, r.event_id)).or_default() += 1;
+    }
+
+    counts
+}
+
+/// Sync the registrants for our webinars and events from Zoom and Eventbrite with our
+/// database, linking them to anyone already on our mailing list. Posts to the public
+/// relations channel when an upcoming event passes one of the `REGISTRATION_MILESTONES`.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_event_registrants(db: &Database) {
+    let mut registrants = get_zoom_webinar_registrants().await;
+    registrants.append(&mut get_eventbrite_registrants().await);
+
+    let contacts: HashMap<String, MailingListSubscriber> = MailingListSubscribers::get_from_db(db).into_iter().map(|s| (s.email.trim().to_lowercase(), s)).collect();
+
+    let existing: Vec<EventRegistrant> = EventRegistrants::get_from_db(db).into();
+    let before = count_registrants_by_event(&existing);
+
+    let mut events: BTreeMap<String, Event> = Default::default();
+    for mut registrant in registrants {
+        registrant.link_to_contact(&contacts);
+        events.entry(format!("{}/{}", registrant.platform, registrant.event_id)).or_insert_with(|| Event {
+            id: registrant.event_id.to_string(),
+            name: registrant.event_name.to_string(),
+            start: registrant.event_start,
+            url: registrant.event_url.to_string(),
+        });
+
+        registrant.upsert_from(db, &registrant.platform).await;
+    }
+
+    let synced: Vec<EventRegistrant> = EventRegistrants::get_from_db(db).into();
+    let after = count_registrants_by_event(&synced);
+    for (key, event) in events {
+        // Nobody needs to hear about registrations for an event that already happened.
+        if event.start.map(|s| s < Utc::now()).unwrap_or(false) {
+            continue;
+        }
+
+        let count = after.get(&key).copied().unwrap_or_default();
+        if let Some(milestone) = passed_milestone(before.get(&key).copied().unwrap_or_default(), count) {
+            let text = render_message(
+                "events.registration_milestone",
+                &json!({
+                    "event": event.name,
+                    "count": count,
+                    "milestone": milestone,
+                    "url": event.url,
+                }),
+            );
+            notify(db, NotificationPriority::Low, ChatChannel::PublicRelations, "events.registration_milestone", json!({ "text": text })).await;
+        }
+    }
+}
+
+/// The data type for the response to Zoom's API for listing the webinars of a user.
+///
+/// FROM: https://marketplace.zoom.us/docs/api-reference/zoom-api/webinars/webinars
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct ZoomWebinarsResponse {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webinars: Vec<ZoomWebinar>,
+}
+
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct ZoomWebinar {
+    /// The webinar ID, also known as the webinar number.
+    pub id: i64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub topic: String,
+    /// Recurring webinars without a fixed time do not have a start time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub join_url: String,
+}
+
+/// The data type for the response to Zoom's API for listing the registrants of a webinar.
+///
+/// FROM: https://marketplace.zoom.us/docs/api-reference/zoom-api/webinars/webinarregistrants
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct ZoomWebinarRegistrantsResponse {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registrants: Vec<ZoomWebinarRegistrant>,
+    /// The token for the next page, empty on the last page.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub next_page_token: String,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ZoomWebinarRegistrant {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub email: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub first_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_name: String,
+    /// The organization the registrant works for.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub org: String,
+    /// The time the registrant registered.
+    pub create_time: DateTime<Utc>,
+}
+
+/// The pagination for a response from Eventbrite's API.
+///
+/// FROM: https://www.eventbrite.com/platform/api#/introduction/paginated-responses
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct EventbritePagination {
+    #[serde(default)]
+    pub has_more_items: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub continuation: String,
+}
+
+/// The data type for the response to Eventbrite's API for listing the events of
+/// an organization.
+///
+/// FROM: https://www.eventbrite.com/platform/api#/reference/event/list/list-events-by-organization
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct EventbriteEventsResponse {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<EventbriteEvent>,
+    #[serde(default)]
+    pub pagination: EventbritePagination,
+}
+
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct EventbriteEvent {
+    pub id: String,
+    #[serde(default)]
+    pub name: EventbriteText,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(default)]
+    pub start: EventbriteTime,
+}
+
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct EventbriteText {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct EventbriteTime {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utc: Option<DateTime<Utc>>,
+}
+
+/// The data type for the response to Eventbrite's API for listing the attendees of an event.
+///
+/// FROM: https://www.eventbrite.com/platform/api#/reference/attendee/list/list-attendees-by-event
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct EventbriteAttendeesResponse {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attendees: Vec<EventbriteAttendee>,
+    #[serde(default)]
+    pub pagination: EventbritePagination,
+}
+
+#[derive(Debug, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct EventbriteAttendee {
+    /// The time the ticket was ordered.
+    pub created: DateTime<Utc>,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub refunded: bool,
+    #[serde(default)]
+    pub profile: EventbriteProfile,
+}
+
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct EventbriteProfile {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub email: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub first_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub company: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::event_registrants::{passed_milestone, refresh_event_registrants, EventRegistrants};
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_event_registrants() {
+        let db = Database::new();
+        refresh_event_registrants(&db).await;
+        EventRegistrants::get_from_db(&db).update_airtable().await;
+    }
+
+    #[test]
+    fn test_passed_milestone() {
+        assert_eq!(passed_milestone(0, 10), None);
+        assert_eq!(passed_milestone(20, 25), Some(25));
+        assert_eq!(passed_milestone(25, 49), None);
+        // Only the highest milestone is posted when a sync passes several.
+        assert_eq!(passed_milestone(10, 120), Some(100));
+        assert_eq!(passed_milestone(120, 100), None);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use std::sync::Mutex;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Jsonb;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::schema_drift::get_airtable_base_computed_fields;
+
+/// The fields of an Airtable record that the model does not have, ie. columns people
+/// added in Airtable by hand. Models generated by the `db` macro keep these, so they are
+/// saved with the record and sent back to Airtable rather than lost.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, FromSqlRow, AsExpression, Deserialize, Serialize)]
+#[sql_type = "Jsonb"]
+#[serde(transparent)]
+pub struct AirtableExtraFields(pub HashMap<String, Value>);
+
+impl AirtableExtraFields {
+    #[instrument]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromSql<Jsonb, Pg> for AirtableExtraFields {
+    #[instrument]
+    #[inline]
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value = <serde_json::Value as FromSql<Jsonb, Pg>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(value).unwrap())
+    }
+}
+
+impl ToSql<Jsonb, Pg> for AirtableExtraFields {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        let value = serde_json::to_value(self).unwrap();
+        <serde_json::Value as ToSql<Jsonb, Pg>>::to_sql(&value, out)
+    }
+}
+
+/// A record with the fields the model does not have split out. The model takes the
+/// fields it knows and the map gets whatever is left.
+#[derive(Deserialize)]
+struct WithExtraFields<T> {
+    #[serde(flatten)]
+    record: T,
+    #[serde(flatten)]
+    extra: HashMap<String, Value>,
+}
+
+/// Deserialize a record from the fields of an Airtable record, with the fields the
+/// record does not have.
+#[instrument(skip(value))]
+#[inline]
+pub fn split_extra_fields<T: DeserializeOwned>(value: Value) -> Result<(T, AirtableExtraFields), serde_json::Error> {
+    let split: WithExtraFields<T> = serde_json::from_value(value)?;
+
+    Ok((split.record, AirtableExtraFields(split.extra)))
+}
+
+/// Add the extra fields to the fields we send to Airtable, leaving out the ones Airtable
+/// computes, since it rejects any value for them, and any the record already has.
+#[instrument(skip(fields))]
+#[inline]
+pub fn add_extra_fields(fields: &mut Value, extra: &AirtableExtraFields, computed: &BTreeSet<String>) {
+    if let Some(fields) = fields.as_object_mut() {
+        for (name, value) in &extra.0 {
+            if !computed.contains(name) && !fields.contains_key(name) {
+                fields.insert(name.to_string(), value.clone());
+            }
+        }
+    }
+}
+
+/// The fields Airtable computes for each table, by base, from the metadata API. This is
+/// fetched once per base. If we can not get it, it is `None` and we send no extra fields
+/// at all, since we can not tell which ones Airtable would reject. Airtable leaves the
+/// fields we do not send as they are when we update a record, so only records we create
+/// again lose them.
+static COMPUTED_FIELDS: Mutex<BTreeMap<String, Option<BTreeMap<String, BTreeSet<String>>>>> = Mutex::new(BTreeMap::new());
+
+/// Add the extra fields of a record to the fields we send to Airtable for it.
+#[instrument(skip(fields))]
+#[inline]
+pub async fn add_writable_extra_fields(base_id: &str, table: &str, fields: &mut Value, extra: &AirtableExtraFields) {
+    if extra.is_empty() {
+        return;
+    }
+
+    let cached = COMPUTED_FIELDS.lock().unwrap().get(base_id).cloned();
+    let base = match cached {
+        Some(base) => base,
+        None => {
+            let base = match get_airtable_base_computed_fields(base_id).await {
+                Ok(base) => Some(base),
+                Err(e) => {
+                    println!("[airtable] not sending the extra fields for base {}, getting its computed fields failed: {}", base_id, e);
+                    None
+                }
+            };
+            COMPUTED_FIELDS.lock().unwrap().insert(base_id.to_string(), base.clone());
+            base
+        }
+    };
+
+    if let Some(base) = base {
+        add_extra_fields(fields, extra, &base.get(table).cloned().unwrap_or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde::Deserialize;
+
+    use crate::extra_fields::{add_extra_fields, split_extra_fields};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Vendor {
+        name: String,
+        #[serde(default)]
+        users: i32,
+    }
+
+    #[test]
+    fn test_extra_fields() {
+        let (vendor, extra) = split_extra_fields::<Vendor>(json!({ "name": "Zoom", "users": 12, "Renewal Notes": "ask for a discount", "Total": 180 })).unwrap();
+        assert_eq!(vendor, Vendor { name: "Zoom".to_string(), users: 12 });
+        assert_eq!(extra.0.len(), 2);
+        assert_eq!(extra.0["Renewal Notes"], json!("ask for a discount"));
+
+        let (_, extra_none) = split_extra_fields::<Vendor>(json!({ "name": "Zoom" })).unwrap();
+        assert!(extra_none.is_empty());
+        assert!(split_extra_fields::<Vendor>(json!({ "users": 12 })).is_err());
+
+        // Computed fields and the fields we have are not sent back.
+        let computed: BTreeSet<String> = vec!["Total".to_string()].into_iter().collect();
+        let mut fields = json!({ "name": "Zoom", "users": 13 });
+        add_extra_fields(&mut fields, &extra, &computed);
+        assert_eq!(fields, json!({ "name": "Zoom", "users": 13, "Renewal Notes": "ask for a discount" }));
+    }
+}
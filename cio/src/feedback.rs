@@ -0,0 +1,320 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+use std::env;
+
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::{Date, DateTime};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use macros::db;
+use schemars::JsonSchema;
+use sendgrid_api::SendGrid;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_ANONYMOUS_FEEDBACK_TABLE, AIRTABLE_BASE_ID_MISC};
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::format::format_date;
+use crate::schema::anonymous_feedbacks;
+use crate::utils::GSUITE_DOMAIN;
+use crate::verify::hmac_sha256_signature;
+
+/// The topics someone can file feedback under, the last one is the default.
+pub static FEEDBACK_TOPICS: &[&str] = &["culture", "hiring", "management", "process", "tools", "other"];
+
+/// The `callback_id` of the Slack shortcut and modal for anonymous feedback.
+pub static FEEDBACK_SLACK_CALLBACK_ID: &str = "anonymous_feedback";
+
+/// How much feedback we take from one person a day, so the form can not be used to
+/// flood leadership.
+const FEEDBACK_LIMIT_PER_DAY: i64 = 5;
+
+/// The longest feedback we take, in characters.
+const FEEDBACK_MAX_LENGTH: usize = 5000;
+
+/// Feedback someone sent without saying who they are. We never store who sent it,
+/// only a salted hash of the sender for the day it was sent, which is enough to limit
+/// how much one person sends a day but can not be used to tell who sent it or to link
+/// feedback sent on different days. For the same reason none of its times are finer
+/// than the day, including when it was last synced.
+///
+/// The table in Airtable is only shared with leadership.
+#[db {
+    new_struct_name = "AnonymousFeedback",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_ANONYMOUS_FEEDBACK_TABLE",
+    source = "feedback",
+    sync_times_by_day = true,
+    match_on = {
+        "sender_hash" = "String",
+        "submitted_at" = "DateTime<Utc>",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "anonymous_feedbacks"]
+pub struct NewAnonymousFeedback {
+    /// One of `FEEDBACK_TOPICS`.
+    pub topic: String,
+    pub feedback: String,
+    /// This is never sent to Airtable or the API.
+    #[serde(default, skip_serializing)]
+    pub sender_hash: String,
+    /// The day the feedback was sent. We leave out the time so it can not be matched up
+    /// with someone's activity.
+    pub submitted_at: DateTime<Utc>,
+    /// If the feedback was in a leadership digest yet.
+    #[serde(default)]
+    pub digested: bool,
+}
+
+/// Implement updating the Airtable record for an AnonymousFeedback.
+#[async_trait]
+impl UpdateAirtableRecord<AnonymousFeedback> for AnonymousFeedback {
+    async fn update_airtable_record(&mut self, _record: AnonymousFeedback) {}
+}
+
+/// Hash who sent feedback with the salt, for the day they sent it. The hash changes
+/// every day so feedback from different days can not be linked to each other.
+#[instrument(skip(salt, sender))]
+#[inline]
+pub fn feedback_sender_hash(salt: &str, sender: &str, day: Date<Utc>) -> String {
+    hmac_sha256_signature(salt, format!("{}|{}", sender.trim().to_lowercase(), day.format("%Y-%m-%d")).as_bytes())
+}
+
+/// Check the topic and feedback someone sent, returning the topic to file it under.
+#[instrument(skip(feedback))]
+#[inline]
+pub fn validate_feedback(topic: &str, feedback: &str) -> Result<String, String> {
+    if feedback.trim().is_empty() {
+        return Err("the feedback is empty".to_string());
+    }
+    if feedback.chars().count() > FEEDBACK_MAX_LENGTH {
+        return Err(format!("the feedback is longer than {} characters", FEEDBACK_MAX_LENGTH));
+    }
+
+    let topic = topic.trim().to_lowercase();
+    if topic.is_empty() {
+        return Ok(FEEDBACK_TOPICS[FEEDBACK_TOPICS.len() - 1].to_string());
+    }
+    if !FEEDBACK_TOPICS.contains(&topic.as_str()) {
+        return Err(format!("the topic `{}` is not one of {}", topic, FEEDBACK_TOPICS.join(", ")));
+    }
+
+    Ok(topic)
+}
+
+/// Save anonymous feedback. The sender is only used to limit how much feedback one
+/// person can send a day, it is hashed with the salt in the `ANONYMOUS_FEEDBACK_SALT`
+/// environment variable and thrown away.
+#[instrument(skip(db, sender, feedback))]
+#[inline]
+pub async fn submit_feedback(db: &Database, sender: &str, topic: &str, feedback: &str) -> Result<AnonymousFeedback, String> {
+    let topic = validate_feedback(topic, feedback)?;
+    if sender.trim().is_empty() {
+        return Err("we could not tell who sent the feedback, so we can not limit how much is sent".to_string());
+    }
+
+    // Without a salt the hashes of everyone's usernames could be computed and compared.
+    let salt = env::var("ANONYMOUS_FEEDBACK_SALT").unwrap_or_default();
+    if salt.is_empty() {
+        return Err("anonymous feedback is not set up, ANONYMOUS_FEEDBACK_SALT is not set".to_string());
+    }
+
+    let today = Utc::today();
+    let sender_hash = feedback_sender_hash(&salt, sender, today);
+    let sent_today: i64 = anonymous_feedbacks::dsl::anonymous_feedbacks
+        .filter(anonymous_feedbacks::dsl::sender_hash.eq(sender_hash.to_string()))
+        .count()
+        .get_result(&db.conn())
+        .unwrap_or_else(|e| panic!("counting anonymous feedback failed: {}", e));
+    if sent_today >= FEEDBACK_LIMIT_PER_DAY {
+        return Err("you have sent a lot of feedback today, please send more tomorrow".to_string());
+    }
+
+    Ok(NewAnonymousFeedback {
+        topic,
+        feedback: feedback.trim().to_string(),
+        sender_hash,
+        submitted_at: today.and_hms(0, 0, 0),
+        digested: false,
+    }
+    .create(db)
+    .await)
+}
+
+/// The Slack modal for sending anonymous feedback, opened by the shortcut.
+/// FROM: https://api.slack.com/surfaces/modals/using
+#[instrument]
+#[inline]
+pub fn feedback_slack_modal() -> Value {
+    let options: Vec<Value> = FEEDBACK_TOPICS.iter().map(|t| json!({ "text": { "type": "plain_text", "text": t }, "value": t })).collect();
+
+    json!({
+        "type": "modal",
+        "callback_id": FEEDBACK_SLACK_CALLBACK_ID,
+        "title": { "type": "plain_text", "text": "Anonymous feedback" },
+        "submit": { "type": "plain_text", "text": "Send" },
+        "close": { "type": "plain_text", "text": "Cancel" },
+        "blocks": [
+            {
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": "Your name is not saved with your feedback. It goes to leadership in a weekly digest."
+                }
+            },
+            {
+                "type": "input",
+                "block_id": "topic",
+                "optional": true,
+                "label": { "type": "plain_text", "text": "Topic" },
+                "element": { "type": "static_select", "action_id": "topic", "options": options }
+            },
+            {
+                "type": "input",
+                "block_id": "feedback",
+                "label": { "type": "plain_text", "text": "Feedback" },
+                "element": { "type": "plain_text_input", "action_id": "feedback", "multiline": true, "max_length": FEEDBACK_MAX_LENGTH }
+            }
+        ]
+    })
+}
+
+/// Get the topic and feedback from a submission of the Slack modal.
+#[instrument(skip(payload))]
+#[inline]
+pub fn parse_feedback_submission(payload: &Value) -> (String, String) {
+    let values = &payload["view"]["state"]["values"];
+    let topic = values["topic"]["topic"]["selected_option"]["value"].as_str().unwrap_or_default();
+    let feedback = values["feedback"]["feedback"]["value"].as_str().unwrap_or_default();
+
+    (topic.to_string(), feedback.to_string())
+}
+
+/// Email the feedback that has not been in a digest yet to the leadership group.
+#[instrument(skip(db))]
+#[inline]
+pub async fn send_feedback_digest(db: &Database) {
+    let pending = anonymous_feedbacks::dsl::anonymous_feedbacks
+        .filter(anonymous_feedbacks::dsl::digested.eq(false))
+        .order_by(anonymous_feedbacks::dsl::submitted_at)
+        .load::<AnonymousFeedback>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the anonymous feedback for the digest failed: {}", e));
+    if pending.is_empty() {
+        println!("[feedback] no new anonymous feedback for the digest");
+        return;
+    }
+
+    let mut by_topic: BTreeMap<String, Vec<&AnonymousFeedback>> = BTreeMap::new();
+    for f in &pending {
+        by_topic.entry(f.topic.to_string()).or_default().push(f);
+    }
+
+    let mut message = format!("There are {} new pieces of anonymous feedback.\n", pending.len());
+    for (topic, feedback) in by_topic {
+        message += &format!("\n## {}\n", topic);
+        for f in feedback {
+            message += &format!("\n{}:\n{}\n", format_date(f.submitted_at.date().naive_utc()), f.feedback);
+        }
+    }
+
+    let sendgrid = SendGrid::new_from_env();
+    sendgrid
+        .send_mail(
+            format!("Anonymous feedback digest: {} new", pending.len()),
+            message,
+            vec![format!("leadership@{}", GSUITE_DOMAIN)],
+            vec![],
+            vec![],
+            format!("feedback@{}", GSUITE_DOMAIN),
+        )
+        .await;
+
+    for mut f in pending {
+        f.digested = true;
+        f.update_in_db(db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::feedback::{feedback_sender_hash, parse_feedback_submission, validate_feedback, AnonymousFeedback};
+
+    #[test]
+    fn test_feedback_sender_hash() {
+        let day = Utc.ymd(2021, 4, 18);
+        let hash = feedback_sender_hash("salt", "U2147483697", day);
+        assert_eq!(hash, feedback_sender_hash("salt", " u2147483697 ", day));
+        assert!(!hash.contains("2147483697"));
+
+        // The hash changes with the salt and every day.
+        assert_ne!(hash, feedback_sender_hash("pepper", "U2147483697", day));
+        assert_ne!(hash, feedback_sender_hash("salt", "U2147483697", Utc.ymd(2021, 4, 19)));
+        assert_ne!(hash, feedback_sender_hash("salt", "U2147483698", day));
+    }
+
+    #[test]
+    fn test_validate_feedback() {
+        assert_eq!(validate_feedback("", "more snacks").unwrap(), "other");
+        assert_eq!(validate_feedback(" Tools ", "more snacks").unwrap(), "tools");
+        assert!(validate_feedback("snacks", "more snacks").unwrap_err().contains("is not one of"));
+        assert_eq!(validate_feedback("tools", "  ").unwrap_err(), "the feedback is empty");
+        assert!(validate_feedback("tools", &"a".repeat(5001)).is_err());
+    }
+
+    #[test]
+    fn test_parse_feedback_submission() {
+        let payload = json!({
+            "type": "view_submission",
+            "user": {"id": "U2147483697"},
+            "view": {"callback_id": "anonymous_feedback", "state": {"values": {
+                "topic": {"topic": {"type": "static_select", "selected_option": {"value": "process"}}},
+                "feedback": {"feedback": {"type": "plain_text_input", "value": "fewer meetings"}},
+            }}},
+        });
+        assert_eq!(parse_feedback_submission(&payload), ("process".to_string(), "fewer meetings".to_string()));
+
+        // The topic is optional.
+        let payload = json!({"view": {"state": {"values": {
+            "topic": {"topic": {"type": "static_select", "selected_option": null}},
+            "feedback": {"feedback": {"type": "plain_text_input", "value": "fewer meetings"}},
+        }}}});
+        assert_eq!(parse_feedback_submission(&payload), ("".to_string(), "fewer meetings".to_string()));
+    }
+
+    #[test]
+    fn test_feedback_times() {
+        let feedback = AnonymousFeedback {
+            id: 1,
+            topic: "tools".to_string(),
+            feedback: "more snacks".to_string(),
+            sender_hash: "abc".to_string(),
+            submitted_at: Utc::today().and_hms(0, 0, 0),
+            digested: false,
+            airtable_record_id: "".to_string(),
+            source: "feedback".to_string(),
+            last_synced_from: "feedback".to_string(),
+            last_synced_at: Some(AnonymousFeedback::synced_at_now()),
+            extra: Default::default(),
+            modified_at: Some(AnonymousFeedback::synced_at_now()),
+        };
+
+        // Nothing we send to Airtable or the API has a time finer than the day.
+        for value in vec![serde_json::to_value(&feedback).unwrap(), feedback.to_airtable_fields()] {
+            let fields = value.as_object().unwrap();
+            assert!(!fields.contains_key("sender_hash"));
+            for (key, field) in fields {
+                if let Some(time) = field.as_str().and_then(|s| DateTime::parse_from_rfc3339(s).ok()) {
+                    assert_eq!(time.time(), NaiveTime::from_hms(0, 0, 0), "{} is finer than the day", key);
+                }
+            }
+        }
+        assert!(serde_json::to_value(&feedback).unwrap().get("last_synced_at").is_some());
+        assert!(serde_json::to_value(&feedback).unwrap().get("modified_at").is_some());
+    }
+}
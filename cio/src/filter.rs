@@ -0,0 +1,252 @@
+use chrono::offset::Utc;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Weekday};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::format::format_date;
+
+/// A filter for the records a job syncs, so we can re-sync a single record after
+/// fixing bad data instead of rerunning the whole job. A record matches if every
+/// `field=value` pair matches the serialized field on the record.
+///
+/// Jobs that have a date for each record, ie. when an applicant applied, also
+/// filter on the dates between `since` and `before`.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct RecordFilter {
+    pub fields: Vec<(String, String)>,
+    /// Only records from on or after this date.
+    pub since: Option<DateTime<Utc>>,
+    /// Only records from before this date.
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl RecordFilter {
+    /// Parse a filter from `field=value` pairs, ie. `name=Okta` or `number=123`.
+    #[instrument]
+    #[inline]
+    pub fn parse(pairs: &[&str]) -> Result<Self, String> {
+        let mut fields: Vec<(String, String)> = Default::default();
+        for pair in pairs {
+            let mut split = pair.splitn(2, '=');
+            let field = split.next().unwrap_or_default().trim();
+            let value = split.next().unwrap_or_default().trim();
+            if field.is_empty() || value.is_empty() {
+                return Err(format!("invalid filter `{}`, expected `field=value`", pair));
+            }
+
+            fields.push((field.to_string(), value.to_string()));
+        }
+
+        Ok(RecordFilter { fields, ..Default::default() })
+    }
+
+    /// Add the dates to filter between, in anything `parse_human_date` understands,
+    /// ie. `--since "last monday" --before "2 weeks ago"`.
+    #[instrument]
+    #[inline]
+    pub fn with_dates(mut self, since: Option<&str>, before: Option<&str>, now: DateTime<Utc>) -> Result<Self, String> {
+        self.since = since.map(|s| parse_human_date(s, now)).transpose()?;
+        self.before = before.map(|s| parse_human_date(s, now)).transpose()?;
+        if let (Some(since), Some(before)) = (self.since, self.before) {
+            if since >= before {
+                return Err(format!(
+                    "the date to filter since, {}, is not before the date to filter before, {}",
+                    since.to_rfc3339(),
+                    before.to_rfc3339()
+                ));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Returns if the filter is empty, meaning every record matches.
+    #[instrument]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && !self.has_dates()
+    }
+
+    /// Returns if the filter has dates to filter between.
+    #[instrument]
+    #[inline]
+    pub fn has_dates(&self) -> bool {
+        self.since.is_some() || self.before.is_some()
+    }
+
+    /// Describe the dates of the filter for a report, ie. `from 2021-03-17 to 2021-03-29`,
+    /// or the default if there are no dates.
+    #[instrument]
+    #[inline]
+    pub fn describe_dates(&self, default: &str) -> String {
+        match (self.since, self.before) {
+            (Some(since), Some(before)) => format!("from {} to {}", format_date(since.date().naive_utc()), format_date(before.date().naive_utc())),
+            (Some(since), None) => format!("since {}", format_date(since.date().naive_utc())),
+            (None, Some(before)) => format!("before {}", format_date(before.date().naive_utc())),
+            (None, None) => default.to_string(),
+        }
+    }
+
+    /// Returns if a record's date is between the dates of the filter.
+    #[instrument]
+    #[inline]
+    pub fn matches_date(&self, date: DateTime<Utc>) -> bool {
+        self.since.map(|since| date >= since).unwrap_or(true) && self.before.map(|before| date < before).unwrap_or(true)
+    }
+
+    /// Returns if a record matches the filter. Strings are compared case insensitively.
+    #[instrument(skip(record))]
+    #[inline]
+    pub fn matches<T: Serialize>(&self, record: &T) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        let v = serde_json::to_value(record).unwrap_or_default();
+        self.fields.iter().all(|(field, value)| match &v[field] {
+            Value::String(s) => s.to_lowercase() == value.to_lowercase(),
+            Value::Null => false,
+            other => other.to_string() == *value,
+        })
+    }
+}
+
+/// Parse a date the way people write it on the command line, relative to now. Dates
+/// without a time are the start of the day in UTC. This understands:
+/// - `now`, `today`, and `yesterday`
+/// - a weekday, ie. `monday` or `last monday`, which is the last one before today
+/// - `last week`, `last month`, and `last year`, which are the same as `1 week ago`
+/// - `3 days ago`, `2 weeks ago`, `an hour ago`, in hours, days, weeks, months, or years
+/// - `2021-04-01` or `2021-04-01T09:00:00Z`
+#[instrument]
+#[inline]
+pub fn parse_human_date(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let words: Vec<String> = input.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+    let today = now.date().and_hms(0, 0, 0);
+
+    match words.as_slice() {
+        ["now"] => return Ok(now),
+        ["today"] => return Ok(today),
+        ["yesterday"] => return Ok(today - Duration::days(1)),
+        ["last", unit] if ["week", "month", "year"].contains(unit) => return date_ago(now, 1, unit),
+        ["last", day] | [day] if day.parse::<Weekday>().is_ok() => {
+            let weekday = day.parse::<Weekday>().unwrap();
+            let mut days = (7 + now.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+            if days == 0 {
+                days = 7;
+            }
+            return Ok(today - Duration::days(days as i64));
+        }
+        [n, unit, "ago"] => {
+            let n: i64 = match *n {
+                "a" | "an" => 1,
+                n => n.parse().map_err(|_| format!("`{}` is not a number in the date `{}`", n, input))?,
+            };
+            return date_ago(now, n, unit.trim_end_matches('s'));
+        }
+        _ => (),
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d") {
+        return Ok(Utc.from_utc_date(&date).and_hms(0, 0, 0));
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(input.trim()) {
+        return Ok(date.with_timezone(&Utc));
+    }
+
+    Err(format!(
+        "could not understand the date `{}`, try something like `today`, `last monday`, `2 weeks ago`, or `2021-04-01`",
+        input
+    ))
+}
+
+/// The date some number of units before now. Everything but hours is the start of the day.
+fn date_ago(now: DateTime<Utc>, n: i64, unit: &str) -> Result<DateTime<Utc>, String> {
+    let today = now.date().and_hms(0, 0, 0);
+    match unit {
+        "hour" => Ok(now - Duration::hours(n)),
+        "day" => Ok(today - Duration::days(n)),
+        "week" => Ok(today - Duration::weeks(n)),
+        "month" | "year" => {
+            let months = if unit == "year" { n * 12 } else { n };
+            let total = now.year() as i64 * 12 + now.month0() as i64 - months;
+            let (year, month) = ((total / 12) as i32, (total % 12) as u32 + 1);
+            // Go back to the last day of the month if the month is shorter, ie.
+            // a month before March 31st is February 28th.
+            let mut day = now.day();
+            while NaiveDate::from_ymd_opt(year, month, day).is_none() {
+                day -= 1;
+            }
+            Ok(Utc.ymd(year, month, day).and_hms(0, 0, 0))
+        }
+        _ => Err(format!("`{}` is not a unit of time we understand, use hours, days, weeks, months, or years", unit)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::filter::{parse_human_date, RecordFilter};
+
+    #[test]
+    fn test_record_filter() {
+        let record = serde_json::json!({"name": "Okta", "number": 123, "users": 10});
+
+        assert!(RecordFilter::default().matches(&record));
+        assert!(RecordFilter::parse(&["name=okta"]).unwrap().matches(&record));
+        assert!(RecordFilter::parse(&["name=Okta", "number=123"]).unwrap().matches(&record));
+        assert!(!RecordFilter::parse(&["number=12"]).unwrap().matches(&record));
+        assert!(!RecordFilter::parse(&["missing=thing"]).unwrap().matches(&record));
+        assert!(RecordFilter::parse(&["name"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_human_date() {
+        // A Wednesday.
+        let now = Utc.ymd(2021, 3, 31).and_hms(15, 30, 0);
+
+        assert_eq!(parse_human_date("now", now).unwrap(), now);
+        assert_eq!(parse_human_date(" Today ", now).unwrap(), Utc.ymd(2021, 3, 31).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("yesterday", now).unwrap(), Utc.ymd(2021, 3, 30).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("last monday", now).unwrap(), Utc.ymd(2021, 3, 29).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("friday", now).unwrap(), Utc.ymd(2021, 3, 26).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("last wednesday", now).unwrap(), Utc.ymd(2021, 3, 24).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("last week", now).unwrap(), Utc.ymd(2021, 3, 24).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("2 weeks ago", now).unwrap(), Utc.ymd(2021, 3, 17).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("an hour ago", now).unwrap(), Utc.ymd(2021, 3, 31).and_hms(14, 30, 0));
+        assert_eq!(parse_human_date("1 month ago", now).unwrap(), Utc.ymd(2021, 2, 28).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("15 months ago", now).unwrap(), Utc.ymd(2019, 12, 31).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("2021-01-05", now).unwrap(), Utc.ymd(2021, 1, 5).and_hms(0, 0, 0));
+        assert_eq!(parse_human_date("2021-01-05T09:00:00-05:00", now).unwrap(), Utc.ymd(2021, 1, 5).and_hms(14, 0, 0));
+
+        assert!(parse_human_date("two weeks ago", now).unwrap_err().contains("is not a number"));
+        assert!(parse_human_date("3 fortnights ago", now).unwrap_err().contains("is not a unit of time"));
+        assert!(parse_human_date("whenever", now).is_err());
+    }
+
+    #[test]
+    fn test_record_filter_dates() {
+        let now = Utc.ymd(2021, 3, 31).and_hms(15, 30, 0);
+        let filter = RecordFilter::default().with_dates(Some("2 weeks ago"), Some("last monday"), now).unwrap();
+        assert!(!filter.is_empty());
+        assert!(filter.has_dates());
+
+        assert!(filter.matches_date(Utc.ymd(2021, 3, 17).and_hms(0, 0, 0)));
+        assert!(filter.matches_date(Utc.ymd(2021, 3, 28).and_hms(23, 59, 59)));
+        assert!(!filter.matches_date(Utc.ymd(2021, 3, 29).and_hms(0, 0, 0)));
+        assert!(!filter.matches_date(Utc.ymd(2021, 3, 16).and_hms(23, 0, 0)));
+        assert!(RecordFilter::default().matches_date(now));
+
+        assert_eq!(filter.describe_dates("this week"), "from 2021-03-17 to 2021-03-29");
+        assert_eq!(
+            RecordFilter::default().with_dates(Some("yesterday"), None, now).unwrap().describe_dates("this week"),
+            "since 2021-03-30"
+        );
+        assert_eq!(RecordFilter::default().describe_dates("this week"), "this week");
+
+        assert!(RecordFilter::default().with_dates(Some("today"), Some("last week"), now).is_err());
+    }
+}
@@ -1,24 +1,41 @@
 use std::env;
 
 use async_trait::async_trait;
-use gsuite_api::GSuite;
+use chrono::naive::NaiveDate;
+use chrono::{Datelike, Duration, TimeZone, Utc};
+use diesel::sql_types::Text;
 use macros::db;
-use okta::Okta;
+use okta::{Application, Okta};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
+use crate::actions_usage::get_github_actions_cost_for_month;
 use crate::airtable::{AIRTABLE_BASE_ID_FINANCE, AIRTABLE_SOFTWARE_VENDORS_TABLE};
-use crate::configs::Group;
+use crate::attachments::AirtableAttachments;
+use crate::carbon::{format_carbon_footprint, get_cloud_emissions};
+use crate::chat::ChatChannel;
+use crate::cloud_costs::get_cloud_costs_by_group_for_month;
+use crate::configs::{Config, Group};
 use crate::core::UpdateAirtableRecord;
 use crate::db::Database;
+use crate::filter::RecordFilter;
+use crate::format::{format_date, format_month, format_usd};
+use crate::headcount::{format_headcount, get_headcount_comparisons};
+use crate::lunch::get_lunch_cost_for_month;
+use crate::matching::{has_pending_match_review, resolve_match, MatchThresholds};
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::progress::Progress;
+use crate::reports::{send_report, Audience, Report};
 use crate::schema::software_vendors;
-use crate::utils::{authenticate_github_jwt, get_gsuite_token, github_org, GSUITE_DOMAIN};
+use crate::utils::{authenticate_github_jwt, get_gsuite_token, github_org, gsuite_client};
 
 #[db {
     new_struct_name = "SoftwareVendor",
     airtable_base_id = "AIRTABLE_BASE_ID_FINANCE",
     airtable_table = "AIRTABLE_SOFTWARE_VENDORS_TABLE",
+    source = "airtable",
     match_on = {
         "name" = "String",
     },
@@ -28,8 +45,8 @@ use crate::utils::{authenticate_github_jwt, get_gsuite_token, github_org, GSUITE
 pub struct NewSoftwareVendor {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub name: String,
-    #[serde(default, skip_serializing_if = "String::is_empty")]
-    pub status: String,
+    #[serde(default)]
+    pub status: VendorStatus,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub description: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -52,8 +69,121 @@ pub struct NewSoftwareVendor {
     pub flat_cost_per_month: f32,
     #[serde(default)]
     pub total_cost_per_month: f32,
+    /// The names of the groups that use the vendor, linked to the groups in Airtable.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[airtable_link = "Group"]
     pub groups: Vec<String>,
+    /// The status of the security questionnaire we sent the vendor.
+    #[serde(default)]
+    pub security_questionnaire_status: SecurityQuestionnaireStatus,
+    /// The classification of the data we store with the vendor. Possible values are:
+    /// - public
+    /// - internal
+    /// - confidential
+    /// - restricted
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub data_classification: String,
+    /// If we have a signed data processing agreement with the vendor.
+    #[serde(default)]
+    pub dpa_signed: bool,
+    /// The date of the last security review for the vendor.
+    #[serde(default = "crate::utils::default_date", serialize_with = "crate::configs::null_date_format::serialize")]
+    pub security_review_date: NaiveDate,
+    /// The receipts and invoices for the vendor, from the attachment column in Airtable.
+    #[serde(default, skip_serializing_if = "AirtableAttachments::is_empty")]
+    pub receipts: AirtableAttachments,
+}
+
+/// The status of a software vendor, the options of the status column in Airtable.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, JsonSchema, FromSqlRow, AsExpression, Deserialize, Serialize)]
+#[sql_type = "Text"]
+pub enum VendorStatus {
+    /// We use and pay for the vendor.
+    Active,
+    /// We are trying the vendor out before we pay for it.
+    Trial,
+    /// We no longer use the vendor.
+    Cancelled,
+}
+
+impl Default for VendorStatus {
+    #[instrument]
+    #[inline]
+    fn default() -> Self {
+        VendorStatus::Active
+    }
+}
+
+crate::text_enum!(VendorStatus);
+
+/// The status of the security questionnaire we sent a software vendor.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, JsonSchema, FromSqlRow, AsExpression, Deserialize, Serialize)]
+#[sql_type = "Text"]
+#[serde(rename_all = "lowercase")]
+pub enum SecurityQuestionnaireStatus {
+    #[serde(rename = "not started", alias = "Not started")]
+    NotStarted,
+    #[serde(alias = "Sent")]
+    Sent,
+    #[serde(alias = "Received")]
+    Received,
+    #[serde(alias = "Approved")]
+    Approved,
+}
+
+impl Default for SecurityQuestionnaireStatus {
+    #[instrument]
+    #[inline]
+    fn default() -> Self {
+        SecurityQuestionnaireStatus::NotStarted
+    }
+}
+
+crate::text_enum!(SecurityQuestionnaireStatus);
+
+impl NewSoftwareVendor {
+    /// Returns if the vendor stores data we classify as sensitive.
+    #[instrument]
+    #[inline]
+    pub fn stores_sensitive_data(&self) -> bool {
+        let classification = self.data_classification.to_lowercase();
+        classification == "confidential" || classification == "restricted"
+    }
+
+    /// Returns if the vendor is overdue for a security review as of the given date.
+    /// Vendors storing sensitive data need to be reviewed at least every 12 months.
+    #[instrument]
+    #[inline]
+    pub fn needs_security_review(&self, today: NaiveDate) -> bool {
+        if !self.stores_sensitive_data() {
+            return false;
+        }
+
+        self.security_review_date == crate::utils::default_date() || today - self.security_review_date > Duration::days(365)
+    }
+
+    /// A stub for a vendor we only know about from its app in Okta, for finance to fill in.
+    #[instrument]
+    #[inline]
+    pub fn from_okta_app(app: &Application) -> Self {
+        serde_json::from_value(json!({
+            "name": app.label,
+            "description": format!("Added from the `{}` app in Okta, fill in the rest.", app.name),
+            "has_okta_integration": true,
+        }))
+        .unwrap()
+    }
+}
+
+/// The apps Okta adds to every org for its own dashboard and admin console.
+static OKTA_BUILTIN_APPS: &[&str] = &["okta_browser_plugin", "okta_enduser", "okta_flow_sso", "saasure"];
+
+/// Returns if an Okta app is one people sign in to a vendor with over SAML or OIDC, rather
+/// than a bookmark, a password vault app, or one of Okta's own.
+#[instrument]
+#[inline]
+pub fn is_sso_app(app: &Application) -> bool {
+    app.status == "ACTIVE" && !OKTA_BUILTIN_APPS.contains(&app.name.as_str()) && (app.sign_on_mode.starts_with("SAML_") || app.sign_on_mode == "OPENID_CONNECT" || app.sign_on_mode == "WS_FEDERATION")
 }
 
 /// Implement updating the Airtable record for a SoftwareVendor.
@@ -64,13 +194,14 @@ impl UpdateAirtableRecord<SoftwareVendor> for SoftwareVendor {
     async fn update_airtable_record(&mut self, _record: SoftwareVendor) {}
 }
 
-/// Sync software vendors from Airtable.
+/// Sync software vendors from Airtable. Only the vendors matching the filter, and with
+/// a last security review between the dates of the filter, are synced.
 #[instrument]
 #[inline]
-pub async fn refresh_software_vendors() {
+pub async fn refresh_software_vendors(filter: &RecordFilter) {
     let gsuite_customer = env::var("GADMIN_ACCOUNT_ID").unwrap();
     let token = get_gsuite_token("").await;
-    let gsuite = GSuite::new(&gsuite_customer, GSUITE_DOMAIN, token.clone());
+    let gsuite = gsuite_client(&gsuite_customer, token.clone());
 
     let db = Database::new();
 
@@ -81,9 +212,14 @@ pub async fn refresh_software_vendors() {
     let slack = slack_chat_api::Slack::new_from_env();
 
     // Get all the records from Airtable.
-    let results: Vec<airtable_api::Record<SoftwareVendor>> = SoftwareVendor::airtable().list_records(&SoftwareVendor::airtable_table(), "Grid view", vec![]).await.unwrap();
+    let results = SoftwareVendor::list_airtable_records().await;
+    let mut progress = Progress::new("Software Vendors", results.len());
     for vendor_record in results {
         let mut vendor: NewSoftwareVendor = vendor_record.fields.into();
+        progress.inc(&vendor.name);
+        if !filter.matches(&vendor) || !filter.matches_date(Utc.from_utc_date(&vendor.security_review_date).and_hms(0, 0, 0)) {
+            continue;
+        }
 
         if vendor.name == "GitHub" {
             // Update the number of GitHub users in our org.
@@ -129,15 +265,272 @@ pub async fn refresh_software_vendors() {
         }
         db_vendor.update(&db).await;
     }
+
+    progress.finish();
+}
+
+/// Flag the vendors storing sensitive data whose security review is older than
+/// 12 months, or that have never been reviewed, in the #security channel.
+#[instrument(skip(db))]
+#[inline]
+pub async fn flag_vendors_needing_security_review(db: &Database) {
+    let today = Utc::now().date().naive_utc();
+
+    let mut flagged: Vec<String> = Default::default();
+    for vendor in SoftwareVendors::get_from_db(db) {
+        let v: NewSoftwareVendor = vendor.into();
+        if !v.needs_security_review(today) {
+            continue;
+        }
+
+        let mut last_review = "never reviewed".to_string();
+        if v.security_review_date != crate::utils::default_date() {
+            last_review = format!("last reviewed {}", format_date(v.security_review_date));
+        }
+
+        flagged.push(format!(
+            "• *{}* ({}) | {} | questionnaire: _{}_ | DPA signed: _{}_",
+            v.name, v.data_classification, last_review, v.security_questionnaire_status, v.dpa_signed
+        ));
+    }
+
+    if flagged.is_empty() {
+        println!("[vendors] all vendors storing sensitive data have a current security review");
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Security,
+        "vendors.security_review",
+        json!({
+            "text": render_message("vendors.security_review", &json!({ "lines": flagged })),
+        }),
+    )
+    .await;
+}
+
+/// Reconcile the SAML and OIDC apps in Okta with our software vendors. Apps are matched
+/// to vendors by name, with the matches we are not sure about queued for review. Apps
+/// with no vendor get a stub vendor so finance tracks them, and vendors that say they
+/// have an Okta integration but have no app are flagged in the #security channel.
+#[instrument(skip(db))]
+#[inline]
+pub async fn reconcile_okta_apps(db: &Database) {
+    let okta = Okta::new_from_env();
+    let apps: Vec<Application> = okta.list_applications().await.unwrap().into_iter().filter(is_sso_app).collect();
+
+    let vendors: Vec<SoftwareVendor> = SoftwareVendors::get_from_db(db).into_iter().collect();
+    let names: Vec<String> = vendors.iter().map(|v| v.name.to_string()).collect();
+    let thresholds = MatchThresholds::default();
+
+    let mut lines: Vec<String> = Default::default();
+    let mut matched: Vec<String> = Default::default();
+    for app in apps {
+        if let Some(name) = resolve_match(db, "okta_app", &app.label, &names, &thresholds).await {
+            matched.push(name);
+            continue;
+        }
+        if has_pending_match_review(db, "okta_app", &app.label) {
+            continue;
+        }
+
+        NewSoftwareVendor::from_okta_app(&app).upsert(db).await;
+        lines.push(format!("• added a stub vendor for *{}* ({}), fill in its costs", app.label, app.sign_on_mode));
+    }
+
+    for mut vendor in vendors {
+        if vendor.status == VendorStatus::Cancelled {
+            continue;
+        }
+
+        let has_app = matched.contains(&vendor.name);
+        if vendor.has_okta_integration && !has_app {
+            lines.push(format!("• *{}* says it has an Okta integration but there is no app for it in Okta", vendor.name));
+        } else if !vendor.has_okta_integration && has_app {
+            vendor.has_okta_integration = true;
+            vendor.update(db).await;
+            lines.push(format!("• marked *{}* as having an Okta integration", vendor.name));
+        }
+    }
+
+    if lines.is_empty() {
+        println!("[vendors] the apps in Okta match our software vendors");
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Security,
+        "vendors.okta_apps",
+        json!({
+            "text": render_message("vendors.okta_apps", &json!({ "lines": lines })),
+        }),
+    )
+    .await;
+}
+
+/// Send a rollup of our spend for the month before the given date. This includes the
+/// recurring software vendor costs, cloud spend, what we spent on CI, and office lunches.
+/// What each vendor costs is only for finance and exec, so everyone else gets a redacted
+/// variant, see the `finance_rollup` report in `crate::reports::REPORTS`. Exec also gets
+/// where each team is against its headcount plan for the quarter, and our estimated
+/// carbon footprint.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_monthly_finance_rollup(db: &Database, config: &Config, today: NaiveDate) {
+    let last_month = NaiveDate::from_ymd(today.year(), today.month(), 1) - Duration::days(1);
+    // The report only reads, so it reads from the replica.
+    let db = &db.read_only();
+
+    let mut vendors: Vec<SoftwareVendor> = SoftwareVendors::get_from_db(db).into_iter().filter(|v| v.total_cost_per_month > 0.0).collect();
+    vendors.sort_by(|a, b| b.total_cost_per_month.partial_cmp(&a.total_cost_per_month).unwrap());
+    let vendors_total: f32 = vendors.iter().map(|v| v.total_cost_per_month).sum();
+
+    let mut report = Report::new(&format!("Finance rollup for {}", format_month(last_month)));
+
+    report.add(Audience::Managers, &format!("*Software vendors:* {}/month", format_usd(vendors_total)));
+    let mut text = "*Top software vendors:*\n".to_string();
+    for v in vendors.iter().take(5) {
+        text += &format!("• {}: {}\n", v.name, format_usd(v.total_cost_per_month));
+    }
+    report.add(Audience::Finance, &text);
+
+    let mut ci: Vec<(String, f32)> = get_github_actions_cost_for_month(db, last_month).into_iter().collect();
+    ci.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    let ci_total: f32 = ci.iter().map(|(_, cost)| cost).sum();
+
+    let mut text = format!("*CI (GitHub Actions):* {}\n", format_usd(ci_total));
+    for (repo, cost) in ci.iter().take(5) {
+        text += &format!("• `{}`: {}\n", repo, format_usd(*cost));
+    }
+    report.add(Audience::All, &text);
+
+    let mut cloud: Vec<(String, f32)> = get_cloud_costs_by_group_for_month(db, last_month).into_iter().collect();
+    cloud.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    let cloud_total: f32 = cloud.iter().map(|(_, cost)| cost).sum();
+
+    let mut text = format!("*Cloud (GCP and AWS):* {}\n", format_usd(cloud_total));
+    for (group, cost) in cloud.iter() {
+        let mut group = group.to_string();
+        if group.is_empty() {
+            group = "no owner".to_string();
+        }
+        text += &format!("• {}: {}\n", group, format_usd(*cost));
+    }
+    report.add(Audience::All, &text);
+
+    let lunch_total = get_lunch_cost_for_month(db, last_month);
+    report.add(Audience::All, &format!("*Office lunches:* {}", format_usd(lunch_total)));
+
+    report.add(Audience::Managers, &format!("*Total:* {}", format_usd(vendors_total + ci_total + cloud_total + lunch_total)));
+
+    let headcount = format_headcount(&get_headcount_comparisons(db, last_month));
+    if !headcount.is_empty() {
+        report.add(Audience::Exec, &headcount);
+    }
+
+    // For our sustainability disclosures.
+    report.add(Audience::Exec, &format_carbon_footprint(&get_cloud_emissions(db, config), last_month));
+
+    send_report("finance_rollup", &report).await;
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::finance::refresh_software_vendors;
+    use chrono::naive::NaiveDate;
+    use chrono::{Datelike, Utc};
+    use okta::Application;
+
+    use crate::configs::get_configs_from_repo;
+    use crate::db::Database;
+    use crate::filter::RecordFilter;
+    use crate::finance::{flag_vendors_needing_security_review, is_sso_app, reconcile_okta_apps, refresh_software_vendors, send_monthly_finance_rollup, NewSoftwareVendor};
+    use crate::utils::authenticate_github_jwt;
 
     #[ignore]
     #[tokio::test(threaded_scheduler)]
     async fn test_software_vendors() {
-        refresh_software_vendors().await;
+        refresh_software_vendors(&RecordFilter::default()).await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_vendor_security_reviews() {
+        let db = Database::new();
+
+        flag_vendors_needing_security_review(&db).await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_okta_apps() {
+        let db = Database::new();
+
+        reconcile_okta_apps(&db).await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_monday_cron_monthly_finance_rollup() {
+        let today = Utc::now().date().naive_utc();
+        // Only send the rollup on the first Monday of the month.
+        if today.day() > 7 {
+            return;
+        }
+
+        let db = Database::new();
+        let github = authenticate_github_jwt();
+        let config = get_configs_from_repo(&github).await;
+
+        send_monthly_finance_rollup(&db, &config, today).await;
+    }
+
+    #[test]
+    fn test_vendor_needs_security_review() {
+        let today = NaiveDate::from_ymd(2021, 4, 1);
+        let mut vendor: NewSoftwareVendor = serde_json::from_str(r#"{"name": "Acme", "data_classification": "Confidential"}"#).unwrap();
+
+        // Never reviewed.
+        assert!(vendor.needs_security_review(today));
+
+        vendor.security_review_date = NaiveDate::from_ymd(2020, 6, 1);
+        assert!(!vendor.needs_security_review(today));
+
+        vendor.security_review_date = NaiveDate::from_ymd(2020, 3, 1);
+        assert!(vendor.needs_security_review(today));
+
+        // Vendors without sensitive data never need a review.
+        vendor.data_classification = "public".to_string();
+        assert!(!vendor.needs_security_review(today));
+    }
+
+    #[test]
+    fn test_okta_app_vendor_stubs() {
+        let mut app = Application {
+            id: "0oa1".to_string(),
+            name: "zoomus".to_string(),
+            label: "Zoom".to_string(),
+            status: "ACTIVE".to_string(),
+            sign_on_mode: "SAML_2_0".to_string(),
+        };
+        assert!(is_sso_app(&app));
+
+        let vendor = NewSoftwareVendor::from_okta_app(&app);
+        assert_eq!(vendor.name, "Zoom");
+        assert!(vendor.has_okta_integration);
+
+        app.sign_on_mode = "BOOKMARK".to_string();
+        assert!(!is_sso_app(&app));
+
+        app.sign_on_mode = "OPENID_CONNECT".to_string();
+        app.status = "INACTIVE".to_string();
+        assert!(!is_sso_app(&app));
+
+        app.status = "ACTIVE".to_string();
+        app.name = "saasure".to_string();
+        assert!(!is_sso_app(&app));
     }
 }
@@ -0,0 +1,191 @@
+use std::fmt::Debug;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+
+/// The format of dates in our reports, ie. `2021-04-01`.
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The format of times in our reports, ie. `2021-04-01 09:00 UTC`.
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+/// The currencies we know how to format, by code, with their symbol and the number of
+/// decimals they are written with.
+const CURRENCIES: &[(&str, &str, usize)] = &[("USD", "$", 2), ("EUR", "€", 2), ("GBP", "£", 2), ("CAD", "CA$", 2), ("JPY", "¥", 0)];
+
+/// Separate the thousands of the whole part of a number with commas, ie. `1234567` is
+/// `1,234,567`.
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+
+    grouped
+}
+
+/// Format an amount of money in a currency, with the symbol and number of decimals of the
+/// currency, ie. `$1,234.50`, `-€12.00`, or `¥1,235`. Currencies we do not know are
+/// written with their code after the amount, ie. `1,234.50 CHF`.
+#[instrument]
+#[inline]
+pub fn format_money<T: Into<f64> + Debug>(amount: T, currency: &str) -> String {
+    let amount: f64 = amount.into();
+    let currency = currency.to_uppercase();
+    let (symbol, decimals) = CURRENCIES
+        .iter()
+        .find(|(code, _, _)| *code == currency)
+        .map(|(_, symbol, decimals)| (*symbol, *decimals))
+        .unwrap_or(("", 2));
+
+    let number = format!("{:.*}", decimals, amount.abs());
+    let (whole, fraction) = match number.find('.') {
+        Some(i) => number.split_at(i),
+        None => (number.as_str(), ""),
+    };
+    // Amounts that round to zero are not negative.
+    let sign = if amount < 0.0 && number.chars().any(|c| c.is_ascii_digit() && c != '0') { "-" } else { "" };
+
+    if symbol.is_empty() {
+        return format!("{}{}{} {}", sign, group_thousands(whole), fraction, currency);
+    }
+
+    format!("{}{}{}{}", sign, symbol, group_thousands(whole), fraction)
+}
+
+/// Format an amount of US dollars, which is what we report our spend in, ie. `$1,234.50`.
+#[instrument]
+#[inline]
+pub fn format_usd<T: Into<f64> + Debug>(amount: T) -> String {
+    format_money(amount, "USD")
+}
+
+/// Format a large number compactly, ie. `950`, `1.2k`, `3M`, or `1.5B`, with at most one
+/// decimal.
+#[instrument]
+#[inline]
+pub fn format_compact<T: Into<f64> + Debug>(n: T) -> String {
+    let n: f64 = n.into();
+
+    let mut value = (n * 10.0).round() / 10.0;
+    let mut suffix = "";
+    for unit in &["k", "M", "B"] {
+        if value.abs() < 1000.0 {
+            break;
+        }
+        value = (value / 100.0).round() / 10.0;
+        suffix = unit;
+    }
+
+    let mut number = format!("{:.1}", value);
+    if number.ends_with(".0") {
+        number.truncate(number.len() - 2);
+    }
+
+    format!("{}{}", number, suffix)
+}
+
+/// Format a change as a percentage with an arrow for its direction, ie. `↑ 25%`, `↓ 10%`,
+/// or `→ 0%`. The change is a fraction, so `0.25` is `↑ 25%`.
+#[instrument]
+#[inline]
+pub fn format_percent_change(change: f64) -> String {
+    let percent = (change * 100.0).round();
+    let arrow = if percent > 0.0 {
+        "↑"
+    } else if percent < 0.0 {
+        "↓"
+    } else {
+        "→"
+    };
+
+    format!("{} {}%", arrow, percent.abs())
+}
+
+/// Format the change from a previous value to the current one as a percentage with an
+/// arrow for its direction, ie. `↑ 25%`. Anything up from nothing is `new`.
+#[instrument]
+#[inline]
+pub fn format_percent_delta<T: Into<f64> + Debug>(previous: T, current: T) -> String {
+    let previous: f64 = previous.into();
+    let current: f64 = current.into();
+    if previous == 0.0 {
+        return if current == 0.0 { format_percent_change(0.0) } else { "new".to_string() };
+    }
+
+    format_percent_change((current - previous) / previous.abs())
+}
+
+/// Format a date for a report, ie. `2021-04-01`.
+#[instrument]
+#[inline]
+pub fn format_date(date: NaiveDate) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+/// Format the month of a date for a report, ie. `April 2021`.
+#[instrument]
+#[inline]
+pub fn format_month(date: NaiveDate) -> String {
+    date.format("%B %Y").to_string()
+}
+
+/// Format a time for a report, in UTC, ie. `2021-04-01 09:00 UTC`.
+#[instrument]
+#[inline]
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+    use chrono::{TimeZone, Utc};
+
+    use crate::format::{format_compact, format_date, format_money, format_month, format_percent_change, format_percent_delta, format_time, format_usd};
+
+    #[test]
+    fn test_format_money() {
+        assert_eq!(format_usd(1234.5), "$1,234.50");
+        assert_eq!(format_usd(0.0), "$0.00");
+        assert_eq!(format_usd(-0.001), "$0.00");
+        assert_eq!(format_usd(-12), "-$12.00");
+        assert_eq!(format_usd(1234567.891_f32), "$1,234,567.88");
+        assert_eq!(format_money(999.999, "eur"), "€1,000.00");
+        assert_eq!(format_money(1234.6, "JPY"), "¥1,235");
+        assert_eq!(format_money(1234.5, "CHF"), "1,234.50 CHF");
+    }
+
+    #[test]
+    fn test_format_compact() {
+        assert_eq!(format_compact(950), "950");
+        assert_eq!(format_compact(1234), "1.2k");
+        assert_eq!(format_compact(12_000), "12k");
+        assert_eq!(format_compact(999_960), "1M");
+        assert_eq!(format_compact(-2_500_000), "-2.5M");
+        assert_eq!(format_compact(1.5e9), "1.5B");
+        assert_eq!(format_compact(0.25), "0.3");
+    }
+
+    #[test]
+    fn test_format_percent() {
+        assert_eq!(format_percent_change(0.254), "↑ 25%");
+        assert_eq!(format_percent_change(-0.1), "↓ 10%");
+        assert_eq!(format_percent_change(0.001), "→ 0%");
+        assert_eq!(format_percent_delta(40, 100), "↑ 150%");
+        assert_eq!(format_percent_delta(100, 75), "↓ 25%");
+        assert_eq!(format_percent_delta(0, 5), "new");
+        assert_eq!(format_percent_delta(0, 0), "→ 0%");
+    }
+
+    #[test]
+    fn test_format_dates() {
+        assert_eq!(format_date(NaiveDate::from_ymd(2021, 4, 1)), "2021-04-01");
+        assert_eq!(format_month(NaiveDate::from_ymd(2021, 4, 1)), "April 2021");
+        assert_eq!(format_time(Utc.ymd(2021, 4, 9).and_hms(8, 1, 30)), "2021-04-09 08:01 UTC");
+    }
+}
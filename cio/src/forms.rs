@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use hubcaps::Github;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::applicants::{get_role_from_sheet_id, refresh_db_applicants, update_applications_with_scoring_results, Applicant};
+use crate::chat::{ChatChannel, CHAT_CHANNELS};
+use crate::configs::get_configs_from_repo;
+use crate::db::Database;
+use crate::filter::RecordFilter;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::reviews::{record_review_response, ReviewsConfig};
+use crate::schema::applicants;
+
+/// The header the Apps Script sends the shared secret in, which must match the
+/// `GOOGLE_FORMS_WEBHOOK_SECRET` environment variable.
+pub static FORMS_SECRET_HEADER: &str = "X-CIO-Forms-Secret";
+
+/// How long before the response we look for applications in the sheet linked to an
+/// application form, in case the clocks of Google and the sheet disagree.
+const APPLICATION_LOOKBACK_MINUTES: i64 = 5;
+
+/// A response to a Google Form, sent by an Apps Script `onFormSubmit` trigger on the form:
+///
+/// ```js
+/// function onFormSubmit(e) {
+///   var answers = {};
+///   e.response.getItemResponses().forEach(function (r) {
+///     var answer = r.getResponse();
+///     answers[r.getItem().getTitle()] = Array.isArray(answer) ? answer.map(String) : [String(answer)];
+///   });
+///   UrlFetchApp.fetch("https://webhooks.corp.oxide.computer/google/forms/response", {
+///     method: "post",
+///     contentType: "application/json",
+///     headers: { "X-CIO-Forms-Secret": PropertiesService.getScriptProperties().getProperty("secret") },
+///     payload: JSON.stringify({
+///       form_id: e.source.getId(),
+///       form_title: e.source.getTitle(),
+///       destination_id: e.source.getDestinationId() || "",
+///       response_id: e.response.getId(),
+///       timestamp: e.response.getTimestamp().toISOString(),
+///       respondent_email: e.response.getRespondentEmail(),
+///       answers: answers,
+///     }),
+///   });
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct GoogleFormResponse {
+    pub form_id: String,
+    #[serde(default)]
+    pub form_title: String,
+    /// The ID of the spreadsheet the form saves its responses to, if it has one.
+    #[serde(default)]
+    pub destination_id: String,
+    pub response_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Empty unless the form collects the emails of the people who respond.
+    #[serde(default)]
+    pub respondent_email: String,
+    /// The answers by the title of each question, in the same shape as the `namedValues`
+    /// of a spreadsheet form submit event.
+    #[serde(default)]
+    pub answers: HashMap<String, Vec<String>>,
+}
+
+/// The data type for an internal request form, ie. for a new laptop, from the `forms`
+/// section of our configs. Responses are posted to the channel.
+///
+/// ```toml
+/// [forms.laptop-request]
+/// form_id = "1FAIpQLSf9d3x0fSSgcCv4TEZ3iHfzk3b7iKvHhxqJq6TcmzKbWxlwtA"
+/// channel = "finance"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct FormConfig {
+    pub form_id: String,
+    /// The channel to post responses to, one of the channels our notifications post to,
+    /// ie. `finance`.
+    pub channel: String,
+}
+
+impl FormConfig {
+    /// Get the channel to post responses to.
+    #[instrument]
+    #[inline]
+    pub fn chat_channel(&self) -> Option<ChatChannel> {
+        CHAT_CHANNELS.iter().find(|c| c.env_name().eq_ignore_ascii_case(&self.channel)).copied()
+    }
+}
+
+/// Where a form response goes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormPipeline {
+    /// An application for a role, which the form saves to one of our application sheets.
+    Applicants { role: String },
+    /// Someone scoring an applicant on the scoring form for the applicant.
+    InterviewFeedback,
+    /// A review in the open review cycle, by the kind of review.
+    Review(String),
+    /// An internal request, by the name of the form in our configs.
+    Request(String),
+}
+
+/// Get the pipeline for a form response. Applications are found by the sheet the form
+/// saves to and scoring forms by the applicant they are for, since there is one of each
+/// per role and per applicant, and everything else by the forms in our configs.
+#[instrument(skip(forms, reviews))]
+#[inline]
+pub fn route_form_response(response: &GoogleFormResponse, is_scoring_form: bool, forms: &BTreeMap<String, FormConfig>, reviews: &ReviewsConfig) -> Option<FormPipeline> {
+    let role = get_role_from_sheet_id(&response.destination_id);
+    if !response.destination_id.is_empty() && !role.is_empty() {
+        return Some(FormPipeline::Applicants { role });
+    }
+    if is_scoring_form {
+        return Some(FormPipeline::InterviewFeedback);
+    }
+    if let Some(kind) = reviews.form_kind(&response.form_id) {
+        return Some(FormPipeline::Review(kind.to_string()));
+    }
+
+    forms.iter().find(|(_, f)| f.form_id == response.form_id).map(|(name, _)| FormPipeline::Request(name.to_string()))
+}
+
+/// Format a response to an internal request form as a message, with an answer per line.
+#[instrument]
+#[inline]
+pub fn format_form_request(response: &GoogleFormResponse) -> Value {
+    let mut answers: Vec<(&String, &Vec<String>)> = response.answers.iter().collect();
+    answers.sort();
+    let lines: Vec<String> = answers.iter().map(|(question, answer)| format!("• *{}*: {}", question, answer.join(", "))).collect();
+    let from = if response.respondent_email.is_empty() {
+        "someone".to_string()
+    } else {
+        response.respondent_email.to_string()
+    };
+
+    json!({
+        "text": render_message("forms.request", &json!({ "title": response.form_title, "email": from, "lines": lines })),
+    })
+}
+
+/// Send a form response to its pipeline. Applications and scores are synced from their
+/// sheets right away rather than on the next run of the `applicants` job, reviews count
+/// towards the open review cycle, and internal requests are posted to their channel.
+/// Returns what was done.
+#[instrument(skip(db, github))]
+#[inline]
+pub async fn handle_form_response(db: &Database, github: &Github, response: &GoogleFormResponse) -> Result<String, String> {
+    let is_scoring_form = applicants::dsl::applicants
+        .filter(applicants::dsl::scoring_form_id.eq(response.form_id.to_string()))
+        .first::<Applicant>(&db.conn())
+        .is_ok();
+
+    // We only need our configs for reviews and internal requests.
+    let config = if is_scoring_form || !get_role_from_sheet_id(&response.destination_id).is_empty() {
+        Default::default()
+    } else {
+        get_configs_from_repo(github).await
+    };
+    let forms = &config.forms;
+
+    match route_form_response(response, is_scoring_form, forms, &config.reviews) {
+        Some(FormPipeline::Applicants { role }) => {
+            let mut filter = RecordFilter::parse(&[&format!("sheet_id={}", response.destination_id)])?;
+            filter.since = Some(response.timestamp - Duration::minutes(APPLICATION_LOOKBACK_MINUTES));
+            refresh_db_applicants(db, &filter).await;
+            Ok(format!("synced the applications for {} since {}", role, response.timestamp))
+        }
+        Some(FormPipeline::InterviewFeedback) => {
+            update_applications_with_scoring_results(db).await;
+            Ok(format!("synced the scores for scoring form {}", response.form_id))
+        }
+        Some(FormPipeline::Review(kind)) => record_review_response(db, &config, response, &kind),
+        Some(FormPipeline::Request(name)) => {
+            let channel = forms[&name]
+                .chat_channel()
+                .ok_or_else(|| format!("form `{}` posts to `{}` which is not a channel", name, forms[&name].channel))?;
+            notify(db, NotificationPriority::Urgent, channel, "forms.request", format_form_request(response)).await;
+            Ok(format!("posted the response to {} to {:?}", name, channel))
+        }
+        None => Ok(format!("form {} ({}) is not one we route", response.form_id, response.form_title)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::chat::ChatChannel;
+    use crate::forms::{format_form_request, route_form_response, FormConfig, FormPipeline, GoogleFormResponse};
+    use crate::reviews::ReviewsConfig;
+
+    #[test]
+    fn test_route_form_response() {
+        let mut response = GoogleFormResponse {
+            form_id: "1FAIpQLSlaptop".to_string(),
+            form_title: "Laptop request".to_string(),
+            destination_id: "".to_string(),
+            response_id: "2_ABaOnud".to_string(),
+            timestamp: Utc.ymd(2021, 5, 6).and_hms(17, 3, 0),
+            respondent_email: "jane@oxidecomputer.com".to_string(),
+            answers: Default::default(),
+        };
+        response.answers.insert("Which laptop?".to_string(), vec!["ThinkPad X1".to_string()]);
+        response.answers.insert("Accessories".to_string(), vec!["Dock".to_string(), "Monitor".to_string()]);
+
+        let mut forms = BTreeMap::new();
+        forms.insert(
+            "laptop-request".to_string(),
+            FormConfig {
+                form_id: "1FAIpQLSlaptop".to_string(),
+                channel: "finance".to_string(),
+            },
+        );
+        assert_eq!(forms["laptop-request"].chat_channel(), Some(ChatChannel::Finance));
+
+        let reviews = ReviewsConfig::default();
+        assert_eq!(route_form_response(&response, false, &forms, &reviews), Some(FormPipeline::Request("laptop-request".to_string())));
+        assert_eq!(route_form_response(&response, true, &forms, &reviews), Some(FormPipeline::InterviewFeedback));
+        assert_eq!(route_form_response(&response, false, &Default::default(), &reviews), None);
+
+        let reviews = ReviewsConfig {
+            peer_form: "1FAIpQLSlaptop".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(route_form_response(&response, false, &forms, &reviews), Some(FormPipeline::Review("peer".to_string())));
+
+        response.destination_id = "1FHA-otHCGwe5fCRpcl89MWI7GHiFfN3EWjO6K943rYA".to_string();
+        assert_eq!(
+            route_form_response(&response, false, &forms, &Default::default()),
+            Some(FormPipeline::Applicants { role: "Engineering".to_string() })
+        );
+
+        assert_eq!(
+            format_form_request(&response)["text"],
+            "*Laptop request* from jane@oxidecomputer.com:\n• *Accessories*: Dock, Monitor\n• *Which laptop?*: ThinkPad X1"
+        );
+    }
+}
@@ -4,6 +4,7 @@ use gsuite_api::{Building as GSuiteBuilding, BuildingAddress, CalendarResource a
 use tracing::{event, instrument, Level};
 
 use crate::configs::{Building, ConferenceRoom, Group};
+use crate::desks::DESK_RESOURCE_TYPE;
 use crate::utils::GSUITE_DOMAIN;
 
 /// Update a group's aliases in GSuite to match our configuration files.
@@ -101,7 +102,13 @@ pub fn update_gsuite_calendar_resource(c: &GSuiteCalendarResource, resource: &Co
     gsuite_conference_room.capacity = Some(resource.capacity);
     gsuite_conference_room.floor_name = resource.floor.to_string();
     gsuite_conference_room.floor_section = resource.section.to_string();
-    gsuite_conference_room.category = "CONFERENCE_ROOM".to_string();
+    // Desks are booked for the day from Slack rather than in a calendar invite, so
+    // they are not offered as rooms.
+    if resource.typev.eq_ignore_ascii_case(DESK_RESOURCE_TYPE) {
+        gsuite_conference_room.category = "OTHER".to_string();
+    } else {
+        gsuite_conference_room.category = "CONFERENCE_ROOM".to_string();
+    }
 
     gsuite_conference_room
 }
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::Datelike;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use tracing::instrument;
+
+use crate::configs::{OpenRole, OpenRoles, User, Users};
+use crate::db::Database;
+use crate::schema::headcount_plans;
+
+/// How many people each team plans to hire in each quarter, from the `headcount_plan`
+/// section of our configs, keyed by the group of the team and then the quarter.
+///
+/// ```toml
+/// [headcount_plan.eng]
+/// "2021-Q2" = 3
+/// "2021-Q3" = 2
+/// ```
+pub type HeadcountPlanConfig = BTreeMap<String, BTreeMap<String, i32>>;
+
+/// Parse a quarter formatted as `2021-Q2` into its year and its number.
+#[instrument]
+#[inline]
+pub fn parse_quarter(quarter: &str) -> Option<(i32, u32)> {
+    let mut parts = quarter.trim().splitn(2, "-Q");
+    let year = parts.next()?.parse::<i32>().ok()?;
+    let number = parts.next()?.parse::<u32>().ok()?;
+    if !(1..=4).contains(&number) {
+        return None;
+    }
+
+    Some((year, number))
+}
+
+/// Get the quarter a day is in, ie. `2021-Q2`.
+#[instrument]
+#[inline]
+pub fn quarter_of(date: NaiveDate) -> String {
+    format!("{}-Q{}", date.year(), (date.month() - 1) / 3 + 1)
+}
+
+/// Get the first and last days of a quarter.
+#[instrument]
+#[inline]
+pub fn quarter_days(quarter: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let (year, number) = parse_quarter(quarter)?;
+    let first = NaiveDate::from_ymd(year, (number - 1) * 3 + 1, 1);
+    let last = if number == 4 {
+        NaiveDate::from_ymd(year, 12, 31)
+    } else {
+        NaiveDate::from_ymd(year, number * 3 + 1, 1).pred()
+    };
+
+    Some((first, last))
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "headcount_plans"]
+pub struct NewHeadcountPlan {
+    pub team: String,
+    pub quarter: String,
+    pub planned: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct HeadcountPlan {
+    pub id: i32,
+    pub team: String,
+    pub quarter: String,
+    pub planned: i32,
+}
+
+/// Replace the headcount plan in the database with the one in our configs.
+#[instrument(skip(db))]
+#[inline]
+pub fn sync_headcount_plans(db: &Database, plan: &HeadcountPlanConfig) {
+    let rows: Vec<NewHeadcountPlan> = plan
+        .iter()
+        .flat_map(|(team, quarters)| {
+            quarters.iter().map(move |(quarter, planned)| NewHeadcountPlan {
+                team: team.to_string(),
+                quarter: quarter.trim().to_string(),
+                planned: *planned,
+            })
+        })
+        .collect();
+
+    let conn = db.conn();
+    diesel::delete(headcount_plans::table)
+        .execute(&conn)
+        .unwrap_or_else(|e| panic!("deleting the headcount plans failed: {}", e));
+    diesel::insert_into(headcount_plans::table)
+        .values(&rows)
+        .execute(&conn)
+        .unwrap_or_else(|e| panic!("creating the headcount plans failed: {}", e));
+}
+
+/// Where a team is against its plan for a quarter.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeadcountComparison {
+    pub team: String,
+    pub quarter: String,
+    pub planned: i32,
+    /// The people who joined the team in the quarter so far.
+    pub hired: i32,
+    /// The roles on the team we are hiring for right now.
+    pub open_roles: i32,
+}
+
+impl HeadcountComparison {
+    /// How many more people the team has hired than planned, negative if it is behind.
+    #[instrument]
+    #[inline]
+    pub fn difference(&self) -> i32 {
+        self.hired - self.planned
+    }
+
+    /// Describe where the team is against its plan, ie. `behind plan by 2`.
+    #[instrument]
+    #[inline]
+    pub fn status(&self) -> String {
+        match self.difference() {
+            0 => "on plan".to_string(),
+            d if d > 0 => format!("ahead of plan by {}", d),
+            d => format!("behind plan by {}", -d),
+        }
+    }
+}
+
+/// Compare the plan for a quarter against the people who joined each team by a day and
+/// the roles that are open. Teams that are furthest behind come first.
+#[instrument(skip(plans, users, roles))]
+#[inline]
+pub fn compare_headcount(plans: &[HeadcountPlan], users: &[User], roles: &[OpenRole], quarter: &str, as_of: NaiveDate) -> Vec<HeadcountComparison> {
+    let (first, last) = match quarter_days(quarter) {
+        Some(days) => days,
+        None => return vec![],
+    };
+    let last = last.min(as_of);
+
+    let mut comparisons: Vec<HeadcountComparison> = plans
+        .iter()
+        .filter(|p| p.quarter == quarter)
+        .map(|p| HeadcountComparison {
+            team: p.team.to_string(),
+            quarter: p.quarter.to_string(),
+            planned: p.planned,
+            hired: users
+                .iter()
+                .filter(|u| !u.is_system_account() && u.groups.contains(&p.team) && first <= u.start_date && u.start_date <= last)
+                .count() as i32,
+            open_roles: roles.iter().filter(|r| r.open && r.team == p.team).count() as i32,
+        })
+        .collect();
+    comparisons.sort_by(|a, b| a.difference().cmp(&b.difference()).then_with(|| a.team.cmp(&b.team)));
+
+    comparisons
+}
+
+/// Get where each team is against its plan for the quarter a day is in.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_headcount_comparisons(db: &Database, as_of: NaiveDate) -> Vec<HeadcountComparison> {
+    let quarter = quarter_of(as_of);
+    let plans: Vec<HeadcountPlan> = headcount_plans::table
+        .filter(headcount_plans::quarter.eq(&quarter))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the headcount plans for {} failed: {}", quarter, e));
+    let users: Vec<User> = Users::get_from_db(db).into();
+    let roles: Vec<OpenRole> = OpenRoles::get_from_db(db).into();
+
+    compare_headcount(&plans, &users, &roles, &quarter, as_of)
+}
+
+/// Format where each team is against its plan for a report, calling out the teams that
+/// are behind with nothing open to hire for.
+#[instrument]
+#[inline]
+pub fn format_headcount(comparisons: &[HeadcountComparison]) -> String {
+    if comparisons.is_empty() {
+        return String::new();
+    }
+
+    let planned: i32 = comparisons.iter().map(|c| c.planned).sum();
+    let hired: i32 = comparisons.iter().map(|c| c.hired).sum();
+    let mut text = format!("*Headcount for {}:* {} of {} planned hires\n", comparisons[0].quarter, hired, planned);
+    for c in comparisons.iter() {
+        let roles = if c.open_roles == 1 { "role" } else { "roles" };
+        let mut line = format!("• {}: {} of {} hired, {} open {}, {}", c.team, c.hired, c.planned, c.open_roles, roles, c.status());
+        if c.difference() < 0 && c.open_roles == 0 {
+            line += " with nothing open to hire for";
+        }
+        text += &format!("{}\n", line);
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+
+    use crate::headcount::{format_headcount, parse_quarter, quarter_days, quarter_of, HeadcountComparison};
+
+    #[test]
+    fn test_quarters() {
+        assert_eq!(parse_quarter("2021-Q2"), Some((2021, 2)));
+        assert_eq!(parse_quarter("2021-Q5"), None);
+        assert_eq!(parse_quarter("Q2-2021"), None);
+        assert_eq!(quarter_of(NaiveDate::from_ymd(2021, 6, 30)), "2021-Q2");
+        assert_eq!(quarter_of(NaiveDate::from_ymd(2021, 10, 1)), "2021-Q4");
+        assert_eq!(quarter_days("2021-Q1"), Some((NaiveDate::from_ymd(2021, 1, 1), NaiveDate::from_ymd(2021, 3, 31))));
+        assert_eq!(quarter_days("2021-Q4"), Some((NaiveDate::from_ymd(2021, 10, 1), NaiveDate::from_ymd(2021, 12, 31))));
+    }
+
+    #[test]
+    fn test_format_headcount() {
+        let comparisons = vec![
+            HeadcountComparison {
+                team: "eng".to_string(),
+                quarter: "2021-Q2".to_string(),
+                planned: 3,
+                hired: 1,
+                open_roles: 0,
+            },
+            HeadcountComparison {
+                team: "ops".to_string(),
+                quarter: "2021-Q2".to_string(),
+                planned: 1,
+                hired: 1,
+                open_roles: 1,
+            },
+            HeadcountComparison {
+                team: "sales".to_string(),
+                quarter: "2021-Q2".to_string(),
+                planned: 1,
+                hired: 2,
+                open_roles: 2,
+            },
+        ];
+
+        assert_eq!(
+            format_headcount(&comparisons),
+            "*Headcount for 2021-Q2:* 4 of 5 planned hires
+• eng: 1 of 3 hired, 0 open roles, behind plan by 2 with nothing open to hire for
+• ops: 1 of 1 hired, 1 open role, on plan
+• sales: 2 of 1 hired, 2 open roles, ahead of plan by 1
+"
+        );
+        assert_eq!(format_headcount(&[]), "");
+    }
+}
@@ -0,0 +1,608 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Read;
+use std::marker::PhantomData;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::instrument;
+
+use crate::applicants::{Applicants, NewApplicant};
+use crate::certs::{Certificates, NewCertificate};
+use crate::cloud_costs::{CloudCosts, NewCloudCost};
+use crate::db::{Database, BATCH_SIZE};
+use crate::event_registrants::{EventRegistrants, NewEventRegistrant};
+use crate::finance::{NewSoftwareVendor, SoftwareVendors};
+use crate::mailing_list::{MailingListSubscribers, NewMailingListSubscriber};
+use crate::progress::Progress;
+use crate::recorded_meetings::{NewRecordedMeeting, RecordedMeetings};
+use crate::shipments::{InboundShipments, NewInboundShipment};
+
+/// The tables we can import CSVs into. The tables synced from our configs are left
+/// out since the next sync of the configs would delete anything imported.
+pub static IMPORT_TABLES: &[&str] = &[
+    "applicants",
+    "certificates",
+    "cloud_costs",
+    "event_registrants",
+    "inbound_shipments",
+    "mailing_list_subscribers",
+    "recorded_meetings",
+    "software_vendors",
+];
+
+/// What we record as the source of the records we import.
+pub const IMPORT_SOURCE: &str = "csv";
+
+/// How many of the rows we could not import we list in the report.
+pub const MAX_REPORTED_IMPORT_ERRORS: usize = 1000;
+
+/// The data type for a mapping file, which says how the columns of a CSV map to
+/// the fields of a model.
+///
+/// ```toml
+/// # The field in the model and the CSV column it comes from.
+/// [columns]
+/// name = "Vendor"
+/// cost_per_user_per_month = "Price (per seat)"
+///
+/// # Fields the CSV does not have, set to the same value for every row.
+/// [defaults]
+/// status = "active"
+///
+/// # How dates are written in the CSV, defaults to `%Y-%m-%d`.
+/// date_format = "%m/%d/%Y"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct ImportMapping {
+    #[serde(default)]
+    pub columns: BTreeMap<String, String>,
+    #[serde(default)]
+    pub defaults: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub date_format: String,
+}
+
+impl ImportMapping {
+    /// Read and decode a mapping file.
+    #[instrument]
+    #[inline]
+    pub fn read(file: &str) -> Result<Self, String> {
+        let body = fs::read_to_string(file).map_err(|e| format!("reading {} failed: {}", file, e))?;
+        toml::from_str(&body).map_err(|e| format!("decoding {} failed: {}", file, e))
+    }
+}
+
+/// A row we could not import.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportError {
+    /// The line in the CSV, counting the header as line 1.
+    pub line: u64,
+    pub message: String,
+}
+
+/// What happened to each row of an import.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub table: String,
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// The rows we could not import, in the order they are in the CSV.
+    pub errors: Vec<ImportError>,
+    /// How many more rows we could not import past `MAX_REPORTED_IMPORT_ERRORS`.
+    pub more_errors: usize,
+}
+
+/// Format an import report for the terminal.
+#[instrument]
+#[inline]
+pub fn format_import_report(report: &ImportReport) -> String {
+    let mut out = format!(
+        "{}: {} created, {} updated, {} unchanged, {} failed\n",
+        report.table,
+        report.created,
+        report.updated,
+        report.unchanged,
+        report.failed()
+    );
+    for e in &report.errors {
+        out += &format!("  line {}: {}\n", e.line, e.message);
+    }
+    if report.more_errors > 0 {
+        out += &format!("  and {} more\n", report.more_errors);
+    }
+
+    out
+}
+
+/// Get the JSON types a field can be, ie. `["string", "null"]`, and its format.
+fn field_types(schema: &Value) -> (Vec<String>, String) {
+    let types = match &schema["type"] {
+        Value::String(t) => vec![t.to_string()],
+        Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).map(|t| t.to_string()).collect(),
+        _ => vec![],
+    };
+
+    (types, schema["format"].as_str().unwrap_or_default().to_string())
+}
+
+/// Follow the reference to the definition of the type of a field in the JSON schema of the
+/// model, ie. for the enums of our controlled vocabularies, so we know its JSON type.
+fn resolve_field_schema(schema: &Value, field_schema: &Value) -> Value {
+    let reference = field_schema["$ref"].as_str().or_else(|| field_schema["allOf"][0]["$ref"].as_str());
+    match reference.and_then(|r| r.strip_prefix("#/definitions/")) {
+        Some(name) => schema["definitions"][name].clone(),
+        None => field_schema.clone(),
+    }
+}
+
+/// Coerce a value from a CSV into the JSON type of a field from its JSON schema.
+#[instrument(skip(schema))]
+#[inline]
+pub fn coerce_value(raw: &str, schema: &Value, date_format: &str) -> Result<Value, String> {
+    let raw = raw.trim();
+    let (types, format) = field_types(schema);
+    if raw.is_empty() && types.iter().any(|t| t == "null") {
+        return Ok(Value::Null);
+    }
+
+    let date_format = if date_format.is_empty() { "%Y-%m-%d" } else { date_format };
+    let kind = types.iter().find(|t| *t != "null").map(|t| t.as_str());
+    if raw.is_empty() && (kind == Some("integer") || kind == Some("number") || format.starts_with("date")) {
+        return Err("a value is required".to_string());
+    }
+
+    match kind {
+        Some("boolean") => match raw.to_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" | "x" | "checked" => Ok(Value::Bool(true)),
+            "false" | "no" | "n" | "0" | "" | "unchecked" => Ok(Value::Bool(false)),
+            _ => Err(format!("`{}` is not a boolean", raw)),
+        },
+        Some("integer") => raw.replace(',', "").parse::<i64>().map(Value::from).map_err(|_| format!("`{}` is not a whole number", raw)),
+        Some("number") => raw
+            .trim_start_matches('$')
+            .trim_end_matches('%')
+            .replace(',', "")
+            .parse::<f64>()
+            .map(Value::from)
+            .map_err(|_| format!("`{}` is not a number", raw)),
+        Some("array") => raw
+            .split(',')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| coerce_value(v, &schema["items"], date_format))
+            .collect::<Result<Vec<Value>, String>>()
+            .map(Value::Array),
+        Some("string") if format == "date-time" => {
+            if let Ok(t) = DateTime::parse_from_rfc3339(raw) {
+                return Ok(Value::String(t.with_timezone(&Utc).to_rfc3339()));
+            }
+            if let Ok(t) = NaiveDateTime::parse_from_str(raw, date_format) {
+                return Ok(Value::String(DateTime::<Utc>::from_utc(t, Utc).to_rfc3339()));
+            }
+            NaiveDate::parse_from_str(raw, date_format)
+                .map(|d| Value::String(DateTime::<Utc>::from_utc(d.and_hms(0, 0, 0), Utc).to_rfc3339()))
+                .map_err(|_| format!("`{}` is not a time in the format `{}`", raw, date_format))
+        }
+        Some("string") if format == "date" => NaiveDate::parse_from_str(raw, date_format)
+            .map(|d| Value::String(d.format("%Y-%m-%d").to_string()))
+            .map_err(|_| format!("`{}` is not a date in the format `{}`", raw, date_format)),
+        Some("string") => Ok(Value::String(raw.to_string())),
+        // Anything else, ie. JSON fields, has to be written as JSON.
+        _ => serde_json::from_str(raw).map_err(|_| format!("`{}` is not valid JSON", raw)),
+    }
+}
+
+/// How to turn each row of a CSV into a record for a model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportPlan {
+    /// The field, its JSON schema, and the index of its column in the CSV.
+    columns: Vec<(String, Value, usize)>,
+    /// The field, its JSON schema, and its value for every row.
+    defaults: Vec<(String, Value, String)>,
+    required: Vec<String>,
+    date_format: String,
+}
+
+impl ImportPlan {
+    /// Check the mapping against the JSON schema of the model and the header of the
+    /// CSV. With no columns in the mapping, every CSV column named after a field is used.
+    #[instrument(skip(schema))]
+    #[inline]
+    pub fn new(schema: &Value, mapping: &ImportMapping, headers: &[String]) -> Result<Self, String> {
+        let properties = schema["properties"].as_object().cloned().unwrap_or_default();
+        let required: Vec<String> = schema["required"]
+            .as_array()
+            .map(|r| r.iter().filter_map(|f| f.as_str()).map(|f| f.to_string()).collect())
+            .unwrap_or_default();
+
+        let mut column_map = mapping.columns.clone();
+        if column_map.is_empty() {
+            for h in headers {
+                if properties.contains_key(h.trim()) {
+                    column_map.insert(h.trim().to_string(), h.to_string());
+                }
+            }
+        }
+
+        let mut columns = vec![];
+        for (field, column) in &column_map {
+            let field_schema = properties.get(field).ok_or_else(|| format!("`{}` is not a field of the model", field))?;
+            let index = headers
+                .iter()
+                .position(|h| h.trim() == column.trim())
+                .ok_or_else(|| format!("the CSV has no column `{}` for `{}`", column, field))?;
+            columns.push((field.to_string(), resolve_field_schema(schema, field_schema), index));
+        }
+
+        let mut defaults = vec![];
+        for (field, value) in &mapping.defaults {
+            let field_schema = properties.get(field).ok_or_else(|| format!("`{}` is not a field of the model", field))?;
+            if column_map.contains_key(field) {
+                return Err(format!("`{}` has both a column and a default", field));
+            }
+            defaults.push((field.to_string(), resolve_field_schema(schema, field_schema), value.to_string()));
+        }
+
+        let missing: Vec<&str> = required
+            .iter()
+            .filter(|f| !column_map.contains_key(*f) && !mapping.defaults.contains_key(*f))
+            .map(|f| f.as_str())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!("the model needs a column or default for {}", missing.join(", ")));
+        }
+
+        Ok(ImportPlan {
+            columns,
+            defaults,
+            required,
+            date_format: mapping.date_format.to_string(),
+        })
+    }
+
+    /// The fields that are set from the CSV or a default.
+    #[instrument]
+    #[inline]
+    pub fn fields(&self) -> Vec<&str> {
+        self.columns.iter().map(|(f, _, _)| f.as_str()).chain(self.defaults.iter().map(|(f, _, _)| f.as_str())).collect()
+    }
+
+    /// Build the JSON for the record from a row, collecting every problem with the row.
+    /// An empty cell for an optional field is left out so the field gets its default.
+    #[instrument]
+    #[inline]
+    pub fn record(&self, row: &[String]) -> Result<Value, Vec<String>> {
+        let mut record = Map::new();
+        let mut errors = vec![];
+
+        let cells = self
+            .columns
+            .iter()
+            .map(|(f, s, i)| (f, s, row.get(*i).map(|c| c.as_str()).unwrap_or_default()))
+            .chain(self.defaults.iter().map(|(f, s, v)| (f, s, v.as_str())));
+        for (field, schema, raw) in cells {
+            let required = self.required.contains(field);
+            if raw.trim().is_empty() && !required {
+                continue;
+            }
+            match coerce_value(raw, schema, &self.date_format) {
+                Ok(v) => {
+                    record.insert(field.to_string(), v);
+                }
+                Err(e) => errors.push(format!("`{}`: {}", field, e)),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Value::Object(record))
+    }
+}
+
+/// Reads the rows of a CSV into records for a model one at a time, so a large file is
+/// never held in memory. Rows we can not parse are returned as errors with their line,
+/// and reading carries on with the next row.
+pub struct CsvRecords<T, R: Read> {
+    reader: csv::Reader<R>,
+    row: csv::StringRecord,
+    plan: ImportPlan,
+    done: bool,
+    record: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned + JsonSchema, R: Read> CsvRecords<T, R> {
+    /// Read the header of the CSV and check the mapping against it.
+    #[instrument(skip(reader))]
+    #[inline]
+    pub fn new(reader: R, mapping: &ImportMapping) -> Result<Self, String> {
+        let schema = serde_json::to_value(schemars::schema_for!(T)).unwrap();
+
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+        let headers: Vec<String> = reader.headers().map_err(|e| format!("reading the CSV header failed: {}", e))?.iter().map(|h| h.to_string()).collect();
+        let plan = ImportPlan::new(&schema, mapping, &headers)?;
+
+        Ok(CsvRecords {
+            reader,
+            row: csv::StringRecord::new(),
+            plan,
+            done: false,
+            record: PhantomData,
+        })
+    }
+
+    /// How the rows are turned into records.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn plan(&self) -> &ImportPlan {
+        &self.plan
+    }
+
+    /// How many bytes of the CSV we have read.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn bytes_read(&self) -> u64 {
+        self.reader.position().byte()
+    }
+}
+
+impl<T: DeserializeOwned + JsonSchema, R: Read> Iterator for CsvRecords<T, R> {
+    type Item = Result<(u64, T), ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.reader.read_record(&mut self.row) {
+            Ok(true) => (),
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or_else(|| self.reader.position().line());
+                // We can skip a row that is not valid, but not a file we can not read.
+                if let csv::ErrorKind::Io(_) = e.kind() {
+                    self.done = true;
+                }
+                return Some(Err(ImportError { line, message: e.to_string() }));
+            }
+        }
+
+        let line = self.row.position().map(|p| p.line()).unwrap_or_default();
+        let cells: Vec<String> = self.row.iter().map(|c| c.to_string()).collect();
+        Some(
+            self.plan
+                .record(&cells)
+                .and_then(|r| serde_json::from_value::<T>(r).map_err(|e| vec![e.to_string()]))
+                .map(|r| (line, r))
+                .map_err(|e| ImportError { line, message: e.join("; ") }),
+        )
+    }
+}
+
+impl ImportReport {
+    /// Add a row we could not import. Past `MAX_REPORTED_IMPORT_ERRORS` we only count
+    /// them, so a file where every row is bad does not fill up memory.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn add_error(&mut self, error: ImportError) {
+        if self.errors.len() < MAX_REPORTED_IMPORT_ERRORS {
+            self.errors.push(error);
+        } else {
+            self.more_errors += 1;
+        }
+    }
+
+    /// How many rows we could not import.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn failed(&self) -> usize {
+        self.errors.len() + self.more_errors
+    }
+}
+
+macro_rules! import_model {
+    ($t:ty, $plural:ty, $db:expr, $table:expr, $csv:expr, $size:expr, $mapping:expr, $upsert:expr) => {{
+        let mut records = CsvRecords::<$t, _>::new($csv, $mapping)?;
+        let match_on = <$t>::match_on_fields();
+        if $upsert && match_on.iter().any(|f| !records.plan().fields().contains(f)) {
+            return Err(format!("upserting into {} needs a column or default for {}", $table, match_on.join(", ")));
+        }
+
+        let mut report = ImportReport {
+            table: $table.to_string(),
+            ..Default::default()
+        };
+        let mut progress = Progress::new_bytes(&format!("Import into {}", $table), $size);
+        // Write the rows in batches, so a large file takes a few queries per batch rather
+        // than a few per row.
+        let mut batch: Vec<(u64, $t)> = Default::default();
+        loop {
+            let row = records.next();
+            let done = row.is_none();
+            if let Some(row) = row {
+                let line = match &row {
+                    Ok((line, _)) => *line,
+                    Err(e) => e.line,
+                };
+                progress.set(records.bytes_read() as usize, &format!("line {}", line));
+                match row {
+                    Ok(r) => batch.push(r),
+                    Err(e) => report.add_error(e),
+                }
+                if batch.len() < BATCH_SIZE {
+                    continue;
+                }
+            }
+
+            // Without upsert, rows for records that already exist, or that are in the
+            // batch twice, are errors.
+            let existing: BTreeSet<String> = if $upsert {
+                Default::default()
+            } else {
+                let records: Vec<$t> = batch.iter().map(|(_, r)| r.clone()).collect();
+                <$t>::get_all_existing_from_db($db, &records).iter().map(|r| r.match_on_key()).collect()
+            };
+            let mut seen: BTreeSet<String> = Default::default();
+            let mut to_write: Vec<$t> = Default::default();
+            for (line, record) in batch.drain(..) {
+                let key = record.match_on_key();
+                if !$upsert && (existing.contains(&key) || !seen.insert(key)) {
+                    report.add_error(ImportError {
+                        line,
+                        message: format!("a record with the same {} already exists, use --upsert to update it", match_on.join(", ")),
+                    });
+                    continue;
+                }
+                to_write.push(record);
+            }
+
+            let upserted = <$t>::upsert_all_from($db, &to_write, IMPORT_SOURCE);
+            report.created += upserted.created.len();
+            report.updated += upserted.updated.len();
+            report.unchanged += upserted.unchanged.len();
+
+            if done {
+                break;
+            }
+        }
+        progress.finish();
+
+        <$plural>::get_from_db($db).update_airtable().await;
+
+        Ok(report)
+    }};
+}
+
+/// Import a CSV of `size` bytes into a table, streaming through it a row at a time. Rows
+/// that fail to parse are collected into the report and the rest are imported. Without
+/// `upsert`, rows that match an existing record on the `match_on` fields of the model
+/// are errors rather than updates.
+#[instrument(skip(db, csv))]
+#[inline]
+pub async fn import_csv<R: Read>(db: &Database, table: &str, csv: R, size: u64, mapping: &ImportMapping, upsert: bool) -> Result<ImportReport, String> {
+    match table {
+        "applicants" => import_model!(NewApplicant, Applicants, db, table, csv, size, mapping, upsert),
+        "certificates" => import_model!(NewCertificate, Certificates, db, table, csv, size, mapping, upsert),
+        "cloud_costs" => import_model!(NewCloudCost, CloudCosts, db, table, csv, size, mapping, upsert),
+        "event_registrants" => import_model!(NewEventRegistrant, EventRegistrants, db, table, csv, size, mapping, upsert),
+        "inbound_shipments" => import_model!(NewInboundShipment, InboundShipments, db, table, csv, size, mapping, upsert),
+        "mailing_list_subscribers" => import_model!(NewMailingListSubscriber, MailingListSubscribers, db, table, csv, size, mapping, upsert),
+        "recorded_meetings" => import_model!(NewRecordedMeeting, RecordedMeetings, db, table, csv, size, mapping, upsert),
+        "software_vendors" => import_model!(NewSoftwareVendor, SoftwareVendors, db, table, csv, size, mapping, upsert),
+        _ => Err(format!("we can not import into {}, only {}", table, IMPORT_TABLES.join(", "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::finance::{NewSoftwareVendor, VendorStatus};
+    use crate::import::{coerce_value, format_import_report, CsvRecords, ImportError, ImportMapping, ImportPlan, ImportReport, MAX_REPORTED_IMPORT_ERRORS};
+
+    #[test]
+    fn test_coerce_value() {
+        assert_eq!(coerce_value(" Okta ", &json!({"type": "string"}), "").unwrap(), json!("Okta"));
+        assert_eq!(coerce_value("Yes", &json!({"type": "boolean"}), "").unwrap(), json!(true));
+        assert_eq!(coerce_value("", &json!({"type": "boolean"}), "").unwrap(), json!(false));
+        assert!(coerce_value("maybe", &json!({"type": "boolean"}), "").is_err());
+        assert_eq!(coerce_value("1,200", &json!({"type": "integer"}), "").unwrap(), json!(1200));
+        assert_eq!(coerce_value("$8.50", &json!({"type": "number"}), "").unwrap(), json!(8.5));
+        assert_eq!(coerce_value("", &json!({"type": ["number", "null"]}), "").unwrap(), json!(null));
+        assert_eq!(coerce_value("eng, ops,", &json!({"type": "array", "items": {"type": "string"}}), "").unwrap(), json!(["eng", "ops"]));
+        assert_eq!(coerce_value("04/13/2021", &json!({"type": "string", "format": "date"}), "%m/%d/%Y").unwrap(), json!("2021-04-13"));
+        assert_eq!(
+            coerce_value("2021-04-13", &json!({"type": ["string", "null"], "format": "date-time"}), "").unwrap(),
+            json!("2021-04-13T00:00:00+00:00")
+        );
+        assert!(coerce_value("13/04/2021", &json!({"type": "string", "format": "date"}), "%m/%d/%Y").is_err());
+    }
+
+    #[test]
+    fn test_import_plan() {
+        let schema = json!({
+            "properties": {
+                "name": {"type": "string"},
+                "users": {"type": "integer"},
+                "status": {"type": "string"},
+            },
+            "required": ["name", "users"],
+        });
+        let headers = vec!["Vendor".to_string(), "Seats".to_string()];
+
+        let mut mapping = ImportMapping::default();
+        mapping.columns.insert("name".to_string(), "Vendor".to_string());
+        assert_eq!(ImportPlan::new(&schema, &mapping, &headers).unwrap_err(), "the model needs a column or default for users");
+
+        mapping.columns.insert("users".to_string(), "Seats".to_string());
+        mapping.defaults.insert("status".to_string(), "active".to_string());
+        let plan = ImportPlan::new(&schema, &mapping, &headers).unwrap();
+        assert_eq!(plan.record(&["Okta".to_string(), "12".to_string()]).unwrap(), json!({"name": "Okta", "users": 12, "status": "active"}));
+        assert_eq!(
+            plan.record(&["Zoom".to_string(), "a dozen".to_string()]).unwrap_err(),
+            vec!["`users`: `a dozen` is not a whole number".to_string()]
+        );
+        assert_eq!(plan.record(&["Zoom".to_string(), "".to_string()]).unwrap_err(), vec!["`users`: a value is required".to_string()]);
+
+        mapping.columns.insert("cost".to_string(), "Cost".to_string());
+        assert_eq!(ImportPlan::new(&schema, &mapping, &headers).unwrap_err(), "`cost` is not a field of the model");
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let csv = "name,users,groups,security_review_date,status\nOkta,40,\"eng,ops\",2021-03-01,Trial\nZoom,lots,,,\nSlack,12,,,Canceled\n";
+        let mut rows = CsvRecords::<NewSoftwareVendor, _>::new(csv.as_bytes(), &ImportMapping::default()).unwrap();
+        let mut records = vec![];
+        let mut errors = vec![];
+        for row in &mut rows {
+            match row {
+                Ok(r) => records.push(r),
+                Err(e) => errors.push(e),
+            }
+        }
+        assert_eq!(rows.bytes_read(), csv.len() as u64);
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, 2);
+        assert_eq!(records[0].1.name, "Okta");
+        assert_eq!(records[0].1.users, 40);
+        assert_eq!(records[0].1.groups, vec!["eng", "ops"]);
+        assert_eq!(records[0].1.status, VendorStatus::Trial);
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[0].message, "`users`: `lots` is not a whole number");
+        assert_eq!(errors[1].line, 4);
+        assert_eq!(errors[1].message, "unknown variant `Canceled`, expected one of `Active`, `Trial`, `Cancelled`");
+    }
+
+    #[test]
+    fn test_import_report_errors() {
+        let mut report = ImportReport {
+            table: "software_vendors".to_string(),
+            created: 2,
+            ..Default::default()
+        };
+        for line in 2..(MAX_REPORTED_IMPORT_ERRORS as u64 + 5) {
+            report.add_error(ImportError {
+                line,
+                message: "`users`: a value is required".to_string(),
+            });
+        }
+
+        assert_eq!(report.errors.len(), MAX_REPORTED_IMPORT_ERRORS);
+        assert_eq!(report.failed(), MAX_REPORTED_IMPORT_ERRORS + 3);
+        let text = format_import_report(&report);
+        assert!(text.starts_with("software_vendors: 2 created, 0 updated, 0 unchanged, 1003 failed\n  line 2: `users`: a value is required\n"));
+        assert!(text.ends_with("  line 1001: `users`: a value is required\n  and 3 more\n"));
+    }
+}
@@ -8,7 +8,6 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
 use google_drive::GoogleDrive;
-use gsuite_api::GSuite;
 use lopdf::{Bookmark, Document, Object, ObjectId};
 use macros::db;
 use pandoc::OutputKind;
@@ -22,12 +21,13 @@ use crate::configs::{User, Users};
 use crate::core::UpdateAirtableRecord;
 use crate::db::Database;
 use crate::schema::{applicant_interviews, applicants, users};
-use crate::utils::{get_gsuite_token, DOMAIN, GSUITE_DOMAIN};
+use crate::utils::{drive_client, get_gsuite_token, gsuite_client, DOMAIN, GSUITE_DOMAIN};
 
 #[db {
     new_struct_name = "ApplicantInterview",
     airtable_base_id = "AIRTABLE_BASE_ID_RECURITING_APPLICATIONS",
     airtable_table = "AIRTABLE_INTERVIEWS_TABLE",
+    source = "google_calendar",
     match_on = {
         "google_event_id" = "String",
     },
@@ -71,7 +71,7 @@ impl UpdateAirtableRecord<ApplicantInterview> for ApplicantInterview {
 pub async fn refresh_interviews(db: &Database) {
     let gsuite_customer = env::var("GADMIN_ACCOUNT_ID").unwrap();
     let token = get_gsuite_token("").await;
-    let gsuite = GSuite::new(&gsuite_customer, GSUITE_DOMAIN, token.clone());
+    let gsuite = gsuite_client(&gsuite_customer, token.clone());
 
     // Get the list of our calendars.
     let calendars = gsuite.list_calendars().await.unwrap();
@@ -220,7 +220,7 @@ pub async fn compile_packets(db: &Database) {
     let token = get_gsuite_token("").await;
 
     // Initialize the Google Drive client.
-    let drive_client = GoogleDrive::new(token);
+    let drive_client = drive_client(token);
     // Figure out where our directory is.
     // It should be in the shared drive : "Automated Documents"/"rfds"
     let shared_drive = drive_client.get_drive_by_name("Automated Documents").await.unwrap();
@@ -414,6 +414,7 @@ The Oxide Team
         let files = drive_client.get_file_by_name(&drive_id, &filename).await.unwrap();
         if !files.is_empty() {
             applicant.interview_packet = format!("https://drive.google.com/open?id={}", files.get(0).unwrap().id);
+            applicant.set_synced_from("google_drive");
         }
         applicant.update(db).await;
     }
@@ -0,0 +1,320 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::Url;
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::progress::format_duration;
+use crate::schema::job_runs;
+
+/// The services we count API calls for. Calls to anything else count as `other`.
+pub static USAGE_SERVICES: &[&str] = &["airtable", "github", "gsuite", "slack"];
+
+/// The API calls, database connection checkouts, and rows written by the job that is running.
+/// Jobs run one at a time, in the scheduler and in the CLI, so these are reset when a
+/// job starts and read when it finishes.
+static API_CALLS: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+static DB_CHECKOUTS: AtomicUsize = AtomicUsize::new(0);
+static ROWS_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Count a call to an API, by the name of the service.
+#[instrument]
+#[inline]
+pub fn count_api_call(service: &str) {
+    let service = if USAGE_SERVICES.contains(&service) { service } else { "other" };
+    *API_CALLS.lock().unwrap().entry(service.to_string()).or_default() += 1;
+}
+
+/// Get the service we count a request to a url against.
+#[instrument]
+#[inline]
+pub fn service_for_url(url: &str) -> &'static str {
+    let host = Url::parse(url).ok().and_then(|u| u.host_str().map(|h| h.to_string())).unwrap_or_default();
+    let is = |domain: &str| host == domain || host.ends_with(&format!(".{}", domain));
+
+    if is("airtable.com") {
+        "airtable"
+    } else if is("github.com") {
+        "github"
+    } else if is("googleapis.com") || is("google.com") {
+        "gsuite"
+    } else if is("slack.com") {
+        "slack"
+    } else {
+        "other"
+    }
+}
+
+/// Count a connection taken from the database pool. This is not a count of queries,
+/// since a connection can run any number of them before it goes back to the pool.
+#[inline]
+pub fn count_db_checkout() {
+    DB_CHECKOUTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count rows the job created, updated, or deleted.
+#[inline]
+pub fn count_rows_written(rows: usize) {
+    ROWS_WRITTEN.fetch_add(rows, Ordering::Relaxed);
+}
+
+/// What a job used while it ran.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JobUsage {
+    pub api_calls: BTreeMap<String, usize>,
+    pub db_checkouts: usize,
+    pub rows_written: usize,
+}
+
+impl JobUsage {
+    #[instrument]
+    #[inline]
+    pub fn calls_to(&self, service: &str) -> i32 {
+        self.api_calls.get(service).copied().unwrap_or_default() as i32
+    }
+}
+
+/// Reset the counts for a job that is about to run.
+#[instrument]
+#[inline]
+pub fn start_job_usage() {
+    take_job_usage();
+}
+
+/// Get the counts for the job that just ran, resetting them.
+#[instrument]
+#[inline]
+pub fn take_job_usage() -> JobUsage {
+    JobUsage {
+        api_calls: std::mem::take(&mut *API_CALLS.lock().unwrap()),
+        db_checkouts: DB_CHECKOUTS.swap(0, Ordering::Relaxed),
+        rows_written: ROWS_WRITTEN.swap(0, Ordering::Relaxed),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "job_runs"]
+pub struct NewJobRun {
+    pub job: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    pub succeeded: bool,
+    pub error: String,
+    pub airtable_calls: i32,
+    pub github_calls: i32,
+    pub gsuite_calls: i32,
+    pub slack_calls: i32,
+    pub other_calls: i32,
+    pub db_checkouts: i32,
+    pub rows_written: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct JobRun {
+    pub id: i32,
+    pub job: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    pub succeeded: bool,
+    pub error: String,
+    pub airtable_calls: i32,
+    pub github_calls: i32,
+    pub gsuite_calls: i32,
+    pub slack_calls: i32,
+    pub other_calls: i32,
+    pub db_checkouts: i32,
+    pub rows_written: i32,
+}
+
+impl NewJobRun {
+    #[instrument]
+    #[inline]
+    pub fn new(job: &str, started_at: DateTime<Utc>, duration: std::time::Duration, result: &Result<(), String>, usage: &JobUsage) -> Self {
+        NewJobRun {
+            job: job.to_string(),
+            started_at,
+            duration_ms: duration.as_millis() as i64,
+            succeeded: result.is_ok(),
+            error: result.as_ref().err().cloned().unwrap_or_default(),
+            airtable_calls: usage.calls_to("airtable"),
+            github_calls: usage.calls_to("github"),
+            gsuite_calls: usage.calls_to("gsuite"),
+            slack_calls: usage.calls_to("slack"),
+            other_calls: usage.calls_to("other"),
+            db_checkouts: usage.db_checkouts as i32,
+            rows_written: usage.rows_written as i32,
+        }
+    }
+}
+
+/// Save a run of a job with what it used, from the counts since `start_job_usage`.
+#[instrument(skip(db))]
+#[inline]
+pub fn record_job_run(db: &Database, job: &str, started_at: DateTime<Utc>, duration: std::time::Duration, result: &Result<(), String>) {
+    let run = NewJobRun::new(job, started_at, duration, result, &take_job_usage());
+    // The job runs are only kept in Postgres.
+    if db.is_sqlite() {
+        println!("[job_runs] {:?}", run);
+        return;
+    }
+
+    diesel::insert_into(job_runs::table)
+        .values(&run)
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("recording the run of job {} failed: {}", job, e));
+}
+
+/// Get the runs of every job since a time.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_job_runs_since(db: &Database, since: DateTime<Utc>) -> Vec<JobRun> {
+    job_runs::table
+        .filter(job_runs::started_at.ge(since))
+        .order_by(job_runs::started_at)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the job runs failed: {}", e))
+}
+
+/// What a job used across its runs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JobUsageSummary {
+    pub job: String,
+    pub runs: i64,
+    pub failed: i64,
+    pub duration_ms: i64,
+    pub airtable_calls: i64,
+    pub github_calls: i64,
+    pub gsuite_calls: i64,
+    pub slack_calls: i64,
+    pub other_calls: i64,
+    pub db_checkouts: i64,
+    pub rows_written: i64,
+}
+
+/// Total up the runs of each job, with the jobs that make the most Airtable calls first
+/// since Airtable has the tightest rate limit.
+#[instrument(skip(runs))]
+#[inline]
+pub fn summarize_job_runs(runs: &[JobRun]) -> Vec<JobUsageSummary> {
+    let mut jobs: BTreeMap<String, JobUsageSummary> = Default::default();
+    for r in runs {
+        let s = jobs.entry(r.job.to_string()).or_insert_with(|| JobUsageSummary {
+            job: r.job.to_string(),
+            ..Default::default()
+        });
+        s.runs += 1;
+        if !r.succeeded {
+            s.failed += 1;
+        }
+        s.duration_ms += r.duration_ms;
+        s.airtable_calls += r.airtable_calls as i64;
+        s.github_calls += r.github_calls as i64;
+        s.gsuite_calls += r.gsuite_calls as i64;
+        s.slack_calls += r.slack_calls as i64;
+        s.other_calls += r.other_calls as i64;
+        s.db_checkouts += r.db_checkouts as i64;
+        s.rows_written += r.rows_written as i64;
+    }
+
+    let mut summaries: Vec<JobUsageSummary> = jobs.into_iter().map(|(_, s)| s).collect();
+    summaries.sort_by(|a, b| b.airtable_calls.cmp(&a.airtable_calls).then(b.duration_ms.cmp(&a.duration_ms)));
+
+    summaries
+}
+
+/// Format the usage of each job as a table to print in a terminal.
+#[instrument(skip(summaries))]
+#[inline]
+pub fn format_job_usage_report(summaries: &[JobUsageSummary]) -> String {
+    if summaries.is_empty() {
+        return "No jobs ran in that window.\n".to_string();
+    }
+
+    let width = summaries.iter().map(|s| s.job.len()).max().unwrap_or_default().max(3);
+    let mut text = format!(
+        "{:<width$}  {:>5}  {:>6}  {:>9}  {:>8}  {:>6}  {:>6}  {:>6}  {:>6}  {:>12}  {:>8}\n",
+        "job",
+        "runs",
+        "failed",
+        "time",
+        "airtable",
+        "github",
+        "gsuite",
+        "slack",
+        "other",
+        "db checkouts",
+        "rows",
+        width = width
+    );
+    for s in summaries {
+        text += &format!(
+            "{:<width$}  {:>5}  {:>6}  {:>9}  {:>8}  {:>6}  {:>6}  {:>6}  {:>6}  {:>12}  {:>8}\n",
+            s.job,
+            s.runs,
+            s.failed,
+            format_duration(std::time::Duration::from_millis(s.duration_ms as u64)),
+            s.airtable_calls,
+            s.github_calls,
+            s.gsuite_calls,
+            s.slack_calls,
+            s.other_calls,
+            s.db_checkouts,
+            s.rows_written,
+            width = width
+        );
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::job_runs::{format_job_usage_report, service_for_url, summarize_job_runs, JobRun};
+
+    #[test]
+    fn test_job_usage() {
+        assert_eq!(service_for_url("https://api.airtable.com/v0/meta/bases/app123/tables"), "airtable");
+        assert_eq!(service_for_url("https://api.github.com/orgs/oxidecomputer"), "github");
+        assert_eq!(service_for_url("https://hooks.slack.com/services/T0/B0/x"), "slack");
+        assert_eq!(service_for_url("https://admin.googleapis.com/admin/directory/v1/users"), "gsuite");
+        assert_eq!(service_for_url("https://notgithub.com/"), "other");
+        assert_eq!(service_for_url("not a url"), "other");
+
+        let run = |job: &str, airtable_calls: i32, succeeded: bool| JobRun {
+            id: 0,
+            job: job.to_string(),
+            started_at: Utc.ymd(2021, 5, 6).and_hms(9, 0, 0),
+            duration_ms: 65_000,
+            succeeded,
+            error: String::new(),
+            airtable_calls,
+            github_calls: 2,
+            gsuite_calls: 0,
+            slack_calls: 0,
+            other_calls: 0,
+            db_checkouts: 10,
+            rows_written: 3,
+        };
+        let summaries = summarize_job_runs(&[run("rfds", 40, true), run("applicants", 120, true), run("rfds", 50, false)]);
+        assert_eq!(summaries.iter().map(|s| s.job.as_str()).collect::<Vec<_>>(), vec!["applicants", "rfds"]);
+        assert_eq!(summaries[1].runs, 2);
+        assert_eq!(summaries[1].failed, 1);
+        assert_eq!(summaries[1].airtable_calls, 90);
+        assert_eq!(summaries[1].rows_written, 6);
+
+        assert_eq!(
+            format_job_usage_report(&summaries),
+            r#"job          runs  failed       time  airtable  github  gsuite   slack   other  db checkouts      rows
+applicants      1       0      1m 5s       120       2       0       0       0            10         3
+rfds            2       1     2m 10s        90       4       0       0       0            20         6
+"#
+        );
+        assert_eq!(format_job_usage_report(&[]), "No jobs ran in that window.\n");
+    }
+}
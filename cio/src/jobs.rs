@@ -0,0 +1,374 @@
+use hubcaps::Github;
+use tracing::instrument;
+
+use crate::actions_usage::{check_github_actions_usage_spikes, refresh_github_actions_usage, GithubActionsUsages};
+use crate::alerts::{refresh_security_alerts, send_security_alerts_digest, Alerts};
+use crate::analytics::PageViews;
+use crate::api_tokens::send_api_token_reminders;
+use crate::applicant_emails::send_applicant_stage_emails;
+use crate::applicant_messages::refresh_applicant_messages;
+use crate::applicants::{
+    refresh_background_checks, refresh_db_applicants, send_hiring_report, update_applicant_reviewers, update_applications_with_scoring_forms, update_applications_with_scoring_results, Applicants,
+};
+use crate::auth_logins::{refresh_auth_users_and_logins, AuthUserLogins, AuthUsers};
+use crate::backups::{backup_airtable, backup_and_verify_db};
+use crate::bucket_audits::{refresh_bucket_audits, BucketAudits};
+use crate::business_days::BusinessCalendar;
+use crate::cloud_costs::{check_cloud_budgets, refresh_cloud_costs, CloudCosts};
+use crate::cohorts::{send_cohort_reports, sync_cohort_events, sync_cohort_members};
+use crate::configs::{refresh_db_configs_and_airtable, sync_user, Config, Users};
+use crate::db::Database;
+use crate::desks::post_office_summary;
+use crate::docusign::remind_docusign_signers;
+use crate::drive_permissions::reconcile_drive_permissions;
+use crate::engineering_metrics::{refresh_engineering_metrics, send_engineering_metrics_report, EngineeringMetrics};
+use crate::event_registrants::{refresh_event_registrants, EventRegistrants};
+use crate::feedback::send_feedback_digest;
+use crate::filter::RecordFilter;
+use crate::finance::{flag_vendors_needing_security_review, reconcile_okta_apps, refresh_software_vendors};
+use crate::interviews::{compile_packets, refresh_interviews};
+use crate::journal_clubs::{refresh_db_journal_club_meetings, JournalClubMeetings, JournalClubPapers};
+use crate::key_rotation::{refresh_service_account_keys, ServiceAccountKeys};
+use crate::licenses::{refresh_repo_license_audits, RepoLicenseAudits};
+use crate::lunch::run_lunch_orders;
+use crate::mailing_list::{refresh_db_mailing_list_subscribers, MailingListSubscribers};
+use crate::matching::refresh_match_reviews;
+use crate::models::{GithubRepos, RFDs};
+use crate::notifications::{send_notification_digests, send_scheduled_messages};
+use crate::oauth_grants::{refresh_oauth_grants, OAuthGrants};
+use crate::password_manager::reconcile_password_manager_groups;
+use crate::policies::send_policy_acknowledgment_requests;
+use crate::posture::{refresh_security_postures, SecurityPostures};
+use crate::profiles::refresh_profiles;
+use crate::rate_limits::refresh_github_rate_limit;
+use crate::recorded_meetings::refresh_recorded_meetings;
+use crate::referrals::send_referrals_report;
+use crate::retention::refresh_people_snapshot;
+use crate::reviews::refresh_review_cycles;
+use crate::rfds::{refresh_db_rfds, send_rfd_changelog};
+use crate::scheduler::SyncConfig;
+use crate::security_events::{refresh_google_workspace_security_events, SecurityEvents};
+use crate::shipments::{refresh_airtable_shipments, refresh_inbound_shipments};
+use crate::shorturls::refresh_shorturls;
+use crate::slack_channels::reconcile_slack_channels;
+use crate::slack_user_groups::reconcile_slack_user_groups;
+use crate::stale::send_stale_items_reminders;
+use crate::tailscale::{cleanup_old_tailscale_devices, refresh_tailscale_devices, TailscaleDevices};
+use crate::take_homes::close_overdue_take_homes;
+use crate::trainings::refresh_trainings;
+use crate::utils::refresh_db_github_repos;
+use crate::visitors::post_expected_visitors;
+use crate::webhook_deliveries::prune_webhook_deliveries;
+use crate::welcome::send_welcome_messages;
+
+/// The jobs we can run and how often they run by default, this can be overridden
+/// per job in the `sync.toml` file.
+pub static JOBS: &[(&str, &str)] = &[
+    ("actions_usage", "6h"),
+    ("actions_usage_spikes", "7d"),
+    ("airtable_backup", "1d"),
+    ("api_token_reminders", "1d"),
+    ("applicant_emails", "5m"),
+    ("applicant_messages", "6h"),
+    ("applicants", "6h"),
+    ("auth_logins", "6h"),
+    ("bucket_audits", "1d"),
+    ("cloud_budgets", "7d"),
+    ("cloud_costs", "6h"),
+    ("cohort_reports", "7d"),
+    ("cohorts", "1d"),
+    ("configs", "6h"),
+    ("db_backup", "1d"),
+    ("docusign_reminders", "1d"),
+    ("drive_permissions", "1d"),
+    ("engineering_metrics", "7d"),
+    ("engineering_metrics_report", "30d"),
+    ("event_registrants", "6h"),
+    ("feedback_digest", "7d"),
+    ("github_repos", "6h"),
+    ("hiring_report", "7d"),
+    ("interviews", "1h"),
+    ("journal_clubs", "6h"),
+    ("license_audits", "6h"),
+    ("lunch_orders", "1h"),
+    ("mailing_list", "6h"),
+    ("match_reviews", "1h"),
+    ("notification_digests", "1d"),
+    ("oauth_grants", "1d"),
+    ("office_summary", "1d"),
+    ("okta_apps", "7d"),
+    ("page_views", "6h"),
+    ("password_manager", "6h"),
+    ("people_snapshots", "1d"),
+    ("policies", "1d"),
+    ("profiles", "6h"),
+    ("rate_limits", "5m"),
+    ("recorded_meetings", "6h"),
+    ("referrals_report", "7d"),
+    ("reviews", "1d"),
+    ("rfd_changelog", "7d"),
+    ("rfds", "6h"),
+    ("scheduled_messages", "5m"),
+    ("security_alerts", "6h"),
+    ("security_alerts_digest", "7d"),
+    ("security_events", "1h"),
+    ("security_postures", "1d"),
+    ("service_account_keys", "1d"),
+    ("shipments", "6h"),
+    ("shorturls", "6h"),
+    ("slack_channels", "6h"),
+    ("slack_user_groups", "6h"),
+    ("software_vendors", "7d"),
+    ("stale_items", "7d"),
+    ("tailscale", "6h"),
+    ("take_homes", "1h"),
+    ("trainings", "1d"),
+    ("vendor_security_reviews", "6h"),
+    ("visitors", "1d"),
+    ("webhook_deliveries", "1d"),
+    ("welcome_messages", "5m"),
+];
+
+/// The jobs that can wait when we are close to the rate limit for an API, since
+/// nobody is waiting on them. The scheduler defers them until we have headroom again,
+/// leaving it for the jobs people notice. This can be overridden per job in the
+/// `sync.toml` file with `low_priority`.
+pub static LOW_PRIORITY_JOBS: &[&str] = &[
+    "actions_usage",
+    "bucket_audits",
+    "engineering_metrics",
+    "github_repos",
+    "journal_clubs",
+    "license_audits",
+    "page_views",
+    "profiles",
+    "shorturls",
+    "software_vendors",
+];
+
+/// The external services each job depends on, from `BREAKER_SERVICES` in the
+/// `circuit_breakers` module. When a service is down the scheduler skips the jobs
+/// that depend on it. Jobs that are not listed only use our database and Slack.
+pub static JOB_SERVICES: &[(&str, &[&str])] = &[
+    ("actions_usage", &["airtable", "github"]),
+    ("airtable_backup", &["airtable"]),
+    ("applicant_emails", &["gsuite"]),
+    ("applicant_messages", &["gsuite"]),
+    ("applicants", &["airtable", "gsuite"]),
+    ("auth_logins", &["airtable"]),
+    ("bucket_audits", &["airtable"]),
+    ("cloud_costs", &["airtable"]),
+    ("cohorts", &["gsuite"]),
+    ("configs", &["airtable", "github", "gsuite"]),
+    ("drive_permissions", &["gsuite"]),
+    ("engineering_metrics", &["airtable", "github"]),
+    ("event_registrants", &["airtable"]),
+    ("github_repos", &["airtable", "github"]),
+    ("interviews", &["gsuite"]),
+    ("journal_clubs", &["airtable", "github"]),
+    ("license_audits", &["airtable", "github"]),
+    ("mailing_list", &["airtable"]),
+    ("match_reviews", &["airtable"]),
+    ("oauth_grants", &["airtable", "gsuite"]),
+    ("okta_apps", &["airtable"]),
+    ("page_views", &["airtable"]),
+    ("policies", &["gsuite"]),
+    ("recorded_meetings", &["airtable", "gsuite"]),
+    ("reviews", &["gsuite"]),
+    ("rfd_changelog", &["github"]),
+    ("rfds", &["airtable", "github"]),
+    ("security_alerts", &["airtable", "github"]),
+    ("security_events", &["airtable", "gsuite"]),
+    ("security_postures", &["airtable", "gsuite"]),
+    ("service_account_keys", &["airtable"]),
+    ("shipments", &["airtable", "gsuite"]),
+    ("shorturls", &["github"]),
+    ("software_vendors", &["airtable"]),
+    ("tailscale", &["airtable"]),
+    ("take_homes", &["github"]),
+    ("vendor_security_reviews", &["airtable"]),
+];
+
+/// Get the external services a job depends on.
+#[instrument]
+#[inline]
+pub fn job_services(job: &str) -> &'static [&'static str] {
+    JOB_SERVICES.iter().find(|(name, _)| *name == job).map(|(_, services)| *services).unwrap_or(&[])
+}
+
+/// The jobs that support only syncing the records matching a `RecordFilter`.
+/// For `configs`, the filter applies to the users.
+pub static FILTERABLE_JOBS: &[&str] = &["applicants", "configs", "rfds", "software_vendors"];
+
+/// The jobs that support filtering on the dates of a `RecordFilter`. The dates are
+/// when applicants applied, the last commit to an RFD, and the last security review
+/// of a software vendor. For `hiring_report`, the dates are the window to count new
+/// applicants in, rather than the last week.
+pub static DATE_FILTERABLE_JOBS: &[&str] = &["applicants", "hiring_report", "rfds", "software_vendors"];
+
+/// The jobs that can be recorded with `--record` and replayed with `--replay`, since
+/// every request they send goes through `vcr::send`. The other jobs also use the API
+/// client crates, ie. airtable-api, hubcaps, or gsuite-api, whose requests we can not
+/// answer from a cassette. None of these use the configs either.
+pub static REPLAYABLE_JOBS: &[&str] = &[
+    "actions_usage_spikes",
+    "api_token_reminders",
+    "engineering_metrics_report",
+    "notification_digests",
+    "people_snapshots",
+    "scheduled_messages",
+    "webhook_deliveries",
+];
+
+/// Run a job by its name with the current config. Returns false if there is no job with that name.
+/// The filter is ignored for jobs that are not in `FILTERABLE_JOBS` or `DATE_FILTERABLE_JOBS`.
+#[instrument(skip(db, github, config, sync_config))]
+#[inline]
+pub async fn run_job(db: &Database, github: &Github, config: &Config, sync_config: &SyncConfig, name: &str, filter: &RecordFilter) -> bool {
+    match name {
+        "actions_usage" => {
+            refresh_github_actions_usage(db).await;
+            GithubActionsUsages::get_from_db(db).update_airtable().await;
+        }
+        "actions_usage_spikes" => check_github_actions_usage_spikes(db).await,
+        "airtable_backup" => backup_airtable().await,
+        "api_token_reminders" => send_api_token_reminders(db).await,
+        "applicant_emails" => send_applicant_stage_emails(db, config).await,
+        "applicant_messages" => refresh_applicant_messages(db, config).await,
+        "applicants" => {
+            refresh_db_applicants(db, filter).await;
+            Applicants::get_from_db(db).update_airtable().await;
+            update_applications_with_scoring_forms(db).await;
+            update_applications_with_scoring_results(db).await;
+            update_applicant_reviewers(db).await;
+            refresh_background_checks(db).await;
+        }
+        "auth_logins" => {
+            refresh_auth_users_and_logins(db).await;
+            AuthUserLogins::get_from_db(db).update_airtable().await;
+            AuthUsers::get_from_db(db).update_airtable().await;
+        }
+        "bucket_audits" => {
+            refresh_bucket_audits(db, config).await;
+            BucketAudits::get_from_db(db).update_airtable().await;
+        }
+        "cloud_budgets" => check_cloud_budgets(db, config).await,
+        "cloud_costs" => {
+            refresh_cloud_costs(db).await;
+            CloudCosts::get_from_db(db).update_airtable().await;
+        }
+        "cohort_reports" => send_cohort_reports(db, config).await,
+        "cohorts" => {
+            sync_cohort_members(db, config);
+            sync_cohort_events(db, config).await;
+        }
+        "configs" if filter.fields.is_empty() => refresh_db_configs_and_airtable(db, github, config.clone()).await,
+        "configs" => {
+            // Only re-sync the matching users, leaving everything else in the
+            // database as is.
+            for user in config.users.values().filter(|u| filter.matches(u)) {
+                sync_user(db, user.clone()).await;
+            }
+            Users::get_from_db(db).update_airtable().await;
+        }
+        "db_backup" => backup_and_verify_db().await,
+        "docusign_reminders" => remind_docusign_signers(db, config).await,
+        "drive_permissions" => reconcile_drive_permissions(db, config).await,
+        "engineering_metrics" => {
+            refresh_engineering_metrics(db, config).await;
+            EngineeringMetrics::get_from_db(db).update_airtable().await;
+        }
+        "engineering_metrics_report" => send_engineering_metrics_report(db).await,
+        "event_registrants" => {
+            refresh_event_registrants(db).await;
+            EventRegistrants::get_from_db(db).update_airtable().await;
+        }
+        "feedback_digest" => send_feedback_digest(db).await,
+        "github_repos" => {
+            refresh_db_github_repos(db, github).await;
+            GithubRepos::get_from_db(db).update_airtable().await;
+        }
+        "hiring_report" => send_hiring_report(db, filter).await,
+        "interviews" => {
+            refresh_interviews(db).await;
+            compile_packets(db).await;
+        }
+        "journal_clubs" => {
+            refresh_db_journal_club_meetings(db, github).await;
+            JournalClubPapers::get_from_db(db).update_airtable().await;
+            JournalClubMeetings::get_from_db(db).update_airtable().await;
+        }
+        "license_audits" => {
+            refresh_repo_license_audits(db, github).await;
+            RepoLicenseAudits::get_from_db(db).update_airtable().await;
+        }
+        "lunch_orders" => run_lunch_orders(db, config).await,
+        "mailing_list" => {
+            refresh_db_mailing_list_subscribers(db).await;
+            MailingListSubscribers::get_from_db(db).update_airtable().await;
+        }
+        "match_reviews" => refresh_match_reviews(db).await,
+        "notification_digests" => send_notification_digests(db).await,
+        "oauth_grants" => {
+            refresh_oauth_grants(db).await;
+            OAuthGrants::get_from_db(db).update_airtable().await;
+        }
+        "office_summary" => post_office_summary(db, config).await,
+        "okta_apps" => reconcile_okta_apps(db).await,
+        "page_views" => PageViews::get_from_db(db).update_airtable().await,
+        "password_manager" => reconcile_password_manager_groups(config).await,
+        "people_snapshots" => refresh_people_snapshot(db),
+        "policies" => send_policy_acknowledgment_requests(db, config).await,
+        "profiles" => refresh_profiles(db).await,
+        "rate_limits" => refresh_github_rate_limit(db, github).await,
+        "recorded_meetings" => refresh_recorded_meetings().await,
+        "referrals_report" => send_referrals_report(db, config).await,
+        "reviews" => refresh_review_cycles(db, config).await,
+        "rfd_changelog" => send_rfd_changelog().await,
+        "rfds" => {
+            refresh_db_rfds(db, github, filter).await;
+            RFDs::get_from_db(db).update_airtable().await;
+        }
+        "scheduled_messages" => send_scheduled_messages(db).await,
+        "security_alerts" => {
+            refresh_security_alerts(db).await;
+            Alerts::get_from_db(db).update_airtable().await;
+        }
+        "security_alerts_digest" => send_security_alerts_digest(db, &BusinessCalendar::new(&config.holidays)).await,
+        "security_events" => {
+            refresh_google_workspace_security_events(db).await;
+            SecurityEvents::get_from_db(db).update_airtable().await;
+        }
+        "security_postures" => {
+            refresh_security_postures(db, config).await;
+            SecurityPostures::get_from_db(db).update_airtable().await;
+        }
+        "service_account_keys" => {
+            refresh_service_account_keys(db, config).await;
+            ServiceAccountKeys::get_from_db(db).update_airtable().await;
+        }
+        "shipments" => {
+            refresh_inbound_shipments().await;
+            refresh_airtable_shipments().await;
+        }
+        "shorturls" => refresh_shorturls().await,
+        "slack_channels" => reconcile_slack_channels(config).await,
+        "slack_user_groups" => reconcile_slack_user_groups(db, config).await,
+        "software_vendors" => refresh_software_vendors(filter).await,
+        "stale_items" => send_stale_items_reminders(db, &BusinessCalendar::new(&config.holidays), &sync_config.stale).await,
+        "tailscale" => {
+            cleanup_old_tailscale_devices().await;
+            refresh_tailscale_devices(db).await;
+            TailscaleDevices::get_from_db(db).update_airtable().await;
+        }
+        "take_homes" => close_overdue_take_homes(db, config).await,
+        "trainings" => refresh_trainings(db, config).await,
+        "vendor_security_reviews" => flag_vendors_needing_security_review(db).await,
+        "visitors" => post_expected_visitors(db, config).await,
+        "webhook_deliveries" => prune_webhook_deliveries(db),
+        "welcome_messages" => send_welcome_messages(db, config).await,
+        _ => return false,
+    }
+
+    true
+}
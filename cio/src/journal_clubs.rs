@@ -22,6 +22,7 @@ use crate::utils::github_org;
     new_struct_name = "JournalClubMeeting",
     airtable_base_id = "AIRTABLE_BASE_ID_MISC",
     airtable_table = "AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE",
+    source = "github",
     match_on = {
         "issue" = "String",
     },
@@ -151,6 +152,7 @@ impl UpdateAirtableRecord<JournalClubMeeting> for JournalClubMeeting {
     new_struct_name = "JournalClubPaper",
     airtable_base_id = "AIRTABLE_BASE_ID_MISC",
     airtable_table = "AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE",
+    source = "github",
     match_on = {
         "link" = "String",
     },
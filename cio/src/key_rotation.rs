@@ -0,0 +1,403 @@
+#![allow(clippy::from_over_into)]
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration};
+use macros::db;
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+use yup_oauth2::{read_service_account_key, ServiceAccountAuthenticator};
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_SERVICE_ACCOUNT_KEYS_TABLE};
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::service_account_keys;
+use crate::utils::{get_gcp_token, get_gsuite_credential_file, google_api};
+
+static IAM_API: &str = "https://iam.googleapis.com/v1";
+pub static SECRET_MANAGER_API: &str = "https://secretmanager.googleapis.com/v1";
+pub static CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// The age in days we rotate a key at if the config does not say.
+pub static DEFAULT_MAX_KEY_AGE_DAYS: i64 = 90;
+
+/// The days we keep the previous keys for after a rotation, so deployments have time to
+/// pick up the new key from the secret store before the old one stops working.
+pub static ROTATION_GRACE_DAYS: i64 = 2;
+
+/// A service account key we rotate, keyed by a name in the `service_account_keys`
+/// section of our configs.
+///
+/// ```toml
+/// [service_account_keys.gadmin]
+/// secret = "projects/oxide-cio/secrets/gadmin-credentials"
+/// max_age_days = 90
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ServiceAccountKeyConfig {
+    /// The email of the service account, empty for the one in our GADMIN credentials.
+    #[serde(default)]
+    pub service_account: String,
+    /// The Secret Manager secret we add each new key to as a version, formatted as
+    /// `projects/{project}/secrets/{secret}`.
+    #[serde(default)]
+    pub secret: String,
+    /// The age in days we rotate the key at, 0 for `DEFAULT_MAX_KEY_AGE_DAYS`.
+    #[serde(default)]
+    pub max_age_days: i64,
+}
+
+impl ServiceAccountKeyConfig {
+    /// The age in days we rotate the key at.
+    #[instrument]
+    #[inline]
+    pub fn max_age_days(&self) -> i64 {
+        if self.max_age_days > 0 {
+            return self.max_age_days;
+        }
+
+        DEFAULT_MAX_KEY_AGE_DAYS
+    }
+}
+
+/// The current key for a service account we rotate.
+#[db {
+    new_struct_name = "ServiceAccountKey",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_SERVICE_ACCOUNT_KEYS_TABLE",
+    source = "gcp",
+    match_on = {
+        "name" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "service_account_keys"]
+pub struct NewServiceAccountKey {
+    /// The name of the key in our configs.
+    pub name: String,
+    pub service_account: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key_id: String,
+    pub key_created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub age_days: i32,
+    #[serde(default)]
+    pub max_age_days: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub secret: String,
+    pub rotated_at: Option<DateTime<Utc>>,
+    /// Either `ok`, `rotated`, or `failed`.
+    pub status: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Implement updating the Airtable record for a ServiceAccountKey.
+#[async_trait]
+impl UpdateAirtableRecord<ServiceAccountKey> for ServiceAccountKey {
+    async fn update_airtable_record(&mut self, _record: ServiceAccountKey) {}
+}
+
+/// A user managed key for a service account, from the IAM API.
+#[derive(Debug, PartialEq, Clone)]
+pub struct IamKey {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Parse the user managed keys from a list of keys from the IAM API, newest first.
+/// FROM: https://cloud.google.com/iam/docs/reference/rest/v1/projects.serviceAccounts.keys
+#[instrument(skip(resp))]
+#[inline]
+pub fn parse_iam_keys(resp: &Value) -> Vec<IamKey> {
+    let mut keys: Vec<IamKey> = resp["keys"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .filter(|k| k["keyType"].as_str().unwrap_or("USER_MANAGED") == "USER_MANAGED" && !k["disabled"].as_bool().unwrap_or_default())
+        .filter_map(|k| {
+            // The name is formatted as `projects/{project}/serviceAccounts/{email}/keys/{id}`.
+            let id = k["name"].as_str()?.rsplit('/').next()?.to_string();
+            let created_at = DateTime::parse_from_rfc3339(k["validAfterTime"].as_str()?).ok()?.with_timezone(&Utc);
+            Some(IamKey { id, created_at })
+        })
+        .collect();
+    keys.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    keys
+}
+
+/// Returns if the key is at or past the age we rotate it at.
+#[instrument]
+#[inline]
+pub fn needs_rotation(key: &IamKey, max_age_days: i64, now: DateTime<Utc>) -> bool {
+    now - key.created_at >= Duration::days(max_age_days)
+}
+
+/// Returns the ids of the keys older than the newest key that can be deleted, which is
+/// once the newest key is `ROTATION_GRACE_DAYS` old.
+#[instrument]
+#[inline]
+pub fn expired_key_ids(keys: &[IamKey], now: DateTime<Utc>) -> Vec<String> {
+    let newest = match keys.first() {
+        Some(k) => k,
+        None => return Default::default(),
+    };
+    if now - newest.created_at < Duration::days(ROTATION_GRACE_DAYS) {
+        return Default::default();
+    }
+
+    keys.iter().skip(1).map(|k| k.id.to_string()).collect()
+}
+
+/// Make a request to one of the Google Cloud APIs we do not have a crate for.
+#[instrument(skip(token, body))]
+#[inline]
+pub async fn gcp_api(token: &str, method: Method, url: &str, body: Option<Value>) -> Result<Value, String> {
+    google_api(token, method, url, &[], body).await
+}
+
+/// Check that a new key can get a token. New keys can take a minute to work, so this retries.
+#[instrument(skip(private_key_data))]
+#[inline]
+async fn verify_key(private_key_data: &str) -> Result<(), String> {
+    let b = base64::decode(private_key_data).map_err(|e| format!("decoding the new key failed: {}", e))?;
+    let key: yup_oauth2::ServiceAccountKey = serde_json::from_slice(&b).map_err(|e| format!("parsing the new key failed: {}", e))?;
+
+    let mut error = String::new();
+    for _ in 0..6 {
+        let auth = ServiceAccountAuthenticator::builder(key.clone()).build().await.map_err(|e| e.to_string())?;
+        match auth.token(&[CLOUD_PLATFORM_SCOPE]).await {
+            Ok(t) if !t.as_str().is_empty() => return Ok(()),
+            Ok(_) => error = "empty token".to_string(),
+            Err(e) => error = e.to_string(),
+        }
+
+        tokio::time::delay_for(std::time::Duration::from_secs(10)).await;
+    }
+
+    Err(format!("the new key could not get a token: {}", error))
+}
+
+/// Create a new key for the service account, check it works, and add it to the secret. If
+/// any step fails the new key is deleted, so we never have a key that is not in the secret.
+/// Returns the new key.
+#[instrument(skip(token, config))]
+#[inline]
+async fn rotate_key(token: &str, service_account: &str, config: &ServiceAccountKeyConfig) -> Result<IamKey, String> {
+    let keys_url = format!("{}/projects/-/serviceAccounts/{}/keys", IAM_API, service_account);
+    let created = gcp_api(token, Method::POST, &keys_url, Some(json!({})))
+        .await
+        .map_err(|e| format!("creating a new key failed: {}", e))?;
+    let name = created["name"].as_str().unwrap_or_default().to_string();
+    let id = name.rsplit('/').next().unwrap_or_default().to_string();
+    // The private key data is the base64 encoded JSON credentials file, which is
+    // also how Secret Manager wants the payload.
+    let private_key_data = created["privateKeyData"].as_str().unwrap_or_default().to_string();
+
+    let mut result = verify_key(&private_key_data).await;
+    if result.is_ok() {
+        result = gcp_api(
+            token,
+            Method::POST,
+            &format!("{}/{}:addVersion", SECRET_MANAGER_API, config.secret),
+            Some(json!({ "payload": { "data": private_key_data } })),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("adding the new key to {} failed: {}", config.secret, e));
+    }
+
+    if let Err(e) = result {
+        if let Err(d) = gcp_api(token, Method::DELETE, &format!("{}/{}", IAM_API, name), None).await {
+            return Err(format!("{}, and deleting the new key {} failed: {}", e, id, d));
+        }
+        return Err(e);
+    }
+
+    Ok(IamKey { id, created_at: Utc::now() })
+}
+
+/// Check the age of each service account key in the `service_account_keys` section of our
+/// configs and rotate the ones that are too old. The new key is added to the secret store,
+/// and the old keys are deleted after `ROTATION_GRACE_DAYS`. Any key we could not check or
+/// rotate is posted to the #security channel as urgent, since it will keep getting older.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn refresh_service_account_keys(db: &Database, config: &Config) {
+    if config.service_account_keys.is_empty() {
+        println!("[key_rotation] no service account keys in our configs");
+        return;
+    }
+
+    let token = get_gcp_token(&[CLOUD_PLATFORM_SCOPE]).await;
+    // The key this deployment is using, we never delete it out from under ourselves.
+    let in_use = read_service_account_key(get_gsuite_credential_file()).await.expect("failed to read gsuite credential file");
+
+    let mut failures: Vec<String> = Default::default();
+    for (name, c) in &config.service_account_keys {
+        let service_account = if c.service_account.is_empty() {
+            in_use.client_email.to_string()
+        } else {
+            c.service_account.to_string()
+        };
+        let previous = ServiceAccountKey::get_from_db(db, name.to_string());
+
+        let mut key = NewServiceAccountKey {
+            name: name.to_string(),
+            service_account: service_account.to_string(),
+            key_id: Default::default(),
+            key_created_at: None,
+            age_days: 0,
+            max_age_days: c.max_age_days() as i32,
+            secret: c.secret.to_string(),
+            rotated_at: previous.and_then(|p| p.rotated_at),
+            status: "ok".to_string(),
+            error: Default::default(),
+            checked_at: Utc::now(),
+        };
+
+        let result = check_key(token.as_str(), &service_account, c, in_use.private_key_id.as_deref().unwrap_or_default(), &mut key).await;
+        if let Err(e) = result {
+            println!("[key_rotation] {}: {}", name, e);
+            failures.push(format!("• *{}* `{}`: {}", name, service_account, e));
+            key.status = "failed".to_string();
+            key.error = e;
+        }
+
+        key.upsert(db).await;
+    }
+
+    if failures.is_empty() {
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Urgent,
+        ChatChannel::Security,
+        "service_account_keys.rotation_failed",
+        json!({
+            "text": render_message("service_account_keys.rotation_failed", &json!({ "lines": failures })),
+        }),
+    )
+    .await;
+}
+
+/// Check the age of the keys for a service account, rotating the newest if it is too old
+/// and deleting the older ones once they are past the grace period.
+#[instrument(skip(token, config, key))]
+#[inline]
+async fn check_key(token: &str, service_account: &str, config: &ServiceAccountKeyConfig, in_use_key_id: &str, key: &mut NewServiceAccountKey) -> Result<(), String> {
+    let keys_url = format!("{}/projects/-/serviceAccounts/{}/keys?keyTypes=USER_MANAGED", IAM_API, service_account);
+    let mut keys = parse_iam_keys(&gcp_api(token, Method::GET, &keys_url, None).await.map_err(|e| format!("listing the keys failed: {}", e))?);
+
+    let now = Utc::now();
+    let rotate = match keys.first() {
+        Some(k) => needs_rotation(k, config.max_age_days(), now),
+        None => true,
+    };
+    if rotate {
+        let new_key = rotate_key(token, service_account, config).await?;
+        println!("[key_rotation] rotated {} to key {}", key.name, new_key.id);
+        key.rotated_at = Some(new_key.created_at);
+        key.status = "rotated".to_string();
+        keys.insert(0, new_key);
+    }
+
+    let newest = keys.first().unwrap();
+    key.key_id = newest.id.to_string();
+    key.key_created_at = Some(newest.created_at);
+    key.age_days = (now - newest.created_at).num_days() as i32;
+
+    let expired = expired_key_ids(&keys, now);
+    if expired.iter().any(|id| id == in_use_key_id) {
+        return Err(format!(
+            "we are still using key {} which was replaced {} days ago, deploy the new key from {}",
+            in_use_key_id, key.age_days, config.secret
+        ));
+    }
+    for id in expired {
+        gcp_api(token, Method::DELETE, &format!("{}/projects/-/serviceAccounts/{}/keys/{}", IAM_API, service_account, id), None)
+            .await
+            .map_err(|e| format!("deleting the old key {} failed: {}", id, e))?;
+        println!("[key_rotation] deleted the old key {} for {}", id, key.name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::configs::Config;
+    use crate::db::Database;
+    use crate::key_rotation::{expired_key_ids, needs_rotation, parse_iam_keys, refresh_service_account_keys, ServiceAccountKeyConfig, ServiceAccountKeys, DEFAULT_MAX_KEY_AGE_DAYS};
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_service_account_keys() {
+        let db = Database::new();
+
+        refresh_service_account_keys(&db, &Config::default()).await;
+
+        ServiceAccountKeys::get_from_db(&db).update_airtable().await;
+    }
+
+    #[test]
+    fn test_service_account_key_rotation() {
+        let resp = json!({
+            "keys": [
+                {
+                    "name": "projects/oxide-cio/serviceAccounts/cio@oxide-cio.iam.gserviceaccount.com/keys/old",
+                    "validAfterTime": "2021-01-01T00:00:00Z",
+                    "keyType": "USER_MANAGED",
+                },
+                {
+                    "name": "projects/oxide-cio/serviceAccounts/cio@oxide-cio.iam.gserviceaccount.com/keys/new",
+                    "validAfterTime": "2021-04-20T00:00:00Z",
+                    "keyType": "USER_MANAGED",
+                },
+                {
+                    "name": "projects/oxide-cio/serviceAccounts/cio@oxide-cio.iam.gserviceaccount.com/keys/google",
+                    "validAfterTime": "2021-04-25T00:00:00Z",
+                    "keyType": "SYSTEM_MANAGED",
+                },
+            ],
+        });
+
+        let keys = parse_iam_keys(&resp);
+        assert_eq!(keys.iter().map(|k| k.id.as_str()).collect::<Vec<_>>(), vec!["new", "old"]);
+        assert_eq!(keys[0].created_at, Utc.ymd(2021, 4, 20).and_hms(0, 0, 0));
+
+        let now = Utc.ymd(2021, 4, 21).and_hms(0, 0, 0);
+        assert!(!needs_rotation(&keys[0], 90, now));
+        assert!(needs_rotation(&keys[1], 90, now));
+
+        // The new key is a day old, so we keep the old one around.
+        assert!(expired_key_ids(&keys, now).is_empty());
+        assert_eq!(expired_key_ids(&keys, now + Duration::days(1)), vec!["old"]);
+        assert!(expired_key_ids(&keys[..1], now + Duration::days(1)).is_empty());
+        assert!(expired_key_ids(&[], now).is_empty());
+
+        assert_eq!(ServiceAccountKeyConfig::default().max_age_days(), DEFAULT_MAX_KEY_AGE_DAYS);
+        assert_eq!(
+            ServiceAccountKeyConfig {
+                max_age_days: 30,
+                ..Default::default()
+            }
+            .max_age_days(),
+            30
+        );
+    }
+}
@@ -1,30 +1,113 @@
 #![recursion_limit = "256"]
 #![allow(clippy::field_reassign_with_default)]
 
+pub mod actions_usage;
 pub mod airtable;
+pub mod airtable_links;
+pub mod airtable_syncs;
+pub mod alerts;
 pub mod analytics;
+pub mod api_tokens;
+pub mod applicant_emails;
+pub mod applicant_messages;
 pub mod applicant_status;
 pub mod applicants;
+pub mod application_form;
+pub mod approvals;
+pub mod attachments;
 pub mod auth_logins;
+pub mod background_checks;
+pub mod backups;
+pub mod board_pack;
+pub mod bucket_audits;
+pub mod business_days;
+pub mod carbon;
 pub mod certs;
+pub mod charts;
+pub mod chat;
+pub mod circuit_breakers;
+pub mod cloud_costs;
+pub mod cohorts;
+pub mod config_check;
 pub mod configs;
 pub mod core;
+pub mod dashboard;
 pub mod db;
+pub mod desks;
+pub mod diff;
+pub mod doctor;
+pub mod docusign;
+pub mod drive_permissions;
+pub mod engineering_metrics;
+pub mod event_bus;
+pub mod event_registrants;
+pub mod extra_fields;
+pub mod feedback;
+pub mod filter;
 pub mod finance;
+pub mod format;
+pub mod forms;
 pub mod gsuite;
+pub mod headcount;
+pub mod import;
 pub mod interviews;
+pub mod job_runs;
+pub mod jobs;
 pub mod journal_clubs;
+pub mod key_rotation;
+pub mod licenses;
+pub mod load_test;
+pub mod lunch;
 pub mod mailing_list;
+pub mod maintenance;
+pub mod matching;
+pub mod messages;
 pub mod models;
+pub mod notifications;
+pub mod oauth_grants;
+pub mod offers;
+pub mod password_manager;
+pub mod policies;
+pub mod posture;
+pub mod profiles;
+pub mod progress;
+pub mod rate_limits;
+pub mod reactions;
 pub mod recorded_meetings;
+pub mod referrals;
+pub mod reports;
+pub mod retention;
+pub mod reviews;
+pub mod rfd_authors;
+pub mod rfd_clone;
+pub mod rfd_references;
 pub mod rfds;
+pub mod schedule;
+pub mod scheduler;
 pub mod schema;
+pub mod schema_drift;
+pub mod scim;
+pub mod security_events;
+pub mod seed;
 pub mod shipments;
 pub mod shorturls;
 pub mod slack;
+pub mod slack_channels;
+pub mod slack_user_groups;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stale;
 pub mod tailscale;
+pub mod take_homes;
 pub mod templates;
+pub mod trainings;
 pub mod utils;
+pub mod vcr;
+pub mod verify;
+pub mod visitors;
+pub mod vocabulary;
+pub mod webhook_deliveries;
+pub mod welcome;
 
 #[macro_use]
 extern crate diesel;
@@ -0,0 +1,406 @@
+#![allow(clippy::from_over_into)]
+use std::collections::{BTreeMap, HashMap};
+
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use hubcaps::Github;
+use macros::db;
+use reqwest::Client;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_DEPENDENCY_LICENSES_TABLE};
+use crate::chat::ChatChannel;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::models::GithubRepos;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::repo_license_audits;
+use crate::utils::{get_file_content_from_repo, github_org};
+
+/// The license audit for the dependencies of a single GitHub repository.
+#[db {
+    new_struct_name = "RepoLicenseAudit",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_DEPENDENCY_LICENSES_TABLE",
+    source = "github",
+    match_on = {
+        "repo" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "repo_license_audits"]
+pub struct NewRepoLicenseAudit {
+    pub repo: String,
+    /// The dependency manifests we found in the repository, ie. `Cargo.lock`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manifests: Vec<String>,
+    #[serde(default)]
+    pub dependencies_count: i32,
+    /// The licenses used by the dependencies and how many dependencies use them,
+    /// formatted as `{license} ({count})`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub licenses: Vec<String>,
+    /// The dependencies with a copyleft license, formatted as `{name}@{version} ({license})`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub copyleft_dependencies: Vec<String>,
+    /// The dependencies we could not find a license for, formatted as `{name}@{version}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unknown_dependencies: Vec<String>,
+    pub audited_at: DateTime<Utc>,
+}
+
+/// Implement updating the Airtable record for a RepoLicenseAudit.
+#[async_trait]
+impl UpdateAirtableRecord<RepoLicenseAudit> for RepoLicenseAudit {
+    async fn update_airtable_record(&mut self, _record: RepoLicenseAudit) {}
+}
+
+/// The package registries we know how to look up licenses from.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PackageRegistry {
+    Crates,
+    Npm,
+}
+
+/// A dependency parsed out of a lock file.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Dependency {
+    pub registry: PackageRegistry,
+    pub name: String,
+    pub version: String,
+    /// The license, if the lock file already told us what it is.
+    pub license: String,
+}
+
+impl Dependency {
+    /// Return the dependency formatted as `{name}@{version}`.
+    #[instrument]
+    #[inline]
+    pub fn id(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+}
+
+/// The kinds of licenses we care about when auditing.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LicenseKind {
+    Permissive,
+    Copyleft,
+    Unknown,
+}
+
+/// The identifiers of licenses we consider copyleft.
+static COPYLEFT_LICENSES: &[&str] = &["AGPL", "GPL", "LGPL", "MPL", "EPL", "EUPL", "CDDL", "OSL", "CC-BY-SA", "SSPL"];
+
+/// Classify an SPDX license expression.
+/// If any alternative in an `OR` expression is not copyleft we can choose that one,
+/// so the expression is only copyleft if every alternative is.
+#[instrument]
+#[inline]
+pub fn classify_license(expression: &str) -> LicenseKind {
+    let e = expression.trim();
+    if e.is_empty() || e.eq_ignore_ascii_case("unknown") || e.eq_ignore_ascii_case("unlicensed") || e.to_uppercase().starts_with("SEE LICENSE") {
+        return LicenseKind::Unknown;
+    }
+
+    let alternatives: Vec<String> = e
+        .replace("(", "")
+        .replace(")", "")
+        .replace(" or ", " OR ")
+        .replace("/", " OR ")
+        .split(" OR ")
+        .map(|s| s.trim().to_uppercase())
+        .collect();
+
+    let mut copyleft = true;
+    for alternative in alternatives.iter() {
+        // An `AND` expression is copyleft if any of its parts are.
+        if !alternative.split(" AND ").any(|l| COPYLEFT_LICENSES.iter().any(|c| l.trim().starts_with(c))) {
+            copyleft = false;
+        }
+    }
+
+    if copyleft {
+        LicenseKind::Copyleft
+    } else {
+        LicenseKind::Permissive
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CargoLock {
+    #[serde(default)]
+    package: Vec<CargoLockPackage>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CargoLockPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    source: String,
+}
+
+/// Parse the dependencies from the contents of a `Cargo.lock` file.
+/// Only packages from crates.io are returned, since path and git dependencies
+/// are usually our own code.
+#[instrument(skip(contents))]
+#[inline]
+pub fn parse_cargo_lock(contents: &str) -> Vec<Dependency> {
+    let lock: CargoLock = match toml::from_str(contents) {
+        Ok(l) => l,
+        Err(e) => {
+            println!("[licenses] parsing Cargo.lock failed: {}", e);
+            return vec![];
+        }
+    };
+
+    lock.package
+        .into_iter()
+        .filter(|p| p.source.starts_with("registry+"))
+        .map(|p| Dependency {
+            registry: PackageRegistry::Crates,
+            name: p.name,
+            version: p.version,
+            license: Default::default(),
+        })
+        .collect()
+}
+
+/// Parse the dependencies from the contents of a `package-lock.json` file.
+/// This handles both the `packages` map from lockfile version 2 and the
+/// `dependencies` map from version 1.
+#[instrument(skip(contents))]
+#[inline]
+pub fn parse_package_lock(contents: &str) -> Vec<Dependency> {
+    let lock: Value = match serde_json::from_str(contents) {
+        Ok(l) => l,
+        Err(e) => {
+            println!("[licenses] parsing package-lock.json failed: {}", e);
+            return vec![];
+        }
+    };
+
+    let mut deps: Vec<Dependency> = Default::default();
+    if let Some(packages) = lock["packages"].as_object() {
+        for (path, package) in packages {
+            // The empty path is the root package itself.
+            if path.is_empty() || package["link"].as_bool().unwrap_or(false) {
+                continue;
+            }
+
+            let name = path.rsplit("node_modules/").next().unwrap_or_default().to_string();
+            deps.push(Dependency {
+                registry: PackageRegistry::Npm,
+                name,
+                version: package["version"].as_str().unwrap_or_default().to_string(),
+                license: package["license"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+    } else if let Some(dependencies) = lock["dependencies"].as_object() {
+        for (name, package) in dependencies {
+            deps.push(Dependency {
+                registry: PackageRegistry::Npm,
+                name: name.to_string(),
+                version: package["version"].as_str().unwrap_or_default().to_string(),
+                license: Default::default(),
+            });
+        }
+    }
+
+    deps.sort();
+    deps.dedup();
+    deps
+}
+
+/// Look up the license for a dependency from its package registry.
+/// Returns an empty string if we could not find the license.
+#[instrument(skip(client))]
+#[inline]
+async fn get_license_from_registry(client: &Client, dep: &Dependency) -> String {
+    let url = match dep.registry {
+        PackageRegistry::Crates => format!("https://crates.io/api/v1/crates/{}/{}", dep.name, dep.version),
+        PackageRegistry::Npm => format!("https://registry.npmjs.org/{}/{}", dep.name, dep.version),
+    };
+
+    let resp = match client.get(&url).send().await {
+        Ok(r) => r,
+        Err(e) => {
+            println!("[licenses] getting license for {} failed: {}", dep.id(), e);
+            return "".to_string();
+        }
+    };
+
+    let body: Value = resp.json().await.unwrap_or_default();
+    let license = match dep.registry {
+        PackageRegistry::Crates => &body["version"]["license"],
+        PackageRegistry::Npm => &body["license"],
+    };
+
+    // Some older npm packages have the license as an object with a type.
+    license.as_str().or_else(|| license["type"].as_str()).unwrap_or_default().to_string()
+}
+
+/// Audit the licenses of the dependencies for every repository in our org and
+/// report any newly introduced copyleft or unknown licenses to Slack.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_repo_license_audits(db: &Database, github: &Github) {
+    let client = Client::builder().user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"))).build().unwrap();
+
+    // Cache the licenses across repos since most of them share dependencies.
+    let mut license_cache: HashMap<Dependency, String> = Default::default();
+    let mut new_findings: Vec<String> = Default::default();
+
+    for repo in GithubRepos::get_from_db(db) {
+        if repo.archived || repo.fork {
+            continue;
+        }
+
+        let r = github.repo(github_org(), repo.name.to_string());
+
+        let mut manifests: Vec<String> = Default::default();
+        let mut deps: Vec<Dependency> = Default::default();
+        for manifest in &["Cargo.lock", "package-lock.json"] {
+            let (content, _) = get_file_content_from_repo(&r, &repo.default_branch, manifest).await;
+            if content.is_empty() {
+                continue;
+            }
+
+            let contents = String::from_utf8(content).unwrap_or_default();
+            if *manifest == "Cargo.lock" {
+                deps.append(&mut parse_cargo_lock(&contents));
+            } else {
+                deps.append(&mut parse_package_lock(&contents));
+            }
+            manifests.push(manifest.to_string());
+        }
+
+        if manifests.is_empty() {
+            // We have nothing to audit.
+            continue;
+        }
+
+        let mut license_counts: BTreeMap<String, i32> = Default::default();
+        let mut copyleft_dependencies: Vec<String> = Default::default();
+        let mut unknown_dependencies: Vec<String> = Default::default();
+        for mut dep in deps.clone() {
+            if dep.license.is_empty() {
+                if let Some(l) = license_cache.get(&dep) {
+                    dep.license = l.to_string();
+                } else {
+                    let license = get_license_from_registry(&client, &dep).await;
+                    license_cache.insert(dep.clone(), license.to_string());
+                    dep.license = license;
+                }
+            }
+
+            match classify_license(&dep.license) {
+                LicenseKind::Copyleft => copyleft_dependencies.push(format!("{} ({})", dep.id(), dep.license)),
+                LicenseKind::Unknown => unknown_dependencies.push(dep.id()),
+                LicenseKind::Permissive => (),
+            }
+
+            let mut license = dep.license.to_string();
+            if license.is_empty() {
+                license = "unknown".to_string();
+            }
+            *license_counts.entry(license).or_insert(0) += 1;
+        }
+
+        // Figure out what is new since the last audit, so we only report new findings.
+        let existing = RepoLicenseAudit::get_from_db(db, repo.name.to_string());
+        for finding in copyleft_dependencies.iter().chain(unknown_dependencies.iter()) {
+            if let Some(e) = &existing {
+                if e.copyleft_dependencies.contains(finding) || e.unknown_dependencies.contains(finding) {
+                    continue;
+                }
+            }
+
+            new_findings.push(format!("• `{}`: {}", repo.name, finding));
+        }
+
+        let audit = NewRepoLicenseAudit {
+            repo: repo.name.to_string(),
+            manifests,
+            dependencies_count: deps.len() as i32,
+            licenses: license_counts.iter().map(|(l, c)| format!("{} ({})", l, c)).collect(),
+            copyleft_dependencies,
+            unknown_dependencies,
+            audited_at: Utc::now(),
+        };
+        audit.upsert(db).await;
+    }
+
+    if new_findings.is_empty() {
+        println!("[licenses] no new copyleft or unknown licenses found");
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Engineering,
+        "licenses.new_findings",
+        json!({
+            "text": render_message("licenses.new_findings", &json!({ "lines": new_findings })),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::licenses::{classify_license, parse_cargo_lock, parse_package_lock, refresh_repo_license_audits, LicenseKind};
+    use crate::utils::authenticate_github_jwt;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_repo_license_audits() {
+        let github = authenticate_github_jwt();
+        let db = Database::new();
+
+        refresh_repo_license_audits(&db, &github).await;
+    }
+
+    #[test]
+    fn test_classify_license() {
+        assert_eq!(classify_license("MIT OR Apache-2.0"), LicenseKind::Permissive);
+        assert_eq!(classify_license("MIT/Apache-2.0"), LicenseKind::Permissive);
+        assert_eq!(classify_license("GPL-3.0-only"), LicenseKind::Copyleft);
+        assert_eq!(classify_license("(MIT OR GPL-2.0)"), LicenseKind::Permissive);
+        assert_eq!(classify_license("MIT AND LGPL-2.1"), LicenseKind::Copyleft);
+        assert_eq!(classify_license("MPL-2.0"), LicenseKind::Copyleft);
+        assert_eq!(classify_license(""), LicenseKind::Unknown);
+        assert_eq!(classify_license("SEE LICENSE IN LICENSE.md"), LicenseKind::Unknown);
+    }
+
+    #[test]
+    fn test_parse_lock_files() {
+        let cargo = r#"
+[[package]]
+name = "cio-api"
+version = "0.2.0"
+
+[[package]]
+name = "serde"
+version = "1.0.125"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+        let deps = parse_cargo_lock(cargo);
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].id(), "serde@1.0.125");
+
+        let npm = r#"{"lockfileVersion": 2, "packages": {"": {"name": "site"}, "node_modules/left-pad": {"version": "1.3.0", "license": "WTFPL"}}}"#;
+        let deps = parse_package_lock(npm);
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].id(), "left-pad@1.3.0");
+        assert_eq!(deps[0].license, "WTFPL");
+    }
+}
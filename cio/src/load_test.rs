@@ -0,0 +1,311 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{SecondsFormat, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::applicants::{Applicants, NewApplicant};
+use crate::configs::{BuildingConfig, Buildings, UserConfig, Users};
+use crate::db::Database;
+use crate::finance::{NewSoftwareVendor, SoftwareVendors};
+use crate::job_runs::{start_job_usage, take_job_usage};
+use crate::models::{NewRFD, RFDs};
+use crate::progress::format_duration;
+use crate::seed::{SeedData, SEED_SOURCE};
+
+/// Counts the allocations the process makes, so the load test can report them. The
+/// load test binary installs it as the global allocator, nothing else does.
+pub struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Get the number of allocations and the bytes allocated so far. These stay at zero
+/// unless `CountingAllocator` is the global allocator.
+#[inline]
+pub fn allocations() -> (usize, usize) {
+    (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed))
+}
+
+/// Where to send Airtable requests rather than the Airtable API, set when the load
+/// test starts the mock Airtable server.
+static AIRTABLE_ENDPOINT: Mutex<Option<String>> = Mutex::new(None);
+static MOCK_AIRTABLE_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+static MOCK_AIRTABLE_RECORDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the endpoint of the mock Airtable server, if it is running.
+#[inline]
+pub fn airtable_endpoint() -> Option<String> {
+    AIRTABLE_ENDPOINT.lock().unwrap().clone()
+}
+
+/// Answer a request to the mock Airtable server. The tables are always empty, so a
+/// sync creates every record, and created or updated records are echoed back with an
+/// Airtable record ID, like the real API.
+#[instrument(skip(body))]
+#[inline]
+pub fn mock_airtable_response(method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+    // The path is `/v0/{base}/{table}`, or `/v0/{base}/{table}/{record}` for a record.
+    let is_record = path.trim_matches('/').split('/').count() > 3;
+    match method {
+        "GET" if is_record => (404, json!({ "error": { "type": "NOT_FOUND", "message": "Could not find what you are looking for" } })),
+        "POST" | "PATCH" => {
+            let mut call: Value = serde_json::from_slice(body).unwrap_or_else(|_| json!({ "records": [] }));
+            if let Some(records) = call["records"].as_array_mut() {
+                for record in records {
+                    if record["id"].as_str().unwrap_or_default().is_empty() {
+                        let n = MOCK_AIRTABLE_RECORDS.fetch_add(1, Ordering::Relaxed);
+                        record["id"] = json!(format!("rec{:014}", n));
+                    }
+                    record["createdTime"] = json!(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true));
+                }
+            }
+            (200, json!({ "records": call["records"] }))
+        }
+        _ => (200, json!({ "records": [] })),
+    }
+}
+
+async fn serve_mock_airtable(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    MOCK_AIRTABLE_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let body = if method == Method::POST || method == Method::PATCH {
+        hyper::body::to_bytes(req.into_body()).await.unwrap_or_default().to_vec()
+    } else {
+        Vec::new()
+    };
+
+    let (status, value) = mock_airtable_response(method.as_str(), &path, &body);
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap())
+}
+
+/// Start the mock Airtable server on a free local port and send every Airtable request
+/// to it. Returns its endpoint.
+#[instrument]
+#[inline]
+pub async fn start_mock_airtable() -> String {
+    let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+    let server = Server::bind(&addr).serve(make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(serve_mock_airtable)) }));
+    let endpoint = format!("http://{}/v0/", server.local_addr());
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            println!("[load_test] the mock Airtable server failed: {}", e);
+        }
+    });
+
+    *AIRTABLE_ENDPOINT.lock().unwrap() = Some(endpoint.to_string());
+    endpoint
+}
+
+/// How a phase of the load test went.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LoadTestResult {
+    pub phase: String,
+    pub records: usize,
+    pub elapsed: Duration,
+    pub allocations: usize,
+    pub allocated_bytes: usize,
+    pub airtable_requests: usize,
+    pub db_checkouts: usize,
+}
+
+impl LoadTestResult {
+    #[instrument]
+    #[inline]
+    pub fn records_per_sec(&self) -> f64 {
+        if self.elapsed.as_secs_f64() == 0.0 {
+            return self.records as f64;
+        }
+
+        self.records as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// The counts a phase is measured from.
+struct Mark {
+    started: Instant,
+    allocations: (usize, usize),
+    airtable_requests: usize,
+}
+
+fn mark() -> Mark {
+    start_job_usage();
+    Mark {
+        started: Instant::now(),
+        allocations: allocations(),
+        airtable_requests: MOCK_AIRTABLE_REQUESTS.load(Ordering::Relaxed),
+    }
+}
+
+fn measure(phase: String, records: usize, mark: Mark) -> LoadTestResult {
+    let elapsed = mark.started.elapsed();
+    let (allocations, allocated_bytes) = allocations();
+    LoadTestResult {
+        phase,
+        records,
+        elapsed,
+        allocations: allocations - mark.allocations.0,
+        allocated_bytes: allocated_bytes - mark.allocations.1,
+        airtable_requests: MOCK_AIRTABLE_REQUESTS.load(Ordering::Relaxed) - mark.airtable_requests,
+        db_checkouts: take_job_usage().db_checkouts,
+    }
+}
+
+/// Save the records for a table in the database, then push the table to Airtable,
+/// measuring each.
+macro_rules! load_test_table {
+    ($t:ty, $plural:ty, $db:expr, $records:expr, $table:expr, $results:expr) => {{
+        let m = mark();
+        <$t>::upsert_all_from($db, $records, SEED_SOURCE);
+        $results.push(measure(format!("db {}", $table), $records.len(), m));
+
+        let rows = <$plural>::get_from_db($db);
+        let m = mark();
+        rows.update_airtable().await;
+        $results.push(measure(format!("airtable {}", $table), rows.0.len(), m));
+    }};
+}
+
+/// Run the database layer and the Airtable sync engine against the seed data, with
+/// the mock Airtable server from `start_mock_airtable`, measuring each table.
+#[instrument(skip(db, data))]
+#[inline]
+pub async fn run_load_test(db: &Database, data: &SeedData) -> Vec<LoadTestResult> {
+    let mut results = Vec::new();
+    load_test_table!(BuildingConfig, Buildings, db, &data.buildings, "buildings", results);
+    load_test_table!(UserConfig, Users, db, &data.users, "users", results);
+    load_test_table!(NewSoftwareVendor, SoftwareVendors, db, &data.software_vendors, "software_vendors", results);
+    load_test_table!(NewRFD, RFDs, db, &data.rfds, "rfds", results);
+    load_test_table!(NewApplicant, Applicants, db, &data.applicants, "applicants", results);
+
+    results
+}
+
+/// Format the results of the load test as a table to print in a terminal.
+#[instrument(skip(results))]
+#[inline]
+pub fn format_load_test_report(results: &[LoadTestResult]) -> String {
+    let width = results.iter().map(|r| r.phase.len()).max().unwrap_or_default().max(5);
+    let mut text = format!(
+        "{:<width$}  {:>7}  {:>7}  {:>10}  {:>11}  {:>9}  {:>8}  {:>12}\n",
+        "phase",
+        "records",
+        "time",
+        "records/s",
+        "allocations",
+        "allocated",
+        "requests",
+        "db checkouts",
+        width = width
+    );
+    for r in results {
+        text += &format!(
+            "{:<width$}  {:>7}  {:>7}  {:>10.1}  {:>11}  {:>7.1}MB  {:>8}  {:>12}\n",
+            r.phase,
+            r.records,
+            format_duration(r.elapsed),
+            r.records_per_sec(),
+            r.allocations,
+            r.allocated_bytes as f64 / 1_000_000.0,
+            r.airtable_requests,
+            r.db_checkouts,
+            width = width
+        );
+    }
+
+    text
+}
+
+/// Get the phases that ran slower than a number of records per second.
+#[instrument(skip(results))]
+#[inline]
+pub fn slower_than(results: &[LoadTestResult], min_records_per_sec: f64) -> Vec<&LoadTestResult> {
+    results.iter().filter(|r| r.records > 0 && r.records_per_sec() < min_records_per_sec).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::load_test::{format_load_test_report, mock_airtable_response, slower_than, LoadTestResult};
+
+    #[test]
+    fn test_mock_airtable_response() {
+        assert_eq!(mock_airtable_response("GET", "/v0/appTest/Employees", b""), (200, json!({ "records": [] })));
+        assert_eq!(mock_airtable_response("GET", "/v0/appTest/Employees/rec123", b"").0, 404);
+        assert_eq!(mock_airtable_response("DELETE", "/v0/appTest/Employees", b"").0, 200);
+
+        let (status, created) = mock_airtable_response("POST", "/v0/appTest/Employees", br#"{"records": [{"fields": {"id": 1}}], "typecast": true}"#);
+        assert_eq!(status, 200);
+        assert!(created["records"][0]["id"].as_str().unwrap().starts_with("rec"));
+        assert_eq!(created["records"][0]["fields"], json!({ "id": 1 }));
+
+        // Updates keep their record IDs.
+        let (_, updated) = mock_airtable_response("PATCH", "/v0/appTest/Employees", br#"{"records": [{"id": "recKeep", "fields": {}}]}"#);
+        assert_eq!(updated["records"][0]["id"], "recKeep");
+    }
+
+    #[test]
+    fn test_format_load_test_report() {
+        let results = vec![
+            LoadTestResult {
+                phase: "db users".to_string(),
+                records: 500,
+                elapsed: Duration::from_millis(250),
+                allocations: 120_000,
+                allocated_bytes: 4_500_000,
+                airtable_requests: 0,
+                db_checkouts: 6,
+            },
+            LoadTestResult {
+                phase: "airtable users".to_string(),
+                records: 500,
+                elapsed: Duration::from_secs(10),
+                allocations: 900_000,
+                allocated_bytes: 80_000_000,
+                airtable_requests: 1_501,
+                db_checkouts: 1,
+            },
+        ];
+        assert_eq!(results[0].records_per_sec(), 2000.0);
+        assert_eq!(slower_than(&results, 100.0), vec![&results[1]]);
+
+        assert_eq!(
+            format_load_test_report(&results),
+            r#"phase           records     time   records/s  allocations  allocated  requests  db checkouts
+db users            500       0s      2000.0       120000      4.5MB         0             6
+airtable users      500      10s        50.0       900000     80.0MB      1501             1
+"#
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc, Weekday};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use schemars::JsonSchema;
+use sendgrid_api::SendGrid;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::db::Database;
+use crate::format::format_usd;
+use crate::messages::render_message;
+use crate::notifications::{notify, notify_user, NotificationPriority};
+use crate::schema::{lunch_days, lunch_orders};
+use crate::slack::get_slack_user_email;
+use crate::trainings::username_for_email;
+use crate::utils::GSUITE_DOMAIN;
+
+/// The `action_id` of the menu in the message we post to collect lunch orders, so
+/// `webhooky` knows which Slack interactions are lunch orders.
+pub static LUNCH_ORDER_ACTION_ID: &str = "lunch_order";
+
+/// The configuration for ordering lunch on office days.
+///
+/// ```toml
+/// [lunch]
+/// caterer = "orders@example.com"
+/// office_days = ["tuesday", "thursday"]
+/// close_hour = 17
+///
+/// [lunch.menu.pad_thai]
+/// name = "Pad thai"
+/// price = 14.5
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct LunchConfig {
+    /// The email of the caterer we send the orders to.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub caterer: String,
+    /// The days of the week we order lunch for the office, ie. `tuesday`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub office_days: Vec<String>,
+    /// The hour of the day, in UTC, orders close and we email them to the caterer.
+    /// Defaults to 17, which is 10am in California.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub close_hour: Option<u32>,
+    /// What people can pick from, by a short name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub menu: BTreeMap<String, MenuItem>,
+}
+
+impl LunchConfig {
+    #[instrument]
+    #[inline]
+    pub fn close_hour(&self) -> u32 {
+        self.close_hour.unwrap_or(17)
+    }
+
+    /// Returns if we order lunch on a day. Nothing is ordered without a caterer and a menu.
+    #[instrument]
+    #[inline]
+    pub fn is_office_day(&self, day: NaiveDate) -> bool {
+        !self.caterer.is_empty() && !self.menu.is_empty() && self.office_days.iter().any(|d| d.parse::<Weekday>().ok() == Some(day.weekday()))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct MenuItem {
+    pub name: String,
+    /// What it costs in US dollars.
+    pub price: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "lunch_days"]
+pub struct NewLunchDay {
+    pub day: NaiveDate,
+    pub posted_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub orders: i32,
+    /// What the order cost in US dollars, once it was sent to the caterer.
+    pub total_cost: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct LunchDay {
+    pub id: i32,
+    pub day: NaiveDate,
+    pub posted_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub orders: i32,
+    pub total_cost: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "lunch_orders"]
+pub struct NewLunchOrder {
+    pub day: NaiveDate,
+    pub username: String,
+    /// The short name of the item in the menu.
+    pub item: String,
+    /// What the item cost when it was ordered, in case the menu changes.
+    pub price: f32,
+    pub ordered_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct LunchOrder {
+    pub id: i32,
+    pub day: NaiveDate,
+    pub username: String,
+    pub item: String,
+    pub price: f32,
+    pub ordered_at: DateTime<Utc>,
+}
+
+/// Format a day the way we say it in the lunch messages, ie. `Thursday, May 20`.
+fn format_lunch_day(day: NaiveDate) -> String {
+    day.format("%A, %B %-d").to_string()
+}
+
+/// Get the message we post to the office channel to collect the lunch orders for a day,
+/// with the menu to pick from.
+#[instrument(skip(config))]
+#[inline]
+pub fn lunch_order_msg(config: &Config, day: NaiveDate) -> Value {
+    let text = render_message("lunch.orders", &json!({ "when": format_lunch_day(day), "deadline": format!("{}:00 UTC", config.lunch.close_hour()) }));
+    let options: Vec<Value> = config
+        .lunch
+        .menu
+        .iter()
+        .map(|(key, item)| {
+            json!({
+                "text": { "type": "plain_text", "text": format!("{} ({})", item.name, format_usd(item.price)) },
+                "value": format!("{}:{}", day, key),
+            })
+        })
+        .collect();
+
+    json!({
+        "text": text,
+        "blocks": [
+            { "type": "section", "text": { "type": "mrkdwn", "text": text } },
+            {
+                "type": "actions",
+                "elements": [{
+                    "type": "static_select",
+                    "action_id": LUNCH_ORDER_ACTION_ID,
+                    "placeholder": { "type": "plain_text", "text": "Pick your lunch" },
+                    "options": options,
+                }],
+            },
+        ],
+    })
+}
+
+/// Parse the day and the menu item from the value of an option in the lunch menu.
+#[instrument]
+#[inline]
+pub fn parse_lunch_order(value: &str) -> Option<(NaiveDate, String)> {
+    let mut parts = value.splitn(2, ':');
+    let day = NaiveDate::parse_from_str(parts.next()?, "%Y-%m-%d").ok()?;
+    let item = parts.next().filter(|i| !i.is_empty())?;
+
+    Some((day, item.to_string()))
+}
+
+/// Get the lunch day we collected orders for, if we did.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_lunch_day(db: &Database, day: NaiveDate) -> Option<LunchDay> {
+    lunch_days::table.filter(lunch_days::day.eq(day)).first::<LunchDay>(&db.conn()).ok()
+}
+
+/// Get the lunch orders for a day.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_lunch_orders(db: &Database, day: NaiveDate) -> Vec<LunchOrder> {
+    lunch_orders::table
+        .filter(lunch_orders::day.eq(day))
+        .order_by(lunch_orders::username)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the lunch orders for {} failed: {}", day, e))
+}
+
+/// Record what someone picked from the lunch menu, replacing what they picked before.
+/// Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn order_lunch(db: &Database, config: &Config, username: &str, value: &str, now: DateTime<Utc>) -> Result<String, String> {
+    let (day, key) = parse_lunch_order(value).ok_or_else(|| format!("`{}` is not a lunch order", value))?;
+    let item = config.lunch.menu.get(&key).ok_or_else(|| format!("`{}` is not on the lunch menu anymore", key))?;
+    let closes = Utc.from_utc_date(&day).and_hms(config.lunch.close_hour(), 0, 0);
+    match get_lunch_day(db, day) {
+        Some(d) if d.sent_at.is_none() && now < closes => (),
+        _ => return Err(format!("orders for lunch on {} are closed", format_lunch_day(day))),
+    }
+
+    diesel::delete(lunch_orders::table.filter(lunch_orders::day.eq(day)).filter(lunch_orders::username.eq(username)))
+        .execute(&db.conn())
+        .map_err(|e| format!("replacing the lunch order of {} failed: {}", username, e))?;
+    diesel::insert_into(lunch_orders::table)
+        .values(&NewLunchOrder {
+            day,
+            username: username.to_string(),
+            item: key.to_string(),
+            price: item.price,
+            ordered_at: now,
+        })
+        .execute(&db.conn())
+        .map_err(|e| format!("saving the lunch order of {} failed: {}", username, e))?;
+
+    Ok(render_message("lunch.ordered", &json!({ "name": item.name, "when": format_lunch_day(day) })))
+}
+
+/// Handle someone picking from the lunch menu in Slack, by their Slack user ID, and let
+/// them know what we got down for them. Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn handle_lunch_order(db: &Database, config: &Config, user_id: &str, value: &str) -> Result<String, String> {
+    let email = get_slack_user_email(user_id).await?;
+    let username = username_for_email(&config.users, &email).ok_or_else(|| format!("{} is not in our directory", email))?;
+
+    let text = match order_lunch(db, config, &username, value, Utc::now()) {
+        Ok(text) => text,
+        Err(e) => format!("Sorry, {}.", e),
+    };
+    notify_user(db, &email, json!({ "text": text })).await;
+
+    Ok(format!("{} picked `{}` from the lunch menu", username, value))
+}
+
+/// Summarize the orders for a day for the caterer: how many of each item with the names
+/// of who ordered it, and what it all costs.
+#[instrument(skip(config, orders))]
+#[inline]
+pub fn summarize_lunch_orders(config: &Config, orders: &[LunchOrder]) -> (Vec<String>, f32) {
+    let mut items: BTreeMap<&str, Vec<&LunchOrder>> = BTreeMap::new();
+    for o in orders {
+        items.entry(o.item.as_str()).or_default().push(o);
+    }
+
+    let mut lines = Vec::new();
+    for (key, orders) in items {
+        let name = config.lunch.menu.get(key).map(|i| i.name.to_string()).unwrap_or_else(|| key.to_string());
+        let people: Vec<String> = orders
+            .iter()
+            .map(|o| {
+                config
+                    .users
+                    .get(&o.username)
+                    .map(|u| format!("{} {}", u.first_name, u.last_name))
+                    .unwrap_or_else(|| o.username.to_string())
+            })
+            .collect();
+        lines.push(format!("• {} × {} ({} each): {}", orders.len(), name, format_usd(orders[0].price), people.join(", ")));
+    }
+
+    (lines, orders.iter().map(|o| o.price).sum())
+}
+
+/// Email the orders for a day to the caterer, record what they cost for the finance
+/// rollup, and let the office channel know.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_lunch_order(db: &Database, config: &Config, lunch: &LunchDay) {
+    let orders = get_lunch_orders(db, lunch.day);
+    let (lines, total) = summarize_lunch_orders(config, &orders);
+
+    if !orders.is_empty() {
+        let sendgrid = SendGrid::new_from_env();
+        sendgrid
+            .send_mail(
+                format!("Lunch order for {}", format_lunch_day(lunch.day)),
+                format!(
+                    "Hi, here is our lunch order for {}:\n\n{}\n\nTotal: {}\n",
+                    format_lunch_day(lunch.day),
+                    lines.join("\n"),
+                    format_usd(total)
+                ),
+                vec![config.lunch.caterer.to_string()],
+                vec![format!("office@{}", GSUITE_DOMAIN)],
+                vec![],
+                format!("office@{}", GSUITE_DOMAIN),
+            )
+            .await;
+    }
+
+    diesel::update(lunch_days::table.filter(lunch_days::id.eq(lunch.id)))
+        .set((lunch_days::sent_at.eq(Some(Utc::now())), lunch_days::orders.eq(orders.len() as i32), lunch_days::total_cost.eq(total)))
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("recording the lunch order for {} failed: {}", lunch.day, e));
+
+    if orders.is_empty() {
+        println!("[lunch] nobody ordered lunch for {}", lunch.day);
+        return;
+    }
+    notify(
+        db,
+        NotificationPriority::Urgent,
+        ChatChannel::Office,
+        "lunch.sent",
+        json!({
+            "text": render_message(
+                "lunch.sent",
+                &json!({ "when": format_lunch_day(lunch.day), "count": orders.len(), "total": format_usd(total), "lines": lines }),
+            ),
+        }),
+    )
+    .await;
+}
+
+/// Collect lunch orders on office days: post the menu to the office channel in the
+/// morning, and send the orders to the caterer once they close.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn run_lunch_orders(db: &Database, config: &Config) {
+    let now = Utc::now();
+    let today = now.date().naive_utc();
+
+    if config.lunch.is_office_day(today) && now.hour() < config.lunch.close_hour() && get_lunch_day(db, today).is_none() {
+        diesel::insert_into(lunch_days::table)
+            .values(&NewLunchDay {
+                day: today,
+                posted_at: now,
+                sent_at: None,
+                orders: 0,
+                total_cost: 0.0,
+            })
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("saving the lunch day {} failed: {}", today, e));
+        notify(db, NotificationPriority::Urgent, ChatChannel::Office, "lunch.orders", lunch_order_msg(config, today)).await;
+    }
+
+    let closed = now - Duration::hours(config.lunch.close_hour() as i64);
+    let pending: Vec<LunchDay> = lunch_days::table
+        .filter(lunch_days::sent_at.is_null())
+        .filter(lunch_days::day.le(closed.date().naive_utc()))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the lunch days to send failed: {}", e));
+    for lunch in pending {
+        send_lunch_order(db, config, &lunch).await;
+    }
+}
+
+/// Get what we spent on lunches in the month of a day, in US dollars.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_lunch_cost_for_month(db: &Database, month: NaiveDate) -> f32 {
+    let first = NaiveDate::from_ymd(month.year(), month.month(), 1);
+    let next = if month.month() == 12 {
+        NaiveDate::from_ymd(month.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd(month.year(), month.month() + 1, 1)
+    };
+    let costs: Vec<f32> = lunch_days::table
+        .filter(lunch_days::day.ge(first))
+        .filter(lunch_days::day.lt(next))
+        .filter(lunch_days::sent_at.is_not_null())
+        .select(lunch_days::total_cost)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the lunch costs for {} failed: {}", month, e));
+
+    costs.iter().sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+    use chrono::{TimeZone, Utc};
+
+    use crate::configs::Config;
+    use crate::lunch::{lunch_order_msg, parse_lunch_order, summarize_lunch_orders, LunchOrder, LUNCH_ORDER_ACTION_ID};
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.lunch = toml::from_str(
+            r#"caterer = "orders@example.com"
+office_days = ["tuesday", "Thursday"]
+
+[menu.pad_thai]
+name = "Pad thai"
+price = 14.5
+
+[menu.salad]
+name = "Salad"
+price = 11
+"#,
+        )
+        .unwrap();
+        config.users = toml::from_str(
+            r#"[jane]
+first_name = "Jane"
+last_name = "Doe"
+username = "jane"
+"#,
+        )
+        .unwrap();
+
+        config
+    }
+
+    #[test]
+    fn test_lunch_order_msg() {
+        let mut config = config();
+        // A Thursday and a Friday.
+        let day = NaiveDate::from_ymd(2021, 5, 20);
+        assert!(config.lunch.is_office_day(day));
+        assert!(!config.lunch.is_office_day(NaiveDate::from_ymd(2021, 5, 21)));
+
+        let msg = lunch_order_msg(&config, day);
+        let select = &msg["blocks"][1]["elements"][0];
+        assert_eq!(select["action_id"], LUNCH_ORDER_ACTION_ID);
+        assert_eq!(select["options"][0]["value"], "2021-05-20:pad_thai");
+        assert_eq!(select["options"][1]["text"]["text"], "Salad ($11.00)");
+
+        assert_eq!(parse_lunch_order("2021-05-20:pad_thai"), Some((day, "pad_thai".to_string())));
+        assert_eq!(parse_lunch_order("2021-05-20:"), None);
+        assert_eq!(parse_lunch_order("pad_thai"), None);
+
+        // Without a caterer there is nobody to order from.
+        config.lunch.caterer = String::new();
+        assert!(!config.lunch.is_office_day(day));
+    }
+
+    #[test]
+    fn test_summarize_lunch_orders() {
+        let config = config();
+        let order = |username: &str, item: &str, price: f32| LunchOrder {
+            id: 1,
+            day: NaiveDate::from_ymd(2021, 5, 20),
+            username: username.to_string(),
+            item: item.to_string(),
+            price,
+            ordered_at: Utc.ymd(2021, 5, 20).and_hms(15, 0, 0),
+        };
+
+        let (lines, total) = summarize_lunch_orders(&config, &[order("jane", "pad_thai", 14.5), order("sam", "pad_thai", 14.5), order("alex", "tacos", 9.0)]);
+        assert_eq!(lines, vec!["• 2 × Pad thai ($14.50 each): Jane Doe, sam".to_string(), "• 1 × tacos ($9.00 each): alex".to_string()]);
+        assert!((total - 38.0).abs() < f32::EPSILON);
+    }
+}
@@ -16,6 +16,7 @@ use tracing::instrument;
 
 use crate::airtable::{AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_MAILING_LIST_SIGNUPS_TABLE};
 use crate::db::Database;
+use crate::messages::render_message;
 use crate::schema::mailing_list_subscribers;
 
 /// The data type for a MailingListSubscriber.
@@ -23,6 +24,7 @@ use crate::schema::mailing_list_subscribers;
     new_struct_name = "MailingListSubscriber",
     airtable_base_id = "AIRTABLE_BASE_ID_CUSTOMER_LEADS",
     airtable_table = "AIRTABLE_MAILING_LIST_SIGNUPS_TABLE",
+    source = "mailchimp",
     match_on = {
         "email" = "String",
     },
@@ -79,7 +81,7 @@ impl NewMailingListSubscriber {
     pub fn as_slack_msg(&self) -> Value {
         let time = self.human_duration();
 
-        let msg = format!("*{}* <mailto:{}|{}>", self.name, self.email, self.email);
+        let msg = render_message("mailing_list.new_subscriber", self);
 
         let mut interest: MessageBlock = Default::default();
         if !self.interest.is_empty() {
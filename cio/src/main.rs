@@ -5,18 +5,26 @@ use std::fs::File;
 use std::io::Read;
 use std::sync::Arc;
 
-use dropshot::{endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseOk, HttpServer, RequestContext};
+use chrono::{Duration, Utc};
+use dropshot::{endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseAccepted, HttpResponseOk, HttpServer, Query, RequestContext, TypedBody};
 use hyper::{Body, Response, StatusCode};
+use schemars::JsonSchema;
+use serde::Deserialize;
 use tracing::{instrument, span, Level};
 use tracing_subscriber::prelude::*;
 
 use cio_api::applicants::{Applicant, Applicants};
+use cio_api::application_form::{client_ip, submit_application, verify_hcaptcha, ApplicationError, ApplicationSubmission, RateLimiter};
 use cio_api::auth_logins::{AuthUser, AuthUsers};
 use cio_api::configs::{Building, Buildings, ConferenceRoom, ConferenceRooms, Group, Groups, Link, Links, User, Users};
 use cio_api::db::Database;
+use cio_api::engineering_metrics::{EngineeringMetric, EngineeringMetrics};
+use cio_api::feedback::submit_feedback;
 use cio_api::journal_clubs::{JournalClubMeeting, JournalClubMeetings};
 use cio_api::mailing_list::{MailingListSubscriber, MailingListSubscribers};
 use cio_api::models::{GithubRepo, GithubRepos, RFDs, RFD};
+use cio_api::profiles::{search_profiles, NewProfile, Profiles};
+use cio_api::rfd_references::{get_rfd_references, RFDReferences};
 
 #[macro_use]
 extern crate serde_json;
@@ -69,18 +77,23 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
      * Build a description of the API.
      */
     let mut api = ApiDescription::new();
+    api.register(api_apply).unwrap();
     api.register(api_get_applicants).unwrap();
     api.register(api_get_auth_users).unwrap();
     api.register(api_get_buildings).unwrap();
     api.register(api_get_conference_rooms).unwrap();
+    api.register(api_get_engineering_metrics).unwrap();
     api.register(api_get_github_repos).unwrap();
     api.register(api_get_groups).unwrap();
     api.register(api_get_journal_club_meetings).unwrap();
     api.register(api_get_links).unwrap();
     api.register(api_get_mailing_list_subscribers).unwrap();
+    api.register(api_get_profiles).unwrap();
+    api.register(api_get_rfd_references).unwrap();
     api.register(api_get_rfds).unwrap();
     api.register(api_get_schema).unwrap();
     api.register(api_get_users).unwrap();
+    api.register(api_submit_feedback).unwrap();
 
     // Print the OpenAPI Spec to stdout.
     let api_file = "openapi-cio.json";
@@ -138,12 +151,20 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     Ok(())
 }
 
+/// How many applications we take from a single client an hour.
+const APPLY_RATE_LIMIT: usize = 5;
+
 /**
  * Application-specific context (state shared by handler functions)
  */
 struct Context {
+    /// The primary database, for the endpoints that write.
     db: Database,
+    /// The read replica, for the endpoints that only read, see `Database::read_only`.
+    read_db: Database,
     schema: openapiv3::OpenAPI,
+    /// Limits the submissions of the application form from each client.
+    apply_limiter: RateLimiter,
 }
 
 impl Context {
@@ -151,7 +172,13 @@ impl Context {
      * Return a new Context.
      */
     pub async fn new(schema: openapiv3::OpenAPI) -> Arc<Context> {
-        let api_context = Context { schema, db: Database::new() };
+        let db = Database::new();
+        let api_context = Context {
+            schema,
+            read_db: db.read_only(),
+            db,
+            apply_limiter: RateLimiter::new(APPLY_RATE_LIMIT, Duration::hours(1)),
+        };
 
         Arc::new(api_context)
     }
@@ -198,11 +225,49 @@ async fn api_get_schema(rqctx: Arc<RequestContext>) -> Result<Response<Body>, Ht
 #[inline]
 async fn api_get_auth_users(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<AuthUser>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(AuthUsers::get_from_db(db).0))
 }
 
+/**
+ * Submit an application for one of our open roles from the form on the careers page.
+ * This is public, so submissions are rate limited and need to pass a captcha.
+ */
+#[endpoint {
+    method = POST,
+    path = "/apply",
+}]
+#[instrument(skip(body_param))]
+#[inline]
+async fn api_apply(rqctx: Arc<RequestContext>, body_param: TypedBody<ApplicationSubmission>) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // We are behind a load balancer, see `client_ip` for why this is the last address.
+    let req = rqctx.request.lock().await;
+    let remote_ip = client_ip(req.headers().get("X-Forwarded-For").and_then(|h| h.to_str().ok()).unwrap_or_default());
+    drop(req);
+
+    if !api_context.apply_limiter.check(&remote_ip, Utc::now()) {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::TOO_MANY_REQUESTS,
+            "too many applications, please try again later".to_string(),
+        ));
+    }
+
+    let submission = body_param.into_inner();
+    verify_hcaptcha(&submission.hcaptcha_token, &remote_ip).await.map_err(|e| HttpError::for_bad_request(None, e))?;
+
+    let applicant = submit_application(db, &submission).await.map_err(|e| match e {
+        ApplicationError::Invalid(e) => HttpError::for_bad_request(None, e),
+        ApplicationError::Failed(e) => HttpError::for_internal_error(e),
+    })?;
+
+    Ok(HttpResponseAccepted(format!("thank you for applying for {}", applicant.role)))
+}
+
 /**
  * Fetch all applicants.
  */
@@ -214,7 +279,7 @@ async fn api_get_auth_users(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk
 #[inline]
 async fn api_get_applicants(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<Applicant>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(Applicants::get_from_db(db).0))
 }
@@ -230,7 +295,7 @@ async fn api_get_applicants(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk
 #[inline]
 async fn api_get_buildings(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<Building>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(Buildings::get_from_db(db).0))
 }
@@ -245,7 +310,7 @@ async fn api_get_buildings(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<
 #[inline]
 async fn api_get_conference_rooms(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<ConferenceRoom>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(ConferenceRooms::get_from_db(db).0))
 }
@@ -261,7 +326,7 @@ async fn api_get_conference_rooms(rqctx: Arc<RequestContext>) -> Result<HttpResp
 #[inline]
 async fn api_get_github_repos(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<GithubRepo>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(GithubRepos::get_from_db(db).0))
 }
@@ -277,7 +342,7 @@ async fn api_get_github_repos(rqctx: Arc<RequestContext>) -> Result<HttpResponse
 #[inline]
 async fn api_get_groups(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<Group>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(Groups::get_from_db(db).0))
 }
@@ -293,7 +358,7 @@ async fn api_get_groups(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec
 #[inline]
 async fn api_get_journal_club_meetings(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<JournalClubMeeting>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(JournalClubMeetings::get_from_db(db).0))
 }
@@ -309,7 +374,7 @@ async fn api_get_journal_club_meetings(rqctx: Arc<RequestContext>) -> Result<Htt
 #[inline]
 async fn api_get_links(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<Link>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(Links::get_from_db(db).0))
 }
@@ -325,11 +390,27 @@ async fn api_get_links(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<
 #[inline]
 async fn api_get_mailing_list_subscribers(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<MailingListSubscriber>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(MailingListSubscribers::get_from_db(db).0))
 }
 
+/**
+ * Fetch the RFDs each RFD references and is referenced by.
+ */
+#[endpoint {
+    method = GET,
+    path = "/rfd_references",
+}]
+#[instrument]
+#[inline]
+async fn api_get_rfd_references(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<RFDReferences>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.read_db;
+
+    Ok(HttpResponseOk(get_rfd_references(db).values().cloned().collect()))
+}
+
 /**
  * Fetch all RFDs.
  */
@@ -341,11 +422,91 @@ async fn api_get_mailing_list_subscribers(rqctx: Arc<RequestContext>) -> Result<
 #[inline]
 async fn api_get_rfds(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<RFD>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(RFDs::get_from_db(db).0))
 }
 
+/**
+ * Fetch the weekly engineering metrics for each team, for the trend charts on the
+ * dashboard.
+ */
+#[endpoint {
+    method = GET,
+    path = "/engineering_metrics",
+}]
+#[instrument]
+#[inline]
+async fn api_get_engineering_metrics(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<EngineeringMetric>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.read_db;
+
+    let mut metrics = EngineeringMetrics::get_from_db(db).0;
+    metrics.sort_by(|a, b| a.team.cmp(&b.team).then(a.week.cmp(&b.week)));
+
+    Ok(HttpResponseOk(metrics))
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct FeedbackSubmission {
+    /// One of the feedback topics, `other` if it is empty.
+    #[serde(default)]
+    topic: String,
+    feedback: String,
+}
+
+/**
+ * Send anonymous feedback to leadership. Who sent it is not saved, the email from our
+ * login proxy is only used to limit how much feedback one person can send a day.
+ */
+#[endpoint {
+    method = POST,
+    path = "/feedback",
+}]
+#[instrument(skip(body_param))]
+#[inline]
+async fn api_submit_feedback(rqctx: Arc<RequestContext>, body_param: TypedBody<FeedbackSubmission>) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    let req = rqctx.request.lock().await;
+    let sender = req.headers().get("X-Forwarded-Email").and_then(|h| h.to_str().ok()).unwrap_or_default().to_string();
+    drop(req);
+
+    let submission = body_param.into_inner();
+    submit_feedback(db, &sender, &submission.topic, &submission.feedback)
+        .await
+        .map_err(|e| HttpError::for_bad_request(None, e))?;
+
+    Ok(HttpResponseAccepted("thank you for the feedback".to_string()))
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct ProfileSearchParams {
+    /// A skill or interest to search for, ie. `kubernetes`.
+    q: Option<String>,
+}
+
+/**
+ * Fetch the skills and interests people shared, or search them with `?q=kubernetes`.
+ */
+#[endpoint {
+    method = GET,
+    path = "/profiles",
+}]
+#[instrument(skip(query_args))]
+#[inline]
+async fn api_get_profiles(rqctx: Arc<RequestContext>, query_args: Query<ProfileSearchParams>) -> Result<HttpResponseOk<Vec<NewProfile>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.read_db;
+
+    let profiles: Vec<NewProfile> = Profiles::get_from_db(db).into_iter().map(|p| p.into()).collect();
+    match query_args.into_inner().q {
+        Some(q) => Ok(HttpResponseOk(search_profiles(profiles, &q).into_iter().map(|m| m.profile).collect())),
+        None => Ok(HttpResponseOk(profiles)),
+    }
+}
+
 /**
  * Fetch a list of employees.
  */
@@ -357,7 +518,7 @@ async fn api_get_rfds(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<R
 #[inline]
 async fn api_get_users(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Vec<User>>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
-    let db = &api_context.db;
+    let db = &api_context.read_db;
 
     Ok(HttpResponseOk(Users::get_from_db(db).0))
 }
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::schema::maintenance_windows;
+
+/// A window of time we are doing maintenance in, keyed by its name in the `maintenance`
+/// section of our configs. The scheduler pauses the affected jobs during the window and
+/// runs them once it ends, and low priority notifications are dropped rather than posted.
+///
+/// ```toml
+/// [maintenance.okta_migration]
+/// reason = "Moving our Okta org"
+/// start = "2021-05-08T16:00:00Z"
+/// end = "2021-05-08T20:00:00Z"
+/// jobs = ["configs", "password_manager"]
+/// ```
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct MaintenanceWindowConfig {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reason: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// The jobs to pause during the window, every job if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jobs: Vec<String>,
+    /// Keep posting low priority notifications during the window, for windows that
+    /// only pause a job or two.
+    #[serde(default)]
+    pub keep_notifications: bool,
+}
+
+impl MaintenanceWindowConfig {
+    /// Returns if the window is going on at the given time.
+    #[instrument]
+    #[inline]
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.start <= now && now < self.end
+    }
+
+    /// Returns if the window pauses a job.
+    #[instrument]
+    #[inline]
+    pub fn pauses(&self, job: &str) -> bool {
+        self.jobs.is_empty() || self.jobs.iter().any(|j| j == job)
+    }
+}
+
+/// Get the name of the maintenance window pausing a job at the given time, if any.
+#[instrument(skip(windows))]
+#[inline]
+pub fn paused_by<'a>(windows: &'a BTreeMap<String, MaintenanceWindowConfig>, job: &str, now: DateTime<Utc>) -> Option<&'a str> {
+    windows.iter().find(|(_, w)| w.is_active(now) && w.pauses(job)).map(|(name, _)| name.as_str())
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "maintenance_windows"]
+pub struct NewMaintenanceWindow {
+    pub name: String,
+    pub reason: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub jobs: Vec<String>,
+    pub keep_notifications: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct MaintenanceWindow {
+    pub id: i32,
+    pub name: String,
+    pub reason: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub jobs: Vec<String>,
+    pub keep_notifications: bool,
+}
+
+/// Replace the maintenance windows in our database with the ones in our configs, so
+/// webhooky knows about them as well as the scheduler.
+#[instrument(skip(db))]
+#[inline]
+pub fn sync_maintenance_windows(db: &Database, windows: &BTreeMap<String, MaintenanceWindowConfig>) {
+    let rows: Vec<NewMaintenanceWindow> = windows
+        .iter()
+        .map(|(name, w)| NewMaintenanceWindow {
+            name: name.to_string(),
+            reason: w.reason.to_string(),
+            starts_at: w.start,
+            ends_at: w.end,
+            jobs: w.jobs.clone(),
+            keep_notifications: w.keep_notifications,
+        })
+        .collect();
+
+    let conn = db.conn();
+    diesel::delete(maintenance_windows::table)
+        .execute(&conn)
+        .unwrap_or_else(|e| panic!("deleting the maintenance windows failed: {}", e));
+    diesel::insert_into(maintenance_windows::table)
+        .values(&rows)
+        .execute(&conn)
+        .unwrap_or_else(|e| panic!("creating the maintenance windows failed: {}", e));
+}
+
+/// Returns if low priority notifications are dropped right now because of a
+/// maintenance window.
+#[instrument(skip(db))]
+#[inline]
+pub fn notifications_suppressed(db: &Database, now: DateTime<Utc>) -> bool {
+    let count: i64 = maintenance_windows::table
+        .filter(maintenance_windows::starts_at.le(now))
+        .filter(maintenance_windows::ends_at.gt(now))
+        .filter(maintenance_windows::keep_notifications.eq(false))
+        .count()
+        .get_result(&db.conn())
+        .unwrap_or_else(|e| panic!("counting the active maintenance windows failed: {}", e));
+
+    count > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::maintenance::{paused_by, MaintenanceWindowConfig};
+
+    #[test]
+    fn test_maintenance_windows() {
+        let windows: BTreeMap<String, MaintenanceWindowConfig> = toml::from_str(
+            r#"[okta_migration]
+start = "2021-05-08T16:00:00Z"
+end = "2021-05-08T20:00:00Z"
+jobs = ["configs", "password_manager"]
+
+[db_upgrade]
+start = "2021-05-09T02:00:00Z"
+end = "2021-05-09T03:00:00Z"
+"#,
+        )
+        .unwrap();
+
+        let during = Utc.ymd(2021, 5, 8).and_hms(17, 0, 0);
+        assert_eq!(paused_by(&windows, "configs", during), Some("okta_migration"));
+        assert_eq!(paused_by(&windows, "rfds", during), None);
+        assert_eq!(paused_by(&windows, "configs", Utc.ymd(2021, 5, 8).and_hms(20, 0, 0)), None);
+        assert_eq!(paused_by(&windows, "configs", Utc.ymd(2021, 5, 8).and_hms(15, 59, 59)), None);
+
+        // Windows without jobs pause every job.
+        assert_eq!(paused_by(&windows, "rfds", Utc.ymd(2021, 5, 9).and_hms(2, 30, 0)), Some("db_upgrade"));
+        assert!(!windows["db_upgrade"].keep_notifications);
+    }
+}
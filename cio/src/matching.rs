@@ -0,0 +1,357 @@
+#![allow(clippy::from_over_into)]
+use std::cmp::{max, min};
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_MATCH_REVIEWS_TABLE};
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::schema::match_reviews;
+
+/// Words that say what kind of company something is rather than which one, and the
+/// noise card processors add to merchant names.
+static NAME_STOPWORDS: &[&str] = &["co", "com", "corp", "corporation", "company", "gmbh", "inc", "io", "llc", "ltd", "the", "us", "www"];
+
+/// The status of a match review before someone has looked at it.
+pub const MATCH_REVIEW_PENDING: &str = "pending";
+/// The status of a match review someone said is the same thing.
+pub const MATCH_REVIEW_ACCEPTED: &str = "accepted";
+/// The status of a match review someone said is not the same thing.
+pub const MATCH_REVIEW_REJECTED: &str = "rejected";
+
+/// The scores at which we trust a match. Scores are from 0 to 1, where 1 is the same
+/// name once normalized.
+#[derive(Debug, Clone, Copy, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct MatchThresholds {
+    /// Matches scoring at least this are used without asking anyone.
+    pub accept: f64,
+    /// Matches scoring at least this, but less than `accept`, are queued for someone
+    /// to review. Anything lower is not a match.
+    pub review: f64,
+}
+
+impl Default for MatchThresholds {
+    fn default() -> Self {
+        MatchThresholds { accept: 0.93, review: 0.82 }
+    }
+}
+
+/// The best match for a name from a list of candidates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchOutcome {
+    /// The candidate scored at least the `accept` threshold.
+    Matched {
+        candidate: String,
+        score: f64,
+    },
+    /// The candidate scored between the `review` and `accept` thresholds.
+    NeedsReview {
+        candidate: String,
+        score: f64,
+    },
+    NoMatch,
+}
+
+/// Normalize a name for matching: lowercase it, split it into words on anything that
+/// is not a letter or number, and drop the words in `NAME_STOPWORDS` and any that are
+/// only numbers, ie. `SQ *ZOOM.US 888-799-9666` becomes `sq zoom`.
+#[instrument]
+#[inline]
+pub fn normalize_name(name: &str) -> String {
+    let lower = name.to_lowercase();
+    let words: Vec<&str> = lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+
+    let kept: Vec<&str> = words.iter().filter(|w| !NAME_STOPWORDS.contains(w) && !w.chars().all(|c| c.is_numeric())).copied().collect();
+    if kept.is_empty() {
+        // Keep something to match on for names that are only stopwords, ie. `The Co`.
+        return words.join(" ");
+    }
+
+    kept.join(" ")
+}
+
+/// Get a name to match on from an email address, ie. `jess.frazelle@example.com`
+/// becomes `jess frazelle`. Anything after a `+` in the address is dropped.
+#[instrument]
+#[inline]
+pub fn name_from_email(email: &str) -> String {
+    let local = email.split('@').next().unwrap_or_default();
+    let local = local.split('+').next().unwrap_or_default();
+    normalize_name(&local.replace(|c: char| c == '.' || c == '_' || c == '-', " "))
+}
+
+/// The Jaro-Winkler similarity of two strings, from 0 to 1. This favors strings that
+/// start the same way, which suits names where the end is often a suffix.
+#[instrument]
+#[inline]
+pub fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    // Characters only match if they are within this distance of each other.
+    let window = (max(a.len(), b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0;
+    for (i, ca) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = min(i + window + 1, b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == *ca {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+    if matches == 0 {
+        return 0.0;
+    }
+
+    // Count the matched characters that are out of order.
+    let a_seq = a.iter().zip(a_matched.iter()).filter(|(_, m)| **m).map(|(c, _)| c);
+    let b_seq = b.iter().zip(b_matched.iter()).filter(|(_, m)| **m).map(|(c, _)| c);
+    let transpositions = a_seq.zip(b_seq).filter(|(x, y)| x != y).count() / 2;
+
+    let m = matches as f64;
+    let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - transpositions as f64) / m) / 3.0;
+
+    let prefix = a.iter().zip(b.iter()).take(4).take_while(|(x, y)| x == y).count();
+    jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+}
+
+/// Score two names by their words rather than their order, so `Zoom Video` and
+/// `Video Communications Zoom` still score well. The words both names share are compared
+/// with the shared words plus the rest of each name, and the best score is used.
+#[instrument]
+#[inline]
+pub fn token_set_score(a: &str, b: &str) -> f64 {
+    let a: BTreeSet<&str> = a.split_whitespace().collect();
+    let b: BTreeSet<&str> = b.split_whitespace().collect();
+
+    let shared = a.intersection(&b).copied().collect::<Vec<&str>>().join(" ");
+    let with_a = format!("{} {}", shared, a.difference(&b).copied().collect::<Vec<&str>>().join(" ")).trim().to_string();
+    let with_b = format!("{} {}", shared, b.difference(&a).copied().collect::<Vec<&str>>().join(" ")).trim().to_string();
+
+    let mut score = jaro_winkler(&with_a, &with_b);
+    if !shared.is_empty() {
+        score = score.max(jaro_winkler(&shared, &with_a)).max(jaro_winkler(&shared, &with_b));
+    }
+
+    score
+}
+
+/// Score how alike two names are from 0 to 1, once they are normalized.
+#[instrument]
+#[inline]
+pub fn match_score(a: &str, b: &str) -> f64 {
+    let a = normalize_name(a);
+    let b = normalize_name(b);
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    jaro_winkler(&a, &b).max(token_set_score(&a, &b))
+}
+
+/// Find the candidate most like the name. If more than one candidate has the best
+/// score, the first one wins.
+#[instrument(skip(candidates))]
+#[inline]
+pub fn best_match(name: &str, candidates: &[String], thresholds: &MatchThresholds) -> MatchOutcome {
+    let mut best: Option<(&String, f64)> = None;
+    for c in candidates {
+        let score = match_score(name, c);
+        if best.map(|(_, s)| score > s).unwrap_or(true) {
+            best = Some((c, score));
+        }
+    }
+
+    match best {
+        Some((c, score)) if score >= thresholds.accept => MatchOutcome::Matched { candidate: c.to_string(), score },
+        Some((c, score)) if score >= thresholds.review => MatchOutcome::NeedsReview { candidate: c.to_string(), score },
+        _ => MatchOutcome::NoMatch,
+    }
+}
+
+/// The data type for a match we were not sure about, for someone to accept or reject
+/// in Airtable.
+#[db {
+    new_struct_name = "MatchReview",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_MATCH_REVIEWS_TABLE",
+    source = "matching",
+    match_on = {
+        "kind" = "String",
+        "name" = "String",
+        "candidate" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "match_reviews"]
+pub struct NewMatchReview {
+    /// What we are matching, ie. `vendor_merchant` or `applicant_email`.
+    pub kind: String,
+    /// The name we were looking for a match for.
+    pub name: String,
+    /// The best match we found for it.
+    pub candidate: String,
+    #[serde(default)]
+    pub score: f32,
+    /// One of `pending`, `accepted`, or `rejected`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+}
+
+/// Implement updating the Airtable record for a MatchReview.
+#[async_trait]
+impl UpdateAirtableRecord<MatchReview> for MatchReview {
+    async fn update_airtable_record(&mut self, _record: MatchReview) {}
+}
+
+/// Find the candidate that matches a name. Matches someone accepted are used and
+/// matches someone rejected are skipped. A new match we are not sure about is queued
+/// for review in Airtable and not used until someone accepts it.
+#[instrument(skip(db, candidates))]
+#[inline]
+pub async fn resolve_match(db: &Database, kind: &str, name: &str, candidates: &[String], thresholds: &MatchThresholds) -> Option<String> {
+    let reviews = match_reviews::dsl::match_reviews
+        .filter(match_reviews::dsl::kind.eq(kind.to_string()))
+        .filter(match_reviews::dsl::name.eq(name.to_string()))
+        .load::<MatchReview>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the match reviews for {} `{}` failed: {}", kind, name, e));
+
+    if let Some(r) = reviews.iter().find(|r| r.status == MATCH_REVIEW_ACCEPTED && candidates.contains(&r.candidate)) {
+        return Some(r.candidate.to_string());
+    }
+
+    let candidates: Vec<String> = candidates
+        .iter()
+        .filter(|c| !reviews.iter().any(|r| r.status == MATCH_REVIEW_REJECTED && r.candidate == **c))
+        .cloned()
+        .collect();
+    match best_match(name, &candidates, thresholds) {
+        MatchOutcome::Matched { candidate, .. } => Some(candidate),
+        MatchOutcome::NeedsReview { candidate, score } => {
+            if !reviews.iter().any(|r| r.candidate == candidate) {
+                println!("[matching] queued {} `{}` ~ `{}` ({:.2}) for review", kind, name, candidate, score);
+                NewMatchReview {
+                    kind: kind.to_string(),
+                    name: name.to_string(),
+                    candidate,
+                    score: score as f32,
+                    status: MATCH_REVIEW_PENDING.to_string(),
+                }
+                .upsert(db)
+                .await;
+            }
+
+            None
+        }
+        MatchOutcome::NoMatch => None,
+    }
+}
+
+/// Returns if a match for the name is waiting on someone to review it.
+#[instrument(skip(db))]
+#[inline]
+pub fn has_pending_match_review(db: &Database, kind: &str, name: &str) -> bool {
+    let count: i64 = match_reviews::dsl::match_reviews
+        .filter(match_reviews::dsl::kind.eq(kind.to_string()))
+        .filter(match_reviews::dsl::name.eq(name.to_string()))
+        .filter(match_reviews::dsl::status.eq(MATCH_REVIEW_PENDING.to_string()))
+        .count()
+        .get_result(&db.conn())
+        .unwrap_or_else(|e| panic!("counting the pending match reviews for {} `{}` failed: {}", kind, name, e));
+
+    count > 0
+}
+
+/// Sync the decisions on match reviews from Airtable into the database.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_match_reviews(db: &Database) {
+    let results = MatchReview::list_airtable_records().await;
+    for record in results {
+        let review: NewMatchReview = record.fields.into();
+
+        let mut db_review = review.upsert_in_db(db);
+        if db_review.airtable_record_id.is_empty() {
+            db_review.airtable_record_id = record.id;
+        }
+        db_review.update(db).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::matching::{best_match, jaro_winkler, match_score, name_from_email, normalize_name, refresh_match_reviews, token_set_score, MatchOutcome, MatchThresholds};
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("SQ *ZOOM.US 888-799-9666"), "sq zoom");
+        assert_eq!(normalize_name("Slack Technologies, Inc."), "slack technologies");
+        assert_eq!(normalize_name("The Co"), "the co");
+        assert_eq!(name_from_email("Jess.Frazelle+jobs@example.com"), "jess frazelle");
+    }
+
+    #[test]
+    fn test_jaro_winkler() {
+        // The examples from the original paper by Winkler.
+        assert!((jaro_winkler("martha", "marhta") - 0.961).abs() < 0.001);
+        assert!((jaro_winkler("dwayne", "duane") - 0.84).abs() < 0.001);
+        assert!((jaro_winkler("dixon", "dicksonx") - 0.813).abs() < 0.001);
+        assert_eq!(jaro_winkler("okta", "okta"), 1.0);
+        assert_eq!(jaro_winkler("abc", "xyz"), 0.0);
+        assert_eq!(jaro_winkler("", "okta"), 0.0);
+    }
+
+    #[test]
+    fn test_token_set_score() {
+        assert_eq!(token_set_score("zoom video", "video zoom"), 1.0);
+        assert_eq!(token_set_score("zoom", "zoom video communications"), 1.0);
+        assert!(token_set_score("google cloud", "amazon web services") < 0.6);
+    }
+
+    #[test]
+    fn test_best_match() {
+        let vendors = vec!["Zoom".to_string(), "Slack".to_string(), "Google Workspace".to_string()];
+        let thresholds = MatchThresholds::default();
+
+        assert_eq!(
+            best_match("ZOOM.US 888-799-9666", &vendors, &thresholds),
+            MatchOutcome::Matched {
+                candidate: "Zoom".to_string(),
+                score: 1.0
+            }
+        );
+        match best_match("Slick", &vendors, &thresholds) {
+            MatchOutcome::NeedsReview { candidate, .. } => assert_eq!(candidate, "Slack"),
+            outcome => panic!("expected a review, got {:?}", outcome),
+        }
+        assert_eq!(best_match("Gusto", &vendors, &thresholds), MatchOutcome::NoMatch);
+        assert_eq!(best_match("Gusto", &[], &thresholds), MatchOutcome::NoMatch);
+        assert!(match_score("", "Zoom") == 0.0);
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_match_reviews() {
+        let db = Database::new();
+        refresh_match_reviews(&db).await;
+    }
+}
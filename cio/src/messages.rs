@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+use tera::{Context, Tera};
+use tracing::instrument;
+
+/// The default template for each type of event we post a message about. The templates
+/// are [Tera](https://tera.netlify.app/docs/) templates that render Slack mrkdwn.
+pub static DEFAULT_MESSAGE_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "actions_usage.spikes",
+        "The following repositories had a spike in GitHub Actions usage week over week:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    ("api_tokens.expiring", "The following API tokens need to be rotated:\n{{ lines | join(sep=\"\n\") }}"),
+    (
+        "api_tokens.rotate",
+        ":key: Please rotate the *{{ name }}* {{ service }} token{% if env_var %} in `{{ env_var }}`{% endif %}, it {{ when }}. Update `expires` in the `tokens` section of our configs once you have.",
+    ),
+    ("applicant.new", "*{{ name }}*  <mailto:{{ email }}|{{ email }}>{% if location %}  {{ location }}{% endif %}"),
+    (
+        "background_checks.completed",
+        "The background check for *{{ name }}* ({{ role }}) is complete: *{{ result }}*. <{{ url }}|View the report in Checkr>",
+    ),
+    (
+        "background_checks.no_hiring_manager",
+        "The background check for *{{ name }}* is complete, but {{ role }} has no hiring manager to tell the result to. Set `hiring_manager` on the role in our configs.",
+    ),
+    (
+        "buckets.newly_public",
+        ":rotating_light: The following buckets became readable by anyone on the internet:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    ("cloud_costs.over_budget", "Cloud spend this month:\n{{ lines | join(sep=\"\n\") }}"),
+    (
+        "cohorts.report",
+        "*{{ title }}* has {{ count }} people: {{ interviewing }} interviewing, {{ offers }} offers out, and {{ accepted }} accepted.{% if lines %} Start dates:\n{{ lines | join(sep=\"\n\") }}{% endif %}",
+    ),
+    (
+        "docusign.status",
+        "The {{ template }} for *{{ name }}* <mailto:{{ email }}|{{ email }}> was {{ status }}.",
+    ),
+    (
+        "drive.over_shared",
+        "The following files in sensitive folders are shared with anyone with the link:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "engineering_metrics.report",
+        "Engineering trends for the last {{ weeks }} weeks, per team:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "events.registration_milestone",
+        ":tada: *{{ event }}* passed {{ milestone }} registrations, {{ count }} people have signed up so far{% if url %}  <{{ url }}|event page>{% endif %}",
+    ),
+    ("forms.request", "*{{ title }}* from {{ email }}:\n{{ lines | join(sep=\"\n\") }}"),
+    (
+        "hiring.weekly",
+        "{{ new_applicants }} new applications {{ period }}, {{ interviewing }} people are interviewing, and we are {{ headcount }} people.",
+    ),
+    (
+        "licenses.new_findings",
+        "Newly introduced dependencies with copyleft or unknown licenses:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "lunch.ordered",
+        "Got it, you are having *{{ name }}* for lunch on {{ when }}. Pick something else from the menu to change it.",
+    ),
+    (
+        "lunch.orders",
+        ":fork_and_knife: We are ordering lunch for the office on {{ when }}. Pick what you would like from the menu by {{ deadline }}.",
+    ),
+    (
+        "lunch.sent",
+        "Sent the lunch order for {{ when }} to the caterer, {{ count }} people for {{ total }}:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    ("mailing_list.new_subscriber", "*{{ name }}* <mailto:{{ email }}|{{ email }}>"),
+    (
+        "oauth_grants.high_risk",
+        ":rotating_light: The following third-party apps were newly granted access to all of someone's mail or files:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "office.summary",
+        "{{ count }} people are in the office today:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "policies.acknowledge",
+        ":page_facing_up: Please read the <{{ url }}|{{ title }}> and react to this message with :+1: to acknowledge that you have.",
+    ),
+    ("posture.violations", "The following people's accounts do not meet our security policy:\n{{ lines | join(sep=\"\n\") }}"),
+    (
+        "referrals.report",
+        "We hired {{ referred_hired }} of the {{ referred }} people referred to us ({{ referred_rate }}) and {{ direct_hired }} of the {{ direct }} people who applied directly ({{ direct_rate }}).{% if lines %} Outstanding referral bonuses:\n{{ lines | join(sep=\"\n\") }}{% else %} There are no outstanding referral bonuses.{% endif %}",
+    ),
+    (
+        "repo_owners.missing",
+        "The following repositories need an owner, add them to `configs/repos.toml`:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "reviews.closed",
+        "The *{{ title }}* review cycle is closed and its {{ count }} reviews are archived in {{ url }}{% if lines %}\nThese reviews were never written:\n{{ lines | join(sep=\"\n\") }}{% endif %}",
+    ),
+    (
+        "reviews.escalation",
+        "The *{{ title }}* review cycle closes {{ when }} and *{{ name }}* still has these reviews to write:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "reviews.reminder",
+        "The *{{ title }}* review cycle closes {{ when }}, you still have these reviews to write:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    ("rfds.missing_references", "The following RFDs reference RFDs that do not exist:\n{{ lines | join(sep=\"\n\") }}"),
+    (
+        "service_account_keys.rotation_failed",
+        ":rotating_light: The following service account keys could not be checked or rotated:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    ("services.recovered", ":white_check_mark: *{{ service }}* is back, the jobs that depend on it are running again."),
+    (
+        "services.unavailable",
+        ":rotating_light: *{{ service }}* failed {{ count }} jobs in a row, the jobs that depend on it are skipped until it recovers. The last error was: {{ error }}",
+    ),
+    (
+        "slack_user_groups.removed",
+        "Removed the following people from Slack user groups since they are not in the matching group in our configs:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "take_homes.closed",
+        "The {{ title }} take-home of *{{ name }}* ({{ role }}) is closed, they {{ status }}: {{ url }}{% if lines %}\nIt is ready for grading by:\n{{ lines | join(sep=\"\n\") }}{% endif %}",
+    ),
+    (
+        "tailscale.offboarded",
+        "Removed the following devices and auth keys of people who were offboarded from the tailnet:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "tailscale.unknown_devices",
+        ":rotating_light: The following devices on the tailnet do not belong to anyone in our directory:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "trainings.escalation",
+        "*{{ name }}* has not taken these required trainings:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "trainings.reminder",
+        "Please take these required trainings:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    ("vendors.okta_apps", "The apps in Okta do not match our software vendors:\n{{ lines | join(sep=\"\n\") }}"),
+    (
+        "vendors.security_review",
+        "The following vendors store sensitive data and need a security review:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    ("visitors.arrived", "Your visitor *{{ name }}* is at the front desk ({{ status }})."),
+    (
+        "visitors.expected",
+        "{{ count }} visitor(s) are expected at the office today:\n{{ lines | join(sep=\"\n\") }}",
+    ),
+    (
+        "welcome.check_in",
+        "Hi {{ first_name }}, you have been here a week now! How is it going? If anything has been confusing or you are missing access to something, let your manager know and we will sort it out.{% if lines %} In case they help:\n{{ lines | join(sep=\"\n\") }}{% endif %}",
+    ),
+    (
+        "welcome.tools",
+        "Hi {{ first_name }}, here are the tools we use every day and where to find them:{% if lines %}\n{{ lines | join(sep=\"\n\") }}{% endif %}",
+    ),
+    (
+        "welcome.welcome",
+        ":wave: Welcome, {{ first_name }}! We are glad you are here.{% if lines %} A few places to start:\n{{ lines | join(sep=\"\n\") }}{% endif %}",
+    ),
+];
+
+/// The data type for our `messages.toml` file which overrides the wording of the
+/// messages we post for a deployment, and the emoji reactions that act on them.
+///
+/// ```toml
+/// [templates]
+/// "applicant.new" = ":wave: *{{ name }}* applied for {{ role }}  <mailto:{{ email }}|{{ email }}>"
+///
+/// [reactions]
+/// eyes = "claim"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize)]
+pub struct MessagesConfig {
+    /// The template for each event type, keyed by the event types in `DEFAULT_MESSAGE_TEMPLATES`.
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+    /// The action for each emoji, one of `advance`, `approve`, `claim`, `reject`, or
+    /// `resolve`. Setting any replaces all of `DEFAULT_REACTIONS` in the `reactions` module.
+    #[serde(default)]
+    pub reactions: BTreeMap<String, String>,
+}
+
+impl MessagesConfig {
+    /// Read and decode the messages config from a file. A file that does not exist is
+    /// the same as an empty config, so every message uses its default template.
+    #[instrument]
+    #[inline]
+    pub fn read(file: &str) -> Result<Self, String> {
+        let body = match fs::read_to_string(file) {
+            Ok(b) => b,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+            Err(e) => return Err(format!("reading {} failed: {}", file, e)),
+        };
+
+        toml::from_str(&body).map_err(|e| format!("decoding {} failed: {}", file, e))
+    }
+}
+
+/// Get the path of the messages config from the `CIO_MESSAGES_CONFIG` environment
+/// variable. Defaults to `messages.toml`.
+#[instrument]
+#[inline]
+pub fn messages_config_file() -> String {
+    let file = env::var("CIO_MESSAGES_CONFIG").unwrap_or_default();
+    if file.is_empty() {
+        return "messages.toml".to_string();
+    }
+
+    file
+}
+
+/// The templates for our messages, with any overrides for the deployment applied
+/// on top of the defaults.
+#[derive(Debug)]
+pub struct MessageTemplates {
+    tera: Tera,
+}
+
+impl MessageTemplates {
+    /// Create the templates from the defaults and the overrides. This fails if an override is
+    /// for an event type we do not have, since that is almost always a typo, or does not parse.
+    #[instrument]
+    #[inline]
+    pub fn new(overrides: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut tera = Tera::default();
+        for (event, template) in DEFAULT_MESSAGE_TEMPLATES {
+            tera.add_raw_template(event, template)
+                .map_err(|e| format!("parsing the default template for {} failed: {:?}", event, e))?;
+        }
+
+        for (event, template) in overrides {
+            if !DEFAULT_MESSAGE_TEMPLATES.iter().any(|(e, _)| e == event) {
+                return Err(format!("{} is not an event type we post messages for", event));
+            }
+
+            tera.add_raw_template(event, template).map_err(|e| format!("parsing the template for {} failed: {:?}", event, e))?;
+        }
+
+        Ok(MessageTemplates { tera })
+    }
+
+    /// Load the templates with the overrides from the messages config.
+    #[instrument]
+    #[inline]
+    pub fn from_config_file(file: &str) -> Result<Self, String> {
+        MessageTemplates::new(&MessagesConfig::read(file)?.templates)
+    }
+
+    /// Render the message for an event.
+    #[instrument(skip(self, context))]
+    #[inline]
+    pub fn render<T: Serialize>(&self, event: &str, context: &T) -> Result<String, String> {
+        let context = Context::from_serialize(context).map_err(|e| format!("serializing the context for {} failed: {:?}", event, e))?;
+        let text = self.tera.render(event, &context).map_err(|e| format!("rendering the template for {} failed: {:?}", event, e))?;
+
+        Ok(text.trim_end().to_string())
+    }
+}
+
+/// Render the message for an event with the templates from the messages config. The config
+/// is read every time so edits take effect without a restart. If the config is invalid or
+/// the override fails to render we fall back to the default template, since a typo in
+/// the wording should never stop the message from being sent.
+#[instrument(skip(context))]
+#[inline]
+pub fn render_message<T: Serialize>(event: &str, context: &T) -> String {
+    let file = messages_config_file();
+    match MessageTemplates::from_config_file(&file).and_then(|t| t.render(event, context)) {
+        Ok(text) => return text,
+        Err(e) => println!("[messages] using the default template for {}: {}", event, e),
+    }
+
+    MessageTemplates::new(&Default::default()).unwrap().render(event, context).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use crate::messages::{MessageTemplates, DEFAULT_MESSAGE_TEMPLATES};
+
+    #[test]
+    fn test_message_templates() {
+        let templates = MessageTemplates::new(&Default::default()).unwrap();
+        assert_eq!(
+            templates.render("applicant.new", &json!({"name": "Jane Doe", "email": "jane@example.com", "location": ""})).unwrap(),
+            "*Jane Doe*  <mailto:jane@example.com|jane@example.com>"
+        );
+        assert_eq!(
+            templates
+                .render("cloud_costs.over_budget", &json!({"lines": ["• *eng* has spent $10.00", "• *ops* has spent $5.00"]}))
+                .unwrap(),
+            "Cloud spend this month:\n• *eng* has spent $10.00\n• *ops* has spent $5.00"
+        );
+
+        let mut overrides = BTreeMap::new();
+        overrides.insert("applicant.new".to_string(), ":tada: *{{ name }}* applied for {{ role }}\n".to_string());
+        let templates = MessageTemplates::new(&overrides).unwrap();
+        assert_eq!(
+            templates.render("applicant.new", &json!({"name": "Jane Doe", "role": "Hardware Engineer"})).unwrap(),
+            ":tada: *Jane Doe* applied for Hardware Engineer"
+        );
+
+        let mut overrides = BTreeMap::new();
+        overrides.insert("applicant.neww".to_string(), "{{ name }}".to_string());
+        assert!(MessageTemplates::new(&overrides).is_err());
+
+        let mut overrides = BTreeMap::new();
+        overrides.insert("applicant.new".to_string(), "{{ name".to_string());
+        assert!(MessageTemplates::new(&overrides).is_err());
+
+        let context = json!({
+            "name": "",
+            "first_name": "",
+            "title": "",
+            "template": "",
+            "status": "",
+            "result": "",
+            "email": "",
+            "location": "",
+            "role": "",
+            "lines": [],
+            "event": "",
+            "milestone": 0,
+            "count": 0,
+            "url": "",
+            "weeks": 0,
+            "new_applicants": 0,
+            "period": "",
+            "interviewing": 0,
+            "headcount": 0,
+            "offers": 0,
+            "accepted": 0,
+            "referred": 0,
+            "referred_hired": 0,
+            "referred_rate": "",
+            "direct": 0,
+            "direct_hired": 0,
+            "direct_rate": "",
+            "service": "",
+            "env_var": "",
+            "when": "",
+            "error": "",
+            "deadline": "",
+            "total": "",
+        });
+        for (event, _) in DEFAULT_MESSAGE_TEMPLATES {
+            assert!(templates.render(event, &context).is_ok(), "{}", event);
+        }
+    }
+}
@@ -14,7 +14,6 @@ use diesel::deserialize::{self, FromSql};
 use diesel::pg::Pg;
 use diesel::serialize::{self, Output, ToSql};
 use diesel::sql_types::Jsonb;
-use google_drive::GoogleDrive;
 use hubcaps::repositories::{Repo, Repository};
 use hubcaps::Github;
 use macros::db;
@@ -25,9 +24,10 @@ use tracing::instrument;
 
 use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_BASE_ID_RACK_ROADMAP, AIRTABLE_GITHUB_REPOS_TABLE, AIRTABLE_RFD_TABLE};
 use crate::core::UpdateAirtableRecord;
-use crate::rfds::{clean_rfd_html_links, get_images_in_branch, get_rfd_contents_from_repo, parse_markdown, update_discussion_link, update_state};
+use crate::rfd_clone::RFDClone;
+use crate::rfds::{clean_rfd_html_links, get_images_in_branch, get_rfd_contents_from_repo, parse_markdown, rewrite_rfd_image_links, update_discussion_link, update_state, upload_rfd_images, RFDImage};
 use crate::schema::{github_repos, rfds as r_f_ds, rfds};
-use crate::utils::{create_or_update_file_in_github_repo, get_gsuite_token, github_org, write_file};
+use crate::utils::{create_or_update_file_in_github_repo, drive_client, get_gsuite_token, github_api_list, github_org, write_file};
 
 /// The data type for a GitHub user.
 #[derive(Debug, Default, PartialEq, Clone, JsonSchema, FromSqlRow, AsExpression, Serialize, Deserialize)]
@@ -94,6 +94,7 @@ impl ToSql<Jsonb, Pg> for GitHubUser {
     new_struct_name = "GithubRepo",
     airtable_base_id = "AIRTABLE_BASE_ID_MISC",
     airtable_table = "AIRTABLE_GITHUB_REPOS_TABLE",
+    source = "github",
     match_on = {
         "github_id" = "String",
     },
@@ -361,6 +362,7 @@ impl NewRepo {
     new_struct_name = "RFD",
     airtable_base_id = "AIRTABLE_BASE_ID_RACK_ROADMAP",
     airtable_table = "AIRTABLE_RFD_TABLE",
+    source = "github",
     match_on = {
         "number" = "i32",
     }
@@ -627,7 +629,7 @@ impl RFD {
                 // Save the image to our temporary directory.
                 let image_path = format!("{}/{}", parent, image.path.replace(&dir, "").trim_start_matches('/'));
 
-                write_file(&PathBuf::from(image_path), from_utf8(&image.content).unwrap_or_default());
+                write_file(&PathBuf::from(image_path), &image.content);
             }
         }
 
@@ -683,13 +685,27 @@ impl RFD {
             branch = r.default_branch.to_string();
         }
 
-        // Get the commits from the last seven days to the file.
-        let commits = repo.commits().list(&format!("/rfd/{}/", self.number_string), &branch, Some(since)).await.unwrap();
+        // Get the commits from the last seven days to the file. hubcaps only gets the
+        // first page of commits, so we get all of them from the API ourselves.
+        let commits: Vec<serde_json::Value> = github_api_list(&format!(
+            "/repos/{}/rfd/commits?path=rfd/{}&sha={}&since={}",
+            github_org(),
+            self.number_string,
+            branch,
+            since.format("%Y-%m-%dT%H:%M:%SZ")
+        ))
+        .await
+        .unwrap();
 
         for commit in commits {
-            let message: Vec<&str> = commit.commit.message.lines().collect();
+            let message: Vec<&str> = commit["commit"]["message"].as_str().unwrap_or_default().lines().collect();
             if !message.is_empty() {
-                changelog += &format!("\t- \"{}\" by @{}\n\t\thttps://github.com/oxidecomputer/rfd/commit/{}\n", message[0], commit.author.login, commit.sha);
+                changelog += &format!(
+                    "\t- \"{}\" by @{}\n\t\thttps://github.com/oxidecomputer/rfd/commit/{}\n",
+                    message[0],
+                    commit["author"]["login"].as_str().unwrap_or_default(),
+                    commit["sha"].as_str().unwrap_or_default()
+                );
             }
         }
 
@@ -757,7 +773,7 @@ impl RFD {
             // Save the image to our temporary directory.
             let image_path = format!("{}/{}", temp_dir_str.trim_end_matches('/'), image.path.replace(&old_dir, "").trim_start_matches('/'));
 
-            write_file(&PathBuf::from(image_path), from_utf8(&image.content).unwrap_or_default());
+            write_file(&PathBuf::from(image_path), &image.content);
         }
 
         let cmd_output = Command::new("asciidoctor-pdf")
@@ -780,7 +796,7 @@ impl RFD {
         let token = get_gsuite_token("").await;
 
         // Initialize the Google Drive client.
-        let drive_client = GoogleDrive::new(token);
+        let drive_client = drive_client(token);
 
         // Figure out where our directory is.
         // It should be in the shared drive : "Automated Documents"/"rfds"
@@ -817,28 +833,13 @@ impl RFD {
         let repo = github.repo(github_org(), "rfd");
         let r = repo.get().await.unwrap();
 
-        // Trim the title.
-        self.title = self.title.trim().to_string();
-
-        // Add leading zeros to the number for the number_string.
-        self.number_string = NewRFD::generate_number_string(self.number);
-
-        // Set the full name.
-        self.name = NewRFD::generate_name(self.number, &self.title);
+        self.expand_generated_fields();
 
-        // Set the short_link.
-        self.short_link = NewRFD::generate_short_link(self.number);
-        // Set the rendered_link.
-        self.rendered_link = NewRFD::generate_rendered_link(&self.number_string);
-
-        let mut branch = self.number_string.to_string();
-        if self.link.contains(&format!("/{}/", r.default_branch)) {
-            branch = r.default_branch.to_string();
-        }
+        let branch = self.branch(&r.default_branch);
 
         // Get the RFD contents from the branch.
         let rfd_dir = format!("/rfd/{}", self.number_string);
-        let (rfd_content, is_markdown, sha) = get_rfd_contents_from_repo(github, &branch, &rfd_dir).await;
+        let (rfd_content, is_markdown, sha, images) = get_rfd_contents_from_repo(github, &branch, &rfd_dir).await;
         self.content = rfd_content;
         self.sha = sha;
 
@@ -857,8 +858,77 @@ impl RFD {
             }
         }
 
-        // Parse the HTML.
-        self.html = self.get_html(&repo, &branch, is_markdown).await;
+        self.expand_rendered_fields(&repo, &branch, is_markdown, &images).await;
+    }
+
+    /// Expand the fields in the RFD from a clone of the rfd repo, rather than getting the
+    /// content, sha, and commit_date from the GitHub API. The commit date for RFDs on the
+    /// default branch is passed in, since it is quicker to find them for every RFD at once.
+    #[instrument(skip(github, clone))]
+    #[inline]
+    pub async fn expand_from_clone(&mut self, github: &Github, clone: &RFDClone, commit_date: Option<DateTime<Utc>>) {
+        let repo = github.repo(github_org(), "rfd");
+
+        self.expand_generated_fields();
+
+        let branch = self.branch(&clone.default_branch);
+
+        // Get the RFD contents from the branch.
+        let (rfd_content, is_markdown, sha) = clone.rfd_contents(&branch, &self.number_string).unwrap_or_default();
+        self.content = rfd_content;
+        self.sha = sha;
+
+        // Make sure the images are in the static images directory on the default branch.
+        let images = clone.rfd_images(&branch, &self.number_string);
+        for image in &images {
+            let new_path = image.path.replace("rfd/", "src/public/static/images/");
+            create_or_update_file_in_github_repo(&repo, &clone.default_branch, &new_path, image.content.to_vec()).await;
+        }
+
+        if branch == clone.default_branch {
+            if let Some(date) = commit_date {
+                self.commit_date = date;
+            }
+        } else if let Some(date) = clone.branch_date(&branch) {
+            self.commit_date = date;
+        }
+
+        self.expand_rendered_fields(&repo, &branch, is_markdown, &images).await;
+    }
+
+    /// Fill in the fields we generate from the number and title.
+    fn expand_generated_fields(&mut self) {
+        // Trim the title.
+        self.title = self.title.trim().to_string();
+
+        // Add leading zeros to the number for the number_string.
+        self.number_string = NewRFD::generate_number_string(self.number);
+
+        // Set the full name.
+        self.name = NewRFD::generate_name(self.number, &self.title);
+
+        // Set the short_link.
+        self.short_link = NewRFD::generate_short_link(self.number);
+        // Set the rendered_link.
+        self.rendered_link = NewRFD::generate_rendered_link(&self.number_string);
+    }
+
+    /// The branch the RFD is on, the default branch once it is published and otherwise
+    /// the branch named for its number.
+    pub fn branch(&self, default_branch: &str) -> String {
+        if self.link.contains(&format!("/{}/", default_branch)) {
+            return default_branch.to_string();
+        }
+
+        self.number_string.to_string()
+    }
+
+    /// Fill in the fields we render from the content.
+    async fn expand_rendered_fields(&mut self, repo: &Repository, branch: &str, is_markdown: bool, images: &[RFDImage]) {
+        // Parse the HTML, with the images it references pointing at where we uploaded them.
+        let html = self.get_html(repo, branch, is_markdown).await;
+        let image_urls = upload_rfd_images(&self.number_string, &self.content, images).await;
+        self.html = rewrite_rfd_image_links(&html, &self.number_string, &image_urls);
 
         self.authors = NewRFD::get_authors(&self.content, is_markdown);
 
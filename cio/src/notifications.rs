@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::charts::{post_charts, take_charts};
+use crate::chat::{notify_channel, slack_message_text, ChatChannel, CHAT_CHANNELS};
+use crate::db::Database;
+use crate::format::format_time;
+use crate::maintenance::notifications_suppressed;
+use crate::schema::{pending_notifications, scheduled_messages, slack_user_ids};
+use crate::slack::{lookup_slack_user_id, post_to_user_id};
+
+/// How long we trust a cached Slack user ID before looking it up again.
+pub const SLACK_USER_ID_CACHE_DAYS: i64 = 30;
+
+/// How long we wait to look up an email again when it had no Slack user, for
+/// example a new hire who has not logged in to Slack yet.
+pub const SLACK_USER_ID_MISS_CACHE_HOURS: i64 = 24;
+
+/// How soon a notification needs to be seen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationPriority {
+    /// Posted right away, ie. sync failures and security alerts.
+    Urgent,
+    /// Held and posted with the other low priority notifications for the channel
+    /// in a single digest by the `notification_digests` job. How often the digest is
+    /// sent is the schedule for the job in `sync.toml`.
+    Low,
+}
+
+/// A low priority notification waiting for the next digest.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "pending_notifications"]
+pub struct NewPendingNotification {
+    /// The name of the channel as used in environment variables, ie. `PUBLIC_RELATIONS`.
+    pub channel: String,
+    /// The event type, the same as the event types for message templates.
+    pub event: String,
+    /// The message in the Slack message format.
+    pub message: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct PendingNotification {
+    pub id: i32,
+    pub channel: String,
+    pub event: String,
+    pub message: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Post a notification to a channel. Urgent notifications are posted right away
+/// and low priority notifications are held for the next digest, or dropped during
+/// a maintenance window.
+#[instrument(skip(db, msg))]
+#[inline]
+pub async fn notify(db: &Database, priority: NotificationPriority, channel: ChatChannel, event: &str, msg: Value) {
+    if priority == NotificationPriority::Urgent {
+        let mut msg = msg;
+        let charts = take_charts(&mut msg);
+        notify_channel(channel, msg).await;
+        post_charts(channel, charts).await;
+        return;
+    }
+
+    if notifications_suppressed(db, Utc::now()) {
+        println!("[notifications] dropping {} during maintenance: {}", event, slack_message_text(&msg));
+        return;
+    }
+
+    let pending = NewPendingNotification {
+        channel: channel.env_name().to_string(),
+        event: event.to_string(),
+        message: msg,
+        created_at: Utc::now(),
+    };
+    diesel::insert_into(pending_notifications::table)
+        .values(&pending)
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("creating pending notification {:?} failed: {}", pending, e));
+}
+
+/// Coalesce the pending notifications for a channel into a single message, grouped
+/// by event type. A single notification is posted as is.
+#[instrument]
+#[inline]
+pub fn format_digest(notifications: &[PendingNotification]) -> Value {
+    if notifications.len() == 1 {
+        let mut msg = notifications[0].message.clone();
+        take_charts(&mut msg);
+        return msg;
+    }
+
+    let mut events: BTreeMap<&str, Vec<&PendingNotification>> = Default::default();
+    for n in notifications {
+        events.entry(&n.event).or_default().push(n);
+    }
+
+    let since = notifications.iter().map(|n| n.created_at).min().unwrap_or_else(Utc::now);
+    let mut text = format!("*{} notifications since {}*\n", notifications.len(), format_time(since));
+    for (event, ns) in events {
+        text += &format!("\n*{}* ({})\n", event, ns.len());
+        for n in ns {
+            text += &format!("{}\n", slack_message_text(&n.message));
+        }
+    }
+
+    json!({ "text": text.trim_end() })
+}
+
+/// Post a digest of the pending notifications to each channel.
+#[instrument(skip(db))]
+#[inline]
+pub async fn send_notification_digests(db: &Database) {
+    let pending: Vec<PendingNotification> = pending_notifications::table
+        .order_by(pending_notifications::created_at)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the pending notifications failed: {}", e));
+
+    for channel in CHAT_CHANNELS {
+        let notifications: Vec<PendingNotification> = pending.iter().filter(|n| n.channel == channel.env_name()).cloned().collect();
+        if notifications.is_empty() {
+            continue;
+        }
+
+        notify_channel(*channel, format_digest(&notifications)).await;
+        for n in &notifications {
+            post_charts(*channel, take_charts(&mut n.message.clone())).await;
+        }
+
+        // Only delete what we posted, anything queued since stays for the next digest.
+        let ids: Vec<i32> = notifications.iter().map(|n| n.id).collect();
+        diesel::delete(pending_notifications::table.filter(pending_notifications::id.eq_any(ids)))
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("deleting the posted notifications for {:?} failed: {}", channel, e));
+        println!("[notifications] posted a digest of {} notifications to {:?}", notifications.len(), channel);
+    }
+}
+
+/// The Slack user ID for an email, cached so we do not look up everyone we message
+/// every time. An empty `slack_user_id` caches that the email has no Slack user.
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[table_name = "slack_user_ids"]
+pub struct NewSlackUserId {
+    pub email: String,
+    pub slack_user_id: String,
+    pub resolved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct SlackUserId {
+    pub id: i32,
+    pub email: String,
+    pub slack_user_id: String,
+    pub resolved_at: DateTime<Utc>,
+}
+
+impl SlackUserId {
+    /// Returns if the cached ID can still be used.
+    #[instrument]
+    #[inline]
+    pub fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        let mut ttl = Duration::days(SLACK_USER_ID_CACHE_DAYS);
+        if self.slack_user_id.is_empty() {
+            ttl = Duration::hours(SLACK_USER_ID_MISS_CACHE_HOURS);
+        }
+
+        now - self.resolved_at < ttl
+    }
+}
+
+/// Resolve an email in our GSuite domain to a Slack user ID, using the cache in the
+/// database when it is fresh. Returns `None` if the email has no Slack user.
+#[instrument(skip(db))]
+#[inline]
+pub async fn get_slack_user_id(db: &Database, email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let cached: Option<SlackUserId> = slack_user_ids::table.filter(slack_user_ids::email.eq(&email)).first(&db.conn()).ok();
+    if let Some(c) = &cached {
+        if c.is_fresh(Utc::now()) {
+            return Some(c.slack_user_id.to_string()).filter(|id| !id.is_empty());
+        }
+    }
+
+    let slack_user_id = match lookup_slack_user_id(&email).await {
+        Ok(id) => id.unwrap_or_default(),
+        Err(e) => {
+            // Keep using the stale ID rather than dropping the message.
+            println!("[notifications] {}", e);
+            return cached.map(|c| c.slack_user_id).filter(|id| !id.is_empty());
+        }
+    };
+
+    let entry = NewSlackUserId {
+        email,
+        slack_user_id: slack_user_id.to_string(),
+        resolved_at: Utc::now(),
+    };
+    diesel::insert_into(slack_user_ids::table)
+        .values(&entry)
+        .on_conflict(slack_user_ids::email)
+        .do_update()
+        .set(&entry)
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("caching the slack user id for {} failed: {}", entry.email, e));
+
+    Some(slack_user_id).filter(|id| !id.is_empty())
+}
+
+/// Send a direct message in Slack to a person by their email.
+#[instrument(skip(db, msg))]
+#[inline]
+pub async fn notify_user(db: &Database, email: &str, msg: Value) {
+    let user_id = match get_slack_user_id(db, email).await {
+        Some(id) => id,
+        None => {
+            println!("[notifications] {} has no slack user, not sending them: {}", email, slack_message_text(&msg));
+            return;
+        }
+    };
+
+    if let Err(e) = post_to_user_id(&user_id, msg).await {
+        println!("[notifications] {}", e);
+    }
+}
+
+/// A direct message waiting to be sent at a time that suits the person, ie. the
+/// start of their day in their timezone.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "scheduled_messages"]
+pub struct NewScheduledMessage {
+    pub email: String,
+    /// The message in the Slack message format.
+    pub message: Value,
+    pub deliver_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct ScheduledMessage {
+    pub id: i32,
+    pub email: String,
+    pub message: Value,
+    pub deliver_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Send a direct message in Slack to a person by their email at a later time. The
+/// message is sent by the first run of the `scheduled_messages` job after `deliver_at`.
+#[instrument(skip(db, msg))]
+#[inline]
+pub fn notify_user_at(db: &Database, email: &str, msg: Value, deliver_at: DateTime<Utc>) {
+    let scheduled = NewScheduledMessage {
+        email: email.to_string(),
+        message: msg,
+        deliver_at,
+        created_at: Utc::now(),
+    };
+    diesel::insert_into(scheduled_messages::table)
+        .values(&scheduled)
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("scheduling message {:?} failed: {}", scheduled, e));
+}
+
+/// Send the scheduled direct messages that are due.
+#[instrument(skip(db))]
+#[inline]
+pub async fn send_scheduled_messages(db: &Database) {
+    let due: Vec<ScheduledMessage> = scheduled_messages::table
+        .filter(scheduled_messages::deliver_at.le(Utc::now()))
+        .order_by(scheduled_messages::deliver_at)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the scheduled messages failed: {}", e));
+
+    for m in due {
+        notify_user(db, &m.email, m.message.clone()).await;
+
+        diesel::delete(scheduled_messages::table.filter(scheduled_messages::id.eq(m.id)))
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("deleting the sent scheduled message {} failed: {}", m.id, e));
+        println!("[notifications] sent the message scheduled for {} to {}", m.deliver_at, m.email);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::db::Database;
+    use crate::notifications::{format_digest, send_notification_digests, send_scheduled_messages, PendingNotification, SlackUserId};
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_notification_digests() {
+        let db = Database::new();
+        send_notification_digests(&db).await;
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_scheduled_messages() {
+        let db = Database::new();
+        send_scheduled_messages(&db).await;
+    }
+
+    #[test]
+    fn test_format_digest() {
+        let notification = |id: i32, event: &str, text: &str| PendingNotification {
+            id,
+            channel: "SECURITY".to_string(),
+            event: event.to_string(),
+            message: json!({ "text": text }),
+            created_at: Utc.ymd(2021, 4, 9).and_hms(8, id as u32, 0),
+        };
+
+        let single = vec![notification(1, "vendors.security_review", "Okta needs a review")];
+        assert_eq!(format_digest(&single), json!({"text": "Okta needs a review"}));
+
+        let many = vec![
+            notification(1, "vendors.security_review", "Okta needs a review"),
+            notification(2, "licenses.new_findings", "• `cio` depends on `foo` (GPL-3.0)"),
+            notification(3, "vendors.security_review", "Zoom needs a review"),
+        ];
+        assert_eq!(
+            format_digest(&many),
+            json!({"text": "*3 notifications since 2021-04-09 08:01 UTC*\n\n*licenses.new_findings* (1)\n• `cio` depends on `foo` (GPL-3.0)\n\n*vendors.security_review* (2)\nOkta needs a review\nZoom needs a review"})
+        );
+    }
+
+    #[test]
+    fn test_slack_user_id_is_fresh() {
+        let now = Utc::now();
+        let mut cached = SlackUserId {
+            id: 1,
+            email: "jane@example.com".to_string(),
+            slack_user_id: "U012AB3CD".to_string(),
+            resolved_at: now - Duration::days(2),
+        };
+        assert!(cached.is_fresh(now));
+
+        cached.resolved_at = now - Duration::days(31);
+        assert!(!cached.is_fresh(now));
+
+        // Misses are looked up again sooner.
+        cached.slack_user_id = "".to_string();
+        cached.resolved_at = now - Duration::hours(2);
+        assert!(cached.is_fresh(now));
+        cached.resolved_at = now - Duration::days(2);
+        assert!(!cached.is_fresh(now));
+    }
+}
@@ -0,0 +1,279 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use macros::db;
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_OAUTH_GRANTS_TABLE};
+use crate::chat::ChatChannel;
+use crate::configs::Users;
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::oauth_grants;
+use crate::utils::{get_gsuite_token, google_api};
+
+static DIRECTORY_API: &str = "https://admin.googleapis.com/admin/directory/v1";
+
+/// The scopes that give a third-party app access to all of someone's mail or files, with
+/// a short name for each. Apps newly granted any of these are posted to the #security
+/// channel.
+pub static HIGH_RISK_OAUTH_SCOPES: &[(&str, &str)] = &[
+    ("https://mail.google.com/", "Gmail full access"),
+    ("https://www.googleapis.com/auth/gmail.modify", "Gmail read and write"),
+    ("https://www.googleapis.com/auth/gmail.readonly", "Gmail read"),
+    ("https://www.googleapis.com/auth/drive", "Drive full access"),
+    ("https://www.googleapis.com/auth/drive.readonly", "Drive read"),
+    ("https://www.googleapis.com/auth/admin.directory.user", "Directory users"),
+];
+
+/// An OAuth token someone in Google Workspace granted to a third-party app.
+#[db {
+    new_struct_name = "OAuthGrant",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_OAUTH_GRANTS_TABLE",
+    source = "gsuite",
+    match_on = {
+        "grant_id" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "oauth_grants"]
+pub struct NewOAuthGrant {
+    /// A unique identifier for the grant formatted as `{email}/{client_id}`.
+    pub grant_id: String,
+    pub email: String,
+    /// The OAuth client ID of the app.
+    pub client_id: String,
+    /// The name of the app, as shown to the person when they granted it.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub app_name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    /// The short names of the scopes in `HIGH_RISK_OAUTH_SCOPES` the app was granted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub high_risk_scopes: Vec<String>,
+    /// If the app is installed on a device, rather than a web app.
+    #[serde(default)]
+    pub native_app: bool,
+    /// When we first saw the grant, Google does not say when it was granted.
+    pub first_seen_at: DateTime<Utc>,
+}
+
+/// Implement updating the Airtable record for an OAuthGrant.
+#[async_trait]
+impl UpdateAirtableRecord<OAuthGrant> for OAuthGrant {
+    async fn update_airtable_record(&mut self, _record: OAuthGrant) {}
+}
+
+impl NewOAuthGrant {
+    /// Parse a token from the Directory API.
+    /// FROM: https://developers.google.com/admin-sdk/directory/reference/rest/v1/tokens
+    #[instrument]
+    #[inline]
+    pub fn from_token(email: &str, t: &Value, first_seen_at: DateTime<Utc>) -> Self {
+        let client_id = t["clientId"].as_str().unwrap_or_default().to_string();
+        let mut scopes: Vec<String> = t["scopes"].as_array().cloned().unwrap_or_default().iter().map(|s| s.as_str().unwrap_or_default().to_string()).collect();
+        scopes.sort();
+
+        NewOAuthGrant {
+            grant_id: format!("{}/{}", email, client_id),
+            email: email.to_string(),
+            client_id,
+            app_name: t["displayText"].as_str().unwrap_or_default().to_string(),
+            high_risk_scopes: high_risk_scopes(&scopes),
+            scopes,
+            native_app: t["nativeApp"].as_bool().unwrap_or_default(),
+            first_seen_at,
+        }
+    }
+}
+
+/// Returns the short names of the high risk scopes in the scopes.
+#[instrument]
+#[inline]
+pub fn high_risk_scopes(scopes: &[String]) -> Vec<String> {
+    HIGH_RISK_OAUTH_SCOPES
+        .iter()
+        .filter(|(scope, _)| scopes.iter().any(|s| s == scope))
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// Returns the high risk scopes in a grant that were not in the grant we had before, if
+/// any.
+#[instrument]
+#[inline]
+pub fn newly_granted_high_risk_scopes(grant: &NewOAuthGrant, existing: Option<&OAuthGrant>) -> Vec<String> {
+    grant
+        .high_risk_scopes
+        .iter()
+        .filter(|s| !existing.map(|e| e.high_risk_scopes.contains(s)).unwrap_or(false))
+        .cloned()
+        .collect()
+}
+
+/// Make a request to the Directory API for what the `gsuite_api` crate does not support yet.
+#[instrument(skip(token))]
+#[inline]
+async fn directory_api(token: &str, method: Method, path: &str) -> Result<Value, String> {
+    google_api(token, method, &format!("{}{}", DIRECTORY_API, path), &[], None).await
+}
+
+/// Sync the third-party apps everyone in our directory granted access to their Google
+/// account. Apps newly granted any of the `HIGH_RISK_OAUTH_SCOPES` are posted to the
+/// #security channel, and grants that were revoked are removed.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_oauth_grants(db: &Database) {
+    let token = get_gsuite_token("").await;
+
+    let mut existing: BTreeMap<String, OAuthGrant> = OAuthGrants::get_from_db(db).into_iter().map(|g| (g.grant_id.to_string(), g)).collect();
+
+    let mut newly_high_risk: Vec<String> = Default::default();
+    for user in Users::get_from_db(db) {
+        let email = user.email();
+        let tokens = match directory_api(token.as_str(), Method::GET, &format!("/users/{}/tokens", email)).await {
+            Ok(t) => t,
+            Err(e) => {
+                println!("[oauth_grants] listing the tokens for {} failed: {}", email, e);
+                // Keep their grants rather than removing them below.
+                existing.retain(|_, g| g.email != email);
+                continue;
+            }
+        };
+
+        for t in tokens["items"].as_array().cloned().unwrap_or_default() {
+            let mut grant = NewOAuthGrant::from_token(&email, &t, Utc::now());
+            let previous = existing.remove(&grant.grant_id);
+            if let Some(p) = &previous {
+                grant.first_seen_at = p.first_seen_at;
+            }
+
+            let new_scopes = newly_granted_high_risk_scopes(&grant, previous.as_ref());
+            if !new_scopes.is_empty() {
+                newly_high_risk.push(format!("• *{}* granted *{}* `{}`: {}", email, grant.app_name, grant.client_id, new_scopes.join(", ")));
+            }
+
+            grant.upsert(db).await;
+        }
+    }
+
+    // The grants we did not see again were revoked.
+    for (grant_id, grant) in existing {
+        println!("[oauth_grants] {} was revoked", grant_id);
+        grant.delete(db).await;
+    }
+
+    if newly_high_risk.is_empty() {
+        println!("[oauth_grants] no apps were newly granted high risk scopes");
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Urgent,
+        ChatChannel::Security,
+        "oauth_grants.high_risk",
+        json!({
+            "text": render_message("oauth_grants.high_risk", &json!({ "lines": newly_high_risk })),
+        }),
+    )
+    .await;
+}
+
+/// Revoke the access of a third-party app for everyone who granted it, or only the
+/// people in `emails` if it is not empty. Returns the grants that were revoked, with the
+/// errors for the ones that could not be.
+#[instrument(skip(db))]
+#[inline]
+pub async fn revoke_oauth_grants(db: &Database, client_id: &str, emails: &[String], dry_run: bool) -> (Vec<String>, Vec<String>) {
+    let token = get_gsuite_token("").await;
+
+    let mut revoked: Vec<String> = Default::default();
+    let mut errors: Vec<String> = Default::default();
+    for grant in OAuthGrants::get_from_db(db) {
+        if grant.client_id != client_id || (!emails.is_empty() && !emails.contains(&grant.email)) {
+            continue;
+        }
+
+        if !dry_run {
+            if let Err(e) = directory_api(token.as_str(), Method::DELETE, &format!("/users/{}/tokens/{}", grant.email, grant.client_id)).await {
+                errors.push(format!("revoking {} for {} failed: {}", grant.app_name, grant.email, e));
+                continue;
+            }
+            grant.delete(db).await;
+        }
+        revoked.push(grant.email.to_string());
+    }
+
+    (revoked, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::db::Database;
+    use crate::oauth_grants::{newly_granted_high_risk_scopes, refresh_oauth_grants, NewOAuthGrant, OAuthGrant, OAuthGrants};
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_oauth_grants() {
+        let db = Database::new();
+
+        refresh_oauth_grants(&db).await;
+
+        OAuthGrants::get_from_db(&db).update_airtable().await;
+    }
+
+    #[test]
+    fn test_oauth_grant_from_token() {
+        let now = Utc.ymd(2021, 4, 26).and_hms(9, 0, 0);
+        let token = json!({
+            "clientId": "1234.apps.googleusercontent.com",
+            "displayText": "Mail Merger",
+            "scopes": ["https://www.googleapis.com/auth/userinfo.email", "https://mail.google.com/", "https://www.googleapis.com/auth/drive.readonly"],
+            "nativeApp": false,
+        });
+
+        let grant = NewOAuthGrant::from_token("jane@oxidecomputer.com", &token, now);
+        assert_eq!(grant.grant_id, "jane@oxidecomputer.com/1234.apps.googleusercontent.com");
+        assert_eq!(grant.app_name, "Mail Merger");
+        assert_eq!(grant.scopes[0], "https://mail.google.com/");
+        assert_eq!(grant.high_risk_scopes, vec!["Gmail full access", "Drive read"]);
+        assert_eq!(newly_granted_high_risk_scopes(&grant, None), vec!["Gmail full access", "Drive read"]);
+
+        // The app had read access to Drive already, so only Gmail is new.
+        let existing = OAuthGrant {
+            id: 1,
+            grant_id: grant.grant_id.to_string(),
+            email: grant.email.to_string(),
+            client_id: grant.client_id.to_string(),
+            app_name: grant.app_name.to_string(),
+            scopes: vec!["https://www.googleapis.com/auth/drive.readonly".to_string()],
+            high_risk_scopes: vec!["Drive read".to_string()],
+            native_app: false,
+            first_seen_at: now,
+            airtable_record_id: Default::default(),
+            source: Default::default(),
+            last_synced_from: Default::default(),
+            last_synced_at: None,
+            extra: Default::default(),
+            modified_at: None,
+        };
+        assert_eq!(newly_granted_high_risk_scopes(&grant, Some(&existing)), vec!["Gmail full access"]);
+
+        let grant = NewOAuthGrant::from_token("jane@oxidecomputer.com", &json!({"clientId": "5678", "scopes": ["openid"]}), now);
+        assert!(grant.high_risk_scopes.is_empty());
+    }
+}
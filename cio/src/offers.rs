@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::applicants::Applicant;
+use crate::approvals::{get_approval_request, request_approval, APPROVAL_APPROVED, APPROVAL_REJECTED};
+use crate::configs::Config;
+use crate::db::Database;
+use crate::format::{format_date, format_money};
+use crate::key_rotation::{gcp_api, CLOUD_PLATFORM_SCOPE, SECRET_MANAGER_API};
+use crate::schema::{applicants, offers};
+use crate::utils::get_gcp_token;
+
+/// The DocuSign template we generate offer letters from. We only send it for an offer
+/// that is in its band or was approved.
+pub const OFFER_LETTER_TEMPLATE: &str = "offer_letter";
+
+/// The approval operation for offers outside of their band.
+pub const OFFER_OUT_OF_BAND: &str = "offer_out_of_band";
+
+/// Where our compensation bands are, from the `comp_bands` section of our configs. The
+/// bands themselves are not in our configs, which everyone can read, but in a Secret
+/// Manager secret only the people who set compensation can change or read.
+///
+/// ```toml
+/// [comp_bands]
+/// secret = "projects/oxide-cio/secrets/comp-bands"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct CompBandsConfig {
+    /// The secret with the bands, formatted as `projects/{project}/secrets/{secret}`.
+    #[serde(default)]
+    pub secret: String,
+}
+
+/// The range of base salaries for a level in a location, in whole units of the currency.
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CompBand {
+    pub min: i64,
+    pub max: i64,
+    /// Defaults to `USD`.
+    #[serde(default)]
+    pub currency: String,
+}
+
+impl CompBand {
+    /// Get the currency of the band.
+    #[instrument]
+    #[inline]
+    pub fn currency(&self) -> String {
+        if self.currency.is_empty() {
+            return "USD".to_string();
+        }
+
+        self.currency.to_uppercase()
+    }
+}
+
+/// Our compensation bands by level, then location, as they are in the secret.
+///
+/// ```toml
+/// [L3]
+/// "US" = { min = 150000, max = 190000 }
+/// "UK" = { min = 95000, max = 120000, currency = "GBP" }
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CompBands(pub BTreeMap<String, BTreeMap<String, CompBand>>);
+
+impl CompBands {
+    /// Parse the bands from the contents of the secret, returning the problems with them.
+    #[instrument(skip(contents))]
+    #[inline]
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let bands: CompBands = toml::from_str(contents).map_err(|e| format!("decoding the comp bands failed: {}", e))?;
+
+        let mut errors: Vec<String> = Default::default();
+        for (level, locations) in bands.0.iter() {
+            for (location, band) in locations.iter() {
+                if band.min <= 0 || band.max < band.min {
+                    errors.push(format!("the band for {} in {} is {} to {}", level, location, band.min, band.max));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join(", "));
+        }
+
+        Ok(bands)
+    }
+
+    /// Get the band for a level in a location, ignoring case.
+    #[instrument]
+    #[inline]
+    pub fn band(&self, level: &str, location: &str) -> Result<&CompBand, String> {
+        let (level, locations) = self
+            .0
+            .iter()
+            .find(|(l, _)| l.eq_ignore_ascii_case(level.trim()))
+            .ok_or_else(|| format!("there are no bands for level `{}`, the levels are {}", level, self.0.keys().cloned().collect::<Vec<_>>().join(", ")))?;
+
+        locations.iter().find(|(l, _)| l.eq_ignore_ascii_case(location.trim())).map(|(_, b)| b).ok_or_else(|| {
+            format!(
+                "there is no band for {} in `{}`, the locations are {}",
+                level,
+                location,
+                locations.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
+}
+
+/// Describe where a salary is against its band, ie. `12.5% above the band`.
+#[instrument]
+#[inline]
+pub fn describe_against_band(salary: i64, min: i64, max: i64) -> String {
+    if salary < min {
+        format!("{:.1}% below the band", (min - salary) as f64 / min as f64 * 100.0)
+    } else if salary > max {
+        format!("{:.1}% above the band", (salary - max) as f64 / max as f64 * 100.0)
+    } else {
+        "in the band".to_string()
+    }
+}
+
+/// Get our compensation bands from the secret in our configs.
+#[instrument(skip(config))]
+#[inline]
+pub async fn get_comp_bands(config: &Config) -> Result<CompBands, String> {
+    if config.comp_bands.secret.is_empty() {
+        return Err("there is no `secret` in the `comp_bands` section of our configs".to_string());
+    }
+
+    let token = get_gcp_token(&[CLOUD_PLATFORM_SCOPE]).await;
+    let resp = gcp_api(
+        token.as_str(),
+        Method::GET,
+        &format!("{}/{}/versions/latest:access", SECRET_MANAGER_API, config.comp_bands.secret),
+        None,
+    )
+    .await
+    .map_err(|e| format!("reading the comp bands failed: {}", e))?;
+    let data = base64::decode(resp["payload"]["data"].as_str().unwrap_or_default()).map_err(|e| format!("decoding the comp bands failed: {}", e))?;
+
+    CompBands::parse(&String::from_utf8_lossy(&data))
+}
+
+/// An offer we are making an applicant, with the band it was checked against when it
+/// was made. Changing the offer makes a new one, so each one is approved on its own.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "offers"]
+pub struct NewOffer {
+    pub applicant_id: i32,
+    pub level: String,
+    pub location: String,
+    /// The base salary, in whole units of the currency.
+    pub salary: i64,
+    pub currency: String,
+    pub start_date: Option<NaiveDate>,
+    pub band_min: i64,
+    pub band_max: i64,
+    pub in_band: bool,
+    /// The username of who made the offer.
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct Offer {
+    pub id: i32,
+    pub applicant_id: i32,
+    pub level: String,
+    pub location: String,
+    pub salary: i64,
+    pub currency: String,
+    pub start_date: Option<NaiveDate>,
+    pub band_min: i64,
+    pub band_max: i64,
+    pub in_band: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Offer {
+    /// Get the subject of the approval request for the offer.
+    #[instrument]
+    #[inline]
+    pub fn approval_subject(&self) -> String {
+        format!("offer-{}", self.id)
+    }
+
+    /// Describe the offer, ie. `L3 in US at $160,000, in the band of $150,000 to $190,000`.
+    #[instrument]
+    #[inline]
+    pub fn describe(&self) -> String {
+        format!(
+            "{} in {} at {}, {} of {} to {}",
+            self.level,
+            self.location,
+            format_money(self.salary as f64, &self.currency),
+            describe_against_band(self.salary, self.band_min, self.band_max),
+            format_money(self.band_min as f64, &self.currency),
+            format_money(self.band_max as f64, &self.currency)
+        )
+    }
+
+    /// Get the fields we fill in on the offer letter, by the label of the text tab in the
+    /// DocuSign template.
+    #[instrument]
+    #[inline]
+    pub fn letter_fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("level".to_string(), self.level.to_string()),
+            ("location".to_string(), self.location.to_string()),
+            ("salary".to_string(), format_money(self.salary as f64, &self.currency)),
+            ("start_date".to_string(), self.start_date.map(|d| d.format("%B %-d, %Y").to_string()).unwrap_or_default()),
+        ]
+    }
+}
+
+/// Get the most recent offer for an applicant.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_latest_offer(db: &Database, applicant_id: i32) -> Option<Offer> {
+    offers::table
+        .filter(offers::applicant_id.eq(applicant_id))
+        .order_by(offers::created_at.desc())
+        .first::<Offer>(&db.conn())
+        .ok()
+}
+
+/// Make an offer for an applicant, checking it against our bands. Offers outside of
+/// their band are sent for approval, and we cannot generate the offer letter until they
+/// are approved. Returns what was done.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn create_offer(db: &Database, config: &Config, email: &str, level: &str, location: &str, salary: i64, start_date: Option<NaiveDate>, created_by: &str) -> Result<String, String> {
+    let applicant = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.trim().to_lowercase()))
+        .order_by(applicants::dsl::submitted_time.desc())
+        .first::<Applicant>(&db.conn())
+        .map_err(|_| format!("{} has not applied", email))?;
+    let bands = get_comp_bands(config).await?;
+    let band = bands.band(level, location)?;
+
+    let offer: Offer = diesel::insert_into(offers::table)
+        .values(&NewOffer {
+            applicant_id: applicant.id,
+            level: level.trim().to_string(),
+            location: location.trim().to_string(),
+            salary,
+            currency: band.currency(),
+            start_date,
+            band_min: band.min,
+            band_max: band.max,
+            in_band: band.min <= salary && salary <= band.max,
+            created_by: created_by.to_string(),
+            created_at: Utc::now(),
+        })
+        .get_result(&db.conn())
+        .map_err(|e| format!("saving the offer for {} failed: {}", email, e))?;
+
+    if offer.in_band {
+        return Ok(format!(
+            "the offer for {} is {}, send the offer letter with `cio docusign send {} --applicant {}`",
+            email,
+            offer.describe(),
+            OFFER_LETTER_TEMPLATE,
+            email
+        ));
+    }
+
+    request_approval(
+        db,
+        &config.approvals,
+        OFFER_OUT_OF_BAND,
+        &offer.approval_subject(),
+        &format!("the offer of {} ({}) by {} is {}", applicant.name, applicant.role, created_by, offer.describe()),
+    )
+    .await;
+
+    Ok(format!("the offer for {} is {}, so it needs approval before we can send the offer letter", email, offer.describe()))
+}
+
+/// Get the offer we can generate the offer letter of an applicant from: their most
+/// recent offer, if it is in its band or was approved.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_offer_for_letter(db: &Database, applicant_id: i32) -> Result<Offer, String> {
+    let offer = get_latest_offer(db, applicant_id).ok_or_else(|| "there is no offer for the applicant yet, make one with `cio offers create`".to_string())?;
+    if offer.in_band {
+        return Ok(offer);
+    }
+
+    match get_approval_request(db, OFFER_OUT_OF_BAND, &offer.approval_subject()) {
+        Some(r) if r.status == APPROVAL_APPROVED => Ok(offer),
+        Some(r) if r.status == APPROVAL_REJECTED => Err(format!("the offer of {} was rejected by {}, make a new one", offer.describe(), r.rejected_by)),
+        _ => Err(format!("the offer of {} is waiting on approval", offer.describe())),
+    }
+}
+
+/// Format the offers for an applicant for the command line, most recent first.
+#[instrument(skip(db))]
+#[inline]
+pub fn format_offers(db: &Database, email: &str) -> Result<String, String> {
+    let ids: Vec<i32> = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.trim().to_lowercase()))
+        .select(applicants::dsl::id)
+        .load(&db.conn())
+        .map_err(|e| format!("getting the applications of {} failed: {}", email, e))?;
+    let all: Vec<Offer> = offers::table
+        .filter(offers::applicant_id.eq_any(ids))
+        .order_by(offers::created_at.desc())
+        .load(&db.conn())
+        .map_err(|e| format!("getting the offers for {} failed: {}", email, e))?;
+    if all.is_empty() {
+        return Ok(format!("There are no offers for {} yet.", email));
+    }
+
+    let mut text = String::new();
+    for offer in all {
+        let status = if offer.in_band {
+            "in band".to_string()
+        } else {
+            get_approval_request(db, OFFER_OUT_OF_BAND, &offer.approval_subject())
+                .map(|r| r.status)
+                .unwrap_or_else(|| "not sent for approval".to_string())
+        };
+        let start = offer.start_date.map(|d| format!(", starting {}", format_date(d))).unwrap_or_default();
+        text += &format!("{}  {}{} by {} ({})\n", offer.created_at.format("%Y-%m-%d"), offer.describe(), start, offer.created_by, status);
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::offers::{describe_against_band, CompBands};
+
+    #[test]
+    fn test_comp_bands() {
+        let bands = CompBands::parse(
+            r#"[L3]
+"US" = { min = 150000, max = 190000 }
+"UK" = { min = 95000, max = 120000, currency = "gbp" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(bands.band("l3", "us").unwrap().max, 190000);
+        assert_eq!(bands.band("L3", "UK").unwrap().currency(), "GBP");
+        assert_eq!(bands.band("L3", "US").unwrap().currency(), "USD");
+        assert_eq!(bands.band("L4", "US").unwrap_err(), "there are no bands for level `L4`, the levels are L3");
+        assert_eq!(bands.band("L3", "Remote").unwrap_err(), "there is no band for L3 in `Remote`, the locations are UK, US");
+
+        assert_eq!(
+            CompBands::parse("[L3]\n\"US\" = { min = 190000, max = 150000 }\n").unwrap_err(),
+            "the band for L3 in US is 190000 to 150000"
+        );
+    }
+
+    #[test]
+    fn test_describe_against_band() {
+        assert_eq!(describe_against_band(160000, 150000, 190000), "in the band");
+        assert_eq!(describe_against_band(150000, 150000, 190000), "in the band");
+        assert_eq!(describe_against_band(209000, 150000, 190000), "10.0% above the band");
+        assert_eq!(describe_against_band(135000, 150000, 190000), "10.0% below the band");
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+
+use reqwest::{Client, Method};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::configs::Config;
+use crate::utils::GSUITE_DOMAIN;
+
+static SCIM_USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+static SCIM_PATCH_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+
+/// Which groups in our password manager the members of each group in our configs are in,
+/// from the `password_manager` section of our configs. Vaults are shared with the groups
+/// in the password manager, so adding someone to `eng` also gives them the `eng` vaults.
+/// Groups in the password manager that are not in the section are left alone.
+///
+/// We talk to the password manager with SCIM, ie. the 1Password SCIM bridge or the
+/// Bitwarden SCIM endpoint, at `PASSWORD_MANAGER_SCIM_URL` with the bearer token in
+/// `PASSWORD_MANAGER_SCIM_TOKEN`.
+///
+/// ```toml
+/// [password_manager.groups]
+/// eng = ["Engineering"]
+/// ops = ["Engineering", "Infrastructure"]
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct PasswordManagerConfig {
+    /// The password manager groups for each group in our configs.
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+/// Returns the emails of the people who should be in each password manager group.
+#[instrument(skip(config))]
+#[inline]
+pub fn desired_password_manager_groups(config: &Config) -> BTreeMap<String, BTreeSet<String>> {
+    let mut desired: BTreeMap<String, BTreeSet<String>> = Default::default();
+    for groups in config.password_manager.groups.values() {
+        for group in groups {
+            desired.entry(group.to_string()).or_default();
+        }
+    }
+
+    for user in config.users.values() {
+        for group in user.groups.iter() {
+            for pm_group in config.password_manager.groups.get(group).cloned().unwrap_or_default() {
+                desired.entry(pm_group).or_default().insert(format!("{}@{}", user.username, GSUITE_DOMAIN));
+            }
+        }
+    }
+
+    desired
+}
+
+/// Returns the members to add to and remove from a group.
+#[instrument]
+#[inline]
+pub fn group_member_changes(desired: &BTreeSet<String>, current: &BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+    let add = desired.difference(current).cloned().collect();
+    let remove = current.difference(desired).cloned().collect();
+
+    (add, remove)
+}
+
+/// A user in the password manager, from SCIM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScimUser {
+    pub id: String,
+    pub email: String,
+    pub active: bool,
+}
+
+impl ScimUser {
+    /// Parse a user from a SCIM resource, the email is the primary email or the user name.
+    #[instrument]
+    #[inline]
+    pub fn from_resource(r: &Value) -> Self {
+        let emails = r["emails"].as_array().cloned().unwrap_or_default();
+        let email = emails
+            .iter()
+            .find(|e| e["primary"].as_bool().unwrap_or_default())
+            .or_else(|| emails.first())
+            .and_then(|e| e["value"].as_str())
+            .or_else(|| r["userName"].as_str())
+            .unwrap_or_default();
+
+        ScimUser {
+            id: r["id"].as_str().unwrap_or_default().to_string(),
+            email: email.to_lowercase(),
+            active: r["active"].as_bool().unwrap_or(true),
+        }
+    }
+}
+
+/// A client for the SCIM API of our password manager.
+struct ScimClient {
+    url: String,
+    token: String,
+    client: Client,
+}
+
+impl ScimClient {
+    fn new_from_env() -> Self {
+        ScimClient {
+            url: env::var("PASSWORD_MANAGER_SCIM_URL").unwrap().trim_end_matches('/').to_string(),
+            token: env::var("PASSWORD_MANAGER_SCIM_TOKEN").unwrap(),
+            client: Client::new(),
+        }
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, String> {
+        let mut req = self.client.request(method, &format!("{}{}", self.url, path)).bearer_auth(&self.token);
+        if let Some(b) = body {
+            req = req.header(reqwest::header::CONTENT_TYPE, "application/scim+json").body(b.to_string());
+        }
+
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("{} {}: {}", status, path, text));
+        }
+        if text.is_empty() {
+            return Ok(Value::Null);
+        }
+
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+
+    /// List all the resources of a type, ie. `Users` or `Groups`, following the pages.
+    async fn list(&self, resource: &str) -> Result<Vec<Value>, String> {
+        let mut resources: Vec<Value> = Default::default();
+        loop {
+            let resp = self.request(Method::GET, &format!("/{}?startIndex={}&count=100", resource, resources.len() + 1), None).await?;
+            let page = resp["Resources"].as_array().cloned().unwrap_or_default();
+            let total = resp["totalResults"].as_u64().unwrap_or_default() as usize;
+            let empty = page.is_empty();
+            resources.extend(page);
+
+            if empty || resources.len() >= total {
+                return Ok(resources);
+            }
+        }
+    }
+
+    async fn patch(&self, path: &str, operations: Value) -> Result<Value, String> {
+        self.request(Method::PATCH, path, Some(json!({ "schemas": [SCIM_PATCH_SCHEMA], "Operations": operations }))).await
+    }
+}
+
+/// Reconcile the groups in our password manager with the groups in our configs. People in
+/// a group in the `password_manager` section are provisioned and added to its password
+/// manager groups, people who left the group are removed, and people in our domain who are
+/// no longer in our configs are deactivated, which revokes their access to every vault.
+#[instrument(skip(config))]
+#[inline]
+pub async fn reconcile_password_manager_groups(config: &Config) {
+    if config.password_manager.groups.is_empty() {
+        println!("[password_manager] no groups in the password_manager section of our configs");
+        return;
+    }
+
+    let scim = ScimClient::new_from_env();
+    let desired = desired_password_manager_groups(config);
+
+    let mut users: BTreeMap<String, ScimUser> = scim.list("Users").await.unwrap().iter().map(ScimUser::from_resource).map(|u| (u.email.to_string(), u)).collect();
+
+    // Provision everyone who should be in a group, and reactivate anyone who came back.
+    let everyone: BTreeSet<String> = desired.values().flatten().cloned().collect();
+    for email in everyone.iter() {
+        match users.get(email).cloned() {
+            Some(u) if u.active => (),
+            Some(u) => match scim.patch(&format!("/Users/{}", u.id), json!([{ "op": "replace", "value": { "active": true } }])).await {
+                Ok(_) => {
+                    println!("[password_manager] reactivated {}", email);
+                    users.insert(email.to_string(), ScimUser { active: true, ..u });
+                }
+                Err(e) => println!("[password_manager] reactivating {} failed: {}", email, e),
+            },
+            None => {
+                let username = email.split('@').next().unwrap_or_default();
+                let (first_name, last_name) = config.users.get(username).map(|u| (u.first_name.to_string(), u.last_name.to_string())).unwrap_or_default();
+                let body = json!({
+                    "schemas": [SCIM_USER_SCHEMA],
+                    "userName": email,
+                    "name": { "givenName": first_name, "familyName": last_name },
+                    "emails": [{ "value": email, "primary": true }],
+                    "active": true,
+                });
+                match scim.request(Method::POST, "/Users", Some(body)).await {
+                    Ok(r) => {
+                        println!("[password_manager] provisioned {}", email);
+                        users.insert(email.to_string(), ScimUser::from_resource(&r));
+                    }
+                    Err(e) => println!("[password_manager] provisioning {} failed: {}", email, e),
+                }
+            }
+        }
+    }
+
+    let emails_by_id: BTreeMap<String, String> = users.values().map(|u| (u.id.to_string(), u.email.to_string())).collect();
+    for group in scim.list("Groups").await.unwrap() {
+        let name = group["displayName"].as_str().unwrap_or_default();
+        let want = match desired.get(name) {
+            Some(w) => w,
+            None => continue,
+        };
+
+        let current: BTreeSet<String> = group["members"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|m| emails_by_id.get(m["value"].as_str().unwrap_or_default()).cloned())
+            .collect();
+        let (add, remove) = group_member_changes(want, &current);
+
+        let mut operations: Vec<Value> = Default::default();
+        let members: Vec<Value> = add.iter().filter_map(|e| users.get(e)).map(|u| json!({ "value": u.id })).collect();
+        if !members.is_empty() {
+            operations.push(json!({ "op": "add", "path": "members", "value": members }));
+        }
+        for email in remove.iter() {
+            operations.push(json!({ "op": "remove", "path": format!("members[value eq \"{}\"]", users[email].id) }));
+        }
+        if operations.is_empty() {
+            continue;
+        }
+
+        match scim.patch(&format!("/Groups/{}", group["id"].as_str().unwrap_or_default()), json!(operations)).await {
+            Ok(_) => println!("[password_manager] group {}: added {:?}, removed {:?}", name, add, remove),
+            Err(e) => println!("[password_manager] updating group {} failed: {}", name, e),
+        }
+    }
+
+    // Deactivate the people in our domain who are no longer in our configs.
+    for user in users.values() {
+        let username = match user.email.strip_suffix(&format!("@{}", GSUITE_DOMAIN)) {
+            Some(u) => u,
+            None => continue,
+        };
+        if !user.active || config.users.contains_key(username) {
+            continue;
+        }
+
+        match scim.patch(&format!("/Users/{}", user.id), json!([{ "op": "replace", "value": { "active": false } }])).await {
+            Ok(_) => println!("[password_manager] deactivated {}", user.email),
+            Err(e) => println!("[password_manager] deactivating {} failed: {}", user.email, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde_json::json;
+
+    use crate::configs::{Config, UserConfig};
+    use crate::password_manager::{desired_password_manager_groups, group_member_changes, ScimUser};
+
+    #[test]
+    fn test_desired_password_manager_groups() {
+        let mut config = Config::default();
+        for (username, groups) in &[("jane", r#"["eng"]"#), ("sam", r#"["eng", "ops"]"#), ("alex", r#"["sales"]"#)] {
+            let user: UserConfig = toml::from_str(&format!(
+                r#"first_name = "{}"
+last_name = "Doe"
+username = "{}"
+groups = {}
+"#,
+                username, username, groups
+            ))
+            .unwrap();
+            config.users.insert(username.to_string(), user);
+        }
+        config.password_manager.groups.insert("eng".to_string(), vec!["Engineering".to_string()]);
+        config.password_manager.groups.insert("ops".to_string(), vec!["Engineering".to_string(), "Infrastructure".to_string()]);
+        config.password_manager.groups.insert("hardware".to_string(), vec!["Hardware".to_string()]);
+
+        let desired = desired_password_manager_groups(&config);
+        assert_eq!(desired.keys().collect::<Vec<_>>(), vec!["Engineering", "Hardware", "Infrastructure"]);
+        assert_eq!(desired["Engineering"].iter().collect::<Vec<_>>(), vec!["jane@oxidecomputer.com", "sam@oxidecomputer.com"]);
+        assert_eq!(desired["Infrastructure"].iter().collect::<Vec<_>>(), vec!["sam@oxidecomputer.com"]);
+        // Nobody is in the group, so everyone in it should be removed.
+        assert!(desired["Hardware"].is_empty());
+
+        let current: BTreeSet<String> = vec!["jane@oxidecomputer.com".to_string(), "alex@oxidecomputer.com".to_string()].into_iter().collect();
+        let (add, remove) = group_member_changes(&desired["Engineering"], &current);
+        assert_eq!(add, vec!["sam@oxidecomputer.com"]);
+        assert_eq!(remove, vec!["alex@oxidecomputer.com"]);
+    }
+
+    #[test]
+    fn test_scim_user_from_resource() {
+        let user = ScimUser::from_resource(&json!({
+            "id": "abc",
+            "userName": "jane",
+            "emails": [{"value": "jane@example.com"}, {"value": "Jane@oxidecomputer.com", "primary": true}],
+        }));
+        assert_eq!(
+            user,
+            ScimUser {
+                id: "abc".to_string(),
+                email: "jane@oxidecomputer.com".to_string(),
+                active: true,
+            }
+        );
+
+        let user = ScimUser::from_resource(&json!({"id": "def", "userName": "sam@oxidecomputer.com", "active": false}));
+        assert_eq!(user.email, "sam@oxidecomputer.com");
+        assert!(!user.active);
+    }
+}
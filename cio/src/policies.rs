@@ -0,0 +1,354 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::configs::{Config, User, UserConfig};
+use crate::db::Database;
+use crate::drive_permissions::drive_api;
+use crate::format::format_date;
+use crate::messages::render_message;
+use crate::notifications::notify_user;
+use crate::reactions::message_ref;
+use crate::schema::policy_acknowledgments;
+use crate::utils::{get_gsuite_token, GSUITE_DOMAIN};
+
+/// A policy everyone has to acknowledge, keyed by its name in the `policies` section of
+/// our configs. The `policies` job sends each person a link to the document in Drive,
+/// and they acknowledge it by reacting to the message.
+///
+/// ```toml
+/// [policies.acceptable_use]
+/// title = "Acceptable use policy"
+/// file_id = "1AbCdEfGhIjKlMnOpQrStUvWxYz"
+/// version = "2021-05"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct PolicyConfig {
+    pub title: String,
+    /// The ID of the document in Drive.
+    pub file_id: String,
+    /// Change this when the policy changes enough that everyone has to acknowledge it
+    /// again. Defaults to `1`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub version: String,
+    /// The groups whose members have to acknowledge it, everyone if there are none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+impl PolicyConfig {
+    /// Get the version of the policy people acknowledge.
+    #[instrument]
+    #[inline]
+    pub fn version(&self) -> String {
+        if self.version.is_empty() {
+            return "1".to_string();
+        }
+
+        self.version.trim().to_string()
+    }
+
+    /// Returns if someone has to acknowledge the policy.
+    #[instrument]
+    #[inline]
+    pub fn applies_to(&self, user: &UserConfig) -> bool {
+        user.typev != "system account" && (self.groups.is_empty() || self.groups.iter().any(|g| user.groups.contains(g)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "policy_acknowledgments"]
+pub struct NewPolicyAcknowledgment {
+    pub policy: String,
+    pub version: String,
+    pub username: String,
+    /// When we sent them the policy to acknowledge.
+    pub requested_at: DateTime<Utc>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct PolicyAcknowledgment {
+    pub id: i32,
+    pub policy: String,
+    pub version: String,
+    pub username: String,
+    pub requested_at: DateTime<Utc>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+}
+
+/// Plan who we have to send each policy to: everyone it applies to who was not sent its
+/// current version yet. Returns the policy and username of each.
+#[instrument(skip(users, requested))]
+#[inline]
+pub fn plan_acknowledgment_requests(policies: &BTreeMap<String, PolicyConfig>, users: &BTreeMap<String, UserConfig>, requested: &BTreeSet<(String, String, String)>) -> Vec<(String, String)> {
+    let mut planned = vec![];
+    for (name, policy) in policies.iter() {
+        let version = policy.version();
+        for user in users.values().filter(|u| policy.applies_to(u)) {
+            if !requested.contains(&(name.to_string(), version.to_string(), user.username.to_string())) {
+                planned.push((name.to_string(), user.username.to_string()));
+            }
+        }
+    }
+
+    planned
+}
+
+/// Get the link to the document of a policy from Drive.
+#[instrument(skip(token))]
+#[inline]
+async fn get_policy_link(token: &str, policy: &PolicyConfig) -> Result<String, String> {
+    let file = drive_api(token, Method::GET, &format!("/files/{}", policy.file_id), &[("fields", "webViewLink".to_string())], None)
+        .await
+        .map_err(|e| format!("getting the document for {} failed: {}", policy.title, e))?;
+
+    Ok(file["webViewLink"].as_str().unwrap_or_default().to_string())
+}
+
+/// Send everyone the policies they have not been sent the current versions of yet, with
+/// a link to the document to acknowledge it from.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_policy_acknowledgment_requests(db: &Database, config: &Config) {
+    let requested: BTreeSet<(String, String, String)> = policy_acknowledgments::table
+        .select((policy_acknowledgments::policy, policy_acknowledgments::version, policy_acknowledgments::username))
+        .load::<(String, String, String)>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the policy acknowledgments failed: {}", e))
+        .into_iter()
+        .collect();
+    let planned = plan_acknowledgment_requests(&config.policies, &config.users, &requested);
+    if planned.is_empty() {
+        return;
+    }
+
+    let token = get_gsuite_token("").await;
+    let mut links: BTreeMap<String, String> = Default::default();
+    for (name, username) in planned {
+        let policy = &config.policies[&name];
+        if !links.contains_key(&name) {
+            let link = get_policy_link(&token, policy).await.unwrap_or_else(|e| {
+                println!("[policies] {}", e);
+                String::new()
+            });
+            links.insert(name.to_string(), link);
+        }
+        // Do not ask anyone to acknowledge a document they can not open.
+        let url = &links[&name];
+        if url.is_empty() {
+            continue;
+        }
+
+        let ack = diesel::insert_into(policy_acknowledgments::table)
+            .values(&NewPolicyAcknowledgment {
+                policy: name.to_string(),
+                version: policy.version(),
+                username: username.to_string(),
+                requested_at: Utc::now(),
+                acknowledged_at: None,
+            })
+            .get_result::<PolicyAcknowledgment>(&db.conn())
+            .unwrap_or_else(|e| panic!("saving the {} acknowledgment for {} failed: {}", name, username, e));
+
+        notify_user(db, &format!("{}@{}", username, GSUITE_DOMAIN), acknowledgment_request_msg(policy, &ack, url)).await;
+        println!("[policies] sent {} version {} to {}", name, ack.version, username);
+    }
+}
+
+/// Get the message we send to ask someone to acknowledge a policy. The first block is
+/// tagged so their reaction to the message can find the acknowledgment again.
+#[instrument]
+#[inline]
+pub fn acknowledgment_request_msg(policy: &PolicyConfig, ack: &PolicyAcknowledgment, url: &str) -> Value {
+    let text = render_message("policies.acknowledge", &json!({ "title": policy.title, "url": url }));
+    json!({
+        "text": text,
+        "blocks": [
+            {
+                "type": "section",
+                "block_id": message_ref("policy", &ack.id.to_string()),
+                "text": { "type": "mrkdwn", "text": text },
+            },
+        ],
+    })
+}
+
+/// Record that someone acknowledged a policy from their reaction to the message we sent
+/// them. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub fn acknowledge_policy(db: &Database, id: &str, user: &User) -> Result<String, String> {
+    let id: i32 = id.parse().map_err(|_| format!("{} is not a policy acknowledgment", id))?;
+    let ack = policy_acknowledgments::table
+        .find(id)
+        .first::<PolicyAcknowledgment>(&db.conn())
+        .map_err(|e| format!("policy acknowledgment {} does not exist: {}", id, e))?;
+    if ack.username != user.username {
+        return Err(format!("{} can not acknowledge the {} policy for {}", user.username, ack.policy, ack.username));
+    }
+    if let Some(at) = ack.acknowledged_at {
+        return Ok(format!("you already acknowledged this on {}", format_date(at.date().naive_utc())));
+    }
+
+    diesel::update(policy_acknowledgments::table.find(id))
+        .set(policy_acknowledgments::acknowledged_at.eq(Some(Utc::now())))
+        .execute(&db.conn())
+        .map_err(|e| format!("saving the acknowledgment {} failed: {}", id, e))?;
+
+    Ok(format!("{} acknowledged this, thank you!", user.full_name()))
+}
+
+/// Get who was sent the current version of a policy, and when they acknowledged it.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_policy_acknowledgments(db: &Database, name: &str, version: &str) -> Vec<PolicyAcknowledgment> {
+    policy_acknowledgments::table
+        .filter(policy_acknowledgments::policy.eq(name))
+        .filter(policy_acknowledgments::version.eq(version))
+        .order_by(policy_acknowledgments::username)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the acknowledgments of {} failed: {}", name, e))
+}
+
+/// Format who has not acknowledged the current version of each policy.
+#[instrument(skip(acks))]
+#[inline]
+pub fn format_policy_acknowledgments(policies: &BTreeMap<String, PolicyConfig>, acks: &BTreeMap<String, Vec<PolicyAcknowledgment>>) -> String {
+    let mut text = String::new();
+    for (name, policy) in policies.iter() {
+        let acks = acks.get(name).cloned().unwrap_or_default();
+        let outstanding: Vec<&PolicyAcknowledgment> = acks.iter().filter(|a| a.acknowledged_at.is_none()).collect();
+        text += &format!("{} (version {}): {} of {} acknowledged\n", policy.title, policy.version(), acks.len() - outstanding.len(), acks.len());
+        for ack in outstanding {
+            text += &format!("  {}: sent {}\n", ack.username, format_date(ack.requested_at.date().naive_utc()));
+        }
+    }
+
+    text
+}
+
+/// Format the acknowledgments of a policy as a CSV, as evidence for an audit.
+#[instrument(skip(acks))]
+#[inline]
+pub fn format_acknowledgment_evidence(acks: &[PolicyAcknowledgment]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(&["policy", "version", "username", "requested_at", "acknowledged_at"]).map_err(|e| e.to_string())?;
+    for ack in acks {
+        writer
+            .write_record(&[
+                ack.policy.to_string(),
+                ack.version.to_string(),
+                ack.username.to_string(),
+                ack.requested_at.to_rfc3339(),
+                ack.acknowledged_at.map(|a| a.to_rfc3339()).unwrap_or_default(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+
+    String::from_utf8(writer.into_inner().map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::configs::UserConfig;
+    use crate::policies::{format_acknowledgment_evidence, format_policy_acknowledgments, plan_acknowledgment_requests, PolicyAcknowledgment, PolicyConfig};
+
+    fn policies() -> BTreeMap<String, PolicyConfig> {
+        toml::from_str(
+            r#"[acceptable_use]
+title = "Acceptable use policy"
+file_id = "1AbC"
+version = "2021-05"
+
+[on_call]
+title = "On-call policy"
+file_id = "1DeF"
+groups = ["eng"]
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plan_acknowledgment_requests() {
+        let users: BTreeMap<String, UserConfig> = toml::from_str(
+            r#"[jane]
+first_name = "Jane"
+last_name = "Doe"
+username = "jane"
+groups = ["eng"]
+
+[sam]
+first_name = "Sam"
+last_name = "Smith"
+username = "sam"
+
+[builds]
+first_name = "Build"
+last_name = "Bot"
+username = "builds"
+type = "system account"
+"#,
+        )
+        .unwrap();
+
+        let mut requested: BTreeSet<(String, String, String)> = Default::default();
+        requested.insert(("acceptable_use".to_string(), "2021-05".to_string(), "jane".to_string()));
+        // An older version does not count.
+        requested.insert(("acceptable_use".to_string(), "2020-01".to_string(), "sam".to_string()));
+
+        assert_eq!(
+            plan_acknowledgment_requests(&policies(), &users, &requested),
+            vec![("acceptable_use".to_string(), "sam".to_string()), ("on_call".to_string(), "jane".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_format_policy_acknowledgments() {
+        let acks = vec![
+            PolicyAcknowledgment {
+                id: 1,
+                policy: "acceptable_use".to_string(),
+                version: "2021-05".to_string(),
+                username: "jane".to_string(),
+                requested_at: Utc.ymd(2021, 5, 18).and_hms(9, 0, 0),
+                acknowledged_at: Some(Utc.ymd(2021, 5, 18).and_hms(9, 30, 0)),
+            },
+            PolicyAcknowledgment {
+                id: 2,
+                policy: "acceptable_use".to_string(),
+                version: "2021-05".to_string(),
+                username: "sam".to_string(),
+                requested_at: Utc.ymd(2021, 5, 18).and_hms(9, 0, 0),
+                acknowledged_at: None,
+            },
+        ];
+        let mut by_policy: BTreeMap<String, Vec<PolicyAcknowledgment>> = Default::default();
+        by_policy.insert("acceptable_use".to_string(), acks.clone());
+
+        assert_eq!(
+            format_policy_acknowledgments(&policies(), &by_policy),
+            "Acceptable use policy (version 2021-05): 1 of 2 acknowledged
+  sam: sent 2021-05-18
+On-call policy (version 1): 0 of 0 acknowledged
+"
+        );
+        assert_eq!(
+            format_acknowledgment_evidence(&acks).unwrap(),
+            "policy,version,username,requested_at,acknowledged_at
+acceptable_use,2021-05,jane,2021-05-18T09:00:00+00:00,2021-05-18T09:30:00+00:00
+acceptable_use,2021-05,sam,2021-05-18T09:00:00+00:00,
+"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+use std::env;
+
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::DateTime;
+use macros::db;
+use okta::Okta;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_SECURITY_POSTURE_TABLE};
+use crate::chat::ChatChannel;
+use crate::configs::{Config, Users};
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::security_postures;
+use crate::utils::{get_gsuite_token, gsuite_client};
+
+/// Our policy for the security of people's accounts, from the `posture` section of our
+/// configs.
+///
+/// ```toml
+/// [posture]
+/// max_password_age_days = 365
+/// admin_groups = ["it"]
+/// ```
+#[derive(Debug, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct PosturePolicy {
+    /// How old an Okta password can be before it needs to be changed, 0 if passwords
+    /// never need to be changed.
+    #[serde(default = "default_max_password_age_days")]
+    pub max_password_age_days: i64,
+    /// The Okta factor types that do not count as MFA on their own, since they can be
+    /// phished or the phone number taken over.
+    #[serde(default = "default_weak_factors")]
+    pub weak_factors: Vec<String>,
+    /// The groups whose members may have admin roles in Okta or Google Workspace. Admin
+    /// roles held by anyone else are violations.
+    #[serde(default)]
+    pub admin_groups: Vec<String>,
+}
+
+fn default_max_password_age_days() -> i64 {
+    365
+}
+
+fn default_weak_factors() -> Vec<String> {
+    vec!["sms".to_string(), "call".to_string(), "email".to_string(), "question".to_string()]
+}
+
+impl Default for PosturePolicy {
+    fn default() -> Self {
+        PosturePolicy {
+            max_password_age_days: default_max_password_age_days(),
+            weak_factors: default_weak_factors(),
+            admin_groups: Default::default(),
+        }
+    }
+}
+
+/// How much each violation takes off of a posture score, out of 100.
+const MISSING_OKTA_MFA_PENALTY: i32 = 40;
+const MISSING_GSUITE_2SV_PENALTY: i32 = 30;
+const UNEXPECTED_ADMIN_PENALTY: i32 = 25;
+const WEAK_OKTA_MFA_PENALTY: i32 = 15;
+const OLD_PASSWORD_PENALTY: i32 = 15;
+
+/// The security posture of a person's accounts, checked against our `PosturePolicy`.
+#[db {
+    new_struct_name = "SecurityPosture",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_SECURITY_POSTURE_TABLE",
+    source = "posture",
+    match_on = {
+        "username" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "security_postures"]
+pub struct NewSecurityPosture {
+    pub username: String,
+    pub email: String,
+    /// If the person has an Okta account, if not the Okta checks are skipped.
+    #[serde(default)]
+    pub has_okta: bool,
+    /// The types of the active factors they enrolled in for MFA in Okta, ie. `push`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub okta_factors: Vec<String>,
+    #[serde(default)]
+    pub password_changed_at: Option<DateTime<Utc>>,
+    /// If the person has a Google Workspace account, if not the Google checks are skipped.
+    #[serde(default)]
+    pub has_gsuite: bool,
+    #[serde(default)]
+    pub gsuite_2sv_enrolled: bool,
+    /// The admin roles they have, ie. `okta:SUPER_ADMIN` or `gsuite:admin`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_roles: Vec<String>,
+    /// Out of 100, less for each violation of our policy.
+    #[serde(default)]
+    pub score: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Implement updating the Airtable record for a SecurityPosture.
+#[async_trait]
+impl UpdateAirtableRecord<SecurityPosture> for SecurityPosture {
+    async fn update_airtable_record(&mut self, _record: SecurityPosture) {}
+}
+
+impl NewSecurityPosture {
+    /// A posture for someone we have not found in Okta or Google Workspace yet.
+    #[instrument]
+    #[inline]
+    pub fn new(username: &str, email: &str, checked_at: DateTime<Utc>) -> Self {
+        NewSecurityPosture {
+            username: username.to_string(),
+            email: email.to_string(),
+            has_okta: false,
+            okta_factors: Default::default(),
+            password_changed_at: None,
+            has_gsuite: false,
+            gsuite_2sv_enrolled: false,
+            admin_roles: Default::default(),
+            score: 0,
+            violations: Default::default(),
+            checked_at,
+        }
+    }
+
+    /// Check the posture against our policy, setting the violations and the score. The
+    /// groups are the groups the person is in, for the admin roles.
+    #[instrument]
+    #[inline]
+    pub fn assess(&mut self, groups: &[String], policy: &PosturePolicy) {
+        let mut violations: Vec<(i32, String)> = Default::default();
+
+        if self.has_okta {
+            let strong: Vec<&String> = self.okta_factors.iter().filter(|f| !policy.weak_factors.contains(f)).collect();
+            if self.okta_factors.is_empty() {
+                violations.push((MISSING_OKTA_MFA_PENALTY, "no active MFA factor in Okta".to_string()));
+            } else if strong.is_empty() {
+                violations.push((WEAK_OKTA_MFA_PENALTY, format!("only weak MFA factors in Okta: {}", self.okta_factors.join(", "))));
+            }
+
+            if policy.max_password_age_days > 0 {
+                if let Some(changed) = self.password_changed_at {
+                    let age = self.checked_at.signed_duration_since(changed).num_days();
+                    if age > policy.max_password_age_days {
+                        violations.push((OLD_PASSWORD_PENALTY, format!("the Okta password is {} days old, the limit is {}", age, policy.max_password_age_days)));
+                    }
+                }
+            }
+        }
+
+        if self.has_gsuite && !self.gsuite_2sv_enrolled {
+            violations.push((MISSING_GSUITE_2SV_PENALTY, "not enrolled in 2-step verification in Google Workspace".to_string()));
+        }
+
+        if !self.admin_roles.is_empty() && !groups.iter().any(|g| policy.admin_groups.contains(g)) {
+            violations.push((UNEXPECTED_ADMIN_PENALTY, format!("has admin roles without being in an admin group: {}", self.admin_roles.join(", "))));
+        }
+
+        self.score = (100 - violations.iter().map(|(penalty, _)| penalty).sum::<i32>()).max(0);
+        self.violations = violations.into_iter().map(|(_, v)| v).collect();
+    }
+}
+
+/// Check the MFA enrollment, password age, and admin roles of everyone in our directory
+/// in Okta and Google Workspace, and post the people who violate our policy to the
+/// #security channel, worst first.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn refresh_security_postures(db: &Database, config: &Config) {
+    let okta = Okta::new_from_env();
+
+    let gsuite_customer = env::var("GADMIN_ACCOUNT_ID").unwrap();
+    let token = get_gsuite_token("").await;
+    let gsuite = gsuite_client(&gsuite_customer, token);
+    let gsuite_users: BTreeMap<String, gsuite_api::User> = gsuite.list_users().await.unwrap().into_iter().map(|u| (u.primary_email.to_lowercase(), u)).collect();
+
+    let mut postures: Vec<NewSecurityPosture> = Default::default();
+    // System accounts are shared and can not enroll in MFA, so they are not checked.
+    for user in Users::get_from_db(db).into_iter().filter(|u| !u.is_system_account()) {
+        let mut posture = NewSecurityPosture::new(&user.username, &user.email(), Utc::now());
+
+        if let Ok(okta_user) = okta.get_user(&posture.email).await {
+            posture.has_okta = true;
+            posture.password_changed_at = okta_user.password_changed;
+            match okta.list_user_factors(&okta_user.id).await {
+                Ok(factors) => posture.okta_factors = factors.into_iter().filter(|f| f.status == "ACTIVE").map(|f| f.factor_type).collect(),
+                Err(e) => println!("[posture] listing the okta factors for {} failed: {}", posture.email, e),
+            }
+            match okta.list_user_roles(&okta_user.id).await {
+                Ok(roles) => posture.admin_roles.extend(roles.into_iter().map(|r| format!("okta:{}", r.role_type))),
+                Err(e) => println!("[posture] listing the okta roles for {} failed: {}", posture.email, e),
+            }
+        }
+
+        if let Some(gsuite_user) = gsuite_users.get(&posture.email.to_lowercase()) {
+            posture.has_gsuite = true;
+            posture.gsuite_2sv_enrolled = gsuite_user.is_enrolled_in2_sv.unwrap_or(false);
+            if gsuite_user.is_admin.unwrap_or(false) {
+                posture.admin_roles.push("gsuite:admin".to_string());
+            }
+            if gsuite_user.is_delegated_admin.unwrap_or(false) {
+                posture.admin_roles.push("gsuite:delegated_admin".to_string());
+            }
+        }
+
+        let groups = config.users.get(&user.username).map(|u| u.groups.clone()).unwrap_or_default();
+        posture.assess(&groups, &config.posture);
+        posture.upsert(db).await;
+        postures.push(posture);
+    }
+
+    let mut violating: Vec<&NewSecurityPosture> = postures.iter().filter(|p| !p.violations.is_empty()).collect();
+    if violating.is_empty() {
+        println!("[posture] everyone's accounts meet our policy");
+        return;
+    }
+    violating.sort_by(|a, b| a.score.cmp(&b.score).then_with(|| a.username.cmp(&b.username)));
+
+    let lines: Vec<String> = violating.iter().map(|p| format!("• *{}* ({}/100): {}", p.username, p.score, p.violations.join("; "))).collect();
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Security,
+        "posture.violations",
+        json!({
+            "text": render_message("posture.violations", &json!({ "lines": lines })),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::configs::get_configs_from_repo;
+    use crate::db::Database;
+    use crate::posture::{refresh_security_postures, NewSecurityPosture, PosturePolicy, SecurityPostures};
+    use crate::utils::authenticate_github_jwt;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_security_postures() {
+        let db = Database::new();
+        let github = authenticate_github_jwt();
+        let config = get_configs_from_repo(&github).await;
+
+        refresh_security_postures(&db, &config).await;
+
+        SecurityPostures::get_from_db(&db).update_airtable().await;
+    }
+
+    #[test]
+    fn test_assess_posture() {
+        let now = Utc.ymd(2021, 4, 25).and_hms(9, 0, 0);
+        let policy = PosturePolicy {
+            admin_groups: vec!["it".to_string()],
+            ..Default::default()
+        };
+
+        let mut posture = NewSecurityPosture {
+            has_okta: true,
+            okta_factors: vec!["push".to_string(), "sms".to_string()],
+            password_changed_at: Some(now - Duration::days(30)),
+            has_gsuite: true,
+            gsuite_2sv_enrolled: true,
+            admin_roles: vec!["gsuite:admin".to_string()],
+            ..NewSecurityPosture::new("jane", "jane@oxidecomputer.com", now)
+        };
+        posture.assess(&["eng".to_string(), "it".to_string()], &policy);
+        assert_eq!(posture.score, 100);
+        assert!(posture.violations.is_empty());
+
+        posture.okta_factors = vec!["sms".to_string()];
+        posture.password_changed_at = Some(now - Duration::days(400));
+        posture.gsuite_2sv_enrolled = false;
+        posture.assess(&["eng".to_string()], &policy);
+        assert_eq!(
+            posture.violations,
+            vec![
+                "only weak MFA factors in Okta: sms",
+                "the Okta password is 400 days old, the limit is 365",
+                "not enrolled in 2-step verification in Google Workspace",
+                "has admin roles without being in an admin group: gsuite:admin",
+            ]
+        );
+        assert_eq!(posture.score, 15);
+
+        // Without an Okta account only the Google checks apply.
+        let mut posture = NewSecurityPosture {
+            has_gsuite: true,
+            ..NewSecurityPosture::new("bot", "bot@oxidecomputer.com", now)
+        };
+        posture.assess(&[], &policy);
+        assert_eq!(posture.violations, vec!["not enrolled in 2-step verification in Google Workspace"]);
+        assert_eq!(posture.score, 70);
+
+        // Everything at once never goes below 0.
+        let mut posture = NewSecurityPosture {
+            has_okta: true,
+            password_changed_at: Some(now - Duration::days(1000)),
+            has_gsuite: true,
+            admin_roles: vec!["okta:SUPER_ADMIN".to_string()],
+            ..NewSecurityPosture::new("joe", "joe@oxidecomputer.com", now)
+        };
+        posture.assess(&[], &policy);
+        assert_eq!(posture.violations[0], "no active MFA factor in Okta");
+        assert_eq!(posture.score, 0);
+    }
+}
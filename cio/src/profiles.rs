@@ -0,0 +1,222 @@
+#![allow(clippy::from_over_into)]
+use async_trait::async_trait;
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_DIRECTORY, AIRTABLE_PROFILES_TABLE};
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::schema::profiles;
+
+/// The skills, interests, and mentorship availability someone shared about themselves.
+/// People fill these in with the form on the Profiles table in Airtable, so we sync
+/// them from Airtable rather than the other way around.
+#[db {
+    new_struct_name = "Profile",
+    airtable_base_id = "AIRTABLE_BASE_ID_DIRECTORY",
+    airtable_table = "AIRTABLE_PROFILES_TABLE",
+    source = "airtable",
+    match_on = {
+        "email" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "profiles"]
+pub struct NewProfile {
+    pub email: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// What they know well enough to help someone with, ie. `Kubernetes`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skills: Vec<String>,
+    /// What they want to learn more about.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interests: Vec<String>,
+    #[serde(default)]
+    pub available_to_mentor: bool,
+}
+
+/// Implement updating the Airtable record for a Profile.
+#[async_trait]
+impl UpdateAirtableRecord<Profile> for Profile {
+    async fn update_airtable_record(&mut self, _record: Profile) {}
+}
+
+/// A profile that matched a search, and whether it matched on a skill or an interest.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct ProfileMatch {
+    pub profile: NewProfile,
+    /// The skills or interests that matched.
+    pub matched: Vec<String>,
+    /// If the profile matched on a skill, otherwise it only matched on an interest.
+    pub knows: bool,
+}
+
+/// Search profiles for a skill or interest. The search is case insensitive and matches
+/// any part of a skill or interest, so `kube` finds `Kubernetes`. People who have it
+/// as a skill come before people who are only interested in it, and people who are
+/// available to mentor come first within each.
+#[instrument(skip(profiles))]
+#[inline]
+pub fn search_profiles(profiles: Vec<NewProfile>, query: &str) -> Vec<ProfileMatch> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return vec![];
+    }
+
+    let mut matches: Vec<ProfileMatch> = Default::default();
+    for profile in profiles {
+        let skills: Vec<String> = profile.skills.iter().filter(|s| s.to_lowercase().contains(&query)).cloned().collect();
+        let interests: Vec<String> = profile.interests.iter().filter(|s| s.to_lowercase().contains(&query)).cloned().collect();
+
+        let knows = !skills.is_empty();
+        let matched = if knows { skills } else { interests };
+        if matched.is_empty() {
+            continue;
+        }
+
+        matches.push(ProfileMatch { profile, matched, knows });
+    }
+
+    matches.sort_by(|a, b| {
+        b.knows
+            .cmp(&a.knows)
+            .then(b.profile.available_to_mentor.cmp(&a.profile.available_to_mentor))
+            .then(a.profile.name.cmp(&b.profile.name))
+    });
+
+    matches
+}
+
+/// Format the response to the `/whoknows` Slack command as mrkdwn.
+#[instrument(skip(matches))]
+#[inline]
+pub fn format_whoknows(query: &str, matches: &[ProfileMatch]) -> String {
+    let query = query.trim();
+    if query.is_empty() {
+        return "Tell me what you are looking for, ie. `/whoknows kubernetes`.".to_string();
+    }
+    if matches.is_empty() {
+        return format!(
+            "Nobody has listed `{}` as a skill or interest yet. You can add yours with the form on the Profiles table in Airtable.",
+            query
+        );
+    }
+
+    let mut lines: Vec<String> = Default::default();
+    let mut knows = true;
+    lines.push(format!("*Who knows `{}`:*", query));
+    for m in matches {
+        if knows && !m.knows {
+            knows = false;
+            lines.push(format!("*Interested in `{}`:*", query));
+        }
+
+        let mut line = format!("• *{}* <mailto:{}|{}>  {}", m.profile.name, m.profile.email, m.profile.email, m.matched.join(", "));
+        if m.profile.available_to_mentor {
+            line += "  _(available to mentor)_";
+        }
+        lines.push(line);
+    }
+    if !matches[0].knows {
+        // Nobody had it as a skill, so say so rather than starting with an empty list.
+        lines[0] = format!("Nobody has listed `{}` as a skill yet.", query);
+    }
+
+    lines.join("\n")
+}
+
+/// Answer the `/whoknows` Slack command from the profiles in the database.
+#[instrument(skip(db))]
+#[inline]
+pub fn whoknows(db: &Database, query: &str) -> String {
+    let profiles: Vec<NewProfile> = Profiles::get_from_db(db).into_iter().map(|p| p.into()).collect();
+
+    format_whoknows(query, &search_profiles(profiles, query))
+}
+
+/// Sync the profiles from Airtable into the database.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_profiles(db: &Database) {
+    let results = Profile::list_airtable_records().await;
+    for record in results {
+        let mut profile: NewProfile = record.fields.into();
+        profile.email = profile.email.trim().to_lowercase();
+        if profile.email.is_empty() {
+            // Someone started filling in the form without saying who they are.
+            continue;
+        }
+
+        let mut db_profile = profile.upsert_in_db(db);
+        if db_profile.airtable_record_id.is_empty() {
+            db_profile.airtable_record_id = record.id;
+        }
+        db_profile.update(db).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::profiles::{format_whoknows, refresh_profiles, search_profiles, NewProfile};
+
+    fn profile(name: &str, skills: &[&str], interests: &[&str], available_to_mentor: bool) -> NewProfile {
+        NewProfile {
+            email: format!("{}@oxidecomputer.com", name.to_lowercase()),
+            name: name.to_string(),
+            skills: skills.iter().map(|s| s.to_string()).collect(),
+            interests: interests.iter().map(|s| s.to_string()).collect(),
+            available_to_mentor,
+        }
+    }
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_profiles() {
+        let db = Database::new();
+        refresh_profiles(&db).await;
+    }
+
+    #[test]
+    fn test_search_profiles() {
+        let profiles = vec![
+            profile("Ann", &["Rust"], &["Kubernetes"], true),
+            profile("Bob", &["Kubernetes", "Terraform"], &[], false),
+            profile("Cat", &["kubectl plugins"], &[], true),
+            profile("Dan", &["Rust"], &[], true),
+        ];
+
+        let matches = search_profiles(profiles.clone(), " KUBE ");
+        let names: Vec<&str> = matches.iter().map(|m| m.profile.name.as_str()).collect();
+        assert_eq!(names, vec!["Cat", "Bob", "Ann"]);
+        assert_eq!(matches[1].matched, vec!["Kubernetes"]);
+        assert!(!matches[2].knows);
+
+        assert!(search_profiles(profiles.clone(), "").is_empty());
+        assert!(search_profiles(profiles, "cobol").is_empty());
+    }
+
+    #[test]
+    fn test_format_whoknows() {
+        let profiles = vec![profile("Ann", &[], &["Kubernetes"], false), profile("Bob", &["Kubernetes"], &[], true)];
+
+        assert_eq!(
+            format_whoknows("kubernetes", &search_profiles(profiles.clone(), "kubernetes")),
+            "*Who knows `kubernetes`:*
+• *Bob* <mailto:bob@oxidecomputer.com|bob@oxidecomputer.com>  Kubernetes  _(available to mentor)_
+*Interested in `kubernetes`:*
+• *Ann* <mailto:ann@oxidecomputer.com|ann@oxidecomputer.com>  Kubernetes"
+        );
+        assert_eq!(
+            format_whoknows("kubernetes", &search_profiles(profiles[..1].to_vec(), "kubernetes")),
+            "Nobody has listed `kubernetes` as a skill yet.
+*Interested in `kubernetes`:*
+• *Ann* <mailto:ann@oxidecomputer.com|ann@oxidecomputer.com>  Kubernetes"
+        );
+        assert!(format_whoknows("cobol", &[]).starts_with("Nobody has listed `cobol`"));
+        assert!(format_whoknows(" ", &[]).contains("/whoknows kubernetes"));
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use tracing::instrument;
+
+/// How often we log progress when we are not drawing a progress bar.
+pub const PLAIN_PROGRESS_INTERVAL_SECS: u64 = 30;
+
+/// If we draw progress bars. This is only turned on by the CLI, the scheduler and
+/// the servers log progress as plain lines.
+static PROGRESS_BARS: AtomicBool = AtomicBool::new(false);
+
+/// Draw progress bars for long syncs when the output is a terminal. Without a
+/// terminal, ie. in CI, progress is still logged as plain lines.
+#[instrument]
+#[inline]
+pub fn enable_progress_bars() {
+    PROGRESS_BARS.store(true, Ordering::Relaxed);
+}
+
+/// Format a duration for people, ie. `1h 5m`, `3m 20s`, or `45s`.
+#[instrument]
+#[inline]
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        return format!("{}h {}m", secs / 3600, (secs % 3600) / 60);
+    }
+    if secs >= 60 {
+        return format!("{}m {}s", secs / 60, secs % 60);
+    }
+
+    format!("{}s", secs)
+}
+
+/// Estimate the time left from the rate so far, `None` until there is a rate.
+#[instrument]
+#[inline]
+pub fn estimate_time_left(done: usize, total: usize, elapsed: Duration) -> Option<Duration> {
+    if done == 0 || done >= total {
+        return None;
+    }
+
+    Some(elapsed.mul_f64((total - done) as f64 / done as f64))
+}
+
+/// The line we log for progress when we are not drawing a progress bar.
+#[instrument]
+#[inline]
+pub fn format_progress_line(name: &str, done: usize, total: usize, current: &str, elapsed: Duration) -> String {
+    let mut line = format!("[progress] {}: {}/{}", name, done, total);
+    if total > 0 {
+        line += &format!(" ({}%)", done * 100 / total);
+    }
+    if !current.is_empty() {
+        line += &format!(", at {}", current);
+    }
+    if let Some(left) = estimate_time_left(done, total, elapsed) {
+        line += &format!(", about {} left", format_duration(left));
+    }
+
+    line
+}
+
+/// Reports the progress of a sync through its records: how many are done out of the
+/// total, the record it is on, and how long it has left. In the CLI on a terminal this
+/// is a progress bar, otherwise it is a log line every `PLAIN_PROGRESS_INTERVAL_SECS`.
+pub struct Progress {
+    name: String,
+    /// What we count, ie. `records`, or `bytes` for a file we stream through.
+    unit: &'static str,
+    total: usize,
+    done: usize,
+    current: String,
+    started: Instant,
+    last_logged: Instant,
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    #[instrument]
+    #[inline]
+    pub fn new(name: &str, total: usize) -> Self {
+        Progress::start(name, total, "records", "{pos}/{len}")
+    }
+
+    /// Report the progress through a file of `total` bytes, for files too large to count
+    /// the records of up front. Set how far through it we are with `set`.
+    #[instrument]
+    #[inline]
+    pub fn new_bytes(name: &str, total: u64) -> Self {
+        Progress::start(name, total as usize, "bytes", "{bytes}/{total_bytes}")
+    }
+
+    fn start(name: &str, total: usize, unit: &'static str, position: &str) -> Self {
+        let mut bar = None;
+        if PROGRESS_BARS.load(Ordering::Relaxed) {
+            // The bar is hidden when stderr is not a terminal.
+            let b = ProgressBar::new(total as u64);
+            if !b.is_hidden() {
+                b.set_style(
+                    ProgressStyle::default_bar()
+                        .template(&format!("{{prefix:.bold}} [{{elapsed_precise}}] [{{bar:40.cyan/blue}}] {} {{wide_msg}} (eta {{eta}})", position))
+                        .progress_chars("=> "),
+                );
+                b.set_prefix(name);
+                bar = Some(b);
+            }
+        }
+
+        if bar.is_none() {
+            println!("[progress] {}: starting on {} {}", name, total, unit);
+        }
+
+        let now = Instant::now();
+        Progress {
+            name: name.to_string(),
+            unit,
+            total,
+            done: 0,
+            current: String::new(),
+            started: now,
+            last_logged: now,
+            bar,
+        }
+    }
+
+    /// Mark a record as done, `current` names it, ie. the name of a vendor.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn inc(&mut self, current: &str) {
+        self.set(self.done + 1, current);
+    }
+
+    /// Set how many records, or bytes, are done, `current` names where we are.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn set(&mut self, done: usize, current: &str) {
+        self.done = done;
+        self.current = current.to_string();
+
+        match &self.bar {
+            Some(b) => {
+                b.set_message(current);
+                b.set_position(done as u64);
+            }
+            None => {
+                if self.last_logged.elapsed() >= Duration::from_secs(PLAIN_PROGRESS_INTERVAL_SECS) {
+                    self.last_logged = Instant::now();
+                    println!("{}", format_progress_line(&self.name, self.done, self.total, &self.current, self.started.elapsed()));
+                }
+            }
+        }
+    }
+
+    /// Finish the progress, logging how long it took.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn finish(self) {
+        if let Some(b) = &self.bar {
+            b.finish_and_clear();
+        }
+
+        println!("[progress] {}: finished {} {} in {}", self.name, self.done, self.unit, format_duration(self.started.elapsed()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::progress::{estimate_time_left, format_duration, format_progress_line};
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(200)), "3m 20s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h 5m");
+    }
+
+    #[test]
+    fn test_format_progress_line() {
+        assert_eq!(estimate_time_left(0, 10, Duration::from_secs(5)), None);
+        assert_eq!(estimate_time_left(10, 10, Duration::from_secs(5)), None);
+        assert_eq!(estimate_time_left(25, 100, Duration::from_secs(60)), Some(Duration::from_secs(180)));
+
+        assert_eq!(
+            format_progress_line("Software Vendors", 25, 100, "Okta", Duration::from_secs(60)),
+            "[progress] Software Vendors: 25/100 (25%), at Okta, about 3m 0s left"
+        );
+        assert_eq!(format_progress_line("RFDs", 0, 0, "", Duration::from_secs(0)), "[progress] RFDs: 0/0");
+    }
+}
@@ -0,0 +1,343 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use hubcaps::Github;
+use reqwest::header::HeaderMap;
+use reqwest::{Response, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::format::TIME_FORMAT;
+use crate::schema::rate_limits;
+
+/// The APIs we track the rate limits of.
+pub static RATE_LIMITED_SERVICES: &[&str] = &["airtable", "github", "slack"];
+
+/// Headroom under this percent of the limit is low.
+pub const LOW_HEADROOM_PERCENT: i32 = 10;
+
+/// How long after a service told us to slow down we treat its headroom as low, if
+/// it did not say how long to wait.
+pub const THROTTLE_COOLDOWN_MINUTES: i64 = 15;
+
+/// How much of its rate limit we have left for an API. GitHub tells us our limit,
+/// while Airtable and Slack only tell us when we went over it, with a 429.
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[table_name = "rate_limits"]
+pub struct NewRateLimit {
+    pub service: String,
+    /// The requests we can make in the window, 0 if the service does not say.
+    pub request_limit: i32,
+    pub remaining: i32,
+    pub resets_at: Option<DateTime<Utc>>,
+    /// How many times the service told us to slow down today.
+    pub throttled: i32,
+    pub last_throttled_at: Option<DateTime<Utc>>,
+    /// How long the service told us to wait the last time it throttled us.
+    pub retry_after_secs: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct RateLimit {
+    pub id: i32,
+    pub service: String,
+    pub request_limit: i32,
+    pub remaining: i32,
+    pub resets_at: Option<DateTime<Utc>>,
+    pub throttled: i32,
+    pub last_throttled_at: Option<DateTime<Utc>>,
+    pub retry_after_secs: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl NewRateLimit {
+    /// Record that the service told us to slow down, counting the 429s per day.
+    #[instrument]
+    #[inline]
+    pub fn throttle(&mut self, retry_after_secs: i32, now: DateTime<Utc>) {
+        if self.last_throttled_at.map(|t| t.date() != now.date()).unwrap_or(true) {
+            self.throttled = 0;
+        }
+        self.throttled += 1;
+        self.last_throttled_at = Some(now);
+        self.retry_after_secs = retry_after_secs;
+        self.updated_at = now;
+    }
+
+    /// Returns if we are close to the rate limit for the service. That is either under
+    /// `LOW_HEADROOM_PERCENT` of the limit until it resets, or being told to wait.
+    #[instrument]
+    #[inline]
+    pub fn is_low(&self, now: DateTime<Utc>) -> bool {
+        let near_limit = self.request_limit > 0 && self.resets_at.map(|r| r > now).unwrap_or(false) && self.remaining * 100 < self.request_limit * LOW_HEADROOM_PERCENT;
+
+        let wait = if self.retry_after_secs > 0 {
+            Duration::seconds(self.retry_after_secs.into())
+        } else {
+            Duration::minutes(THROTTLE_COOLDOWN_MINUTES)
+        };
+        let throttled = self.last_throttled_at.map(|t| now - t < wait).unwrap_or(false);
+
+        near_limit || throttled
+    }
+
+    /// Describe the headroom for people, ie. `412 of 5000 left until 14:00 UTC`.
+    #[instrument]
+    #[inline]
+    pub fn describe(&self, now: DateTime<Utc>) -> String {
+        let mut parts: Vec<String> = Default::default();
+        if self.request_limit > 0 {
+            match self.resets_at {
+                Some(r) if r > now => parts.push(format!("{} of {} left until {}", self.remaining, self.request_limit, r.format(TIME_FORMAT))),
+                _ => parts.push(format!("{} left", self.request_limit)),
+            }
+        }
+        if self.last_throttled_at.map(|t| t.date() == now.date()).unwrap_or(false) {
+            parts.push(format!("throttled {} times today", self.throttled));
+        }
+        if parts.is_empty() {
+            return "not throttled today".to_string();
+        }
+
+        parts.join(", ")
+    }
+}
+
+impl From<RateLimit> for NewRateLimit {
+    fn from(r: RateLimit) -> Self {
+        NewRateLimit {
+            service: r.service,
+            request_limit: r.request_limit,
+            remaining: r.remaining,
+            resets_at: r.resets_at,
+            throttled: r.throttled,
+            last_throttled_at: r.last_throttled_at,
+            retry_after_secs: r.retry_after_secs,
+            updated_at: r.updated_at,
+        }
+    }
+}
+
+/// Get the rate limit we last saw for a service.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_rate_limit(db: &Database, service: &str) -> NewRateLimit {
+    match rate_limits::table.filter(rate_limits::service.eq(service)).first::<RateLimit>(&db.conn()) {
+        Ok(r) => r.into(),
+        Err(_) => NewRateLimit {
+            service: service.to_string(),
+            request_limit: 0,
+            remaining: 0,
+            resets_at: None,
+            throttled: 0,
+            last_throttled_at: None,
+            retry_after_secs: 0,
+            updated_at: Utc::now(),
+        },
+    }
+}
+
+/// Get the rate limits we last saw for every service we track.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_rate_limits(db: &Database) -> Vec<NewRateLimit> {
+    RATE_LIMITED_SERVICES.iter().map(|s| get_rate_limit(db, s)).collect()
+}
+
+fn save_rate_limit(db: &Database, rate_limit: &NewRateLimit) {
+    diesel::insert_into(rate_limits::table)
+        .values(rate_limit)
+        .on_conflict(rate_limits::service)
+        .do_update()
+        .set(rate_limit)
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("saving the rate limit for {} failed: {}", rate_limit.service, e));
+}
+
+/// Get the services we are close to the rate limit for.
+#[instrument(skip(db))]
+#[inline]
+pub fn low_rate_limits(db: &Database, now: DateTime<Utc>) -> Vec<String> {
+    get_rate_limits(db).into_iter().filter(|r| r.is_low(now)).map(|r| r.service).collect()
+}
+
+/// Record the rate limit for our GitHub token. Getting it does not count against it.
+#[instrument(skip(db, github))]
+#[inline]
+pub async fn refresh_github_rate_limit(db: &Database, github: &Github) {
+    let response = match github.rate_limit().get().await {
+        Ok(r) => r,
+        Err(e) => {
+            println!("[rate_limits] getting the github rate limit failed: {}", e);
+            return;
+        }
+    };
+
+    let mut rate_limit = get_rate_limit(db, "github");
+    rate_limit.request_limit = response.resources.core.limit as i32;
+    rate_limit.remaining = response.resources.core.remaining as i32;
+    rate_limit.resets_at = Some(Utc.timestamp(response.resources.core.reset.into(), 0));
+    rate_limit.updated_at = Utc::now();
+    save_rate_limit(db, &rate_limit);
+}
+
+/// Parse the `X-RateLimit-*` headers GitHub sends with every response into the limit,
+/// what is remaining, and when it resets.
+/// FROM: https://docs.github.com/en/rest/overview/resources-in-the-rest-api#rate-limiting
+#[instrument(skip(headers))]
+#[inline]
+pub fn parse_github_rate_limit_headers(headers: &HeaderMap) -> Option<(i32, i32, DateTime<Utc>)> {
+    let header = |name: &str| -> Option<i64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+
+    Some((
+        header("x-ratelimit-limit")? as i32,
+        header("x-ratelimit-remaining")? as i32,
+        Utc.timestamp(header("x-ratelimit-reset")?, 0),
+    ))
+}
+
+/// Record the rate limit from the headers of a GitHub response once we are low on
+/// headroom, so the scheduler backs off before the next `rate_limits` job. We skip
+/// the database otherwise since this runs for every request.
+#[instrument(skip(resp))]
+#[inline]
+pub fn check_github_rate_limit(resp: &Response) {
+    let (limit, remaining, resets_at) = match parse_github_rate_limit_headers(resp.headers()) {
+        Some(r) => r,
+        None => return,
+    };
+    if remaining * 100 >= limit * LOW_HEADROOM_PERCENT {
+        return;
+    }
+
+    let db = Database::new();
+    let mut rate_limit = get_rate_limit(&db, "github");
+    rate_limit.request_limit = limit;
+    rate_limit.remaining = remaining;
+    rate_limit.resets_at = Some(resets_at);
+    rate_limit.updated_at = Utc::now();
+    save_rate_limit(&db, &rate_limit);
+}
+
+/// Record that a service told us to slow down.
+#[instrument]
+#[inline]
+pub fn record_throttled(service: &str, retry_after_secs: i32) {
+    let db = Database::new();
+    let mut rate_limit = get_rate_limit(&db, service);
+    rate_limit.throttle(retry_after_secs, Utc::now());
+    save_rate_limit(&db, &rate_limit);
+}
+
+/// Check a response for a 429, recording it with how long the service said to wait
+/// in the `Retry-After` header.
+#[instrument(skip(resp))]
+#[inline]
+pub fn check_throttled(service: &str, resp: &Response) -> Result<(), String> {
+    if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(());
+    }
+
+    let retry_after: i32 = resp.headers().get("retry-after").and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse().ok()).unwrap_or_default();
+    record_throttled(service, retry_after);
+
+    Err(format!("{} rate limited us, retry after {}s", service, retry_after))
+}
+
+/// Fail on an error from Airtable, recording it if Airtable rate limited us.
+#[instrument]
+#[inline]
+pub fn airtable_failed<T>(e: airtable_api::APIError) -> T {
+    if e.status_code.as_u16() == 429 {
+        // Airtable asks us to wait 30 seconds once we go over 5 requests a second.
+        record_throttled("airtable", 30);
+    }
+
+    panic!("airtable request failed: {}", e)
+}
+
+/// The rate limits for the dashboard and the status endpoint, formatted for people.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RateLimitStatus {
+    pub service: String,
+    pub low: bool,
+    pub headroom: String,
+}
+
+/// Get the status of the rate limit for each service we track.
+#[instrument(skip(db))]
+#[inline]
+pub fn rate_limit_statuses(db: &Database, now: DateTime<Utc>) -> Vec<RateLimitStatus> {
+    get_rate_limits(db)
+        .into_iter()
+        .map(|r| RateLimitStatus {
+            low: r.is_low(now),
+            headroom: r.describe(now),
+            service: r.service,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use crate::rate_limits::{parse_github_rate_limit_headers, NewRateLimit};
+
+    #[test]
+    fn test_rate_limit_headroom() {
+        let now = Utc.ymd(2021, 5, 3).and_hms(12, 0, 0);
+        let mut github = NewRateLimit {
+            service: "github".to_string(),
+            request_limit: 5000,
+            remaining: 600,
+            resets_at: Some(now + Duration::minutes(30)),
+            throttled: 0,
+            last_throttled_at: None,
+            retry_after_secs: 0,
+            updated_at: now,
+        };
+        assert!(!github.is_low(now));
+        assert_eq!(github.describe(now), "600 of 5000 left until 2021-05-03 12:30 UTC");
+
+        github.remaining = 499;
+        assert!(github.is_low(now));
+        // Once the window resets we have all of it again.
+        assert!(!github.is_low(now + Duration::hours(1)));
+
+        let mut slack = NewRateLimit {
+            service: "slack".to_string(),
+            request_limit: 0,
+            ..github
+        };
+        assert!(!slack.is_low(now));
+        assert_eq!(slack.describe(now), "not throttled today");
+
+        slack.throttle(60, now - Duration::days(1));
+        slack.throttle(60, now);
+        slack.throttle(60, now);
+        assert_eq!(slack.throttled, 2);
+        assert!(slack.is_low(now + Duration::seconds(30)));
+        assert!(!slack.is_low(now + Duration::seconds(90)));
+        assert_eq!(slack.describe(now), "throttled 2 times today");
+
+        // Without a retry-after we back off for the cooldown.
+        slack.throttle(0, now);
+        assert!(slack.is_low(now + Duration::minutes(10)));
+        assert!(!slack.is_low(now + Duration::minutes(20)));
+    }
+
+    #[test]
+    fn test_parse_github_rate_limit_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_github_rate_limit_headers(&headers), None);
+
+        headers.insert("X-RateLimit-Limit", HeaderValue::from_static("5000"));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("4987"));
+        headers.insert("X-RateLimit-Reset", HeaderValue::from_static("1620028800"));
+        assert_eq!(parse_github_rate_limit_headers(&headers), Some((5000, 4987, Utc.ymd(2021, 5, 3).and_hms(8, 0, 0))));
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::alerts::Alert;
+use crate::applicants::{update_applicant_status_in_sheet, Applicant};
+use crate::approvals::decide_approval_request;
+use crate::configs::{User, Users};
+use crate::db::Database;
+use crate::messages::{messages_config_file, MessagesConfig};
+use crate::policies::acknowledge_policy;
+use crate::schema::applicants;
+use crate::slack::{get_slack_message, get_slack_user_email, post_thread_reply};
+use crate::utils::{github_api_patch, github_org, DOMAIN, GSUITE_DOMAIN};
+
+/// The emoji that trigger an action when someone reacts with them on one of our
+/// messages, used when the messages config does not set any in `[reactions]`.
+pub static DEFAULT_REACTIONS: &[(&str, &str)] = &[
+    ("+1", "approve"),
+    ("-1", "reject"),
+    ("arrow_forward", "advance"),
+    ("raising_hand", "claim"),
+    ("white_check_mark", "resolve"),
+];
+
+/// An action taken when someone reacts to one of our messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReactionAction {
+    /// Move an applicant to the next stage of our hiring process.
+    Advance,
+    /// Approve a request in an approval chain, or acknowledge a policy.
+    Approve,
+    /// Assign a security alert to the person who reacted.
+    Claim,
+    /// Reject a request in an approval chain.
+    Reject,
+    /// Dismiss a security alert in GitHub.
+    Resolve,
+}
+
+impl FromStr for ReactionAction {
+    type Err = String;
+
+    #[instrument]
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "advance" => Ok(ReactionAction::Advance),
+            "approve" => Ok(ReactionAction::Approve),
+            "claim" => Ok(ReactionAction::Claim),
+            "reject" => Ok(ReactionAction::Reject),
+            "resolve" => Ok(ReactionAction::Resolve),
+            _ => Err(format!("{} is not a reaction action, it must be one of `advance`, `approve`, `claim`, `reject`, or `resolve`", s)),
+        }
+    }
+}
+
+/// Get the action for an emoji. The reactions from the messages config replace the
+/// defaults entirely, so a deployment can turn off an emoji by leaving it out.
+#[instrument]
+#[inline]
+pub fn reaction_action(reactions: &BTreeMap<String, String>, emoji: &str) -> Option<ReactionAction> {
+    // Skin tones are sent as a suffix, ie. `raising_hand::skin-tone-2`.
+    let emoji = emoji.split("::").next().unwrap_or_default();
+    if reactions.is_empty() {
+        return DEFAULT_REACTIONS.iter().find(|(e, _)| *e == emoji).and_then(|(_, a)| a.parse().ok());
+    }
+
+    reactions.get(emoji).and_then(|a| a.parse().ok())
+}
+
+/// Tag a message with the record it is about, ie. `alert:cio/dependabot/3`. This is
+/// set as the `block_id` of the first block so we can find the record from a reaction.
+#[instrument]
+#[inline]
+pub fn message_ref(kind: &str, key: &str) -> String {
+    format!("{}:{}", kind, key)
+}
+
+/// Parse the kind and key of the record from a message tag.
+#[instrument]
+#[inline]
+pub fn parse_message_ref(block_id: &str) -> Option<(&str, &str)> {
+    let mut parts = block_id.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(kind), Some(key)) if !kind.is_empty() && !key.is_empty() => Some((kind, key)),
+        _ => None,
+    }
+}
+
+/// Find the user in our directory for an email from Slack. People might use their
+/// email at either of our domains or one of their aliases.
+#[instrument(skip(users))]
+#[inline]
+pub fn find_directory_user<'a>(users: &'a [User], email: &str) -> Option<&'a User> {
+    let email = email.trim().to_lowercase().replace(DOMAIN, GSUITE_DOMAIN);
+    users
+        .iter()
+        .find(|u| u.email().to_lowercase() == email || u.aliases.iter().any(|a| format!("{}@{}", a, GSUITE_DOMAIN).to_lowercase() == email))
+}
+
+/// Handle a `reaction_added` event from the Slack events API, taking the action for
+/// the emoji on the record the message is about. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn handle_slack_reaction(db: &Database, event: &Value) -> Result<String, String> {
+    if event["type"].as_str() != Some("reaction_added") || event["item"]["type"].as_str() != Some("message") {
+        return Ok("not a reaction to a message".to_string());
+    }
+
+    let config = MessagesConfig::read(&messages_config_file())?;
+    let emoji = event["reaction"].as_str().unwrap_or_default();
+    let action = match reaction_action(&config.reactions, emoji) {
+        Some(a) => a,
+        None => return Ok(format!("no action for :{}:", emoji)),
+    };
+
+    let channel = event["item"]["channel"].as_str().unwrap_or_default();
+    let ts = event["item"]["ts"].as_str().unwrap_or_default();
+    let message = get_slack_message(channel, ts).await?;
+    let block_id = message["blocks"][0]["block_id"].as_str().unwrap_or_default();
+    let (kind, key) = match parse_message_ref(block_id) {
+        Some(r) => r,
+        None => return Ok("the message is not about a record".to_string()),
+    };
+
+    let email = get_slack_user_email(event["user"].as_str().unwrap_or_default()).await?;
+    let users: Vec<User> = Users::get_from_db(db).into();
+    let user = match find_directory_user(&users, &email) {
+        Some(u) => u,
+        None => return Err(format!("{} is not in our directory", email)),
+    };
+
+    let done = match (kind, action) {
+        ("alert", ReactionAction::Claim) => claim_alert(db, key, user).await?,
+        ("alert", ReactionAction::Resolve) => resolve_alert(db, key, user).await?,
+        ("applicant", ReactionAction::Advance) => advance_applicant(db, key, user).await?,
+        ("approval", ReactionAction::Approve) => decide_approval_request(db, key, user, true).await?,
+        ("approval", ReactionAction::Reject) => decide_approval_request(db, key, user, false).await?,
+        ("policy", ReactionAction::Approve) => acknowledge_policy(db, key, user)?,
+        _ => return Ok(format!("{:?} does not apply to {} messages", action, kind)),
+    };
+
+    if let Err(e) = post_thread_reply(channel, ts, &done).await {
+        println!("[reactions] {}", e);
+    }
+
+    Ok(done)
+}
+
+/// Assign an alert to a user.
+#[instrument(skip(db))]
+#[inline]
+async fn claim_alert(db: &Database, alert_id: &str, user: &User) -> Result<String, String> {
+    let mut alert = Alert::get_from_db(db, alert_id.to_string()).ok_or_else(|| format!("alert {} does not exist", alert_id))?;
+    alert.assignee = user.username.to_string();
+    alert.update(db).await;
+
+    Ok(format!("{} claimed this alert", user.full_name()))
+}
+
+/// Dismiss an alert in GitHub and mark it as dismissed in our database.
+#[instrument(skip(db))]
+#[inline]
+async fn resolve_alert(db: &Database, alert_id: &str, user: &User) -> Result<String, String> {
+    let mut alert = Alert::get_from_db(db, alert_id.to_string()).ok_or_else(|| format!("alert {} does not exist", alert_id))?;
+    if !alert.is_open() {
+        return Ok(format!("this alert is already {}", alert.state));
+    }
+
+    let (path, body) = match alert.tool.as_str() {
+        "dependabot" => (
+            format!("/repos/{}/{}/dependabot/alerts/{}", github_org(), alert.repo, alert.number),
+            json!({ "state": "dismissed", "dismissed_reason": "tolerable_risk" }),
+        ),
+        _ => (
+            format!("/repos/{}/{}/code-scanning/alerts/{}", github_org(), alert.repo, alert.number),
+            json!({ "state": "dismissed", "dismissed_reason": "won't fix" }),
+        ),
+    };
+    github_api_patch::<Value>(&path, &body)
+        .await
+        .map_err(|e| format!("dismissing alert {} in GitHub failed: {}", alert_id, e))?;
+
+    alert.state = "dismissed".to_string();
+    if alert.assignee.is_empty() {
+        alert.assignee = user.username.to_string();
+    }
+    alert.update(db).await;
+
+    Ok(format!("{} resolved this alert", user.full_name()))
+}
+
+/// Move an applicant to the next stage of our hiring process. The key is the
+/// `{sheet_id}/{email}` of the applicant.
+#[instrument(skip(db))]
+#[inline]
+async fn advance_applicant(db: &Database, key: &str, user: &User) -> Result<String, String> {
+    let (sheet_id, email) = key.split_at(key.find('/').ok_or_else(|| format!("{} is not an applicant", key))?);
+    let email = email.trim_start_matches('/');
+
+    let mut applicant = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.to_string()))
+        .filter(applicants::dsl::sheet_id.eq(sheet_id.to_string()))
+        .first::<Applicant>(&db.conn())
+        .map_err(|e| format!("applicant {} does not exist: {}", key, e))?;
+
+    let status = applicant.status;
+    let next = match status.next() {
+        Some(n) => n,
+        None => return Ok(format!("{} is {} so there is no next stage", applicant.name, applicant.status)),
+    };
+
+    // Update the sheet first, otherwise the next sync of the applicants would
+    // move them back.
+    update_applicant_status_in_sheet(sheet_id, email, &next.to_string()).await?;
+    applicant.status = next;
+    applicant.update(db).await;
+    applicant.stage_changed(db, status).await;
+
+    Ok(format!("{} moved {} to {}", user.full_name(), applicant.name, applicant.status))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::applicant_status::Status;
+    use crate::reactions::{message_ref, parse_message_ref, reaction_action, ReactionAction};
+
+    #[test]
+    fn test_reaction_action() {
+        let defaults = BTreeMap::new();
+        assert_eq!(reaction_action(&defaults, "raising_hand"), Some(ReactionAction::Claim));
+        assert_eq!(reaction_action(&defaults, "raising_hand::skin-tone-3"), Some(ReactionAction::Claim));
+        assert_eq!(reaction_action(&defaults, "+1::skin-tone-2"), Some(ReactionAction::Approve));
+        assert_eq!(reaction_action(&defaults, "tada"), None);
+
+        let mut configured = BTreeMap::new();
+        configured.insert("eyes".to_string(), "claim".to_string());
+        assert_eq!(reaction_action(&configured, "eyes"), Some(ReactionAction::Claim));
+        assert_eq!(reaction_action(&configured, "raising_hand"), None);
+    }
+
+    #[test]
+    fn test_message_ref() {
+        let r = message_ref("alert", "cio/dependabot/3");
+        assert_eq!(r, "alert:cio/dependabot/3");
+        assert_eq!(parse_message_ref(&r), Some(("alert", "cio/dependabot/3")));
+        assert_eq!(parse_message_ref("applicant:1FHA/jane@example.com"), Some(("applicant", "1FHA/jane@example.com")));
+        assert_eq!(parse_message_ref("x7Yq"), None);
+    }
+
+    #[test]
+    fn test_next_status() {
+        assert_eq!(Status::NeedsToBeTriaged.next(), Some(Status::NextSteps));
+        assert_eq!(Status::Onboarding.next(), Some(Status::Hired));
+        assert_eq!(Status::Declined.next(), None);
+    }
+}
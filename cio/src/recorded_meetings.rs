@@ -5,8 +5,6 @@ use std::str::from_utf8;
 use async_trait::async_trait;
 use chrono::offset::Utc;
 use chrono::DateTime;
-use google_drive::GoogleDrive;
-use gsuite_api::GSuite;
 use macros::db;
 use revai::RevAI;
 use schemars::JsonSchema;
@@ -19,13 +17,14 @@ use crate::core::UpdateAirtableRecord;
 use crate::db::Database;
 use crate::models::truncate;
 use crate::schema::recorded_meetings;
-use crate::utils::{get_gsuite_token, GSUITE_DOMAIN};
+use crate::utils::{drive_client, get_gsuite_token, gsuite_client, GSUITE_DOMAIN};
 
 /// The data type for a recorded meeting.
 #[db {
     new_struct_name = "RecordedMeeting",
     airtable_base_id = "AIRTABLE_BASE_ID_MISC",
     airtable_table = "AIRTABLE_RECORDED_MEETINGS_TABLE",
+    source = "google_calendar",
     match_on = {
         "google_event_id" = "String",
     },
@@ -85,7 +84,7 @@ pub async fn refresh_recorded_meetings() {
 
     let gsuite_customer = env::var("GADMIN_ACCOUNT_ID").unwrap();
     let token = get_gsuite_token("").await;
-    let mut gsuite = GSuite::new(&gsuite_customer, GSUITE_DOMAIN, token.clone());
+    let mut gsuite = gsuite_client(&gsuite_customer, token.clone());
     let revai = RevAI::new_from_env();
 
     // Get the list of our calendars.
@@ -94,7 +93,7 @@ pub async fn refresh_recorded_meetings() {
     // Iterate over the calendars.
     for calendar in calendars {
         if calendar.id.ends_with(GSUITE_DOMAIN) {
-            gsuite = GSuite::new(&gsuite_customer, GSUITE_DOMAIN, get_gsuite_token("").await);
+            gsuite = gsuite_client(&gsuite_customer, get_gsuite_token("").await);
 
             // Let's get all the events on this calendar and try and see if they
             // have a meeting recorded.
@@ -141,7 +140,7 @@ pub async fn refresh_recorded_meetings() {
                 }
 
                 let delegated_token = get_gsuite_token(&owner).await;
-                let drive_client = GoogleDrive::new(delegated_token);
+                let drive_client = drive_client(delegated_token);
 
                 // If we have a chat log, we should download it.
                 let mut chat_log = "".to_string();
@@ -211,6 +210,7 @@ pub async fn refresh_recorded_meetings() {
                     let job = revai.create_job(video_contents).await.unwrap();
                     // Set the transcript id.
                     db_meeting.transcript_id = job.id.to_string();
+                    db_meeting.set_synced_from("revai");
                     db_meeting.update(&db).await;
                 } else {
                     // We have a transcript id, let's try and get the transcript if we don't have
@@ -219,6 +219,7 @@ pub async fn refresh_recorded_meetings() {
                         // Now let's try to get the transcript.
                         let transcript = revai.get_transcript(&db_meeting.transcript_id).await.unwrap_or_default();
                         db_meeting.transcript = transcript.trim().to_string();
+                        db_meeting.set_synced_from("revai");
                         db_meeting.update(&db).await;
                     }
                 }
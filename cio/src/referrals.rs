@@ -0,0 +1,457 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::applicant_status::Status;
+use crate::applicants::Applicant;
+use crate::chat::ChatChannel;
+use crate::configs::{Config, User, Users};
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::{applicants, referrals};
+use crate::utils::GSUITE_DOMAIN;
+
+/// A referral the applicant told us about on the application form.
+pub static REFERRAL_SOURCE_APPLICATION: &str = "application";
+
+/// A referral someone added by hand with `cio referrals add`.
+pub static REFERRAL_SOURCE_MANUAL: &str = "manual";
+
+/// How many days after someone is hired we pay the bonus for referring them, if the
+/// `referrals` section of our configs does not say.
+const DEFAULT_PAY_AFTER_DAYS: i64 = 90;
+
+/// The bonus we pay for referrals, from the `referrals` section of our configs.
+///
+/// ```toml
+/// [referrals]
+/// bonus = 5000.0
+/// pay_after_days = 90
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ReferralConfig {
+    /// The bonus in dollars for referring someone we hire. The bonus is fixed when they
+    /// are hired, so changing it does not change what we owe for earlier referrals.
+    #[serde(default)]
+    pub bonus: f32,
+    /// How many days after someone is hired the bonus is due. Defaults to 90.
+    #[serde(default)]
+    pub pay_after_days: i64,
+}
+
+impl ReferralConfig {
+    /// Get how long after someone is hired the bonus for referring them is due.
+    #[instrument]
+    #[inline]
+    pub fn pay_after(&self) -> Duration {
+        if self.pay_after_days > 0 {
+            Duration::days(self.pay_after_days)
+        } else {
+            Duration::days(DEFAULT_PAY_AFTER_DAYS)
+        }
+    }
+}
+
+/// Someone who works here referring an applicant. There is one referral per person,
+/// by their email, however many roles they apply for, and the first referral wins.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "referrals"]
+pub struct NewReferral {
+    /// The application the referral came with, or the first application of the person
+    /// for referrals added by hand.
+    pub applicant_id: i32,
+    pub email: String,
+    /// The username of the person who referred them.
+    pub referrer: String,
+    /// Where we heard about the referral, `application` or `manual`.
+    pub source: String,
+    pub referred_at: DateTime<Utc>,
+    /// When the person first applied without a referral, if they did before they were
+    /// referred. These referrals count towards the conversion rate but do not earn a bonus.
+    pub applied_directly_at: Option<DateTime<Utc>>,
+    pub hired_at: Option<DateTime<Utc>>,
+    pub bonus: f32,
+    pub bonus_paid_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, AsChangeset)]
+#[table_name = "referrals"]
+pub struct Referral {
+    pub id: i32,
+    pub applicant_id: i32,
+    pub email: String,
+    pub referrer: String,
+    pub source: String,
+    pub referred_at: DateTime<Utc>,
+    pub applied_directly_at: Option<DateTime<Utc>>,
+    pub hired_at: Option<DateTime<Utc>>,
+    pub bonus: f32,
+    pub bonus_paid_at: Option<DateTime<Utc>>,
+}
+
+impl Referral {
+    /// Mark the referral as hired, fixing the bonus for it. Returns if it changed.
+    #[instrument]
+    #[inline]
+    pub fn hired(&mut self, at: DateTime<Utc>, config: &ReferralConfig) -> bool {
+        if self.hired_at.is_some() {
+            return false;
+        }
+
+        self.hired_at = Some(at);
+        self.bonus = if self.applied_directly_at.is_none() { config.bonus } else { 0.0 };
+        true
+    }
+
+    /// Get when the bonus for the referral is due, if we owe one and have not paid it.
+    #[instrument]
+    #[inline]
+    pub fn bonus_due(&self, config: &ReferralConfig) -> Option<DateTime<Utc>> {
+        if self.bonus <= 0.0 || self.bonus_paid_at.is_some() {
+            return None;
+        }
+
+        self.hired_at.map(|h| h + config.pay_after())
+    }
+}
+
+/// How many of the people referred to us and the people who applied directly we hired.
+/// People are counted once however many roles they applied for.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReferralConversion {
+    pub referred: usize,
+    pub referred_hired: usize,
+    pub direct: usize,
+    pub direct_hired: usize,
+}
+
+impl ReferralConversion {
+    /// Count the people referred and not from the status of each application, by email.
+    #[instrument(skip(applications, referred))]
+    #[inline]
+    pub fn new(applications: &[(String, Status)], referred: &HashSet<String>) -> Self {
+        let mut people: Vec<&String> = applications.iter().map(|(e, _)| e).collect();
+        people.sort();
+        people.dedup();
+
+        let mut conversion: ReferralConversion = Default::default();
+        for email in people {
+            let hired = applications.iter().any(|(e, s)| e == email && is_hired(*s));
+            if referred.contains(email) {
+                conversion.referred += 1;
+                conversion.referred_hired += hired as usize;
+            } else {
+                conversion.direct += 1;
+                conversion.direct_hired += hired as usize;
+            }
+        }
+
+        conversion
+    }
+}
+
+/// Format the share of people we hired as a percentage.
+#[instrument]
+#[inline]
+pub fn conversion_rate(hired: usize, total: usize) -> String {
+    if total == 0 {
+        return "n/a".to_string();
+    }
+
+    format!("{:.1}%", hired as f64 * 100.0 / total as f64)
+}
+
+/// If an applicant with the status accepted their offer.
+fn is_hired(status: Status) -> bool {
+    status == Status::Hired || status == Status::Onboarding
+}
+
+/// Normalize how an applicant wrote the name or email of the person who referred them,
+/// so we can match it against our users.
+#[instrument]
+#[inline]
+pub fn normalize_referrer(answer: &str) -> String {
+    let answer = answer.trim().trim_start_matches('@').to_lowercase();
+    answer.trim_end_matches(&format!("@{}", GSUITE_DOMAIN)).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Find the user an applicant said referred them, by their username, an alias, their
+/// email, or their full name.
+#[instrument(skip(users))]
+#[inline]
+pub fn find_referrer(users: &[User], answer: &str) -> Option<String> {
+    let answer = normalize_referrer(answer);
+    if answer.is_empty() {
+        return None;
+    }
+
+    users
+        .iter()
+        .find(|u| u.username == answer || u.aliases.contains(&answer) || u.full_name().to_lowercase() == answer)
+        .map(|u| u.username.to_string())
+}
+
+/// Record a referral for an applicant, unless the person was already referred. Returns
+/// what was done.
+#[instrument(skip(db))]
+#[inline]
+async fn record_referral(db: &Database, applicant: &Applicant, referrer: &str, source: &str) -> Result<String, String> {
+    if let Ok(referral) = referrals::table.filter(referrals::email.eq(applicant.email.to_string())).first::<Referral>(&db.conn()) {
+        return Ok(format!("{} was already referred by {}", applicant.email, referral.referrer));
+    }
+
+    // Anyone who applied without a referral before this application applied directly.
+    let applied_directly_at = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(applicant.email.to_string()))
+        .filter(applicants::dsl::id.ne(applicant.id))
+        .filter(applicants::dsl::referred_by.eq(""))
+        .filter(applicants::dsl::submitted_time.lt(applicant.submitted_time))
+        .order_by(applicants::dsl::submitted_time)
+        .first::<Applicant>(&db.conn())
+        .map(|a| a.submitted_time)
+        .ok();
+
+    diesel::insert_into(referrals::table)
+        .values(&NewReferral {
+            applicant_id: applicant.id,
+            email: applicant.email.to_string(),
+            referrer: referrer.to_string(),
+            source: source.to_string(),
+            referred_at: applicant.submitted_time,
+            applied_directly_at,
+            hired_at: None,
+            bonus: 0.0,
+            bonus_paid_at: None,
+        })
+        .execute(&db.conn())
+        .map_err(|e| format!("saving the referral of {} by {} failed: {}", applicant.email, referrer, e))?;
+
+    match applied_directly_at {
+        Some(at) => Ok(format!(
+            "recorded the referral of {} by {}, who applied directly on {} so it has no bonus",
+            applicant.email,
+            referrer,
+            at.format("%Y-%m-%d")
+        )),
+        None => Ok(format!("recorded the referral of {} by {}", applicant.email, referrer)),
+    }
+}
+
+/// Record the referral an applicant told us about on the application form. Returns
+/// what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn record_referral_from_application(db: &Database, applicant: &Applicant) -> Result<String, String> {
+    // We sync every application each time, so skip looking up the referrer once we
+    // have the referral.
+    if let Ok(referral) = referrals::table.filter(referrals::email.eq(applicant.email.to_string())).first::<Referral>(&db.conn()) {
+        return Ok(format!("{} was already referred by {}", applicant.email, referral.referrer));
+    }
+
+    let users = Users::get_from_db(db).0;
+    let referrer = find_referrer(&users, &applicant.referred_by).ok_or_else(|| {
+        format!(
+            "{} said they were referred by `{}` who is not a user, add the referral with `cio referrals add`",
+            applicant.email, applicant.referred_by
+        )
+    })?;
+
+    record_referral(db, applicant, &referrer, REFERRAL_SOURCE_APPLICATION).await
+}
+
+/// Record a referral by hand, for the applicant's application for the role or their
+/// first application. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn add_referral(db: &Database, email: &str, referrer: &str, role: Option<&str>) -> Result<String, String> {
+    if User::get_from_db(db, referrer.to_string()).is_none() {
+        return Err(format!("`{}` is not a user", referrer));
+    }
+
+    let mut query = applicants::dsl::applicants.filter(applicants::dsl::email.eq(email.to_string())).into_boxed();
+    if let Some(role) = role {
+        query = query.filter(applicants::dsl::role.eq(role.to_string()));
+    }
+    let applicant = query
+        .order_by(applicants::dsl::submitted_time)
+        .first::<Applicant>(&db.conn())
+        .map_err(|_| format!("{} has not applied{}", email, role.map(|r| format!(" for {}", r)).unwrap_or_default()))?;
+
+    record_referral(db, &applicant, referrer, REFERRAL_SOURCE_MANUAL).await
+}
+
+/// Mark the bonus for referring someone as paid. Returns what was done.
+#[instrument(skip(db))]
+#[inline]
+pub async fn mark_referral_bonus_paid(db: &Database, email: &str) -> Result<String, String> {
+    let mut referral = referrals::table
+        .filter(referrals::email.eq(email.to_string()))
+        .first::<Referral>(&db.conn())
+        .map_err(|_| format!("{} was not referred", email))?;
+    if referral.hired_at.is_none() || referral.bonus <= 0.0 {
+        return Err(format!("the referral of {} by {} has no bonus", email, referral.referrer));
+    }
+    if let Some(paid_at) = referral.bonus_paid_at {
+        return Ok(format!("the bonus for the referral of {} was already paid on {}", email, paid_at.format("%Y-%m-%d")));
+    }
+
+    referral.bonus_paid_at = Some(Utc::now());
+    diesel::update(referrals::table.find(referral.id))
+        .set(&referral)
+        .execute(&db.conn())
+        .map_err(|e| format!("updating the referral of {} failed: {}", email, e))?;
+
+    Ok(format!("marked the ${:.2} bonus to {} for the referral of {} as paid", referral.bonus, referral.referrer, email))
+}
+
+/// Post a report to the #finance channel with how many referred applicants we hire,
+/// compared to those who applied directly, and the referral bonuses we owe. Referrals
+/// for people who were hired since the last report get their bonus first.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_referrals_report(db: &Database, config: &Config) {
+    let applications: Vec<(String, Status)> = applicants::dsl::applicants
+        .select((applicants::dsl::email, applicants::dsl::status))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the applicants failed: {}", e));
+    let mut all: Vec<Referral> = referrals::table.load(&db.conn()).unwrap_or_else(|e| panic!("getting the referrals failed: {}", e));
+
+    let now = Utc::now();
+    for referral in all.iter_mut() {
+        if !applications.iter().any(|(e, s)| *e == referral.email && *s == Status::Hired) || !referral.hired(now, &config.referrals) {
+            continue;
+        }
+        diesel::update(referrals::table.find(referral.id))
+            .set(&*referral)
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("updating the referral of {} failed: {}", referral.email, e));
+    }
+
+    let referred: HashSet<String> = all.iter().map(|r| r.email.to_string()).collect();
+    let conversion = ReferralConversion::new(&applications, &referred);
+
+    let mut owed: Vec<(DateTime<Utc>, &Referral)> = all.iter().filter_map(|r| r.bonus_due(&config.referrals).map(|d| (d, r))).collect();
+    owed.sort_by_key(|(d, _)| *d);
+    let lines: Vec<String> = owed
+        .iter()
+        .map(|(due, r)| {
+            format!(
+                "• ${:.2} to *{}* for referring {}, due {}{}",
+                r.bonus,
+                r.referrer,
+                r.email,
+                due.format("%Y-%m-%d"),
+                if *due <= now { " (overdue)" } else { "" }
+            )
+        })
+        .collect();
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Finance,
+        "referrals.report",
+        json!({
+            "text": render_message(
+                "referrals.report",
+                &json!({
+                    "referred": conversion.referred,
+                    "referred_hired": conversion.referred_hired,
+                    "referred_rate": conversion_rate(conversion.referred_hired, conversion.referred),
+                    "direct": conversion.direct,
+                    "direct_hired": conversion.direct_hired,
+                    "direct_rate": conversion_rate(conversion.direct_hired, conversion.direct),
+                    "lines": lines,
+                }),
+            ),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::applicant_status::Status;
+    use crate::referrals::{conversion_rate, normalize_referrer, Referral, ReferralConfig, ReferralConversion};
+
+    #[test]
+    fn test_normalize_referrer() {
+        assert_eq!(normalize_referrer("  Jane   Doe "), "jane doe");
+        assert_eq!(normalize_referrer("@jane"), "jane");
+        assert_eq!(normalize_referrer("Jane@OxideComputer.com"), "jane");
+        assert_eq!(normalize_referrer(""), "");
+    }
+
+    #[test]
+    fn test_referral_bonus() {
+        let config = ReferralConfig { bonus: 5000.0, pay_after_days: 0 };
+        let hired_at = Utc.ymd(2021, 5, 3).and_hms(16, 0, 0);
+        let mut referral = Referral {
+            id: 1,
+            applicant_id: 42,
+            email: "sam@example.com".to_string(),
+            referrer: "jane".to_string(),
+            source: "application".to_string(),
+            referred_at: Utc.ymd(2021, 2, 1).and_hms(9, 30, 0),
+            applied_directly_at: None,
+            hired_at: None,
+            bonus: 0.0,
+            bonus_paid_at: None,
+        };
+        assert_eq!(referral.bonus_due(&config), None);
+
+        assert!(referral.hired(hired_at, &config));
+        assert_eq!(referral.bonus, 5000.0);
+        assert_eq!(referral.bonus_due(&config), Some(hired_at + Duration::days(90)));
+        // The bonus is fixed when they are hired.
+        assert!(!referral.hired(hired_at + Duration::days(1), &ReferralConfig::default()));
+        assert_eq!(referral.bonus, 5000.0);
+
+        referral.bonus_paid_at = Some(Utc::now());
+        assert_eq!(referral.bonus_due(&config), None);
+
+        // People who applied directly before they were referred do not earn a bonus.
+        referral.hired_at = None;
+        referral.bonus_paid_at = None;
+        referral.applied_directly_at = Some(Utc.ymd(2020, 11, 9).and_hms(12, 0, 0));
+        assert!(referral.hired(hired_at, &config));
+        assert_eq!(referral.bonus, 0.0);
+        assert_eq!(referral.bonus_due(&config), None);
+    }
+
+    #[test]
+    fn test_referral_conversion() {
+        let applications = vec![
+            ("sam@example.com".to_string(), Status::Declined),
+            ("sam@example.com".to_string(), Status::Hired),
+            ("alex@example.com".to_string(), Status::Interviewing),
+            ("kim@example.com".to_string(), Status::Onboarding),
+            ("lee@example.com".to_string(), Status::Declined),
+            ("pat@example.com".to_string(), Status::NeedsToBeTriaged),
+        ];
+        let referred: HashSet<String> = vec!["sam@example.com".to_string(), "alex@example.com".to_string()].into_iter().collect();
+
+        let conversion = ReferralConversion::new(&applications, &referred);
+        assert_eq!(
+            conversion,
+            ReferralConversion {
+                referred: 2,
+                referred_hired: 1,
+                direct: 3,
+                direct_hired: 1,
+            }
+        );
+        assert_eq!(conversion_rate(conversion.referred_hired, conversion.referred), "50.0%");
+        assert_eq!(conversion_rate(conversion.direct_hired, conversion.direct), "33.3%");
+        assert_eq!(conversion_rate(0, 0), "n/a");
+    }
+}
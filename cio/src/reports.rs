@@ -0,0 +1,149 @@
+use sendgrid_api::SendGrid;
+use tracing::instrument;
+
+use crate::chat::{notify_channel, ChatChannel};
+use crate::utils::GSUITE_DOMAIN;
+
+/// Who can see a part of a report. Each tier also sees everything the tiers before it
+/// see, so `Exec` sees the whole report.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Audience {
+    /// Everyone in the company.
+    All,
+    Managers,
+    Finance,
+    Exec,
+}
+
+/// Where the variant of a report for an audience goes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReportRoute {
+    /// Post it to a chat channel.
+    Channel(ChatChannel),
+    /// Email it to a Google group, by the name of the group, ie. `managers` for
+    /// managers@.
+    Group(&'static str),
+}
+
+/// A report we generate and where each of its audiences gets it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportDefinition {
+    pub name: &'static str,
+    /// Where the variant for each audience goes. Audiences without a route do not get
+    /// the report.
+    pub routes: &'static [(Audience, ReportRoute)],
+}
+
+/// The reports with parts that not everyone should see, ie. what we spend on each
+/// software vendor, which says a lot about what we pay for people's tools.
+pub static REPORTS: &[ReportDefinition] = &[ReportDefinition {
+    name: "finance_rollup",
+    routes: &[
+        (Audience::All, ReportRoute::Channel(ChatChannel::Engineering)),
+        (Audience::Managers, ReportRoute::Group("managers")),
+        (Audience::Finance, ReportRoute::Channel(ChatChannel::Finance)),
+        (Audience::Exec, ReportRoute::Group("leadership")),
+    ],
+}];
+
+/// A report made of sections that are each for an audience and up.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    pub title: String,
+    pub sections: Vec<(Audience, String)>,
+}
+
+impl Report {
+    #[instrument]
+    #[inline]
+    pub fn new(title: &str) -> Self {
+        Report {
+            title: title.to_string(),
+            sections: Default::default(),
+        }
+    }
+
+    /// Add a section that the audience, and the tiers after it, can see.
+    #[instrument]
+    #[inline]
+    pub fn add(&mut self, audience: Audience, text: &str) {
+        self.sections.push((audience, text.trim_end().to_string()));
+    }
+
+    /// The text of the variant of the report for an audience, with the sections it can
+    /// not see left out.
+    #[instrument]
+    #[inline]
+    pub fn redacted_for(&self, audience: Audience) -> String {
+        let mut text = format!("*{}*\n", self.title);
+        for (_, section) in self.sections.iter().filter(|(a, _)| *a <= audience) {
+            text += &format!("\n{}\n", section);
+        }
+
+        text.trim_end().to_string()
+    }
+}
+
+/// Send the variant of a report for each audience in its definition to where the
+/// audience gets it.
+#[instrument]
+#[inline]
+pub async fn send_report(name: &str, report: &Report) {
+    let definition = match REPORTS.iter().find(|r| r.name == name) {
+        Some(d) => d,
+        None => panic!("there is no report definition for {}", name),
+    };
+
+    for (audience, route) in definition.routes {
+        let text = report.redacted_for(*audience);
+        match route {
+            ReportRoute::Channel(channel) => notify_channel(*channel, json!({ "text": text })).await,
+            ReportRoute::Group(group) => {
+                let sendgrid = SendGrid::new_from_env();
+                sendgrid
+                    .send_mail(
+                        report.title.to_string(),
+                        text,
+                        vec![format!("{}@{}", group, GSUITE_DOMAIN)],
+                        vec![],
+                        vec![],
+                        format!("finance@{}", GSUITE_DOMAIN),
+                    )
+                    .await;
+            }
+        }
+        println!("[reports] sent the {:?} variant of {} to {:?}", audience, name, route);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reports::{Audience, Report, REPORTS};
+
+    #[test]
+    fn test_report_redacted_for() {
+        let mut report = Report::new("Finance rollup for March 2021");
+        report.add(Audience::All, "*CI (GitHub Actions):* $12.00\n");
+        report.add(Audience::Managers, "*Software vendors:* $100.00/month");
+        report.add(Audience::Finance, "• Okta: $80.00");
+
+        assert_eq!(report.redacted_for(Audience::All), "*Finance rollup for March 2021*\n\n*CI (GitHub Actions):* $12.00");
+        assert_eq!(
+            report.redacted_for(Audience::Managers),
+            "*Finance rollup for March 2021*\n\n*CI (GitHub Actions):* $12.00\n\n*Software vendors:* $100.00/month"
+        );
+        assert_eq!(report.redacted_for(Audience::Exec), report.redacted_for(Audience::Finance));
+        assert!(report.redacted_for(Audience::Finance).ends_with("• Okta: $80.00"));
+    }
+
+    #[test]
+    fn test_report_definitions() {
+        for definition in REPORTS {
+            let mut audiences: Vec<Audience> = definition.routes.iter().map(|(a, _)| *a).collect();
+            let routes = audiences.len();
+            audiences.sort();
+            audiences.dedup();
+            assert_eq!(audiences.len(), routes, "{} routes an audience twice", definition.name);
+        }
+    }
+}
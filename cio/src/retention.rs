@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use tracing::instrument;
+
+use crate::configs::{User, Users};
+use crate::db::Database;
+use crate::schema::{offboarded_users, people_snapshots, retention_cohorts};
+use crate::utils::default_date;
+
+/// We count tenure in years of 365 days, it is close enough for these numbers.
+const DAYS_IN_YEAR: i64 = 365;
+
+/// How long someone was here, from the directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenure {
+    pub start: NaiveDate,
+    /// The day they left, if they did.
+    pub end: Option<NaiveDate>,
+}
+
+impl Tenure {
+    /// Returns if they were here on a day.
+    #[instrument]
+    #[inline]
+    pub fn here_on(&self, day: NaiveDate) -> bool {
+        self.start <= day && self.end.map(|end| day < end).unwrap_or(true)
+    }
+
+    /// How many days they were here by a day.
+    #[instrument]
+    #[inline]
+    pub fn days(&self, day: NaiveDate) -> i64 {
+        (self.end.map(|end| end.min(day)).unwrap_or(day) - self.start).num_days().max(0)
+    }
+}
+
+/// Our tenure and attrition numbers on a day. We take one a day so we can see how the
+/// rolling numbers change over time.
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[table_name = "people_snapshots"]
+pub struct NewPeopleSnapshot {
+    pub taken_on: NaiveDate,
+    pub headcount: i32,
+    pub median_tenure_days: i32,
+    pub tenure_under_1y: i32,
+    pub tenure_1y_to_2y: i32,
+    pub tenure_2y_to_4y: i32,
+    pub tenure_over_4y: i32,
+    /// The people who left in the 12 months up to the day.
+    pub departures_12m: i32,
+    /// The people who left in the 12 months up to the day, as a percent of our average
+    /// headcount over those months.
+    pub attrition_12m: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct PeopleSnapshot {
+    pub id: i32,
+    pub taken_on: NaiveDate,
+    pub headcount: i32,
+    pub median_tenure_days: i32,
+    pub tenure_under_1y: i32,
+    pub tenure_1y_to_2y: i32,
+    pub tenure_2y_to_4y: i32,
+    pub tenure_over_4y: i32,
+    pub departures_12m: i32,
+    pub attrition_12m: f32,
+}
+
+/// How many of the people who started in a year were still here after each full year,
+/// as of the day of a snapshot. There is only a number for the years everyone in the
+/// cohort has been here for.
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[table_name = "retention_cohorts"]
+pub struct NewRetentionCohort {
+    pub taken_on: NaiveDate,
+    /// The year they started.
+    pub cohort: i32,
+    pub started: i32,
+    /// How many were still here after 1 year, 2 years, and so on.
+    pub retained: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct RetentionCohort {
+    pub id: i32,
+    pub taken_on: NaiveDate,
+    pub cohort: i32,
+    pub started: i32,
+    pub retained: Vec<i32>,
+}
+
+/// Compute our tenure and attrition numbers and the retention of each cohort on a day.
+#[instrument(skip(tenures))]
+#[inline]
+pub fn compute_people_snapshot(tenures: &[Tenure], day: NaiveDate) -> (NewPeopleSnapshot, Vec<NewRetentionCohort>) {
+    let mut days: Vec<i64> = tenures.iter().filter(|t| t.here_on(day)).map(|t| t.days(day)).collect();
+    days.sort_unstable();
+    let median = match days.len() {
+        0 => 0,
+        n if n % 2 == 0 => (days[n / 2 - 1] + days[n / 2]) / 2,
+        n => days[n / 2],
+    };
+    let in_years = |from: i64, to: i64| days.iter().filter(|d| from * DAYS_IN_YEAR <= **d && **d < to * DAYS_IN_YEAR).count() as i32;
+
+    let year_ago = day - Duration::days(DAYS_IN_YEAR);
+    let departures = tenures.iter().filter(|t| t.end.map(|end| year_ago < end && end <= day).unwrap_or(false)).count();
+    let average_headcount = (tenures.iter().filter(|t| t.here_on(year_ago)).count() + days.len()) as f32 / 2.0;
+    let attrition = if average_headcount > 0.0 { departures as f32 / average_headcount * 100.0 } else { 0.0 };
+
+    let snapshot = NewPeopleSnapshot {
+        taken_on: day,
+        headcount: days.len() as i32,
+        median_tenure_days: median as i32,
+        tenure_under_1y: in_years(0, 1),
+        tenure_1y_to_2y: in_years(1, 2),
+        tenure_2y_to_4y: in_years(2, 4),
+        tenure_over_4y: days.iter().filter(|d| 4 * DAYS_IN_YEAR <= **d).count() as i32,
+        departures_12m: departures as i32,
+        attrition_12m: attrition,
+    };
+
+    let mut by_year: BTreeMap<i32, Vec<&Tenure>> = Default::default();
+    for t in tenures.iter().filter(|t| t.start <= day) {
+        by_year.entry(t.start.year()).or_default().push(t);
+    }
+    let cohorts = by_year
+        .into_iter()
+        .map(|(year, members)| {
+            // Everyone in the cohort has been here for a year by a year after the last
+            // day of the year they started in.
+            let last_start = NaiveDate::from_ymd(year, 12, 31);
+            let retained = (1..)
+                .take_while(|n| last_start + Duration::days(n * DAYS_IN_YEAR) <= day)
+                .map(|n| members.iter().filter(|t| t.here_on(t.start + Duration::days(n * DAYS_IN_YEAR))).count() as i32)
+                .collect();
+
+            NewRetentionCohort {
+                taken_on: day,
+                cohort: year,
+                started: members.len() as i32,
+                retained,
+            }
+        })
+        .collect();
+
+    (snapshot, cohorts)
+}
+
+/// Get the tenures of everyone in the directory, and of the people who left that we know
+/// the start dates of.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_tenures(db: &Database) -> Vec<Tenure> {
+    let users: Vec<User> = Users::get_from_db(db).into();
+    let mut tenures: Vec<Tenure> = users
+        .into_iter()
+        .filter(|u| !u.is_system_account() && u.start_date != default_date())
+        .map(|u| Tenure { start: u.start_date, end: None })
+        .collect();
+
+    let left: Vec<(Option<NaiveDate>, DateTime<Utc>)> = offboarded_users::table
+        .select((offboarded_users::start_date, offboarded_users::offboarded_at))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the offboarded users failed: {}", e));
+    tenures.extend(left.into_iter().filter_map(|(start, offboarded_at)| {
+        start.map(|start| Tenure {
+            start,
+            end: Some(offboarded_at.date().naive_utc()),
+        })
+    }));
+
+    tenures
+}
+
+/// Take today's snapshot of our tenure, attrition, and retention numbers, replacing the
+/// one from earlier today if there is one.
+#[instrument(skip(db))]
+#[inline]
+pub fn refresh_people_snapshot(db: &Database) {
+    let today = Utc::now().date().naive_utc();
+    let (snapshot, cohorts) = compute_people_snapshot(&get_tenures(db), today);
+
+    let conn = db.conn();
+    diesel::insert_into(people_snapshots::table)
+        .values(&snapshot)
+        .on_conflict(people_snapshots::taken_on)
+        .do_update()
+        .set(&snapshot)
+        .execute(&conn)
+        .unwrap_or_else(|e| panic!("saving the people snapshot for {} failed: {}", today, e));
+    diesel::delete(retention_cohorts::table.filter(retention_cohorts::taken_on.eq(today)))
+        .execute(&conn)
+        .unwrap_or_else(|e| panic!("deleting the retention cohorts for {} failed: {}", today, e));
+    diesel::insert_into(retention_cohorts::table)
+        .values(&cohorts)
+        .execute(&conn)
+        .unwrap_or_else(|e| panic!("saving the retention cohorts for {} failed: {}", today, e));
+
+    println!(
+        "[people] {} people, median tenure of {} days, {:.1}% attrition over the last 12 months",
+        snapshot.headcount, snapshot.median_tenure_days, snapshot.attrition_12m
+    );
+}
+
+/// Get the most recent snapshot, with the retention of each cohort on that day.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_latest_people_snapshot(db: &Database) -> Option<(PeopleSnapshot, Vec<RetentionCohort>)> {
+    let snapshot = people_snapshots::table.order_by(people_snapshots::taken_on.desc()).first::<PeopleSnapshot>(&db.conn()).ok()?;
+    let cohorts = retention_cohorts::table
+        .filter(retention_cohorts::taken_on.eq(snapshot.taken_on))
+        .order_by(retention_cohorts::cohort)
+        .load::<RetentionCohort>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the retention cohorts for {} failed: {}", snapshot.taken_on, e));
+
+    Some((snapshot, cohorts))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+
+    use crate::retention::{compute_people_snapshot, Tenure};
+
+    fn tenure(start: (i32, u32, u32), end: Option<(i32, u32, u32)>) -> Tenure {
+        Tenure {
+            start: NaiveDate::from_ymd(start.0, start.1, start.2),
+            end: end.map(|e| NaiveDate::from_ymd(e.0, e.1, e.2)),
+        }
+    }
+
+    #[test]
+    fn test_compute_people_snapshot() {
+        let tenures = vec![
+            tenure((2017, 3, 1), None),
+            tenure((2019, 6, 1), None),
+            tenure((2019, 9, 1), Some((2020, 12, 1))),
+            tenure((2020, 2, 1), None),
+            tenure((2020, 8, 1), Some((2021, 3, 1))),
+            tenure((2021, 1, 4), None),
+        ];
+        let (snapshot, cohorts) = compute_people_snapshot(&tenures, NaiveDate::from_ymd(2021, 5, 17));
+
+        assert_eq!(snapshot.headcount, 4);
+        assert_eq!(snapshot.tenure_under_1y, 1);
+        assert_eq!(snapshot.tenure_1y_to_2y, 2);
+        assert_eq!(snapshot.tenure_2y_to_4y, 0);
+        assert_eq!(snapshot.tenure_over_4y, 1);
+        // 2019-06-01 and 2020-02-01 are the middle two, 716 and 471 days.
+        assert_eq!(snapshot.median_tenure_days, 593);
+        assert_eq!(snapshot.departures_12m, 2);
+        // There were 4 people a year ago and 4 now.
+        assert!((snapshot.attrition_12m - 50.0).abs() < 0.01);
+
+        let years: Vec<(i32, i32, Vec<i32>)> = cohorts.into_iter().map(|c| (c.cohort, c.started, c.retained)).collect();
+        assert_eq!(years, vec![(2017, 1, vec![1, 1, 1]), (2019, 2, vec![2]), (2020, 2, vec![]), (2021, 1, vec![])]);
+    }
+
+    #[test]
+    fn test_compute_people_snapshot_empty() {
+        let (snapshot, cohorts) = compute_people_snapshot(&[], NaiveDate::from_ymd(2021, 5, 17));
+        assert_eq!(snapshot.headcount, 0);
+        assert_eq!(snapshot.median_tenure_days, 0);
+        assert_eq!(snapshot.attrition_12m, 0.0);
+        assert!(cohorts.is_empty());
+    }
+}
@@ -0,0 +1,688 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use reqwest::Method;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::configs::{Config, UserConfig};
+use crate::db::Database;
+use crate::drive_permissions::{drive_api, get_folder_id};
+use crate::format::format_date;
+use crate::forms::GoogleFormResponse;
+use crate::messages::render_message;
+use crate::notifications::notify_user;
+use crate::schema::{review_assignments, review_cycles};
+use crate::utils::{drive_client, get_gsuite_token, GSUITE_DOMAIN};
+
+/// The kinds of reviews in a cycle, each has its own Google Form.
+pub static REVIEW_KINDS: &[&str] = &["self", "peer", "manager"];
+
+/// How we run review cycles, from the `reviews` section of our configs. Each kind of
+/// review is a Google Form with the same Apps Script as our other forms, so responses
+/// come in through the forms webhook and count towards the open cycle.
+///
+/// ```toml
+/// [reviews]
+/// self_form = "1FAIpQLSeSelf"
+/// peer_form = "1FAIpQLSePeer"
+/// manager_form = "1FAIpQLSeManager"
+/// drive = "People"
+/// folder = "Reviews"
+/// reminder_days = [7, 3, 1]
+///
+/// [reviews.managers]
+/// jane = "sam"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct ReviewsConfig {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub self_form: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub peer_form: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub manager_form: String,
+    /// The question on the peer and manager forms that asks who the review is for.
+    /// Defaults to `Who are you reviewing?`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub subject_question: String,
+    /// The manager of each person, by username. People without a manager only get self
+    /// and peer reviews.
+    #[serde(default)]
+    pub managers: BTreeMap<String, String>,
+    /// The shared drive and the folder in it we archive the reviews to when a cycle closes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub drive: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub folder: String,
+    /// How many days before a cycle closes we remind people of the reviews they have not
+    /// written. Defaults to 7, 3, and 1 days before.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reminder_days: Vec<i64>,
+    /// From how many days before a cycle closes the reminders also go to the manager of
+    /// the reviewer. Defaults to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalate_days: Option<i64>,
+}
+
+impl ReviewsConfig {
+    /// Get the kind of review a form is for, if it is one of ours.
+    #[instrument]
+    #[inline]
+    pub fn form_kind(&self, form_id: &str) -> Option<&'static str> {
+        if form_id.is_empty() {
+            return None;
+        }
+
+        if form_id == self.self_form {
+            Some("self")
+        } else if form_id == self.peer_form {
+            Some("peer")
+        } else if form_id == self.manager_form {
+            Some("manager")
+        } else {
+            None
+        }
+    }
+
+    /// Get the question that asks who the review is for.
+    #[instrument]
+    #[inline]
+    pub fn subject_question(&self) -> String {
+        if self.subject_question.is_empty() {
+            return "Who are you reviewing?".to_string();
+        }
+
+        self.subject_question.to_string()
+    }
+
+    /// Get the days before a cycle closes we send reminders on.
+    #[instrument]
+    #[inline]
+    pub fn reminder_days(&self) -> Vec<i64> {
+        if self.reminder_days.is_empty() {
+            return vec![7, 3, 1];
+        }
+
+        self.reminder_days.clone()
+    }
+
+    /// Get the days before a cycle closes the reminders also go to managers.
+    #[instrument]
+    #[inline]
+    pub fn escalate_days(&self) -> i64 {
+        self.escalate_days.unwrap_or(1)
+    }
+}
+
+/// Plan the reviews for a cycle: everyone reviews themselves and managers review the
+/// people they manage. Peer reviews are added by hand, since people pick their peers.
+/// Returns the kind, reviewer, and subject of each review.
+#[instrument(skip(config))]
+#[inline]
+pub fn plan_review_assignments(config: &ReviewsConfig, people: &[String]) -> Vec<(String, String, String)> {
+    let mut assignments: Vec<(String, String, String)> = Default::default();
+    for person in people {
+        assignments.push(("self".to_string(), person.to_string(), person.to_string()));
+        if let Some(manager) = config.managers.get(person) {
+            assignments.push(("manager".to_string(), manager.to_string(), person.to_string()));
+        }
+    }
+
+    assignments
+}
+
+/// Find the username of someone from how they were written on a form, which is their
+/// username, their email, or their full name.
+#[instrument(skip(users))]
+#[inline]
+pub fn resolve_reviewer(users: &BTreeMap<String, UserConfig>, text: &str) -> Option<String> {
+    let text = text.trim().to_lowercase();
+    let local = text.trim_end_matches(&format!("@{}", GSUITE_DOMAIN)).to_string();
+
+    users
+        .values()
+        .find(|u| u.username == local || u.aliases.iter().any(|a| *a == local) || format!("{} {}", u.first_name, u.last_name).to_lowercase() == text)
+        .map(|u| u.username.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "review_cycles"]
+pub struct NewReviewCycle {
+    pub name: String,
+    /// The username of who opened the cycle, they are told when it closes.
+    pub opened_by: String,
+    pub opened_at: DateTime<Utc>,
+    /// The last day to write reviews, the cycle closes the day after.
+    pub closes_on: NaiveDate,
+    pub closed_at: Option<DateTime<Utc>>,
+    /// The folder the reviews were archived to when the cycle closed.
+    pub archive_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct ReviewCycle {
+    pub id: i32,
+    pub name: String,
+    pub opened_by: String,
+    pub opened_at: DateTime<Utc>,
+    pub closes_on: NaiveDate,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub archive_url: String,
+}
+
+/// A review someone is to write about someone in a cycle, with their answers once they
+/// write it.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "review_assignments"]
+pub struct NewReviewAssignment {
+    pub cycle_id: i32,
+    /// One of `REVIEW_KINDS`.
+    pub kind: String,
+    /// The usernames of who writes the review and who it is about.
+    pub reviewer: String,
+    pub subject: String,
+    pub response_id: String,
+    /// The answers by the title of each question.
+    pub answers: Value,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// How many reminders we sent, and the day of the last one.
+    pub reminders: i32,
+    pub reminded_on: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct ReviewAssignment {
+    pub id: i32,
+    pub cycle_id: i32,
+    pub kind: String,
+    pub reviewer: String,
+    pub subject: String,
+    pub response_id: String,
+    pub answers: Value,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub reminders: i32,
+    pub reminded_on: Option<NaiveDate>,
+}
+
+impl ReviewAssignment {
+    /// Describe the review for the reviewer, ie. `peer review of sam`.
+    #[instrument]
+    #[inline]
+    pub fn describe(&self) -> String {
+        if self.kind == "self" {
+            return "self review".to_string();
+        }
+
+        format!("{} review of {}", self.kind, self.subject)
+    }
+}
+
+/// Get a review cycle by its name.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_review_cycle(db: &Database, name: &str) -> Result<ReviewCycle, String> {
+    review_cycles::table
+        .filter(review_cycles::name.eq(name))
+        .first::<ReviewCycle>(&db.conn())
+        .map_err(|_| format!("there is no review cycle named `{}`", name))
+}
+
+/// Get the reviews in a cycle.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_review_assignments(db: &Database, cycle_id: i32) -> Vec<ReviewAssignment> {
+    review_assignments::table
+        .filter(review_assignments::cycle_id.eq(cycle_id))
+        .order_by((review_assignments::subject, review_assignments::kind, review_assignments::reviewer))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the reviews for cycle {} failed: {}", cycle_id, e))
+}
+
+/// Add reviews to a cycle, leaving the ones it already has alone.
+#[instrument(skip(db))]
+#[inline]
+fn add_review_assignments(db: &Database, cycle_id: i32, assignments: &[(String, String, String)]) -> usize {
+    let rows: Vec<NewReviewAssignment> = assignments
+        .iter()
+        .map(|(kind, reviewer, subject)| NewReviewAssignment {
+            cycle_id,
+            kind: kind.to_string(),
+            reviewer: reviewer.to_string(),
+            subject: subject.to_string(),
+            response_id: String::new(),
+            answers: json!({}),
+            completed_at: None,
+            reminders: 0,
+            reminded_on: None,
+        })
+        .collect();
+
+    diesel::insert_into(review_assignments::table)
+        .values(&rows)
+        .on_conflict((review_assignments::cycle_id, review_assignments::kind, review_assignments::reviewer, review_assignments::subject))
+        .do_nothing()
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("adding the reviews to cycle {} failed: {}", cycle_id, e))
+}
+
+/// Open a review cycle, with a self review for everyone in our configs and a manager
+/// review for everyone with a manager. Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn open_review_cycle(db: &Database, config: &Config, name: &str, closes_on: NaiveDate, opened_by: &str) -> Result<String, String> {
+    if !config.users.contains_key(opened_by) {
+        return Err(format!("`{}` is not a user", opened_by));
+    }
+    if closes_on < Utc::now().date().naive_utc() {
+        return Err(format!("the cycle would close on {}, which has passed", format_date(closes_on)));
+    }
+    if let Some(open) = review_cycles::table.filter(review_cycles::closed_at.is_null()).first::<ReviewCycle>(&db.conn()).ok() {
+        return Err(format!("the `{}` review cycle is still open until {}", open.name, format_date(open.closes_on)));
+    }
+
+    let cycle: ReviewCycle = diesel::insert_into(review_cycles::table)
+        .values(&NewReviewCycle {
+            name: name.to_string(),
+            opened_by: opened_by.to_string(),
+            opened_at: Utc::now(),
+            closes_on,
+            closed_at: None,
+            archive_url: String::new(),
+        })
+        .get_result(&db.conn())
+        .map_err(|e| format!("opening the `{}` review cycle failed: {}", name, e))?;
+
+    let people: Vec<String> = config.users.values().filter(|u| u.typev != "system account").map(|u| u.username.to_string()).collect();
+    let added = add_review_assignments(db, cycle.id, &plan_review_assignments(&config.reviews, &people));
+
+    Ok(format!(
+        "opened the `{}` review cycle with {} reviews for {} people, it closes after {}",
+        name,
+        added,
+        people.len(),
+        format_date(closes_on)
+    ))
+}
+
+/// Add peer reviewers for someone in an open cycle. Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn add_peer_reviewers(db: &Database, config: &Config, name: &str, subject: &str, peers: &[String]) -> Result<String, String> {
+    let cycle = get_review_cycle(db, name)?;
+    if cycle.closed_at.is_some() {
+        return Err(format!("the `{}` review cycle is closed", name));
+    }
+    for username in peers.iter().chain(std::iter::once(&subject.to_string())) {
+        if !config.users.contains_key(username) {
+            return Err(format!("`{}` is not a user", username));
+        }
+    }
+
+    let assignments: Vec<(String, String, String)> = peers.iter().map(|p| ("peer".to_string(), p.to_string(), subject.to_string())).collect();
+    let added = add_review_assignments(db, cycle.id, &assignments);
+
+    Ok(format!("added {} peer reviewers for {} to the `{}` review cycle", added, subject, name))
+}
+
+/// Record a response to one of our review forms against the open cycle. Responses
+/// after a cycle closes are refused, since its reviews are already archived. Returns
+/// what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn record_review_response(db: &Database, config: &Config, response: &GoogleFormResponse, kind: &str) -> Result<String, String> {
+    let cycle = review_cycles::table
+        .filter(review_cycles::closed_at.is_null())
+        .first::<ReviewCycle>(&db.conn())
+        .map_err(|_| format!("there is no open review cycle for the {} review from {}", kind, response.respondent_email))?;
+
+    let reviewer =
+        resolve_reviewer(&config.users, &response.respondent_email).ok_or_else(|| format!("{} review form responses must collect emails, {} is not a user", kind, response.respondent_email))?;
+    let subject = if kind == "self" {
+        reviewer.to_string()
+    } else {
+        let question = config.reviews.subject_question();
+        let answer = response.answers.get(&question).and_then(|a| a.first()).cloned().unwrap_or_default();
+        resolve_reviewer(&config.users, &answer).ok_or_else(|| format!("the answer to `{}` from {} is `{}`, which is not a user", question, reviewer, answer))?
+    };
+
+    // Peers can review someone they were not asked to, so there might not be a review
+    // to complete yet.
+    let review = NewReviewAssignment {
+        cycle_id: cycle.id,
+        kind: kind.to_string(),
+        reviewer: reviewer.to_string(),
+        subject: subject.to_string(),
+        response_id: response.response_id.to_string(),
+        answers: json!(response.answers),
+        completed_at: Some(response.timestamp),
+        reminders: 0,
+        reminded_on: None,
+    };
+    diesel::insert_into(review_assignments::table)
+        .values(&review)
+        .on_conflict((review_assignments::cycle_id, review_assignments::kind, review_assignments::reviewer, review_assignments::subject))
+        .do_update()
+        .set((
+            review_assignments::response_id.eq(&review.response_id),
+            review_assignments::answers.eq(&review.answers),
+            review_assignments::completed_at.eq(&review.completed_at),
+        ))
+        .execute(&db.conn())
+        .map_err(|e| format!("recording the {} review of {} by {} failed: {}", kind, subject, reviewer, e))?;
+
+    Ok(format!("recorded the {} review of {} by {} in the `{}` cycle", kind, subject, reviewer, cycle.name))
+}
+
+/// Format the reviews about someone as a document for the archive, with the answers to
+/// each review in the order of the questions.
+#[instrument(skip(reviews))]
+#[inline]
+pub fn format_review_packet(cycle: &str, name: &str, reviews: &[&ReviewAssignment]) -> String {
+    let mut text = format!("# {} reviews of {}\n", cycle, name);
+    for review in reviews.iter().filter(|r| r.completed_at.is_some()) {
+        let title = if review.kind == "self" {
+            "Self review".to_string()
+        } else {
+            format!("{} review by {}", review.kind[..1].to_uppercase() + &review.kind[1..], review.reviewer)
+        };
+        text += &format!("\n## {}\n", title);
+
+        let answers: BTreeMap<String, Vec<String>> = serde_json::from_value(review.answers.clone()).unwrap_or_default();
+        for (question, answer) in answers {
+            text += &format!("\n**{}**\n\n{}\n", question, answer.join(", "));
+        }
+    }
+
+    text
+}
+
+/// Archive the reviews in a cycle to a folder for the cycle in our configs, a read-only
+/// document per person. Returns the link to the folder.
+#[instrument(skip(config, cycle, reviews))]
+#[inline]
+async fn archive_reviews(config: &Config, cycle: &ReviewCycle, reviews: &[ReviewAssignment]) -> Result<String, String> {
+    let token = get_gsuite_token("").await;
+    let drive_client = drive_client(token.clone());
+    let token = token.as_str();
+
+    let drive_id = drive_client
+        .get_drive_by_name(&config.reviews.drive)
+        .await
+        .map_err(|e| format!("getting the shared drive `{}` failed: {}", config.reviews.drive, e))?
+        .id;
+    let parent_id = get_folder_id(token, &drive_id, &config.reviews.folder).await?;
+    let folder = drive_api(
+        token,
+        Method::POST,
+        "/files",
+        &[],
+        Some(json!({ "name": cycle.name, "mimeType": "application/vnd.google-apps.folder", "parents": [parent_id] })),
+    )
+    .await?;
+    let folder_id = folder["id"].as_str().unwrap_or_default().to_string();
+
+    let mut by_subject: BTreeMap<&str, Vec<&ReviewAssignment>> = Default::default();
+    for review in reviews.iter().filter(|r| r.completed_at.is_some()) {
+        by_subject.entry(&review.subject).or_default().push(review);
+    }
+    for (subject, reviews) in by_subject {
+        let name = config.users.get(subject).map(|u| format!("{} {}", u.first_name, u.last_name)).unwrap_or_else(|| subject.to_string());
+        let filename = format!("{} reviews of {}.md", cycle.name, name);
+        let packet = format_review_packet(&cycle.name, &name, &reviews);
+        drive_client
+            .create_or_upload_file(&drive_id, &folder_id, &filename, "text/markdown", packet.as_bytes())
+            .await
+            .map_err(|e| format!("uploading {} failed: {}", filename, e))?;
+
+        // Lock the document so the reviews stay as they were written.
+        let files = drive_client.get_file_by_name(&drive_id, &filename).await.map_err(|e| format!("finding {} failed: {}", filename, e))?;
+        if let Some(file) = files.get(0) {
+            drive_api(
+                token,
+                Method::PATCH,
+                &format!("/files/{}", file.id),
+                &[],
+                Some(json!({ "contentRestrictions": [{ "readOnly": true, "reason": format!("The {} review cycle is closed", cycle.name) }] })),
+            )
+            .await?;
+        }
+    }
+
+    Ok(format!("https://drive.google.com/drive/folders/{}", folder_id))
+}
+
+/// Close a review cycle: refuse any more responses, archive its reviews, and tell the
+/// person who opened it which reviews were never written. Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn close_review_cycle(db: &Database, config: &Config, name: &str) -> Result<String, String> {
+    let cycle = get_review_cycle(db, name)?;
+    if cycle.closed_at.is_some() {
+        return Err(format!("the `{}` review cycle is already closed, its reviews are in {}", name, cycle.archive_url));
+    }
+
+    // Close it before we archive, so responses that come in while we do are refused
+    // rather than left out of the archive.
+    diesel::update(review_cycles::table.find(cycle.id))
+        .set(review_cycles::closed_at.eq(Some(Utc::now())))
+        .execute(&db.conn())
+        .map_err(|e| format!("closing the `{}` review cycle failed: {}", name, e))?;
+
+    let reviews = get_review_assignments(db, cycle.id);
+    let archive_url = archive_reviews(config, &cycle, &reviews).await?;
+    diesel::update(review_cycles::table.find(cycle.id))
+        .set(review_cycles::archive_url.eq(&archive_url))
+        .execute(&db.conn())
+        .map_err(|e| format!("saving the archive of the `{}` review cycle failed: {}", name, e))?;
+
+    let written = reviews.iter().filter(|r| r.completed_at.is_some()).count();
+    let lines: Vec<String> = reviews.iter().filter(|r| r.completed_at.is_none()).map(|r| format!("• {}: {}", r.reviewer, r.describe())).collect();
+    if let Some(opener) = config.users.get(&cycle.opened_by) {
+        notify_user(
+            db,
+            &opener.email(),
+            json!({
+                "text": render_message("reviews.closed", &json!({ "title": cycle.name, "count": written, "url": archive_url, "lines": lines })),
+            }),
+        )
+        .await;
+    }
+
+    Ok(format!("closed the `{}` review cycle and archived {} reviews to {}", name, written, archive_url))
+}
+
+/// Remind people of the reviews they have not written on the days before the open cycle
+/// closes, and tell their managers too once it is close, then close the cycle once its
+/// last day has passed.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn refresh_review_cycles(db: &Database, config: &Config) {
+    let cycle = match review_cycles::table.filter(review_cycles::closed_at.is_null()).first::<ReviewCycle>(&db.conn()) {
+        Ok(c) => c,
+        Err(_) => return,
+    };
+
+    let today = Utc::now().date().naive_utc();
+    if cycle.closes_on < today {
+        match close_review_cycle(db, config, &cycle.name).await {
+            Ok(done) => println!("[reviews] {}", done),
+            Err(e) => println!("[reviews] {}", e),
+        }
+        return;
+    }
+
+    let days_left = (cycle.closes_on - today).num_days();
+    if !config.reviews.reminder_days().contains(&days_left) {
+        return;
+    }
+
+    let mut by_reviewer: BTreeMap<String, Vec<ReviewAssignment>> = Default::default();
+    for review in get_review_assignments(db, cycle.id) {
+        if review.completed_at.is_none() && review.reminded_on != Some(today) {
+            by_reviewer.entry(review.reviewer.to_string()).or_default().push(review);
+        }
+    }
+
+    let when = if days_left == 0 { "today".to_string() } else { format!("after {}", format_date(cycle.closes_on)) };
+    for (reviewer, reviews) in by_reviewer {
+        let lines: Vec<String> = reviews.iter().map(|r| format!("• {}", r.describe())).collect();
+        notify_user(
+            db,
+            &format!("{}@{}", reviewer, GSUITE_DOMAIN),
+            json!({
+                "text": render_message("reviews.reminder", &json!({ "title": cycle.name, "when": when, "lines": lines })),
+            }),
+        )
+        .await;
+
+        if days_left <= config.reviews.escalate_days() {
+            if let Some(manager) = config.reviews.managers.get(&reviewer) {
+                let name = config.users.get(&reviewer).map(|u| format!("{} {}", u.first_name, u.last_name)).unwrap_or_else(|| reviewer.to_string());
+                notify_user(
+                    db,
+                    &format!("{}@{}", manager, GSUITE_DOMAIN),
+                    json!({
+                        "text": render_message("reviews.escalation", &json!({ "title": cycle.name, "when": when, "name": name, "lines": lines })),
+                    }),
+                )
+                .await;
+            }
+        }
+
+        let ids: Vec<i32> = reviews.iter().map(|r| r.id).collect();
+        diesel::update(review_assignments::table.filter(review_assignments::id.eq_any(ids)))
+            .set((review_assignments::reminders.eq(review_assignments::reminders + 1), review_assignments::reminded_on.eq(Some(today))))
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("saving the reminders for {} failed: {}", reviewer, e));
+    }
+}
+
+/// Format who has written their reviews in a cycle for the command line.
+#[instrument(skip(db))]
+#[inline]
+pub fn format_review_cycle(db: &Database, name: &str) -> Result<String, String> {
+    let cycle = get_review_cycle(db, name)?;
+    let reviews = get_review_assignments(db, cycle.id);
+
+    let mut by_reviewer: BTreeMap<&str, Vec<&ReviewAssignment>> = Default::default();
+    for review in reviews.iter() {
+        by_reviewer.entry(&review.reviewer).or_default().push(review);
+    }
+
+    let written = reviews.iter().filter(|r| r.completed_at.is_some()).count();
+    let mut text = match &cycle.closed_at {
+        Some(closed_at) => format!(
+            "The `{}` review cycle closed {}, {} of {} reviews were written: {}\n",
+            name,
+            format_date(closed_at.date().naive_utc()),
+            written,
+            reviews.len(),
+            cycle.archive_url
+        ),
+        None => format!(
+            "The `{}` review cycle closes after {}, {} of {} reviews are written.\n",
+            name,
+            format_date(cycle.closes_on),
+            written,
+            reviews.len()
+        ),
+    };
+    for (reviewer, reviews) in by_reviewer {
+        let missing: Vec<String> = reviews.iter().filter(|r| r.completed_at.is_none()).map(|r| r.describe()).collect();
+        text += &format!("• {}: {} of {} written", reviewer, reviews.len() - missing.len(), reviews.len());
+        if !missing.is_empty() {
+            text += &format!(", missing the {}", missing.join(", "));
+        }
+        text += "\n";
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::naive::NaiveDate;
+    use chrono::{TimeZone, Utc};
+
+    use crate::reviews::{format_review_packet, plan_review_assignments, ReviewAssignment, ReviewsConfig};
+
+    #[test]
+    fn test_reviews_config() {
+        let config: ReviewsConfig = toml::from_str(
+            r#"self_form = "1FAIpQLSeSelf"
+peer_form = "1FAIpQLSePeer"
+manager_form = "1FAIpQLSeManager"
+
+[managers]
+jane = "sam"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.form_kind("1FAIpQLSePeer"), Some("peer"));
+        assert_eq!(config.form_kind("1FAIpQLSeLaptop"), None);
+        assert_eq!(config.form_kind(""), None);
+        assert_eq!(config.subject_question(), "Who are you reviewing?");
+        assert_eq!(config.reminder_days(), vec![7, 3, 1]);
+        assert_eq!(config.escalate_days(), 1);
+
+        assert_eq!(
+            plan_review_assignments(&config, &["jane".to_string(), "sam".to_string()]),
+            vec![
+                ("self".to_string(), "jane".to_string(), "jane".to_string()),
+                ("manager".to_string(), "sam".to_string(), "jane".to_string()),
+                ("self".to_string(), "sam".to_string(), "sam".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_review_packet() {
+        let review = |kind: &str, reviewer: &str, answers: serde_json::Value, written: bool| ReviewAssignment {
+            id: 1,
+            cycle_id: 1,
+            kind: kind.to_string(),
+            reviewer: reviewer.to_string(),
+            subject: "jane".to_string(),
+            response_id: "2_ABaOnud".to_string(),
+            answers,
+            completed_at: if written { Some(Utc.ymd(2021, 6, 1).and_hms(17, 0, 0)) } else { None },
+            reminders: 0,
+            reminded_on: Some(NaiveDate::from_ymd(2021, 5, 31)),
+        };
+        let reviews = vec![
+            review("self", "jane", json!({ "What went well?": ["Shipping the rack"] }), true),
+            review("peer", "sam", json!({ "What went well?": ["Debugging"], "What could be better?": ["Docs"] }), true),
+            review("manager", "ann", json!({}), false),
+        ];
+
+        assert_eq!(
+            format_review_packet("2021-h1", "Jane Doe", &reviews.iter().collect::<Vec<_>>()),
+            "# 2021-h1 reviews of Jane Doe
+
+## Self review
+
+**What went well?**
+
+Shipping the rack
+
+## Peer review by sam
+
+**What could be better?**
+
+Docs
+
+**What went well?**
+
+Debugging
+"
+        );
+        assert_eq!(reviews[2].describe(), "manager review of jane");
+        assert_eq!(reviews[0].describe(), "self review");
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+use hubcaps::Github;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::configs::{User, Users};
+use crate::db::Database;
+use crate::models::RFDs;
+use crate::schema::rfd_authors;
+use crate::utils::{create_or_update_file_in_github_repo, github_api_list, github_org, GSUITE_DOMAIN};
+
+/// Where we publish the contribution stats for RFD authors in the rfd repo, for the
+/// internal site.
+const RFD_AUTHOR_STATS_PATH: &str = "src/public/static/rfd-authors.json";
+
+/// An author from the `authors` line in the frontmatter of an RFD, ie.
+/// `Jess <jess@oxide.computer>`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RFDAuthorLine {
+    pub name: String,
+    pub email: String,
+}
+
+/// The author of a commit that changed an RFD.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RFDCommitAuthor {
+    pub name: String,
+    pub email: String,
+    /// The GitHub login of the author, if we know it.
+    pub github: String,
+}
+
+/// A person in the user directory, with the ways they might show up as the author of an
+/// RFD.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DirectoryPerson {
+    pub name: String,
+    pub username: String,
+    pub github: String,
+    /// The email addresses of the person, their primary email first.
+    pub emails: Vec<String>,
+}
+
+impl From<&User> for DirectoryPerson {
+    fn from(user: &User) -> Self {
+        let mut emails = vec![user.email()];
+        emails.extend(user.aliases.iter().map(|a| format!("{}@{}", a, GSUITE_DOMAIN)));
+        if !user.recovery_email.is_empty() {
+            emails.push(user.recovery_email.to_string());
+        }
+
+        DirectoryPerson {
+            name: user.full_name(),
+            username: user.username.to_string(),
+            github: user.github.to_string(),
+            emails,
+        }
+    }
+}
+
+/// An author of an RFD, from the frontmatter, the git history, or both.
+#[derive(Debug, Default, Clone, PartialEq, Insertable)]
+#[table_name = "rfd_authors"]
+pub struct NewRFDAuthor {
+    pub rfd_number: i32,
+    /// The full name of the author, from the user directory if we found them there.
+    pub name: String,
+    pub email: String,
+    /// The username of the author in the user directory, empty if we did not find them.
+    pub username: String,
+    pub github: String,
+    /// Whether the author is in the `authors` line of the RFD.
+    pub in_frontmatter: bool,
+    /// How many commits the author made to the RFD.
+    pub commits: i32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Queryable)]
+pub struct RFDAuthor {
+    pub id: i32,
+    pub rfd_number: i32,
+    pub name: String,
+    pub email: String,
+    pub username: String,
+    pub github: String,
+    pub in_frontmatter: bool,
+    pub commits: i32,
+}
+
+/// How much a person contributed to RFDs.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RFDAuthorStats {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub email: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub github: String,
+    /// The RFDs the person is an author of.
+    #[serde(default)]
+    pub authored: Vec<i32>,
+    /// The RFDs the person committed to without being an author.
+    #[serde(default)]
+    pub contributed: Vec<i32>,
+    /// How many commits the person made to RFDs.
+    pub commits: i32,
+    /// How many of the RFDs the person authored are in each state.
+    #[serde(default)]
+    pub states: BTreeMap<String, i32>,
+}
+
+/// Parse the authors from the `authors` line of an RFD, ie.
+/// `Jess <jess@oxide.computer>, Joe and Jane <jane@oxide.computer>`.
+#[instrument]
+#[inline]
+pub fn parse_rfd_authors(authors: &str) -> Vec<RFDAuthorLine> {
+    let split = Regex::new(r"\s*(?:,|;|&|\band\b)\s*").unwrap();
+    let re = Regex::new(r"^(.*?)\s*<([^>]*)>$").unwrap();
+
+    split
+        .split(authors.trim())
+        .map(|a| a.trim())
+        .filter(|a| !a.is_empty())
+        .map(|a| match re.captures(a) {
+            Some(cap) => RFDAuthorLine {
+                name: cap[1].trim().to_string(),
+                email: cap[2].trim().to_string(),
+            },
+            None if a.contains('@') => RFDAuthorLine {
+                name: Default::default(),
+                email: a.to_string(),
+            },
+            None => RFDAuthorLine {
+                name: a.to_string(),
+                email: Default::default(),
+            },
+        })
+        .collect()
+}
+
+/// Find the person in the user directory an author is, by their email, GitHub login, or
+/// name, in that order.
+fn find_person<'a>(people: &'a [DirectoryPerson], name: &str, email: &str, github: &str) -> Option<&'a DirectoryPerson> {
+    let (name, email, github) = (name.trim().to_lowercase(), email.trim().to_lowercase(), github.trim().to_lowercase());
+
+    people
+        .iter()
+        .find(|p| !email.is_empty() && p.emails.iter().any(|e| e.to_lowercase() == email))
+        .or_else(|| people.iter().find(|p| !github.is_empty() && p.github.to_lowercase() == github))
+        .or_else(|| people.iter().find(|p| !name.is_empty() && (p.name.to_lowercase() == name || p.username.to_lowercase() == name)))
+}
+
+/// The GitHub login in a GitHub noreply email, ie. `jessfraz` for
+/// `1445228+jessfraz@users.noreply.github.com`.
+fn github_from_noreply_email(email: &str) -> String {
+    match email.strip_suffix("@users.noreply.github.com") {
+        Some(user) => user.rsplit('+').next().unwrap_or_default().to_string(),
+        None => String::new(),
+    }
+}
+
+/// Get the author of an RFD for a name, email and GitHub login, adding them if they are
+/// not there yet. Authors we found in the user directory are keyed on their username, and
+/// the rest on their name.
+fn author_entry<'a>(authors: &'a mut BTreeMap<String, NewRFDAuthor>, number: i32, people: &[DirectoryPerson], name: &str, email: &str, github: &str) -> &'a mut NewRFDAuthor {
+    let github = if github.is_empty() { github_from_noreply_email(email) } else { github.to_string() };
+    let new = match find_person(people, name, email, &github) {
+        Some(p) => NewRFDAuthor {
+            rfd_number: number,
+            name: p.name.to_string(),
+            email: p.emails.first().cloned().unwrap_or_default(),
+            username: p.username.to_string(),
+            github: p.github.to_string(),
+            ..Default::default()
+        },
+        None => NewRFDAuthor {
+            rfd_number: number,
+            name: if name.is_empty() { email.to_string() } else { name.to_string() },
+            email: email.to_string(),
+            github,
+            ..Default::default()
+        },
+    };
+
+    let key = if new.username.is_empty() { new.name.to_lowercase() } else { new.username.to_string() };
+    let existing = authors.entry(key).or_insert_with(|| new.clone());
+    // Fill in what we learn about people we did not find in the directory.
+    if existing.email.is_empty() {
+        existing.email = new.email;
+    }
+    if existing.github.is_empty() {
+        existing.github = new.github;
+    }
+    existing
+}
+
+/// Work out the authors of an RFD from the authors in its frontmatter and the authors of
+/// the commits that changed it, as the people they are in the user directory. Commits from
+/// bots are left out.
+#[instrument(skip(frontmatter, commits, people))]
+#[inline]
+pub fn normalize_rfd_authors(number: i32, frontmatter: &[RFDAuthorLine], commits: &[RFDCommitAuthor], people: &[DirectoryPerson]) -> Vec<NewRFDAuthor> {
+    let mut authors: BTreeMap<String, NewRFDAuthor> = Default::default();
+    for a in frontmatter {
+        author_entry(&mut authors, number, people, &a.name, &a.email, "").in_frontmatter = true;
+    }
+    for c in commits {
+        if c.name.ends_with("[bot]") || c.github.ends_with("[bot]") {
+            continue;
+        }
+        author_entry(&mut authors, number, people, &c.name, &c.email, &c.github).commits += 1;
+    }
+
+    authors.values().cloned().collect()
+}
+
+/// Work out how much each person contributed to RFDs from the authors of each RFD and the
+/// state each RFD is in. The people who authored the most RFDs come first.
+#[instrument(skip(authors, states))]
+#[inline]
+pub fn build_rfd_author_stats(authors: &[RFDAuthor], states: &BTreeMap<i32, String>) -> Vec<RFDAuthorStats> {
+    let mut stats: BTreeMap<String, RFDAuthorStats> = Default::default();
+    for a in authors {
+        let key = if a.username.is_empty() { a.name.to_lowercase() } else { a.username.to_string() };
+        let s = stats.entry(key).or_insert_with(|| RFDAuthorStats {
+            name: a.name.to_string(),
+            username: a.username.to_string(),
+            email: a.email.to_string(),
+            github: a.github.to_string(),
+            ..Default::default()
+        });
+
+        s.commits += a.commits;
+        if a.in_frontmatter {
+            s.authored.push(a.rfd_number);
+            if let Some(state) = states.get(&a.rfd_number).filter(|state| !state.is_empty()) {
+                *s.states.entry(state.to_string()).or_default() += 1;
+            }
+        } else {
+            s.contributed.push(a.rfd_number);
+        }
+    }
+
+    let mut stats: Vec<RFDAuthorStats> = stats
+        .into_iter()
+        .map(|(_, mut s)| {
+            s.authored.sort_unstable();
+            s.contributed.sort_unstable();
+            s
+        })
+        .collect();
+    stats.sort_by(|a, b| b.authored.len().cmp(&a.authored.len()).then(b.commits.cmp(&a.commits)).then(a.name.cmp(&b.name)));
+
+    stats
+}
+
+/// Get the people in the user directory, leaving out system accounts.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_directory_people(db: &Database) -> Vec<DirectoryPerson> {
+    Users::get_from_db(db).into_iter().filter(|u| !u.is_system_account()).map(|u| DirectoryPerson::from(&u)).collect()
+}
+
+/// Get the authors of the commits on a branch that changed the directory for an RFD, from
+/// the GitHub API.
+#[instrument]
+#[inline]
+pub async fn get_rfd_commit_authors(branch: &str, number_string: &str) -> Vec<RFDCommitAuthor> {
+    let path = format!("/repos/{}/rfd/commits?sha={}&path=rfd/{}", github_org(), branch, number_string);
+    match github_api_list::<serde_json::Value>(&path).await {
+        Ok(commits) => commits
+            .iter()
+            .map(|c| RFDCommitAuthor {
+                name: c["commit"]["author"]["name"].as_str().unwrap_or_default().to_string(),
+                email: c["commit"]["author"]["email"].as_str().unwrap_or_default().to_string(),
+                github: c["author"]["login"].as_str().unwrap_or_default().to_string(),
+            })
+            .collect(),
+        Err(e) => {
+            println!("[rfd] getting the commits for RFD {} failed: {}", number_string, e);
+            vec![]
+        }
+    }
+}
+
+/// Replace the authors of an RFD in the database with the ones from its `authors` line
+/// and the commits that changed it.
+#[instrument(skip(db, commits, people))]
+#[inline]
+pub fn update_rfd_authors(db: &Database, number: i32, authors: &str, commits: &[RFDCommitAuthor], people: &[DirectoryPerson]) {
+    let rows = normalize_rfd_authors(number, &parse_rfd_authors(authors), commits, people);
+    let conn = db.conn();
+
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        diesel::delete(rfd_authors::dsl::rfd_authors.filter(rfd_authors::dsl::rfd_number.eq(number))).execute(&conn)?;
+        diesel::insert_into(rfd_authors::table).values(&rows).execute(&conn)?;
+        Ok(())
+    })
+    .unwrap_or_else(|e| panic!("updating the authors for RFD {} failed: {}", number, e));
+}
+
+/// Get how much each person contributed to RFDs from the database.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_rfd_author_stats(db: &Database) -> Vec<RFDAuthorStats> {
+    let authors = rfd_authors::dsl::rfd_authors
+        .load::<RFDAuthor>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the rfd authors failed: {}", e));
+    let states: BTreeMap<i32, String> = RFDs::get_from_db(db).into_iter().map(|r| (r.number, r.state)).collect();
+
+    build_rfd_author_stats(&authors, &states)
+}
+
+/// Publish how much each person contributed to RFDs to the rfd repo, for the internal
+/// site.
+#[instrument(skip(db, github))]
+#[inline]
+pub async fn publish_rfd_author_stats(db: &Database, github: &Github) {
+    let stats = get_rfd_author_stats(db);
+
+    let repo = github.repo(github_org(), "rfd");
+    let r = repo.get().await.unwrap();
+    create_or_update_file_in_github_repo(&repo, &r.default_branch, RFD_AUTHOR_STATS_PATH, serde_json::to_vec_pretty(&stats).unwrap()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::rfd_authors::{build_rfd_author_stats, normalize_rfd_authors, parse_rfd_authors, DirectoryPerson, RFDAuthor, RFDAuthorLine, RFDCommitAuthor};
+
+    fn person(name: &str, username: &str, github: &str, emails: &[&str]) -> DirectoryPerson {
+        DirectoryPerson {
+            name: name.to_string(),
+            username: username.to_string(),
+            github: github.to_string(),
+            emails: emails.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    fn commit(name: &str, email: &str, github: &str) -> RFDCommitAuthor {
+        RFDCommitAuthor {
+            name: name.to_string(),
+            email: email.to_string(),
+            github: github.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_rfd_authors() {
+        let authors = parse_rfd_authors("Jess Frazelle <jess@oxide.computer>, Andy and jane@example.com; Bo <bo@example.com>");
+        assert_eq!(
+            authors,
+            vec![
+                RFDAuthorLine {
+                    name: "Jess Frazelle".to_string(),
+                    email: "jess@oxide.computer".to_string(),
+                },
+                RFDAuthorLine {
+                    name: "Andy".to_string(),
+                    email: "".to_string(),
+                },
+                RFDAuthorLine {
+                    name: "".to_string(),
+                    email: "jane@example.com".to_string(),
+                },
+                RFDAuthorLine {
+                    name: "Bo".to_string(),
+                    email: "bo@example.com".to_string(),
+                },
+            ]
+        );
+        assert!(parse_rfd_authors("  ").is_empty());
+    }
+
+    #[test]
+    fn test_normalize_rfd_authors() {
+        let people = vec![
+            person("Jess Frazelle", "jess", "jessfraz", &["jess@oxide.computer", "jess@example.com"]),
+            person("Andy Smith", "andy", "asmith", &["andy@oxide.computer"]),
+        ];
+        let frontmatter = parse_rfd_authors("jess frazelle, Pat <pat@example.com>");
+        let commits = vec![
+            commit("Jess", "jess@example.com", ""),
+            commit("A. Smith", "1234+asmith@users.noreply.github.com", ""),
+            commit("Pat", "pat@home.example.com", "patgh"),
+            commit("dependabot[bot]", "support@github.com", "dependabot[bot]"),
+        ];
+
+        let authors = normalize_rfd_authors(7, &frontmatter, &commits, &people);
+        assert_eq!(authors.len(), 3);
+
+        let andy = authors.iter().find(|a| a.username == "andy").unwrap();
+        assert_eq!(
+            (andy.name.as_str(), andy.email.as_str(), andy.in_frontmatter, andy.commits),
+            ("Andy Smith", "andy@oxide.computer", false, 1)
+        );
+        let jess = authors.iter().find(|a| a.username == "jess").unwrap();
+        assert_eq!((jess.name.as_str(), jess.github.as_str(), jess.in_frontmatter, jess.commits), ("Jess Frazelle", "jessfraz", true, 1));
+        let pat = authors.iter().find(|a| a.name == "Pat").unwrap();
+        assert_eq!(
+            (pat.email.as_str(), pat.github.as_str(), pat.username.as_str(), pat.in_frontmatter, pat.commits),
+            ("pat@example.com", "patgh", "", true, 1)
+        );
+        assert!(authors.iter().all(|a| a.rfd_number == 7));
+    }
+
+    #[test]
+    fn test_build_rfd_author_stats() {
+        let author = |rfd_number: i32, name: &str, username: &str, in_frontmatter: bool, commits: i32| RFDAuthor {
+            rfd_number,
+            name: name.to_string(),
+            username: username.to_string(),
+            in_frontmatter,
+            commits,
+            ..Default::default()
+        };
+        let authors = vec![
+            author(2, "Jess Frazelle", "jess", true, 4),
+            author(1, "Jess Frazelle", "jess", true, 1),
+            author(3, "Jess Frazelle", "jess", false, 2),
+            author(1, "Pat", "", true, 3),
+        ];
+        let mut states: BTreeMap<i32, String> = Default::default();
+        states.insert(1, "published".to_string());
+        states.insert(2, "discussion".to_string());
+
+        let stats = build_rfd_author_stats(&authors, &states);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].username, "jess");
+        assert_eq!(stats[0].authored, vec![1, 2]);
+        assert_eq!(stats[0].contributed, vec![3]);
+        assert_eq!(stats[0].commits, 7);
+        assert_eq!(stats[0].states.get("published"), Some(&1));
+        assert_eq!(stats[0].states.get("discussion"), Some(&1));
+        assert_eq!(stats[1].name, "Pat");
+        assert_eq!(stats[1].authored, vec![1]);
+    }
+}
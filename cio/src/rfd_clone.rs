@@ -0,0 +1,411 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chrono::offset::Utc;
+use chrono::{DateTime, TimeZone};
+use git2::build::RepoBuilder;
+use git2::{BranchType, Commit, Cred, DiffOptions, FetchOptions, FetchPrune, ObjectType, Oid, RemoteCallbacks, Repository, Sort, TreeWalkMode, TreeWalkResult};
+use tracing::instrument;
+
+use crate::models::NewRFD;
+use crate::rfd_authors::RFDCommitAuthor;
+use crate::rfds::{is_image, parse_rfd_csv, RFDImage};
+use crate::utils::github_org;
+
+/// The file in the git directory of the clone with the commit each branch was at when we
+/// last synced, so the next sync only expands the RFDs that changed.
+const LAST_SYNCED_FILE: &str = "cio-last-synced.json";
+
+/// The path of the RFD csv in the rfd repo.
+const RFD_CSV_PATH: &str = ".helpers/rfd.csv";
+
+/// A bare clone of the rfd repo on disk. Syncing from a clone is a lot faster than the
+/// GitHub contents API for large RFD repos and does not eat into our rate limit. We read
+/// files straight from the commits of each branch, so there is no working tree to update.
+pub struct RFDClone {
+    pub repo: Repository,
+    pub default_branch: String,
+}
+
+/// Get the number of the RFD a path in the rfd repo belongs to, ie. 42 for
+/// `rfd/0042/README.adoc`.
+#[instrument]
+#[inline]
+pub fn rfd_number_from_path(path: &str) -> Option<i32> {
+    let mut parts = path.trim_start_matches('/').split('/');
+    if parts.next() != Some("rfd") {
+        return None;
+    }
+
+    parts.next()?.parse().ok()
+}
+
+/// Clone the rfd repo into a directory, or fetch every branch if we cloned it there
+/// already. git2 can not make shallow clones, so the first sync clones the whole history
+/// and every sync after only fetches what is new.
+/// This authenticates with the `GITHUB_TOKEN` environment variable.
+#[instrument]
+#[inline]
+pub fn open_rfd_clone(dir: &str, default_branch: &str) -> Result<RFDClone, String> {
+    let token = env::var("GITHUB_TOKEN").unwrap_or_default();
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |_url, _username, _allowed| Cred::userpass_plaintext("x-access-token", &token));
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+    // Forget the branches that were deleted, ie. after an RFD was merged.
+    fetch_options.prune(FetchPrune::On);
+
+    let repo = if Path::new(dir).join("HEAD").exists() {
+        let repo = Repository::open_bare(dir).map_err(|e| format!("opening the rfd clone in {} failed: {}", dir, e))?;
+        {
+            let mut remote = repo.find_remote("origin").map_err(|e| format!("finding the origin of the rfd clone in {} failed: {}", dir, e))?;
+            remote
+                .fetch(&["+refs/heads/*:refs/remotes/origin/*"], Some(&mut fetch_options), None)
+                .map_err(|e| format!("fetching the rfd repo into {} failed: {}", dir, e))?;
+        }
+        repo
+    } else {
+        println!("[rfd] cloning the rfd repo into {}, this takes a while the first time", dir);
+        RepoBuilder::new()
+            .bare(true)
+            .fetch_options(fetch_options)
+            .clone(&format!("https://github.com/{}/rfd.git", github_org()), Path::new(dir))
+            .map_err(|e| format!("cloning the rfd repo into {} failed: {}", dir, e))?
+    };
+
+    Ok(RFDClone {
+        repo,
+        default_branch: default_branch.to_string(),
+    })
+}
+
+impl RFDClone {
+    /// The commit a branch is at, ie. `0042`.
+    fn branch_commit(&self, branch: &str) -> Option<Commit> {
+        self.repo.find_reference(&format!("refs/remotes/origin/{}", branch)).ok()?.peel_to_commit().ok()
+    }
+
+    /// Read a file at a commit, returning the contents and the sha of the file. The sha is
+    /// the same one the GitHub contents API returns.
+    fn read_file_at(&self, commit: &Commit, path: &str) -> Option<(Vec<u8>, String)> {
+        let entry = commit.tree().ok()?.get_path(Path::new(path)).ok()?;
+        let blob = entry.to_object(&self.repo).ok()?.peel_to_blob().ok()?;
+
+        Some((blob.content().to_vec(), blob.id().to_string()))
+    }
+
+    /// Read a file on a branch, returning the contents and the sha of the file.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn read_file(&self, branch: &str, path: &str) -> Option<(Vec<u8>, String)> {
+        self.read_file_at(&self.branch_commit(branch)?, path)
+    }
+
+    /// The commit each branch is at, by the name of the branch.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn branch_heads(&self) -> Result<BTreeMap<String, String>, String> {
+        let mut heads: BTreeMap<String, String> = Default::default();
+        for branch in self
+            .repo
+            .branches(Some(BranchType::Remote))
+            .map_err(|e| format!("listing the branches of the rfd clone failed: {}", e))?
+        {
+            let (branch, _) = branch.map_err(|e| format!("listing the branches of the rfd clone failed: {}", e))?;
+            let name = match branch.name() {
+                Ok(Some(name)) => name.trim_start_matches("origin/").to_string(),
+                _ => continue,
+            };
+            if name == "HEAD" {
+                continue;
+            }
+
+            if let Ok(commit) = branch.get().peel_to_commit() {
+                heads.insert(name, commit.id().to_string());
+            }
+        }
+
+        Ok(heads)
+    }
+
+    /// Get the RFDs from the RFD csv on the default branch.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn rfds(&self) -> Result<BTreeMap<i32, NewRFD>, String> {
+        let (csv, _) = self
+            .read_file(&self.default_branch, RFD_CSV_PATH)
+            .ok_or_else(|| format!("there is no {} on {} in the rfd clone", RFD_CSV_PATH, self.default_branch))?;
+
+        Ok(parse_rfd_csv(&csv[..]))
+    }
+
+    /// Get the asciidoc or markdown contents of an RFD on a branch, returning the contents,
+    /// if they are markdown, and the sha of the file, the same as `get_rfd_contents_from_repo`.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn rfd_contents(&self, branch: &str, number_string: &str) -> Option<(String, bool, String)> {
+        for (file, is_markdown) in &[("README.adoc", false), ("README.md", true)] {
+            if let Some((content, sha)) = self.read_file(branch, &format!("rfd/{}/{}", number_string, file)) {
+                return Some((String::from_utf8_lossy(&content).trim().to_string(), *is_markdown, sha));
+            }
+        }
+
+        None
+    }
+
+    /// Get the images in the directory for an RFD on a branch, including the directories
+    /// below it, the same as `get_images_in_branch`.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn rfd_images(&self, branch: &str, number_string: &str) -> Vec<RFDImage> {
+        let dir = format!("rfd/{}", number_string);
+        let tree = match self
+            .branch_commit(branch)
+            .and_then(|c| c.tree().ok())
+            .and_then(|t| t.get_path(Path::new(&dir)).ok())
+            .and_then(|e| e.to_object(&self.repo).ok())
+            .and_then(|o| o.peel_to_tree().ok())
+        {
+            Some(tree) => tree,
+            None => return vec![],
+        };
+
+        let mut images: Vec<RFDImage> = Default::default();
+        tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+            let name = entry.name().unwrap_or_default();
+            if entry.kind() == Some(ObjectType::Blob) && is_image(name) {
+                if let Ok(blob) = self.repo.find_blob(entry.id()) {
+                    images.push(RFDImage {
+                        path: format!("{}/{}{}", dir, root, name),
+                        content: blob.content().to_vec(),
+                    });
+                }
+            }
+            TreeWalkResult::Ok
+        })
+        .unwrap_or_default();
+
+        images
+    }
+
+    /// The RFDs that changed on the default branch between two commits, from the files
+    /// that changed in their directories and the rows that changed in the RFD csv.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn changed_rfds(&self, from: &str, to: &str) -> Result<BTreeSet<i32>, String> {
+        let find = |sha: &str| {
+            Oid::from_str(sha)
+                .and_then(|oid| self.repo.find_commit(oid))
+                .map_err(|e| format!("finding commit {} in the rfd clone failed: {}", sha, e))
+        };
+        let (from, to) = (find(from)?, find(to)?);
+
+        let diff = self
+            .repo
+            .diff_tree_to_tree(from.tree().ok().as_ref(), to.tree().ok().as_ref(), None)
+            .map_err(|e| format!("diffing {} and {} in the rfd clone failed: {}", from.id(), to.id(), e))?;
+
+        let mut changed: BTreeSet<i32> = Default::default();
+        let mut csv_changed = false;
+        for delta in diff.deltas() {
+            for file in &[delta.old_file(), delta.new_file()] {
+                let path = file.path().and_then(|p| p.to_str()).unwrap_or_default();
+                if path == RFD_CSV_PATH {
+                    csv_changed = true;
+                }
+                if let Some(number) = rfd_number_from_path(path) {
+                    changed.insert(number);
+                }
+            }
+        }
+
+        if csv_changed {
+            let read_csv = |commit: &Commit| self.read_file_at(commit, RFD_CSV_PATH).map(|(csv, _)| parse_rfd_csv(&csv[..])).unwrap_or_default();
+            let (old, new) = (read_csv(&from), read_csv(&to));
+            for (number, rfd) in &new {
+                if old.get(number) != Some(rfd) {
+                    changed.insert(*number);
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// The date of the last commit to the directory of each RFD on the default branch. We
+    /// walk back through the history until we have found a date for every RFD, so this is
+    /// quick for RFDs that changed recently.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn commit_dates(&self, numbers: &BTreeSet<i32>) -> BTreeMap<i32, DateTime<Utc>> {
+        let mut dates: BTreeMap<i32, DateTime<Utc>> = Default::default();
+        let head = match self.branch_commit(&self.default_branch) {
+            Some(head) => head,
+            None => return dates,
+        };
+        let mut walk = match self.repo.revwalk() {
+            Ok(walk) => walk,
+            Err(e) => {
+                println!("[rfd] walking the history of the rfd clone failed: {}", e);
+                return dates;
+            }
+        };
+        walk.set_sorting(Sort::TIME).unwrap_or_default();
+        walk.push(head.id()).unwrap_or_default();
+
+        for oid in walk.flatten() {
+            if dates.len() == numbers.len() {
+                break;
+            }
+            let commit = match self.repo.find_commit(oid) {
+                Ok(commit) => commit,
+                Err(_) => continue,
+            };
+            let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+            let diff = match self.repo.diff_tree_to_tree(parent_tree.as_ref(), commit.tree().ok().as_ref(), None) {
+                Ok(diff) => diff,
+                Err(_) => continue,
+            };
+
+            let date = Utc.timestamp(commit.author().when().seconds(), 0);
+            for delta in diff.deltas() {
+                if let Some(number) = delta.new_file().path().and_then(|p| p.to_str()).and_then(rfd_number_from_path) {
+                    if numbers.contains(&number) {
+                        dates.entry(number).or_insert(date);
+                    }
+                }
+            }
+        }
+
+        dates
+    }
+
+    /// The authors of the commits on a branch that changed the directory for an RFD, one
+    /// for each commit.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn commit_authors(&self, branch: &str, number_string: &str) -> Vec<RFDCommitAuthor> {
+        let mut authors: Vec<RFDCommitAuthor> = Default::default();
+        let head = match self.branch_commit(branch) {
+            Some(head) => head,
+            None => return authors,
+        };
+        let mut walk = match self.repo.revwalk() {
+            Ok(walk) => walk,
+            Err(e) => {
+                println!("[rfd] walking the history of the rfd clone failed: {}", e);
+                return authors;
+            }
+        };
+        walk.push(head.id()).unwrap_or_default();
+
+        let mut opts = DiffOptions::new();
+        opts.pathspec(format!("rfd/{}/", number_string));
+        for oid in walk.flatten() {
+            let commit = match self.repo.find_commit(oid) {
+                Ok(commit) => commit,
+                Err(_) => continue,
+            };
+            let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+            let changed = self
+                .repo
+                .diff_tree_to_tree(parent_tree.as_ref(), commit.tree().ok().as_ref(), Some(&mut opts))
+                .map(|diff| diff.deltas().next().is_some())
+                .unwrap_or_default();
+            if !changed {
+                continue;
+            }
+
+            let author = commit.author();
+            authors.push(RFDCommitAuthor {
+                name: author.name().unwrap_or_default().to_string(),
+                email: author.email().unwrap_or_default().to_string(),
+                // We only know the GitHub login of the author from the API.
+                github: Default::default(),
+            });
+        }
+
+        authors
+    }
+
+    /// The date of the last commit on a branch.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn branch_date(&self, branch: &str) -> Option<DateTime<Utc>> {
+        self.branch_commit(branch).map(|c| Utc.timestamp(c.author().when().seconds(), 0))
+    }
+
+    /// The commit each branch was at when we last synced.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn last_synced(&self) -> BTreeMap<String, String> {
+        fs::read_to_string(self.repo.path().join(LAST_SYNCED_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save the commit each branch is at, once we have synced them.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn save_last_synced(&self, heads: &BTreeMap<String, String>) {
+        let path = self.repo.path().join(LAST_SYNCED_FILE);
+        if let Err(e) = fs::write(&path, serde_json::to_string_pretty(heads).unwrap()) {
+            println!("[rfd] saving the last synced commits to {} failed: {}", path.display(), e);
+        }
+    }
+}
+
+/// Work out which RFDs need to be expanded again, from the commit each branch is at now and
+/// was at when we last synced. The RFDs on the default branch that changed are passed in,
+/// since working them out needs the clone, and RFDs in discussion have a branch named for
+/// their number.
+#[instrument]
+#[inline]
+pub fn rfds_to_sync(default_branch_changes: &BTreeSet<i32>, heads: &BTreeMap<String, String>, last_synced: &BTreeMap<String, String>) -> BTreeSet<i32> {
+    let mut changed = default_branch_changes.clone();
+    for (branch, sha) in heads {
+        if last_synced.get(branch) == Some(sha) {
+            continue;
+        }
+        if let Ok(number) = branch.parse::<i32>() {
+            changed.insert(number);
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use crate::rfd_clone::{rfd_number_from_path, rfds_to_sync};
+
+    #[test]
+    fn test_rfd_number_from_path() {
+        assert_eq!(rfd_number_from_path("rfd/0042/README.adoc"), Some(42));
+        assert_eq!(rfd_number_from_path("/rfd/0001/images/diagram.svg"), Some(1));
+        assert_eq!(rfd_number_from_path("rfd/README.md"), None);
+        assert_eq!(rfd_number_from_path(".helpers/rfd.csv"), None);
+        assert_eq!(rfd_number_from_path("src/rfd/0042"), None);
+    }
+
+    #[test]
+    fn test_rfds_to_sync() {
+        let heads: BTreeMap<String, String> = vec![("master", "a1"), ("0042", "b2"), ("0043", "c3"), ("fix-typo", "d4")]
+            .into_iter()
+            .map(|(b, s)| (b.to_string(), s.to_string()))
+            .collect();
+        let last: BTreeMap<String, String> = vec![("master", "a0"), ("0042", "b2"), ("fix-typo", "d3")]
+            .into_iter()
+            .map(|(b, s)| (b.to_string(), s.to_string()))
+            .collect();
+        let default_branch_changes: BTreeSet<i32> = vec![7].into_iter().collect();
+
+        // 0042 has not moved, 0043 is new, and branches not named for an RFD are left out.
+        assert_eq!(rfds_to_sync(&default_branch_changes, &heads, &last), vec![7, 43].into_iter().collect());
+    }
+}
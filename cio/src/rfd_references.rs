@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+use hubcaps::Github;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::chat::ChatChannel;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::models::{NewRFD, RFDs};
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::rfd_references;
+use crate::utils::{create_or_update_file_in_github_repo, github_org};
+
+/// Where we publish the references between RFDs in the rfd repo, for the static site.
+const RFD_REFERENCES_PATH: &str = "src/public/static/rfd-references.json";
+
+/// A reference from one RFD to another, ie. RFD 42 saying "see RFD 7". We keep references
+/// to RFDs that do not exist yet, so they show up once the RFD is written.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "rfd_references"]
+pub struct NewRFDReference {
+    pub from_number: i32,
+    pub to_number: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct RFDReference {
+    pub id: i32,
+    pub from_number: i32,
+    pub to_number: i32,
+}
+
+/// The RFDs an RFD references and is referenced by.
+#[derive(Debug, Default, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct RFDReferences {
+    pub number: i32,
+    #[serde(default)]
+    pub references: Vec<i32>,
+    #[serde(default)]
+    pub referenced_by: Vec<i32>,
+}
+
+/// Parse the numbers of the other RFDs an RFD references, from mentions like `RFD 42`,
+/// `RFD-0042`, or `rfd 42`, and links to `https://42.rfd.oxide.computer` or
+/// `/rfd/0042`. References to itself are left out.
+#[instrument(skip(content))]
+#[inline]
+pub fn parse_rfd_references(content: &str, number: i32) -> BTreeSet<i32> {
+    let re = Regex::new(r"(?i)(?:\bRFD[\s\-]?#?0*([0-9]{1,4})\b|https?://0*([0-9]{1,4})\.rfd\.oxide\.computer|/rfd/0*([0-9]{1,4})\b)").unwrap();
+
+    re.captures_iter(content)
+        .filter_map(|cap| cap.get(1).or_else(|| cap.get(2)).or_else(|| cap.get(3)))
+        .filter_map(|m| m.as_str().parse::<i32>().ok())
+        .filter(|n| *n != number)
+        .collect()
+}
+
+/// Build the references each RFD makes and gets from the edges between them.
+#[instrument(skip(edges))]
+#[inline]
+pub fn build_rfd_reference_graph(edges: &[(i32, i32)]) -> BTreeMap<i32, RFDReferences> {
+    let mut graph: BTreeMap<i32, RFDReferences> = Default::default();
+    for (from, to) in edges {
+        graph.entry(*from).or_insert_with(|| RFDReferences { number: *from, ..Default::default() }).references.push(*to);
+        graph.entry(*to).or_insert_with(|| RFDReferences { number: *to, ..Default::default() }).referenced_by.push(*from);
+    }
+
+    for r in graph.values_mut() {
+        r.references.sort_unstable();
+        r.references.dedup();
+        r.referenced_by.sort_unstable();
+        r.referenced_by.dedup();
+    }
+
+    graph
+}
+
+/// Get the references between all the RFDs from the database.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_rfd_references(db: &Database) -> BTreeMap<i32, RFDReferences> {
+    let edges: Vec<(i32, i32)> = rfd_references::dsl::rfd_references
+        .load::<RFDReference>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the rfd references failed: {}", e))
+        .into_iter()
+        .map(|r| (r.from_number, r.to_number))
+        .collect();
+
+    build_rfd_reference_graph(&edges)
+}
+
+/// Replace the references an RFD makes in the database with the ones in its content.
+/// Returns the references that are new since the last time.
+#[instrument(skip(db, content))]
+#[inline]
+pub fn update_rfd_references(db: &Database, number: i32, content: &str) -> BTreeSet<i32> {
+    let references = parse_rfd_references(content, number);
+    let conn = db.conn();
+
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let existing: BTreeSet<i32> = rfd_references::dsl::rfd_references
+            .filter(rfd_references::dsl::from_number.eq(number))
+            .select(rfd_references::dsl::to_number)
+            .load::<i32>(&conn)?
+            .into_iter()
+            .collect();
+
+        diesel::delete(rfd_references::dsl::rfd_references.filter(rfd_references::dsl::from_number.eq(number))).execute(&conn)?;
+        let rows: Vec<NewRFDReference> = references.iter().map(|to| NewRFDReference { from_number: number, to_number: *to }).collect();
+        diesel::insert_into(rfd_references::table).values(&rows).execute(&conn)?;
+
+        Ok(references.difference(&existing).cloned().collect())
+    })
+    .unwrap_or_else(|e| panic!("updating the references for RFD {} failed: {}", number, e))
+}
+
+/// Update the references the synced RFDs make, and warn in the #engineering channel about
+/// the new references to RFDs that do not exist. Each missing reference is only warned
+/// about once, when it is added.
+#[instrument(skip(db, rfds))]
+#[inline]
+pub async fn refresh_rfd_references(db: &Database, rfds: &[(i32, String)]) {
+    let known: BTreeSet<i32> = RFDs::get_from_db(db).into_iter().map(|r| r.number).collect();
+
+    let mut lines: Vec<String> = Default::default();
+    for (number, content) in rfds {
+        for missing in update_rfd_references(db, *number, content).into_iter().filter(|n| !known.contains(n)) {
+            println!("[rfd] RFD {} references RFD {}, which does not exist", number, missing);
+            lines.push(format!("• RFD {} references RFD {}", NewRFD::generate_number_string(*number), missing));
+        }
+    }
+    if lines.is_empty() {
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Engineering,
+        "rfds.missing_references",
+        json!({ "text": render_message("rfds.missing_references", &json!({ "lines": lines })) }),
+    )
+    .await;
+}
+
+/// Publish the references between RFDs to the rfd repo, so the static site can show what
+/// references each RFD.
+#[instrument(skip(db, github))]
+#[inline]
+pub async fn publish_rfd_references(db: &Database, github: &Github) {
+    let references: Vec<RFDReferences> = get_rfd_references(db).values().cloned().collect();
+
+    let repo = github.repo(github_org(), "rfd");
+    let r = repo.get().await.unwrap();
+    create_or_update_file_in_github_repo(&repo, &r.default_branch, RFD_REFERENCES_PATH, serde_json::to_vec_pretty(&references).unwrap()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rfd_references::{build_rfd_reference_graph, parse_rfd_references};
+
+    #[test]
+    fn test_parse_rfd_references() {
+        let content = "As RFD 7 and rfd-0012 say, see also RFD #3 and https://21.rfd.oxide.computer or \
+                       link:/rfd/0030[RFD 30]. This is RFD 42. RFDs are great, PRFD 5 is not an RFD.";
+        assert_eq!(parse_rfd_references(content, 42), vec![3, 7, 12, 21, 30].into_iter().collect());
+        assert!(parse_rfd_references("No references here.", 1).is_empty());
+    }
+
+    #[test]
+    fn test_build_rfd_reference_graph() {
+        let graph = build_rfd_reference_graph(&[(42, 7), (42, 12), (12, 7), (42, 7)]);
+
+        assert_eq!(graph[&42].references, vec![7, 12]);
+        assert!(graph[&42].referenced_by.is_empty());
+        assert_eq!(graph[&7].referenced_by, vec![12, 42]);
+        assert_eq!(graph[&12].references, vec![7]);
+        assert_eq!(graph[&12].referenced_by, vec![42]);
+    }
+}
@@ -1,19 +1,26 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::io::Read;
 use std::str::from_utf8;
 
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use comrak::{markdown_to_html, ComrakOptions};
 use csv::ReaderBuilder;
-use futures_util::TryStreamExt;
 use hubcaps::repositories::Repository;
 use hubcaps::Github;
 use regex::Regex;
 use sendgrid_api::SendGrid;
 use tracing::instrument;
 
+use crate::backups::upload_to_gcs;
 use crate::db::Database;
+use crate::filter::RecordFilter;
+use crate::format::format_date;
 use crate::models::{NewRFD, RFDs};
-use crate::utils::{authenticate_github_jwt, create_or_update_file_in_github_repo, github_org, DOMAIN};
+use crate::rfd_authors::{get_directory_people, get_rfd_commit_authors, publish_rfd_author_stats, update_rfd_authors};
+use crate::rfd_clone::{open_rfd_clone, rfds_to_sync};
+use crate::rfd_references::{publish_rfd_references, refresh_rfd_references};
+use crate::utils::{authenticate_github_jwt, create_or_update_file_in_github_repo, github_org, list_github_tree, DOMAIN};
 
 /// Get the RFDs from the rfd GitHub repo.
 #[instrument]
@@ -24,15 +31,28 @@ pub async fn get_rfds_from_repo(github: &Github) -> BTreeMap<i32, NewRFD> {
 
     // Get the contents of the .helpers/rfd.csv file.
     let rfd_csv_content = repo.content().file("/.helpers/rfd.csv", &r.default_branch).await.expect("failed to get rfd csv content").content;
-    let rfd_csv_string = from_utf8(&rfd_csv_content).unwrap();
 
+    parse_rfd_csv(&rfd_csv_content[..])
+}
+
+/// Parse the RFDs from the .helpers/rfd.csv file in the rfd repo, a row at a time.
+/// Rows we can not parse are skipped rather than failing the whole sync.
+#[instrument(skip(rfd_csv))]
+#[inline]
+pub fn parse_rfd_csv<R: Read>(rfd_csv: R) -> BTreeMap<i32, NewRFD> {
     // Create the csv reader.
-    let mut csv_reader = ReaderBuilder::new().delimiter(b',').has_headers(true).from_reader(rfd_csv_string.as_bytes());
+    let mut csv_reader = ReaderBuilder::new().delimiter(b',').has_headers(true).from_reader(rfd_csv);
 
     // Create the BTreeMap of RFDs.
     let mut rfds: BTreeMap<i32, NewRFD> = Default::default();
     for r in csv_reader.deserialize() {
-        let mut rfd: NewRFD = r.unwrap();
+        let mut rfd: NewRFD = match r {
+            Ok(rfd) => rfd,
+            Err(e) => {
+                println!("[rfds] skipping a row of the RFD csv we could not parse: {}", e);
+                continue;
+            }
+        };
 
         // TODO: this whole thing is a mess jessfraz needs to cleanup
         rfd.number_string = NewRFD::generate_number_string(rfd.number);
@@ -45,10 +65,11 @@ pub async fn get_rfds_from_repo(github: &Github) -> BTreeMap<i32, NewRFD> {
     rfds
 }
 
-/// Try to get the markdown or asciidoc contents from the repo.
+/// Try to get the markdown or asciidoc contents from the repo, along with the images in
+/// the directory for the RFD.
 #[instrument]
 #[inline]
-pub async fn get_rfd_contents_from_repo(github: &Github, branch: &str, dir: &str) -> (String, bool, String) {
+pub async fn get_rfd_contents_from_repo(github: &Github, branch: &str, dir: &str) -> (String, bool, String, Vec<RFDImage>) {
     let repo = github.repo(github_org(), "rfd");
     let r = repo.get().await.unwrap();
     let repo_contents = repo.content();
@@ -77,70 +98,59 @@ pub async fn get_rfd_contents_from_repo(github: &Github, branch: &str, dir: &str
 
     // Get all the images in the branch and make sure they are in the images directory on master.
     let images = get_images_in_branch(&repo, dir, branch).await;
-    for image in images {
+    for image in &images {
         let new_path = image.path.replace("rfd/", "src/public/static/images/");
         // Make sure we have this file in the static images dir on the master branch.
         create_or_update_file_in_github_repo(&repo, &r.default_branch, &new_path, image.content.to_vec()).await;
     }
 
-    (decoded, is_markdown, sha)
+    (decoded, is_markdown, sha, images)
+}
+
+/// An image in the directory for an RFD, with its contents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RFDImage {
+    /// The path from the root of the rfd repo, ie. `rfd/0001/diagram.svg`.
+    pub path: String,
+    pub content: Vec<u8>,
 }
 
-// Get all the images in a specific directory of a GitHub branch.
+// Get all the images in a specific directory of a GitHub branch, including the
+// directories below it.
 #[instrument(skip(repo))]
 #[inline]
-pub async fn get_images_in_branch(repo: &Repository, dir: &str, branch: &str) -> Vec<hubcaps::content::File> {
-    let mut files: Vec<hubcaps::content::File> = Default::default();
+pub async fn get_images_in_branch(repo: &Repository, dir: &str, branch: &str) -> Vec<RFDImage> {
+    let files = match list_github_tree("rfd", branch, dir).await {
+        Ok(files) => files,
+        Err(e) => {
+            println!("[rfd] listing the files in {} on branch {} failed: {}", dir, branch, e);
+            return vec![];
+        }
+    };
 
-    // Get all the images in the branch and make sure they are in the images directory on master.
-    for file in repo.content().iter(dir, branch).try_collect::<Vec<hubcaps::content::DirectoryItem>>().await.unwrap() {
-        if is_image(&file.name) {
-            // Get the contents of the image.
-            match repo.content().file(&file.path, branch).await {
-                Ok(f) => {
-                    // Push the file to our vector.
-                    files.push(f);
+    let mut images: Vec<RFDImage> = Default::default();
+    for file in files {
+        if !is_image(&file.path) {
+            continue;
+        }
+
+        // Get the contents from the Git Data API, since unlike the contents API it
+        // works for files bigger than 1MB.
+        match repo.git().blob(&file.sha).await {
+            Ok(blob) => {
+                // Base64 decode the contents.
+                // TODO: move this logic to hubcaps.
+                let v = blob.content.replace("\n", "");
+                match base64::decode_config(&v, base64::STANDARD) {
+                    Ok(content) => images.push(RFDImage { path: file.path, content }),
+                    Err(e) => println!("[rfd] decoding file contents for {} failed: {}", file.path, e),
                 }
-                Err(e) => match e {
-                    hubcaps::errors::Error::Fault { code: _, ref error } => {
-                        if error.message.contains("too large") {
-                            // The file is too big for us to get it's contents through this API.
-                            // The error suggests we use the Git Data API but we need the file sha for
-                            // that.
-                            // We have the sha we can see if the files match using the
-                            // Git Data API.
-                            let blob = repo.git().blob(&file.sha).await.unwrap();
-                            // Base64 decode the contents.
-                            // TODO: move this logic to hubcaps.
-                            let v = blob.content.replace("\n", "");
-                            let decoded = base64::decode_config(&v, base64::STANDARD).unwrap();
-
-                            // Push the new file.
-                            files.push(hubcaps::content::File {
-                                encoding: hubcaps::content::Encoding::Base64,
-                                size: file.size,
-                                name: file.name,
-                                path: file.path,
-                                content: hubcaps::content::DecodedContents(decoded.to_vec()),
-                                sha: file.sha,
-                                url: file.url,
-                                git_url: file.git_url,
-                                html_url: file.html_url,
-                                download_url: file.download_url.unwrap_or_default(),
-                                _links: file._links,
-                            });
-
-                            continue;
-                        }
-                        println!("[rfd] getting file contents for {} failed: {}", file.path, e);
-                    }
-                    _ => println!("[rfd] getting file contents for {} failed: {}", file.path, e),
-                },
             }
+            Err(e) => println!("[rfd] getting file contents for {} failed: {}", file.path, e),
         }
     }
 
-    files
+    images
 }
 
 #[instrument]
@@ -178,6 +188,100 @@ pub fn clean_rfd_html_links(content: &str, num: &str) -> String {
     cleaned
 }
 
+/// Get the images an RFD references that are in the rfd repo, as paths relative to the
+/// directory for the RFD, ie. `diagram.svg` for `image::diagram.svg[]` in asciidoc or
+/// `![diagram](./diagram.svg)` in markdown. Images from other sites are left out.
+#[instrument(skip(content))]
+#[inline]
+pub fn referenced_images(content: &str) -> Vec<String> {
+    let re = Regex::new(r#"(?:image::?([^\[\s]+)\[|!\[[^\]]*\]\(([^)\s]+)|<img[^>]+src="([^"]+)")"#).unwrap();
+
+    let mut images: Vec<String> = Default::default();
+    for cap in re.captures_iter(content) {
+        let path = match cap.get(1).or_else(|| cap.get(2)).or_else(|| cap.get(3)) {
+            Some(p) => p.as_str().trim_start_matches("./"),
+            None => continue,
+        };
+        if path.contains("://") || path.starts_with("data:") || path.starts_with('/') || !is_image(path) {
+            continue;
+        }
+
+        if !images.iter().any(|i| i == path) {
+            images.push(path.to_string());
+        }
+    }
+
+    images
+}
+
+/// Point the links to the images an RFD references at where we uploaded them, by the
+/// path of the image relative to the directory for the RFD. This runs on HTML that has
+/// been through `clean_rfd_html_links`, which points them at the images on the RFD site.
+#[instrument(skip(html, urls))]
+#[inline]
+pub fn rewrite_rfd_image_links(html: &str, num: &str, urls: &BTreeMap<String, String>) -> String {
+    let mut rewritten = html.to_string();
+    for (path, url) in urls {
+        for prefix in &["", "./"] {
+            rewritten = rewritten.replace(&format!(r#""/static/images/{}/{}{}""#, num, prefix, path), &format!(r#""{}""#, url));
+        }
+    }
+
+    rewritten
+}
+
+/// The content type to upload an image with.
+fn image_content_type(path: &str) -> &'static str {
+    if path.ends_with(".svg") {
+        "image/svg+xml"
+    } else if path.ends_with(".png") {
+        "image/png"
+    } else if path.ends_with(".jpg") || path.ends_with(".jpeg") {
+        "image/jpeg"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Upload the images an RFD references to the bucket in the `RFD_STATIC_BUCKET`
+/// environment variable, so the rendered HTML works outside the RFD site, ie. in
+/// Airtable and the API. Returns the URL of each image by its path relative to the
+/// directory for the RFD. The URLs are on the CDN in the `RFD_STATIC_URL` environment
+/// variable, or the public URL of the bucket if it is not set.
+#[instrument(skip(content, images))]
+#[inline]
+pub async fn upload_rfd_images(num: &str, content: &str, images: &[RFDImage]) -> BTreeMap<String, String> {
+    let mut urls: BTreeMap<String, String> = Default::default();
+    let bucket = env::var("RFD_STATIC_BUCKET").unwrap_or_default();
+    if bucket.is_empty() {
+        return urls;
+    }
+    let mut base_url = env::var("RFD_STATIC_URL").unwrap_or_default();
+    if base_url.is_empty() {
+        base_url = format!("https://storage.googleapis.com/{}", bucket);
+    }
+
+    for path in referenced_images(content) {
+        let image = match images.iter().find(|i| i.path == format!("rfd/{}/{}", num, path)) {
+            Some(i) => i,
+            None => {
+                println!("[rfd] RFD {} references the image {} but it is not in the repo", num, path);
+                continue;
+            }
+        };
+
+        let name = format!("rfd/{}/{}", num, path);
+        match upload_to_gcs(&bucket, &name, image_content_type(&path), image.content.clone()).await {
+            Ok(()) => {
+                urls.insert(path, format!("{}/{}", base_url.trim_end_matches('/'), name.replace(' ', "%20")));
+            }
+            Err(e) => println!("[rfd] {}", e),
+        }
+    }
+
+    urls
+}
+
 #[instrument]
 #[inline]
 pub fn update_discussion_link(content: &str, link: &str, is_markdown: bool) -> String {
@@ -215,13 +319,35 @@ pub fn update_state(content: &str, state: &str, is_markdown: bool) -> String {
 }
 
 // Sync the rfds with our database.
+// If the `RFD_REPO_DIR` environment variable is set, we sync from a clone of the rfd
+// repo in that directory rather than the GitHub contents API.
 #[instrument(skip(db))]
 #[inline]
-pub async fn refresh_db_rfds(db: &Database, github: &Github) {
+pub async fn refresh_db_rfds(db: &Database, github: &Github, filter: &RecordFilter) {
+    let dir = env::var("RFD_REPO_DIR").unwrap_or_default();
+    if !dir.is_empty() {
+        match refresh_db_rfds_from_clone(db, github, filter, &dir).await {
+            Ok(()) => return,
+            Err(e) => println!("[rfd] syncing from the clone in {} failed, syncing from the GitHub API instead: {}", dir, e),
+        }
+    }
+
     let rfds = get_rfds_from_repo(github).await;
+    let default_branch = github.repo(github_org(), "rfd").get().await.unwrap().default_branch;
+    let people = get_directory_people(db);
+
+    // The commit dates are not in the RFD csv, we only know them once we have expanded
+    // an RFD. So we filter on the commit dates from the last sync, and always sync the
+    // RFDs that are not in the database yet.
+    let commit_dates: BTreeMap<i32, DateTime<Utc>> = RFDs::get_from_db(db).into_iter().map(|r| (r.number, r.commit_date)).collect();
 
     // Sync rfds.
+    let mut synced: Vec<(i32, String)> = Default::default();
     for (_, rfd) in rfds {
+        if !filter.matches(&rfd) || !commit_dates.get(&rfd.number).map(|d| filter.matches_date(*d)).unwrap_or(true) {
+            continue;
+        }
+
         let mut new_rfd = rfd.upsert(db).await;
 
         // Expand the fields in the RFD.
@@ -233,7 +359,104 @@ pub async fn refresh_db_rfds(db: &Database, github: &Github) {
         // Update the RFD again.
         // We do this so the expand functions are only one place.
         new_rfd.update(db).await;
+
+        // Update the authors of the RFD from the frontmatter and the git history.
+        let commits = get_rfd_commit_authors(&new_rfd.branch(&default_branch), &new_rfd.number_string).await;
+        update_rfd_authors(db, new_rfd.number, &new_rfd.authors, &commits, &people);
+
+        synced.push((new_rfd.number, new_rfd.content));
     }
+
+    // Update the references between the RFDs we synced.
+    refresh_rfd_references(db, &synced).await;
+    publish_rfd_references(db, github).await;
+    publish_rfd_author_stats(db, github).await;
+}
+
+/// Sync the rfds with our database from a clone of the rfd repo in a directory, cloning
+/// it first if it is not there. Only the RFDs that changed since the last sync, or are not
+/// in the database yet, are expanded and have their PDFs made again. A filter on fields,
+/// ie. `number=42`, syncs the matching RFDs whether they changed or not.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_db_rfds_from_clone(db: &Database, github: &Github, filter: &RecordFilter, dir: &str) -> Result<(), String> {
+    let r = github.repo(github_org(), "rfd").get().await.map_err(|e| format!("getting the rfd repo failed: {}", e))?;
+    let clone = open_rfd_clone(dir, &r.default_branch)?;
+    let rfds = clone.rfds()?;
+    let heads = clone.branch_heads()?;
+    let last_synced = clone.last_synced();
+
+    // Work out what changed on the default branch since the last sync. If we have not
+    // synced before, or the last commit we synced is gone, everything changed.
+    let default_branch_changes = match (last_synced.get(&r.default_branch), heads.get(&r.default_branch)) {
+        (Some(from), Some(to)) => clone.changed_rfds(from, to).unwrap_or_else(|e| {
+            println!("[rfd] {}, syncing every RFD", e);
+            rfds.keys().cloned().collect()
+        }),
+        _ => rfds.keys().cloned().collect(),
+    };
+    let changed = rfds_to_sync(&default_branch_changes, &heads, &last_synced);
+
+    let commit_dates: BTreeMap<i32, DateTime<Utc>> = RFDs::get_from_db(db).into_iter().map(|r| (r.number, r.commit_date)).collect();
+    let to_sync: BTreeSet<i32> = rfds
+        .values()
+        .filter(|rfd| filter.matches(rfd))
+        .filter(|rfd| !filter.fields.is_empty() || changed.contains(&rfd.number) || !commit_dates.contains_key(&rfd.number))
+        .map(|rfd| rfd.number)
+        .collect();
+    println!("[rfd] {} of {} RFDs changed since the last sync", to_sync.len(), rfds.len());
+    // Find the commit dates for all the RFDs on the default branch at once, since it
+    // means walking back through the history.
+    let on_default_branch: BTreeSet<i32> = to_sync.iter().filter(|n| rfds[n].link.contains(&format!("/{}/", r.default_branch))).cloned().collect();
+    let mut new_commit_dates = clone.commit_dates(&on_default_branch);
+    for number in to_sync.difference(&on_default_branch) {
+        if let Some(date) = clone.branch_date(&NewRFD::generate_number_string(*number)) {
+            new_commit_dates.insert(*number, date);
+        }
+    }
+
+    let people = get_directory_people(db);
+    let mut synced: Vec<(i32, String)> = Default::default();
+    for (number, rfd) in rfds {
+        if !to_sync.contains(&number) {
+            continue;
+        }
+        // Filter on the commit date from the clone, which we know before expanding.
+        let commit_date = new_commit_dates.get(&number).or_else(|| commit_dates.get(&number));
+        if !commit_date.map(|d| filter.matches_date(*d)).unwrap_or(true) {
+            continue;
+        }
+
+        let mut new_rfd = rfd.upsert(db).await;
+
+        // Expand the fields in the RFD.
+        new_rfd.expand_from_clone(github, &clone, new_commit_dates.get(&number).copied()).await;
+
+        // Make and update the PDF versions.
+        new_rfd.convert_and_upload_pdf(github).await;
+
+        // Update the RFD again.
+        new_rfd.update(db).await;
+
+        // Update the authors of the RFD from the frontmatter and the git history.
+        let commits = clone.commit_authors(&new_rfd.branch(&clone.default_branch), &new_rfd.number_string);
+        update_rfd_authors(db, new_rfd.number, &new_rfd.authors, &commits, &people);
+
+        synced.push((new_rfd.number, new_rfd.content));
+    }
+
+    // Update the references between the RFDs we synced.
+    refresh_rfd_references(db, &synced).await;
+    publish_rfd_references(db, github).await;
+    publish_rfd_author_stats(db, github).await;
+
+    // Only remember what we synced if we synced everything, otherwise the RFDs we
+    // filtered out would not be synced next time.
+    if filter.is_empty() {
+        clone.save_last_synced(&heads);
+    }
+
+    Ok(())
 }
 
 /// Create a changelog email for the RFDs.
@@ -242,7 +465,7 @@ pub async fn send_rfd_changelog() {
     let db = Database::new();
     let github = authenticate_github_jwt();
     let seven_days_ago = Utc::now() - Duration::days(7);
-    let week_format = format!("from {} to {}", seven_days_ago.format("%m-%d-%Y"), Utc::now().format("%m-%d-%Y"));
+    let week_format = format!("from {} to {}", format_date(seven_days_ago.date().naive_utc()), format_date(Utc::now().date().naive_utc()));
 
     let mut changelog = format!("Changes to RFDs for the week {}:\n", week_format);
 
@@ -273,9 +496,12 @@ pub async fn send_rfd_changelog() {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use crate::db::Database;
+    use crate::filter::RecordFilter;
     use crate::models::{NewRFD, RFDs};
-    use crate::rfds::{clean_rfd_html_links, refresh_db_rfds, send_rfd_changelog, update_discussion_link, update_state};
+    use crate::rfds::{clean_rfd_html_links, referenced_images, refresh_db_rfds, rewrite_rfd_image_links, send_rfd_changelog, update_discussion_link, update_state};
     use crate::utils::authenticate_github_jwt;
 
     #[ignore]
@@ -285,7 +511,7 @@ mod tests {
         let db = Database::new();
 
         let github = authenticate_github_jwt();
-        refresh_db_rfds(&db, &github).await;
+        refresh_db_rfds(&db, &github, &RecordFilter::default()).await;
 
         // Update rfds in airtable.
         RFDs::get_from_db(&db).update_airtable().await;
@@ -553,4 +779,31 @@ sdf
         title = NewRFD::get_title(&content);
         assert_eq!(expected, title);
     }
+
+    #[test]
+    fn test_referenced_images() {
+        let content = r#"
+image::diagram.svg[Diagram]
+See image:./icons/warning.png[Warning] and image::https://example.com/logo.png[].
+![Rack](./rack.jpg) and ![Again](rack.jpg)
+<img src="photos/board.jpeg" alt="board">
+image::diagram.svg[Again]
+link:notes.txt[Notes]
+"#;
+        assert_eq!(referenced_images(content), vec!["diagram.svg", "icons/warning.png", "rack.jpg", "photos/board.jpeg"]);
+        assert!(referenced_images("no images here").is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_rfd_image_links() {
+        let html = clean_rfd_html_links(r#"<img src="diagram.svg" alt="Diagram"><img src="./rack.jpg"><img src="other.png">"#, "0042");
+        let mut urls = BTreeMap::new();
+        urls.insert("diagram.svg".to_string(), "https://static.example.com/rfd/0042/diagram.svg".to_string());
+        urls.insert("rack.jpg".to_string(), "https://static.example.com/rfd/0042/rack.jpg".to_string());
+
+        assert_eq!(
+            rewrite_rfd_image_links(&html, "0042", &urls),
+            r#"<img src="https://static.example.com/rfd/0042/diagram.svg" alt="Diagram"><img src="https://static.example.com/rfd/0042/rack.jpg"><img src="/static/images/0042/other.png">"#
+        );
+    }
 }
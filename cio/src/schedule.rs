@@ -0,0 +1,282 @@
+use std::collections::BTreeSet;
+
+use chrono::offset::LocalResult;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use tracing::instrument;
+
+/// The timezone for schedules that do not set one.
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+/// How far ahead we look for the next time a schedule fires. Five years covers
+/// schedules that only fire on February 29th.
+const MAX_LOOKAHEAD_DAYS: i64 = 5 * 366;
+
+static MONTH_NAMES: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+static WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parse the name of a timezone from the tz database, ie. `America/Los_Angeles`.
+/// An empty name is `DEFAULT_TIMEZONE`.
+#[instrument]
+#[inline]
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(Tz::UTC);
+    }
+
+    name.parse::<Tz>()
+        .map_err(|_| format!("`{}` is not a timezone, use a name from the tz database like `America/Los_Angeles`", name))
+}
+
+/// A cron expression: `minute hour day-of-month month day-of-week`. Each field is `*`,
+/// a number, a range like `1-5`, a step like `*/15` or `9-17/2`, or a list of those
+/// like `0,30`. Months and days of the week can also be names, ie. `jan` or `mon-fri`.
+/// The `@hourly`, `@daily`, `@weekly`, `@monthly`, and `@yearly` shorthands work too.
+///
+/// Like cron, when both the day of the month and the day of the week are set, the
+/// schedule fires on days matching either.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: BTreeSet<u32>,
+    hours: BTreeSet<u32>,
+    days_of_month: BTreeSet<u32>,
+    months: BTreeSet<u32>,
+    /// Sunday is 0.
+    days_of_week: BTreeSet<u32>,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    /// Parse a cron expression.
+    #[instrument]
+    #[inline]
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            e => e,
+        };
+
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("`{}` is not a cron expression, it needs 5 fields: minute hour day-of-month month day-of-week", expr));
+        }
+
+        let mut days_of_week = parse_cron_field(fields[4], 0, 7, WEEKDAY_NAMES).map_err(|e| format!("the day of the week in `{}` {}", expr, e))?;
+        // Sunday can be written as 0 or 7.
+        if days_of_week.remove(&7) {
+            days_of_week.insert(0);
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_cron_field(fields[0], 0, 59, &[]).map_err(|e| format!("the minute in `{}` {}", expr, e))?,
+            hours: parse_cron_field(fields[1], 0, 23, &[]).map_err(|e| format!("the hour in `{}` {}", expr, e))?,
+            days_of_month: parse_cron_field(fields[2], 1, 31, &[]).map_err(|e| format!("the day of the month in `{}` {}", expr, e))?,
+            months: parse_cron_field(fields[3], 1, 12, MONTH_NAMES).map_err(|e| format!("the month in `{}` {}", expr, e))?,
+            days_of_week,
+            days_of_month_restricted: !fields[2].starts_with('*'),
+            days_of_week_restricted: !fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let dom = self.days_of_month.contains(&date.day());
+        let dow = self.days_of_week.contains(&date.weekday().num_days_from_sunday());
+        if self.days_of_month_restricted && self.days_of_week_restricted {
+            return dom || dow;
+        }
+
+        dom && dow
+    }
+
+    /// Returns if the schedule fires at a wall clock time.
+    #[instrument]
+    #[inline]
+    pub fn matches(&self, t: &NaiveDateTime) -> bool {
+        self.months.contains(&t.month()) && self.matches_day(t.date()) && self.hours.contains(&t.hour()) && self.minutes.contains(&t.minute())
+    }
+
+    /// Get the next time after `after` the schedule fires, reading the schedule as wall
+    /// clock times in the timezone. Around daylight saving time changes, a time skipped
+    /// when the clocks go forward fires when the clocks skip past it, and a time that
+    /// happens twice when the clocks go back only fires the first time.
+    #[instrument(skip(tz))]
+    #[inline]
+    pub fn next_after<T: TimeZone>(&self, after: DateTime<Utc>, tz: &T) -> Option<DateTime<Utc>> {
+        let local = after.with_timezone(tz).naive_local();
+        let mut t = NaiveDateTime::new(local.date(), NaiveTime::from_hms(local.hour(), local.minute(), 0)) + Duration::minutes(1);
+        let limit = t + Duration::days(MAX_LOOKAHEAD_DAYS);
+
+        while t < limit {
+            if !self.months.contains(&t.month()) {
+                let (y, m) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = NaiveDate::from_ymd(y, m, 1).and_hms(0, 0, 0);
+                continue;
+            }
+            if !self.matches_day(t.date()) {
+                t = t.date().succ().and_hms(0, 0, 0);
+                continue;
+            }
+            if !self.hours.contains(&t.hour()) {
+                t = t.date().and_hms(t.hour(), 0, 0) + Duration::hours(1);
+                continue;
+            }
+            if !self.minutes.contains(&t.minute()) {
+                t += Duration::minutes(1);
+                continue;
+            }
+
+            if let Some(fires) = resolve_local_time(tz, &t) {
+                if fires > after {
+                    return Some(fires);
+                }
+            }
+            t += Duration::minutes(1);
+        }
+
+        None
+    }
+
+    /// Returns if the schedule fired in the window `(since, now]`.
+    #[instrument(skip(tz))]
+    #[inline]
+    pub fn fired_between<T: TimeZone>(&self, since: DateTime<Utc>, now: DateTime<Utc>, tz: &T) -> bool {
+        self.next_after(since, tz).map(|t| t <= now).unwrap_or(false)
+    }
+}
+
+/// Get the instant for a wall clock time in a timezone. A time that happens twice is the
+/// first of the two and a time that does not exist is the first time after the gap.
+fn resolve_local_time<T: TimeZone>(tz: &T, t: &NaiveDateTime) -> Option<DateTime<Utc>> {
+    let mut t = *t;
+    // Gaps are never more than a day, in practice they are an hour.
+    for _ in 0..24 * 60 {
+        match tz.from_local_datetime(&t) {
+            LocalResult::Single(x) => return Some(x.with_timezone(&Utc)),
+            LocalResult::Ambiguous(earliest, _) => return Some(earliest.with_timezone(&Utc)),
+            LocalResult::None => t += Duration::minutes(1),
+        }
+    }
+
+    None
+}
+
+/// Parse a field of a cron expression into the values it matches.
+fn parse_cron_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<BTreeSet<u32>, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        if let Some(i) = names.iter().position(|n| n.eq_ignore_ascii_case(s)) {
+            return Ok(i as u32 + min);
+        }
+        let v: u32 = s.parse().map_err(|_| format!("has `{}` which is not a number", s))?;
+        if v < min || v > max {
+            return Err(format!("has `{}` which is not between {} and {}", v, min, max));
+        }
+        Ok(v)
+    };
+
+    let mut values = BTreeSet::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (r, s.parse::<u32>().ok().filter(|s| *s > 0).ok_or_else(|| format!("has `{}` which is not a step", s))?),
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            r => match r.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                // `5/15` means every 15 starting at 5.
+                None if step > 1 => (value(r)?, max),
+                None => (value(r)?, value(r)?),
+            },
+        };
+        if start > end {
+            return Err(format!("has the range `{}` which is backwards", range));
+        }
+
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::schedule::{parse_timezone, CronSchedule};
+
+    #[test]
+    fn test_parse_cron_schedule() {
+        let s = CronSchedule::parse("*/15 9-17 * * mon-fri").unwrap();
+        assert!(s.matches(&NaiveDate::from_ymd(2021, 4, 14).and_hms(9, 45, 0)));
+        assert!(!s.matches(&NaiveDate::from_ymd(2021, 4, 14).and_hms(9, 50, 0)));
+        assert!(!s.matches(&NaiveDate::from_ymd(2021, 4, 17).and_hms(9, 45, 0)));
+
+        // Sunday is both 0 and 7.
+        assert_eq!(CronSchedule::parse("0 0 * * 7").unwrap(), CronSchedule::parse("@weekly").unwrap());
+
+        assert!(CronSchedule::parse("0 9 * *").is_err());
+        assert!(CronSchedule::parse("60 9 * * *").is_err());
+        assert!(CronSchedule::parse("0 17-9 * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn test_cron_schedule_next_after() {
+        let tz = parse_timezone("America/Los_Angeles").unwrap();
+        let s = CronSchedule::parse("0 9 * * mon").unwrap();
+
+        // Wednesday, April 14th 2021 at noon in Los Angeles is 19:00 UTC.
+        let next = s.next_after(Utc.ymd(2021, 4, 14).and_hms(19, 0, 0), &tz).unwrap();
+        assert_eq!(next, Utc.ymd(2021, 4, 19).and_hms(16, 0, 0));
+        assert_eq!(s.next_after(next, &tz).unwrap(), Utc.ymd(2021, 4, 26).and_hms(16, 0, 0));
+
+        // The same schedule in UTC.
+        let utc = parse_timezone("").unwrap();
+        assert_eq!(s.next_after(Utc.ymd(2021, 4, 14).and_hms(19, 0, 0), &utc).unwrap(), Utc.ymd(2021, 4, 19).and_hms(9, 0, 0));
+
+        // When both days are set, either one fires.
+        let s = CronSchedule::parse("0 0 1 * fri").unwrap();
+        assert_eq!(s.next_after(Utc.ymd(2021, 4, 14).and_hms(0, 0, 0), &utc).unwrap(), Utc.ymd(2021, 4, 16).and_hms(0, 0, 0));
+        assert_eq!(s.next_after(Utc.ymd(2021, 4, 30).and_hms(0, 0, 0), &utc).unwrap(), Utc.ymd(2021, 5, 1).and_hms(0, 0, 0));
+
+        assert_eq!(
+            CronSchedule::parse("0 0 29 2 *").unwrap().next_after(Utc.ymd(2021, 4, 14).and_hms(0, 0, 0), &utc).unwrap(),
+            Utc.ymd(2024, 2, 29).and_hms(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_cron_schedule_daylight_saving_time() {
+        let tz = parse_timezone("America/Los_Angeles").unwrap();
+
+        // 02:30 did not happen on March 14th 2021, the clocks went from 02:00 to 03:00 PDT.
+        let s = CronSchedule::parse("30 2 * * *").unwrap();
+        let next = s.next_after(Utc.ymd(2021, 3, 14).and_hms(0, 0, 0), &tz).unwrap();
+        assert_eq!(next, Utc.ymd(2021, 3, 14).and_hms(10, 0, 0));
+        assert_eq!(s.next_after(next, &tz).unwrap(), Utc.ymd(2021, 3, 15).and_hms(9, 30, 0));
+
+        // 01:30 happened twice on November 7th 2021, we only fire the first time.
+        let s = CronSchedule::parse("30 1 * * *").unwrap();
+        let next = s.next_after(Utc.ymd(2021, 11, 7).and_hms(0, 0, 0), &tz).unwrap();
+        assert_eq!(next, Utc.ymd(2021, 11, 7).and_hms(8, 30, 0));
+        assert_eq!(s.next_after(next, &tz).unwrap(), Utc.ymd(2021, 11, 8).and_hms(9, 30, 0));
+
+        // A schedule firing every 15 minutes fires once at the end of the gap.
+        let s = CronSchedule::parse("*/15 * * * *").unwrap();
+        let before = Utc.ymd(2021, 3, 14).and_hms(9, 45, 0);
+        let next = s.next_after(before, &tz).unwrap();
+        assert_eq!(next, Utc.ymd(2021, 3, 14).and_hms(10, 0, 0));
+        assert_eq!(s.next_after(next, &tz).unwrap(), Utc.ymd(2021, 3, 14).and_hms(10, 15, 0));
+
+        assert!(s.fired_between(before, next, &tz));
+        assert!(!s.fired_between(next, next, &tz));
+    }
+}
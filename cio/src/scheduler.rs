@@ -0,0 +1,610 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::time::{Instant, SystemTime};
+
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration};
+use chrono_tz::Tz;
+use hubcaps::Github;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::business_days::BusinessCalendar;
+use crate::circuit_breakers::{skipped_result, CircuitBreakers};
+use crate::configs::{get_configs_sha, try_get_configs_from_repo, Config};
+use crate::dashboard::{publish_dashboard, DashboardJob};
+use crate::db::Database;
+use crate::doctor::catch_panic;
+use crate::filter::RecordFilter;
+use crate::format::format_time;
+use crate::job_runs::{record_job_run, start_job_usage};
+use crate::jobs::{job_services, run_job, JOBS, LOW_PRIORITY_JOBS};
+use crate::maintenance::{paused_by, sync_maintenance_windows};
+use crate::progress::format_duration;
+use crate::rate_limits::low_rate_limits;
+use crate::schedule::{parse_timezone, CronSchedule};
+use crate::stale::StaleConfig;
+use crate::utils::authenticate_github_jwt;
+
+/// The data type for our `sync.toml` file which configures when each job runs.
+///
+/// ```toml
+/// [jobs.applicants]
+/// every = "5m"
+///
+/// [jobs.software_vendors]
+/// every = "7d"
+/// enabled = false
+///
+/// [jobs.stale_items]
+/// cron = "0 9 * * mon"
+/// timezone = "America/Los_Angeles"
+///
+/// [jobs.vendor_security_reviews]
+/// cron = "0 10 * * *"
+/// business_days_only = true
+///
+/// [jobs.profiles]
+/// low_priority = false
+///
+/// [stale]
+/// branch_days = 60
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct SyncConfig {
+    /// The timezone for the cron schedules of jobs that do not set their own,
+    /// defaults to UTC.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub timezone: String,
+    #[serde(default)]
+    pub jobs: BTreeMap<String, JobScheduleConfig>,
+    /// When the `stale_items` job considers branches and pull requests stale.
+    #[serde(default)]
+    pub stale: StaleConfig,
+}
+
+/// The schedule for a single job.
+#[derive(Debug, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct JobScheduleConfig {
+    /// How often the job runs, ie. `30s`, `5m`, `6h`, or `7d`.
+    /// Defaults to the interval for the job in `JOBS`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub every: String,
+    /// When the job runs as a cron expression, ie. `0 9 * * mon-fri`, read in the
+    /// timezone for the job. This takes the place of `every`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub cron: String,
+    /// The timezone for `cron`, ie. `America/Los_Angeles`. Defaults to the timezone
+    /// for the sync config.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub timezone: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Skip the runs that fall on weekends and the holidays in our configs, in the
+    /// timezone for the job. This is for jobs that send reminders.
+    #[serde(default)]
+    pub business_days_only: bool,
+    /// Defer the job while we are close to the rate limit for an API. Defaults to
+    /// whether the job is in `LOW_PRIORITY_JOBS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_priority: Option<bool>,
+}
+
+/// When a job runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobSchedule {
+    /// The job runs when the scheduler starts and then every interval.
+    Every(Duration),
+    /// The job runs when the cron schedule fires in the timezone.
+    Cron(CronSchedule, Tz),
+}
+
+impl JobSchedule {
+    /// Returns if a job that last ran at `last_run` is due. Jobs on a cron schedule
+    /// that have not run since the scheduler started wait for the schedule to fire.
+    #[instrument]
+    #[inline]
+    pub fn is_due(&self, last_run: Option<DateTime<Utc>>, started: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        match (self, last_run) {
+            (JobSchedule::Every(interval), Some(t)) => now - t >= *interval,
+            (JobSchedule::Every(_), None) => true,
+            (JobSchedule::Cron(cron, tz), t) => cron.fired_between(t.unwrap_or(started), now, tz),
+        }
+    }
+
+    /// Returns when a job that last ran at `last_run` runs next, ie. now if it has not run
+    /// yet and runs on an interval.
+    #[instrument]
+    #[inline]
+    pub fn next_run(&self, last_run: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match (self, last_run) {
+            (JobSchedule::Every(interval), Some(t)) => Some(t + *interval),
+            (JobSchedule::Every(_), None) => Some(now),
+            (JobSchedule::Cron(cron, tz), _) => cron.next_after(now, tz),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl SyncConfig {
+    /// Read and decode the sync config from a file.
+    #[instrument]
+    #[inline]
+    pub fn read(file: &str) -> Self {
+        let body = fs::read_to_string(file).expect("reading the sync config failed");
+        toml::from_str(&body).expect("decoding the sync config failed")
+    }
+
+    /// Returns the interval for a job, or `None` if the job is disabled.
+    #[instrument]
+    #[inline]
+    pub fn interval(&self, job: &str, default: &str) -> Option<Duration> {
+        match self.jobs.get(job) {
+            Some(schedule) if !schedule.enabled => None,
+            Some(schedule) if !schedule.every.is_empty() => parse_interval(&schedule.every).ok(),
+            _ => parse_interval(default).ok(),
+        }
+    }
+
+    /// Returns the schedule for a job, or `None` if the job is disabled or its
+    /// schedule is invalid. Use `validate` to find out what is invalid.
+    #[instrument]
+    #[inline]
+    pub fn schedule(&self, job: &str, default: &str) -> Option<JobSchedule> {
+        match self.jobs.get(job) {
+            Some(schedule) if schedule.enabled && !schedule.cron.is_empty() => Some(JobSchedule::Cron(CronSchedule::parse(&schedule.cron).ok()?, self.timezone_for(job))),
+            _ => self.interval(job, default).map(JobSchedule::Every),
+        }
+    }
+
+    /// Describe when a job runs for people, ie. `every 6h`,
+    /// `0 9 * * mon (America/Los_Angeles)`, or `disabled`.
+    #[instrument]
+    #[inline]
+    pub fn describe_schedule(&self, job: &str, default: &str) -> String {
+        match self.jobs.get(job) {
+            Some(schedule) if !schedule.enabled => "disabled".to_string(),
+            Some(schedule) if !schedule.cron.is_empty() => format!("{} ({})", schedule.cron, self.timezone_for(job).name()),
+            Some(schedule) if !schedule.every.is_empty() => format!("every {}", schedule.every),
+            _ => format!("every {}", default),
+        }
+    }
+
+    /// Returns if a job should only run on business days.
+    #[instrument]
+    #[inline]
+    pub fn business_days_only(&self, job: &str) -> bool {
+        self.jobs.get(job).map(|s| s.business_days_only).unwrap_or(false)
+    }
+
+    /// Returns if a job waits for headroom when we are close to the rate limit for an API.
+    #[instrument]
+    #[inline]
+    pub fn is_low_priority(&self, job: &str) -> bool {
+        self.jobs.get(job).and_then(|s| s.low_priority).unwrap_or_else(|| LOW_PRIORITY_JOBS.contains(&job))
+    }
+
+    /// Get the timezone for a job, UTC if it is invalid.
+    #[instrument]
+    #[inline]
+    pub fn timezone_for(&self, job: &str) -> Tz {
+        let tz = match self.jobs.get(job) {
+            Some(s) if !s.timezone.is_empty() => &s.timezone,
+            _ => &self.timezone,
+        };
+
+        parse_timezone(tz).unwrap_or(Tz::UTC)
+    }
+
+    /// Check the schedules and timezones, returning what is wrong with them.
+    #[instrument]
+    #[inline]
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors: Vec<String> = Default::default();
+        if let Err(e) = parse_timezone(&self.timezone) {
+            errors.push(e);
+        }
+
+        for (job, schedule) in self.jobs.iter() {
+            if !JOBS.iter().any(|(name, _)| name == job) {
+                errors.push(format!("job `{}` does not exist", job));
+            }
+            if !schedule.every.is_empty() && !schedule.cron.is_empty() {
+                errors.push(format!("job `{}` has both `every` and `cron`, use one", job));
+            }
+            if !schedule.every.is_empty() {
+                if let Err(e) = parse_interval(&schedule.every) {
+                    errors.push(format!("job `{}`: {}", job, e));
+                }
+            }
+            if !schedule.cron.is_empty() {
+                if let Err(e) = CronSchedule::parse(&schedule.cron) {
+                    errors.push(format!("job `{}`: {}", job, e));
+                }
+            }
+            if let Err(e) = parse_timezone(&schedule.timezone) {
+                errors.push(format!("job `{}`: {}", job, e));
+            }
+        }
+
+        if self.stale.branch_days <= 0 || self.stale.pull_request_days <= 0 {
+            errors.push("the days in `stale` must be more than zero".to_string());
+        }
+
+        errors
+    }
+}
+
+/// The longest interval a job can run at, in days.
+const MAX_INTERVAL_DAYS: i64 = 365;
+
+/// Parse an interval like `30s`, `5m`, `6h`, or `7d`, which must be more than zero and
+/// at most `MAX_INTERVAL_DAYS`.
+#[instrument]
+#[inline]
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (i, unit) = s.char_indices().last().ok_or_else(|| "the interval is empty, it should be like `5m`".to_string())?;
+    let n = s[..i].trim().parse::<i64>().map_err(|_| format!("`{}` is not an interval like `5m`", s))?;
+    if n <= 0 {
+        return Err(format!("the interval `{}` must be more than zero", s));
+    }
+
+    let unit_seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return Err(format!("`{}` is not an interval like `5m`, the unit must be one of s, m, h, d", s)),
+    };
+    // Check the bounds before making the duration, which panics if it overflows.
+    match n.checked_mul(unit_seconds) {
+        Some(seconds) if seconds <= MAX_INTERVAL_DAYS * 24 * 60 * 60 => Ok(Duration::seconds(seconds)),
+        _ => Err(format!("the interval `{}` must be at most {}d", s, MAX_INTERVAL_DAYS)),
+    }
+}
+
+/// How often we check the configs repo for changes.
+const CONFIG_POLL_INTERVAL_MINUTES: i64 = 5;
+
+/// Get the time the sync config file was last modified.
+fn modified(file: &str) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+/// Get the configs from the repo, returning `None` if they fail to decode or
+/// validate so we keep running with the previous version.
+#[instrument]
+#[inline]
+async fn reload_configs(github: &Github) -> Option<Config> {
+    let configs = match try_get_configs_from_repo(github).await {
+        Ok(c) => c,
+        Err(e) => {
+            println!("[scheduler] decoding the new configs failed, keeping the old configs:\n{}", e.join("\n"));
+            return None;
+        }
+    };
+
+    let errors = configs.validate();
+    if !errors.is_empty() {
+        println!("[scheduler] the new configs are invalid, keeping the old configs:\n{}", errors.join("\n"));
+        return None;
+    }
+
+    Some(configs)
+}
+
+/// Get the status of each job for the dashboard, from when the jobs last ran, how long
+/// they took, and how the last run went.
+#[instrument(skip(sync_config, last_run, durations, results))]
+#[inline]
+fn job_statuses(
+    sync_config: &SyncConfig,
+    last_run: &BTreeMap<String, DateTime<Utc>>,
+    durations: &BTreeMap<String, std::time::Duration>,
+    results: &BTreeMap<String, String>,
+    now: DateTime<Utc>,
+) -> Vec<DashboardJob> {
+    JOBS.iter()
+        .map(|(job, default)| {
+            let last = last_run.get(*job).copied();
+            DashboardJob {
+                name: job.to_string(),
+                schedule: sync_config.describe_schedule(job, default),
+                last_run: last.map(format_time).unwrap_or_default(),
+                duration: durations.get(*job).map(|d| format_duration(*d)).unwrap_or_default(),
+                next_run: sync_config.schedule(job, default).and_then(|s| s.next_run(last, now)).map(format_time).unwrap_or_default(),
+                result: results.get(*job).cloned().unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+/// Run the jobs on their schedules forever. The sync config is reloaded whenever
+/// the file changes so schedules can be changed without restarting the daemon.
+/// Likewise, the configs repo is polled for new commits and the config used by
+/// the jobs is swapped out once the new version is decoded and validated. After each
+/// cycle that runs jobs, the internal dashboard is published with their status.
+/// Jobs that come due during a maintenance window in our configs wait for the window
+/// to end and then run right away to catch up. Low priority jobs that come due while
+/// we are close to the rate limit for an API wait until we have headroom again.
+/// Each job runs with a circuit breaker for the external services it depends on, so
+/// when one is down its jobs are skipped until a probe finds it is back, and then run.
+#[instrument]
+#[inline]
+pub async fn run_scheduler(sync_config_file: &str) {
+    let db = Database::new();
+    let github = authenticate_github_jwt();
+
+    let mut configs_sha = get_configs_sha().await;
+    let mut configs = match try_get_configs_from_repo(&github).await {
+        Ok(c) => c,
+        Err(e) => panic!("decoding the configs failed:\n{}", e.join("\n")),
+    };
+    let mut configs_checked = Utc::now();
+    sync_maintenance_windows(&db, &configs.maintenance);
+
+    let mut sync_config = SyncConfig::read(sync_config_file);
+    let errors = sync_config.validate();
+    if !errors.is_empty() {
+        panic!("{} is invalid:\n{}", sync_config_file, errors.join("\n"));
+    }
+    let mut sync_config_modified = modified(sync_config_file);
+    let started = Utc::now();
+    let mut last_run: BTreeMap<String, DateTime<Utc>> = Default::default();
+    let mut durations: BTreeMap<String, std::time::Duration> = Default::default();
+    let mut paused: BTreeSet<String> = Default::default();
+    let mut deferred: BTreeSet<String> = Default::default();
+    let mut results: BTreeMap<String, String> = Default::default();
+    let mut breakers = CircuitBreakers::default();
+
+    loop {
+        // Reload the sync config if it changed. If the new sync config is invalid
+        // we keep running with the old one.
+        let m = modified(sync_config_file);
+        if m != sync_config_modified {
+            sync_config_modified = m;
+            match fs::read_to_string(sync_config_file).map(|body| toml::from_str::<SyncConfig>(&body)) {
+                Ok(Ok(c)) => {
+                    let errors = c.validate();
+                    if errors.is_empty() {
+                        println!("[scheduler] reloaded {}", sync_config_file);
+                        sync_config = c;
+                    } else {
+                        println!("[scheduler] {} is invalid, keeping the old config:\n{}", sync_config_file, errors.join("\n"));
+                    }
+                }
+                Ok(Err(e)) => println!("[scheduler] decoding {} failed, keeping the old config: {}", sync_config_file, e),
+                Err(e) => println!("[scheduler] reading {} failed, keeping the old config: {}", sync_config_file, e),
+            }
+        }
+
+        if Utc::now() - configs_checked >= Duration::minutes(CONFIG_POLL_INTERVAL_MINUTES) {
+            configs_checked = Utc::now();
+            let sha = get_configs_sha().await;
+            if !sha.is_empty() && sha != configs_sha {
+                if let Some(c) = reload_configs(&github).await {
+                    println!("[scheduler] reloaded the configs at {}", sha);
+                    sync_maintenance_windows(&db, &c.maintenance);
+                    configs = c;
+                    configs_sha = sha;
+                }
+            }
+        }
+
+        let low = low_rate_limits(&db, Utc::now());
+        let mut ran = !breakers.probe(&db, Utc::now()).await.is_empty();
+        for (job, default) in JOBS {
+            let schedule = match sync_config.schedule(job, default) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            let due = schedule.is_due(last_run.get(*job).copied(), started, Utc::now());
+            if let Some(window) = paused_by(&configs.maintenance, job, Utc::now()) {
+                if due && paused.insert(job.to_string()) {
+                    println!("[scheduler] pausing job {} until the {} maintenance window ends", job, window);
+                }
+                continue;
+            }
+
+            // Jobs that were paused catch up as soon as the window ends.
+            let catch_up = paused.remove(*job);
+            if !due && !catch_up {
+                continue;
+            }
+
+            // We leave last_run alone so the job runs as soon as we have headroom again.
+            if !low.is_empty() && sync_config.is_low_priority(job) {
+                if deferred.insert(job.to_string()) {
+                    println!("[scheduler] deferring job {}, we are close to the rate limit for {}", job, low.join(", "));
+                }
+                if catch_up {
+                    paused.insert(job.to_string());
+                }
+                continue;
+            }
+            deferred.remove(*job);
+
+            // Like deferred jobs, skipped jobs run as soon as the service is back.
+            let down = breakers.open_for(job_services(job));
+            if !down.is_empty() {
+                let result = skipped_result(&down);
+                if results.get(*job) != Some(&result) {
+                    println!("[scheduler] skipping job {}, {} is unavailable", job, down.join(", "));
+                    results.insert(job.to_string(), result);
+                    ran = true;
+                }
+                if catch_up {
+                    paused.insert(job.to_string());
+                }
+                continue;
+            }
+
+            let today = Utc::now().with_timezone(&sync_config.timezone_for(job)).naive_local().date();
+            if sync_config.business_days_only(job) && !BusinessCalendar::new(&configs.holidays).is_business_day(today) {
+                println!("[scheduler] skipping job {}, {} is not a business day", job, today);
+                last_run.insert(job.to_string(), Utc::now());
+                continue;
+            }
+
+            if catch_up {
+                println!("[scheduler] running job {} to catch up after maintenance", job);
+            } else {
+                println!("[scheduler] running job {}", job);
+            }
+            let started_at = Utc::now();
+            last_run.insert(job.to_string(), started_at);
+            start_job_usage();
+            let start = Instant::now();
+            // Run the job on its own task so a job that panics fails rather than taking
+            // down the scheduler.
+            let (job_db, job_github, job_configs, job_sync_config, name) = (db.clone(), github.clone(), configs.clone(), sync_config.clone(), job.to_string());
+            let result = catch_panic(async move {
+                run_job(&job_db, &job_github, &job_configs, &job_sync_config, &name, &RecordFilter::default()).await;
+            })
+            .await;
+            durations.insert(job.to_string(), start.elapsed());
+            if let Err(e) = &result {
+                println!("[scheduler] job {} failed: {}", job, e);
+            }
+            record_job_run(&db, job, started_at, start.elapsed(), &result);
+            results.insert(job.to_string(), result.as_ref().map(|_| "ok".to_string()).unwrap_or_else(|e| format!("failed: {}", e)));
+            for service in breakers.record_job(job_services(job), &result, Utc::now()) {
+                println!("[scheduler] opening the circuit breaker for {}, skipping the jobs that depend on it until it recovers", service);
+                breakers.notify_opened(&db, &service).await;
+            }
+            ran = true;
+        }
+
+        if ran {
+            publish_dashboard(&db, job_statuses(&sync_config, &last_run, &durations, &results, Utc::now())).await;
+        }
+
+        tokio::time::delay_for(std::time::Duration::from_secs(30)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::scheduler::{parse_interval, JobSchedule, SyncConfig};
+    use crate::stale::STALE_PULL_REQUEST_DAYS;
+
+    #[test]
+    fn test_sync_config_intervals() {
+        assert_eq!(parse_interval("5m"), Ok(Duration::minutes(5)));
+        assert_eq!(parse_interval("7d"), Ok(Duration::days(7)));
+        assert!(parse_interval("5").is_err());
+        assert!(parse_interval("fivem").is_err());
+        assert!(parse_interval("5é").is_err());
+        assert!(parse_interval("é").is_err());
+        assert!(parse_interval("").is_err());
+        assert_eq!(parse_interval("0m"), Err("the interval `0m` must be more than zero".to_string()));
+        assert!(parse_interval("-5m").is_err());
+        assert_eq!(parse_interval("365d"), Ok(Duration::days(365)));
+        assert_eq!(parse_interval("366d"), Err("the interval `366d` must be at most 365d".to_string()));
+        assert!(parse_interval("99999999999999d").is_err());
+        assert!(parse_interval(&format!("{}s", i64::MAX)).is_err());
+
+        let config: SyncConfig = toml::from_str(
+            r#"[jobs.applicants]
+every = "5m"
+
+[jobs.software_vendors]
+enabled = false
+
+[jobs.profiles]
+low_priority = false
+
+[stale]
+branch_days = 60
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.interval("applicants", "6h"), Some(Duration::minutes(5)));
+        assert_eq!(config.interval("software_vendors", "7d"), None);
+        assert_eq!(config.interval("rfds", "6h"), Some(Duration::hours(6)));
+
+        assert!(config.is_low_priority("github_repos"));
+        assert!(!config.is_low_priority("profiles"));
+        assert!(!config.is_low_priority("rfds"));
+
+        assert_eq!(config.stale.branch_days, 60);
+        assert_eq!(config.stale.pull_request_days, STALE_PULL_REQUEST_DAYS);
+    }
+
+    #[test]
+    fn test_sync_config_cron_schedules() {
+        let config: SyncConfig = toml::from_str(
+            r#"timezone = "America/Los_Angeles"
+
+[jobs.stale_items]
+cron = "0 9 * * mon"
+
+[jobs.notification_digests]
+cron = "0 9 * * *"
+timezone = "Europe/London"
+business_days_only = true
+"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_empty());
+
+        let started = Utc.ymd(2021, 4, 12).and_hms(15, 0, 0);
+        let schedule = config.schedule("stale_items", "7d").unwrap();
+        // Monday, April 12th 2021 at 09:00 in Los Angeles is 16:00 UTC.
+        assert!(!schedule.is_due(None, started, Utc.ymd(2021, 4, 12).and_hms(15, 59, 0)));
+        assert!(schedule.is_due(None, started, Utc.ymd(2021, 4, 12).and_hms(16, 0, 30)));
+        assert!(!schedule.is_due(Some(Utc.ymd(2021, 4, 12).and_hms(16, 0, 30)), started, Utc.ymd(2021, 4, 13).and_hms(16, 0, 0)));
+
+        // The job timezone wins over the sync config timezone, 09:00 in London is 08:00 UTC.
+        let schedule = config.schedule("notification_digests", "1d").unwrap();
+        assert!(schedule.is_due(None, started, Utc.ymd(2021, 4, 13).and_hms(8, 0, 0)));
+        assert!(config.business_days_only("notification_digests"));
+        assert!(!config.business_days_only("stale_items"));
+        assert_eq!(config.timezone_for("notification_digests"), chrono_tz::Europe::London);
+
+        assert_eq!(config.schedule("rfds", "6h"), Some(JobSchedule::Every(Duration::hours(6))));
+
+        assert_eq!(config.describe_schedule("stale_items", "7d"), "0 9 * * mon (America/Los_Angeles)");
+        assert_eq!(config.describe_schedule("rfds", "6h"), "every 6h");
+        // Monday, April 19th 2021 at 09:00 in Los Angeles is the next run after the last one.
+        let schedule = config.schedule("stale_items", "7d").unwrap();
+        assert_eq!(
+            schedule.next_run(Some(Utc.ymd(2021, 4, 12).and_hms(16, 0, 30)), Utc.ymd(2021, 4, 13).and_hms(16, 0, 0)),
+            Some(Utc.ymd(2021, 4, 19).and_hms(16, 0, 0))
+        );
+        let schedule = JobSchedule::Every(Duration::hours(6));
+        assert_eq!(schedule.next_run(None, started), Some(started));
+        assert_eq!(schedule.next_run(Some(started), started), Some(started + Duration::hours(6)));
+
+        let config: SyncConfig = toml::from_str(
+            r#"timezone = "Pacific/Nowhere"
+
+[jobs.stale_items]
+every = "7d"
+cron = "0 9 * * mon"
+
+[jobs.not_a_job]
+cron = "0 25 * * *"
+
+[jobs.rfds]
+every = "0m"
+
+[stale]
+pull_request_days = 0
+"#,
+        )
+        .unwrap();
+        let errors = config.validate();
+        assert_eq!(errors.len(), 6);
+        assert!(errors.contains(&"job `rfds`: the interval `0m` must be more than zero".to_string()));
+    }
+}
@@ -1,3 +1,61 @@
+table! {
+    alerts (id) {
+        id -> Int4,
+        alert_id -> Varchar,
+        repo -> Varchar,
+        tool -> Varchar,
+        number -> Int4,
+        state -> Varchar,
+        severity -> Varchar,
+        package -> Varchar,
+        summary -> Varchar,
+        html_url -> Varchar,
+        owner_team -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        assignee -> Varchar,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    anonymous_feedbacks (id) {
+        id -> Int4,
+        topic -> Varchar,
+        feedback -> Text,
+        sender_hash -> Varchar,
+        submitted_at -> Timestamptz,
+        digested -> Bool,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    applicant_emails (id) {
+        id -> Int4,
+        applicant_id -> Int4,
+        template -> Varchar,
+        to_email -> Varchar,
+        subject -> Varchar,
+        status -> Varchar,
+        gmail_id -> Varchar,
+        thread_id -> Varchar,
+        error -> Varchar,
+        created_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     applicant_interviews (id) {
         id -> Int4,
@@ -10,6 +68,28 @@ table! {
         event_link -> Varchar,
         applicant -> Array<Text>,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    applicant_messages (id) {
+        id -> Int4,
+        applicant_id -> Int4,
+        mailbox -> Varchar,
+        thread_id -> Varchar,
+        gmail_id -> Varchar,
+        message_id -> Varchar,
+        from_email -> Varchar,
+        recipients -> Array<Text>,
+        subject -> Varchar,
+        snippet -> Varchar,
+        direction -> Varchar,
+        sent_at -> Timestamptz,
     }
 }
 
@@ -25,6 +105,33 @@ table! {
         no -> Int4,
         not_applicable -> Int4,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    applicant_stage_changes (id) {
+        id -> Int4,
+        applicant_id -> Int4,
+        from_status -> Varchar,
+        to_status -> Varchar,
+        changed_at -> Timestamptz,
+        processed_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    applicant_threads (id) {
+        id -> Int4,
+        applicant_id -> Int4,
+        mailbox -> Varchar,
+        thread_id -> Varchar,
+        linked_by -> Varchar,
+        linked_at -> Timestamptz,
     }
 }
 
@@ -88,7 +195,32 @@ table! {
         criminal_background_check_status -> Varchar,
         motor_vehicle_background_check_status -> Varchar,
         geocode_cache -> Varchar,
+        referred_by -> Varchar,
+        take_home_repo -> Varchar,
+        take_home_score -> Float4,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    approval_requests (id) {
+        id -> Int4,
+        operation -> Varchar,
+        subject -> Varchar,
+        description -> Varchar,
+        approvers -> Array<Text>,
+        required -> Int4,
+        ordered -> Bool,
+        approved_by -> Array<Text>,
+        rejected_by -> Varchar,
+        status -> Varchar,
+        requested_at -> Timestamptz,
+        decided_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -118,6 +250,11 @@ table! {
         link_to_auth_user_logins -> Array<Text>,
         link_to_page_views -> Array<Text>,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -145,6 +282,48 @@ table! {
         user_agent -> Varchar,
         link_to_auth_user -> Array<Text>,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    background_checks (id) {
+        id -> Int4,
+        applicant_id -> Int4,
+        email -> Varchar,
+        candidate_id -> Varchar,
+        package -> Varchar,
+        report_id -> Varchar,
+        status -> Varchar,
+        result -> Varchar,
+        adjudication -> Varchar,
+        requested_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+        notified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    bucket_audits (id) {
+        id -> Int4,
+        bucket -> Varchar,
+        project -> Varchar,
+        public -> Bool,
+        bindings -> Array<Text>,
+        lifecycle_delete_age -> Int4,
+        retention_days -> Int4,
+        findings -> Array<Text>,
+        audited_at -> Timestamptz,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -163,6 +342,11 @@ table! {
         employees -> Array<Text>,
         conference_rooms -> Array<Text>,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -175,6 +359,54 @@ table! {
         valid_days_left -> Int4,
         expiration_date -> Date,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    cloud_costs (id) {
+        id -> Int4,
+        cost_id -> Varchar,
+        provider -> Varchar,
+        month -> Date,
+        project -> Varchar,
+        service -> Varchar,
+        owner_group -> Varchar,
+        cost -> Float4,
+        currency -> Varchar,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    cohort_events (id) {
+        id -> Int4,
+        cohort -> Varchar,
+        milestone -> Varchar,
+        date -> Date,
+        calendar_id -> Varchar,
+        event_id -> Varchar,
+        synced_at -> Timestamptz,
+    }
+}
+
+table! {
+    cohort_members (id) {
+        id -> Int4,
+        cohort -> Varchar,
+        applicant_id -> Int4,
+        source -> Varchar,
+        start_date -> Nullable<Date>,
+        added_at -> Timestamptz,
     }
 }
 
@@ -190,6 +422,94 @@ table! {
         floor -> Varchar,
         section -> Varchar,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    docusign_envelopes (id) {
+        id -> Int4,
+        envelope_id -> Varchar,
+        template -> Varchar,
+        applicant_id -> Nullable<Int4>,
+        software_vendor_id -> Nullable<Int4>,
+        signer_name -> Varchar,
+        signer_email -> Varchar,
+        status -> Varchar,
+        sent_at -> Timestamptz,
+        status_changed_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+        reminded_at -> Nullable<Timestamptz>,
+        reminders -> Int4,
+    }
+}
+
+table! {
+    engineering_metrics (id) {
+        id -> Int4,
+        team -> Varchar,
+        week -> Date,
+        prs_opened -> Int4,
+        prs_merged -> Int4,
+        median_review_hours -> Float4,
+        median_merge_hours -> Float4,
+        deploys -> Int4,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    event_registrants (id) {
+        id -> Int4,
+        registrant_id -> Varchar,
+        platform -> Varchar,
+        event_id -> Varchar,
+        event_name -> Varchar,
+        event_start -> Nullable<Timestamptz>,
+        event_url -> Varchar,
+        email -> Varchar,
+        first_name -> Varchar,
+        last_name -> Varchar,
+        name -> Varchar,
+        company -> Varchar,
+        registered_at -> Timestamptz,
+        existing_contact -> Bool,
+        link_to_mailing_list_signups -> Array<Text>,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    github_actions_usages (id) {
+        id -> Int4,
+        repo -> Varchar,
+        date -> Date,
+        runs -> Int4,
+        ubuntu_minutes -> Int4,
+        macos_minutes -> Int4,
+        windows_minutes -> Int4,
+        billable_minutes -> Int4,
+        cost -> Float4,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -263,6 +583,11 @@ table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -285,6 +610,20 @@ table! {
         who_can_view_membership -> Varchar,
         enable_collaborative_inbox -> Bool,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    headcount_plans (id) {
+        id -> Int4,
+        team -> Varchar,
+        quarter -> Varchar,
+        planned -> Int4,
     }
 }
 
@@ -303,6 +642,29 @@ table! {
         name -> Varchar,
         notes -> Varchar,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    job_runs (id) {
+        id -> Int4,
+        job -> Varchar,
+        started_at -> Timestamptz,
+        duration_ms -> Int8,
+        succeeded -> Bool,
+        error -> Varchar,
+        airtable_calls -> Int4,
+        github_calls -> Int4,
+        gsuite_calls -> Int4,
+        slack_calls -> Int4,
+        other_calls -> Int4,
+        db_checkouts -> Int4,
+        rows_written -> Int4,
     }
 }
 
@@ -318,6 +680,11 @@ table! {
         state -> Varchar,
         recording -> Varchar,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -329,6 +696,11 @@ table! {
         meeting -> Varchar,
         link_to_meeting -> Array<Text>,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -341,6 +713,33 @@ table! {
         aliases -> Array<Text>,
         short_link -> Varchar,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    lunch_days (id) {
+        id -> Int4,
+        day -> Date,
+        posted_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+        orders -> Int4,
+        total_cost -> Float4,
+    }
+}
+
+table! {
+    lunch_orders (id) {
+        id -> Int4,
+        day -> Date,
+        username -> Varchar,
+        item -> Varchar,
+        price -> Float4,
+        ordered_at -> Timestamptz,
     }
 }
 
@@ -363,6 +762,122 @@ table! {
         tags -> Array<Text>,
         link_to_people -> Array<Text>,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    maintenance_windows (id) {
+        id -> Int4,
+        name -> Varchar,
+        reason -> Varchar,
+        starts_at -> Timestamptz,
+        ends_at -> Timestamptz,
+        jobs -> Array<Text>,
+        keep_notifications -> Bool,
+    }
+}
+
+table! {
+    match_reviews (id) {
+        id -> Int4,
+        kind -> Varchar,
+        name -> Varchar,
+        candidate -> Varchar,
+        score -> Float4,
+        status -> Varchar,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    oauth_grants (id) {
+        id -> Int4,
+        grant_id -> Varchar,
+        email -> Varchar,
+        client_id -> Varchar,
+        app_name -> Varchar,
+        scopes -> Array<Text>,
+        high_risk_scopes -> Array<Text>,
+        native_app -> Bool,
+        first_seen_at -> Timestamptz,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    offboarded_users (id) {
+        id -> Int4,
+        username -> Varchar,
+        email -> Varchar,
+        first_name -> Varchar,
+        last_name -> Varchar,
+        offboarded_at -> Timestamptz,
+        start_date -> Nullable<Date>,
+    }
+}
+
+table! {
+    offers (id) {
+        id -> Int4,
+        applicant_id -> Int4,
+        level -> Varchar,
+        location -> Varchar,
+        salary -> Int8,
+        currency -> Varchar,
+        start_date -> Nullable<Date>,
+        band_min -> Int8,
+        band_max -> Int8,
+        in_band -> Bool,
+        created_by -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    office_bookings (id) {
+        id -> Int4,
+        resource -> Varchar,
+        building -> Varchar,
+        desk -> Bool,
+        username -> Varchar,
+        day -> Date,
+        booked_at -> Timestamptz,
+    }
+}
+
+table! {
+    open_roles (id) {
+        id -> Int4,
+        name -> Varchar,
+        team -> Varchar,
+        location -> Varchar,
+        description -> Text,
+        job_description_url -> Varchar,
+        application_form_url -> Varchar,
+        sheet_id -> Varchar,
+        open -> Bool,
+        hiring_manager -> Varchar,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -376,6 +891,78 @@ table! {
         page_link -> Varchar,
         link_to_auth_user -> Array<Text>,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    pending_notifications (id) {
+        id -> Int4,
+        channel -> Varchar,
+        event -> Varchar,
+        message -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    people_snapshots (id) {
+        id -> Int4,
+        taken_on -> Date,
+        headcount -> Int4,
+        median_tenure_days -> Int4,
+        tenure_under_1y -> Int4,
+        tenure_1y_to_2y -> Int4,
+        tenure_2y_to_4y -> Int4,
+        tenure_over_4y -> Int4,
+        departures_12m -> Int4,
+        attrition_12m -> Float4,
+    }
+}
+
+table! {
+    policy_acknowledgments (id) {
+        id -> Int4,
+        policy -> Varchar,
+        version -> Varchar,
+        username -> Varchar,
+        requested_at -> Timestamptz,
+        acknowledged_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    profiles (id) {
+        id -> Int4,
+        email -> Varchar,
+        name -> Varchar,
+        skills -> Array<Text>,
+        interests -> Array<Text>,
+        available_to_mentor -> Bool,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    rate_limits (id) {
+        id -> Int4,
+        service -> Varchar,
+        request_limit -> Int4,
+        remaining -> Int4,
+        resets_at -> Nullable<Timestamptz>,
+        throttled -> Int4,
+        last_throttled_at -> Nullable<Timestamptz>,
+        retry_after_secs -> Int4,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -397,6 +984,118 @@ table! {
         event_link -> Varchar,
         location -> Varchar,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    referrals (id) {
+        id -> Int4,
+        applicant_id -> Int4,
+        email -> Varchar,
+        referrer -> Varchar,
+        source -> Varchar,
+        referred_at -> Timestamptz,
+        applied_directly_at -> Nullable<Timestamptz>,
+        hired_at -> Nullable<Timestamptz>,
+        bonus -> Float4,
+        bonus_paid_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    repo_license_audits (id) {
+        id -> Int4,
+        repo -> Varchar,
+        manifests -> Array<Text>,
+        dependencies_count -> Int4,
+        licenses -> Array<Text>,
+        copyleft_dependencies -> Array<Text>,
+        unknown_dependencies -> Array<Text>,
+        audited_at -> Timestamptz,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    repo_owners (id) {
+        id -> Int4,
+        repo -> Varchar,
+        team -> Varchar,
+        owners -> Array<Text>,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    retention_cohorts (id) {
+        id -> Int4,
+        taken_on -> Date,
+        cohort -> Int4,
+        started -> Int4,
+        retained -> Array<Int4>,
+    }
+}
+
+table! {
+    review_assignments (id) {
+        id -> Int4,
+        cycle_id -> Int4,
+        kind -> Varchar,
+        reviewer -> Varchar,
+        subject -> Varchar,
+        response_id -> Varchar,
+        answers -> Jsonb,
+        completed_at -> Nullable<Timestamptz>,
+        reminders -> Int4,
+        reminded_on -> Nullable<Date>,
+    }
+}
+
+table! {
+    review_cycles (id) {
+        id -> Int4,
+        name -> Varchar,
+        opened_by -> Varchar,
+        opened_at -> Timestamptz,
+        closes_on -> Date,
+        closed_at -> Nullable<Timestamptz>,
+        archive_url -> Varchar,
+    }
+}
+
+table! {
+    rfd_authors (id) {
+        id -> Int4,
+        rfd_number -> Int4,
+        name -> Varchar,
+        email -> Varchar,
+        username -> Varchar,
+        github -> Varchar,
+        in_frontmatter -> Bool,
+        commits -> Int4,
+    }
+}
+
+table! {
+    rfd_references (id) {
+        id -> Int4,
+        from_number -> Int4,
+        to_number -> Int4,
     }
 }
 
@@ -422,6 +1121,99 @@ table! {
         pdf_link_github -> Varchar,
         pdf_link_google_drive -> Varchar,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    scheduled_messages (id) {
+        id -> Int4,
+        email -> Varchar,
+        message -> Jsonb,
+        deliver_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    security_events (id) {
+        id -> Int4,
+        event_id -> Varchar,
+        provider -> Varchar,
+        category -> Varchar,
+        event_type -> Varchar,
+        name -> Varchar,
+        actor_email -> Varchar,
+        target -> Varchar,
+        ip_address -> Varchar,
+        outcome -> Varchar,
+        details -> Array<Text>,
+        occurred_at -> Timestamptz,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    security_postures (id) {
+        id -> Int4,
+        username -> Varchar,
+        email -> Varchar,
+        has_okta -> Bool,
+        okta_factors -> Array<Text>,
+        password_changed_at -> Nullable<Timestamptz>,
+        has_gsuite -> Bool,
+        gsuite_2sv_enrolled -> Bool,
+        admin_roles -> Array<Text>,
+        score -> Int4,
+        violations -> Array<Text>,
+        checked_at -> Timestamptz,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    service_account_keys (id) {
+        id -> Int4,
+        name -> Varchar,
+        service_account -> Varchar,
+        key_id -> Varchar,
+        key_created_at -> Nullable<Timestamptz>,
+        age_days -> Int4,
+        max_age_days -> Int4,
+        secret -> Varchar,
+        rotated_at -> Nullable<Timestamptz>,
+        status -> Varchar,
+        error -> Varchar,
+        checked_at -> Timestamptz,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    slack_user_ids (id) {
+        id -> Int4,
+        email -> Varchar,
+        slack_user_id -> Varchar,
+        resolved_at -> Timestamptz,
     }
 }
 
@@ -442,7 +1234,98 @@ table! {
         flat_cost_per_month -> Float4,
         total_cost_per_month -> Float4,
         groups -> Array<Text>,
+        security_questionnaire_status -> Varchar,
+        data_classification -> Varchar,
+        dpa_signed -> Bool,
+        security_review_date -> Date,
+        receipts -> Jsonb,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    tailscale_devices (id) {
+        id -> Int4,
+        device_id -> Varchar,
+        name -> Varchar,
+        hostname -> Varchar,
+        os -> Varchar,
+        login -> Varchar,
+        username -> Varchar,
+        tags -> Array<Text>,
+        client_version -> Varchar,
+        status -> Varchar,
+        created -> Timestamptz,
+        last_seen -> Timestamptz,
+        expires -> Nullable<Timestamptz>,
+        airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    take_home_assignments (id) {
+        id -> Int4,
+        applicant_id -> Int4,
+        assignment -> Varchar,
+        repo -> Varchar,
+        github -> Varchar,
+        sent_at -> Timestamptz,
+        due_at -> Timestamptz,
+        submitted_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    take_home_grades (id) {
+        id -> Int4,
+        assignment_id -> Int4,
+        grader -> Varchar,
+        score -> Int4,
+        notes -> Text,
+        graded_at -> Timestamptz,
+    }
+}
+
+table! {
+    tokens (id) {
+        id -> Int4,
+        name -> Varchar,
+        service -> Varchar,
+        owner -> Varchar,
+        env_var -> Varchar,
+        scopes -> Array<Text>,
+        created -> Nullable<Date>,
+        expires -> Nullable<Date>,
+        last_reminded_at -> Nullable<Timestamptz>,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    training_completions (id) {
+        id -> Int4,
+        training -> Varchar,
+        username -> Varchar,
+        course -> Varchar,
+        completed_at -> Timestamptz,
+        source -> Varchar,
+        imported_at -> Timestamptz,
     }
 }
 
@@ -475,29 +1358,125 @@ table! {
         birthday -> Date,
         public_ssh_keys -> Array<Text>,
         typev -> Varchar,
+        timezone -> Varchar,
         airtable_record_id -> Varchar,
+        source -> Varchar,
+        last_synced_from -> Varchar,
+        last_synced_at -> Nullable<Timestamptz>,
+        extra -> Jsonb,
+        modified_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    visitors (id) {
+        id -> Int4,
+        name -> Varchar,
+        email -> Varchar,
+        company -> Varchar,
+        host -> Varchar,
+        visit_date -> Date,
+        registered_by -> Varchar,
+        nda_envelope_id -> Varchar,
+        arrived_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        source -> Varchar,
+        delivery_id -> Varchar,
+        received_at -> Timestamptz,
+    }
+}
+
+table! {
+    welcome_messages (id) {
+        id -> Int4,
+        slack_user_id -> Varchar,
+        email -> Varchar,
+        first_name -> Varchar,
+        step -> Varchar,
+        deliver_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
+    alerts,
+    anonymous_feedbacks,
+    applicant_emails,
     applicant_interviews,
+    applicant_messages,
     applicant_reviewers,
+    applicant_stage_changes,
+    applicant_threads,
     applicants,
+    approval_requests,
     auth_user_logins,
     auth_users,
+    background_checks,
+    bucket_audits,
     buildings,
     certificates,
+    cloud_costs,
+    cohort_events,
+    cohort_members,
     conference_rooms,
+    docusign_envelopes,
+    engineering_metrics,
+    event_registrants,
+    github_actions_usages,
     github_repos,
     groups,
+    headcount_plans,
     inbound_shipments,
+    job_runs,
     journal_club_meetings,
     journal_club_papers,
     links,
+    lunch_days,
+    lunch_orders,
     mailing_list_subscribers,
+    maintenance_windows,
+    match_reviews,
+    oauth_grants,
+    offboarded_users,
+    offers,
+    office_bookings,
+    open_roles,
     page_views,
+    pending_notifications,
+    people_snapshots,
+    policy_acknowledgments,
+    profiles,
+    rate_limits,
     recorded_meetings,
+    referrals,
+    repo_license_audits,
+    repo_owners,
+    retention_cohorts,
+    review_assignments,
+    review_cycles,
+    rfd_authors,
+    rfd_references,
     rfds,
+    scheduled_messages,
+    security_events,
+    security_postures,
+    service_account_keys,
+    slack_user_ids,
     software_vendors,
+    tailscale_devices,
+    take_home_assignments,
+    take_home_grades,
+    tokens,
+    training_completions,
     users,
+    visitors,
+    webhook_deliveries,
+    welcome_messages,
 );
@@ -0,0 +1,323 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use airtable_api::api_key_from_env;
+use reqwest::{Client, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::actions_usage::GithubActionsUsage;
+use crate::alerts::Alert;
+use crate::analytics::PageView;
+use crate::api_tokens::ApiToken;
+use crate::applicants::{Applicant, ApplicantReviewer};
+use crate::auth_logins::{AuthUser, AuthUserLogin};
+use crate::bucket_audits::BucketAudit;
+use crate::certs::Certificate;
+use crate::cloud_costs::CloudCost;
+use crate::configs::{Building, ConferenceRoom, Group, Link, OpenRole, RepoOwner, User};
+use crate::engineering_metrics::EngineeringMetric;
+use crate::event_registrants::EventRegistrant;
+use crate::feedback::AnonymousFeedback;
+use crate::finance::SoftwareVendor;
+use crate::interviews::ApplicantInterview;
+use crate::journal_clubs::{JournalClubMeeting, JournalClubPaper};
+use crate::key_rotation::ServiceAccountKey;
+use crate::licenses::RepoLicenseAudit;
+use crate::mailing_list::MailingListSubscriber;
+use crate::matching::MatchReview;
+use crate::models::{GithubRepo, RFD};
+use crate::oauth_grants::OAuthGrant;
+use crate::posture::SecurityPosture;
+use crate::profiles::Profile;
+use crate::recorded_meetings::RecordedMeeting;
+use crate::security_events::SecurityEvent;
+use crate::shipments::InboundShipment;
+use crate::tailscale::TailscaleDevice;
+use crate::vcr;
+
+/// A model we sync to Airtable and the fields it sends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncedModel {
+    pub name: String,
+    pub base_id: String,
+    pub table: String,
+    pub fields: Vec<String>,
+}
+
+/// Get the names of the fields a model serializes to Airtable, with the fields that have a
+/// different name in Airtable renamed to their column.
+fn model_fields<T: JsonSchema>(airtable_field_names: &[(&str, &str)]) -> Vec<String> {
+    let schema = schemars::schema_for!(T);
+    // The extra fields are the Airtable columns we do not have, sent back as they were,
+    // and the provenance fields and the modified time are only kept in the database.
+    let skipped = ["extra", "source", "last_synced_from", "last_synced_at", "modified_at"];
+    let fields: Vec<String> = schema
+        .schema
+        .object
+        .map(|o| o.properties.keys().filter(|k| !skipped.contains(&k.as_str())).cloned().collect())
+        .unwrap_or_default();
+
+    fields
+        .into_iter()
+        .map(|f| match airtable_field_names.iter().find(|(name, _)| *name == f) {
+            Some((_, column)) => column.to_string(),
+            None => f,
+        })
+        .collect()
+}
+
+macro_rules! synced_model {
+    ($t:ty) => {
+        SyncedModel {
+            name: stringify!($t).to_string(),
+            base_id: <$t>::airtable_base_id(),
+            table: <$t>::airtable_table(),
+            fields: model_fields::<$t>(&<$t>::airtable_field_names()),
+        }
+    };
+}
+
+/// Returns every model generated by the `db` macro, which we sync to Airtable.
+#[instrument]
+#[inline]
+pub fn synced_models() -> Vec<SyncedModel> {
+    vec![
+        synced_model!(Alert),
+        synced_model!(AnonymousFeedback),
+        synced_model!(ApiToken),
+        synced_model!(Applicant),
+        synced_model!(ApplicantInterview),
+        synced_model!(ApplicantReviewer),
+        synced_model!(AuthUser),
+        synced_model!(AuthUserLogin),
+        synced_model!(BucketAudit),
+        synced_model!(Building),
+        synced_model!(Certificate),
+        synced_model!(CloudCost),
+        synced_model!(ConferenceRoom),
+        synced_model!(EngineeringMetric),
+        synced_model!(EventRegistrant),
+        synced_model!(GithubActionsUsage),
+        synced_model!(GithubRepo),
+        synced_model!(Group),
+        synced_model!(InboundShipment),
+        synced_model!(JournalClubMeeting),
+        synced_model!(JournalClubPaper),
+        synced_model!(Link),
+        synced_model!(MailingListSubscriber),
+        synced_model!(MatchReview),
+        synced_model!(OAuthGrant),
+        synced_model!(OpenRole),
+        synced_model!(PageView),
+        synced_model!(Profile),
+        synced_model!(RecordedMeeting),
+        synced_model!(RepoLicenseAudit),
+        synced_model!(RepoOwner),
+        synced_model!(RFD),
+        synced_model!(SecurityEvent),
+        synced_model!(SecurityPosture),
+        synced_model!(ServiceAccountKey),
+        synced_model!(SoftwareVendor),
+        synced_model!(TailscaleDevice),
+        synced_model!(User),
+    ]
+}
+
+/// The drift between the fields of a model and its table in Airtable.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchemaDrift {
+    pub model: String,
+    pub base_id: String,
+    pub table: String,
+    /// If the table does not exist in the base at all.
+    pub table_missing: bool,
+    /// The fields we send that the Airtable table does not have. Airtable rejects
+    /// any record with these, so syncs for the model fail until they are added.
+    pub missing_in_airtable: Vec<String>,
+    /// The fields in the Airtable table that we do not send. These are usually
+    /// formulas, lookups, or links someone added by hand, so they are only informational.
+    pub missing_in_code: Vec<String>,
+}
+
+impl SchemaDrift {
+    /// Returns if the drift breaks syncing the model.
+    #[instrument]
+    #[inline]
+    pub fn is_breaking(&self) -> bool {
+        self.table_missing || !self.missing_in_airtable.is_empty()
+    }
+}
+
+/// Compare the fields of a model to the fields of its Airtable table.
+#[instrument]
+#[inline]
+pub fn compare_fields(model: &SyncedModel, airtable_fields: Option<&[String]>) -> SchemaDrift {
+    let mut drift = SchemaDrift {
+        model: model.name.to_string(),
+        base_id: model.base_id.to_string(),
+        table: model.table.to_string(),
+        ..Default::default()
+    };
+
+    let airtable_fields: BTreeSet<&String> = match airtable_fields {
+        Some(f) => f.iter().collect(),
+        None => {
+            drift.table_missing = true;
+            return drift;
+        }
+    };
+    let code_fields: BTreeSet<&String> = model.fields.iter().collect();
+
+    drift.missing_in_airtable = code_fields.difference(&airtable_fields).map(|f| f.to_string()).collect();
+    drift.missing_in_code = airtable_fields.difference(&code_fields).map(|f| f.to_string()).collect();
+
+    drift
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct BaseSchema {
+    #[serde(default)]
+    tables: Vec<TableSchema>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct TableSchema {
+    name: String,
+    #[serde(default)]
+    fields: Vec<FieldSchema>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct FieldSchema {
+    name: String,
+    #[serde(default, rename = "type")]
+    typev: String,
+}
+
+/// The types of Airtable fields that Airtable computes, which reject any value we send.
+pub static COMPUTED_AIRTABLE_FIELD_TYPES: &[&str] = &[
+    "autoNumber",
+    "button",
+    "count",
+    "createdBy",
+    "createdTime",
+    "externalSyncSource",
+    "formula",
+    "lastModifiedBy",
+    "lastModifiedTime",
+    "multipleLookupValues",
+    "rollup",
+];
+
+async fn get_airtable_base_schema(base_id: &str) -> Result<BaseSchema, String> {
+    // The load test answers these from its mock Airtable server.
+    let endpoint = crate::load_test::airtable_endpoint().unwrap_or_else(|| "https://api.airtable.com/v0/".to_string());
+    let client = Client::new();
+    let resp = vcr::send(&client, client.get(&format!("{}meta/bases/{}/tables", endpoint, base_id)).bearer_auth(api_key_from_env()))
+        .await
+        .map_err(|e| e.to_string())?;
+    match resp.status() {
+        StatusCode::OK => (),
+        s => return Err(format!("getting the schema for base {} failed with status {}: {}", base_id, s, resp.text().await.unwrap_or_default())),
+    };
+
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+/// Get the fields for each table in an Airtable base from the metadata API.
+#[instrument]
+#[inline]
+pub async fn get_airtable_base_fields(base_id: &str) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let schema = get_airtable_base_schema(base_id).await?;
+
+    Ok(schema.tables.into_iter().map(|t| (t.name, t.fields.into_iter().map(|f| f.name).collect())).collect())
+}
+
+/// Get the fields Airtable computes, ie. formulas and lookups, for each table in an
+/// Airtable base from the metadata API.
+#[instrument]
+#[inline]
+pub async fn get_airtable_base_computed_fields(base_id: &str) -> Result<BTreeMap<String, BTreeSet<String>>, String> {
+    let schema = get_airtable_base_schema(base_id).await?;
+
+    Ok(schema
+        .tables
+        .into_iter()
+        .map(|t| {
+            let computed = t.fields.into_iter().filter(|f| COMPUTED_AIRTABLE_FIELD_TYPES.contains(&f.typev.as_str())).map(|f| f.name).collect();
+            (t.name, computed)
+        })
+        .collect())
+}
+
+/// Compare every model we sync to its Airtable table.
+#[instrument]
+#[inline]
+pub async fn check_schema_drift() -> Result<Vec<SchemaDrift>, String> {
+    let mut bases: BTreeMap<String, BTreeMap<String, Vec<String>>> = Default::default();
+    let mut drifts: Vec<SchemaDrift> = Default::default();
+    for model in synced_models() {
+        if !bases.contains_key(&model.base_id) {
+            bases.insert(model.base_id.to_string(), get_airtable_base_fields(&model.base_id).await?);
+        }
+
+        drifts.push(compare_fields(&model, bases[&model.base_id].get(&model.table).map(|f| f.as_slice())));
+    }
+
+    Ok(drifts)
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::JsonSchema;
+
+    use crate::schema_drift::{compare_fields, model_fields, synced_models, SyncedModel};
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Thing {
+        name: String,
+        email: String,
+        extra: String,
+        last_synced_at: String,
+    }
+
+    #[test]
+    fn test_model_fields() {
+        let mut fields = model_fields::<Thing>(&[("name", "Candidate Name")]);
+        fields.sort();
+        assert_eq!(fields, vec!["Candidate Name".to_string(), "email".to_string()]);
+    }
+
+    #[test]
+    fn test_compare_fields() {
+        let model = SyncedModel {
+            name: "SoftwareVendor".to_string(),
+            base_id: "appduLHDVQ332gKyf".to_string(),
+            table: "Software Vendors".to_string(),
+            fields: vec!["id".to_string(), "name".to_string(), "users".to_string(), "owner".to_string()],
+        };
+
+        let airtable_fields = vec!["id".to_string(), "name".to_string(), "users".to_string(), "Total Cost".to_string()];
+        let drift = compare_fields(&model, Some(&airtable_fields));
+        assert!(drift.is_breaking());
+        assert_eq!(drift.missing_in_airtable, vec!["owner".to_string()]);
+        assert_eq!(drift.missing_in_code, vec!["Total Cost".to_string()]);
+
+        let drift = compare_fields(&model, None);
+        assert!(drift.table_missing);
+        assert!(drift.is_breaking());
+    }
+
+    #[test]
+    fn test_synced_models() {
+        let models = synced_models();
+        assert_eq!(models.len(), 38);
+
+        let vendors = models.iter().find(|m| m.name == "SoftwareVendor").unwrap();
+        assert_eq!(vendors.table, "Software Vendors");
+        assert!(vendors.fields.contains(&"airtable_record_id".to_string()));
+        assert!(!vendors.fields.contains(&"last_synced_from".to_string()));
+    }
+}
@@ -0,0 +1,275 @@
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::configs::{Groups, User, Users};
+use crate::db::Database;
+use crate::schema::offboarded_users;
+use crate::utils::default_date;
+
+pub static SCIM_USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub static SCIM_GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub static SCIM_LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+
+/// The most resources we return in a page if the client does not ask for fewer.
+pub static SCIM_MAX_COUNT: usize = 100;
+
+/// Someone who was removed from our configs. SCIM clients see them as deactivated rather
+/// than gone, so the vendors that sync from us suspend their accounts instead of deleting
+/// them and their data.
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[table_name = "offboarded_users"]
+pub struct NewOffboardedUser {
+    pub username: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub offboarded_at: DateTime<Utc>,
+    /// When they started, for our retention numbers. This is empty for people who left
+    /// before we kept it, or who did not have a start date in our configs.
+    pub start_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct OffboardedUser {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub offboarded_at: DateTime<Utc>,
+    pub start_date: Option<NaiveDate>,
+}
+
+/// Remember someone who was removed from our configs.
+#[instrument(skip(db))]
+#[inline]
+pub fn record_offboarded_user(db: &Database, user: &User) {
+    let entry = NewOffboardedUser {
+        username: user.username.to_string(),
+        email: user.email(),
+        first_name: user.first_name.to_string(),
+        last_name: user.last_name.to_string(),
+        offboarded_at: Utc::now(),
+        start_date: Some(user.start_date).filter(|d| *d != default_date()),
+    };
+    diesel::insert_into(offboarded_users::table)
+        .values(&entry)
+        .on_conflict(offboarded_users::username)
+        .do_update()
+        .set(&entry)
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("recording that {} was offboarded failed: {}", entry.username, e));
+}
+
+/// Forget that someone was offboarded, for when they are added back to our configs.
+#[instrument(skip(db))]
+#[inline]
+pub fn clear_offboarded_user(db: &Database, username: &str) {
+    diesel::delete(offboarded_users::table.filter(offboarded_users::username.eq(username)))
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("clearing that {} was offboarded failed: {}", username, e));
+}
+
+/// A SCIM user resource. The id is the username, which never changes for a person.
+/// FROM: https://datatracker.ietf.org/doc/html/rfc7643#section-4.1
+#[instrument]
+#[inline]
+pub fn scim_user(base_url: &str, username: &str, email: &str, first_name: &str, last_name: &str, groups: &[String], active: bool) -> Value {
+    json!({
+        "schemas": [SCIM_USER_SCHEMA],
+        "id": username,
+        "externalId": username,
+        "userName": email,
+        "name": {
+            "formatted": format!("{} {}", first_name, last_name),
+            "givenName": first_name,
+            "familyName": last_name,
+        },
+        "displayName": format!("{} {}", first_name, last_name),
+        "emails": [{ "value": email, "type": "work", "primary": true }],
+        "active": active,
+        "groups": groups.iter().map(|g| json!({ "value": g, "display": g, "$ref": format!("{}/Groups/{}", base_url, g) })).collect::<Vec<_>>(),
+        "meta": {
+            "resourceType": "User",
+            "location": format!("{}/Users/{}", base_url, username),
+        },
+    })
+}
+
+/// A SCIM group resource for a group in our configs. The id is the group name, and the
+/// members are the usernames of the people in it.
+/// FROM: https://datatracker.ietf.org/doc/html/rfc7643#section-4.2
+#[instrument]
+#[inline]
+pub fn scim_group(base_url: &str, name: &str, members: &[String]) -> Value {
+    json!({
+        "schemas": [SCIM_GROUP_SCHEMA],
+        "id": name,
+        "externalId": name,
+        "displayName": name,
+        "members": members.iter().map(|m| json!({ "value": m, "$ref": format!("{}/Users/{}", base_url, m) })).collect::<Vec<_>>(),
+        "meta": {
+            "resourceType": "Group",
+            "location": format!("{}/Groups/{}", base_url, name),
+        },
+    })
+}
+
+/// Parse a SCIM filter. We only support the `eq` filters clients use to look up a single
+/// resource, ie. `userName eq "jane@oxidecomputer.com"`. Returns the attribute and the value.
+/// FROM: https://datatracker.ietf.org/doc/html/rfc7644#section-3.4.2.2
+#[instrument]
+#[inline]
+pub fn parse_scim_filter(filter: &str) -> Result<(String, String), String> {
+    let parts: Vec<&str> = filter.trim().splitn(3, ' ').collect();
+    if parts.len() != 3 || !parts[1].eq_ignore_ascii_case("eq") {
+        return Err(format!("the filter `{}` is not supported, only `{{attribute}} eq \"{{value}}\"` is", filter));
+    }
+
+    let value = parts[2].trim();
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(format!("the value in the filter `{}` must be quoted", filter));
+    }
+
+    Ok((parts[0].to_string(), value[1..value.len() - 1].to_string()))
+}
+
+/// Returns if a resource matches a filter from `parse_scim_filter`. Attribute names and
+/// values are compared case insensitively, like the `userName` and emails they are used for.
+#[instrument(skip(resource))]
+#[inline]
+pub fn scim_filter_matches(resource: &Value, attribute: &str, value: &str) -> bool {
+    let attribute = attribute.to_lowercase();
+    if attribute == "emails.value" || attribute == "emails" {
+        return resource["emails"]
+            .as_array()
+            .map(|emails| emails.iter().any(|e| e["value"].as_str().unwrap_or_default().eq_ignore_ascii_case(value)))
+            .unwrap_or_default();
+    }
+
+    let field = match attribute.as_str() {
+        "id" => "id",
+        "externalid" => "externalId",
+        "username" => "userName",
+        "displayname" => "displayName",
+        _ => return false,
+    };
+    resource[field].as_str().unwrap_or_default().eq_ignore_ascii_case(value)
+}
+
+/// A SCIM list response with a page of the resources. The start index is 1-based.
+/// FROM: https://datatracker.ietf.org/doc/html/rfc7644#section-3.4.2.4
+#[instrument(skip(resources))]
+#[inline]
+pub fn scim_list_response(resources: Vec<Value>, start_index: usize, count: usize) -> Value {
+    let start_index = start_index.max(1);
+    let total = resources.len();
+    let page: Vec<Value> = resources.into_iter().skip(start_index - 1).take(count.min(SCIM_MAX_COUNT)).collect();
+
+    json!({
+        "schemas": [SCIM_LIST_SCHEMA],
+        "totalResults": total,
+        "startIndex": start_index,
+        "itemsPerPage": page.len(),
+        "Resources": page,
+    })
+}
+
+/// What our SCIM endpoint supports. It is read only, since our configs are the source of truth.
+#[instrument]
+#[inline]
+pub fn scim_service_provider_config() -> Value {
+    json!({
+        "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig"],
+        "patch": { "supported": false },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": SCIM_MAX_COUNT },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "Bearer Token",
+            "description": "The token in `SCIM_BEARER_TOKEN`",
+            "primary": true,
+        }],
+    })
+}
+
+/// Get every person in our directory as a SCIM user, with the people who were offboarded
+/// as deactivated users.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_scim_users(db: &Database, base_url: &str) -> Vec<Value> {
+    let mut resources: Vec<Value> = Users::get_from_db(db)
+        .into_iter()
+        .map(|u| scim_user(base_url, &u.username, &u.email(), &u.first_name, &u.last_name, &u.groups, true))
+        .collect();
+
+    let offboarded: Vec<OffboardedUser> = offboarded_users::table.order_by(offboarded_users::username).load(&db.conn()).unwrap();
+    for u in offboarded {
+        // Someone who came back is in our directory again.
+        if resources.iter().any(|r| r["id"] == u.username.as_str()) {
+            continue;
+        }
+        resources.push(scim_user(base_url, &u.username, &u.email, &u.first_name, &u.last_name, &[], false));
+    }
+
+    resources
+}
+
+/// Get every group in our configs as a SCIM group with its members.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_scim_groups(db: &Database, base_url: &str) -> Vec<Value> {
+    let users: Vec<User> = Users::get_from_db(db).into_iter().collect();
+
+    Groups::get_from_db(db)
+        .into_iter()
+        .map(|g| {
+            let members: Vec<String> = users.iter().filter(|u| u.groups.contains(&g.name)).map(|u| u.username.to_string()).collect();
+            scim_group(base_url, &g.name, &members)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::scim::{parse_scim_filter, scim_filter_matches, scim_group, scim_list_response, scim_user};
+
+    #[test]
+    fn test_scim_resources() {
+        let base_url = "https://webhooky.oxide.computer/scim/v2";
+        let user = scim_user(base_url, "jane", "jane@oxidecomputer.com", "Jane", "Doe", &["eng".to_string()], false);
+        assert_eq!(user["id"], "jane");
+        assert_eq!(user["userName"], "jane@oxidecomputer.com");
+        assert_eq!(user["active"], false);
+        assert_eq!(user["groups"][0]["value"], "eng");
+        assert_eq!(user["meta"]["location"], "https://webhooky.oxide.computer/scim/v2/Users/jane");
+
+        let group = scim_group(base_url, "eng", &["jane".to_string()]);
+        assert_eq!(group["displayName"], "eng");
+        assert_eq!(group["members"], json!([{"value": "jane", "$ref": "https://webhooky.oxide.computer/scim/v2/Users/jane"}]));
+
+        let (attribute, value) = parse_scim_filter(r#"userName eq "Jane@oxidecomputer.com""#).unwrap();
+        assert_eq!(attribute, "userName");
+        assert!(scim_filter_matches(&user, &attribute, &value));
+        assert!(scim_filter_matches(&user, "emails.value", "jane@oxidecomputer.com"));
+        assert!(!scim_filter_matches(&user, "userName", "sam@oxidecomputer.com"));
+        assert!(!scim_filter_matches(&user, "title", "Engineer"));
+        assert!(parse_scim_filter(r#"userName sw "jane""#).is_err());
+        assert!(parse_scim_filter("userName eq jane").is_err());
+
+        let resources: Vec<_> = (0..5).map(|i| json!({ "id": i })).collect();
+        let list = scim_list_response(resources, 2, 2);
+        assert_eq!(list["totalResults"], 5);
+        assert_eq!(list["itemsPerPage"], 2);
+        assert_eq!(list["Resources"], json!([{"id": 1}, {"id": 2}]));
+        assert_eq!(scim_list_response(vec![], 0, 10)["startIndex"], 1);
+    }
+}
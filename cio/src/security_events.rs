@@ -0,0 +1,420 @@
+#![allow(clippy::from_over_into)]
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
+use macros::db;
+use reqwest::{Client, Url};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_SECURITY_EVENTS_TABLE};
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::format::format_time;
+use crate::schema::security_events;
+use crate::utils::get_gsuite_token;
+
+/// The Google Workspace audit logs we ingest, by the name of their application in the
+/// Reports API.
+pub static GOOGLE_WORKSPACE_AUDIT_APPLICATIONS: &[&str] = &["admin", "login"];
+
+/// How far back to ingest the audit logs the first time, the Reports API keeps them for
+/// six months.
+const SECURITY_EVENTS_INITIAL_DAYS: i64 = 30;
+
+/// The event types we normalize the events of each provider to, by the category and name
+/// of the event at the provider. Events that are not in here get `{category}.{name}`.
+/// Events from Okta's System Log should be normalized to the same types, so one query
+/// covers an account across every provider.
+static SECURITY_EVENT_TYPES: &[(&str, &str, &str)] = &[
+    ("login", "login_success", "login.success"),
+    ("login", "login_failure", "login.failure"),
+    ("login", "login_challenge", "login.challenge"),
+    ("login", "login_verification", "login.challenge"),
+    ("login", "logout", "logout"),
+    ("login", "suspicious_login", "login.suspicious"),
+    ("login", "suspicious_login_less_secure_app", "login.suspicious"),
+    ("login", "password_edit", "password.changed"),
+    ("login", "2sv_enroll", "mfa.enrolled"),
+    ("login", "2sv_disable", "mfa.disabled"),
+    ("login", "account_disabled_hijacked", "account.disabled"),
+    ("login", "account_disabled_password_leak", "account.disabled"),
+    ("admin", "CREATE_USER", "user.created"),
+    ("admin", "DELETE_USER", "user.deleted"),
+    ("admin", "SUSPEND_USER", "user.suspended"),
+    ("admin", "UNSUSPEND_USER", "user.unsuspended"),
+    ("admin", "CHANGE_PASSWORD", "password.changed"),
+    ("admin", "GRANT_ADMIN_PRIVILEGE", "admin.granted"),
+    ("admin", "REVOKE_ADMIN_PRIVILEGE", "admin.revoked"),
+    ("admin", "ASSIGN_ROLE", "admin.granted"),
+    ("admin", "UNASSIGN_ROLE", "admin.revoked"),
+    ("admin", "ADD_GROUP_MEMBER", "group.member_added"),
+    ("admin", "REMOVE_GROUP_MEMBER", "group.member_removed"),
+    ("admin", "CHANGE_EMAIL_SETTING", "email.settings_changed"),
+    ("admin", "GRANT_DELEGATED_ADMIN_PRIVILEGES", "admin.granted"),
+];
+
+/// The parameters of an event that name the account it was done to, rather than by.
+static SECURITY_EVENT_TARGET_PARAMETERS: &[&str] = &["affected_email_address", "USER_EMAIL"];
+
+/// A normalized event from the audit logs of one of our identity providers, ie. a login
+/// to Google Workspace or an admin suspending someone.
+#[db {
+    new_struct_name = "SecurityEvent",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_SECURITY_EVENTS_TABLE",
+    source = "audit_logs",
+    match_on = {
+        "event_id" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "security_events"]
+pub struct NewSecurityEvent {
+    /// A unique identifier for the event formatted as `{provider}/{id at the provider}`.
+    /// We use this to dedupe events between syncs.
+    pub event_id: String,
+    /// Where the event is from, either `google_workspace` or `okta`.
+    pub provider: String,
+    /// The log the event is from at the provider, ie. `login` or `admin`.
+    pub category: String,
+    /// The type of the event, normalized across providers, ie. `login.failure`.
+    pub event_type: String,
+    /// The name of the event at the provider, ie. `login_failure`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// The email of the account that did it.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub actor_email: String,
+    /// The email of the account it was done to, if it was not done by the account to
+    /// itself.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub target: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub ip_address: String,
+    /// Either `success` or `failure`, empty if the provider does not say.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub outcome: String,
+    /// The rest of the parameters of the event, formatted as `{name}={value}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Implement updating the Airtable record for a SecurityEvent.
+#[async_trait]
+impl UpdateAirtableRecord<SecurityEvent> for SecurityEvent {
+    async fn update_airtable_record(&mut self, _record: SecurityEvent) {}
+}
+
+/// Returns the normalized type of an event from its category and name at the provider.
+#[instrument]
+#[inline]
+pub fn security_event_type(category: &str, name: &str) -> String {
+    match SECURITY_EVENT_TYPES.iter().find(|(c, n, _)| *c == category && *n == name) {
+        Some((_, _, t)) => t.to_string(),
+        None => format!("{}.{}", category, name.to_lowercase()),
+    }
+}
+
+/// Get the value of a parameter of an event in the Reports API, as a string.
+fn google_parameter_value(p: &Value) -> String {
+    for key in &["value", "intValue", "boolValue"] {
+        match &p[*key] {
+            Value::Null => continue,
+            Value::String(s) => return s.to_string(),
+            v => return v.to_string(),
+        }
+    }
+    if let Some(values) = p["multiValue"].as_array() {
+        return values.iter().map(|v| v.as_str().unwrap_or_default()).collect::<Vec<&str>>().join(",");
+    }
+
+    Default::default()
+}
+
+/// Normalize an activity from the Google Workspace Reports API into our security events,
+/// one for each event in the activity.
+/// FROM: https://developers.google.com/admin-sdk/reports/reference/rest/v1/activities
+#[instrument(skip(activity))]
+#[inline]
+pub fn normalize_google_activity(category: &str, activity: &Value) -> Vec<NewSecurityEvent> {
+    let occurred_at = activity["id"]["time"]
+        .as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now);
+    let unique_qualifier = activity["id"]["uniqueQualifier"].as_str().unwrap_or_default();
+    let actor_email = activity["actor"]["email"].as_str().unwrap_or_default().to_lowercase();
+
+    let mut events: Vec<NewSecurityEvent> = Default::default();
+    for (i, event) in activity["events"].as_array().cloned().unwrap_or_default().iter().enumerate() {
+        let name = event["name"].as_str().unwrap_or_default().to_string();
+
+        let mut target = String::new();
+        let mut details: Vec<String> = Default::default();
+        for p in event["parameters"].as_array().cloned().unwrap_or_default() {
+            let key = p["name"].as_str().unwrap_or_default();
+            let value = google_parameter_value(&p);
+            if SECURITY_EVENT_TARGET_PARAMETERS.contains(&key) {
+                target = value.to_lowercase();
+            } else {
+                details.push(format!("{}={}", key, value));
+            }
+        }
+        details.sort();
+        if target == actor_email {
+            target = Default::default();
+        }
+
+        let outcome = match name.as_str() {
+            "login_success" => "success",
+            "login_failure" => "failure",
+            _ => "",
+        };
+
+        events.push(NewSecurityEvent {
+            event_id: format!("google_workspace/{}/{}/{}/{}", category, occurred_at.timestamp(), unique_qualifier, i),
+            provider: "google_workspace".to_string(),
+            category: category.to_string(),
+            event_type: security_event_type(category, &name),
+            name,
+            actor_email: actor_email.to_string(),
+            target,
+            ip_address: activity["ipAddress"].as_str().unwrap_or_default().to_string(),
+            outcome: outcome.to_string(),
+            details,
+            occurred_at,
+        });
+    }
+
+    events
+}
+
+/// List the activities in a Google Workspace audit log since a time.
+#[instrument(skip(token))]
+#[inline]
+async fn list_google_activities(token: &str, application: &str, since: DateTime<Utc>) -> Result<Vec<Value>, String> {
+    let mut activities: Vec<Value> = Default::default();
+    let mut page_token = String::new();
+    loop {
+        let mut url = Url::parse("https://admin.googleapis.com/admin/reports/v1/activity/users/all/applications").unwrap();
+        url.path_segments_mut().unwrap().push(application);
+        url.query_pairs_mut().append_pair("startTime", &since.to_rfc3339()).append_pair("maxResults", "1000");
+        if !page_token.is_empty() {
+            url.query_pairs_mut().append_pair("pageToken", &page_token);
+        }
+
+        let resp: Value = Client::new()
+            .get(url)
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp["error"].is_null() {
+            return Err(format!("listing the {} audit log failed: {}", application, resp["error"]));
+        }
+        activities.extend(resp["items"].as_array().cloned().unwrap_or_default());
+
+        page_token = resp["nextPageToken"].as_str().unwrap_or_default().to_string();
+        if page_token.is_empty() {
+            return Ok(activities);
+        }
+    }
+}
+
+/// Returns when the last event we have from a provider's log happened, if we have any.
+#[instrument(skip(db))]
+#[inline]
+pub fn last_security_event_at(db: &Database, provider: &str, category: &str) -> Option<DateTime<Utc>> {
+    security_events::dsl::security_events
+        .filter(security_events::dsl::provider.eq(provider.to_string()))
+        .filter(security_events::dsl::category.eq(category.to_string()))
+        .select(diesel::dsl::max(security_events::dsl::occurred_at))
+        .first::<Option<DateTime<Utc>>>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the last {} {} security event failed: {}", provider, category, e))
+}
+
+/// Ingest the Google Workspace admin and login audit logs into our security events. Each
+/// log picks up from the last event we have from it, the Reports API can take a few
+/// hours to include an event so the last day is always fetched again.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_google_workspace_security_events(db: &Database) {
+    let token = get_gsuite_token("").await;
+
+    for application in GOOGLE_WORKSPACE_AUDIT_APPLICATIONS {
+        let since = match last_security_event_at(db, "google_workspace", application) {
+            Some(t) => t - Duration::days(1),
+            None => Utc::now() - Duration::days(SECURITY_EVENTS_INITIAL_DAYS),
+        };
+
+        let activities = match list_google_activities(token.as_str(), application, since).await {
+            Ok(a) => a,
+            Err(e) => {
+                println!("[security_events] {}", e);
+                continue;
+            }
+        };
+
+        // The events are pushed to Airtable with the rest of the table by the job.
+        let events: Vec<NewSecurityEvent> = activities.iter().flat_map(|activity| normalize_google_activity(application, activity)).collect();
+        NewSecurityEvent::upsert_all(db, &events);
+        println!(
+            "[security_events] ingested {} events from the google workspace {} audit log since {}",
+            events.len(),
+            application,
+            format_time(since)
+        );
+    }
+}
+
+/// Get everything an account did, or had done to it, between two times, across every
+/// provider, oldest first. This answers "what did this account do the day before
+/// offboarding".
+#[instrument(skip(db))]
+#[inline]
+pub fn get_account_activity(db: &Database, email: &str, since: DateTime<Utc>, before: DateTime<Utc>) -> Vec<SecurityEvent> {
+    let email = email.to_lowercase();
+    security_events::dsl::security_events
+        .filter(security_events::dsl::actor_email.eq(email.to_string()).or(security_events::dsl::target.eq(email.to_string())))
+        .filter(security_events::dsl::occurred_at.ge(since))
+        .filter(security_events::dsl::occurred_at.lt(before))
+        .order_by(security_events::dsl::occurred_at)
+        .load::<SecurityEvent>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the activity for {} failed: {}", email, e))
+}
+
+/// Format the activity of an account as a timeline, one event per line.
+#[instrument(skip(events))]
+#[inline]
+pub fn format_account_activity(email: &str, events: &[SecurityEvent]) -> String {
+    if events.is_empty() {
+        return format!("There is no activity for {} in that window.\n", email);
+    }
+
+    let mut text = String::new();
+    for e in events {
+        let mut line = format!("{}  {:<22} {:<16}", format_time(e.occurred_at), e.event_type, e.provider);
+        if e.actor_email != email.to_lowercase() {
+            line += &format!(" by {}", e.actor_email);
+        }
+        if !e.target.is_empty() && e.target != email.to_lowercase() {
+            line += &format!(" on {}", e.target);
+        }
+        if !e.ip_address.is_empty() {
+            line += &format!(" from {}", e.ip_address);
+        }
+        if !e.details.is_empty() {
+            line += &format!(" ({})", e.details.join(", "));
+        }
+        text += line.trim_end();
+        text += "\n";
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::db::Database;
+    use crate::security_events::{format_account_activity, normalize_google_activity, refresh_google_workspace_security_events, security_event_type, SecurityEvent, SecurityEvents};
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_security_events() {
+        let db = Database::new();
+
+        refresh_google_workspace_security_events(&db).await;
+
+        SecurityEvents::get_from_db(&db).update_airtable().await;
+    }
+
+    #[test]
+    fn test_normalize_google_activity() {
+        let activity = json!({
+            "id": {"time": "2021-04-19T17:03:00.000Z", "uniqueQualifier": "-4237", "applicationName": "login"},
+            "actor": {"email": "Jane@oxidecomputer.com", "callerType": "USER"},
+            "ipAddress": "203.0.113.7",
+            "events": [
+                {"type": "login", "name": "login_failure", "parameters": [
+                    {"name": "login_type", "value": "google_password"},
+                    {"name": "is_suspicious", "boolValue": true},
+                ]},
+                {"type": "account_warning", "name": "account_disabled_password_leak", "parameters": [
+                    {"name": "affected_email_address", "value": "jane@oxidecomputer.com"},
+                ]},
+            ],
+        });
+
+        let events = normalize_google_activity("login", &activity);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_id, "google_workspace/login/1618851780/-4237/0");
+        assert_eq!(events[0].event_type, "login.failure");
+        assert_eq!(events[0].outcome, "failure");
+        assert_eq!(events[0].actor_email, "jane@oxidecomputer.com");
+        assert_eq!(events[0].details, vec!["is_suspicious=true", "login_type=google_password"]);
+        assert_eq!(events[0].occurred_at, Utc.ymd(2021, 4, 19).and_hms(17, 3, 0));
+        // Something that happened to the account itself has no target.
+        assert_eq!(events[1].event_type, "account.disabled");
+        assert_eq!(events[1].target, "");
+
+        let activity = json!({
+            "id": {"time": "2021-04-20T09:00:00Z", "uniqueQualifier": "88"},
+            "actor": {"email": "admin@oxidecomputer.com"},
+            "events": [{"type": "USER_SETTINGS", "name": "SUSPEND_USER", "parameters": [{"name": "USER_EMAIL", "value": "jane@oxidecomputer.com"}]}],
+        });
+        let events = normalize_google_activity("admin", &activity);
+        assert_eq!(events[0].event_type, "user.suspended");
+        assert_eq!(events[0].target, "jane@oxidecomputer.com");
+        assert_eq!(events[0].outcome, "");
+
+        assert_eq!(security_event_type("admin", "CREATE_DATA_TRANSFER_REQUEST"), "admin.create_data_transfer_request");
+    }
+
+    #[test]
+    fn test_format_account_activity() {
+        let event = |event_type: &str, actor_email: &str, target: &str, ip_address: &str| SecurityEvent {
+            id: 1,
+            event_id: "google_workspace/login/1/1/0".to_string(),
+            provider: "google_workspace".to_string(),
+            category: "login".to_string(),
+            event_type: event_type.to_string(),
+            name: Default::default(),
+            actor_email: actor_email.to_string(),
+            target: target.to_string(),
+            ip_address: ip_address.to_string(),
+            outcome: Default::default(),
+            details: Default::default(),
+            occurred_at: Utc.ymd(2021, 4, 19).and_hms(17, 3, 0),
+            airtable_record_id: Default::default(),
+            source: Default::default(),
+            last_synced_from: Default::default(),
+            last_synced_at: None,
+            extra: Default::default(),
+            modified_at: None,
+        };
+
+        let events = vec![
+            event("login.success", "jane@oxidecomputer.com", "", "203.0.113.7"),
+            event("user.suspended", "admin@oxidecomputer.com", "jane@oxidecomputer.com", ""),
+        ];
+        assert_eq!(
+            format_account_activity("Jane@oxidecomputer.com", &events),
+            "2021-04-19 17:03 UTC  login.success          google_workspace from 203.0.113.7\n2021-04-19 17:03 UTC  user.suspended         google_workspace by admin@oxidecomputer.com\n"
+        );
+        assert_eq!(
+            format_account_activity("jane@oxidecomputer.com", &[]),
+            "There is no activity for jane@oxidecomputer.com in that window.\n"
+        );
+    }
+}
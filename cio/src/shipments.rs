@@ -11,22 +11,22 @@ use reqwest::StatusCode;
 use schemars::JsonSchema;
 use sendgrid_api::SendGrid;
 use serde::{Deserialize, Serialize};
-use sheets::Sheets;
 use shippo::{Address, CustomsDeclaration, CustomsItem, NewShipment, NewTransaction, Parcel, Shippo};
 use tracing::instrument;
 
-use crate::airtable::{AIRTABLE_BASE_ID_SHIPMENTS, AIRTABLE_INBOUND_TABLE, AIRTABLE_OUTBOUND_TABLE};
+use crate::airtable::{airtable_client, AIRTABLE_BASE_ID_SHIPMENTS, AIRTABLE_INBOUND_TABLE, AIRTABLE_OUTBOUND_TABLE};
 use crate::core::UpdateAirtableRecord;
 use crate::db::Database;
 use crate::models::get_value;
 use crate::schema::inbound_shipments;
-use crate::utils::{get_gsuite_token, DOMAIN};
+use crate::utils::{get_gsuite_token, sheets_client, DOMAIN};
 
 /// The data type for an inbound shipment.
 #[db {
     new_struct_name = "InboundShipment",
     airtable_base_id = "AIRTABLE_BASE_ID_SHIPMENTS",
     airtable_table = "AIRTABLE_INBOUND_TABLE",
+    source = "airtable",
     match_on = {
         "tracking_number" = "String",
         "carrier" = "String",
@@ -739,7 +739,7 @@ impl Shipment {
     #[inline]
     pub async fn push_to_airtable(&self) {
         // Initialize the Airtable client.
-        let airtable = airtable_api::Airtable::new(airtable_api::api_key_from_env(), AIRTABLE_BASE_ID_SHIPMENTS, "");
+        let airtable = airtable_client(AIRTABLE_BASE_ID_SHIPMENTS);
 
         // Create the record.
         let record = airtable_api::Record {
@@ -760,7 +760,7 @@ impl Shipment {
     #[inline]
     pub async fn update_in_airtable(&mut self, existing_record: &mut airtable_api::Record<Shipment>) {
         // Initialize the Airtable client.
-        let airtable = airtable_api::Airtable::new(airtable_api::api_key_from_env(), AIRTABLE_BASE_ID_SHIPMENTS, "");
+        let airtable = airtable_client(AIRTABLE_BASE_ID_SHIPMENTS);
 
         // Run the custom trait to update the new record from the old record.
         self.update_airtable_record(existing_record.fields.clone()).await;
@@ -786,7 +786,7 @@ impl Shipment {
     pub async fn create_or_update_in_airtable(&mut self) {
         // Check if we already have the row in Airtable.
         // Initialize the Airtable client.
-        let airtable = airtable_api::Airtable::new(airtable_api::api_key_from_env(), AIRTABLE_BASE_ID_SHIPMENTS, "");
+        let airtable = airtable_client(AIRTABLE_BASE_ID_SHIPMENTS);
 
         let result: Vec<airtable_api::Record<Shipment>> = airtable.list_records(AIRTABLE_OUTBOUND_TABLE, "Grid view", vec![]).await.unwrap();
 
@@ -812,7 +812,7 @@ impl Shipment {
     #[inline]
     pub async fn get_from_airtable(id: &str) -> Self {
         // Initialize the Airtable client.
-        let airtable = airtable_api::Airtable::new(airtable_api::api_key_from_env(), AIRTABLE_BASE_ID_SHIPMENTS, "");
+        let airtable = airtable_client(AIRTABLE_BASE_ID_SHIPMENTS);
 
         let record: airtable_api::Record<Shipment> = airtable.get_record(AIRTABLE_OUTBOUND_TABLE, id).await.unwrap();
 
@@ -1063,7 +1063,7 @@ pub async fn get_google_sheets_shipments() -> Vec<Shipment> {
     let token = get_gsuite_token("").await;
 
     // Initialize the GSuite sheets client.
-    let sheets_client = Sheets::new(token.clone());
+    let sheets_client = sheets_client(token.clone());
 
     // Iterate over the Google sheets and get the shipments.
     let mut shipments: Vec<Shipment> = Default::default();
@@ -1,9 +1,14 @@
+use std::collections::BTreeMap;
 use std::env;
 
-use reqwest::{Body, Client, StatusCode};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Body, Client, RequestBuilder, StatusCode, Url};
 use serde_json::Value;
 use tracing::instrument;
 
+use crate::rate_limits::check_throttled;
+use crate::vcr;
+
 /// The Slack app webhook URL for our app to post to the #hiring channel.
 #[instrument]
 #[inline]
@@ -11,6 +16,13 @@ pub fn get_hiring_channel_post_url() -> String {
     env::var("SLACK_HIRING_CHANNEL_POST_URL").unwrap()
 }
 
+/// The Slack app webhook URL for our app to post to the #office channel.
+#[instrument]
+#[inline]
+pub fn get_office_channel_post_url() -> String {
+    env::var("SLACK_OFFICE_CHANNEL_POST_URL").unwrap()
+}
+
 /// The Slack app webhook URL for our app to post to the #public-relations channel.
 #[instrument]
 #[inline]
@@ -18,17 +30,551 @@ pub fn get_public_relations_channel_post_url() -> String {
     env::var("SLACK_PUBLIC_RELATIONS_CHANNEL_POST_URL").unwrap()
 }
 
+/// The Slack app webhook URL for our app to post to the #engineering channel.
+#[instrument]
+#[inline]
+pub fn get_engineering_channel_post_url() -> String {
+    env::var("SLACK_ENGINEERING_CHANNEL_POST_URL").unwrap()
+}
+
+/// The Slack app webhook URL for our app to post to the #security channel.
+#[instrument]
+#[inline]
+pub fn get_security_channel_post_url() -> String {
+    env::var("SLACK_SECURITY_CHANNEL_POST_URL").unwrap()
+}
+
+/// The Slack app webhook URL for our app to post to the #finance channel.
+#[instrument]
+#[inline]
+pub fn get_finance_channel_post_url() -> String {
+    env::var("SLACK_FINANCE_CHANNEL_POST_URL").unwrap()
+}
+
 /// Post text to a channel.
 #[instrument]
 #[inline]
 pub async fn post_to_channel(url: String, v: Value) {
     let client = Client::new();
-    let resp = client.post(&url).body(Body::from(v.to_string())).send().await.unwrap();
+    let resp = vcr::send(&client, client.post(&url).body(Body::from(v.to_string()))).await.unwrap();
 
     match resp.status() {
         StatusCode::OK => (),
+        StatusCode::TOO_MANY_REQUESTS => {
+            let _ = check_throttled("slack", &resp);
+            println!("posting to slack webhook ({}) failed, slack rate limited us", url);
+        }
         s => {
             println!("posting to slack webhook ({}) failed, status: {} | resp: {}", url, s, resp.text().await.unwrap());
         }
     };
 }
+
+/// Make a request to the Slack Web API, recording it if Slack rate limits us.
+async fn slack_api(req: RequestBuilder) -> Result<Value, String> {
+    let resp = vcr::send(&Client::new(), req).await.map_err(|e| e.to_string())?;
+    check_throttled("slack", &resp)?;
+
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+/// Look up the ID of a user in Slack by their email, returning `None` if there is no
+/// user with that email. This uses the bot token in the `SLACK_TOKEN` environment variable.
+#[instrument]
+#[inline]
+pub async fn lookup_slack_user_id(email: &str) -> Result<Option<String>, String> {
+    let resp = slack_api(
+        Client::new()
+            .get("https://slack.com/api/users.lookupByEmail")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .query(&[("email", email)]),
+    )
+    .await?;
+
+    if resp["ok"].as_bool().unwrap_or_default() {
+        return Ok(resp["user"]["id"].as_str().map(|id| id.to_string()));
+    }
+    if resp["error"].as_str() == Some("users_not_found") {
+        return Ok(None);
+    }
+
+    Err(format!("looking up slack user by email {} failed: {}", email, resp))
+}
+
+/// Send a direct message to a user in Slack by their ID.
+/// This uses the bot token in the `SLACK_TOKEN` environment variable.
+#[instrument(skip(v))]
+#[inline]
+pub async fn post_to_user_id(user_id: &str, v: Value) -> Result<(), String> {
+    // Posting to a user ID sends the message to the DM with our app.
+    let mut body = v;
+    body["channel"] = json!(user_id);
+    let resp = slack_api(
+        Client::new()
+            .post("https://slack.com/api/chat.postMessage")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .json(&body),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("posting slack message to user {} failed: {}", user_id, resp));
+    }
+
+    Ok(())
+}
+
+/// Get a message in a channel by its timestamp. The bot for the `SLACK_TOKEN` needs
+/// to be in the channel to read its history.
+#[instrument]
+#[inline]
+pub async fn get_slack_message(channel: &str, ts: &str) -> Result<Value, String> {
+    let resp = slack_api(
+        Client::new()
+            .get("https://slack.com/api/conversations.history")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .query(&[("channel", channel), ("latest", ts), ("inclusive", "true"), ("limit", "1")]),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("getting slack message {} in {} failed: {}", ts, channel, resp));
+    }
+
+    match resp["messages"].as_array().and_then(|m| m.first()) {
+        Some(m) if m["ts"].as_str() == Some(ts) => Ok(m.clone()),
+        _ => Err(format!("slack message {} in {} does not exist", ts, channel)),
+    }
+}
+
+/// Get the email for a user in Slack by their ID.
+#[instrument]
+#[inline]
+pub async fn get_slack_user_email(user_id: &str) -> Result<String, String> {
+    let resp = slack_api(
+        Client::new()
+            .get("https://slack.com/api/users.info")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .query(&[("user", user_id)]),
+    )
+    .await?;
+
+    match resp["user"]["profile"]["email"].as_str() {
+        Some(email) if resp["ok"].as_bool().unwrap_or_default() => Ok(email.to_string()),
+        _ => Err(format!("getting the email for slack user {} failed: {}", user_id, resp)),
+    }
+}
+
+/// Reply in the thread of a message in a channel.
+#[instrument]
+#[inline]
+pub async fn post_thread_reply(channel: &str, ts: &str, text: &str) -> Result<(), String> {
+    let resp = slack_api(
+        Client::new()
+            .post("https://slack.com/api/chat.postMessage")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .json(&json!({
+                "channel": channel,
+                "thread_ts": ts,
+                "text": text,
+            })),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("replying to slack message {} in {} failed: {}", ts, channel, resp));
+    }
+
+    Ok(())
+}
+
+/// Upload a file to a channel by its ID, ie. a chart for a report.
+/// This uses the bot token in the `SLACK_TOKEN` environment variable.
+#[instrument(skip(bytes))]
+#[inline]
+pub async fn upload_slack_file(channel_id: &str, filename: &str, title: &str, bytes: Vec<u8>) -> Result<(), String> {
+    let form = Form::new()
+        .text("channels", channel_id.to_string())
+        .text("title", title.to_string())
+        .part("file", Part::bytes(bytes).file_name(filename.to_string()));
+    let resp = slack_api(
+        Client::new()
+            .post("https://slack.com/api/files.upload")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .multipart(form),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("uploading {} to slack channel {} failed: {}", filename, channel_id, resp));
+    }
+
+    Ok(())
+}
+
+/// Get the email of everyone in the workspace by their user ID, leaving out bots and
+/// deactivated users.
+#[instrument]
+#[inline]
+pub async fn list_slack_user_emails() -> Result<BTreeMap<String, String>, String> {
+    let mut emails: BTreeMap<String, String> = Default::default();
+    let mut cursor = String::new();
+    loop {
+        let resp = slack_api(
+            Client::new()
+                .get("https://slack.com/api/users.list")
+                .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+                .query(&[("limit", "200"), ("cursor", &cursor)]),
+        )
+        .await?;
+        if !resp["ok"].as_bool().unwrap_or_default() {
+            return Err(format!("listing slack users failed: {}", resp));
+        }
+
+        for member in resp["members"].as_array().cloned().unwrap_or_default() {
+            if member["deleted"].as_bool().unwrap_or_default() || member["is_bot"].as_bool().unwrap_or_default() {
+                continue;
+            }
+            if let (Some(id), Some(email)) = (member["id"].as_str(), member["profile"]["email"].as_str()) {
+                emails.insert(id.to_string(), email.to_lowercase());
+            }
+        }
+
+        cursor = resp["response_metadata"]["next_cursor"].as_str().unwrap_or_default().to_string();
+        if cursor.is_empty() {
+            return Ok(emails);
+        }
+    }
+}
+
+/// A user group in Slack, ie. `@eng`, with the user IDs of its members.
+/// FROM: https://api.slack.com/types/usergroup
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SlackUserGroup {
+    pub id: String,
+    pub handle: String,
+    pub name: String,
+    pub users: Vec<String>,
+    /// Disabled user groups keep their handle, so we enable them rather than create
+    /// them again.
+    pub disabled: bool,
+}
+
+impl SlackUserGroup {
+    /// Parse a user group from the Slack API.
+    #[instrument]
+    #[inline]
+    pub fn from_value(v: &Value) -> Self {
+        SlackUserGroup {
+            id: v["id"].as_str().unwrap_or_default().to_string(),
+            handle: v["handle"].as_str().unwrap_or_default().to_string(),
+            name: v["name"].as_str().unwrap_or_default().to_string(),
+            users: v["users"].as_array().cloned().unwrap_or_default().iter().filter_map(|u| u.as_str()).map(|u| u.to_string()).collect(),
+            disabled: v["date_delete"].as_i64().unwrap_or_default() > 0,
+        }
+    }
+}
+
+/// List the user groups in the workspace with their members, including the disabled ones.
+/// Managing user groups needs the `usergroups:read` and `usergroups:write` scopes on the
+/// token in the `SLACK_TOKEN` environment variable.
+#[instrument]
+#[inline]
+pub async fn list_slack_user_groups() -> Result<Vec<SlackUserGroup>, String> {
+    let resp = slack_api(
+        Client::new()
+            .get("https://slack.com/api/usergroups.list")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .query(&[("include_users", "true"), ("include_disabled", "true")]),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("listing slack user groups failed: {}", resp));
+    }
+
+    Ok(resp["usergroups"].as_array().cloned().unwrap_or_default().iter().map(SlackUserGroup::from_value).collect())
+}
+
+/// Create a user group, which people mention with its handle.
+#[instrument]
+#[inline]
+pub async fn create_slack_user_group(handle: &str, name: &str, description: &str) -> Result<SlackUserGroup, String> {
+    let resp = slack_api(
+        Client::new()
+            .post("https://slack.com/api/usergroups.create")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .json(&json!({
+                "handle": handle,
+                "name": name,
+                "description": description,
+            })),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("creating slack user group @{} failed: {}", handle, resp));
+    }
+
+    Ok(SlackUserGroup::from_value(&resp["usergroup"]))
+}
+
+/// Enable a user group that was disabled.
+#[instrument]
+#[inline]
+pub async fn enable_slack_user_group(id: &str) -> Result<(), String> {
+    let resp = slack_api(
+        Client::new()
+            .post("https://slack.com/api/usergroups.enable")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .json(&json!({ "usergroup": id })),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("enabling slack user group {} failed: {}", id, resp));
+    }
+
+    Ok(())
+}
+
+/// Replace the members of a user group with the users by their IDs. Slack does not
+/// allow a user group with no members, so there must be at least one.
+#[instrument]
+#[inline]
+pub async fn update_slack_user_group_members(id: &str, users: &[String]) -> Result<(), String> {
+    let resp = slack_api(
+        Client::new()
+            .post("https://slack.com/api/usergroups.users.update")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .json(&json!({
+                "usergroup": id,
+                "users": users.join(","),
+            })),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("updating the members of slack user group {} failed: {}", id, resp));
+    }
+
+    Ok(())
+}
+
+/// A public or private channel in Slack.
+/// FROM: https://api.slack.com/types/conversation
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SlackChannel {
+    pub id: String,
+    pub name: String,
+    pub topic: String,
+    pub purpose: String,
+    pub is_private: bool,
+    pub is_archived: bool,
+    /// Whether the bot for the `SLACK_TOKEN` is in the channel.
+    pub is_member: bool,
+}
+
+impl SlackChannel {
+    /// Parse a channel from the Slack API.
+    #[instrument]
+    #[inline]
+    pub fn from_value(v: &Value) -> Self {
+        SlackChannel {
+            id: v["id"].as_str().unwrap_or_default().to_string(),
+            name: v["name"].as_str().unwrap_or_default().to_string(),
+            topic: v["topic"]["value"].as_str().unwrap_or_default().to_string(),
+            purpose: v["purpose"]["value"].as_str().unwrap_or_default().to_string(),
+            is_private: v["is_private"].as_bool().unwrap_or_default(),
+            is_archived: v["is_archived"].as_bool().unwrap_or_default(),
+            is_member: v["is_member"].as_bool().unwrap_or_default(),
+        }
+    }
+}
+
+/// List the public channels in the workspace, and the private channels the bot is in,
+/// including the archived ones.
+#[instrument]
+#[inline]
+pub async fn list_slack_channels() -> Result<Vec<SlackChannel>, String> {
+    let mut channels: Vec<SlackChannel> = Default::default();
+    let mut cursor = String::new();
+    loop {
+        let resp = slack_api(
+            Client::new()
+                .get("https://slack.com/api/conversations.list")
+                .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+                .query(&[("types", "public_channel,private_channel"), ("limit", "200"), ("cursor", &cursor)]),
+        )
+        .await?;
+        if !resp["ok"].as_bool().unwrap_or_default() {
+            return Err(format!("listing slack channels failed: {}", resp));
+        }
+
+        channels.extend(resp["channels"].as_array().cloned().unwrap_or_default().iter().map(SlackChannel::from_value));
+
+        cursor = resp["response_metadata"]["next_cursor"].as_str().unwrap_or_default().to_string();
+        if cursor.is_empty() {
+            return Ok(channels);
+        }
+    }
+}
+
+/// Call a method of the Slack conversations API that acts on a channel, ie.
+/// `conversations.join`, with the arguments in the body.
+/// FROM: https://api.slack.com/methods#conversations
+#[instrument(skip(body))]
+#[inline]
+pub async fn update_slack_channel(method: &str, body: Value) -> Result<Value, String> {
+    let resp = slack_api(
+        Client::new()
+            .post(&format!("https://slack.com/api/{}", method))
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .json(&body),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("{} for {} failed: {}", method, body, resp));
+    }
+
+    Ok(resp)
+}
+
+/// A slash command someone ran in Slack, ie. `/whoknows kubernetes`.
+/// FROM: https://api.slack.com/interactivity/slash-commands
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SlackCommand {
+    /// The command, including the slash.
+    pub command: String,
+    /// Everything after the command.
+    pub text: String,
+    pub user_id: String,
+}
+
+/// Decode the form encoded body Slack sends for slash commands and interactions.
+fn slack_form_pairs(body: &[u8]) -> Vec<(String, String)> {
+    // The body is encoded the same way as a query string, so let the url crate decode it.
+    let url = Url::parse(&format!("https://slack.com/?{}", String::from_utf8_lossy(body))).unwrap();
+
+    url.query_pairs().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+/// Parse the form encoded body Slack sends for a slash command.
+#[instrument(skip(body))]
+#[inline]
+pub fn parse_slack_command(body: &[u8]) -> SlackCommand {
+    let mut command = SlackCommand::default();
+    for (key, value) in slack_form_pairs(body) {
+        match key.as_str() {
+            "command" => command.command = value.to_string(),
+            "text" => command.text = value.trim().to_string(),
+            "user_id" => command.user_id = value.to_string(),
+            _ => (),
+        }
+    }
+
+    command
+}
+
+/// Parse the body Slack sends for an interaction, ie. someone using a shortcut or
+/// submitting a modal, which is JSON in the `payload` form field.
+/// FROM: https://api.slack.com/interactivity/handling#payloads
+#[instrument(skip(body))]
+#[inline]
+pub fn parse_slack_interaction(body: &[u8]) -> Result<Value, String> {
+    match slack_form_pairs(body).into_iter().find(|(k, _)| k == "payload") {
+        Some((_, payload)) => serde_json::from_str(&payload).map_err(|e| format!("decoding the slack interaction failed: {}", e)),
+        None => Err("the slack interaction does not have a payload".to_string()),
+    }
+}
+
+/// Open a modal for the person who used a shortcut, with the `trigger_id` from the
+/// interaction. This uses the bot token in the `SLACK_TOKEN` environment variable.
+#[instrument(skip(view))]
+#[inline]
+pub async fn open_slack_modal(trigger_id: &str, view: Value) -> Result<(), String> {
+    let resp = slack_api(
+        Client::new()
+            .post("https://slack.com/api/views.open")
+            .bearer_auth(env::var("SLACK_TOKEN").unwrap_or_default())
+            .json(&json!({
+                "trigger_id": trigger_id,
+                "view": view,
+            })),
+    )
+    .await?;
+    if !resp["ok"].as_bool().unwrap_or_default() {
+        return Err(format!("opening slack modal failed: {}", resp));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slack::{parse_slack_command, parse_slack_interaction, SlackChannel, SlackCommand, SlackUserGroup};
+
+    #[test]
+    fn test_parse_slack_command() {
+        let body = b"token=gIkuvaNzQIHg97ATvDxqgjtO&team_id=T0001&user_id=U2147483697&command=%2Fwhoknows&text=rust+%26+kubernetes&response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2F1234";
+        assert_eq!(
+            parse_slack_command(body),
+            SlackCommand {
+                command: "/whoknows".to_string(),
+                text: "rust & kubernetes".to_string(),
+                user_id: "U2147483697".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_slack_interaction() {
+        let payload = parse_slack_interaction(b"payload=%7B%22type%22%3A%22shortcut%22%2C%22callback_id%22%3A%22anonymous_feedback%22%7D").unwrap();
+        assert_eq!(payload["type"], "shortcut");
+        assert_eq!(payload["callback_id"], "anonymous_feedback");
+
+        assert!(parse_slack_interaction(b"token=abc").is_err());
+    }
+
+    #[test]
+    fn test_slack_user_group_from_value() {
+        let group = SlackUserGroup::from_value(&json!({
+            "id": "S0614TZR7",
+            "team_id": "T060RNRCH",
+            "handle": "eng",
+            "name": "Engineering",
+            "date_delete": 0,
+            "users": ["U060R4BJ4", "W123A4BC5"],
+        }));
+        assert_eq!(
+            group,
+            SlackUserGroup {
+                id: "S0614TZR7".to_string(),
+                handle: "eng".to_string(),
+                name: "Engineering".to_string(),
+                users: vec!["U060R4BJ4".to_string(), "W123A4BC5".to_string()],
+                disabled: false,
+            }
+        );
+
+        assert!(SlackUserGroup::from_value(&json!({ "id": "S0615G0KT", "handle": "oncall", "date_delete": 1446598059 })).disabled);
+    }
+
+    #[test]
+    fn test_slack_channel_from_value() {
+        let channel = SlackChannel::from_value(&json!({
+            "id": "C012AB3CD",
+            "name": "eng",
+            "is_channel": true,
+            "is_archived": false,
+            "is_private": false,
+            "is_member": true,
+            "topic": { "value": "Engineering", "creator": "U012A3CDE", "last_set": 1449252889 },
+            "purpose": { "value": "", "creator": "", "last_set": 0 },
+        }));
+        assert_eq!(
+            channel,
+            SlackChannel {
+                id: "C012AB3CD".to_string(),
+                name: "eng".to_string(),
+                topic: "Engineering".to_string(),
+                purpose: "".to_string(),
+                is_private: false,
+                is_archived: false,
+                is_member: true,
+            }
+        );
+    }
+}
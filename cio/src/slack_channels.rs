@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::chat::CHAT_CHANNELS;
+use crate::configs::Config;
+use crate::slack::{list_slack_channels, update_slack_channel, SlackChannel};
+
+/// The data type for a channel in Slack, from the `slack_channels` section of our configs,
+/// keyed by the name of the channel. Channels in Slack that are not in the section are
+/// left alone.
+///
+/// ```toml
+/// [slack_channels.eng]
+/// topic = "Engineering"
+/// purpose = "Where we talk about what we are building."
+///
+/// [slack_channels.old-eng]
+/// deprecated = true
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct SlackChannelConfig {
+    /// The ID of the channel in Slack. If this is set, the channel is renamed back when
+    /// someone renames it in Slack, rather than a new channel being created.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub topic: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub purpose: String,
+    /// Private channels are only created private, existing channels are not converted.
+    #[serde(default)]
+    pub private: bool,
+    /// Deprecated channels are archived.
+    #[serde(default)]
+    pub deprecated: bool,
+}
+
+/// A change to make to a channel in Slack to match our configs, in the order they
+/// are made.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlackChannelChange {
+    Create,
+    Unarchive,
+    Join,
+    Rename(String),
+    SetTopic(String),
+    SetPurpose(String),
+    Archive,
+}
+
+impl SlackChannelChange {
+    /// The Slack API method and its arguments for the change to a channel, by the ID of
+    /// the channel in Slack.
+    #[instrument]
+    #[inline]
+    pub fn request(&self, name: &str, config: &SlackChannelConfig, id: &str) -> (&'static str, serde_json::Value) {
+        match self {
+            SlackChannelChange::Create => ("conversations.create", json!({ "name": name, "is_private": config.private })),
+            SlackChannelChange::Unarchive => ("conversations.unarchive", json!({ "channel": id })),
+            SlackChannelChange::Join => ("conversations.join", json!({ "channel": id })),
+            SlackChannelChange::Rename(name) => ("conversations.rename", json!({ "channel": id, "name": name })),
+            SlackChannelChange::SetTopic(topic) => ("conversations.setTopic", json!({ "channel": id, "topic": topic })),
+            SlackChannelChange::SetPurpose(purpose) => ("conversations.setPurpose", json!({ "channel": id, "purpose": purpose })),
+            SlackChannelChange::Archive => ("conversations.archive", json!({ "channel": id })),
+        }
+    }
+}
+
+/// Check the name of a channel is one Slack allows: lowercase letters, numbers,
+/// hyphens, and underscores, at most 80 characters.
+#[instrument]
+#[inline]
+pub fn check_slack_channel_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 80 {
+        return Err(format!("slack channel `{}` must have a name between 1 and 80 characters", name));
+    }
+    if !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_') {
+        return Err(format!("slack channel `{}` can only have lowercase letters, numbers, hyphens, and underscores in its name", name));
+    }
+
+    Ok(())
+}
+
+/// Find the channel in Slack for a channel in our configs, by its ID if it has one
+/// and otherwise by its name.
+#[instrument(skip(channels))]
+#[inline]
+pub fn find_slack_channel<'a>(name: &str, config: &SlackChannelConfig, channels: &'a [SlackChannel]) -> Option<&'a SlackChannel> {
+    if !config.id.is_empty() {
+        return channels.iter().find(|c| c.id == config.id);
+    }
+
+    channels.iter().find(|c| c.name == name)
+}
+
+/// Returns the changes to make to a channel in Slack so it matches our configs.
+#[instrument]
+#[inline]
+pub fn slack_channel_changes(name: &str, config: &SlackChannelConfig, existing: Option<&SlackChannel>) -> Vec<SlackChannelChange> {
+    let mut changes: Vec<SlackChannelChange> = Default::default();
+    let channel = match existing {
+        Some(c) => c,
+        None if config.deprecated => return changes,
+        None => {
+            // The bot is in the channels it creates.
+            changes.push(SlackChannelChange::Create);
+            if !config.topic.is_empty() {
+                changes.push(SlackChannelChange::SetTopic(config.topic.to_string()));
+            }
+            if !config.purpose.is_empty() {
+                changes.push(SlackChannelChange::SetPurpose(config.purpose.to_string()));
+            }
+            return changes;
+        }
+    };
+
+    if config.deprecated {
+        if !channel.is_archived {
+            if !channel.is_member {
+                changes.push(SlackChannelChange::Join);
+            }
+            changes.push(SlackChannelChange::Archive);
+        }
+        return changes;
+    }
+
+    if channel.is_archived {
+        changes.push(SlackChannelChange::Unarchive);
+    }
+    if !channel.is_member {
+        changes.push(SlackChannelChange::Join);
+    }
+    if channel.name != name {
+        changes.push(SlackChannelChange::Rename(name.to_string()));
+    }
+    if !config.topic.is_empty() && channel.topic != config.topic {
+        changes.push(SlackChannelChange::SetTopic(config.topic.to_string()));
+    }
+    if !config.purpose.is_empty() && channel.purpose != config.purpose {
+        changes.push(SlackChannelChange::SetPurpose(config.purpose.to_string()));
+    }
+
+    changes
+}
+
+/// Reconcile the channels in Slack with the `slack_channels` section of our configs.
+/// Missing channels are created, topics and purposes are set, deprecated channels are
+/// archived, and the bot joins every channel. The bot also joins the channels in the
+/// `SLACK_{CHANNEL}_CHANNEL_ID` environment variables our notifications post to, since
+/// posting to a channel the bot is not in fails.
+#[instrument(skip(config))]
+#[inline]
+pub async fn reconcile_slack_channels(config: &Config) {
+    let channels = list_slack_channels().await.unwrap_or_else(|e| panic!("getting the slack channels failed: {}", e));
+
+    // The channels our notifications post to, by their IDs.
+    let mut notified: BTreeMap<String, String> = Default::default();
+    for channel in CHAT_CHANNELS {
+        let id = env::var(format!("SLACK_{}_CHANNEL_ID", channel.env_name())).unwrap_or_default();
+        if !id.is_empty() {
+            notified.insert(id, channel.env_name().to_lowercase());
+        }
+    }
+
+    for (name, channel_config) in config.slack_channels.iter() {
+        let existing = find_slack_channel(name, channel_config, &channels);
+        let mut id = existing.map(|c| c.id.to_string()).unwrap_or_default();
+        if channel_config.deprecated && notified.contains_key(&id) {
+            println!("[slack_channels] not archiving #{}, our {} notifications post to it", name, notified[&id]);
+            continue;
+        }
+
+        for change in slack_channel_changes(name, channel_config, existing) {
+            let (method, body) = change.request(name, channel_config, &id);
+            match update_slack_channel(method, body).await {
+                Ok(resp) => {
+                    if change == SlackChannelChange::Create {
+                        id = resp["channel"]["id"].as_str().unwrap_or_default().to_string();
+                    }
+                    println!("[slack_channels] #{}: {:?}", name, change);
+                }
+                Err(e) => {
+                    println!("[slack_channels] #{}: {}", name, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    for (id, notifications) in notified {
+        match channels.iter().find(|c| c.id == id) {
+            None => println!("[slack_channels] the channel {} for our {} notifications does not exist or is private", id, notifications),
+            Some(c) if c.is_archived => println!("[slack_channels] #{} for our {} notifications is archived", c.name, notifications),
+            Some(c) if !c.is_member => match update_slack_channel("conversations.join", json!({ "channel": c.id })).await {
+                Ok(_) => println!("[slack_channels] joined #{} for our {} notifications", c.name, notifications),
+                Err(e) => println!("[slack_channels] #{}: {}", c.name, e),
+            },
+            Some(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slack::SlackChannel;
+    use crate::slack_channels::{check_slack_channel_name, find_slack_channel, slack_channel_changes, SlackChannelChange, SlackChannelConfig};
+
+    #[test]
+    fn test_slack_channel_changes() {
+        let config = SlackChannelConfig {
+            topic: "Engineering".to_string(),
+            purpose: "Where we talk about what we are building.".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            slack_channel_changes("eng", &config, None),
+            vec![
+                SlackChannelChange::Create,
+                SlackChannelChange::SetTopic("Engineering".to_string()),
+                SlackChannelChange::SetPurpose("Where we talk about what we are building.".to_string())
+            ]
+        );
+
+        let channel = SlackChannel {
+            id: "C012AB3CD".to_string(),
+            name: "eng".to_string(),
+            topic: "Engineering".to_string(),
+            purpose: "Where we talk about what we are building.".to_string(),
+            is_member: true,
+            ..Default::default()
+        };
+        assert!(slack_channel_changes("eng", &config, Some(&channel)).is_empty());
+
+        // Someone renamed the channel and the bot left it.
+        let renamed = SlackChannel {
+            name: "engineering".to_string(),
+            topic: "".to_string(),
+            is_member: false,
+            ..channel.clone()
+        };
+        assert_eq!(
+            slack_channel_changes("eng", &config, Some(&renamed)),
+            vec![
+                SlackChannelChange::Join,
+                SlackChannelChange::Rename("eng".to_string()),
+                SlackChannelChange::SetTopic("Engineering".to_string())
+            ]
+        );
+
+        let deprecated = SlackChannelConfig {
+            deprecated: true,
+            ..Default::default()
+        };
+        assert_eq!(slack_channel_changes("eng", &deprecated, Some(&channel)), vec![SlackChannelChange::Archive]);
+        assert!(slack_channel_changes("eng", &deprecated, None).is_empty());
+        let archived = SlackChannel { is_archived: true, ..channel.clone() };
+        assert!(slack_channel_changes("eng", &deprecated, Some(&archived)).is_empty());
+        assert_eq!(slack_channel_changes("eng", &config, Some(&archived)), vec![SlackChannelChange::Unarchive]);
+
+        // Channels with an ID are found by it, so a rename does not create another channel.
+        let channels = vec![renamed];
+        assert!(find_slack_channel("eng", &config, &channels).is_none());
+        let with_id = SlackChannelConfig {
+            id: "C012AB3CD".to_string(),
+            ..config
+        };
+        assert_eq!(find_slack_channel("eng", &with_id, &channels).unwrap().name, "engineering");
+    }
+
+    #[test]
+    fn test_check_slack_channel_name() {
+        assert!(check_slack_channel_name("eng").is_ok());
+        assert!(check_slack_channel_name("eng-oncall_2").is_ok());
+        assert!(check_slack_channel_name("Eng").is_err());
+        assert!(check_slack_channel_name("eng oncall").is_err());
+        assert!(check_slack_channel_name("").is_err());
+        assert!(check_slack_channel_name(&"a".repeat(81)).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::password_manager::group_member_changes;
+use crate::slack::{create_slack_user_group, enable_slack_user_group, list_slack_user_emails, list_slack_user_groups, update_slack_user_group_members, SlackUserGroup};
+use crate::utils::GSUITE_DOMAIN;
+
+/// Which Slack user groups mirror the groups in our configs, from the `slack_user_groups`
+/// section of our configs, keyed by the group with the Slack handle as the value. The
+/// members of the Slack user group are kept the same as the GSuite group, so mentioning
+/// `@oncall` reaches whoever is in `oncall` today. User groups in Slack that are not in
+/// the section are left alone.
+///
+/// ```toml
+/// [slack_user_groups.groups]
+/// eng = "eng"
+/// oncall = "oncall"
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct SlackUserGroupsConfig {
+    /// The Slack user group handle, without the `@`, for each group in our configs.
+    #[serde(default)]
+    pub groups: BTreeMap<String, String>,
+}
+
+/// Returns the emails of the people who should be in each Slack user group, by handle.
+#[instrument(skip(config))]
+#[inline]
+pub fn desired_slack_user_groups(config: &Config) -> BTreeMap<String, BTreeSet<String>> {
+    let mut desired: BTreeMap<String, BTreeSet<String>> = Default::default();
+    for handle in config.slack_user_groups.groups.values() {
+        desired.entry(handle.to_string()).or_default();
+    }
+
+    for user in config.users.values() {
+        for group in user.groups.iter() {
+            if let Some(handle) = config.slack_user_groups.groups.get(group) {
+                desired.entry(handle.to_string()).or_default().insert(format!("{}@{}", user.username, GSUITE_DOMAIN));
+            }
+        }
+    }
+
+    desired
+}
+
+/// Reconcile the user groups in Slack with the groups in our configs. User groups that
+/// are missing are created, and their members are replaced with the people in the group
+/// in our configs. The people who were in a Slack user group but are not in its group,
+/// ie. because someone added them in Slack by hand, are removed and posted to the
+/// engineering channel so someone can add them to the group in our configs if they
+/// should be there.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn reconcile_slack_user_groups(db: &Database, config: &Config) {
+    if config.slack_user_groups.groups.is_empty() {
+        println!("[slack_user_groups] no groups in the slack_user_groups section of our configs");
+        return;
+    }
+
+    let desired = desired_slack_user_groups(config);
+
+    let emails = list_slack_user_emails().await.unwrap_or_else(|e| panic!("getting the slack users failed: {}", e));
+    let ids: BTreeMap<String, String> = emails.iter().map(|(id, email)| (email.to_string(), id.to_string())).collect();
+    let mut existing: BTreeMap<String, SlackUserGroup> = list_slack_user_groups()
+        .await
+        .unwrap_or_else(|e| panic!("getting the slack user groups failed: {}", e))
+        .into_iter()
+        .map(|g| (g.handle.to_string(), g))
+        .collect();
+
+    let mut removed: Vec<String> = Default::default();
+    for (group, handle) in config.slack_user_groups.groups.iter() {
+        let want = &desired[handle];
+        let members: Vec<String> = want.iter().filter_map(|email| ids.get(email)).cloned().collect();
+        for email in want.iter().filter(|email| !ids.contains_key(*email)) {
+            println!("[slack_user_groups] {} is in {} but not in slack", email, group);
+        }
+        if members.is_empty() {
+            println!("[slack_user_groups] nobody in {} is in slack, skipping @{}", group, handle);
+            continue;
+        }
+
+        let user_group = match existing.remove(handle) {
+            Some(g) if g.disabled => match enable_slack_user_group(&g.id).await {
+                Ok(_) => {
+                    println!("[slack_user_groups] enabled @{}", handle);
+                    g
+                }
+                Err(e) => {
+                    println!("[slack_user_groups] {}", e);
+                    continue;
+                }
+            },
+            Some(g) => g,
+            None => {
+                let description = config.groups.get(group).map(|g| g.description.to_string()).unwrap_or_default();
+                match create_slack_user_group(handle, group, &description).await {
+                    Ok(g) => {
+                        println!("[slack_user_groups] created @{}", handle);
+                        g
+                    }
+                    Err(e) => {
+                        println!("[slack_user_groups] {}", e);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        // Compare by email, since that is how people are matched to our configs.
+        let current: BTreeSet<String> = user_group.users.iter().filter_map(|id| emails.get(id)).cloned().collect();
+        let (add, remove) = group_member_changes(want, &current);
+        let unknown = user_group.users.iter().filter(|id| !emails.contains_key(*id)).count();
+        if add.is_empty() && remove.is_empty() && unknown == 0 {
+            continue;
+        }
+
+        match update_slack_user_group_members(&user_group.id, &members).await {
+            Ok(_) => {
+                println!("[slack_user_groups] @{}: added {:?}, removed {:?}", handle, add, remove);
+                if !remove.is_empty() {
+                    removed.push(format!("• `@{}`: {}", handle, remove.join(", ")));
+                }
+            }
+            Err(e) => println!("[slack_user_groups] {}", e),
+        }
+    }
+
+    if removed.is_empty() {
+        return;
+    }
+
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Engineering,
+        "slack_user_groups.removed",
+        json!({
+            "text": render_message("slack_user_groups.removed", &json!({ "lines": removed })),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configs::{Config, UserConfig};
+    use crate::slack_user_groups::desired_slack_user_groups;
+
+    #[test]
+    fn test_desired_slack_user_groups() {
+        let mut config = Config::default();
+        for (username, groups) in &[("jane", r#"["eng", "oncall"]"#), ("sam", r#"["eng"]"#), ("alex", r#"["sales"]"#)] {
+            let user: UserConfig = toml::from_str(&format!(
+                r#"first_name = "{}"
+last_name = "Doe"
+username = "{}"
+groups = {}
+"#,
+                username, username, groups
+            ))
+            .unwrap();
+            config.users.insert(username.to_string(), user);
+        }
+        config.slack_user_groups.groups.insert("eng".to_string(), "eng".to_string());
+        config.slack_user_groups.groups.insert("oncall".to_string(), "oncall".to_string());
+        config.slack_user_groups.groups.insert("hardware".to_string(), "hw".to_string());
+
+        let desired = desired_slack_user_groups(&config);
+        assert_eq!(desired.keys().collect::<Vec<_>>(), vec!["eng", "hw", "oncall"]);
+        assert_eq!(desired["eng"].iter().collect::<Vec<_>>(), vec!["jane@oxidecomputer.com", "sam@oxidecomputer.com"]);
+        assert_eq!(desired["oncall"].iter().collect::<Vec<_>>(), vec!["jane@oxidecomputer.com"]);
+        assert!(desired["hw"].is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use diesel::connection::SimpleConnection;
+use diesel::sql_types::{Integer, Text};
+use diesel::sqlite::SqliteConnection;
+use diesel::{sql_query, Connection, RunQueryDsl};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::instrument;
+
+/// A SQLite database for local development, so the syncs can run without a Postgres.
+/// Our tables use Postgres arrays and jsonb, so rather than keep a second schema and a
+/// second set of migrations, the records of every synced table share one table here,
+/// each kept as the JSON it serializes to, by its id and the values of the fields we
+/// match it on. Fields we never serialize, like the sender of anonymous feedback, are
+/// not kept. The tables that are not synced to Airtable are only kept in Postgres.
+pub struct SqliteStore {
+    conn: Mutex<SqliteConnection>,
+}
+
+#[derive(QueryableByName)]
+struct StoredRecord {
+    #[sql_type = "Text"]
+    record: String,
+}
+
+#[derive(QueryableByName)]
+struct NextId {
+    #[sql_type = "Integer"]
+    id: i32,
+}
+
+/// The fields of an existing record that saving new data for it keeps.
+static KEPT_FIELDS: &[&str] = &["id", "airtable_record_id", "source", "extra"];
+
+impl SqliteStore {
+    /// Open the SQLite database at a path, creating it if it does not exist yet.
+    #[instrument]
+    #[inline]
+    pub fn open(path: &str) -> Result<Self, String> {
+        let conn = SqliteConnection::establish(path).map_err(|e| format!("opening the SQLite database {} failed: {}", path, e))?;
+        conn.batch_execute(
+            "CREATE TABLE IF NOT EXISTS records (
+                table_name TEXT NOT NULL,
+                id INTEGER NOT NULL,
+                match_key TEXT NOT NULL,
+                record TEXT NOT NULL,
+                PRIMARY KEY (table_name, id)
+            );
+            CREATE INDEX IF NOT EXISTS records_match_key ON records (table_name, match_key);",
+        )
+        .map_err(|e| format!("creating the records table in {} failed: {}", path, e))?;
+
+        Ok(SqliteStore { conn: Mutex::new(conn) })
+    }
+
+    fn decode<T: DeserializeOwned>(table: &str, stored: StoredRecord) -> T {
+        serde_json::from_str(&stored.record).unwrap_or_else(|e| panic!("[sqlite] decoding a record in {} failed: {}", table, e))
+    }
+
+    /// Get the record in a table with the values of the fields we match it on, see
+    /// `match_on_key`.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn find<T: DeserializeOwned>(&self, table: &str, match_key: &str) -> Option<T> {
+        let conn = self.conn.lock().unwrap();
+        sql_query("SELECT record FROM records WHERE table_name = ? AND match_key = ? LIMIT 1")
+            .bind::<Text, _>(table)
+            .bind::<Text, _>(match_key)
+            .load::<StoredRecord>(&*conn)
+            .unwrap_or_else(|e| panic!("[sqlite] getting a record from {} failed: {}", table, e))
+            .pop()
+            .map(|r| SqliteStore::decode(table, r))
+    }
+
+    /// Get every record in a table, newest first like the `get_from_db` of the tables.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn list<T: DeserializeOwned>(&self, table: &str) -> Vec<T> {
+        let conn = self.conn.lock().unwrap();
+        sql_query("SELECT record FROM records WHERE table_name = ? ORDER BY id DESC")
+            .bind::<Text, _>(table)
+            .load::<StoredRecord>(&*conn)
+            .unwrap_or_else(|e| panic!("[sqlite] getting the records from {} failed: {}", table, e))
+            .into_iter()
+            .map(|r| SqliteStore::decode(table, r))
+            .collect()
+    }
+
+    /// Save a record as it is, by its id.
+    #[instrument(skip(self, record))]
+    #[inline]
+    pub fn put<T: Serialize>(&self, table: &str, id: i32, match_key: &str, record: &T) {
+        let conn = self.conn.lock().unwrap();
+        sql_query("INSERT OR REPLACE INTO records (table_name, id, match_key, record) VALUES (?, ?, ?, ?)")
+            .bind::<Text, _>(table)
+            .bind::<Integer, _>(id)
+            .bind::<Text, _>(match_key)
+            .bind::<Text, _>(serde_json::to_string(record).unwrap())
+            .execute(&*conn)
+            .unwrap_or_else(|e| panic!("[sqlite] saving record {} in {} failed: {}", id, table, e));
+    }
+
+    /// Save the fields of a record, like `create_in_db_from` and `upsert_in_db_from` do in
+    /// Postgres. A new record gets the next id and `from` as its source, and an existing
+    /// one keeps its id, Airtable record ID, source, and extra fields.
+    #[instrument(skip(self, fields, existing))]
+    #[inline]
+    pub fn save<F: Serialize, T: Serialize + DeserializeOwned>(&self, table: &str, match_key: &str, fields: &F, existing: Option<&T>, from: &str, now: DateTime<Utc>) -> T {
+        let mut value = serde_json::to_value(fields).unwrap();
+        let record = value.as_object_mut().unwrap();
+        match existing {
+            Some(e) => {
+                let e = serde_json::to_value(e).unwrap();
+                for field in KEPT_FIELDS {
+                    if let Some(v) = e.get(*field) {
+                        record.insert(field.to_string(), v.clone());
+                    }
+                }
+            }
+            None => {
+                record.insert("id".to_string(), Value::from(self.next_id(table)));
+                record.insert("source".to_string(), Value::from(from));
+            }
+        }
+        record.insert("last_synced_from".to_string(), Value::from(from));
+        record.insert("last_synced_at".to_string(), serde_json::to_value(now).unwrap());
+        record.insert("modified_at".to_string(), serde_json::to_value(now).unwrap());
+
+        let id = value["id"].as_i64().unwrap_or_default() as i32;
+        let saved: T = serde_json::from_value(value).unwrap_or_else(|e| panic!("[sqlite] building record {} in {} failed: {}", id, table, e));
+        self.put(table, id, match_key, &saved);
+        saved
+    }
+
+    /// Delete a record by its id. Returns how many records were deleted.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn delete(&self, table: &str, id: i32) -> usize {
+        let conn = self.conn.lock().unwrap();
+        sql_query("DELETE FROM records WHERE table_name = ? AND id = ?")
+            .bind::<Text, _>(table)
+            .bind::<Integer, _>(id)
+            .execute(&*conn)
+            .unwrap_or_else(|e| panic!("[sqlite] deleting record {} from {} failed: {}", id, table, e))
+    }
+
+    fn next_id(&self, table: &str) -> i32 {
+        let conn = self.conn.lock().unwrap();
+        sql_query("SELECT COALESCE(MAX(id), 0) + 1 AS id FROM records WHERE table_name = ?")
+            .bind::<Text, _>(table)
+            .load::<NextId>(&*conn)
+            .unwrap_or_else(|e| panic!("[sqlite] getting the next id for {} failed: {}", table, e))
+            .pop()
+            .map(|n| n.id)
+            .unwrap_or(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde::{Deserialize, Serialize};
+
+    use crate::sqlite::SqliteStore;
+
+    #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+    struct Thing {
+        #[serde(default)]
+        id: i32,
+        name: String,
+        #[serde(default)]
+        airtable_record_id: String,
+        #[serde(default)]
+        source: String,
+        #[serde(default)]
+        last_synced_from: String,
+    }
+
+    #[derive(Serialize)]
+    struct NewThing {
+        name: String,
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let now = Utc.ymd(2021, 5, 24).and_hms(9, 0, 0);
+
+        let created: Thing = store.save("things", "(\"a\",)", &NewThing { name: "a".to_string() }, None, "seed", now);
+        assert_eq!(created.id, 1);
+        assert_eq!(created.source, "seed");
+        let other: Thing = store.save("things", "(\"b\",)", &NewThing { name: "b".to_string() }, None, "seed", now);
+        assert_eq!(other.id, 2);
+
+        // Saving new data for a record keeps where it came from and its Airtable record ID.
+        let mut existing = created.clone();
+        existing.airtable_record_id = "rec1".to_string();
+        store.put("things", existing.id, "(\"a\",)", &existing);
+        let updated: Thing = store.save("things", "(\"a\",)", &NewThing { name: "a2".to_string() }, Some(&existing), "configs", now);
+        assert_eq!((updated.id, updated.name.as_str(), updated.airtable_record_id.as_str()), (1, "a2", "rec1"));
+        assert_eq!((updated.source.as_str(), updated.last_synced_from.as_str()), ("seed", "configs"));
+
+        assert_eq!(store.find::<Thing>("things", "(\"a\",)"), Some(updated));
+        assert_eq!(store.find::<Thing>("others", "(\"a\",)"), None);
+        assert_eq!(store.list::<Thing>("things").iter().map(|t| t.id).collect::<Vec<_>>(), vec![2, 1]);
+
+        assert_eq!(store.delete("things", 2), 1);
+        assert_eq!(store.list::<Thing>("things").len(), 1);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+
+use chrono::offset::Utc;
+use chrono::DateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::business_days::BusinessCalendar;
+use crate::configs::{User, Users};
+use crate::db::Database;
+use crate::models::{GithubRepo, GithubRepos};
+use crate::notifications::notify_user_at;
+use crate::schedule::CronSchedule;
+use crate::utils::{github_api_get, github_api_list, github_org};
+
+/// The number of days without a commit before a branch is considered stale, unless
+/// `branch_days` in the `stale` section of `sync.toml` says otherwise.
+pub const STALE_BRANCH_DAYS: i64 = 90;
+
+/// The number of days without any activity before a pull request is considered stale,
+/// unless `pull_request_days` in the `stale` section of `sync.toml` says otherwise.
+pub const STALE_PULL_REQUEST_DAYS: i64 = 30;
+
+/// When we send people their stale items, in their own timezone, so the reminder
+/// is waiting for them at the start of their day rather than in the middle of the night.
+pub const STALE_ITEMS_REMINDER_SCHEDULE: &str = "0 9 * * mon-fri";
+
+/// When branches and pull requests are considered stale, from the `stale` section of
+/// `sync.toml`.
+///
+/// ```toml
+/// [stale]
+/// branch_days = 90
+/// pull_request_days = 30
+/// ```
+#[derive(Debug, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct StaleConfig {
+    /// Defaults to `STALE_BRANCH_DAYS`.
+    #[serde(default = "default_branch_days")]
+    pub branch_days: i64,
+    /// Defaults to `STALE_PULL_REQUEST_DAYS`.
+    #[serde(default = "default_pull_request_days")]
+    pub pull_request_days: i64,
+}
+
+impl Default for StaleConfig {
+    fn default() -> Self {
+        StaleConfig {
+            branch_days: STALE_BRANCH_DAYS,
+            pull_request_days: STALE_PULL_REQUEST_DAYS,
+        }
+    }
+}
+
+fn default_branch_days() -> i64 {
+    STALE_BRANCH_DAYS
+}
+
+fn default_pull_request_days() -> i64 {
+    STALE_PULL_REQUEST_DAYS
+}
+
+/// A branch or pull request that has not had any activity in a while.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleItem {
+    pub repo: String,
+    /// Either `branch` or `pull request`.
+    pub kind: String,
+    pub name: String,
+    pub html_url: String,
+    /// The GitHub login of the author of the last commit for a branch, or the
+    /// person who opened a pull request.
+    pub author: String,
+    pub last_activity: DateTime<Utc>,
+}
+
+impl StaleItem {
+    /// Returns the number of days since the last activity.
+    #[instrument]
+    #[inline]
+    pub fn days_inactive(&self, now: DateTime<Utc>) -> i64 {
+        now.signed_duration_since(self.last_activity).num_days()
+    }
+}
+
+/// Parse a timestamp from the GitHub API.
+fn parse_time(v: &Value) -> Option<DateTime<Utc>> {
+    v.as_str().and_then(|s| DateTime::parse_from_rfc3339(s).ok()).map(|t| t.with_timezone(&Utc))
+}
+
+/// Get the branches for a repository that have not had a commit in `days`.
+/// The default branch and protected branches are never considered stale.
+#[instrument]
+#[inline]
+pub async fn get_stale_branches(repo: &GithubRepo, now: DateTime<Utc>, days: i64) -> Vec<StaleItem> {
+    let branches: Vec<Value> = github_api_list(&format!("/repos/{}/{}/branches", github_org(), repo.name)).await.unwrap_or_default();
+
+    let mut stale: Vec<StaleItem> = Default::default();
+    for branch in branches {
+        let name = branch["name"].as_str().unwrap_or_default().to_string();
+        if name == repo.default_branch || branch["protected"].as_bool().unwrap_or_default() {
+            continue;
+        }
+
+        let sha = branch["commit"]["sha"].as_str().unwrap_or_default();
+        let commit: Value = match github_api_get(&format!("/repos/{}/{}/commits/{}", github_org(), repo.name, sha)).await {
+            Ok(c) => c,
+            Err(e) => {
+                println!("[stale] getting commit {} for {}/{} failed: {}", sha, repo.name, name, e);
+                continue;
+            }
+        };
+
+        let last_activity = match parse_time(&commit["commit"]["committer"]["date"]) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let item = StaleItem {
+            repo: repo.name.to_string(),
+            kind: "branch".to_string(),
+            html_url: format!("https://github.com/{}/{}/tree/{}", github_org(), repo.name, name),
+            name,
+            author: commit["author"]["login"].as_str().unwrap_or_default().to_string(),
+            last_activity,
+        };
+        if item.days_inactive(now) >= days {
+            stale.push(item);
+        }
+    }
+
+    stale
+}
+
+/// Get the open pull requests for a repository that have not been updated in `days`.
+#[instrument]
+#[inline]
+pub async fn get_stale_pull_requests(repo: &GithubRepo, now: DateTime<Utc>, days: i64) -> Vec<StaleItem> {
+    let pulls: Vec<Value> = github_api_list(&format!("/repos/{}/{}/pulls?state=open", github_org(), repo.name)).await.unwrap_or_default();
+
+    let mut stale: Vec<StaleItem> = Default::default();
+    for pull in pulls {
+        let last_activity = match parse_time(&pull["updated_at"]) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let item = StaleItem {
+            repo: repo.name.to_string(),
+            kind: "pull request".to_string(),
+            name: format!("#{} {}", pull["number"].as_i64().unwrap_or_default(), pull["title"].as_str().unwrap_or_default()),
+            html_url: pull["html_url"].as_str().unwrap_or_default().to_string(),
+            author: pull["user"]["login"].as_str().unwrap_or_default().to_string(),
+            last_activity,
+        };
+        if item.days_inactive(now) >= days {
+            stale.push(item);
+        }
+    }
+
+    stale
+}
+
+/// Format the message we send to a person listing their stale branches and pull requests.
+#[instrument]
+#[inline]
+pub fn format_stale_items_message(items: &[StaleItem], now: DateTime<Utc>) -> String {
+    let mut text = format!(
+        "You have {} stale branches or pull requests. Please merge, close, or delete them if they are no longer needed. :broom:\n",
+        items.len()
+    );
+
+    for (kind, heading) in &[("pull request", "pull requests"), ("branch", "branches")] {
+        let mut matching: Vec<&StaleItem> = items.iter().filter(|i| i.kind == *kind).collect();
+        if matching.is_empty() {
+            continue;
+        }
+        matching.sort_by(|a, b| a.last_activity.cmp(&b.last_activity));
+
+        text += &format!("\n*{}*\n", heading);
+        for item in matching {
+            text += &format!("• `{}` <{}|{}> ({} days inactive)\n", item.repo, item.html_url, item.name, item.days_inactive(now));
+        }
+    }
+
+    text
+}
+
+/// Scan all the repositories in our org for stale branches and pull requests
+/// and send each author a direct message in Slack listing their stale items at
+/// the next `STALE_ITEMS_REMINDER_SCHEDULE` time on a business day in their timezone.
+#[instrument(skip(db, calendar))]
+#[inline]
+pub async fn send_stale_items_reminders(db: &Database, calendar: &BusinessCalendar, stale: &StaleConfig) {
+    let now = Utc::now();
+
+    let mut items: Vec<StaleItem> = Default::default();
+    for repo in GithubRepos::get_from_db(db) {
+        if repo.archived || repo.fork {
+            continue;
+        }
+
+        items.append(&mut get_stale_branches(&repo, now, stale.branch_days).await);
+        items.append(&mut get_stale_pull_requests(&repo, now, stale.pull_request_days).await);
+    }
+
+    // Attribute the items to people in our directory by their GitHub handle.
+    let users: Vec<User> = Users::get_from_db(db).into();
+    let mut by_user: BTreeMap<String, (&User, Vec<StaleItem>)> = Default::default();
+    let mut unattributed = 0;
+    for item in items {
+        match users.iter().find(|u| !u.github.is_empty() && u.github.to_lowercase() == item.author.to_lowercase()) {
+            Some(user) => by_user.entry(user.email()).or_insert_with(|| (user, vec![])).1.push(item),
+            None => unattributed += 1,
+        }
+    }
+
+    if unattributed > 0 {
+        println!("[stale] {} stale items could not be attributed to a user in our directory", unattributed);
+    }
+
+    let schedule = CronSchedule::parse(STALE_ITEMS_REMINDER_SCHEDULE).unwrap();
+    for (email, (user, user_items)) in by_user {
+        let deliver_at = calendar.next_business_fire(&schedule, now, &user.local_timezone()).unwrap_or(now);
+        notify_user_at(db, &email, json!({ "text": format_stale_items_message(&user_items, now) }), deliver_at);
+        println!("[stale] scheduled {} stale items for {} at {}", user_items.len(), email, deliver_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::business_days::BusinessCalendar;
+    use crate::db::Database;
+    use crate::stale::{format_stale_items_message, send_stale_items_reminders, StaleConfig, StaleItem};
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_monday_cron_stale_items() {
+        let db = Database::new();
+
+        send_stale_items_reminders(&db, &BusinessCalendar::default(), &StaleConfig::default()).await;
+    }
+
+    #[test]
+    fn test_format_stale_items_message() {
+        let now = Utc::now();
+        let items = vec![
+            StaleItem {
+                repo: "cio".to_string(),
+                kind: "branch".to_string(),
+                name: "old-thing".to_string(),
+                html_url: "https://github.com/oxidecomputer/cio/tree/old-thing".to_string(),
+                author: "jessfraz".to_string(),
+                last_activity: now - Duration::days(120),
+            },
+            StaleItem {
+                repo: "cio".to_string(),
+                kind: "pull request".to_string(),
+                name: "#12 Fix all the things".to_string(),
+                html_url: "https://github.com/oxidecomputer/cio/pull/12".to_string(),
+                author: "jessfraz".to_string(),
+                last_activity: now - Duration::days(45),
+            },
+        ];
+
+        let text = format_stale_items_message(&items, now);
+        assert!(text.starts_with("You have 2 stale"));
+        assert!(text.contains("*pull requests*"));
+        assert!(text.contains("*branches*"));
+        assert!(text.contains("(120 days inactive)"));
+        assert!(text.find("#12 Fix").unwrap() < text.find("old-thing").unwrap());
+    }
+}
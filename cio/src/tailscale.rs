@@ -1,7 +1,25 @@
-use chrono::{Duration, Utc};
-use tailscale_api::Tailscale;
+#![allow(clippy::from_over_into)]
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::offset::Utc;
+use chrono::{DateTime, Duration};
+use macros::db;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tailscale_api::{Device, Tailscale};
 use tracing::instrument;
 
+use crate::airtable::{AIRTABLE_BASE_ID_MISC, AIRTABLE_TAILSCALE_DEVICES_TABLE};
+use crate::chat::ChatChannel;
+use crate::configs::{User, Users};
+use crate::core::UpdateAirtableRecord;
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::tailscale_devices;
+use crate::utils::{DOMAIN, GSUITE_DOMAIN};
+
 /// When we generate VMs for the console repo on every branch we get lingering
 /// Tailscale devices that need to cleaned up when they are no longer active.
 /// This function does that.
@@ -30,13 +48,218 @@ pub async fn cleanup_old_tailscale_devices() {
     }
 }
 
+/// A device on our tailnet and the person in our directory it belongs to.
+#[db {
+    new_struct_name = "TailscaleDevice",
+    airtable_base_id = "AIRTABLE_BASE_ID_MISC",
+    airtable_table = "AIRTABLE_TAILSCALE_DEVICES_TABLE",
+    source = "tailscale",
+    match_on = {
+        "device_id" = "String",
+    },
+}]
+#[derive(Debug, Insertable, AsChangeset, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+#[table_name = "tailscale_devices"]
+pub struct NewTailscaleDevice {
+    pub device_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hostname: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub os: String,
+    /// The Tailscale login of the person who added the device, ie. `jane@oxidecomputer.com`
+    /// or `jane@github`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub login: String,
+    /// The username of the person in our directory the login belongs to, if any.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub client_version: String,
+    /// Either `ok`, `tagged`, `shared`, `offboarded`, or `unknown`.
+    pub status: String,
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// When the node key expires, empty if key expiry is disabled for the device.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// Implement updating the Airtable record for a TailscaleDevice.
+#[async_trait]
+impl UpdateAirtableRecord<TailscaleDevice> for TailscaleDevice {
+    async fn update_airtable_record(&mut self, _record: TailscaleDevice) {}
+}
+
+/// Returns the username of the person in our directory a Tailscale login belongs to. Logins
+/// are either an email or `{github username}@github` for people who log in with GitHub.
+#[instrument(skip(users))]
+#[inline]
+pub fn directory_username(login: &str, users: &[User]) -> Option<String> {
+    let (name, domain) = login.rsplit_once('@')?;
+    let name = name.to_lowercase();
+
+    let user = if domain == "github" {
+        users.iter().find(|u| !u.github.is_empty() && u.github.to_lowercase() == name)
+    } else if domain == GSUITE_DOMAIN || domain == DOMAIN {
+        users.iter().find(|u| u.username == name || u.aliases.contains(&name))
+    } else {
+        None
+    };
+
+    user.map(|u| u.username.to_string())
+}
+
+/// Returns if a Tailscale login is an account in our domain, so a login in our domain that
+/// is not in our directory is someone who was offboarded.
+#[instrument]
+#[inline]
+pub fn is_our_login(login: &str) -> bool {
+    matches!(login.rsplit_once('@'), Some((_, domain)) if domain == GSUITE_DOMAIN || domain == DOMAIN)
+}
+
+impl NewTailscaleDevice {
+    /// Create a device from the Tailscale API and map it to the person in our directory.
+    #[instrument(skip(device, users))]
+    #[inline]
+    pub fn new(device: &Device, users: &[User]) -> Self {
+        let username = directory_username(&device.user, users).unwrap_or_default();
+
+        let status = if !device.tags.is_empty() {
+            "tagged"
+        } else if device.is_external {
+            "shared"
+        } else if !username.is_empty() {
+            "ok"
+        } else if is_our_login(&device.user) {
+            "offboarded"
+        } else {
+            "unknown"
+        };
+
+        NewTailscaleDevice {
+            device_id: device.id.to_string(),
+            name: device.name.to_string(),
+            hostname: device.hostname.to_string(),
+            os: device.os.to_string(),
+            login: device.user.to_string(),
+            username,
+            tags: device.tags.clone(),
+            client_version: device.client_version.to_string(),
+            status: status.to_string(),
+            created: device.created,
+            last_seen: device.last_seen,
+            expires: if device.never_expires { None } else { Some(device.expires) },
+        }
+    }
+}
+
+/// Sync the devices on our tailnet and map them to the people in our directory. Devices
+/// and auth keys of people who were offboarded are expired, and devices that do not belong
+/// to anyone in our directory are posted to the #security channel.
+#[instrument(skip(db))]
+#[inline]
+pub async fn refresh_tailscale_devices(db: &Database) {
+    let tailscale = Tailscale::new_from_env();
+    let users: Vec<User> = Users::get_from_db(db).into_iter().collect();
+
+    let mut existing: BTreeMap<String, TailscaleDevice> = TailscaleDevices::get_from_db(db).into_iter().map(|d| (d.device_id.to_string(), d)).collect();
+
+    let mut offboarded: Vec<String> = Default::default();
+    let mut unknown: Vec<String> = Default::default();
+    for device in tailscale.list_devices().await.unwrap() {
+        let d = NewTailscaleDevice::new(&device, &users);
+        let previous = existing.remove(&d.device_id);
+
+        if d.status == "offboarded" && d.expires.map(|e| e > Utc::now()).unwrap_or(true) {
+            match tailscale.expire_device(&d.device_id).await {
+                Ok(_) => offboarded.push(format!("• expired *{}* of {}", d.name, d.login)),
+                Err(e) => println!("[tailscale] expiring {} of {} failed: {}", d.name, d.login, e),
+            }
+        }
+
+        if d.status == "unknown" && previous.map(|p| p.status != "unknown").unwrap_or(true) {
+            unknown.push(format!("• *{}* ({}) of {}, last seen {}", d.name, d.os, d.login, d.last_seen.format("%Y-%m-%d")));
+        }
+
+        d.upsert(db).await;
+    }
+
+    // The devices we did not see again were removed from the tailnet.
+    for (_, device) in existing {
+        device.delete(db).await;
+    }
+
+    // Auth keys can add devices without logging in, so delete the ones created by
+    // people who were offboarded.
+    let logins: BTreeMap<String, String> = tailscale.list_users().await.unwrap().into_iter().map(|u| (u.id, u.login_name)).collect();
+    for key in tailscale.list_keys().await.unwrap() {
+        let login = match logins.get(&key.user_id) {
+            Some(l) => l,
+            None => continue,
+        };
+        if key.revoked.is_some() || !is_our_login(login) || directory_username(login, &users).is_some() {
+            continue;
+        }
+
+        match tailscale.delete_key(&key.id).await {
+            Ok(_) => offboarded.push(format!("• deleted auth key `{}` {} of {}", key.id, key.description, login)),
+            Err(e) => println!("[tailscale] deleting auth key {} of {} failed: {}", key.id, login, e),
+        }
+    }
+
+    if !offboarded.is_empty() {
+        notify(
+            db,
+            NotificationPriority::Low,
+            ChatChannel::Security,
+            "tailscale.offboarded",
+            json!({
+                "text": render_message("tailscale.offboarded", &json!({ "lines": offboarded })),
+            }),
+        )
+        .await;
+    }
+
+    if !unknown.is_empty() {
+        notify(
+            db,
+            NotificationPriority::Urgent,
+            ChatChannel::Security,
+            "tailscale.unknown_devices",
+            json!({
+                "text": render_message("tailscale.unknown_devices", &json!({ "lines": unknown })),
+            }),
+        )
+        .await;
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::tailscale::cleanup_old_tailscale_devices;
+    use crate::db::Database;
+    use crate::tailscale::{cleanup_old_tailscale_devices, directory_username, is_our_login, refresh_tailscale_devices, TailscaleDevices};
 
     #[ignore]
     #[tokio::test(threaded_scheduler)]
     async fn test_cron_tailscale() {
         cleanup_old_tailscale_devices().await;
+
+        let db = Database::new();
+        refresh_tailscale_devices(&db).await;
+        TailscaleDevices::get_from_db(&db).update_airtable().await;
+    }
+
+    #[test]
+    fn test_tailscale_directory_username() {
+        assert!(is_our_login("jane@oxidecomputer.com"));
+        assert!(is_our_login("jane@oxide.computer"));
+        assert!(!is_our_login("jane@github"));
+        assert!(!is_our_login("jane@example.com"));
+        assert!(!is_our_login("tagged-devices"));
+
+        assert_eq!(directory_username("jane@example.com", &[]), None);
+        assert_eq!(directory_username("tagged-devices", &[]), None);
     }
 }
@@ -0,0 +1,511 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use hubcaps::collaborators::Permissions;
+use hubcaps::Github;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::applicants::Applicant;
+use crate::chat::ChatChannel;
+use crate::configs::Config;
+use crate::db::Database;
+use crate::format::format_time;
+use crate::messages::render_message;
+use crate::notifications::{notify, NotificationPriority};
+use crate::schema::{applicants, take_home_assignments, take_home_grades};
+use crate::utils::{github_api_delete, github_api_get, github_api_list, github_api_post, github_org};
+
+/// How many days candidates have for a take-home, if its config does not say.
+const DEFAULT_TAKE_HOME_DAYS: i64 = 7;
+
+/// The lowest and highest score a grader can give a take-home.
+pub const TAKE_HOME_SCORES: (i32, i32) = (1, 5);
+
+/// A take-home assignment, from the `take_homes` section of our configs, by name.
+///
+/// ```toml
+/// [take_homes.firmware]
+/// repo = "take-home-firmware"
+/// days = 7
+/// graders = ["jane", "sam"]
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct TakeHomeConfig {
+    /// The private repository in our GitHub organization with the assignment. It must
+    /// be marked as a template repository, since we generate the repository for each
+    /// candidate from it.
+    pub repo: String,
+    /// How many days candidates have to push their work. Defaults to 7.
+    #[serde(default)]
+    pub days: i64,
+    /// The usernames of the people who grade the assignment.
+    #[serde(default)]
+    pub graders: Vec<String>,
+}
+
+impl TakeHomeConfig {
+    /// Get how long candidates have for the assignment.
+    #[instrument]
+    #[inline]
+    pub fn time_allowed(&self) -> Duration {
+        if self.days > 0 {
+            Duration::days(self.days)
+        } else {
+            Duration::days(DEFAULT_TAKE_HOME_DAYS)
+        }
+    }
+}
+
+/// A take-home we sent a candidate: the repository we made for them and how long they
+/// have to work in it. There is one per applicant and assignment.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "take_home_assignments"]
+pub struct NewTakeHomeAssignment {
+    pub applicant_id: i32,
+    /// The name of the assignment in our configs.
+    pub assignment: String,
+    /// The name of the repository we made for the candidate, in our organization.
+    pub repo: String,
+    /// The GitHub login of the candidate, without the `@`.
+    pub github: String,
+    pub sent_at: DateTime<Utc>,
+    pub due_at: DateTime<Utc>,
+    /// When the candidate last committed to the repository before we took away their
+    /// access, or when they told us they were done.
+    pub submitted_at: Option<DateTime<Utc>>,
+    /// When we took away the access of the candidate to the repository.
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, AsChangeset)]
+#[table_name = "take_home_assignments"]
+pub struct TakeHomeAssignment {
+    pub id: i32,
+    pub applicant_id: i32,
+    pub assignment: String,
+    pub repo: String,
+    pub github: String,
+    pub sent_at: DateTime<Utc>,
+    pub due_at: DateTime<Utc>,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl TakeHomeAssignment {
+    /// Get the link to the repository of the candidate.
+    #[instrument]
+    #[inline]
+    pub fn url(&self) -> String {
+        format!("https://github.com/{}/{}", github_org(), self.repo)
+    }
+
+    /// Describe when the candidate submitted the assignment, for the hiring channel.
+    #[instrument]
+    #[inline]
+    pub fn submission(&self) -> String {
+        match self.submitted_at {
+            None => "did not push anything".to_string(),
+            Some(at) if at > self.due_at => format!("pushed their last commit at {}, after the deadline of {}", format_time(at), format_time(self.due_at)),
+            Some(at) => format!("pushed their last commit at {}", format_time(at)),
+        }
+    }
+}
+
+/// The score a grader gave a take-home. Grading again replaces the grade.
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[table_name = "take_home_grades"]
+pub struct NewTakeHomeGrade {
+    pub assignment_id: i32,
+    /// The username of the grader.
+    pub grader: String,
+    pub score: i32,
+    pub notes: String,
+    pub graded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct TakeHomeGrade {
+    pub id: i32,
+    pub assignment_id: i32,
+    pub grader: String,
+    pub score: i32,
+    pub notes: String,
+    pub graded_at: DateTime<Utc>,
+}
+
+/// Get the name of the repository we make for a candidate, from the name of the
+/// assignment and their GitHub handle from their application.
+#[instrument]
+#[inline]
+pub fn candidate_repo_name(assignment: &str, github: &str) -> String {
+    format!("{}-{}", assignment, github.trim().trim_start_matches('@'))
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "-")
+}
+
+/// Get the average of the scores graders gave a take-home, or zero if nobody has
+/// graded it yet.
+#[instrument]
+#[inline]
+pub fn average_score(grades: &[TakeHomeGrade]) -> f32 {
+    if grades.is_empty() {
+        return 0.0;
+    }
+
+    grades.iter().map(|g| g.score as f32).sum::<f32>() / grades.len() as f32
+}
+
+/// Get the most recent application of an applicant.
+fn latest_application(db: &Database, email: &str) -> Result<Applicant, String> {
+    applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(email.trim().to_lowercase()))
+        .order_by(applicants::dsl::submitted_time.desc())
+        .first::<Applicant>(&db.conn())
+        .map_err(|_| format!("{} has not applied", email))
+}
+
+/// Get the take-home we sent an applicant, the most recent if we sent them more than one.
+fn latest_take_home(db: &Database, email: &str) -> Result<(Applicant, TakeHomeAssignment), String> {
+    let applicant = latest_application(db, email)?;
+    let take_home = take_home_assignments::table
+        .filter(take_home_assignments::applicant_id.eq(applicant.id))
+        .order_by(take_home_assignments::sent_at.desc())
+        .first::<TakeHomeAssignment>(&db.conn())
+        .map_err(|_| format!("we have not sent {} a take-home", email))?;
+
+    Ok((applicant, take_home))
+}
+
+/// Get when the candidate last committed to their repository, if they did.
+#[instrument]
+#[inline]
+async fn last_commit_by(take_home: &TakeHomeAssignment) -> Result<Option<DateTime<Utc>>, String> {
+    let commits: Vec<Value> = github_api_get(&format!("/repos/{}/{}/commits?author={}&per_page=1", github_org(), take_home.repo, take_home.github))
+        .await
+        .map_err(|e| format!("getting the commits to {} failed: {}", take_home.repo, e))?;
+
+    Ok(commits
+        .first()
+        .and_then(|c| c["commit"]["committer"]["date"].as_str())
+        .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+        .map(|d| d.with_timezone(&Utc)))
+}
+
+/// Take away the access of the candidate to their repository, including the invitation
+/// if they never accepted it.
+#[instrument]
+#[inline]
+async fn revoke_access(take_home: &TakeHomeAssignment) -> Result<(), String> {
+    let org = github_org();
+    let invitations: Vec<Value> = github_api_list(&format!("/repos/{}/{}/invitations", org, take_home.repo))
+        .await
+        .map_err(|e| format!("getting the invitations to {} failed: {}", take_home.repo, e))?;
+    for invitation in invitations {
+        if invitation["invitee"]["login"].as_str().unwrap_or_default().eq_ignore_ascii_case(&take_home.github) {
+            github_api_delete(&format!("/repos/{}/{}/invitations/{}", org, take_home.repo, invitation["id"]))
+                .await
+                .map_err(|e| format!("deleting the invitation of {} to {} failed: {}", take_home.github, take_home.repo, e))?;
+        }
+    }
+
+    github_api_delete(&format!("/repos/{}/{}/collaborators/{}", org, take_home.repo, take_home.github))
+        .await
+        .map_err(|e| format!("removing {} from {} failed: {}", take_home.github, take_home.repo, e))
+}
+
+/// Close a take-home: record when the candidate submitted it, take away their access,
+/// and let the hiring channel know it is ready to grade.
+#[instrument(skip(db, config))]
+#[inline]
+async fn close_take_home(db: &Database, config: &Config, applicant: &Applicant, mut take_home: TakeHomeAssignment) -> Result<String, String> {
+    if take_home.submitted_at.is_none() {
+        take_home.submitted_at = last_commit_by(&take_home).await?;
+    }
+    revoke_access(&take_home).await?;
+    take_home.revoked_at = Some(Utc::now());
+    diesel::update(take_home_assignments::table.find(take_home.id))
+        .set(&take_home)
+        .execute(&db.conn())
+        .map_err(|e| format!("updating the take-home of {} failed: {}", applicant.email, e))?;
+
+    let graders: Vec<String> = config.take_homes.get(&take_home.assignment).map(|t| t.graders.clone()).unwrap_or_default();
+    notify(
+        db,
+        NotificationPriority::Low,
+        ChatChannel::Hiring,
+        "take_homes.closed",
+        json!({
+            "text": render_message(
+                "take_homes.closed",
+                &json!({
+                    "title": take_home.assignment,
+                    "name": applicant.name,
+                    "role": applicant.role,
+                    "status": take_home.submission(),
+                    "url": take_home.url(),
+                    "lines": graders.iter().map(|g| format!("• {}", g)).collect::<Vec<String>>(),
+                }),
+            ),
+        }),
+    )
+    .await;
+
+    Ok(format!("closed the {} take-home of {}, they {}", take_home.assignment, applicant.email, take_home.submission()))
+}
+
+/// Send a candidate a take-home: make them a private repository from the assignment
+/// and invite them to it until the deadline. Returns what was done.
+#[instrument(skip(db, github, config))]
+#[inline]
+pub async fn send_take_home(db: &Database, github: &Github, config: &Config, assignment: &str, email: &str, days: Option<i64>) -> Result<String, String> {
+    let take_home_config = config.take_homes.get(assignment).ok_or_else(|| {
+        format!(
+            "`{}` is not one of our take-homes, which are {}",
+            assignment,
+            config.take_homes.keys().cloned().collect::<Vec<_>>().join(", ")
+        )
+    })?;
+    let applicant = latest_application(db, email)?;
+    let login = applicant.github.trim().trim_start_matches('@').to_string();
+    if login.is_empty() {
+        return Err(format!("{} did not give us their GitHub handle, add it to their application first", email));
+    }
+
+    let existing = take_home_assignments::table
+        .filter(take_home_assignments::applicant_id.eq(applicant.id))
+        .filter(take_home_assignments::assignment.eq(assignment.to_string()))
+        .first::<TakeHomeAssignment>(&db.conn());
+    if let Ok(existing) = existing {
+        return Err(format!("we already sent {} the {} take-home, in {}", email, assignment, existing.url()));
+    }
+
+    let org = github_org();
+    let repo = candidate_repo_name(assignment, &login);
+    github_api_post::<Value>(
+        &format!("/repos/{}/{}/generate", org, take_home_config.repo),
+        &json!({
+            "owner": org,
+            "name": repo,
+            "description": format!("The {} take-home of {}", assignment, applicant.name),
+            "private": true,
+        }),
+    )
+    .await
+    .map_err(|e| format!("making {} from {} failed: {}", repo, take_home_config.repo, e))?;
+    github
+        .repo(org.to_string(), repo.to_string())
+        .collaborators()
+        .add(&login, &Permissions::Push)
+        .await
+        .map_err(|e| format!("inviting {} to {} failed: {}", login, repo, e))?;
+
+    let sent_at = Utc::now();
+    let time_allowed = match days {
+        Some(d) if d > 0 => Duration::days(d),
+        _ => take_home_config.time_allowed(),
+    };
+    let take_home: TakeHomeAssignment = diesel::insert_into(take_home_assignments::table)
+        .values(&NewTakeHomeAssignment {
+            applicant_id: applicant.id,
+            assignment: assignment.to_string(),
+            repo,
+            github: login,
+            sent_at,
+            due_at: sent_at + time_allowed,
+            submitted_at: None,
+            revoked_at: None,
+        })
+        .get_result(&db.conn())
+        .map_err(|e| format!("saving the take-home of {} failed: {}", email, e))?;
+    diesel::update(applicants::dsl::applicants.find(applicant.id))
+        .set(applicants::dsl::take_home_repo.eq(take_home.url()))
+        .execute(&db.conn())
+        .map_err(|e| format!("updating the application of {} failed: {}", email, e))?;
+
+    Ok(format!("invited @{} to {}, their access ends at {}", take_home.github, take_home.url(), format_time(take_home.due_at)))
+}
+
+/// Close the take-home of a candidate who told us they are done before the deadline.
+/// Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn submit_take_home(db: &Database, config: &Config, email: &str) -> Result<String, String> {
+    let (applicant, mut take_home) = latest_take_home(db, email)?;
+    if take_home.revoked_at.is_some() {
+        return Err(format!("the {} take-home of {} is already closed", take_home.assignment, email));
+    }
+
+    take_home.submitted_at = Some(Utc::now());
+    close_take_home(db, config, &applicant, take_home).await
+}
+
+/// Close the take-homes that are past their deadline.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn close_overdue_take_homes(db: &Database, config: &Config) {
+    let overdue: Vec<TakeHomeAssignment> = take_home_assignments::table
+        .filter(take_home_assignments::revoked_at.is_null())
+        .filter(take_home_assignments::due_at.le(Utc::now()))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the overdue take-homes failed: {}", e));
+
+    for take_home in overdue {
+        let applicant = match applicants::dsl::applicants.find(take_home.applicant_id).first::<Applicant>(&db.conn()) {
+            Ok(a) => a,
+            Err(e) => {
+                println!("[take_homes] applicant {} does not exist: {}", take_home.applicant_id, e);
+                continue;
+            }
+        };
+        match close_take_home(db, config, &applicant, take_home).await {
+            Ok(done) => println!("[take_homes] {}", done),
+            Err(e) => println!("[take_homes] {}", e),
+        }
+    }
+}
+
+/// Record the score a grader gave the take-home of a candidate and update the average
+/// on their application. Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn grade_take_home(db: &Database, config: &Config, email: &str, grader: &str, score: i32, notes: &str) -> Result<String, String> {
+    let (min, max) = TAKE_HOME_SCORES;
+    if score < min || score > max {
+        return Err(format!("the score must be from {} to {}", min, max));
+    }
+    let (applicant, take_home) = latest_take_home(db, email)?;
+    let graders = config.take_homes.get(&take_home.assignment).map(|t| t.graders.clone()).unwrap_or_default();
+    if !graders.is_empty() && !graders.iter().any(|g| g == grader) {
+        return Err(format!("{} does not grade the {} take-home, {} do", grader, take_home.assignment, graders.join(", ")));
+    }
+
+    let grade = NewTakeHomeGrade {
+        assignment_id: take_home.id,
+        grader: grader.to_string(),
+        score,
+        notes: notes.to_string(),
+        graded_at: Utc::now(),
+    };
+    diesel::insert_into(take_home_grades::table)
+        .values(&grade)
+        .on_conflict((take_home_grades::assignment_id, take_home_grades::grader))
+        .do_update()
+        .set(&grade)
+        .execute(&db.conn())
+        .map_err(|e| format!("saving the grade of {} failed: {}", grader, e))?;
+
+    let grades: Vec<TakeHomeGrade> = take_home_grades::table
+        .filter(take_home_grades::assignment_id.eq(take_home.id))
+        .load(&db.conn())
+        .map_err(|e| format!("getting the grades of {} failed: {}", email, e))?;
+    let average = average_score(&grades);
+    diesel::update(applicants::dsl::applicants.find(applicant.id))
+        .set(applicants::dsl::take_home_score.eq(average))
+        .execute(&db.conn())
+        .map_err(|e| format!("updating the application of {} failed: {}", email, e))?;
+
+    Ok(format!(
+        "{} gave the {} take-home of {} a {}, it now averages {:.1} from {} graders",
+        grader,
+        take_home.assignment,
+        email,
+        score,
+        average,
+        grades.len()
+    ))
+}
+
+/// Get the take-homes we sent, oldest first, with the email of the candidate and the
+/// grades.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_take_homes(db: &Database) -> Vec<(String, TakeHomeAssignment, Vec<TakeHomeGrade>)> {
+    let take_homes: Vec<TakeHomeAssignment> = take_home_assignments::table
+        .order_by(take_home_assignments::sent_at)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the take-homes failed: {}", e));
+    let grades: Vec<TakeHomeGrade> = take_home_grades::table.load(&db.conn()).unwrap_or_else(|e| panic!("getting the take-home grades failed: {}", e));
+    let emails: BTreeMap<i32, String> = applicants::dsl::applicants
+        .select((applicants::dsl::id, applicants::dsl::email))
+        .load::<(i32, String)>(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the applicants failed: {}", e))
+        .into_iter()
+        .collect();
+
+    take_homes
+        .into_iter()
+        .map(|t| {
+            let email = emails.get(&t.applicant_id).cloned().unwrap_or_else(|| t.applicant_id.to_string());
+            let g = grades.iter().filter(|g| g.assignment_id == t.id).cloned().collect();
+            (email, t, g)
+        })
+        .collect()
+}
+
+/// Format the take-homes we sent for the command line, one per line.
+#[instrument(skip(take_homes))]
+#[inline]
+pub fn format_take_homes(take_homes: &[(String, TakeHomeAssignment, Vec<TakeHomeGrade>)]) -> String {
+    let mut text = String::new();
+    if take_homes.is_empty() {
+        text += "We have not sent any take-homes yet.\n";
+    }
+    for (email, take_home, grades) in take_homes {
+        let state = if take_home.revoked_at.is_some() {
+            format!("closed, they {}", take_home.submission())
+        } else {
+            format!("open until {}", format_time(take_home.due_at))
+        };
+        let graded = if grades.is_empty() {
+            "not graded".to_string()
+        } else {
+            format!(
+                "{} (average {:.1})",
+                grades.iter().map(|g| format!("{} gave {}", g.grader, g.score)).collect::<Vec<String>>().join(", "),
+                average_score(grades)
+            )
+        };
+        text += &format!("{:<10} {}  {}, {}\n    {}\n", take_home.assignment, email, state, graded, take_home.repo);
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::take_homes::{average_score, candidate_repo_name, TakeHomeConfig, TakeHomeGrade};
+
+    #[test]
+    fn test_candidate_repo_name() {
+        assert_eq!(candidate_repo_name("firmware", "@JaneDoe"), "firmware-janedoe");
+        assert_eq!(candidate_repo_name("firmware", " jane.doe "), "firmware-jane-doe");
+    }
+
+    #[test]
+    fn test_average_score() {
+        let grade = |score: i32| TakeHomeGrade {
+            id: 0,
+            assignment_id: 1,
+            grader: "jane".to_string(),
+            score,
+            notes: "".to_string(),
+            graded_at: Utc.ymd(2021, 5, 12).and_hms(17, 0, 0),
+        };
+        assert_eq!(average_score(&[]), 0.0);
+        assert_eq!(average_score(&[grade(4)]), 4.0);
+        assert_eq!(average_score(&[grade(4), grade(3)]), 3.5);
+    }
+
+    #[test]
+    fn test_time_allowed() {
+        assert_eq!(TakeHomeConfig::default().time_allowed().num_days(), 7);
+        let config = TakeHomeConfig { days: 3, ..Default::default() };
+        assert_eq!(config.time_allowed().num_days(), 3);
+    }
+}
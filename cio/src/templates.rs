@@ -1,12 +1,13 @@
 use std::collections::BTreeMap;
 
+use comrak::{markdown_to_html, ComrakOptions};
 use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext};
 use hubcaps::repositories::Repository;
 use hubcaps::Github;
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use crate::configs::{Groups, UserConfig, Users};
+use crate::configs::{Groups, OpenRole, OpenRoles, RepoOwners, UserConfig, Users};
 use crate::db::Database;
 use crate::shorturls::ShortUrl;
 use crate::utils::{create_or_update_file_in_github_repo, github_org};
@@ -147,6 +148,95 @@ pub async fn generate_terraform_files_for_aws_and_github(github: &Github, users:
     }
 }
 
+/// Generate the `CODEOWNERS` file for each repository that has an owner in the
+/// configs repo. The file is saved in the `.github` directory on the default branch.
+#[instrument(skip(db))]
+#[inline]
+pub async fn generate_codeowners_for_repos(github: &Github, db: &Database) {
+    let github_org = github_org();
+
+    // Initialize handlebars.
+    let handlebars = Handlebars::new();
+
+    for owner in RepoOwners::get_from_db(db) {
+        if owner.team.is_empty() && owner.owners.is_empty() {
+            // We have nobody to put in the file.
+            continue;
+        }
+
+        let repo = github.repo(github_org.to_string(), owner.repo.to_string());
+        let r = match repo.get().await {
+            Ok(r) => r,
+            Err(e) => {
+                println!("[codeowners] getting repo {} failed: {}", owner.repo, e);
+                continue;
+            }
+        };
+
+        let rendered = handlebars
+            .render_template(&TEMPLATE_CODEOWNERS, &json!({"org": github_org, "team": owner.team, "owners": owner.owners}))
+            .unwrap();
+
+        create_or_update_file_in_github_repo(&repo, &r.default_branch, ".github/CODEOWNERS", rendered.as_bytes().to_vec()).await;
+    }
+}
+
+/// The repository for our website, the careers page is generated into it.
+pub static CAREERS_PAGE_REPO: &str = "website";
+
+/// A role as it is shown on the careers page.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CareersPageRole {
+    pub name: String,
+    pub team: String,
+    pub location: String,
+    /// The description of the role rendered as HTML.
+    pub description: String,
+    pub job_description_url: String,
+    pub application_form_url: String,
+}
+
+/// Get the roles to show on the careers page, which are only the open roles.
+#[instrument(skip(roles))]
+#[inline]
+pub fn get_careers_page_roles(roles: Vec<OpenRole>) -> Vec<CareersPageRole> {
+    let mut page_roles: Vec<CareersPageRole> = roles
+        .into_iter()
+        .filter(|r| r.open)
+        .map(|r| CareersPageRole {
+            description: markdown_to_html(&r.description, &ComrakOptions::default()).trim().to_string(),
+            name: r.name,
+            team: r.team,
+            location: r.location,
+            job_description_url: r.job_description_url,
+            application_form_url: r.application_form_url,
+        })
+        .collect();
+    page_roles.sort_by(|a, b| a.name.cmp(&b.name));
+
+    page_roles
+}
+
+/// Generate the careers page for the website from the open roles, as both JSON
+/// and HTML, so the roles on the site and the forms they link to always match
+/// the roles we are hiring for.
+#[instrument(skip(db))]
+#[inline]
+pub async fn generate_careers_page(github: &Github, db: &Database) {
+    let roles = get_careers_page_roles(OpenRoles::get_from_db(db).into());
+
+    let repo = github.repo(github_org(), CAREERS_PAGE_REPO);
+    let r = repo.get().await.unwrap();
+
+    let json_rendered = serde_json::to_string_pretty(&json!({ "roles": roles })).unwrap();
+    create_or_update_file_in_github_repo(&repo, &r.default_branch, "careers/roles.json", json_rendered.as_bytes().to_vec()).await;
+
+    // Initialize handlebars.
+    let handlebars = Handlebars::new();
+    let html_rendered = handlebars.render_template(&TEMPLATE_CAREERS_PAGE, &roles).unwrap();
+    create_or_update_file_in_github_repo(&repo, &r.default_branch, "careers/roles.html", html_rendered.as_bytes().to_vec()).await;
+}
+
 /// Generate nginx and terraform files for shorturls.
 /// This is used for short URL link generation like:
 ///   - {link}.corp.oxide.computer
@@ -230,6 +320,26 @@ pub static TEMPLATE_WARNING: &str = "# THIS FILE HAS BEEN GENERATED BY THE CIO R
 # Instead change the link in configs/links.toml
 ";
 
+/// Template for the CODEOWNERS file in each repository.
+pub static TEMPLATE_CODEOWNERS: &str = r#"# THIS FILE HAS BEEN GENERATED BY THE CIO REPO
+# AND SHOULD NEVER BE EDITED BY HAND!!
+# Instead change the owners in configs/repos.toml
+* {{#if this.team}}@{{this.org}}/{{this.team}}{{/if}}{{#each this.owners}} @{{this}}{{/each}}
+"#;
+
+/// Template for the list of roles on the careers page.
+pub static TEMPLATE_CAREERS_PAGE: &str = r#"<!-- THIS FILE HAS BEEN GENERATED BY THE CIO REPO AND SHOULD NEVER BE EDITED BY HAND!! -->
+<!-- Instead change the roles in configs/roles.toml -->
+{{#if this}}<ul class="roles">
+{{#each this}}  <li class="role">
+    <h3>{{this.name}}</h3>
+    <p class="role-details">{{#if this.team}}{{this.team}}{{/if}}{{#if this.location}} | {{this.location}}{{/if}}</p>
+    {{{this.description}}}
+    <p>{{#if this.job_description_url}}<a href="{{this.job_description_url}}">Read the job description</a> | {{/if}}<a href="{{this.application_form_url}}">Apply</a></p>
+  </li>
+{{/each}}</ul>{{else}}<p class="roles">We are not hiring for any roles right now.</p>{{/if}}
+"#;
+
 /// Template for creating nginx conf files for the subdomain urls.
 pub static TEMPLATE_NGINX: &str = r#"{{#each this}}
 # Redirect {{this.link}} to {{this.name}}.{{this.subdomain}}.oxide.computer
@@ -391,3 +501,45 @@ resource "okta_group" "{{terraformize this.name}}" {
 }
 {{/if}}{{/each}}
 "#;
+
+#[cfg(test)]
+mod tests {
+    use handlebars::Handlebars;
+
+    use crate::configs::OpenRole;
+    use crate::templates::{get_careers_page_roles, CareersPageRole, TEMPLATE_CAREERS_PAGE};
+
+    #[test]
+    fn test_careers_page() {
+        let role = |name: &str, open: bool| OpenRole {
+            id: 1,
+            name: name.to_string(),
+            team: "eng".to_string(),
+            location: "Remote".to_string(),
+            description: "Build *computers*.".to_string(),
+            job_description_url: "".to_string(),
+            application_form_url: "https://forms.gle/abc".to_string(),
+            sheet_id: "1FHA".to_string(),
+            open,
+            airtable_record_id: "".to_string(),
+            source: "configs".to_string(),
+            last_synced_from: "configs".to_string(),
+            last_synced_at: None,
+            extra: Default::default(),
+            modified_at: None,
+        };
+
+        let roles = get_careers_page_roles(vec![role("Software Engineer", true), role("Sales", false), role("Hardware Engineer", true)]);
+        assert_eq!(roles.iter().map(|r| r.name.as_str()).collect::<Vec<&str>>(), vec!["Hardware Engineer", "Software Engineer"]);
+        assert_eq!(roles[0].description, "<p>Build <em>computers</em>.</p>");
+
+        let html = Handlebars::new().render_template(TEMPLATE_CAREERS_PAGE, &roles).unwrap();
+        assert!(html.contains("<h3>Hardware Engineer</h3>"));
+        assert!(html.contains("<p>Build <em>computers</em>.</p>"));
+        assert!(html.contains(r#"<a href="https://forms.gle/abc">Apply</a>"#));
+        assert!(!html.contains("Sales"));
+
+        let html = Handlebars::new().render_template(TEMPLATE_CAREERS_PAGE, &Vec::<CareersPageRole>::new()).unwrap();
+        assert!(html.contains("We are not hiring for any roles right now."));
+    }
+}
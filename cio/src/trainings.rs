@@ -0,0 +1,578 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::io::Read;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Duration, Utc};
+use diesel::{QueryDsl, RunQueryDsl};
+use reqwest::{Client, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::configs::{Config, UserConfig};
+use crate::db::Database;
+use crate::format::format_date;
+use crate::import::{format_import_report, CsvRecords, ImportError, ImportMapping, ImportReport};
+use crate::messages::render_message;
+use crate::notifications::notify_user;
+use crate::schema::training_completions;
+use crate::utils::{default_date, GSUITE_DOMAIN};
+
+/// How we track the trainings everyone has to take, from the `trainings` section of our
+/// configs. Completions come from the reports of our training vendor, either a CSV
+/// exported by hand and imported with `cio trainings import` or the report the
+/// `trainings` job downloads from `report_url` each day.
+///
+/// ```toml
+/// [trainings]
+/// report_url = "https://training.example.com/api/reports/completions.csv"
+/// escalate_days = 7
+///
+/// [trainings.mapping.columns]
+/// email = "Learner Email"
+/// course = "Course Name"
+/// completed_at = "Completion Date"
+///
+/// [trainings.required.security_awareness]
+/// title = "Security awareness"
+/// courses = ["Security Awareness 2021", "Security Awareness 2020"]
+/// due_days = 30
+/// every_days = 365
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct TrainingsConfig {
+    /// The URL of the vendor's completion report as a CSV, downloaded with the
+    /// `TRAINING_VENDOR_API_KEY` as a bearer token.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub report_url: String,
+    /// How the columns of the vendor's report map to `email`, `course`, and
+    /// `completed_at`. Without columns, the columns named after them are used.
+    #[serde(default)]
+    pub mapping: ImportMapping,
+    /// How many days a training is overdue before we also tell the person's manager,
+    /// from the managers in the `reviews` section. Defaults to 7.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalate_days: Option<i64>,
+    #[serde(default)]
+    pub required: BTreeMap<String, RequiredTraining>,
+}
+
+impl TrainingsConfig {
+    /// Get how many days a training is overdue before we tell the person's manager.
+    #[instrument]
+    #[inline]
+    pub fn escalate_days(&self) -> i64 {
+        self.escalate_days.unwrap_or(7)
+    }
+
+    /// Get the name of the training a course in the vendor's reports counts towards.
+    #[instrument]
+    #[inline]
+    pub fn training_for_course(&self, course: &str) -> Option<&str> {
+        self.required
+            .iter()
+            .find(|(_, t)| t.courses.iter().any(|c| c.trim().eq_ignore_ascii_case(course.trim())))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// A training people have to take.
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct RequiredTraining {
+    pub title: String,
+    /// The names of the course in the vendor's reports. Taking any of them counts, so a
+    /// course the vendor renames each year still counts.
+    #[serde(default)]
+    pub courses: Vec<String>,
+    /// Where to take the training, for the reminders.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    /// The groups whose members have to take it, everyone if there are none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// How many days after they start people have to take it by.
+    #[serde(default)]
+    pub due_days: i64,
+    /// How many days after taking it people have to take it again, if they do.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every_days: Option<i64>,
+    /// The day we started requiring it. People who started before have `due_days` from
+    /// this day instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<NaiveDate>,
+}
+
+impl RequiredTraining {
+    /// Returns if someone has to take the training.
+    #[instrument]
+    #[inline]
+    pub fn applies_to(&self, user: &UserConfig) -> bool {
+        user.typev != "system account" && (self.groups.is_empty() || self.groups.iter().any(|g| user.groups.contains(g)))
+    }
+
+    /// Get the day someone has to take the training by, from the day they started and
+    /// the last time they took it. Returns `None` once they do not have to take it again,
+    /// or if we do not know when they started.
+    #[instrument]
+    #[inline]
+    pub fn due_on(&self, start_date: NaiveDate, last_completed: Option<NaiveDate>) -> Option<NaiveDate> {
+        if let Some(last) = last_completed {
+            return self.every_days.map(|d| last + Duration::days(d));
+        }
+
+        let start = if start_date == default_date() {
+            self.since?
+        } else {
+            self.since.map(|s| s.max(start_date)).unwrap_or(start_date)
+        };
+        Some(start + Duration::days(self.due_days))
+    }
+}
+
+/// Where someone is with a training.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingStatus {
+    pub training: String,
+    pub title: String,
+    pub url: String,
+    pub username: String,
+    pub last_completed: Option<NaiveDate>,
+    pub due_on: Option<NaiveDate>,
+}
+
+impl TrainingStatus {
+    /// Returns if they have taken it and do not have to take it again yet.
+    #[instrument]
+    #[inline]
+    pub fn is_done(&self, today: NaiveDate) -> bool {
+        self.last_completed.is_some() && self.due_on.map(|d| today <= d).unwrap_or(true)
+    }
+
+    /// How many days it is overdue, negative if it is not due yet.
+    #[instrument]
+    #[inline]
+    pub fn days_overdue(&self, today: NaiveDate) -> Option<i64> {
+        self.due_on.map(|d| (today - d).num_days())
+    }
+
+    /// Returns if we remind them of it today: a week before it is due, the day it is due,
+    /// and every week it is overdue. The `trainings` job runs once a day.
+    #[instrument]
+    #[inline]
+    pub fn remind_on(&self, today: NaiveDate) -> bool {
+        match self.days_overdue(today) {
+            Some(days) => days == -7 || (days >= 0 && days % 7 == 0),
+            None => false,
+        }
+    }
+
+    /// Returns if we tell their manager about it today: once it is `escalate_days`
+    /// overdue and every week after that.
+    #[instrument]
+    #[inline]
+    pub fn escalate_on(&self, today: NaiveDate, escalate_days: i64) -> bool {
+        match self.days_overdue(today) {
+            Some(days) => days > 0 && days >= escalate_days && (days - escalate_days) % 7 == 0,
+            None => false,
+        }
+    }
+
+    /// Describe where they are with it, ie. `overdue since 2021-05-03`.
+    #[instrument]
+    #[inline]
+    pub fn state(&self, today: NaiveDate) -> String {
+        match (self.days_overdue(today), self.due_on) {
+            (Some(days), Some(due)) if days > 0 => format!("overdue since {}", format_date(due)),
+            (_, Some(due)) => format!("due by {}", format_date(due)),
+            (_, None) => match self.last_completed {
+                Some(last) => format!("taken {}", format_date(last)),
+                None => "not taken yet".to_string(),
+            },
+        }
+    }
+
+    /// Describe where they are with it for a message, linking to the training.
+    #[instrument]
+    #[inline]
+    pub fn describe(&self, today: NaiveDate) -> String {
+        let title = if self.url.is_empty() { self.title.to_string() } else { format!("<{}|{}>", self.url, self.title) };
+        format!("{}, {}", title, self.state(today))
+    }
+}
+
+/// Get where everyone is with each of the trainings they have to take, from the last time
+/// they took each one by training and username.
+#[instrument(skip(users, last_completed))]
+#[inline]
+pub fn training_statuses(config: &TrainingsConfig, users: &BTreeMap<String, UserConfig>, last_completed: &BTreeMap<(String, String), NaiveDate>) -> Vec<TrainingStatus> {
+    let mut statuses = vec![];
+    for (name, training) in config.required.iter() {
+        for user in users.values().filter(|u| training.applies_to(u)) {
+            let last = last_completed.get(&(name.to_string(), user.username.to_string())).cloned();
+            statuses.push(TrainingStatus {
+                training: name.to_string(),
+                title: training.title.to_string(),
+                url: training.url.to_string(),
+                username: user.username.to_string(),
+                last_completed: last,
+                due_on: training.due_on(user.start_date, last),
+            });
+        }
+    }
+
+    statuses
+}
+
+/// Get the username of someone in our directory from their email, by their username or
+/// one of their aliases.
+#[instrument(skip(users))]
+#[inline]
+pub fn username_for_email(users: &BTreeMap<String, UserConfig>, email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let local = email.strip_suffix(&format!("@{}", GSUITE_DOMAIN))?;
+    users.values().find(|u| u.username == local || u.aliases.iter().any(|a| a == local)).map(|u| u.username.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "training_completions"]
+pub struct NewTrainingCompletion {
+    pub training: String,
+    pub username: String,
+    /// The name of the course in the vendor's report.
+    pub course: String,
+    pub completed_at: DateTime<Utc>,
+    /// Where we got it from, `csv` for a report imported by hand or `api` for the report
+    /// the job downloads.
+    pub source: String,
+    pub imported_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct TrainingCompletion {
+    pub id: i32,
+    pub training: String,
+    pub username: String,
+    pub course: String,
+    pub completed_at: DateTime<Utc>,
+    pub source: String,
+    pub imported_at: DateTime<Utc>,
+}
+
+/// A row of the vendor's completion report. Rows for courses that are not finished yet
+/// have no `completed_at`.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize)]
+pub struct TrainingReportRow {
+    pub email: String,
+    pub course: String,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Import the completions from a report from the training vendor. Rows for courses we do
+/// not require or that are not finished are skipped, and completions we already have are
+/// left alone, so the same report can be imported again.
+#[instrument(skip(db, config, csv))]
+#[inline]
+pub fn import_training_completions<R: Read>(db: &Database, config: &Config, csv: R, mapping: &ImportMapping, source: &str) -> Result<ImportReport, String> {
+    let records = CsvRecords::<TrainingReportRow, _>::new(csv, mapping)?;
+    let mut report = ImportReport {
+        table: "training_completions".to_string(),
+        ..Default::default()
+    };
+
+    let imported_at = Utc::now();
+    for row in records {
+        let (line, row) = match row {
+            Ok(r) => r,
+            Err(e) => {
+                report.add_error(e);
+                continue;
+            }
+        };
+        let (training, completed_at) = match (config.trainings.training_for_course(&row.course), row.completed_at) {
+            (Some(t), Some(c)) => (t, c),
+            _ => continue,
+        };
+        let username = match username_for_email(&config.users, &row.email) {
+            Some(u) => u,
+            None => {
+                report.add_error(ImportError {
+                    line,
+                    message: format!("`{}` is not anyone in our directory", row.email),
+                });
+                continue;
+            }
+        };
+
+        let created = diesel::insert_into(training_completions::table)
+            .values(&NewTrainingCompletion {
+                training: training.to_string(),
+                username: username.to_string(),
+                course: row.course.trim().to_string(),
+                completed_at,
+                source: source.to_string(),
+                imported_at,
+            })
+            .on_conflict_do_nothing()
+            .execute(&db.conn())
+            .map_err(|e| format!("saving the {} completion of {} failed: {}", training, username, e))?;
+        if created > 0 {
+            report.created += 1;
+        } else {
+            report.unchanged += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Download the completion report from the training vendor.
+#[instrument]
+#[inline]
+pub async fn download_training_report(config: &TrainingsConfig) -> Result<Vec<u8>, String> {
+    let key = env::var("TRAINING_VENDOR_API_KEY").unwrap_or_default();
+    if key.is_empty() {
+        return Err("TRAINING_VENDOR_API_KEY is not set, not downloading the training report".to_string());
+    }
+
+    let resp = Client::new()
+        .get(&config.report_url)
+        .bearer_auth(key)
+        .send()
+        .await
+        .map_err(|e| format!("downloading the training report failed: {}", e))?;
+    match resp.status() {
+        StatusCode::OK => (),
+        s => return Err(format!("downloading the training report failed, status: {}", s)),
+    }
+
+    resp.bytes().await.map(|b| b.to_vec()).map_err(|e| format!("reading the training report failed: {}", e))
+}
+
+/// Get the last time each person took each training, by training and username.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_last_completions(db: &Database) -> BTreeMap<(String, String), NaiveDate> {
+    let completions: Vec<(String, String, DateTime<Utc>)> = training_completions::table
+        .select((training_completions::training, training_completions::username, training_completions::completed_at))
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the training completions failed: {}", e));
+
+    let mut last: BTreeMap<(String, String), NaiveDate> = Default::default();
+    for (training, username, completed_at) in completions {
+        let day = completed_at.date().naive_utc();
+        let entry = last.entry((training, username)).or_insert(day);
+        *entry = (*entry).max(day);
+    }
+
+    last
+}
+
+/// Get where everyone is with each of the trainings they have to take.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn get_training_statuses(db: &Database, config: &Config) -> Vec<TrainingStatus> {
+    training_statuses(&config.trainings, &config.users, &get_last_completions(db))
+}
+
+/// Download the vendor's report, if we have one, and remind people of the trainings they
+/// have to take. Trainings that stay overdue are escalated to their managers.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn refresh_trainings(db: &Database, config: &Config) {
+    if !config.trainings.report_url.is_empty() {
+        let imported = match download_training_report(&config.trainings).await {
+            Ok(csv) => import_training_completions(db, config, csv.as_slice(), &config.trainings.mapping, "api"),
+            Err(e) => Err(e),
+        };
+        match imported {
+            Ok(report) => print!("[trainings] {}", format_import_report(&report)),
+            Err(e) => println!("[trainings] {}", e),
+        }
+    }
+
+    let today = Utc::now().date().naive_utc();
+    let escalate_days = config.trainings.escalate_days();
+    let mut reminders: BTreeMap<String, Vec<String>> = Default::default();
+    let mut escalations: BTreeMap<String, Vec<String>> = Default::default();
+    for status in get_training_statuses(db, config) {
+        if status.remind_on(today) {
+            reminders.entry(status.username.to_string()).or_default().push(format!("• {}", status.describe(today)));
+        }
+        if status.escalate_on(today, escalate_days) {
+            escalations.entry(status.username.to_string()).or_default().push(format!("• {}", status.describe(today)));
+        }
+    }
+
+    for (username, lines) in reminders {
+        notify_user(
+            db,
+            &format!("{}@{}", username, GSUITE_DOMAIN),
+            json!({
+                "text": render_message("trainings.reminder", &json!({ "lines": lines })),
+            }),
+        )
+        .await;
+    }
+
+    for (username, lines) in escalations {
+        let manager = match config.reviews.managers.get(&username) {
+            Some(m) => m,
+            None => {
+                println!("[trainings] {} has overdue trainings but no manager to tell", username);
+                continue;
+            }
+        };
+        let name = config.users.get(&username).map(|u| format!("{} {}", u.first_name, u.last_name)).unwrap_or_else(|| username.to_string());
+        notify_user(
+            db,
+            &format!("{}@{}", manager, GSUITE_DOMAIN),
+            json!({
+                "text": render_message("trainings.escalation", &json!({ "name": name, "lines": lines })),
+            }),
+        )
+        .await;
+    }
+}
+
+/// Format where everyone is with the trainings they have to take for the command line,
+/// listing who has not taken each one.
+#[instrument(skip(statuses))]
+#[inline]
+pub fn format_training_statuses(statuses: &[TrainingStatus], today: NaiveDate) -> String {
+    let mut by_training: BTreeMap<&str, Vec<&TrainingStatus>> = Default::default();
+    for status in statuses.iter() {
+        by_training.entry(&status.training).or_default().push(status);
+    }
+
+    let mut text = String::new();
+    for (_, statuses) in by_training {
+        let done = statuses.iter().filter(|s| s.is_done(today)).count();
+        let overdue = statuses.iter().filter(|s| s.days_overdue(today).map(|d| d > 0).unwrap_or(false)).count();
+        text += &format!("{}: {} of {} done, {} overdue\n", statuses[0].title, done, statuses.len(), overdue);
+        for status in statuses.iter().filter(|s| !s.is_done(today)) {
+            text += &format!("  {}: {}\n", status.username, status.state(today));
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::naive::NaiveDate;
+
+    use crate::configs::UserConfig;
+    use crate::trainings::{format_training_statuses, training_statuses, username_for_email, RequiredTraining, TrainingsConfig};
+    use crate::utils::default_date;
+
+    #[test]
+    fn test_training_due_on() {
+        let training: RequiredTraining = toml::from_str(
+            r#"title = "Security awareness"
+courses = ["Security Awareness 2021"]
+due_days = 30
+every_days = 365
+since = "2021-03-01"
+"#,
+        )
+        .unwrap();
+
+        // People who started before we required it have from when we did.
+        assert_eq!(training.due_on(NaiveDate::from_ymd(2019, 6, 1), None), Some(NaiveDate::from_ymd(2021, 3, 31)));
+        assert_eq!(training.due_on(NaiveDate::from_ymd(2021, 5, 3), None), Some(NaiveDate::from_ymd(2021, 6, 2)));
+        assert_eq!(training.due_on(default_date(), None), Some(NaiveDate::from_ymd(2021, 3, 31)));
+        assert_eq!(
+            training.due_on(NaiveDate::from_ymd(2019, 6, 1), Some(NaiveDate::from_ymd(2021, 3, 15))),
+            Some(NaiveDate::from_ymd(2022, 3, 15))
+        );
+
+        let once = RequiredTraining { due_days: 60, ..Default::default() };
+        assert_eq!(once.due_on(NaiveDate::from_ymd(2021, 5, 3), Some(NaiveDate::from_ymd(2021, 5, 10))), None);
+        assert_eq!(once.due_on(default_date(), None), None);
+    }
+
+    #[test]
+    fn test_training_statuses() {
+        let config: TrainingsConfig = toml::from_str(
+            r#"[required.harassment_prevention]
+title = "Harassment prevention"
+courses = ["Harassment Prevention"]
+due_days = 30
+
+[required.security_awareness]
+title = "Security awareness"
+courses = ["Security Awareness 2021", "Security Awareness 2020"]
+url = "https://training.example.com/security"
+groups = ["eng"]
+due_days = 14
+every_days = 365
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.training_for_course(" security awareness 2020"), Some("security_awareness"));
+        assert_eq!(config.training_for_course("Phishing 101"), None);
+
+        let users: BTreeMap<String, UserConfig> = toml::from_str(
+            r#"[jane]
+first_name = "Jane"
+last_name = "Doe"
+username = "jane"
+aliases = ["jd"]
+groups = ["eng"]
+start_date = "2021-04-01"
+
+[sam]
+first_name = "Sam"
+last_name = "Smith"
+username = "sam"
+start_date = "2021-05-03"
+
+[builds]
+first_name = "Build"
+last_name = "Bot"
+username = "builds"
+type = "system account"
+"#,
+        )
+        .unwrap();
+        assert_eq!(username_for_email(&users, "JD@oxidecomputer.com"), Some("jane".to_string()));
+        assert_eq!(username_for_email(&users, "jane@example.com"), None);
+
+        let mut last_completed: BTreeMap<(String, String), NaiveDate> = Default::default();
+        last_completed.insert(("harassment_prevention".to_string(), "jane".to_string()), NaiveDate::from_ymd(2021, 4, 20));
+
+        let today = NaiveDate::from_ymd(2021, 5, 20);
+        let statuses = training_statuses(&config, &users, &last_completed);
+        let due: Vec<(&str, &str, Option<NaiveDate>)> = statuses.iter().map(|s| (s.training.as_str(), s.username.as_str(), s.due_on)).collect();
+        assert_eq!(
+            due,
+            vec![
+                ("harassment_prevention", "jane", None),
+                ("harassment_prevention", "sam", Some(NaiveDate::from_ymd(2021, 6, 2))),
+                ("security_awareness", "jane", Some(NaiveDate::from_ymd(2021, 4, 15))),
+            ]
+        );
+
+        // Jane's is 35 days overdue, so we remind her every week and tell her manager
+        // every week from 7 days overdue.
+        assert!(statuses[2].remind_on(today));
+        assert!(statuses[2].escalate_on(today, 7));
+        assert!(!statuses[2].escalate_on(today, 10));
+        assert!(!statuses[1].remind_on(today));
+        assert!(statuses[1].remind_on(NaiveDate::from_ymd(2021, 5, 26)));
+        assert!(!statuses[1].escalate_on(NaiveDate::from_ymd(2021, 6, 2), 0));
+
+        assert_eq!(
+            format_training_statuses(&statuses, today),
+            "Harassment prevention: 1 of 2 done, 0 overdue
+  sam: due by 2021-06-02
+Security awareness: 0 of 1 done, 1 overdue
+  jane: overdue since 2021-04-15
+"
+        );
+    }
+}
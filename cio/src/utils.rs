@@ -9,39 +9,48 @@ use std::thread;
 use std::time;
 
 use futures_util::stream::TryStreamExt;
-use hubcaps::http_cache::FileBasedCache;
+use google_drive::GoogleDrive;
+use gsuite_api::GSuite;
+use hubcaps::http_cache::{FileBasedCache, HttpCache};
 use hubcaps::issues::Issue;
 use hubcaps::repositories::{OrgRepoType, OrganizationRepoListOptions, Repository};
 use hubcaps::{Credentials, Github, InstallationTokenGenerator, JWTCredentials};
 use reqwest::get;
-use reqwest::Client;
+use reqwest::{Client, Method};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use sheets::Sheets;
 use tracing::instrument;
 use yup_oauth2::{read_service_account_key, AccessToken, ServiceAccountAuthenticator};
 
 use crate::db::Database;
+use crate::job_runs::count_api_call;
 use crate::models::{GithubRepo, GithubRepos, NewRepo};
+use crate::rate_limits::{check_github_rate_limit, check_throttled};
+use crate::vcr;
 
 pub static DOMAIN: &str = "oxide.computer";
 pub static GSUITE_DOMAIN: &str = "oxidecomputer.com";
 
 /// Write a file.
-#[instrument]
+#[instrument(skip(contents))]
 #[inline]
-pub fn write_file(file: &Path, contents: &str) {
+pub fn write_file<C: AsRef<[u8]>>(file: &Path, contents: C) {
     // create each directory.
     fs::create_dir_all(file.parent().unwrap()).unwrap();
 
     // Write to the file.
     let mut f = fs::File::create(file.to_path_buf()).unwrap();
-    f.write_all(contents.as_bytes()).unwrap();
+    f.write_all(contents.as_ref()).unwrap();
 
     println!("wrote file: {}", file.to_str().unwrap());
 }
 
-/// Get a GSuite token.
+/// Get the path to the GSuite service account credentials file.
 #[instrument]
 #[inline]
-pub async fn get_gsuite_token(subject: &str) -> AccessToken {
+pub fn get_gsuite_credential_file() -> String {
     let gsuite_key = env::var("GSUITE_KEY_ENCODED").unwrap_or_default();
     // Get the GSuite credentials file.
     let mut gsuite_credential_file = env::var("GADMIN_CREDENTIAL_FILE").unwrap_or_default();
@@ -61,6 +70,15 @@ pub async fn get_gsuite_token(subject: &str) -> AccessToken {
         gsuite_credential_file = file_path.to_str().unwrap().to_string();
     }
 
+    gsuite_credential_file
+}
+
+/// Get a GSuite token.
+#[instrument]
+#[inline]
+pub async fn get_gsuite_token(subject: &str) -> AccessToken {
+    let gsuite_credential_file = get_gsuite_credential_file();
+
     let mut gsuite_subject = env::var("GADMIN_SUBJECT").unwrap();
     if !subject.is_empty() {
         gsuite_subject = subject.to_string();
@@ -78,6 +96,8 @@ pub async fn get_gsuite_token(subject: &str) -> AccessToken {
             "https://www.googleapis.com/auth/admin.directory.group",
             "https://www.googleapis.com/auth/admin.directory.resource.calendar",
             "https://www.googleapis.com/auth/admin.directory.user",
+            "https://www.googleapis.com/auth/admin.directory.user.security",
+            "https://www.googleapis.com/auth/admin.reports.audit.readonly",
             "https://www.googleapis.com/auth/calendar",
             "https://www.googleapis.com/auth/apps.groups.settings",
             "https://www.googleapis.com/auth/spreadsheets",
@@ -93,6 +113,76 @@ pub async fn get_gsuite_token(subject: &str) -> AccessToken {
     token
 }
 
+/// Create a GSuite client for our domain. Every request it sends is counted as a call
+/// to GSuite for the job that is running.
+#[instrument(skip(token))]
+#[inline]
+pub fn gsuite_client(customer: &str, token: AccessToken) -> GSuite {
+    GSuite::new(customer, GSUITE_DOMAIN, token).with_request_hook(|_| count_api_call("gsuite"))
+}
+
+/// Create a Google Drive client. Every request it sends is counted as a call to GSuite
+/// for the job that is running.
+#[instrument(skip(token))]
+#[inline]
+pub fn drive_client(token: AccessToken) -> GoogleDrive {
+    GoogleDrive::new(token).with_request_hook(|_| count_api_call("gsuite"))
+}
+
+/// Create a Google Sheets client. Every request it sends is counted as a call to GSuite
+/// for the job that is running.
+#[instrument(skip(token))]
+#[inline]
+pub fn sheets_client(token: AccessToken) -> Sheets {
+    Sheets::new(token).with_request_hook(|_| count_api_call("gsuite"))
+}
+
+/// The scope for reading the mail of a user.
+pub static GMAIL_READONLY_SCOPE: &str = "https://www.googleapis.com/auth/gmail.readonly";
+
+/// The scope for sending mail as a user.
+pub static GMAIL_SEND_SCOPE: &str = "https://www.googleapis.com/auth/gmail.send";
+
+/// Get a token for the mail of a user with one of the Gmail scopes, impersonating them.
+/// This is separate from `get_gsuite_token` since the service account needs the scope
+/// added to its domain-wide delegation, and not every job should be able to read or
+/// send mail.
+#[instrument]
+#[inline]
+pub async fn get_gmail_token(subject: &str, scope: &str) -> AccessToken {
+    let secret = read_service_account_key(get_gsuite_credential_file()).await.expect("failed to read gsuite credential file");
+    let auth = ServiceAccountAuthenticator::builder(secret)
+        .subject(subject.to_string())
+        .build()
+        .await
+        .expect("failed to create authenticator");
+
+    let token = auth.token(&[scope]).await.expect("failed to get token");
+
+    if token.as_str().is_empty() {
+        panic!("empty token is not valid");
+    }
+
+    token
+}
+
+/// Get a token for the Google Cloud APIs, ie. BigQuery, authenticated as the
+/// service account itself rather than impersonating a user.
+#[instrument]
+#[inline]
+pub async fn get_gcp_token(scopes: &[&str]) -> AccessToken {
+    let secret = read_service_account_key(get_gsuite_credential_file()).await.expect("failed to read gsuite credential file");
+    let auth = ServiceAccountAuthenticator::builder(secret).build().await.expect("failed to create authenticator");
+
+    let token = auth.token(scopes).await.expect("failed to get token");
+
+    if token.as_str().is_empty() {
+        panic!("empty token is not valid");
+    }
+
+    token
+}
+
 /// Check if a GitHub issue already exists.
 #[instrument]
 #[inline]
@@ -124,6 +214,31 @@ pub async fn get_github_user_public_ssh_keys(handle: &str) -> Vec<String> {
         .collect()
 }
 
+/// The HTTP cache for our GitHub clients. hubcaps looks up the etag in the cache for
+/// every request it sends, including each page of a list, so this is where we count
+/// our calls to GitHub for the job that is running.
+#[derive(Clone, Debug)]
+struct CountingCache(FileBasedCache);
+
+impl HttpCache for CountingCache {
+    fn cache_response(&self, uri: &str, body: &[u8], etag: &[u8], next_link: &Option<String>) -> hubcaps::Result<()> {
+        self.0.cache_response(uri, body, etag, next_link)
+    }
+
+    fn lookup_etag(&self, uri: &str) -> hubcaps::Result<String> {
+        count_api_call("github");
+        self.0.lookup_etag(uri)
+    }
+
+    fn lookup_body(&self, uri: &str) -> hubcaps::Result<String> {
+        self.0.lookup_body(uri)
+    }
+
+    fn lookup_next_link(&self, uri: &str) -> hubcaps::Result<Option<String>> {
+        self.0.lookup_next_link(uri)
+    }
+}
+
 /// Authenticate with GitHub.
 #[instrument]
 #[inline]
@@ -131,7 +246,7 @@ pub fn authenticate_github() -> Github {
     // Initialize the github client.
     let github_token = env::var("GITHUB_TOKEN").unwrap();
     // Create the HTTP cache.
-    let http_cache = Box::new(FileBasedCache::new(format!("{}/.cache/github", env::var("HOME").unwrap())));
+    let http_cache = Box::new(CountingCache(FileBasedCache::new(format!("{}/.cache/github", env::var("HOME").unwrap()))));
     Github::custom(
         "https://api.github.com",
         concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
@@ -160,7 +275,7 @@ pub fn authenticate_github_jwt() -> Github {
     let jwt = JWTCredentials::new(app_id, key.data).unwrap();
 
     // Create the HTTP cache.
-    let http_cache = Box::new(FileBasedCache::new(format!("{}/.cache/github", env::var("HOME").unwrap())));
+    let http_cache = Box::new(CountingCache(FileBasedCache::new(format!("{}/.cache/github", env::var("HOME").unwrap()))));
 
     let token_generator = InstallationTokenGenerator::new(installation_id, jwt);
 
@@ -179,6 +294,243 @@ pub fn github_org() -> String {
     env::var("GITHUB_ORG").unwrap()
 }
 
+/// Get all the pages of results for a GitHub REST API path that hubcaps does not
+/// support yet, ie. `/repos/{owner}/{repo}/code-scanning/alerts`.
+/// This authenticates with the `GITHUB_TOKEN` environment variable.
+#[instrument]
+#[inline]
+pub async fn github_api_list<T: DeserializeOwned>(path: &str) -> Result<Vec<T>, reqwest::Error> {
+    let client = Client::builder().user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"))).build()?;
+    let token = env::var("GITHUB_TOKEN").unwrap();
+
+    let per_page = 100;
+    let mut separator = "?";
+    if path.contains('?') {
+        separator = "&";
+    }
+
+    let mut results: Vec<T> = Default::default();
+    let mut page = 1;
+    loop {
+        let resp = vcr::send(
+            &client,
+            client
+                .get(&format!("https://api.github.com{}{}per_page={}&page={}", path, separator, per_page, page))
+                .header("Authorization", format!("token {}", token))
+                .header("Accept", "application/vnd.github.v3+json"),
+        )
+        .await?;
+        check_github_rate_limit(&resp);
+        let mut items: Vec<T> = resp.error_for_status()?.json().await?;
+
+        let done = items.len() < per_page;
+        results.append(&mut items);
+        if done {
+            break;
+        }
+
+        page += 1;
+    }
+
+    Ok(results)
+}
+
+/// Get a single object from a GitHub REST API path that hubcaps does not support yet.
+/// This authenticates with the `GITHUB_TOKEN` environment variable.
+#[instrument]
+#[inline]
+pub async fn github_api_get<T: DeserializeOwned>(path: &str) -> Result<T, reqwest::Error> {
+    let client = Client::builder().user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"))).build()?;
+    let token = env::var("GITHUB_TOKEN").unwrap();
+
+    let resp = vcr::send(
+        &client,
+        client
+            .get(&format!("https://api.github.com{}", path))
+            .header("Authorization", format!("token {}", token))
+            .header("Accept", "application/vnd.github.v3+json"),
+    )
+    .await?;
+    check_github_rate_limit(&resp);
+
+    resp.error_for_status()?.json().await
+}
+
+/// Update an object at a GitHub REST API path that hubcaps does not support yet.
+/// This authenticates with the `GITHUB_TOKEN` environment variable.
+#[instrument(skip(body))]
+#[inline]
+pub async fn github_api_patch<T: DeserializeOwned>(path: &str, body: &Value) -> Result<T, reqwest::Error> {
+    let client = Client::builder().user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"))).build()?;
+    let token = env::var("GITHUB_TOKEN").unwrap();
+
+    let resp = vcr::send(
+        &client,
+        client
+            .patch(&format!("https://api.github.com{}", path))
+            .header("Authorization", format!("token {}", token))
+            .header("Accept", "application/vnd.github.v3+json")
+            .json(body),
+    )
+    .await?;
+    check_github_rate_limit(&resp);
+
+    resp.error_for_status()?.json().await
+}
+
+/// Create an object at a GitHub REST API path that hubcaps does not support yet, ie.
+/// `/repos/{template_owner}/{template_repo}/generate`.
+/// This authenticates with the `GITHUB_TOKEN` environment variable.
+#[instrument(skip(body))]
+#[inline]
+pub async fn github_api_post<T: DeserializeOwned>(path: &str, body: &Value) -> Result<T, reqwest::Error> {
+    let client = Client::builder().user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"))).build()?;
+    let token = env::var("GITHUB_TOKEN").unwrap();
+
+    let resp = vcr::send(
+        &client,
+        client
+            .post(&format!("https://api.github.com{}", path))
+            .header("Authorization", format!("token {}", token))
+            .header("Accept", "application/vnd.github.v3+json")
+            .json(body),
+    )
+    .await?;
+    check_github_rate_limit(&resp);
+
+    resp.error_for_status()?.json().await
+}
+
+/// Delete an object at a GitHub REST API path that hubcaps does not support yet.
+/// This authenticates with the `GITHUB_TOKEN` environment variable.
+#[instrument]
+#[inline]
+pub async fn github_api_delete(path: &str) -> Result<(), reqwest::Error> {
+    let client = Client::builder().user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"))).build()?;
+    let token = env::var("GITHUB_TOKEN").unwrap();
+
+    let resp = vcr::send(
+        &client,
+        client
+            .delete(&format!("https://api.github.com{}", path))
+            .header("Authorization", format!("token {}", token))
+            .header("Accept", "application/vnd.github.v3+json"),
+    )
+    .await?;
+    check_github_rate_limit(&resp);
+    resp.error_for_status()?;
+
+    Ok(())
+}
+
+/// Make a request to one of the Google APIs we do not have a crate for, or that our crates
+/// do not support yet. This goes through the recorder, so it is counted for the job that
+/// is running. An empty response is returned as `Value::Null`.
+#[instrument(skip(token, body))]
+#[inline]
+pub async fn google_api(token: &str, method: Method, url: &str, query: &[(&str, String)], body: Option<Value>) -> Result<Value, String> {
+    let client = Client::new();
+    let mut req = client.request(method, url).bearer_auth(token).query(query);
+    if let Some(b) = body {
+        req = req.json(&b);
+    }
+
+    let resp = vcr::send(&client, req).await.map_err(|e| e.to_string())?;
+    check_throttled("gsuite", &resp)?;
+
+    let status = resp.status();
+    let text = resp.text().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("{} {}: {}", status, url, text));
+    }
+    if text.is_empty() {
+        return Ok(Value::Null);
+    }
+
+    serde_json::from_str(&text).map_err(|e| e.to_string())
+}
+
+/// A file or directory in a git tree, from the GitHub Git Trees API.
+/// FROM: https://docs.github.com/en/rest/reference/git#get-a-tree
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct GitTreeEntry {
+    pub path: String,
+    /// Either `blob` for a file, `tree` for a directory, or `commit` for a submodule.
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    pub sha: String,
+    #[serde(default)]
+    pub size: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitTree {
+    #[serde(default)]
+    tree: Vec<GitTreeEntry>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+/// The tree-ish for a directory on a branch, ie. `master:rfd/0001`.
+fn git_tree_ish(branch: &str, dir: &str) -> String {
+    let dir = dir.trim_matches('/');
+    if dir.is_empty() {
+        return branch.to_string();
+    }
+
+    format!("{}:{}", branch, dir)
+}
+
+/// Join the path of an entry in a tree to the directory the tree is for.
+fn join_git_tree_path(dir: &str, path: &str) -> String {
+    let dir = dir.trim_matches('/');
+    if dir.is_empty() {
+        return path.to_string();
+    }
+
+    format!("{}/{}", dir, path)
+}
+
+/// List all the files in a directory of one of our GitHub repos, including the files in
+/// the directories below it, with their paths from the root of the repo. The contents
+/// API only returns the first 1000 files in a directory, so this uses the Git Trees API.
+/// If the tree is too big for GitHub to return at once, we walk it a directory at a time.
+/// This authenticates with the `GITHUB_TOKEN` environment variable.
+#[instrument]
+#[inline]
+pub async fn list_github_tree(repo: &str, branch: &str, dir: &str) -> Result<Vec<GitTreeEntry>, reqwest::Error> {
+    let tree: GitTree = github_api_get(&format!("/repos/{}/{}/git/trees/{}?recursive=1", github_org(), repo, git_tree_ish(branch, dir))).await?;
+    if !tree.truncated {
+        return Ok(tree
+            .tree
+            .into_iter()
+            .filter(|e| e.entry_type == "blob")
+            .map(|mut e| {
+                e.path = join_git_tree_path(dir, &e.path);
+                e
+            })
+            .collect());
+    }
+
+    println!("[github] the tree for {} on {} in {} is too big to get at once, walking it instead", dir, branch, repo);
+    let mut files: Vec<GitTreeEntry> = Default::default();
+    // The directories left to walk, by their path and tree-ish.
+    let mut dirs = vec![(dir.trim_matches('/').to_string(), git_tree_ish(branch, dir))];
+    while let Some((path, tree_ish)) = dirs.pop() {
+        let tree: GitTree = github_api_get(&format!("/repos/{}/{}/git/trees/{}", github_org(), repo, tree_ish)).await?;
+        for mut entry in tree.tree {
+            entry.path = join_git_tree_path(&path, &entry.path);
+            match entry.entry_type.as_str() {
+                "blob" => files.push(entry),
+                "tree" => dirs.push((entry.path, entry.sha)),
+                _ => (),
+            }
+        }
+    }
+
+    Ok(files)
+}
+
 /// List all the GitHub repositories for our org.
 #[instrument]
 #[inline]
@@ -412,7 +764,7 @@ pub fn default_date() -> chrono::naive::NaiveDate {
 mod tests {
     use crate::db::Database;
     use crate::models::GithubRepos;
-    use crate::utils::{authenticate_github_jwt, refresh_db_github_repos};
+    use crate::utils::{authenticate_github_jwt, git_tree_ish, join_git_tree_path, refresh_db_github_repos};
 
     #[ignore]
     #[tokio::test(threaded_scheduler)]
@@ -426,4 +778,12 @@ mod tests {
 
         GithubRepos::get_from_db(&db).update_airtable().await;
     }
+
+    #[test]
+    fn test_git_tree_paths() {
+        assert_eq!(git_tree_ish("master", "/rfd/0001/"), "master:rfd/0001");
+        assert_eq!(git_tree_ish("0042", ""), "0042");
+        assert_eq!(join_git_tree_path("/rfd/0001", "images/diagram.svg"), "rfd/0001/images/diagram.svg");
+        assert_eq!(join_git_tree_path("", "README.md"), "README.md");
+    }
 }
@@ -0,0 +1,395 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, RequestBuilder, Response, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::job_runs::{count_api_call, service_for_url};
+
+/// Headers that always hold secrets.
+pub static REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+    "x-api-key",
+    "x-cio-signature-256",
+    "x-hub-signature-256",
+];
+
+/// Query parameters and JSON fields that always hold secrets, ie. the tokens in an
+/// OAuth token response.
+pub static REDACTED_FIELDS: &[&str] = &["access_token", "api_key", "client_secret", "id_token", "key", "password", "refresh_token", "secret", "token"];
+
+/// Environment variables with any of these in their name hold secrets. Their values
+/// are redacted wherever they show up in a request or response.
+pub static SECRET_ENV_VAR_MARKERS: &[&str] = &["CREDENTIAL", "KEY", "PASSWORD", "POST_URL", "SECRET", "TOKEN", "WEBHOOK_URL"];
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// A request we sent and the response we got back.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Interaction {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub request_headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub request_body: String,
+    pub status: u16,
+    #[serde(default)]
+    pub response_headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub response_body: String,
+}
+
+/// The requests and responses of a sync run, with the secrets redacted, so we can
+/// replay the run locally to reproduce a bug from production.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Cassette {
+    /// The job the cassette was recorded for.
+    pub job: String,
+    pub recorded_at: String,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Find the first interaction we have not replayed yet for a request. The url is
+    /// compared after redacting it, the same as when it was recorded.
+    #[instrument(skip(self, played))]
+    #[inline]
+    pub fn find(&self, played: &[bool], method: &str, url: &str) -> Option<usize> {
+        self.interactions
+            .iter()
+            .enumerate()
+            .find(|(i, r)| !played.get(*i).copied().unwrap_or(false) && r.method == method && r.url == url)
+            .map(|(i, _)| i)
+    }
+}
+
+/// Redacts secrets from requests and responses.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Redactor {
+    /// The name and value of each secret, longest value first so a secret that
+    /// contains another is redacted whole.
+    secrets: Vec<(String, String)>,
+}
+
+impl Redactor {
+    #[instrument(skip(secrets))]
+    #[inline]
+    pub fn new(mut secrets: Vec<(String, String)>) -> Self {
+        // Short values, ie. `true` or a port, would redact far more than the secret.
+        secrets.retain(|(_, value)| value.len() >= 8);
+        secrets.sort_by(|a, b| b.1.len().cmp(&a.1.len()));
+
+        Redactor { secrets }
+    }
+
+    /// Redact the values of the environment variables that look like secrets.
+    #[instrument]
+    #[inline]
+    pub fn from_env() -> Self {
+        Redactor::new(env::vars().filter(|(name, _)| SECRET_ENV_VAR_MARKERS.iter().any(|m| name.contains(m))).collect())
+    }
+
+    /// Replace any secret in a string with the name of its environment variable.
+    #[instrument(skip(self, s))]
+    #[inline]
+    pub fn redact(&self, s: &str) -> String {
+        let mut s = s.to_string();
+        for (name, value) in &self.secrets {
+            s = s.replace(value, &format!("[REDACTED:{}]", name));
+        }
+
+        s
+    }
+
+    /// Redact the secrets in a url, including the query parameters that hold secrets.
+    #[instrument(skip(self))]
+    #[inline]
+    pub fn redact_url(&self, url: &str) -> String {
+        let mut parsed = match Url::parse(url) {
+            Ok(u) => u,
+            Err(_) => return self.redact(url),
+        };
+        let pairs: Vec<(String, String)> = parsed
+            .query_pairs()
+            .map(|(k, v)| {
+                let v = if REDACTED_FIELDS.contains(&k.to_lowercase().as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    v.to_string()
+                };
+                (k.to_string(), v)
+            })
+            .collect();
+        if !pairs.is_empty() {
+            parsed.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+
+        self.redact(parsed.as_str())
+    }
+
+    /// Redact the headers that hold secrets and any secrets in the others.
+    #[instrument(skip(self, headers))]
+    #[inline]
+    pub fn redact_headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        headers
+            .iter()
+            .map(|(k, v)| {
+                let name = k.as_str().to_lowercase();
+                let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    self.redact(&String::from_utf8_lossy(v.as_bytes()))
+                };
+                (name, value)
+            })
+            .collect()
+    }
+
+    /// Redact a request or response body. JSON bodies also have the fields that hold
+    /// secrets redacted.
+    #[instrument(skip(self, body))]
+    #[inline]
+    pub fn redact_body(&self, body: &[u8]) -> String {
+        let body = String::from_utf8_lossy(body).to_string();
+        let body = match serde_json::from_str::<Value>(&body) {
+            Ok(mut v) => {
+                redact_json_fields(&mut v);
+                v.to_string()
+            }
+            Err(_) => body,
+        };
+
+        self.redact(&body)
+    }
+}
+
+fn redact_json_fields(v: &mut Value) {
+    match v {
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                if REDACTED_FIELDS.contains(&k.to_lowercase().as_str()) && v.is_string() {
+                    *v = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json_fields(v);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json_fields),
+        _ => (),
+    }
+}
+
+enum Mode {
+    Record { file: String, cassette: Cassette, redactor: Redactor },
+    Replay { cassette: Cassette, played: Vec<bool>, redactor: Redactor },
+}
+
+/// If we are recording or replaying, set by the CLI like the progress bars.
+static VCR: Mutex<Option<Mode>> = Mutex::new(None);
+
+/// Record the requests we send and the responses we get back into a cassette, which
+/// is written to the file after every request.
+#[instrument]
+#[inline]
+pub fn start_recording(file: &str, job: &str) {
+    *VCR.lock().unwrap() = Some(Mode::Record {
+        file: file.to_string(),
+        cassette: Cassette {
+            job: job.to_string(),
+            recorded_at: Utc::now().to_rfc3339(),
+            interactions: Default::default(),
+        },
+        redactor: Redactor::from_env(),
+    });
+}
+
+/// Answer the requests we send from a cassette rather than sending them. Requests
+/// are matched in order by their method and url, and a request that is not in the
+/// cassette fails the run.
+#[instrument]
+#[inline]
+pub fn start_replaying(file: &str) -> Result<(), String> {
+    let body = fs::read_to_string(file).map_err(|e| format!("reading the cassette {} failed: {}", file, e))?;
+    let cassette: Cassette = serde_json::from_str(&body).map_err(|e| format!("decoding the cassette {} failed: {}", file, e))?;
+    println!("[vcr] replaying {} requests for {} recorded at {}", cassette.interactions.len(), cassette.job, cassette.recorded_at);
+
+    *VCR.lock().unwrap() = Some(Mode::Replay {
+        played: vec![false; cassette.interactions.len()],
+        cassette,
+        redactor: Redactor::from_env(),
+    });
+    Ok(())
+}
+
+fn write_cassette(file: &str, cassette: &Cassette) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(cassette).unwrap();
+    fs::write(file, json).map_err(|e| format!("writing the cassette {} failed: {}", file, e))
+}
+
+/// Stop recording and write the cassette to its file.
+#[instrument]
+#[inline]
+pub fn finish_recording() -> Result<(), String> {
+    if let Some(Mode::Record { file, cassette, .. }) = VCR.lock().unwrap().take() {
+        write_cassette(&file, &cassette)?;
+        println!("[vcr] recorded {} requests to {}", cassette.interactions.len(), file);
+    }
+
+    Ok(())
+}
+
+/// Send a request, recording it or answering it from the cassette if we are
+/// recording or replaying. Otherwise this is the same as sending it. Every request
+/// is also counted against the job that is running.
+///
+/// Only the requests we send ourselves go through here, not the ones the API client
+/// crates send, so only the jobs in `jobs::REPLAYABLE_JOBS` can be recorded.
+#[instrument(skip(client, req))]
+#[inline]
+pub async fn send(client: &Client, req: RequestBuilder) -> Result<Response, reqwest::Error> {
+    let redactor = match &*VCR.lock().unwrap() {
+        None => None,
+        Some(Mode::Record { redactor, .. }) | Some(Mode::Replay { redactor, .. }) => Some(redactor.clone()),
+    };
+    let request = req.build()?;
+    count_api_call(service_for_url(request.url().as_str()));
+    let redactor = match redactor {
+        Some(r) => r,
+        None => return client.execute(request).await,
+    };
+
+    let method = request.method().to_string();
+    let url = redactor.redact_url(request.url().as_str());
+
+    if let Some(Mode::Replay { cassette, played, .. }) = &mut *VCR.lock().unwrap() {
+        let i = cassette
+            .find(played, &method, &url)
+            .unwrap_or_else(|| panic!("[vcr] the cassette does not have a response for {} {}", method, url));
+        played[i] = true;
+        return Ok(to_response(&cassette.interactions[i]));
+    }
+
+    let mut interaction = Interaction {
+        method,
+        url,
+        request_headers: redactor.redact_headers(request.headers()),
+        request_body: request.body().and_then(|b| b.as_bytes()).map(|b| redactor.redact_body(b)).unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let resp = client.execute(request).await?;
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let body = resp.bytes().await?;
+
+    interaction.status = status.as_u16();
+    interaction.response_headers = redactor.redact_headers(&headers);
+    interaction.response_body = redactor.redact_body(&body);
+    if let Some(Mode::Record { file, cassette, .. }) = &mut *VCR.lock().unwrap() {
+        cassette.interactions.push(interaction);
+        // Write as we go so a run that panics, ie. the bug we are recording, still
+        // leaves a cassette behind.
+        if let Err(e) = write_cassette(file, cassette) {
+            println!("[vcr] {}", e);
+        }
+    }
+
+    // We read the body to record it, so hand back a response with the same body.
+    let mut builder = http::Response::builder().status(status);
+    for (k, v) in headers.iter() {
+        builder = builder.header(k, v);
+    }
+    Ok(Response::from(builder.body(body.to_vec()).unwrap()))
+}
+
+fn to_response(interaction: &Interaction) -> Response {
+    let mut builder = http::Response::builder().status(interaction.status);
+    for (k, v) in &interaction.response_headers {
+        builder = builder.header(k.as_str(), v.as_str());
+    }
+
+    Response::from(builder.body(interaction.response_body.clone().into_bytes()).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+    use serde_json::{json, Value};
+
+    use crate::jobs::{job_services, JOBS, REPLAYABLE_JOBS};
+    use crate::vcr::{Cassette, Interaction, Redactor};
+
+    #[test]
+    fn test_vcr_redaction() {
+        let redactor = Redactor::new(vec![("SLACK_TOKEN".to_string(), "xoxb-1234-5678".to_string()), ("GITHUB_ORG".to_string(), "oxide".to_string())]);
+
+        assert_eq!(
+            redactor.redact_url("https://slack.com/api/users.lookupByEmail?email=jane%40oxidecomputer.com&token=xoxb-1234-5678"),
+            "https://slack.com/api/users.lookupByEmail?email=jane%40oxidecomputer.com&token=%5BREDACTED%5D"
+        );
+        assert_eq!(redactor.redact("the token is xoxb-1234-5678"), "the token is [REDACTED:SLACK_TOKEN]");
+        // Short values are not treated as secrets.
+        assert_eq!(redactor.redact("oxide"), "oxide");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer xoxb-1234-5678"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let headers = redactor.redact_headers(&headers);
+        assert_eq!(headers["authorization"], "[REDACTED]");
+        assert_eq!(headers["content-type"], "application/json");
+
+        let body: Value = serde_json::from_str(&redactor.redact_body(br#"{"ok":true,"access_token":"ya29.abc","items":[{"refresh_token":"1//xyz","name":"xoxb-1234-5678"}]}"#)).unwrap();
+        assert_eq!(
+            body,
+            json!({"ok": true, "access_token": "[REDACTED]", "items": [{"refresh_token": "[REDACTED]", "name": "[REDACTED:SLACK_TOKEN]"}]})
+        );
+        assert_eq!(redactor.redact_body(b"token xoxb-1234-5678"), "token [REDACTED:SLACK_TOKEN]");
+    }
+
+    #[test]
+    fn test_vcr_cassette_matching() {
+        let interaction = |method: &str, url: &str, body: &str| Interaction {
+            method: method.to_string(),
+            url: url.to_string(),
+            status: 200,
+            response_body: body.to_string(),
+            ..Default::default()
+        };
+        let cassette = Cassette {
+            job: "rfds".to_string(),
+            recorded_at: "2021-05-05T09:00:00+00:00".to_string(),
+            interactions: vec![
+                interaction("GET", "https://api.github.com/repos/oxidecomputer/rfd", "first"),
+                interaction("GET", "https://api.github.com/repos/oxidecomputer/rfd", "second"),
+                interaction("PATCH", "https://api.github.com/repos/oxidecomputer/rfd", "patched"),
+            ],
+        };
+
+        let mut played = vec![false; 3];
+        assert_eq!(cassette.find(&played, "GET", "https://api.github.com/repos/oxidecomputer/rfd"), Some(0));
+        played[0] = true;
+        assert_eq!(cassette.find(&played, "GET", "https://api.github.com/repos/oxidecomputer/rfd"), Some(1));
+        assert_eq!(cassette.find(&played, "PATCH", "https://api.github.com/repos/oxidecomputer/rfd"), Some(2));
+        played[1] = true;
+        assert_eq!(cassette.find(&played, "GET", "https://api.github.com/repos/oxidecomputer/rfd"), None);
+    }
+
+    #[test]
+    fn test_vcr_replayable_jobs() {
+        for job in REPLAYABLE_JOBS {
+            assert!(JOBS.iter().any(|(name, _)| name == job), "{} is not a job", job);
+            // The services with breakers are all reached through API client crates.
+            assert!(job_services(job).is_empty(), "{} uses {:?}", job, job_services(job));
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::env;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use tracing::instrument;
+
+/// How old a signed request from Slack can be before we reject it as a replay.
+pub const SLACK_SIGNATURE_MAX_AGE_SECS: i64 = 5 * 60;
+
+/// Compare two byte strings in time that only depends on their lengths, so the
+/// comparison does not leak how much of a signature someone has guessed.
+#[instrument(skip(a, b))]
+#[inline]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[instrument(skip(secret, body))]
+#[inline]
+fn hmac_sha256(secret: &[u8], body: &[u8]) -> Vec<u8> {
+    // HMAC takes keys of any length so this can not fail.
+    let mut mac = Hmac::<Sha256>::new_varkey(secret).unwrap();
+    mac.update(body);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[instrument(skip(bytes))]
+#[inline]
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Sign a body the same way GitHub signs webhooks, `sha256=` followed by the hex
+/// HMAC-SHA256 of the body with the secret. We sign the events we send this way so
+/// subscribers can verify them with the same code they use for GitHub.
+#[instrument(skip(secret, body))]
+#[inline]
+pub fn hmac_sha256_signature(secret: &str, body: &[u8]) -> String {
+    format!("sha256={}", to_hex(&hmac_sha256(secret.as_bytes(), body)))
+}
+
+/// Verify the `X-Hub-Signature-256` header GitHub sends with webhooks, which is
+/// `sha256=` followed by the hex HMAC-SHA256 of the body with the webhook secret.
+#[instrument(skip(secret, body))]
+#[inline]
+pub fn verify_github_signature(secret: &str, signature: &str, body: &[u8]) -> Result<(), String> {
+    if secret.is_empty() {
+        return Err("the GitHub webhook secret is not set".to_string());
+    }
+
+    let signature = signature.trim().to_lowercase();
+    if !signature.starts_with("sha256=") {
+        return Err("the GitHub signature is missing or not sha256".to_string());
+    }
+
+    let expected = hmac_sha256_signature(secret, body);
+    if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+        return Err("the GitHub signature does not match".to_string());
+    }
+
+    Ok(())
+}
+
+/// Verify the `X-Slack-Signature` header Slack sends with requests, which is `v0=`
+/// followed by the hex HMAC-SHA256 of `v0:{timestamp}:{body}` with our signing secret.
+/// The timestamp is the `X-Slack-Request-Timestamp` header, which we also check is
+/// recent so a captured request can not be replayed.
+#[instrument(skip(secret, body))]
+#[inline]
+pub fn verify_slack_signature(secret: &str, timestamp: &str, signature: &str, body: &[u8], now: DateTime<Utc>) -> Result<(), String> {
+    if secret.is_empty() {
+        return Err("the Slack signing secret is not set".to_string());
+    }
+
+    let ts: i64 = timestamp.trim().parse().map_err(|_| format!("the Slack request timestamp `{}` is not valid", timestamp))?;
+    if (now.timestamp() - ts).abs() > SLACK_SIGNATURE_MAX_AGE_SECS {
+        return Err(format!("the Slack request timestamp {} is too old", ts));
+    }
+
+    let signature = match signature.trim().strip_prefix("v0=") {
+        Some(s) => s.to_lowercase(),
+        None => return Err("the Slack signature is missing or not v0".to_string()),
+    };
+
+    let mut base = format!("v0:{}:", ts).into_bytes();
+    base.extend_from_slice(body);
+    let expected = to_hex(&hmac_sha256(secret.as_bytes(), &base));
+    if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+        return Err("the Slack signature does not match".to_string());
+    }
+
+    Ok(())
+}
+
+/// Verify the `X-DocuSign-Signature-1` header DocuSign Connect sends with events,
+/// which is the base64 HMAC-SHA256 of the body with the Connect HMAC key.
+#[instrument(skip(secret, body))]
+#[inline]
+pub fn verify_docusign_signature(secret: &str, signature: &str, body: &[u8]) -> Result<(), String> {
+    if secret.is_empty() {
+        return Err("the DocuSign Connect HMAC key is not set".to_string());
+    }
+    if signature.trim().is_empty() {
+        return Err("the DocuSign signature is missing".to_string());
+    }
+
+    let expected = base64::encode(hmac_sha256(secret.as_bytes(), body));
+    if !constant_time_eq(expected.as_bytes(), signature.trim().as_bytes()) {
+        return Err("the DocuSign signature does not match".to_string());
+    }
+
+    Ok(())
+}
+
+/// Verify the `X-Checkr-Signature` header Checkr sends with webhooks, which is the hex
+/// HMAC-SHA256 of the body with our API key.
+#[instrument(skip(secret, body))]
+#[inline]
+pub fn verify_checkr_signature(secret: &str, signature: &str, body: &[u8]) -> Result<(), String> {
+    if secret.is_empty() {
+        return Err("the Checkr API key is not set".to_string());
+    }
+    if signature.trim().is_empty() {
+        return Err("the Checkr signature is missing".to_string());
+    }
+
+    let expected = to_hex(&hmac_sha256(secret.as_bytes(), body));
+    if !constant_time_eq(expected.as_bytes(), signature.trim().to_lowercase().as_bytes()) {
+        return Err("the Checkr signature does not match".to_string());
+    }
+
+    Ok(())
+}
+
+/// Check the shared secret a client of one of our webhooks sent, ie. the lobby tablet or
+/// an Apps Script, against the one in the environment variable `env_name`. Requests are
+/// refused if we do not have a secret set, rather than accepted from anyone.
+#[instrument(skip(given))]
+#[inline]
+pub fn verify_shared_secret(env_name: &str, given: &str) -> Result<(), String> {
+    let expected = env::var(env_name).unwrap_or_default();
+    if expected.is_empty() {
+        return Err(format!("{} is not set", env_name));
+    }
+    if !constant_time_eq(expected.as_bytes(), given.as_bytes()) {
+        return Err(format!("the secret does not match {}", env_name));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::verify::{constant_time_eq, hmac_sha256_signature, verify_checkr_signature, verify_docusign_signature, verify_github_signature, verify_shared_secret, verify_slack_signature};
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_verify_github_signature() {
+        // The example from the GitHub docs on securing webhooks.
+        let secret = "It's a Secret to Everybody";
+        let body = b"Hello, World!";
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(verify_github_signature(secret, signature, body).is_ok());
+        assert!(verify_github_signature(secret, &signature.to_uppercase(), body).is_ok());
+        assert_eq!(hmac_sha256_signature(secret, body), signature);
+
+        assert!(verify_github_signature(secret, signature, b"Hello, World?").is_err());
+        assert!(verify_github_signature("another secret", signature, body).is_err());
+        assert!(verify_github_signature(secret, "sha1=757107ea0eb2509fc211221cce984b8a37570b6d", body).is_err());
+        assert!(verify_github_signature("", signature, body).is_err());
+    }
+
+    #[test]
+    fn test_verify_slack_signature() {
+        let secret = "8f742231b10e8888abcd99yyyzzz85a5";
+        let body = b"token=xyzz0WbapA4vBCDEFasx0q6G";
+        let signature = "v0=8a2b7124f7f40357c2227bb1175c525fc1840d77da1a54b37028e17d1dc33503";
+        let now = Utc.timestamp(1531420618 + 60, 0);
+        assert!(verify_slack_signature(secret, "1531420618", signature, body, now).is_ok());
+
+        assert!(verify_slack_signature(secret, "1531420619", signature, body, now).is_err());
+        assert!(verify_slack_signature(secret, "1531420618", signature, b"token=abc", now).is_err());
+        assert!(verify_slack_signature(secret, "", signature, body, now).is_err());
+
+        // Replays of an old request are rejected even with a valid signature.
+        let later = Utc.timestamp(1531420618 + 6 * 60, 0);
+        assert_eq!(
+            verify_slack_signature(secret, "1531420618", signature, body, later).unwrap_err(),
+            "the Slack request timestamp 1531420618 is too old"
+        );
+    }
+
+    #[test]
+    fn test_verify_docusign_signature() {
+        let secret = "NAv+QYsQYFsvKpWLxYF9jS6ooHcpuENsOoQ8SPjNDHo=";
+        let body = br#"{"event":"envelope-completed"}"#;
+        let signature = "GQ3E8uvkVe+Vj+7I3Vn33PQIZsE3wDdegVLe7UJ9H1c=";
+        assert!(verify_docusign_signature(secret, signature, body).is_ok());
+
+        assert!(verify_docusign_signature(secret, signature, br#"{"event":"envelope-voided"}"#).is_err());
+        assert!(verify_docusign_signature(secret, "", body).is_err());
+        assert!(verify_docusign_signature("", signature, body).is_err());
+    }
+
+    #[test]
+    fn test_verify_checkr_signature() {
+        let secret = "83ebeabdec09f6670863766f792ead24d61fe3f9";
+        let body = br#"{"type":"report.completed"}"#;
+        let signature = "277e84044feb1a6a7e346c968cf66f53c9d331c9e604a495cb63117140f9decf";
+        assert!(verify_checkr_signature(secret, signature, body).is_ok());
+        assert!(verify_checkr_signature(secret, &signature.to_uppercase(), body).is_ok());
+
+        assert!(verify_checkr_signature(secret, signature, br#"{"type":"report.suspended"}"#).is_err());
+        assert!(verify_checkr_signature(secret, "", body).is_err());
+        assert!(verify_checkr_signature("", signature, body).is_err());
+    }
+
+    #[test]
+    fn test_verify_shared_secret() {
+        assert_eq!(verify_shared_secret("CIO_TEST_SHARED_SECRET", ""), Err("CIO_TEST_SHARED_SECRET is not set".to_string()));
+
+        env::set_var("CIO_TEST_SHARED_SECRET", "s3cret");
+        assert!(verify_shared_secret("CIO_TEST_SHARED_SECRET", "s3cret").is_ok());
+        assert!(verify_shared_secret("CIO_TEST_SHARED_SECRET", "s3cre").is_err());
+        assert!(verify_shared_secret("CIO_TEST_SHARED_SECRET", "").is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::chat::ChatChannel;
+use crate::configs::{Config, UserConfig};
+use crate::db::Database;
+use crate::docusign::{send_envelope, DocuSignEnvelope};
+use crate::format::format_date;
+use crate::messages::render_message;
+use crate::notifications::{notify, notify_user, NotificationPriority};
+use crate::schema::{docusign_envelopes, visitors};
+use crate::utils::GSUITE_DOMAIN;
+
+/// The name of the DocuSign template in the `docusign` section of our configs we send
+/// visitors to sign before they come in. Visitors do not get an NDA without one.
+pub const VISITOR_NDA_TEMPLATE: &str = "visitor_nda";
+
+/// The header the lobby tablet sends the shared secret in, which must match the
+/// `VISITORS_WEBHOOK_SECRET` environment variable.
+pub static VISITORS_SECRET_HEADER: &str = "X-CIO-Visitors-Secret";
+
+/// What we record as who registered a visitor who checked in at the lobby tablet
+/// without being registered first.
+pub const LOBBY_TABLET: &str = "lobby";
+
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "visitors"]
+pub struct NewVisitor {
+    pub name: String,
+    pub email: String,
+    pub company: String,
+    /// The username of who they are visiting.
+    pub host: String,
+    pub visit_date: NaiveDate,
+    /// The username of who registered them, or `lobby` for people who checked in at the
+    /// lobby tablet without being registered.
+    pub registered_by: String,
+    /// The DocuSign envelope of their NDA, if we sent them one.
+    pub nda_envelope_id: String,
+    pub arrived_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct Visitor {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub company: String,
+    pub host: String,
+    pub visit_date: NaiveDate,
+    pub registered_by: String,
+    pub nda_envelope_id: String,
+    pub arrived_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Visitor {
+    /// Describe the visitor for a list, ie. `Jane Doe (Acme) visiting Sam Smith, NDA signed`.
+    #[instrument(skip(users))]
+    #[inline]
+    pub fn describe(&self, users: &BTreeMap<String, UserConfig>, nda_status: &str) -> String {
+        let host = users.get(&self.host).map(|u| format!("{} {}", u.first_name, u.last_name)).unwrap_or_else(|| self.host.to_string());
+        let mut text = self.name.to_string();
+        if !self.company.is_empty() {
+            text += &format!(" ({})", self.company);
+        }
+        text += &format!(" visiting {}, {}", host, describe_nda(nda_status));
+        if self.arrived_at.is_some() {
+            text += ", arrived";
+        }
+
+        text
+    }
+}
+
+/// Describe the status of a visitor's NDA envelope, which is empty if they have none.
+#[instrument]
+#[inline]
+pub fn describe_nda(status: &str) -> String {
+    match status {
+        "" => "no NDA".to_string(),
+        "completed" => "NDA signed".to_string(),
+        s => format!("NDA {}", s),
+    }
+}
+
+/// A visitor checking in at the lobby tablet.
+#[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+pub struct VisitorCheckIn {
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub company: String,
+    /// The username or the full name of who they are visiting.
+    pub host: String,
+}
+
+/// Find the username of a host by their username or their full name, since people at
+/// the lobby tablet know who they are visiting by name.
+#[instrument(skip(users))]
+#[inline]
+pub fn resolve_host(users: &BTreeMap<String, UserConfig>, host: &str) -> Option<String> {
+    let host = host.trim().trim_start_matches('@').to_lowercase();
+    users
+        .values()
+        .find(|u| !u.is_system_account() && (u.username == host || format!("{} {}", u.first_name, u.last_name).to_lowercase() == host))
+        .map(|u| u.username.to_string())
+}
+
+/// Get the NDA envelope to link a visitor to: one they already signed, or a new one if
+/// we have a template for it. Returns an empty envelope ID without a template.
+#[instrument(skip(db, config))]
+#[inline]
+async fn visitor_nda(db: &Database, config: &Config, name: &str, email: &str) -> Result<String, String> {
+    if let Ok(signed) = docusign_envelopes::table
+        .filter(docusign_envelopes::template.eq(VISITOR_NDA_TEMPLATE))
+        .filter(docusign_envelopes::signer_email.eq(email))
+        .filter(docusign_envelopes::status.eq("completed"))
+        .first::<DocuSignEnvelope>(&db.conn())
+    {
+        return Ok(signed.envelope_id);
+    }
+    if !config.docusign.templates.contains_key(VISITOR_NDA_TEMPLATE) {
+        return Ok(String::new());
+    }
+
+    let envelope = send_envelope(db, config, VISITOR_NDA_TEMPLATE, name, email, None, None).await?;
+    Ok(envelope.envelope_id)
+}
+
+/// Register a visitor we expect on a day and send them the NDA to sign before they come
+/// in. Returns what was done.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn add_visitor(db: &Database, config: &Config, name: &str, email: &str, company: &str, host: &str, visit_date: NaiveDate, registered_by: &str) -> Result<String, String> {
+    let name = name.trim();
+    let email = email.trim().to_lowercase();
+    if name.is_empty() || !email.contains('@') {
+        return Err("a visitor needs a name and an email".to_string());
+    }
+    let host = resolve_host(&config.users, host).ok_or_else(|| format!("`{}` is not anyone in our directory", host))?;
+
+    let nda_envelope_id = visitor_nda(db, config, name, &email).await?;
+    let visitor = diesel::insert_into(visitors::table)
+        .values(&NewVisitor {
+            name: name.to_string(),
+            email: email.to_string(),
+            company: company.trim().to_string(),
+            host,
+            visit_date,
+            registered_by: registered_by.to_string(),
+            nda_envelope_id,
+            arrived_at: None,
+            created_at: Utc::now(),
+        })
+        .get_result::<Visitor>(&db.conn())
+        .map_err(|e| format!("saving the visitor {} failed: {}", email, e))?;
+
+    Ok(format!(
+        "added {} visiting {} on {}{}",
+        visitor.name,
+        visitor.host,
+        format_date(visitor.visit_date),
+        if visitor.nda_envelope_id.is_empty() { "" } else { ", their NDA is in DocuSign" }
+    ))
+}
+
+/// Check in a visitor at the lobby tablet and tell their host they are here. Visitors
+/// we did not expect today are registered as they check in. Returns what was done.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn check_in_visitor(db: &Database, config: &Config, check_in: &VisitorCheckIn) -> Result<String, String> {
+    let today = Utc::now().date().naive_utc();
+    let email = check_in.email.trim().to_lowercase();
+    let expected = visitors::table
+        .filter(visitors::email.eq(&email))
+        .filter(visitors::visit_date.eq(today))
+        .first::<Visitor>(&db.conn())
+        .ok();
+    if expected.is_none() {
+        add_visitor(db, config, &check_in.name, &email, &check_in.company, &check_in.host, today, LOBBY_TABLET).await?;
+    }
+
+    let visitor = diesel::update(visitors::table.filter(visitors::email.eq(&email)).filter(visitors::visit_date.eq(today)))
+        .set(visitors::arrived_at.eq(Some(Utc::now())))
+        .get_result::<Visitor>(&db.conn())
+        .map_err(|e| format!("checking in the visitor {} failed: {}", email, e))?;
+
+    let nda_status = get_nda_status(db, &visitor.nda_envelope_id);
+    let text = render_message("visitors.arrived", &json!({ "name": visitor.name, "status": describe_nda(&nda_status) }));
+    notify_user(db, &format!("{}@{}", visitor.host, GSUITE_DOMAIN), json!({ "text": text })).await;
+
+    Ok(format!("checked in {} visiting {}", visitor.name, visitor.host))
+}
+
+/// Get the status of an NDA envelope, or an empty string if there is none.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_nda_status(db: &Database, envelope_id: &str) -> String {
+    if envelope_id.is_empty() {
+        return String::new();
+    }
+
+    docusign_envelopes::table
+        .filter(docusign_envelopes::envelope_id.eq(envelope_id))
+        .select(docusign_envelopes::status)
+        .first::<String>(&db.conn())
+        .unwrap_or_default()
+}
+
+/// Get the visitors we expect on a day.
+#[instrument(skip(db))]
+#[inline]
+pub fn get_visitors_on(db: &Database, day: NaiveDate) -> Vec<Visitor> {
+    visitors::table
+        .filter(visitors::visit_date.eq(day))
+        .order_by(visitors::name)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the visitors on {} failed: {}", day, e))
+}
+
+/// Format the visitors on a day as the lines of a list.
+#[instrument(skip(db, config))]
+#[inline]
+pub fn format_visitors(db: &Database, config: &Config, visitors: &[Visitor]) -> Vec<String> {
+    visitors.iter().map(|v| format!("• {}", v.describe(&config.users, &get_nda_status(db, &v.nda_envelope_id)))).collect()
+}
+
+/// Post the visitors we expect today to the office channel, so whoever is in knows who
+/// to let in.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn post_expected_visitors(db: &Database, config: &Config) {
+    let today = Utc::now().date().naive_utc();
+    let visitors = get_visitors_on(db, today);
+    if visitors.is_empty() {
+        return;
+    }
+
+    let lines = format_visitors(db, config, &visitors);
+    notify(
+        db,
+        NotificationPriority::Urgent,
+        ChatChannel::Office,
+        "visitors.expected",
+        json!({
+            "text": render_message("visitors.expected", &json!({ "count": visitors.len(), "lines": lines })),
+        }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::naive::NaiveDate;
+    use chrono::{TimeZone, Utc};
+
+    use crate::configs::UserConfig;
+    use crate::visitors::{resolve_host, Visitor};
+
+    #[test]
+    fn test_visitors() {
+        let users: BTreeMap<String, UserConfig> = toml::from_str(
+            r#"[sam]
+first_name = "Sam"
+last_name = "Smith"
+username = "sam"
+
+[builds]
+first_name = "Build"
+last_name = "Bot"
+username = "builds"
+type = "system account"
+"#,
+        )
+        .unwrap();
+        assert_eq!(resolve_host(&users, "Sam Smith "), Some("sam".to_string()));
+        assert_eq!(resolve_host(&users, "@sam"), Some("sam".to_string()));
+        assert_eq!(resolve_host(&users, "builds"), None);
+
+        let mut visitor = Visitor {
+            id: 1,
+            name: "Jane Doe".to_string(),
+            email: "jane@example.com".to_string(),
+            company: "Acme".to_string(),
+            host: "sam".to_string(),
+            visit_date: NaiveDate::from_ymd(2021, 5, 20),
+            registered_by: "sam".to_string(),
+            nda_envelope_id: "93be49ab".to_string(),
+            arrived_at: None,
+            created_at: Utc.ymd(2021, 5, 18).and_hms(9, 0, 0),
+        };
+        assert_eq!(visitor.describe(&users, "completed"), "Jane Doe (Acme) visiting Sam Smith, NDA signed");
+        assert_eq!(visitor.describe(&users, "sent"), "Jane Doe (Acme) visiting Sam Smith, NDA sent");
+
+        visitor.company = String::new();
+        visitor.host = "alex".to_string();
+        visitor.arrived_at = Some(Utc.ymd(2021, 5, 20).and_hms(16, 0, 0));
+        assert_eq!(visitor.describe(&users, ""), "Jane Doe visiting alex, no NDA, arrived");
+    }
+}
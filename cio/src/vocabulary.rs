@@ -0,0 +1,71 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::instrument;
+
+/// Store an enum with a controlled vocabulary, ie. the options of a single select column in
+/// Airtable, as the name it is serialized with, in a text column. The names come from the
+/// `#[serde(rename = "...")]` attributes on the variants, so the enum needs to derive
+/// `FromSqlRow`, `AsExpression`, `Deserialize`, and `Serialize`, with
+/// `#[sql_type = "Text"]`. This also implements `Display` with the names.
+///
+/// Values that are not in the vocabulary are rejected, with an error listing the options,
+/// both when they are read from the database and when they are read from Airtable.
+#[macro_export]
+macro_rules! text_enum {
+    ($t:ty) => {
+        impl diesel::deserialize::FromSql<diesel::sql_types::Text, diesel::pg::Pg> for $t {
+            fn from_sql(bytes: Option<&[u8]>) -> diesel::deserialize::Result<Self> {
+                let value = <String as diesel::deserialize::FromSql<diesel::sql_types::Text, diesel::pg::Pg>>::from_sql(bytes)?;
+                $crate::vocabulary::parse_vocabulary(&value).map_err(|e| e.into())
+            }
+        }
+
+        impl diesel::serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg> for $t {
+            fn to_sql<W: std::io::Write>(&self, out: &mut diesel::serialize::Output<W, diesel::pg::Pg>) -> diesel::serialize::Result {
+                <String as diesel::serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg>>::to_sql(&$crate::vocabulary::vocabulary_name(self), out)
+            }
+        }
+
+        impl std::fmt::Display for $t {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", $crate::vocabulary::vocabulary_name(self))
+            }
+        }
+    };
+}
+
+/// Parse a value of an enum with a controlled vocabulary from its name. Values that are not
+/// in the vocabulary return an error listing the options.
+#[instrument]
+#[inline]
+pub fn parse_vocabulary<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string())).map_err(|e| format!("`{}` is not a valid value: {}", value, e))
+}
+
+/// Returns the name of a value of an enum with a controlled vocabulary.
+#[instrument(skip(value))]
+#[inline]
+pub fn vocabulary_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value).unwrap().as_str().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::applicant_status::Status;
+    use crate::finance::{SecurityQuestionnaireStatus, VendorStatus};
+    use crate::vocabulary::{parse_vocabulary, vocabulary_name};
+
+    #[test]
+    fn test_vocabulary() {
+        assert_eq!(parse_vocabulary::<Status>("Needs to be triaged"), Ok(Status::NeedsToBeTriaged));
+        assert_eq!(vocabulary_name(&Status::GivingOffer), "Giving offer");
+        assert_eq!(Status::KeepingWarm.to_string(), "Keeping warm");
+
+        assert_eq!(parse_vocabulary::<VendorStatus>("Cancelled"), Ok(VendorStatus::Cancelled));
+        assert_eq!(parse_vocabulary::<SecurityQuestionnaireStatus>("Not started"), Ok(SecurityQuestionnaireStatus::NotStarted));
+        assert_eq!(SecurityQuestionnaireStatus::NotStarted.to_string(), "not started");
+
+        let err = parse_vocabulary::<VendorStatus>("Canceled").unwrap_err();
+        assert_eq!(err, "`Canceled` is not a valid value: unknown variant `Canceled`, expected one of `Active`, `Trial`, `Cancelled`");
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Duration, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use tracing::instrument;
+
+use crate::db::Database;
+use crate::schema::webhook_deliveries;
+
+/// How long we remember the webhooks we processed. GitHub only lets you redeliver
+/// webhooks from the last few days and Slack gives up retrying within the hour.
+pub const WEBHOOK_DELIVERY_RETENTION_DAYS: i64 = 30;
+
+/// A webhook we processed, so we know to skip it when it is sent again.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "webhook_deliveries"]
+pub struct NewWebhookDelivery {
+    /// Where the webhook is from, ie. `github` or `slack`.
+    pub source: String,
+    /// The ID the source gives the delivery, which stays the same when it is sent
+    /// again. This is the `X-GitHub-Delivery` header for GitHub and the `event_id`
+    /// for Slack.
+    pub delivery_id: String,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub source: String,
+    pub delivery_id: String,
+    pub received_at: DateTime<Utc>,
+}
+
+/// Record that we received a webhook, returning false if we already have, in which
+/// case it should be skipped. We record the delivery before handling it so a retry
+/// that comes in while we are still handling the first try is skipped too. Deliveries
+/// without an ID can not be told apart so they are always handled.
+#[instrument(skip(db))]
+#[inline]
+pub fn record_webhook_delivery(db: &Database, source: &str, delivery_id: &str) -> bool {
+    if delivery_id.trim().is_empty() {
+        return true;
+    }
+
+    let delivery = NewWebhookDelivery {
+        source: source.to_string(),
+        delivery_id: delivery_id.trim().to_string(),
+        received_at: Utc::now(),
+    };
+    let inserted = diesel::insert_into(webhook_deliveries::table)
+        .values(&delivery)
+        .on_conflict((webhook_deliveries::source, webhook_deliveries::delivery_id))
+        .do_nothing()
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("recording webhook delivery {:?} failed: {}", delivery, e));
+
+    inserted == 1
+}
+
+/// Forget the deliveries older than `WEBHOOK_DELIVERY_RETENTION_DAYS`, nothing will
+/// send them again.
+#[instrument(skip(db))]
+#[inline]
+pub fn prune_webhook_deliveries(db: &Database) {
+    let cutoff = Utc::now() - Duration::days(WEBHOOK_DELIVERY_RETENTION_DAYS);
+    let deleted = diesel::delete(webhook_deliveries::table.filter(webhook_deliveries::received_at.lt(cutoff)))
+        .execute(&db.conn())
+        .unwrap_or_else(|e| panic!("deleting the webhook deliveries before {} failed: {}", cutoff, e));
+    println!("[webhook_deliveries] forgot {} deliveries from before {}", deleted, cutoff);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::webhook_deliveries::prune_webhook_deliveries;
+
+    #[ignore]
+    #[tokio::test(threaded_scheduler)]
+    async fn test_cron_webhook_deliveries() {
+        let db = Database::new();
+        prune_webhook_deliveries(&db);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::configs::{Config, LinkConfig};
+use crate::db::Database;
+use crate::messages::render_message;
+use crate::schedule::{parse_timezone, CronSchedule};
+use crate::schema::welcome_messages;
+use crate::slack::post_to_user_id;
+use crate::utils::GSUITE_DOMAIN;
+
+/// The direct messages we send new people in Slack and how many days after they join
+/// we send each. The messages are the `welcome.{step}` message templates.
+pub static WELCOME_STEPS: &[(&str, i64)] = &[("welcome", 0), ("tools", 2), ("check_in", 7)];
+
+/// When we send the messages after the first, in the person's timezone, so they arrive
+/// at the start of their day.
+pub const WELCOME_MESSAGE_SCHEDULE: &str = "0 9 * * *";
+
+/// The links from our configs to send new people with each welcome message, from the
+/// `welcome` section of our configs, keyed by the steps in `WELCOME_STEPS`.
+///
+/// ```toml
+/// [welcome.links]
+/// welcome = ["handbook", "benefits"]
+/// tools = ["github", "rfd", "meet"]
+/// ```
+#[derive(Debug, Default, PartialEq, Clone, JsonSchema, Deserialize, Serialize)]
+pub struct WelcomeConfig {
+    /// The names of the links in the `links` section of our configs for each step.
+    #[serde(default)]
+    pub links: BTreeMap<String, Vec<String>>,
+}
+
+/// A welcome message for someone who joined Slack. There is one for each step, so a
+/// step is never sent twice, even if Slack tells us about the same person again.
+#[derive(Debug, Clone, PartialEq, Insertable)]
+#[table_name = "welcome_messages"]
+pub struct NewWelcomeMessage {
+    pub slack_user_id: String,
+    pub email: String,
+    pub first_name: String,
+    /// The step from `WELCOME_STEPS`.
+    pub step: String,
+    pub deliver_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+pub struct WelcomeMessage {
+    pub id: i32,
+    pub slack_user_id: String,
+    pub email: String,
+    pub first_name: String,
+    pub step: String,
+    pub deliver_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Get when to send a welcome message the number of days after someone joined. The
+/// first is sent right away and the rest at the next `WELCOME_MESSAGE_SCHEDULE` time on
+/// that day in their timezone.
+#[instrument]
+#[inline]
+pub fn welcome_deliver_at(joined: DateTime<Utc>, days: i64, tz: &Tz) -> DateTime<Utc> {
+    if days == 0 {
+        return joined;
+    }
+
+    let date = joined.with_timezone(tz).date().naive_local() + Duration::days(days);
+    let start_of_day = tz
+        .from_local_datetime(&date.and_hms(0, 0, 0))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or(joined + Duration::days(days));
+
+    CronSchedule::parse(WELCOME_MESSAGE_SCHEDULE).unwrap().next_after(start_of_day, tz).unwrap_or(start_of_day)
+}
+
+/// Schedule the welcome messages for someone who joined Slack, from the user in a
+/// `team_join` event. Only people with an email in our domain are welcomed, not
+/// guests or bots. Returns what was done.
+/// FROM: https://api.slack.com/events/team_join
+#[instrument(skip(db))]
+#[inline]
+pub fn start_welcome_sequence(db: &Database, user: &Value, now: DateTime<Utc>) -> Result<String, String> {
+    let slack_user_id = user["id"].as_str().unwrap_or_default();
+    let email = user["profile"]["email"].as_str().unwrap_or_default().to_lowercase();
+    if user["is_bot"].as_bool().unwrap_or_default() || user["is_restricted"].as_bool().unwrap_or_default() || user["is_ultra_restricted"].as_bool().unwrap_or_default() {
+        return Ok(format!("not welcoming {}, they are a bot or a guest", slack_user_id));
+    }
+    if !email.ends_with(&format!("@{}", GSUITE_DOMAIN)) {
+        return Ok(format!("not welcoming {}, {} is not in our domain", slack_user_id, email));
+    }
+
+    let first_name = user["profile"]["first_name"].as_str().or_else(|| user["real_name"].as_str()).unwrap_or_default();
+    let tz = parse_timezone(user["tz"].as_str().unwrap_or_default()).unwrap_or(Tz::UTC);
+    let messages: Vec<NewWelcomeMessage> = WELCOME_STEPS
+        .iter()
+        .map(|(step, days)| NewWelcomeMessage {
+            slack_user_id: slack_user_id.to_string(),
+            email: email.to_string(),
+            first_name: first_name.to_string(),
+            step: step.to_string(),
+            deliver_at: welcome_deliver_at(now, *days, &tz),
+            sent_at: None,
+            created_at: now,
+        })
+        .collect();
+    let inserted = diesel::insert_into(welcome_messages::table)
+        .values(&messages)
+        .on_conflict((welcome_messages::slack_user_id, welcome_messages::step))
+        .do_nothing()
+        .execute(&db.conn())
+        .map_err(|e| format!("scheduling the welcome messages for {} failed: {}", email, e))?;
+
+    Ok(format!("scheduled {} welcome messages for {}", inserted, email))
+}
+
+/// Format a welcome message with the links for its step.
+#[instrument(skip(links))]
+#[inline]
+pub fn format_welcome_message(step: &str, first_name: &str, links: &[LinkConfig]) -> Value {
+    let lines: Vec<String> = links
+        .iter()
+        .map(|l| {
+            if l.description.is_empty() {
+                format!("• <{}|{}>", l.link, l.name)
+            } else {
+                format!("• <{}|{}>: {}", l.link, l.name, l.description)
+            }
+        })
+        .collect();
+
+    json!({
+        "text": render_message(&format!("welcome.{}", step), &json!({ "first_name": first_name, "lines": lines })),
+    })
+}
+
+/// Send the welcome messages that are due. Messages that fail to send are tried again
+/// the next time this runs.
+#[instrument(skip(db, config))]
+#[inline]
+pub async fn send_welcome_messages(db: &Database, config: &Config) {
+    let due: Vec<WelcomeMessage> = welcome_messages::table
+        .filter(welcome_messages::sent_at.is_null())
+        .filter(welcome_messages::deliver_at.le(Utc::now()))
+        .order_by(welcome_messages::deliver_at)
+        .load(&db.conn())
+        .unwrap_or_else(|e| panic!("getting the welcome messages that are due failed: {}", e));
+
+    for m in due {
+        // The links are keyed by their names in our configs, they do not have them set.
+        let links: Vec<LinkConfig> = config
+            .welcome
+            .links
+            .get(&m.step)
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|name| config.links.get(name).map(|l| LinkConfig { name: name.to_string(), ..l.clone() }))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Err(e) = post_to_user_id(&m.slack_user_id, format_welcome_message(&m.step, &m.first_name, &links)).await {
+            println!("[welcome] {}", e);
+            continue;
+        }
+
+        diesel::update(welcome_messages::table.filter(welcome_messages::id.eq(m.id)))
+            .set(welcome_messages::sent_at.eq(Utc::now()))
+            .execute(&db.conn())
+            .unwrap_or_else(|e| panic!("marking the welcome message {} as sent failed: {}", m.id, e));
+        println!("[welcome] sent the {} message to {}", m.step, m.email);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    use crate::configs::LinkConfig;
+    use crate::welcome::{format_welcome_message, welcome_deliver_at};
+
+    #[test]
+    fn test_welcome_deliver_at() {
+        // Joined on a Monday afternoon in California.
+        let joined = Utc.ymd(2021, 5, 3).and_hms(22, 30, 0);
+        let tz: Tz = "America/Los_Angeles".parse().unwrap();
+        assert_eq!(welcome_deliver_at(joined, 0, &tz), joined);
+        assert_eq!(welcome_deliver_at(joined, 2, &tz), Utc.ymd(2021, 5, 5).and_hms(16, 0, 0));
+        assert_eq!(welcome_deliver_at(joined, 7, &tz), Utc.ymd(2021, 5, 10).and_hms(16, 0, 0));
+
+        // It is already Tuesday in Tokyo.
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        assert_eq!(welcome_deliver_at(joined, 2, &tz), Utc.ymd(2021, 5, 6).and_hms(0, 0, 0));
+    }
+
+    #[test]
+    fn test_format_welcome_message() {
+        let handbook = LinkConfig {
+            name: "handbook".to_string(),
+            description: "How we work".to_string(),
+            link: "https://handbook.example.com".to_string(),
+            ..Default::default()
+        };
+        let rfd = LinkConfig {
+            name: "rfd".to_string(),
+            link: "https://rfd.example.com".to_string(),
+            ..Default::default()
+        };
+
+        let msg = format_welcome_message("tools", "Jane", &[handbook, rfd]);
+        let text = msg["text"].as_str().unwrap();
+        assert!(text.starts_with("Hi Jane,"), "{}", text);
+        assert!(text.ends_with("\n• <https://handbook.example.com|handbook>: How we work\n• <https://rfd.example.com|rfd>"), "{}", text);
+    }
+}
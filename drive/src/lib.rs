@@ -15,7 +15,9 @@
  *     // Get the GSuite credentials file.
  *     let gsuite_credential_file = env::var("GADMIN_CREDENTIAL_FILE").unwrap();
  *     let gsuite_subject = env::var("GADMIN_SUBJECT").unwrap();
- *     let gsuite_secret = read_service_account_key(gsuite_credential_file).await.expect("failed to read gsuite credential file");
+ *     let gsuite_secret = read_service_account_key(gsuite_credential_file)
+ *         .await
+ *         .expect("failed to read gsuite credential file");
  *     let auth = ServiceAccountAuthenticator::builder(gsuite_secret)
  *         .subject(gsuite_subject.to_string())
  *         .build()
@@ -23,7 +25,10 @@
  *         .expect("failed to create authenticator");
  *
  *     // Add the scopes to the secret and get the token.
- *     let token = auth.token(&["https://www.googleapis.com/auth/drive"]).await.expect("failed to get token");
+ *     let token = auth
+ *         .token(&["https://www.googleapis.com/auth/drive"])
+ *         .await
+ *         .expect("failed to get token");
  *
  *     if token.as_str().is_empty() {
  *         panic!("empty token is not valid");
@@ -50,7 +55,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use bytes::Bytes;
-use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest::{header, Client, Method, Request, Response, StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use yup_oauth2::AccessToken;
 
@@ -60,6 +65,7 @@ const ENDPOINT: &str = "https://www.googleapis.com/drive/v3/";
 /// Entrypoint for interacting with the Google Drive API.
 pub struct GoogleDrive {
     token: AccessToken,
+    on_request: Option<fn(&Request)>,
 
     client: Arc<Client>,
 }
@@ -71,16 +77,36 @@ impl GoogleDrive {
     pub fn new(token: AccessToken) -> Self {
         let client = Client::builder().timeout(Duration::from_secs(360)).build();
         match client {
-            Ok(c) => Self { token, client: Arc::new(c) },
+            Ok(c) => Self {
+                token,
+                on_request: None,
+                client: Arc::new(c),
+            },
             Err(e) => panic!("creating client failed: {:?}", e),
         }
     }
 
+    /// Call a function with every request before it is sent, ie. to count the
+    /// requests we make. A paginated list calls it once for each page.
+    pub fn with_request_hook(mut self, hook: fn(&Request)) -> Self {
+        self.on_request = Some(hook);
+        self
+    }
+
     /// Get the currently set authorization token.
     pub fn get_token(&self) -> &AccessToken {
         &self.token
     }
 
+    /// Send a request, calling the request hook first if there is one.
+    async fn execute(&self, request: Request) -> Result<Response, reqwest::Error> {
+        if let Some(hook) = self.on_request {
+            hook(&request);
+        }
+
+        self.client.execute(request).await
+    }
+
     fn request<B>(&self, method: Method, path: String, body: B, query: Option<Vec<(&str, String)>>, content: &[u8], mime_type: &str) -> Request
     where
         B: Serialize,
@@ -160,7 +186,7 @@ impl GoogleDrive {
             "",
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -179,7 +205,7 @@ impl GoogleDrive {
         // Build the request.
         let request = self.request(Method::GET, format!("files/{}/export", id), (), Some(vec![("mimeType", "text/plain".to_string())]), &[], "");
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -199,7 +225,7 @@ impl GoogleDrive {
         // Build the request.
         let request = self.request(Method::GET, format!("files/{}", id), (), Some(vec![("supportsAllDrives", "true".to_string())]), &[], "");
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -226,13 +252,14 @@ impl GoogleDrive {
                 ("supportsAllDrives", "true".to_string()),
                 ("includeItemsFromAllDrives", "true".to_string()),
                 ("driveId", drive_id.to_string()),
-                ("q", format!("name = '{}'", name)),
+                // Quotes and backslashes in the name have to be escaped in the query.
+                ("q", format!("name = '{}'", name.replace('\\', "\\\\").replace('\'', "\\'"))),
             ]),
             &[],
             "",
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -254,7 +281,7 @@ impl GoogleDrive {
         // Build the request.
         let request = self.request(Method::GET, "drives".to_string(), (), Some(vec![("useDomainAdminAccess", "true".to_string())]), &[], "");
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -310,7 +337,7 @@ impl GoogleDrive {
             folder_mime_type,
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             StatusCode::CREATED => (),
@@ -374,7 +401,7 @@ impl GoogleDrive {
             "",
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -391,7 +418,7 @@ impl GoogleDrive {
         // Now upload the file to that location.
         let request = self.request(Method::PUT, location.to_string(), (), None, contents, mime_type);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             StatusCode::CREATED => (),
@@ -406,6 +433,65 @@ impl GoogleDrive {
         Ok(())
     }
 
+    /// Create a new file in a drive, even if one with the same name already exists,
+    /// returning the file.
+    pub async fn create_file(&self, drive_id: &str, parent_id: &str, name: &str, mime_type: &str, contents: &[u8]) -> Result<File, APIError> {
+        let mut f: File = Default::default();
+        f.name = name.to_string();
+        f.mime_type = mime_type.to_string();
+        if !parent_id.is_empty() {
+            f.parents = vec![parent_id.to_string()];
+        } else {
+            f.parents = vec![drive_id.to_string()];
+        }
+
+        // Build the request to get the URL upload location.
+        let request = self.request(
+            Method::POST,
+            "https://www.googleapis.com/upload/drive/v3/files".to_string(),
+            f,
+            Some(vec![
+                ("uploadType", "resumable".to_string()),
+                ("supportsAllDrives", "true".to_string()),
+                ("includeItemsFromAllDrives", "true".to_string()),
+            ]),
+            &[],
+            "",
+        );
+
+        let resp = self.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                });
+            }
+        };
+
+        // Get the "Location" header.
+        let location = resp.headers().get("Location").unwrap().to_str().unwrap();
+
+        // Now upload the file to that location.
+        let request = self.request(Method::PUT, location.to_string(), (), None, contents, mime_type);
+
+        let resp = self.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            StatusCode::CREATED => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                });
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
     /// Delete a file by its name.
     pub async fn delete_file_by_name(&self, drive_id: &str, name: &str) -> Result<(), APIError> {
         // Check if the file exists.
@@ -419,7 +505,7 @@ impl GoogleDrive {
         // Make the request.
         let request = self.request(Method::DELETE, format!("files/{}", file.id), (), Some(vec![("includeItemsFromAllDrives", "true".to_string())]), &[], "");
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             StatusCode::CREATED => (),
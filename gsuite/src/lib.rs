@@ -63,7 +63,7 @@ use chrono::offset::Utc;
 use chrono::DateTime;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest::{header, Client, Method, Request, Response, StatusCode, Url};
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize, Serializer};
 use serde_json::value::Value;
@@ -84,6 +84,7 @@ pub struct GSuite {
     domain: String,
 
     token: AccessToken,
+    on_request: Option<fn(&Request)>,
 
     client: Arc<Client>,
 }
@@ -98,15 +99,32 @@ impl GSuite {
             customer: customer.to_string(),
             domain: domain.to_string(),
             token,
+            on_request: None,
             client: Arc::new(client),
         }
     }
 
+    /// Call a function with every request before it is sent, ie. to count the
+    /// requests we make. A paginated list calls it once for each page.
+    pub fn with_request_hook(mut self, hook: fn(&Request)) -> Self {
+        self.on_request = Some(hook);
+        self
+    }
+
     /// Get the currently set authorization token.
     pub fn get_token(&self) -> &AccessToken {
         &self.token
     }
 
+    /// Send a request, calling the request hook first if there is one.
+    async fn execute(&self, request: Request) -> Result<Response, reqwest::Error> {
+        if let Some(hook) = self.on_request {
+            hook(&request);
+        }
+
+        self.client.execute(request).await
+    }
+
     fn request<B>(&self, endpoint: &str, method: Method, path: &str, body: B, query: Option<&[(&str, &str)]>) -> Request
     where
         B: Serialize,
@@ -148,7 +166,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::GET, "groups", (), Some(&[("customer", &self.customer), ("domain", &self.domain)]));
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -170,7 +188,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(GROUPS_SETTINGS_ENDPOINT, Method::GET, group_email, (), Some(&[("alt", "json")]));
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -190,7 +208,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::PUT, &format!("groups/{}", group.id), group, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -209,7 +227,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(GROUPS_SETTINGS_ENDPOINT, Method::PUT, &settings.email, settings, Some(&[("alt", "json")]));
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -228,7 +246,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::POST, "groups", group, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -261,7 +279,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::POST, &format!("groups/{}/aliases", group_key), a, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -284,7 +302,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::GET, &format!("groups/{}/hasMember/{}", group_id, email), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -317,7 +335,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::PUT, &format!("groups/{}/members/{}", group_id, email), member, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -347,7 +365,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::POST, &format!("groups/{}/members", group_id), member, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -366,7 +384,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::DELETE, &format!("groups/{}/members/{}", group_id, email), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -387,7 +405,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::DELETE, &format!("groups/{}", group_key), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -412,7 +430,7 @@ impl GSuite {
             Some(&[("customer", &self.customer), ("domain", &self.domain), ("projection", "full")]),
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -434,7 +452,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::PUT, &format!("users/{}", user.id), user, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -453,7 +471,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::POST, "users", user, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -475,7 +493,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::DELETE, &format!("users/{}", user_key), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -507,7 +525,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::POST, &format!("users/{}/aliases", user_id), a, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             StatusCode::NOT_FOUND => {
@@ -537,7 +555,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::GET, &format!("customer/{}/resources/calendars", self.customer), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -565,7 +583,7 @@ impl GSuite {
             None,
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -584,7 +602,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::POST, &format!("customer/{}/resources/calendars", self.customer), resource, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -604,7 +622,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::DELETE, &format!("customer/{}/resources/calendars/{}", self.customer, id), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -623,7 +641,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::GET, &format!("customer/{}/resources/buildings", self.customer), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -651,7 +669,7 @@ impl GSuite {
             None,
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -670,7 +688,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::POST, &format!("customer/{}/resources/buildings", self.customer), building, None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -690,7 +708,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(DIRECTORY_ENDPOINT, Method::DELETE, &format!("customer/{}/resources/buildings/{}", self.customer, id), (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -709,7 +727,7 @@ impl GSuite {
         // Build the request.
         let request = self.request(CALENDAR_ENDPOINT, Method::GET, "users/me/calendarList", (), None);
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -737,7 +755,7 @@ impl GSuite {
             Some(&[("singleEvents", "true"), ("maxResults", "2500"), ("showDeleted", &show_deleted.to_string())]),
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -765,7 +783,7 @@ impl GSuite {
             Some(&[("singleEvents", "true"), ("maxResults", "2500"), ("timeMax", &Utc::now().to_rfc3339())]),
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
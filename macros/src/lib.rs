@@ -7,7 +7,7 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use serde::Deserialize;
 use serde_tokenstream::from_tokenstream;
-use syn::{Field, ItemStruct, Type};
+use syn::{Field, ItemStruct, Lit, Meta, NestedMeta, Type};
 
 /// The parameters passed to our macro.
 #[derive(Deserialize, Debug)]
@@ -15,6 +15,11 @@ struct Params {
     /// The name of the new struct that has the added fields of:
     ///   - id: i32
     ///   - airtable_record_id: String
+    ///   - source: String
+    ///   - last_synced_from: String
+    ///   - last_synced_at: Option<DateTime<Utc>>
+    ///   - extra: AirtableExtraFields
+    ///   - modified_at: Option<DateTime<Utc>>
     new_struct_name: String,
     /// The name of the table in Airtable where this information should be sync on every
     /// database operation.
@@ -22,6 +27,15 @@ struct Params {
     /// The Airtable base ID where this information should be sync on every
     /// database operation.
     airtable_base_id: String,
+    /// The system that feeds the records in this table by default, ie. `configs`, `airtable`,
+    /// or the name of an API like `github`. This is recorded on each record as its `source`
+    /// when it is created and as its `last_synced_from` when a sync changes it, so when a field
+    /// looks wrong we can tell where it came from.
+    source: String,
+    /// If the times of the syncs that change a record are only kept to the day, ie. for
+    /// anonymous feedback, where the time could tell who sent it.
+    #[serde(default)]
+    sync_times_by_day: bool,
     /// A boolean representing if the new struct has a custom PartialEq implementation.
     /// If so, we will not add the derive method PartialEq to the new struct.
     #[serde(default)]
@@ -30,6 +44,12 @@ struct Params {
     match_on: HashMap<String, String>,
 }
 
+/// Generate the database and Airtable functions for a struct. Fields that have a different
+/// name in Airtable can set the name of their column with `#[airtable_field = "..."]`,
+/// which is used whenever we send the record to or read it from Airtable. Linked-record
+/// fields can hold the keys of the records they link to, rather than their Airtable record
+/// IDs, with `#[airtable_link = "Model"]`, where `Model` is a model with a single field to
+/// match on whose table is also in the base of the struct.
 #[proc_macro_attribute]
 pub fn db(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     do_db(attr.into(), item.into()).into()
@@ -58,29 +78,108 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut filter = quote!();
     let mut args = quote!();
     let mut function_args = quote!();
+    let mut key_args = quote!();
+    let mut match_on_fields = quote!();
+    // The filter for the record `r` in a batch, ie. `email = r.email AND sheet_id = r.sheet_id`.
+    let mut match_filter = quote!();
+    // Models with a single field to match on can be linked to from other models, by the
+    // value of that field.
+    let mut airtable_link_key = quote!();
+    if params.match_on.len() == 1 {
+        let f = format_ident!("{}", params.match_on.keys().next().unwrap());
+        airtable_link_key = quote! {
+            /// The key other models link to this record by, the field we match on.
+            pub fn airtable_link_key(&self) -> String {
+                self.#f.to_string()
+            }
+        };
+    }
     for (field, type_) in params.match_on {
         let f = format_ident!("{}", field);
         let t: Type = syn::parse_str(&type_).unwrap();
         filter = quote!(#filter.filter(#db_schema::dsl::#f.eq(#f.clone())));
         args = quote!(#args,#f: #t);
         function_args = quote!(#function_args self.#f.clone(),);
+        key_args = quote!(#key_args &#f,);
+        match_on_fields = quote!(#match_on_fields #field,);
+        let eq = quote!(crate::schema::#db_schema::dsl::#f.eq(r.#f.clone()));
+        match_filter = if match_filter.is_empty() { eq } else { quote!(#match_filter.and(#eq)) };
     }
 
     // Get the original struct information.
-    let og_struct: ItemStruct = syn::parse2(item.clone()).unwrap();
-    let mut fields: Vec<&Field> = Default::default();
+    let mut og_struct: ItemStruct = syn::parse2(item).unwrap();
+    let mut fields: Vec<Field> = Default::default();
     let mut struct_inners = quote!();
-    for field in og_struct.fields.iter() {
-        fields.push(field);
+    let mut airtable_field_names = quote!();
+    let mut attachment_mirrors = quote!();
+    let mut airtable_link_maps = quote!();
+    let mut airtable_link_keys = quote!();
+    let mut airtable_link_ids = quote!();
+    for field in og_struct.fields.iter_mut() {
         let ident = field.ident.clone();
+
+        // Fields can be named differently in Airtable with `#[airtable_field = "..."]`.
+        // The attribute is only for us, so we take it off the field.
+        if let Some(column) = take_string_attribute(field, "airtable_field") {
+            let name = serialized_field_name(field);
+            airtable_field_names = quote!(#airtable_field_names (#name, #column),);
+        }
+
+        // Linked-record fields declared with `#[airtable_link = "Model"]` hold the keys of
+        // the records they link to, rather than their Airtable record IDs.
+        if let Some(model) = take_string_attribute(field, "airtable_link") {
+            let model: syn::Path = syn::parse_str(&model).unwrap();
+            airtable_link_maps = quote! {
+                #airtable_link_maps
+                // Airtable can only link records in the same base, so the linked table is
+                // listed from the base of this model, ie. a synced copy of the table.
+                let records: Vec<airtable_api::Record<serde_json::Value>> = #new_struct_name::airtable()
+                    .list_records(&#model::airtable_table(), "Grid view", vec![])
+                    .await
+                    .unwrap_or_else(crate::rate_limits::airtable_failed);
+                links.insert(
+                    &#model::airtable_table(),
+                    records
+                        .into_iter()
+                        .filter_map(|r| #model::from_airtable_record(r).map_err(|e| println!("[airtable] skipping linked record: {}", e)).ok())
+                        .map(|r| (r.id, r.fields.airtable_link_key()))
+                        .collect(),
+                );
+            };
+            airtable_link_keys = quote!(#airtable_link_keys self.#ident = links.keys(&#model::airtable_table(), &self.#ident););
+            airtable_link_ids = quote!(#airtable_link_ids self.#ident = links.ids(&#model::airtable_table(), &self.#ident););
+        }
+
+        // The URLs of attachments from Airtable expire, so we mirror them.
+        if is_attachments_field(field) {
+            attachment_mirrors = quote!(#attachment_mirrors self.#ident.mirror(&#new_struct_name::airtable_table()).await;);
+        }
+
+        fields.push(field.clone());
         struct_inners = quote!(#struct_inners#ident: item.#ident.clone(),);
     }
-    let og_struct_name = og_struct.ident;
+    let og_struct_name = og_struct.ident.clone();
+
+    // When a batched upsert updates a record, every field is set from the row we tried to insert.
+    let mut excluded_set = quote!();
+    for field in &fields {
+        let ident = field.ident.clone();
+        excluded_set = quote!(#excluded_set crate::schema::#db_schema::dsl::#ident.eq(diesel::upsert::excluded(crate::schema::#db_schema::dsl::#ident)),);
+    }
 
     // Get the Airtable information.
     let airtable_base_id = format_ident!("{}", params.airtable_base_id);
     let airtable_table = format_ident!("{}", params.airtable_table);
 
+    let source = params.source;
+    let synced_at_now = if params.sync_times_by_day {
+        quote!(chrono::Utc::today().and_hms(0, 0, 0))
+    } else {
+        quote!(chrono::Utc::now())
+    };
+    // The records whose times are only kept to the day changed since the start of the day.
+    let modified_cutoff = if params.sync_times_by_day { quote!(since.date().and_hms(0, 0, 0)) } else { quote!(since) };
+
     let airtable = quote! {
     // Import what we need from diesel so the database queries work.
     use diesel::prelude::*;
@@ -104,17 +203,63 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[instrument(skip(db))]
         #[inline]
         pub fn create_in_db(&self, db: &crate::db::Database) -> #new_struct_name {
-            diesel::insert_into(crate::schema::#db_schema::table)
+            self.create_in_db_from(db, #source)
+        }
+
+        /// Create a new record in the database, recording where the data came from.
+        #[instrument(skip(db))]
+        #[inline]
+        pub fn create_in_db_from(&self, db: &crate::db::Database, from: &str) -> #new_struct_name {
+            #[cfg(feature = "sqlite")]
+            {
+                if let Some(store) = db.sqlite() {
+                    crate::job_runs::count_rows_written(1);
+                    return store.save(stringify!(#db_schema), &self.match_on_key(), self, None::<&#new_struct_name>, from, #new_struct_name::synced_at_now());
+                }
+            }
+
+            let r: #new_struct_name = diesel::insert_into(crate::schema::#db_schema::table)
                 .values(self)
                 .get_result(&db.conn())
-                .unwrap_or_else(|e| panic!("creating record {:?} failed: {}", self, e))
+                .unwrap_or_else(|e| panic!("creating record {:?} failed: {}", self, e));
+            crate::job_runs::count_rows_written(1);
+
+            // Set the provenance for the record.
+            diesel::update(&r)
+                .set((
+                    crate::schema::#db_schema::dsl::source.eq(from),
+                    crate::schema::#db_schema::dsl::last_synced_from.eq(from),
+                    crate::schema::#db_schema::dsl::last_synced_at.eq(Some(#new_struct_name::synced_at_now())),
+                    crate::schema::#db_schema::dsl::modified_at.eq(Some(#new_struct_name::synced_at_now())),
+                ))
+                .get_result::<#new_struct_name>(&db.conn())
+                .unwrap_or_else(|e| panic!("unable to set the provenance for record {}: {}", r.id, e))
+        }
+
+        /// The fields we match on to find the existing record for this one in the database.
+        pub fn match_on_fields() -> Vec<&'static str> {
+            vec![#match_on_fields]
+        }
+
+        /// Get the existing record in the database with the same `match_on` fields as this one.
+        #[instrument(skip(db))]
+        #[inline]
+        pub fn get_existing_from_db(&self, db: &crate::db::Database) -> Option<#new_struct_name> {
+            #new_struct_name::get_from_db(db, #function_args)
         }
 
         /// Create or update the record in the database and Airtable.
         #[instrument(skip(db))]
         #[inline]
         pub async fn upsert(&self, db: &crate::db::Database) -> #new_struct_name {
-            let mut record = self.upsert_in_db(db);
+            self.upsert_from(db, #source).await
+        }
+
+        /// Create or update the record in the database and Airtable, recording where the data came from.
+        #[instrument(skip(db))]
+        #[inline]
+        pub async fn upsert_from(&self, db: &crate::db::Database, from: &str) -> #new_struct_name {
+            let mut record = self.upsert_in_db_from(db, from);
 
             // Let's also update this record in Airtable.
             let new_airtable_record = record.upsert_in_airtable().await;
@@ -132,16 +277,167 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[instrument(skip(db))]
         #[inline]
         pub fn upsert_in_db(&self, db: &crate::db::Database) -> #new_struct_name {
+            self.upsert_in_db_from(db, #source)
+        }
+
+        /// Create or update the record in the database, recording where the data came from.
+        #[instrument(skip(db))]
+        #[inline]
+        pub fn upsert_in_db_from(&self, db: &crate::db::Database, from: &str) -> #new_struct_name {
             // See if we already have the record in the database.
             if let Some(r) = #new_struct_name::get_from_db(db, #function_args) {
+                // If nothing changed we leave the record as is, so the time it was
+                // last synced is the last time its data actually changed.
+                if #og_struct_name::from(r.clone()) == *self {
+                    return r;
+                }
+
                 // Update the record.
+                crate::job_runs::count_rows_written(1);
+                #[cfg(feature = "sqlite")]
+                {
+                    if let Some(store) = db.sqlite() {
+                        return store.save(stringify!(#db_schema), &self.match_on_key(), self, Some(&r), from, #new_struct_name::synced_at_now());
+                    }
+                }
                 return diesel::update(&r)
-                    .set(self)
+                    .set((
+                        self,
+                        crate::schema::#db_schema::dsl::last_synced_from.eq(from),
+                        crate::schema::#db_schema::dsl::last_synced_at.eq(Some(#new_struct_name::synced_at_now())),
+                        crate::schema::#db_schema::dsl::modified_at.eq(Some(#new_struct_name::synced_at_now())),
+                    ))
                     .get_result::<#new_struct_name>(&db.conn())
                     .unwrap_or_else(|e| panic!("unable to update record {}: {}", r.id, e));
             }
 
-            self.create_in_db(db)
+            self.create_in_db_from(db, from)
+        }
+
+        /// The values of the `match_on` fields of the record, to tell the records in a batch apart.
+        pub fn match_on_key(&self) -> String {
+            format!("{:?}", (#function_args))
+        }
+
+        /// Get the existing records in the database with the same `match_on` fields as a batch of records.
+        #[instrument(skip(db, records))]
+        #[inline]
+        pub fn get_all_existing_from_db(db: &crate::db::Database, records: &[Self]) -> Vec<#new_struct_name> {
+            #[cfg(feature = "sqlite")]
+            {
+                if let Some(store) = db.sqlite() {
+                    return records.iter().filter_map(|r| store.find(stringify!(#db_schema), &r.match_on_key())).collect();
+                }
+            }
+
+            let mut existing: Vec<#new_struct_name> = Default::default();
+            for chunk in records.chunks(crate::db::BATCH_SIZE) {
+                let mut query = crate::schema::#db_schema::dsl::#db_schema.into_boxed();
+                for r in chunk {
+                    query = query.or_filter(#match_filter);
+                }
+                existing.append(&mut query.load::<#new_struct_name>(&db.conn()).unwrap_or_else(|e| panic!("getting the existing records for a batch failed: {}", e)));
+            }
+
+            existing
+        }
+
+        /// Create or update a batch of records in the database.
+        #[instrument(skip(db, records))]
+        #[inline]
+        pub fn upsert_all(db: &crate::db::Database, records: &[Self]) -> crate::db::UpsertedRecords<#new_struct_name> {
+            #og_struct_name::upsert_all_from(db, records, #source)
+        }
+
+        /// Create or update a batch of records in the database, recording where the data came from.
+        /// Rather than a few round trips per record like `upsert_in_db_from`, this takes one query to
+        /// find the existing records, one to insert the new ones, and one to update the changed ones
+        /// for every `BATCH_SIZE` records. Records that did not change are left as they are.
+        #[instrument(skip(db, records))]
+        #[inline]
+        pub fn upsert_all_from(db: &crate::db::Database, records: &[Self], from: &str) -> crate::db::UpsertedRecords<#new_struct_name> {
+            // If a record is in the batch more than once the last one wins, like it would
+            // upserting them one at a time.
+            let mut batch: std::collections::BTreeMap<String, Self> = Default::default();
+            for r in records {
+                batch.insert(r.match_on_key(), r.clone());
+            }
+            let batch: Vec<Self> = batch.into_iter().map(|(_, r)| r).collect();
+
+            let mut result: crate::db::UpsertedRecords<#new_struct_name> = Default::default();
+            for chunk in batch.chunks(crate::db::BATCH_SIZE) {
+                let existing: std::collections::BTreeMap<String, #new_struct_name> =
+                    #og_struct_name::get_all_existing_from_db(db, chunk).into_iter().map(|r| (r.match_on_key(), r)).collect();
+
+                let mut new_records: Vec<&Self> = Default::default();
+                let mut changed_records: Vec<(i32, &Self)> = Default::default();
+                for r in chunk {
+                    match existing.get(&r.match_on_key()) {
+                        Some(e) if #og_struct_name::from(e.clone()) == *r => result.unchanged.push(e.clone()),
+                        Some(e) => changed_records.push((e.id, r)),
+                        None => new_records.push(r),
+                    }
+                }
+
+                #[cfg(feature = "sqlite")]
+                {
+                    if let Some(store) = db.sqlite() {
+                        let now = #new_struct_name::synced_at_now();
+                        crate::job_runs::count_rows_written(new_records.len() + changed_records.len());
+                        for r in new_records {
+                            result.created.push(store.save(stringify!(#db_schema), &r.match_on_key(), r, None::<&#new_struct_name>, from, now));
+                        }
+                        for (_, r) in changed_records {
+                            result.updated.push(store.save(stringify!(#db_schema), &r.match_on_key(), r, existing.get(&r.match_on_key()), from, now));
+                        }
+                        continue;
+                    }
+                }
+
+                let now = Some(#new_struct_name::synced_at_now());
+                if !new_records.is_empty() {
+                    let ids: Vec<i32> = diesel::insert_into(crate::schema::#db_schema::table)
+                        .values(new_records)
+                        .returning(crate::schema::#db_schema::dsl::id)
+                        .get_results(&db.conn())
+                        .unwrap_or_else(|e| panic!("creating a batch of records failed: {}", e));
+
+                    // Set the provenance for the records.
+                    let mut created = diesel::update(crate::schema::#db_schema::dsl::#db_schema.filter(crate::schema::#db_schema::dsl::id.eq_any(ids)))
+                        .set((
+                            crate::schema::#db_schema::dsl::source.eq(from),
+                            crate::schema::#db_schema::dsl::last_synced_from.eq(from),
+                            crate::schema::#db_schema::dsl::last_synced_at.eq(now),
+                            crate::schema::#db_schema::dsl::modified_at.eq(now),
+                        ))
+                        .get_results::<#new_struct_name>(&db.conn())
+                        .unwrap_or_else(|e| panic!("unable to set the provenance for a batch of records: {}", e));
+                    crate::job_runs::count_rows_written(created.len());
+                    result.created.append(&mut created);
+                }
+
+                if !changed_records.is_empty() {
+                    // Update the changed records in one statement by inserting them with their ids,
+                    // which conflict with the existing records.
+                    let rows: Vec<_> = changed_records.into_iter().map(|(id, r)| (crate::schema::#db_schema::dsl::id.eq(id), r)).collect();
+                    let mut updated = diesel::insert_into(crate::schema::#db_schema::table)
+                        .values(rows)
+                        .on_conflict(crate::schema::#db_schema::dsl::id)
+                        .do_update()
+                        .set((
+                            #excluded_set
+                            crate::schema::#db_schema::dsl::last_synced_from.eq(from),
+                            crate::schema::#db_schema::dsl::last_synced_at.eq(now),
+                            crate::schema::#db_schema::dsl::modified_at.eq(now),
+                        ))
+                        .get_results::<#new_struct_name>(&db.conn())
+                        .unwrap_or_else(|e| panic!("updating a batch of records failed: {}", e));
+                    crate::job_runs::count_rows_written(updated.len());
+                    result.updated.append(&mut updated);
+                }
+            }
+
+            result
         }
     }
 
@@ -154,6 +450,26 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 
     impl #new_struct_name {
+        /// Record that the data in the record was last changed by a sync from `from`, ie. `airtable`
+        /// or the name of an API. Call this before `update` or `update_in_db` when changing a record
+        /// with data from somewhere other than where the record is usually synced from.
+        #[instrument]
+        #[inline]
+        pub fn set_synced_from(&mut self, from: &str) {
+            self.last_synced_from = from.to_string();
+            self.last_synced_at = Some(#new_struct_name::synced_at_now());
+        }
+
+        /// The time to record for a sync that changes the record now.
+        pub fn synced_at_now() -> chrono::DateTime<chrono::Utc> {
+            #synced_at_now
+        }
+
+        /// The values of the `match_on` fields of the record, to tell the records in a batch apart.
+        pub fn match_on_key(&self) -> String {
+            format!("{:?}", (#function_args))
+        }
+
         /// Update the record in the database and Airtable.
         #[instrument(skip(db))]
         #[inline]
@@ -173,9 +489,20 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[instrument(skip(db))]
         #[inline]
         pub fn update_in_db(&self, db: &crate::db::Database) -> Self {
-            // Update the record.
+            // Update the record, marking it as modified so the next push to Airtable
+            // picks it up.
+            let mut record = self.clone();
+            record.modified_at = Some(#new_struct_name::synced_at_now());
+            crate::job_runs::count_rows_written(1);
+            #[cfg(feature = "sqlite")]
+            {
+                if let Some(store) = db.sqlite() {
+                    store.put(stringify!(#db_schema), record.id, &record.match_on_key(), &record);
+                    return record;
+                }
+            }
             diesel::update(self)
-                .set(self.clone())
+                .set(record)
                 .get_result::<#new_struct_name>(&db.conn())
                 .unwrap_or_else(|e| panic!("[db] unable to update record {}: {}", self.id, e))
         }
@@ -184,6 +511,13 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[tracing::instrument(skip(db))]
         #[inline]
         pub fn get_from_db(db: &crate::db::Database#args) -> Option<Self> {
+            #[cfg(feature = "sqlite")]
+            {
+                if let Some(store) = db.sqlite() {
+                    return store.find(stringify!(#db_schema), &format!("{:?}", (#key_args)));
+                }
+            }
+
             match #db_schema::dsl::#db_schema#filter.first::<#new_struct_name>(&db.conn()) {
                 Ok(r) => {
                     return Some(r);
@@ -199,11 +533,188 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[tracing::instrument]
         #[inline]
         pub async fn get_from_airtable(id: &str) -> Self {
-            let record = #new_struct_name::airtable()
+            let record: airtable_api::Record<serde_json::Value> = #new_struct_name::airtable()
                 .get_record(&#new_struct_name::airtable_table(), id)
-                .await.unwrap();
+                .await.unwrap_or_else(crate::rate_limits::airtable_failed);
+
+            let mut fields = #new_struct_name::from_airtable_fields(record.fields).unwrap();
+            fields.mirror_airtable_attachments().await;
+            fields.resolve_airtable_links(&#new_struct_name::airtable_links().await);
+            fields
+        }
+
+        #airtable_link_key
+
+        /// Get the records the linked-record fields link to, so we can resolve the links
+        /// between their Airtable record IDs and their keys.
+        #[allow(unused_mut)]
+        #[tracing::instrument]
+        #[inline]
+        pub async fn airtable_links() -> crate::airtable_links::AirtableLinks {
+            let mut links = crate::airtable_links::AirtableLinks::default();
+            #airtable_link_maps
+            links
+        }
+
+        /// Resolve the linked-record fields from the Airtable record IDs they link to, to
+        /// the keys of the records.
+        #[allow(unused_variables)]
+        pub fn resolve_airtable_links(&mut self, links: &crate::airtable_links::AirtableLinks) {
+            #airtable_link_keys
+        }
+
+        /// Replace the keys in the linked-record fields with the Airtable record IDs they link
+        /// to, before we send the record to Airtable.
+        #[allow(unused_variables)]
+        pub fn link_airtable_records(&mut self, links: &crate::airtable_links::AirtableLinks) {
+            #airtable_link_ids
+        }
+
+        /// Mirror the attachments in the record out of Airtable, since the URLs Airtable
+        /// gives us for them expire.
+        #[tracing::instrument]
+        #[inline]
+        pub async fn mirror_airtable_attachments(&mut self) {
+            #attachment_mirrors
+        }
+
+        /// The fields that have a different name in Airtable, from the name we serialize
+        /// the field as to the name of its column in Airtable.
+        pub fn airtable_field_names() -> Vec<(&'static str, &'static str)> {
+            vec![#airtable_field_names]
+        }
+
+        /// Serialize the record to the fields of an Airtable record, with the fields named
+        /// after their columns in Airtable. The extra fields are left out, see
+        /// `to_airtable_fields_with_extra`, and so are the provenance fields and the modified
+        /// time, which only we keep and which Airtable rejects since the tables have no
+        /// columns for them.
+        pub fn to_airtable_fields(&self) -> serde_json::Value {
+            let mut value = serde_json::to_value(self).unwrap();
+            if let Some(fields) = value.as_object_mut() {
+                for field in &["extra", "source", "last_synced_from", "last_synced_at", "modified_at"] {
+                    fields.remove(*field);
+                }
+                for (name, column) in #new_struct_name::airtable_field_names() {
+                    if let Some(v) = fields.remove(name) {
+                        fields.insert(column.to_string(), v);
+                    }
+                }
+            }
+
+            value
+        }
+
+        /// Serialize the record to the fields we send to Airtable, with the extra fields
+        /// Airtable will take back.
+        #[tracing::instrument]
+        #[inline]
+        pub async fn to_airtable_fields_with_extra(&self) -> serde_json::Value {
+            let mut value = self.to_airtable_fields();
+            crate::extra_fields::add_writable_extra_fields(&#new_struct_name::airtable_base_id(), &#new_struct_name::airtable_table(), &mut value, &self.extra).await;
+            value
+        }
+
+        /// Deserialize the record from the fields of an Airtable record, with the fields named
+        /// after their columns in Airtable. The fields we do not have are kept in `extra`.
+        pub fn from_airtable_fields(mut value: serde_json::Value) -> Result<Self, serde_json::Error> {
+            if let Some(fields) = value.as_object_mut() {
+                for (name, column) in #new_struct_name::airtable_field_names() {
+                    if let Some(v) = fields.remove(column) {
+                        fields.insert(name.to_string(), v);
+                    }
+                }
+            }
+
+            let (mut record, extra) = crate::extra_fields::split_extra_fields::<Self>(value)?;
+            record.extra = extra;
+            Ok(record)
+        }
+
+        /// Convert a record to what we send to Airtable.
+        pub fn to_airtable_record(record: airtable_api::Record<#new_struct_name>) -> airtable_api::Record<serde_json::Value> {
+            airtable_api::Record {
+                id: record.id,
+                created_time: record.created_time,
+                fields: record.fields.to_airtable_fields(),
+            }
+        }
+
+        /// Convert a record we got from Airtable, failing for records we can not read, ie.
+        /// with a status that is not one of the options.
+        pub fn from_airtable_record(record: airtable_api::Record<serde_json::Value>) -> Result<airtable_api::Record<#new_struct_name>, String> {
+            match #new_struct_name::from_airtable_fields(record.fields) {
+                Ok(fields) => Ok(airtable_api::Record {
+                    id: record.id,
+                    created_time: record.created_time,
+                    fields,
+                }),
+                Err(e) => Err(format!("reading record {} in {} failed: {}", record.id, #new_struct_name::airtable_table(), e)),
+            }
+        }
+
+        /// Get the ID of our record that a record from Airtable is for, without reading
+        /// the rest of its fields.
+        fn airtable_record_db_id(record: &airtable_api::Record<serde_json::Value>) -> Option<i32> {
+            record.fields.get("id").and_then(|id| id.as_i64()).map(|id| id as i32)
+        }
+
+        /// List the records in the Airtable table.
+        #[tracing::instrument]
+        #[inline]
+        pub async fn list_airtable_records() -> Vec<airtable_api::Record<#new_struct_name>> {
+            #new_struct_name::list_airtable_records_with_formula("", &#new_struct_name::airtable_links().await).await.0
+        }
+
+        /// List the records in the Airtable table that were modified since a time, resolving
+        /// their linked-record fields with the links from `airtable_links`.
+        #[tracing::instrument(skip(links))]
+        #[inline]
+        pub async fn list_airtable_records_modified_since(
+            since: chrono::DateTime<chrono::Utc>,
+            links: &crate::airtable_links::AirtableLinks,
+        ) -> Vec<airtable_api::Record<#new_struct_name>> {
+            #new_struct_name::list_airtable_records_with_formula(&crate::airtable_syncs::modified_since_formula(since), links).await.0
+        }
+
+        /// List the records in the Airtable table that match a formula, or every record if
+        /// the formula is empty. Also returns the IDs of our records that the records we
+        /// could not read are for, so a sync can leave them alone.
+        #[tracing::instrument(skip(links))]
+        #[inline]
+        async fn list_airtable_records_with_formula(
+            formula: &str,
+            links: &crate::airtable_links::AirtableLinks,
+        ) -> (Vec<airtable_api::Record<#new_struct_name>>, std::collections::BTreeSet<i32>) {
+            let records: Vec<airtable_api::Record<serde_json::Value>> = #new_struct_name::airtable()
+                .list_records_with_formula(&#new_struct_name::airtable_table(), "Grid view", vec![], formula)
+                .await
+                .unwrap_or_else(crate::rate_limits::airtable_failed);
+
+            // Records we can not read, ie. with a status that is not one of the options, are
+            // left out rather than failing the whole sync.
+            let mut unreadable: std::collections::BTreeSet<i32> = Default::default();
+            let mut records: Vec<airtable_api::Record<#new_struct_name>> = records
+                .into_iter()
+                .filter_map(|record| {
+                    let db_id = #new_struct_name::airtable_record_db_id(&record);
+                    match #new_struct_name::from_airtable_record(record) {
+                        Ok(r) => Some(r),
+                        Err(e) => {
+                            println!("[airtable] skipping: {}", e);
+                            unreadable.extend(db_id);
+                            None
+                        }
+                    }
+                })
+                .collect();
+
+            for record in records.iter_mut() {
+                record.fields.mirror_airtable_attachments().await;
+                record.fields.resolve_airtable_links(links);
+            }
 
-            record.fields
+            (records, unreadable)
         }
 
         /// Delete a record from the database and Airtable.
@@ -220,10 +731,19 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[instrument(skip(db))]
         #[inline]
         pub fn delete_from_db(&self, db: &crate::db::Database) {
-            diesel::delete(
+            #[cfg(feature = "sqlite")]
+            {
+                if let Some(store) = db.sqlite() {
+                    crate::job_runs::count_rows_written(store.delete(stringify!(#db_schema), self.id));
+                    return;
+                }
+            }
+
+            let deleted = diesel::delete(
                 crate::schema::#db_schema::dsl::#db_schema.filter(
                     crate::schema::#db_schema::dsl::id.eq(self.id)))
                     .execute(&db.conn()).unwrap();
+            crate::job_runs::count_rows_written(deleted);
         }
 
         /// Create the Airtable client.
@@ -231,14 +751,21 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[tracing::instrument]
         #[inline]
         fn airtable() -> airtable_api::Airtable {
-            airtable_api::Airtable::new(airtable_api::api_key_from_env(), #airtable_base_id, "")
+            crate::airtable::airtable_client(#airtable_base_id)
+        }
+
+        /// Return the Airtable base ID.
+        #[tracing::instrument]
+        #[inline]
+        pub fn airtable_base_id() -> String {
+            #airtable_base_id.to_string()
         }
 
         /// Return the Airtable table name.
         /// We do this in it's own function so our other functions are more DRY.
         #[tracing::instrument]
         #[inline]
-        fn airtable_table() -> String {
+        pub fn airtable_table() -> String {
             #airtable_table.to_string()
         }
 
@@ -246,34 +773,77 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[tracing::instrument]
         #[inline]
         pub async fn create_in_airtable(&mut self) -> airtable_api::Record<#new_struct_name> {
-            // Create the record.
+            self.create_in_airtable_with_links(&#new_struct_name::airtable_links().await).await
+        }
+
+        /// Create the row in the Airtable base, with the links from `airtable_links` so a
+        /// sync of many records only lists the linked tables once.
+        #[tracing::instrument(skip(links))]
+        #[inline]
+        pub async fn create_in_airtable_with_links(&mut self, links: &crate::airtable_links::AirtableLinks) -> airtable_api::Record<#new_struct_name> {
+            // Create the record, with the records it links to by their Airtable record IDs.
+            let mut fields = self.clone();
+            fields.link_airtable_records(links);
             let record = airtable_api::Record {
                 id: "".to_string(),
                 created_time: None,
-                fields: self.clone(),
+                fields: fields.to_airtable_fields_with_extra().await,
             };
 
             // Send the new record to the Airtable client.
-            let records : Vec<airtable_api::Record<#new_struct_name>> = #new_struct_name::airtable()
+            let records : Vec<airtable_api::Record<serde_json::Value>> = #new_struct_name::airtable()
                 .create_records(&#new_struct_name::airtable_table(), vec![record])
                 .await
-                .unwrap();
+                .unwrap_or_else(crate::rate_limits::airtable_failed);
 
             println!("[airtable] created new row: {:?}", self);
 
-            // Return the first record back.
-            records.get(0).unwrap().clone()
+            // Return the first record back, or what we sent if we can not read it.
+            let created = records.get(0).unwrap().clone();
+            match #new_struct_name::from_airtable_record(created.clone()) {
+                Ok(mut record) => {
+                    record.fields.resolve_airtable_links(links);
+                    record
+                }
+                Err(e) => {
+                    println!("[airtable] {}", e);
+                    airtable_api::Record {
+                        id: created.id,
+                        created_time: created.created_time,
+                        fields: self.clone(),
+                    }
+                }
+            }
         }
 
         /// Update the record in Airtable.
         #[tracing::instrument]
         #[inline]
         pub async fn update_in_airtable(&self, existing_record: &mut airtable_api::Record<#new_struct_name>) -> airtable_api::Record<#new_struct_name> {
+            self.update_in_airtable_with_links(existing_record, &#new_struct_name::airtable_links().await).await
+        }
+
+        /// Update the record in Airtable, with the links from `airtable_links` so a sync of
+        /// many records only lists the linked tables once.
+        #[tracing::instrument(skip(links))]
+        #[inline]
+        pub async fn update_in_airtable_with_links(
+            &self,
+            existing_record: &mut airtable_api::Record<#new_struct_name>,
+            links: &crate::airtable_links::AirtableLinks,
+        ) -> airtable_api::Record<#new_struct_name> {
             let mut mut_self = self.clone();
             // Run the custom trait to update the new record from the old record.
             // We do this because where we join Airtable tables, things tend to get a little
             // weird if we aren't nit picky about this.
             mut_self.update_airtable_record(existing_record.fields.clone()).await;
+            // Keep what people added in Airtable to the fields we do not have.
+            mut_self.extra = existing_record.fields.extra.clone();
+            // Airtable does not have the provenance fields, so they are not a change.
+            existing_record.fields.source = mut_self.source.to_string();
+            existing_record.fields.last_synced_from = mut_self.last_synced_from.to_string();
+            existing_record.fields.last_synced_at = mut_self.last_synced_at;
+            existing_record.fields.modified_at = mut_self.modified_at;
 
             // If the Airtable record and the record that was passed in are the same, then we can return early since
             // we do not need to update it in Airtable.
@@ -286,11 +856,15 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
 
             existing_record.fields = mut_self;
 
-            // Send the updated record to Airtable.
-            let records : Vec<airtable_api::Record<#new_struct_name>> = #new_struct_name::airtable().update_records(
+            // Send the updated record to Airtable, with the records it links to by their
+            // Airtable record IDs.
+            let mut record = existing_record.clone();
+            record.fields.link_airtable_records(links);
+            let fields = record.fields.to_airtable_fields_with_extra().await;
+            let records : Vec<airtable_api::Record<serde_json::Value>> = #new_struct_name::airtable().update_records(
                 &#new_struct_name::airtable_table(),
-                vec![existing_record.clone()],
-            ).await.unwrap();
+                vec![airtable_api::Record { id: record.id, created_time: record.created_time, fields }],
+            ).await.unwrap_or_else(crate::rate_limits::airtable_failed);
 
             println!("[airtable] id={} updated", self.id);
 
@@ -298,23 +872,61 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
                 return existing_record.clone();
             }
 
-            records.get(0).unwrap().clone()
+            match #new_struct_name::from_airtable_record(records.get(0).unwrap().clone()) {
+                Ok(mut record) => {
+                    record.fields.resolve_airtable_links(links);
+                    record
+                }
+                Err(e) => {
+                    // We sent what we have, so it is what is in Airtable now.
+                    println!("[airtable] {}", e);
+                    existing_record.clone()
+                }
+            }
         }
 
         /// Get the existing record in Airtable that matches this id.
         #[tracing::instrument]
         #[inline]
         pub async fn get_existing_airtable_record(&self) -> Option<airtable_api::Record<#new_struct_name>> {
-                // Let's get the existing record from airtable.
-                match #new_struct_name::airtable()
-                        .get_record(&#new_struct_name::airtable_table(), &self.airtable_record_id)
-                        .await {
-                            Ok(v) => return Some(v),
-                            Err(e) => {
-                                println!("getting airtable record failed: {}", self.airtable_record_id);
-                                return None;
-                            }
-                        }
+            self.get_existing_airtable_record_with_links(&#new_struct_name::airtable_links().await).await
+        }
+
+        /// Get the existing record in Airtable that matches this id, with the links from
+        /// `airtable_links` so a sync of many records only lists the linked tables once.
+        #[tracing::instrument(skip(links))]
+        #[inline]
+        pub async fn get_existing_airtable_record_with_links(&self, links: &crate::airtable_links::AirtableLinks) -> Option<airtable_api::Record<#new_struct_name>> {
+            match self.read_existing_airtable_record(links).await {
+                Ok(record) => record,
+                Err(e) => {
+                    println!("[airtable] {}", e);
+                    None
+                }
+            }
+        }
+
+        /// Get the existing record in Airtable that matches this id, `None` if we could not
+        /// get it and an error if we got it but can not read it.
+        #[tracing::instrument(skip(links))]
+        #[inline]
+        async fn read_existing_airtable_record(&self, links: &crate::airtable_links::AirtableLinks) -> Result<Option<airtable_api::Record<#new_struct_name>>, String> {
+            // Let's get the existing record from airtable.
+            let v = match #new_struct_name::airtable()
+                .get_record::<serde_json::Value>(&#new_struct_name::airtable_table(), &self.airtable_record_id)
+                .await
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    println!("getting airtable record {} failed: {}", self.airtable_record_id, e);
+                    return Ok(None);
+                }
+            };
+
+            let mut record = #new_struct_name::from_airtable_record(v)?;
+            record.fields.mirror_airtable_attachments().await;
+            record.fields.resolve_airtable_links(links);
+            Ok(Some(record))
         }
 
 
@@ -322,14 +934,26 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[tracing::instrument]
         #[inline]
         pub async fn upsert_in_airtable(&mut self) -> airtable_api::Record<#new_struct_name> {
+            let links = #new_struct_name::airtable_links().await;
+
             // First check if we have an `airtable_record_id` for this record.
             // If we do we can move ahead faster.
             if !self.airtable_record_id.is_empty() {
-                let mut er: Option<airtable_api::Record<#new_struct_name>> = self.get_existing_airtable_record().await;
-
-                if let Some(mut existing_record) = er {
-                    // Return the result from the update.
-                    return self.update_in_airtable(&mut existing_record).await;
+                match self.read_existing_airtable_record(&links).await {
+                    Ok(Some(mut existing_record)) => {
+                        // Return the result from the update.
+                        return self.update_in_airtable_with_links(&mut existing_record, &links).await;
+                    }
+                    Err(e) => {
+                        // Leave the record we can not read alone, rather than making another.
+                        println!("[airtable] skipping: {}", e);
+                        return airtable_api::Record {
+                            id: self.airtable_record_id.to_string(),
+                            created_time: None,
+                            fields: self.clone(),
+                        };
+                    }
+                    Ok(None) => (),
                 }
                 // Otherwise we need to continue through the other loop.
             }
@@ -339,16 +963,16 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             // This is slow so we should always try to make sure we have the airtable_record_id
             // set. This function is mostly here until we migrate away from the old way of doing
             // things.
-            let records = #new_struct_name_plural::get_from_airtable().await;
+            let records = #new_struct_name_plural::get_from_airtable_with_links(&links).await;
             for (id, record) in records {
                 if self.id == id {
-                    return self.update_in_airtable(&mut record.clone()).await;
+                    return self.update_in_airtable_with_links(&mut record.clone(), &links).await;
                 }
             }
 
             // We've tried everything to find the record in our existing Airtable but it is not
             // there. We need to create it.
-            self.create_in_airtable().await
+            self.create_in_airtable_with_links(&links).await
         }
 
         /// Delete a record from Airtable.
@@ -357,7 +981,7 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         pub async fn delete_from_airtable(&self) {
             if !self.airtable_record_id.is_empty() {
                 // Delete the record from airtable.
-                #new_struct_name::airtable().delete_record(&#new_struct_name::airtable_table(), &self.airtable_record_id).await.unwrap();
+                #new_struct_name::airtable().delete_record(&#new_struct_name::airtable_table(), &self.airtable_record_id).await.unwrap_or_else(crate::rate_limits::airtable_failed);
             }
         }
     }
@@ -385,6 +1009,13 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[tracing::instrument(skip(db))]
         #[inline]
         pub fn get_from_db(db: &crate::db::Database) -> Self {
+            #[cfg(feature = "sqlite")]
+            {
+                if let Some(store) = db.sqlite() {
+                    return #new_struct_name_plural(store.list(stringify!(#db_schema)));
+                }
+            }
+
             #new_struct_name_plural(
                 crate::schema::#db_schema::dsl::#db_schema
                     .order_by(crate::schema::#db_schema::dsl::id.desc())
@@ -397,10 +1028,15 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[tracing::instrument]
         #[inline]
         pub async fn get_from_airtable() -> std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>> {
-            let result: Vec<airtable_api::Record<#new_struct_name>> = #new_struct_name::airtable()
-                .list_records(&#new_struct_name::airtable_table(), "Grid view", vec![])
-                .await
-                .unwrap();
+            #new_struct_name_plural::get_from_airtable_with_links(&#new_struct_name::airtable_links().await).await
+        }
+
+        /// Get the current records for this type from Airtable, with the links from
+        /// `airtable_links`.
+        #[tracing::instrument(skip(links))]
+        #[inline]
+        pub async fn get_from_airtable_with_links(links: &crate::airtable_links::AirtableLinks) -> std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>> {
+            let result = #new_struct_name::list_airtable_records_with_formula("", links).await.0;
 
             let mut records: std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>> =
                 Default::default();
@@ -412,19 +1048,47 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
 
         /// Update Airtable records in a table from a vector.
+        ///
+        /// Between full syncs, every `AIRTABLE_FULL_SYNC_HOURS`, we only push the records
+        /// that changed on either side since the last push rather than listing the table.
+        /// The linked tables are listed once for the whole sync, not for every record.
         #[tracing::instrument(skip(self))]
         #[inline]
         pub async fn update_airtable(&self) {
-            let mut records = #new_struct_name_plural::get_from_airtable().await;
+            let started_at = chrono::Utc::now();
+            let base_id = #new_struct_name::airtable_base_id();
+            let table = #new_struct_name::airtable_table();
+            let links = #new_struct_name::airtable_links().await;
+            if let Some((since, record_ids)) = crate::airtable_syncs::delta_sync_since(&base_id, &table, started_at) {
+                let record_ids = self.update_airtable_since(since, record_ids, &links).await;
+                crate::airtable_syncs::record_airtable_sync(&base_id, &table, started_at, record_ids, false);
+                return;
+            }
 
+            let mut record_ids: std::collections::BTreeMap<i32, String> = Default::default();
+            let (result, unreadable) = #new_struct_name::list_airtable_records_with_formula("", &links).await;
+            let mut records: std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>> =
+                result.into_iter().map(|r| (r.fields.id, r)).collect();
+
+            let mut progress = crate::progress::Progress::new(&#new_struct_name::airtable_table(), self.0.len());
             for mut vec_record in self.0.clone() {
+                progress.inc(&vec_record.id.to_string());
+
+                // Leave the records we could not read in Airtable alone, rather than making
+                // another for them.
+                if unreadable.contains(&vec_record.id) {
+                    record_ids.insert(vec_record.id, vec_record.airtable_record_id.to_string());
+                    continue;
+                }
+
                 // See if we have it in our Airtable records.
                 match records.get(&vec_record.id) {
                     Some(r) => {
                         let mut record = r.clone();
 
                         // Update the record in Airtable.
-                        vec_record.update_in_airtable(&mut record).await;
+                        let record = vec_record.update_in_airtable_with_links(&mut record, &links).await;
+                        record_ids.insert(vec_record.id, record.id);
 
                         // Remove it from the map.
                         records.remove(&vec_record.id);
@@ -432,7 +1096,8 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
                     None => {
                         // We do not have the record in Airtable, Let's create it.
                         // Create the record in Airtable.
-                        vec_record.create_in_airtable().await;
+                        let record = vec_record.create_in_airtable_with_links(&links).await;
+                        record_ids.insert(vec_record.id, record.id);
 
                         // Remove it from the map.
                         records.remove(&vec_record.id);
@@ -444,8 +1109,72 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             // since they don't exist in our vector.
             for (_, record) in records {
                 // Delete the record from airtable.
-                #new_struct_name::airtable().delete_record(&#new_struct_name::airtable_table(), &record.id).await.unwrap();
+                #new_struct_name::airtable().delete_record(&#new_struct_name::airtable_table(), &record.id).await.unwrap_or_else(crate::rate_limits::airtable_failed);
+            }
+
+            progress.finish();
+            crate::airtable_syncs::record_airtable_sync(&base_id, &table, started_at, record_ids, true);
+        }
+
+        /// Push the records that changed since a time, in Airtable or in the database, to
+        /// Airtable. Only the records modified in Airtable are listed, the records that
+        /// only changed on our side are fetched one by one by their Airtable record ID.
+        /// Deleted records are left for the next full sync. Returns the Airtable record
+        /// IDs with the ones for any records we created.
+        #[tracing::instrument(skip(self, record_ids, links))]
+        #[inline]
+        pub async fn update_airtable_since(
+            &self,
+            since: chrono::DateTime<chrono::Utc>,
+            mut record_ids: std::collections::BTreeMap<i32, String>,
+            links: &crate::airtable_links::AirtableLinks,
+        ) -> std::collections::BTreeMap<i32, String> {
+            let mut modified: std::collections::BTreeMap<i32, airtable_api::Record<#new_struct_name>> = Default::default();
+            for record in #new_struct_name::list_airtable_records_modified_since(since, links).await {
+                modified.insert(record.fields.id, record);
+            }
+
+            let mut progress = crate::progress::Progress::new(&#new_struct_name::airtable_table(), self.0.len());
+            for mut vec_record in self.0.clone() {
+                progress.inc(&vec_record.id.to_string());
+
+                if let Some(r) = modified.get(&vec_record.id) {
+                    let record = vec_record.update_in_airtable_with_links(&mut r.clone(), links).await;
+                    record_ids.insert(vec_record.id, record.id);
+                    continue;
+                }
+
+                if !crate::airtable_syncs::changed_since(vec_record.modified_at, #modified_cutoff) {
+                    continue;
+                }
+
+                // The record only changed on our side.
+                if vec_record.airtable_record_id.is_empty() {
+                    vec_record.airtable_record_id = record_ids.get(&vec_record.id).cloned().unwrap_or_default();
+                }
+                let existing = if vec_record.airtable_record_id.is_empty() {
+                    None
+                } else {
+                    match vec_record.read_existing_airtable_record(links).await {
+                        Ok(r) => r,
+                        Err(e) => {
+                            // Leave the record we can not read alone, rather than making another.
+                            println!("[airtable] skipping: {}", e);
+                            record_ids.insert(vec_record.id, vec_record.airtable_record_id.to_string());
+                            continue;
+                        }
+                    }
+                };
+                let record = match existing {
+                    Some(mut existing) => vec_record.update_in_airtable_with_links(&mut existing, links).await,
+                    // The record is new since the last full sync.
+                    None => vec_record.create_in_airtable_with_links(links).await,
+                };
+                record_ids.insert(vec_record.id, record.id);
             }
+
+            progress.finish();
+            record_ids
         }
     }
     };
@@ -457,7 +1186,7 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 
     let new_struct = quote!(
-        #item
+        #og_struct
 
         #[derive(
             Debug,
@@ -476,12 +1205,130 @@ fn do_db(attr: TokenStream, item: TokenStream) -> TokenStream {
             #[serde(default)]
             pub id: i32,
             #(#fields),*,
-            // This has to be the last field, due to the schemas.
+            // This and the provenance fields have to be the last fields, due to the schemas.
             #[serde(default, skip_serializing_if = "String::is_empty")]
             pub airtable_record_id: String,
+            /// The system that fed the record when it was created.
+            #[serde(default, skip_serializing_if = "String::is_empty")]
+            pub source: String,
+            /// The system that fed the last sync that changed the record.
+            #[serde(default, skip_serializing_if = "String::is_empty")]
+            pub last_synced_from: String,
+            /// The time of the last sync that changed the record.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub last_synced_at: Option<chrono::DateTime<chrono::Utc>>,
+            /// The fields of the Airtable record that we do not have, sent back as they were.
+            #[serde(default, skip_serializing_if = "crate::extra_fields::AirtableExtraFields::is_empty")]
+            pub extra: crate::extra_fields::AirtableExtraFields,
+            /// The time the record last changed in the database, by a sync or anything else.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub modified_at: Option<chrono::DateTime<chrono::Utc>>,
         }
 
         #airtable
     );
     new_struct
 }
+
+/// Take an attribute of ours in the form `#[name = "..."]`, ie. `#[airtable_field = "..."]`,
+/// off a field, returning its value.
+fn take_string_attribute(field: &mut Field, name: &str) -> Option<String> {
+    let ident = field.ident.clone();
+    let mut value = None;
+    field.attrs.retain(|attr| {
+        if !attr.path.is_ident(name) {
+            return true;
+        }
+
+        match attr.parse_meta() {
+            Ok(Meta::NameValue(nv)) => match nv.lit {
+                Lit::Str(s) => value = Some(s.value()),
+                _ => panic!("`{}` on {:?} must be a string", name, ident),
+            },
+            _ => panic!("`{}` on {:?} must be in the form `#[{} = \"...\"]`", name, ident, name),
+        }
+        false
+    });
+
+    value
+}
+
+/// If the field is attachments from an Airtable attachment column, ie. `AirtableAttachments`.
+fn is_attachments_field(field: &Field) -> bool {
+    match &field.ty {
+        Type::Path(p) => p.path.segments.last().map(|s| s.ident == "AirtableAttachments").unwrap_or_default(),
+        _ => false,
+    }
+}
+
+/// The name serde serializes a field as, its `#[serde(rename = "...")]` if it has one.
+fn serialized_field_name(field: &Field) -> String {
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident("serde")) {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested {
+                if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+                    if let (true, Lit::Str(s)) = (nv.path.is_ident("rename"), nv.lit) {
+                        return s.value();
+                    }
+                }
+            }
+        }
+    }
+
+    field.ident.as_ref().map(|i| i.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::ItemStruct;
+
+    use crate::{is_attachments_field, serialized_field_name, take_string_attribute};
+
+    #[test]
+    fn test_airtable_field() {
+        let mut item: ItemStruct = syn::parse_str(
+            r#"
+            struct NewThing {
+                #[airtable_field = "Candidate Name"]
+                #[serde(default)]
+                pub name: String,
+                #[serde(rename = "Email", default)]
+                #[airtable_field = "Email Address"]
+                pub email: String,
+                #[airtable_link = "Group"]
+                pub groups: Vec<String>,
+            }
+            "#,
+        )
+        .unwrap();
+        let fields: Vec<_> = item
+            .fields
+            .iter_mut()
+            .map(|f| {
+                (
+                    take_string_attribute(f, "airtable_field"),
+                    take_string_attribute(f, "airtable_link"),
+                    serialized_field_name(f),
+                    f.attrs.len(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            fields,
+            vec![
+                (Some("Candidate Name".to_string()), None, "name".to_string(), 1),
+                (Some("Email Address".to_string()), None, "Email".to_string(), 1),
+                (None, Some("Group".to_string()), "groups".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_attachments_field() {
+        let item: ItemStruct = syn::parse_str("struct NewThing { receipts: AirtableAttachments, other: crate::attachments::AirtableAttachments, name: String }").unwrap();
+        let fields: Vec<bool> = item.fields.iter().map(is_attachments_field).collect();
+
+        assert_eq!(fields, vec![true, true, false]);
+    }
+}
@@ -305,6 +305,76 @@ impl Okta {
         Ok(result)
     }
 
+    /// List the factors a user enrolled in for MFA.
+    pub async fn list_user_factors(&self, user_id: &str) -> Result<Vec<Factor>, APIError> {
+        // Build the request.
+        let rb = self.request(Method::GET, format!("/api/v1/users/{}/factors", user_id), ());
+        let request = rb.build().unwrap();
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        let result: Vec<Factor> = resp.json().await.unwrap();
+
+        Ok(result)
+    }
+
+    /// List the admin roles assigned to a user.
+    pub async fn list_user_roles(&self, user_id: &str) -> Result<Vec<Role>, APIError> {
+        // Build the request.
+        let rb = self.request(Method::GET, format!("/api/v1/users/{}/roles", user_id), ());
+        let request = rb.build().unwrap();
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        let result: Vec<Role> = resp.json().await.unwrap();
+
+        Ok(result)
+    }
+
+    /// List the applications in our org, ie. the SAML and OIDC apps people sign in to.
+    pub async fn list_applications(&self) -> Result<Vec<Application>, APIError> {
+        // Build the request.
+        // TODO: paginate.
+        let rb = self.request(Method::GET, "/api/v1/apps?limit=200", ());
+        let request = rb.build().unwrap();
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        let result: Vec<Application> = resp.json().await.unwrap();
+
+        Ok(result)
+    }
+
     /// Add user to a group.
     pub async fn add_user_to_group(&self, group_id: &str, user: &str) -> Result<(), APIError> {
         // First we need to get the user to get their user_id.
@@ -541,6 +611,57 @@ pub struct GroupProfile {
     pub description: String,
 }
 
+/// A factor a user enrolled in for MFA.
+/// FROM: https://developer.okta.com/docs/reference/api/factors/
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Factor {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// The type of the factor, ie. `push`, `token:software:totp`, `u2f`, or `sms`.
+    #[serde(default, rename = "factorType", skip_serializing_if = "String::is_empty")]
+    pub factor_type: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub provider: String,
+    /// The status of the factor, only `ACTIVE` factors can be used to sign in.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+}
+
+/// An admin role assigned to a user.
+/// FROM: https://developer.okta.com/docs/reference/api/roles/
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Role {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// The type of the role, ie. `SUPER_ADMIN` or `ORG_ADMIN`.
+    #[serde(default, rename = "type", skip_serializing_if = "String::is_empty")]
+    pub role_type: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+}
+
+/// An application people sign in to through Okta.
+/// FROM: https://developer.okta.com/docs/reference/api/apps/
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Application {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// The name of the app in the Okta catalog, ie. `zoomus` or `slack`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// The name people see for the app, ie. `Zoom`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    /// Either `ACTIVE` or `INACTIVE`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+    /// How people sign in to the app, ie. `SAML_2_0`, `OPENID_CONNECT`, or `BOOKMARK`.
+    #[serde(default, rename = "signOnMode", skip_serializing_if = "String::is_empty")]
+    pub sign_on_mode: String,
+}
+
 pub mod deserialize_null_string {
     use serde::{self, Deserialize, Deserializer};
 
@@ -15,7 +15,9 @@
  *     // Get the GSuite credentials file.
  *     let gsuite_credential_file = env::var("GADMIN_CREDENTIAL_FILE").unwrap();
  *     let gsuite_subject = env::var("GADMIN_SUBJECT").unwrap();
- *     let gsuite_secret = read_service_account_key(gsuite_credential_file).await.expect("failed to read gsuite credential file");
+ *     let gsuite_secret = read_service_account_key(gsuite_credential_file)
+ *         .await
+ *         .expect("failed to read gsuite credential file");
  *     let auth = ServiceAccountAuthenticator::builder(gsuite_secret)
  *         .subject(gsuite_subject.to_string())
  *         .build()
@@ -23,7 +25,10 @@
  *         .expect("failed to create authenticator");
  *
  *     // Add the scopes to the secret and get the token.
- *     let token = auth.token(&["https://www.googleapis.com/auth/spreadsheets"]).await.expect("failed to get token");
+ *     let token = auth
+ *         .token(&["https://www.googleapis.com/auth/spreadsheets"])
+ *         .await
+ *         .expect("failed to get token");
  *
  *     if token.as_str().is_empty() {
  *         panic!("empty token is not valid");
@@ -33,7 +38,10 @@
  *     let sheets_client = Sheets::new(token);
  *
  *     // Get the values in the sheet.
- *     let sheet_values = sheets_client.get_values("sheet_id", "Form Responses 1!A1:S1000".to_string()).await.unwrap();
+ *     let sheet_values = sheets_client
+ *         .get_values("sheet_id", "Form Responses 1!A1:S1000".to_string())
+ *         .await
+ *         .unwrap();
  *     let values = sheet_values.values.unwrap();
  *
  *     if values.is_empty() {
@@ -51,7 +59,7 @@ use std::error;
 use std::fmt;
 use std::sync::Arc;
 
-use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use reqwest::{header, Client, Method, Request, Response, StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use yup_oauth2::AccessToken;
 
@@ -61,6 +69,7 @@ const ENDPOINT: &str = "https://sheets.googleapis.com/v4/";
 /// Entrypoint for interacting with the Google Sheets API.
 pub struct Sheets {
     token: AccessToken,
+    on_request: Option<fn(&Request)>,
 
     client: Arc<Client>,
 }
@@ -72,16 +81,36 @@ impl Sheets {
     pub fn new(token: AccessToken) -> Self {
         let client = Client::builder().build();
         match client {
-            Ok(c) => Self { token, client: Arc::new(c) },
+            Ok(c) => Self {
+                token,
+                on_request: None,
+                client: Arc::new(c),
+            },
             Err(e) => panic!("creating client failed: {:?}", e),
         }
     }
 
+    /// Call a function with every request before it is sent, ie. to count the
+    /// requests we make. A paginated list calls it once for each page.
+    pub fn with_request_hook(mut self, hook: fn(&Request)) -> Self {
+        self.on_request = Some(hook);
+        self
+    }
+
     /// Get the currently set authorization token.
     pub fn get_token(&self) -> &AccessToken {
         &self.token
     }
 
+    /// Send a request, calling the request hook first if there is one.
+    async fn execute(&self, request: Request) -> Result<Response, reqwest::Error> {
+        if let Some(hook) = self.on_request {
+            hook(&request);
+        }
+
+        self.client.execute(request).await
+    }
+
     fn request<B>(&self, method: Method, path: String, body: B, query: Option<Vec<(&str, String)>>) -> Request
     where
         B: Serialize,
@@ -134,7 +163,7 @@ impl Sheets {
             ]),
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -185,7 +214,37 @@ impl Sheets {
             ]),
         );
 
-        let resp = self.client.execute(request).await.unwrap();
+        let resp = self.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Append rows after the last row of the table in a range, in one request so
+    /// appends at the same time do not write over each other.
+    pub async fn append_values(&self, sheet_id: &str, range: &str, values: Vec<Vec<String>>) -> Result<AppendValuesResponse, APIError> {
+        // Build the request.
+        let request = self.request(
+            Method::POST,
+            format!("spreadsheets/{}/values/{}:append", sheet_id.to_string(), range.to_string()),
+            ValueRange {
+                range: Some(range.to_string()),
+                values: Some(values),
+                major_dimension: None,
+            },
+            Some(vec![("valueInputOption", "USER_ENTERED".to_string()), ("insertDataOption", "INSERT_ROWS".to_string())]),
+        );
+
+        let resp = self.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
             s => {
@@ -288,3 +347,17 @@ pub struct UpdateValuesResponse {
     #[serde(rename = "updatedCells")]
     pub updated_cells: Option<i32>,
 }
+
+/// The response returned from appending values.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct AppendValuesResponse {
+    /// The spreadsheet the updates were applied to.
+    #[serde(rename = "spreadsheetId")]
+    pub spreadsheet_id: Option<String>,
+    /// The range (in A1 notation) of the table that values are being appended to
+    /// (before the values were appended). Empty if no table was found.
+    #[serde(rename = "tableRange")]
+    pub table_range: Option<String>,
+    /// Information about the updates that were applied.
+    pub updates: Option<UpdateValuesResponse>,
+}
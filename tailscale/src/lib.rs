@@ -143,6 +143,106 @@ impl Tailscale {
 
         Ok(())
     }
+
+    /// Expire the node key of a device, which disconnects it until someone logs in on it again.
+    pub async fn expire_device(&self, device_id: &str) -> Result<(), APIError> {
+        let request = self.request(Method::POST, &format!("device/{}/expire", device_id), (), None);
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        Ok(())
+    }
+
+    /// List the users in the tailnet.
+    pub async fn list_users(&self) -> Result<Vec<User>, APIError> {
+        let request = self.request(Method::GET, &format!("tailnet/{}/users", self.domain), (), None);
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        let r: APIResponse = resp.json().await.unwrap();
+
+        Ok(r.users)
+    }
+
+    /// List the auth keys in the tailnet. The list only has the ids of the keys, so this
+    /// gets each of them.
+    pub async fn list_keys(&self) -> Result<Vec<Key>, APIError> {
+        let request = self.request(Method::GET, &format!("tailnet/{}/keys", self.domain), (), None);
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        let r: APIResponse = resp.json().await.unwrap();
+
+        let mut keys: Vec<Key> = Default::default();
+        for key in r.keys {
+            keys.push(self.get_key(&key.id).await?);
+        }
+
+        Ok(keys)
+    }
+
+    /// Get an auth key.
+    pub async fn get_key(&self, key_id: &str) -> Result<Key, APIError> {
+        let request = self.request(Method::GET, &format!("tailnet/{}/keys/{}", self.domain, key_id), (), None);
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Delete an auth key, so it can no longer be used to add devices.
+    pub async fn delete_key(&self, key_id: &str) -> Result<(), APIError> {
+        let request = self.request(Method::DELETE, &format!("tailnet/{}/keys/{}", self.domain, key_id), (), None);
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        Ok(())
+    }
 }
 
 /// Error type returned by our library.
@@ -176,6 +276,10 @@ impl error::Error for APIError {
 pub struct APIResponse {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub devices: Vec<Device>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<User>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<Key>,
 }
 
 /// The data type for a device.
@@ -227,4 +331,38 @@ pub struct Device {
     pub route_all: bool,
     #[serde(default, rename = "hasSubnet")]
     pub has_subnet: bool,
+    /// The ACL tags of the device, devices with tags are not owned by a person.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// The data type for a user in the tailnet.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct User {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty", rename = "displayName")]
+    pub display_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty", rename = "loginName")]
+    pub login_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub role: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+}
+
+/// The data type for an auth key. When listing keys only the id is set.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Key {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub created: Option<DateTime<Utc>>,
+    pub expires: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub revoked: Option<DateTime<Utc>>,
+    /// The id of the user who created the key.
+    #[serde(default, skip_serializing_if = "String::is_empty", rename = "userId")]
+    pub user_id: String,
 }
@@ -20,6 +20,7 @@ use chrono::{DateTime, TimeZone};
 use chrono_humanize::HumanTime;
 use diesel::prelude::*;
 use dropshot::{endpoint, ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError, HttpResponseAccepted, HttpResponseOk, HttpServer, Path, Query, RequestContext, TypedBody};
+use futures_util::stream::TryStreamExt;
 use google_drive::GoogleDrive;
 use hubcaps::issues::{IssueListOptions, State};
 use hubcaps::Github;
@@ -32,17 +33,38 @@ use tracing_subscriber::prelude::*;
 use cio_api::analytics::NewPageView;
 use cio_api::applicants::get_role_from_sheet_id;
 use cio_api::applicants::{Applicant, NewApplicant};
-use cio_api::configs::{get_configs_from_repo, sync_buildings, sync_certificates, sync_conference_rooms, sync_github_outside_collaborators, sync_groups, sync_links, sync_users};
+use cio_api::background_checks::{handle_checkr_event, CheckrEvent};
+use cio_api::chat::{notify_channel, ChatChannel};
+use cio_api::config_check::{check_configs, post_config_check};
+use cio_api::configs::{get_configs_from_repo, sync_buildings, sync_certificates, sync_conference_rooms, sync_github_outside_collaborators, sync_groups, sync_links, sync_repo_owners, sync_users};
 use cio_api::db::Database;
+use cio_api::desks::{book_office, cancel_office_bookings, handle_desk_command, parse_booking_day, OfficeBookingRequest, OFFICE_BOOKINGS_SECRET_HEADER};
+use cio_api::docusign::{handle_docusign_event, DocuSignConnectEvent};
+use cio_api::event_bus::emit_event;
+use cio_api::feedback::{feedback_slack_modal, parse_feedback_submission, submit_feedback, FEEDBACK_SLACK_CALLBACK_ID};
+use cio_api::forms::{handle_form_response, GoogleFormResponse, FORMS_SECRET_HEADER};
+use cio_api::lunch::{handle_lunch_order, LUNCH_ORDER_ACTION_ID};
 use cio_api::mailing_list::{MailchimpWebhook, MailingListSubscriber};
+use cio_api::maintenance::sync_maintenance_windows;
 use cio_api::models::{GitHubUser, NewRFD, NewRepo, RFD};
+use cio_api::notifications::{notify, NotificationPriority};
+use cio_api::profiles::whoknows;
+use cio_api::rate_limits::{rate_limit_statuses, RateLimitStatus};
+use cio_api::reactions::handle_slack_reaction;
+use cio_api::rfd_references::refresh_rfd_references;
 use cio_api::rfds::is_image;
 use cio_api::schema::applicants;
+use cio_api::scim::{get_scim_groups, get_scim_users, parse_scim_filter, scim_filter_matches, scim_list_response, scim_service_provider_config, SCIM_MAX_COUNT};
 use cio_api::shipments::{get_shipments_spreadsheets, InboundShipment, NewInboundShipment, Shipment};
 use cio_api::shorturls::{generate_shorturls_for_configs_links, generate_shorturls_for_repos, generate_shorturls_for_rfds};
-use cio_api::slack::{get_hiring_channel_post_url, get_public_relations_channel_post_url, post_to_channel};
+use cio_api::slack::{get_slack_user_email, open_slack_modal, parse_slack_command, parse_slack_interaction};
 use cio_api::templates::generate_terraform_files_for_okta;
+use cio_api::trainings::username_for_email;
 use cio_api::utils::{authenticate_github_jwt, create_or_update_file_in_github_repo, get_file_content_from_repo, get_gsuite_token, github_org};
+use cio_api::verify::{constant_time_eq, verify_checkr_signature, verify_docusign_signature, verify_github_signature, verify_shared_secret, verify_slack_signature};
+use cio_api::visitors::{check_in_visitor, VisitorCheckIn, VISITORS_SECRET_HEADER};
+use cio_api::webhook_deliveries::record_webhook_delivery;
+use cio_api::welcome::start_welcome_sequence;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
@@ -99,17 +121,31 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
      */
     api.register(ping).unwrap();
     api.register(github_rate_limit).unwrap();
+    api.register(status).unwrap();
     api.register(listen_airtable_applicants_edit_webhooks).unwrap();
     api.register(listen_airtable_shipments_inbound_create_webhooks).unwrap();
     api.register(listen_airtable_shipments_outbound_create_webhooks).unwrap();
     api.register(listen_airtable_shipments_outbound_edit_webhooks).unwrap();
     api.register(listen_analytics_page_view_webhooks).unwrap();
+    api.register(listen_checkr_background_update_webhooks).unwrap();
+    api.register(listen_docusign_envelope_update_webhooks).unwrap();
     api.register(listen_google_sheets_edit_webhooks).unwrap();
     api.register(listen_google_sheets_row_create_webhooks).unwrap();
+    api.register(listen_google_forms_response_webhooks).unwrap();
     api.register(listen_github_webhooks).unwrap();
     api.register(listen_mailchimp_webhooks).unwrap();
+    api.register(listen_office_bookings).unwrap();
     api.register(listen_shippo_tracking_update_webhooks).unwrap();
+    api.register(listen_slack_commands).unwrap();
+    api.register(listen_slack_events).unwrap();
+    api.register(listen_slack_interactions).unwrap();
+    api.register(listen_visitors_check_in).unwrap();
     api.register(ping_mailchimp_webhooks).unwrap();
+    api.register(get_scim_group).unwrap();
+    api.register(get_scim_service_provider_config).unwrap();
+    api.register(get_scim_user).unwrap();
+    api.register(list_scim_groups).unwrap();
+    api.register(list_scim_users).unwrap();
     api.register(trigger_rfd_update_by_number).unwrap();
 
     /*
@@ -177,6 +213,27 @@ impl Context {
     }
 }
 
+/// Read the headers and the raw body of a request. Webhooks are signed over the exact
+/// bytes of the body, so we need those to verify the signature before we parse it.
+#[instrument]
+#[inline]
+async fn read_request(rqctx: &Arc<RequestContext>) -> Result<(http::HeaderMap, Vec<u8>), HttpError> {
+    let mut req = rqctx.request.lock().await;
+    let headers = req.headers().clone();
+    let body = hyper::body::to_bytes(req.body_mut())
+        .await
+        .map_err(|e| HttpError::for_bad_request(None, format!("reading the request body failed: {}", e)))?;
+
+    Ok((headers, body.to_vec()))
+}
+
+/// Get the value of a header, or an empty string if it is not set.
+#[instrument]
+#[inline]
+fn get_header(headers: &http::HeaderMap, name: &str) -> String {
+    headers.get(name).and_then(|h| h.to_str().ok()).unwrap_or_default().to_string()
+}
+
 /*
  * HTTP API interface
  */
@@ -199,21 +256,27 @@ async fn ping(_rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<String>, Htt
 }]
 #[instrument]
 #[inline]
-async fn listen_github_webhooks(rqctx: Arc<RequestContext>, body_param: TypedBody<GitHubWebhook>) -> Result<HttpResponseAccepted<String>, HttpError> {
+async fn listen_github_webhooks(rqctx: Arc<RequestContext>) -> Result<HttpResponseAccepted<String>, HttpError> {
     let api_context = Context::from_rqctx(&rqctx);
 
-    let event = body_param.into_inner();
+    // Make sure the event is from GitHub.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_github_signature(&env::var("GITHUB_WEBHOOK_SECRET").unwrap_or_default(), &get_header(&headers, "X-Hub-Signature-256"), &body) {
+        event!(Level::WARN, "github webhook has an invalid signature: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid signature".to_string()));
+    }
+
+    let event: GitHubWebhook = serde_json::from_slice(&body).map_err(|e| HttpError::for_bad_request(None, format!("decoding the github webhook failed: {}", e)))?;
+
+    // GitHub sends redeliveries with the same delivery ID as the first try.
+    let delivery_id = get_header(&headers, "X-GitHub-Delivery");
+    if !record_webhook_delivery(&api_context.db, "github", &delivery_id) {
+        event!(Level::INFO, "ignoring github delivery {} we already handled", delivery_id);
+        return Ok(HttpResponseAccepted("ok".to_string()));
+    }
 
     // Parse the `X-GitHub-Event` header.
-    // TODO: make this nicer when supported as a first class method in dropshot.
-    let req = rqctx.request.lock().await;
-    let req_headers = req.headers();
-    let event_type_string = req_headers
-        .get("X-GitHub-Event")
-        .unwrap_or(&http::header::HeaderValue::from_str("").unwrap())
-        .to_str()
-        .unwrap()
-        .to_string();
+    let event_type_string = get_header(&headers, "X-GitHub-Event");
     let event_type = EventType::from_str(&event_type_string).unwrap();
 
     // Save all events to influxdb.
@@ -306,11 +369,15 @@ async fn listen_github_webhooks(rqctx: Arc<RequestContext>, body_param: TypedBod
                 }
                 _ => (),
             },
-            Repo::Configs => {
-                if let EventType::Push = event_type {
+            Repo::Configs => match event_type {
+                EventType::Push => {
                     return handle_configs_push(api_context, event).await;
                 }
-            }
+                EventType::PullRequest => {
+                    return handle_configs_pull_request(api_context, event).await;
+                }
+                _ => (),
+            },
             _ => {
                 // We can throw this out, log it and return early.
                 event!(Level::INFO, "`{}` event was to the {} repo, no automations are set up for this repo yet", event_type, repo_name);
@@ -357,6 +424,7 @@ async fn trigger_rfd_update_by_number(rqctx: Arc<RequestContext>, path_params: P
 
     // Save the rfd back to our database.
     rfd.update(db).await;
+    refresh_rfd_references(db, &[(rfd.number, rfd.content.to_string())]).await;
 
     Ok(HttpResponseAccepted("ok".to_string()))
 }
@@ -392,6 +460,29 @@ pub struct GitHubRateLimit {
     pub reset: String,
 }
 
+/** Get the status of the APIs we use, ie. how close we are to their rate limits. */
+#[endpoint {
+    method = GET,
+    path = "/status",
+}]
+#[instrument]
+#[inline]
+async fn status(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<Status>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    Ok(HttpResponseOk(Status {
+        rate_limits: rate_limit_statuses(db, Utc::now()),
+    }))
+}
+
+/// The status of the APIs we use. Airtable and Slack only show up as low after they
+/// rate limit us, GitHub tells us how much of our limit is left.
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize, Serialize)]
+pub struct Status {
+    pub rate_limits: Vec<RateLimitStatus>,
+}
+
 /**
  * Listen for edits to our Google Sheets.
  * These are set up with a Google Apps script on the sheets themselves.
@@ -454,6 +545,7 @@ async fn listen_google_sheets_edit_webhooks(rqctx: Arc<RequestContext>, body_par
         return Ok(HttpResponseAccepted("ok".to_string()));
     }
     let mut a = result.unwrap();
+    let old_status = a.status;
 
     // Now let's update the correct item for them.
     if column_header.contains("have sent email that we received their application?") {
@@ -468,12 +560,8 @@ async fn listen_google_sheets_edit_webhooks(rqctx: Arc<RequestContext>, body_par
         }
     } else if column_header.contains("status") {
         // Parse the new status.
-        let mut status = cio_api::applicant_status::Status::from_str(&event.event.value).unwrap_or_default().to_string();
-        status = status.trim().to_string();
-        if !status.is_empty() {
-            a.status = status;
-            a.raw_status = event.event.value.to_string();
-        }
+        a.status = cio_api::applicant_status::Status::from_str(&event.event.value).unwrap_or_default();
+        a.raw_status = event.event.value.to_string();
     } else if column_header.contains("value reflected") {
         // Update the value reflected.
         a.value_reflected = event.event.value.to_lowercase();
@@ -502,12 +590,16 @@ async fn listen_google_sheets_edit_webhooks(rqctx: Arc<RequestContext>, body_par
 
     // Update the applicant in the database and Airtable.
     let new_applicant = a.update(db).await;
+    if old_status != new_applicant.status {
+        new_applicant.stage_changed(db, old_status).await;
+    }
 
     // Get all the hiring issues on the configs repository.
     let configs_issues = github
         .repo(github_org(), "configs")
         .issues()
-        .list(&IssueListOptions::builder().per_page(100).state(State::All).labels(vec!["hiring"]).build())
+        .iter(&IssueListOptions::builder().per_page(100).state(State::All).labels(vec!["hiring"]).build())
+        .try_collect::<Vec<hubcaps::issues::Issue>>()
         .await
         .unwrap();
     new_applicant.create_github_onboarding_issue(&github, &configs_issues).await;
@@ -631,6 +723,17 @@ async fn listen_google_sheets_row_create_webhooks(rqctx: Arc<RequestContext>, bo
         return Ok(HttpResponseAccepted("ok".to_string()));
     }
 
+    // Google can send the event for a row more than once, if we already have the
+    // applicant we do not want to email them or announce them again.
+    if let Ok(existing) = applicants::dsl::applicants
+        .filter(applicants::dsl::email.eq(applicant.email.to_string()))
+        .filter(applicants::dsl::sheet_id.eq(applicant.sheet_id.to_string()))
+        .first::<Applicant>(&db.conn())
+    {
+        applicant.sent_email_received = existing.sent_email_received;
+        applicant.sent_email_follow_up = existing.sent_email_follow_up;
+    }
+
     // We do not need to add one to the end of the columns to get the column where the email sent verification is
     // because google sheets index's at 0, so adding one would put us over, we are just right here.
     let sent_email_received_column_index = event.event.range.column_end;
@@ -649,7 +752,7 @@ async fn listen_google_sheets_row_create_webhooks(rqctx: Arc<RequestContext>, bo
         event!(Level::INFO, "applicant is new, sending internal notifications: {:?}", applicant);
 
         // Post to Slack.
-        post_to_channel(get_hiring_channel_post_url(), applicant.as_slack_msg()).await;
+        notify_channel(ChatChannel::Hiring, applicant.as_slack_msg()).await;
 
         // Send a company-wide email.
         applicant.send_email_internally().await;
@@ -673,6 +776,43 @@ pub struct GoogleSpreadsheetRowCreateEvent {
     pub spreadsheet: GoogleSpreadsheet,
 }
 
+/**
+ * Listen for responses to our Google Forms.
+ * These are set up with a Google Apps script on the forms themselves, see `GoogleFormResponse`.
+ */
+#[endpoint {
+    method = POST,
+    path = "/google/forms/response",
+}]
+#[instrument]
+#[inline]
+async fn listen_google_forms_response_webhooks(rqctx: Arc<RequestContext>) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // Make sure the response is from our Apps Script.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_shared_secret("GOOGLE_FORMS_WEBHOOK_SECRET", &get_header(&headers, FORMS_SECRET_HEADER)) {
+        event!(Level::WARN, "google form response has an invalid secret: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid secret".to_string()));
+    }
+
+    let response: GoogleFormResponse = serde_json::from_slice(&body).map_err(|e| HttpError::for_bad_request(None, format!("decoding the google form response failed: {}", e)))?;
+
+    // Apps Script retries requests that fail, so we ignore the responses we already handled.
+    if !record_webhook_delivery(db, "google_forms", &response.response_id) {
+        event!(Level::INFO, "ignoring retry of google form response {}", response.response_id);
+        return Ok(HttpResponseAccepted("ok".to_string()));
+    }
+
+    match handle_form_response(db, &api_context.github, &response).await {
+        Ok(done) => event!(Level::INFO, "google form response {}: {}", response.response_id, done),
+        Err(e) => event!(Level::WARN, "handling google form response {} failed: {}", response.response_id, e),
+    }
+
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
 /**
  * Listen for rows edited in our Airtable workspace.
  * These are set up with an Airtable script on the workspaces themselves.
@@ -859,6 +999,82 @@ async fn listen_airtable_shipments_inbound_create_webhooks(rqctx: Arc<RequestCon
     Ok(HttpResponseAccepted("ok".to_string()))
 }
 
+/**
+ * Listen for background check updates from Checkr.
+ * These are set up in the Checkr dashboard for our account.
+ */
+#[endpoint {
+    method = POST,
+    path = "/checkr/background/update",
+}]
+#[instrument]
+#[inline]
+async fn listen_checkr_background_update_webhooks(rqctx: Arc<RequestContext>) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // Make sure the event is from Checkr.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_checkr_signature(&env::var("CHECKR_API_KEY").unwrap_or_default(), &get_header(&headers, "X-Checkr-Signature"), &body) {
+        event!(Level::WARN, "checkr event has an invalid signature: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid signature".to_string()));
+    }
+
+    let event: CheckrEvent = serde_json::from_slice(&body).map_err(|e| HttpError::for_bad_request(None, format!("decoding the checkr event failed: {}", e)))?;
+
+    // Checkr retries events we do not respond to, so we ignore the events we already handled.
+    if !record_webhook_delivery(db, "checkr", &event.id) {
+        event!(Level::INFO, "ignoring retry of checkr event {}", event.id);
+        return Ok(HttpResponseAccepted("ok".to_string()));
+    }
+
+    match handle_checkr_event(db, &event).await {
+        Ok(done) => event!(Level::INFO, "checkr event {}: {}", event.event_type, done),
+        Err(e) => event!(Level::WARN, "handling checkr event {} failed: {}", event.event_type, e),
+    }
+
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
+/**
+ * Listen for envelope status changes from DocuSign Connect.
+ * These are set up with a Connect configuration for our account, sending JSON with
+ * HMAC signatures.
+ */
+#[endpoint {
+    method = POST,
+    path = "/docusign/envelope/update",
+}]
+#[instrument]
+#[inline]
+async fn listen_docusign_envelope_update_webhooks(rqctx: Arc<RequestContext>) -> Result<HttpResponseAccepted<String>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // Make sure the event is from DocuSign.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_docusign_signature(&env::var("DOCUSIGN_CONNECT_HMAC_KEY").unwrap_or_default(), &get_header(&headers, "X-DocuSign-Signature-1"), &body) {
+        event!(Level::WARN, "docusign event has an invalid signature: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid signature".to_string()));
+    }
+
+    let event: DocuSignConnectEvent = serde_json::from_slice(&body).map_err(|e| HttpError::for_bad_request(None, format!("decoding the docusign event failed: {}", e)))?;
+
+    // Connect retries events we do not respond to in time, so we ignore the events we
+    // already handled.
+    if !record_webhook_delivery(db, "docusign", &event.delivery_id()) {
+        event!(Level::INFO, "ignoring retry of docusign event {}", event.delivery_id());
+        return Ok(HttpResponseAccepted("ok".to_string()));
+    }
+
+    match handle_docusign_event(db, &event).await {
+        Ok(done) => event!(Level::INFO, "docusign event {}: {}", event.event, done),
+        Err(e) => event!(Level::WARN, "handling docusign event {} failed: {}", event.event, e),
+    }
+
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
 /**
  * Listen for shimpment tracking updated from Shippo.
  */
@@ -896,6 +1112,253 @@ pub struct ShippoTrackingUpdateEvent {
     pub data: shippo::TrackingStatus,
 }
 
+/** Listen for events from the Slack events API. */
+#[endpoint {
+    method = POST,
+    path = "/slack/events",
+}]
+#[instrument]
+#[inline]
+async fn listen_slack_events(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // Make sure the event is from Slack.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_slack_signature(
+        &env::var("SLACK_SIGNING_SECRET").unwrap_or_default(),
+        &get_header(&headers, "X-Slack-Request-Timestamp"),
+        &get_header(&headers, "X-Slack-Signature"),
+        &body,
+        Utc::now(),
+    ) {
+        event!(Level::WARN, "slack event has an invalid signature: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid signature".to_string()));
+    }
+
+    let event: serde_json::Value = serde_json::from_slice(&body).map_err(|e| HttpError::for_bad_request(None, format!("decoding the slack event failed: {}", e)))?;
+
+    // Slack sends this when we set up the events URL.
+    if event["type"] == "url_verification" {
+        return Ok(HttpResponseOk(json!({ "challenge": event["challenge"] })));
+    }
+
+    // Slack retries events we do not respond to within 3 seconds with the same event ID,
+    // so we ignore the events we already handled.
+    if !record_webhook_delivery(db, "slack", event["event_id"].as_str().unwrap_or_default()) {
+        event!(Level::INFO, "ignoring retry of slack event {}", event["event_id"]);
+        return Ok(HttpResponseOk(json!({})));
+    }
+
+    let handled = match event["event"]["type"].as_str().unwrap_or_default() {
+        // Someone joined our workspace, so we start sending them the welcome messages.
+        "team_join" => start_welcome_sequence(db, &event["event"]["user"], Utc::now()),
+        _ => handle_slack_reaction(db, &event["event"]).await,
+    };
+    match handled {
+        Ok(done) => event!(Level::INFO, "slack event {}: {}", event["event_id"], done),
+        Err(e) => event!(Level::WARN, "handling slack event {} failed: {}", event["event_id"], e),
+    }
+
+    Ok(HttpResponseOk(json!({})))
+}
+
+/** Listen for slash commands from Slack, ie. `/whoknows kubernetes`. */
+#[endpoint {
+    method = POST,
+    path = "/slack/commands",
+}]
+#[instrument]
+#[inline]
+async fn listen_slack_commands(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // Make sure the command is from Slack.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_slack_signature(
+        &env::var("SLACK_SIGNING_SECRET").unwrap_or_default(),
+        &get_header(&headers, "X-Slack-Request-Timestamp"),
+        &get_header(&headers, "X-Slack-Signature"),
+        &body,
+        Utc::now(),
+    ) {
+        event!(Level::WARN, "slack command has an invalid signature: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid signature".to_string()));
+    }
+
+    let command = parse_slack_command(&body);
+    event!(Level::INFO, "slack command `{} {}` from {}", command.command, command.text, command.user_id);
+
+    // Only the person who ran the command sees the response.
+    let text = match command.command.as_str() {
+        "/desk" => {
+            let configs = get_configs_from_repo(&api_context.github).await;
+            match get_slack_user_email(&command.user_id).await.map(|email| username_for_email(&configs.users, &email)) {
+                Ok(Some(username)) => handle_desk_command(db, &configs, &username, &command.text),
+                Ok(None) => "Sorry, you are not in our directory.".to_string(),
+                Err(e) => format!("Sorry, looking you up in Slack failed: {}", e),
+            }
+        }
+        "/whoknows" => whoknows(db, &command.text),
+        c => format!("I do not know the command `{}`.", c),
+    };
+
+    Ok(HttpResponseOk(json!({
+        "response_type": "ephemeral",
+        "text": text,
+    })))
+}
+
+/** Listen for interactions from Slack, ie. someone using a shortcut or submitting a modal. */
+#[endpoint {
+    method = POST,
+    path = "/slack/interactions",
+}]
+#[instrument]
+#[inline]
+async fn listen_slack_interactions(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // Make sure the interaction is from Slack.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_slack_signature(
+        &env::var("SLACK_SIGNING_SECRET").unwrap_or_default(),
+        &get_header(&headers, "X-Slack-Request-Timestamp"),
+        &get_header(&headers, "X-Slack-Signature"),
+        &body,
+        Utc::now(),
+    ) {
+        event!(Level::WARN, "slack interaction has an invalid signature: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid signature".to_string()));
+    }
+
+    let payload = parse_slack_interaction(&body).map_err(|e| HttpError::for_bad_request(None, e))?;
+
+    // Someone picked their lunch from the menu we posted to the office channel.
+    let action = &payload["actions"][0];
+    if payload["type"] == "block_actions" && action["action_id"] == LUNCH_ORDER_ACTION_ID {
+        let configs = get_configs_from_repo(&api_context.github).await;
+        match handle_lunch_order(
+            db,
+            &configs,
+            payload["user"]["id"].as_str().unwrap_or_default(),
+            action["selected_option"]["value"].as_str().unwrap_or_default(),
+        )
+        .await
+        {
+            Ok(done) => event!(Level::INFO, "{}", done),
+            Err(e) => event!(Level::WARN, "handling lunch order failed: {}", e),
+        }
+        return Ok(HttpResponseOk(json!({})));
+    }
+
+    let callback_id = payload["callback_id"].as_str().or_else(|| payload["view"]["callback_id"].as_str()).unwrap_or_default();
+    if callback_id != FEEDBACK_SLACK_CALLBACK_ID {
+        event!(Level::INFO, "ignoring slack interaction `{}` with callback `{}`", payload["type"], callback_id);
+        return Ok(HttpResponseOk(json!({})));
+    }
+
+    match payload["type"].as_str().unwrap_or_default() {
+        "shortcut" => {
+            if let Err(e) = open_slack_modal(payload["trigger_id"].as_str().unwrap_or_default(), feedback_slack_modal()).await {
+                event!(Level::WARN, "opening the anonymous feedback modal failed: {}", e);
+            }
+        }
+        "view_submission" => {
+            // We do not log who sent the feedback, the user is only used for the limit.
+            let (topic, feedback) = parse_feedback_submission(&payload);
+            if let Err(e) = submit_feedback(db, payload["user"]["id"].as_str().unwrap_or_default(), &topic, &feedback).await {
+                // Show the error under the feedback in the modal, leaving it open.
+                return Ok(HttpResponseOk(json!({
+                    "response_action": "errors",
+                    "errors": { "feedback": e },
+                })));
+            }
+        }
+        t => event!(Level::INFO, "ignoring slack interaction `{}` for anonymous feedback", t),
+    }
+
+    Ok(HttpResponseOk(json!({})))
+}
+
+/**
+ * Listen for requests to book a desk or a room for a day, or to cancel a booking.
+ * Clients send our shared secret in a header and say who the booking is for.
+ */
+#[endpoint {
+    method = POST,
+    path = "/office/bookings",
+}]
+#[instrument]
+#[inline]
+async fn listen_office_bookings(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<String>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // Make sure the request is from one of our clients.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_shared_secret("OFFICE_BOOKINGS_SECRET", &get_header(&headers, OFFICE_BOOKINGS_SECRET_HEADER)) {
+        event!(Level::WARN, "office booking has an invalid secret: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid secret".to_string()));
+    }
+
+    let request: OfficeBookingRequest = serde_json::from_slice(&body).map_err(|e| HttpError::for_bad_request(None, format!("decoding the office booking failed: {}", e)))?;
+    let day = parse_booking_day(&request.day, Utc::now().date().naive_utc()).map_err(|e| HttpError::for_bad_request(None, e))?;
+
+    let configs = get_configs_from_repo(&api_context.github).await;
+    let result = if request.cancel {
+        cancel_office_bookings(db, &request.username, day)
+    } else {
+        book_office(db, &configs, &request.username, day, &request.resource)
+    };
+    match result {
+        Ok(done) => {
+            event!(Level::INFO, "{}", done);
+            Ok(HttpResponseOk(done))
+        }
+        Err(e) => Err(HttpError::for_bad_request(None, e)),
+    }
+}
+
+/**
+ * Listen for visitors checking in at the lobby tablet.
+ * The tablet sends our shared secret in a header, since it is not signed in as anyone.
+ */
+#[endpoint {
+    method = POST,
+    path = "/visitors/check-in",
+}]
+#[instrument]
+#[inline]
+async fn listen_visitors_check_in(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<String>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let db = &api_context.db;
+
+    // Make sure the check in is from the lobby tablet.
+    let (headers, body) = read_request(&rqctx).await?;
+    if let Err(e) = verify_shared_secret("VISITORS_WEBHOOK_SECRET", &get_header(&headers, VISITORS_SECRET_HEADER)) {
+        event!(Level::WARN, "visitor check in has an invalid secret: {}", e);
+        return Err(HttpError::for_bad_request(None, "invalid secret".to_string()));
+    }
+
+    let check_in: VisitorCheckIn = serde_json::from_slice(&body).map_err(|e| HttpError::for_bad_request(None, format!("decoding the visitor check in failed: {}", e)))?;
+
+    // The tablet shows the error to the visitor, so they can fix what they typed.
+    let configs = get_configs_from_repo(&api_context.github).await;
+    match check_in_visitor(db, &configs, &check_in).await {
+        Ok(done) => {
+            event!(Level::INFO, "{}", done);
+            Ok(HttpResponseOk("ok".to_string()))
+        }
+        Err(e) => {
+            event!(Level::WARN, "checking in visitor {} failed: {}", check_in.email, e);
+            Err(HttpError::for_bad_request(None, e))
+        }
+    }
+}
+
 /** Ping endpoint for MailChimp webhooks. */
 #[endpoint {
     method = GET,
@@ -959,9 +1422,16 @@ async fn listen_mailchimp_webhooks(rqctx: Arc<RequestContext>, query_args: Query
         let subscriber = new_subscriber.upsert(db).await;
 
         // Parse the signup into a slack message.
-        // Send the message to the slack channel.
-        post_to_channel(get_public_relations_channel_post_url(), new_subscriber.as_slack_msg()).await;
-        event!(Level::INFO, "subscriber {} posted to Slack", subscriber.email);
+        // Hold the message for the next digest to the public relations channel.
+        notify(
+            db,
+            NotificationPriority::Low,
+            ChatChannel::PublicRelations,
+            "mailing_list.new_subscriber",
+            new_subscriber.as_slack_msg(),
+        )
+        .await;
+        event!(Level::INFO, "subscriber {} queued for the next digest", subscriber.email);
 
         event!(Level::INFO, "subscriber {} created successfully", subscriber.email);
     } else {
@@ -1804,6 +2274,8 @@ async fn handle_rfd_push(api_context: Arc<Context>, event: GitHubWebhook) -> Res
             // Update the PDFs for the RFD.
             rfd.convert_and_upload_pdf(&api_context.github).await;
             rfd.update(db).await;
+            // The references are published to the static site with the nightly sync.
+            refresh_rfd_references(db, &[(rfd.number, rfd.content.to_string())]).await;
             event!(Level::INFO, "updated pdf `{}` for RFD {}", new_rfd.number_string, rfd.get_pdf_filename());
 
             // Check if the RFD state changed from what is currently in the
@@ -1818,7 +2290,8 @@ async fn handle_rfd_push(api_context: Arc<Context>, event: GitHubWebhook) -> Res
                 // First, we need to make sure we don't already have a pull request open.
                 let pulls = github_repo
                     .pulls()
-                    .list(&hubcaps::pulls::PullListOptions::builder().state(hubcaps::issues::State::Open).build())
+                    .iter(&hubcaps::pulls::PullListOptions::builder().state(hubcaps::issues::State::Open).per_page(100).build())
+                    .try_collect::<Vec<hubcaps::pulls::Pull>>()
                     .await
                     .unwrap();
                 // Check if any pull requests are from our branch.
@@ -1895,6 +2368,10 @@ async fn handle_rfd_push(api_context: Arc<Context>, event: GitHubWebhook) -> Res
                 // Keep in mind: this push will kick off another webhook.
                 create_or_update_file_in_github_repo(&github_repo, branch, &file, rfd_mut.content.as_bytes().to_vec()).await;
                 event!(Level::INFO, "updated state to `published` for  RFD {}", new_rfd.number_string);
+
+                emit_rfd_published(&rfd_mut).await;
+            } else if old_rfd_state != rfd.state && rfd.state == "published" {
+                emit_rfd_published(&rfd).await;
             }
 
             // If the title of the RFD changed, delete the old PDF file so it
@@ -1949,6 +2426,25 @@ async fn handle_rfd_push(api_context: Arc<Context>, event: GitHubWebhook) -> Res
     Ok(HttpResponseAccepted("ok".to_string()))
 }
 
+/// Tell the tools that subscribe to our events that an RFD was published.
+#[instrument]
+#[inline]
+async fn emit_rfd_published(rfd: &RFD) {
+    emit_event(
+        "rfd.published",
+        json!({
+            "number": rfd.number,
+            "number_string": rfd.number_string,
+            "title": rfd.title,
+            "name": rfd.name,
+            "link": rfd.link,
+            "short_link": rfd.short_link,
+            "authors": rfd.authors,
+        }),
+    )
+    .await;
+}
+
 /// Handle a `push` event for the configs repo.
 #[instrument(skip(api_context))]
 #[inline]
@@ -2006,7 +2502,7 @@ async fn handle_configs_push(api_context: Arc<Context>, event: GitHubWebhook) ->
 
     // Check if the users.toml file changed.
     if commit.file_changed("configs/users.toml") {
-        sync_users(&api_context.db, &api_context.github, configs.users).await;
+        sync_users(&api_context.db, &api_context.github, configs.users, &configs.approvals).await;
     }
 
     if commit.file_changed("configs/users.toml") || commit.file_changed("configs/groups.toml") {
@@ -2031,6 +2527,18 @@ async fn handle_configs_push(api_context: Arc<Context>, event: GitHubWebhook) ->
         sync_certificates(&api_context.db, &api_context.github, configs.certificates).await;
     }
 
+    // Check if the repos.toml file changed.
+    // Do this after we sync the users and groups since we check the owner teams
+    // have members.
+    if commit.file_changed("configs/repos.toml") {
+        sync_repo_owners(&api_context.db, &api_context.github, configs.repos).await;
+    }
+
+    // Check if the maintenance.toml file changed.
+    if commit.file_changed("configs/maintenance.toml") {
+        sync_maintenance_windows(&api_context.db, &configs.maintenance);
+    }
+
     // Check if the github-outside-collaborators.toml file changed.
     if commit.file_changed("configs/github-outside-collaborators.toml") {
         // Sync github outside collaborators.
@@ -2042,6 +2550,27 @@ async fn handle_configs_push(api_context: Arc<Context>, event: GitHubWebhook) ->
     Ok(HttpResponseAccepted("ok".to_string()))
 }
 
+/// Handle a `pull_request` event for the configs repo. We check the configs at the
+/// head of the pull request before it is merged and post the result on the pull
+/// request.
+#[instrument(skip(api_context))]
+#[inline]
+async fn handle_configs_pull_request(api_context: Arc<Context>, event: GitHubWebhook) -> Result<HttpResponseAccepted<String>, HttpError> {
+    // We only care about new commits on the pull request.
+    if event.action != "opened" && event.action != "synchronize" && event.action != "reopened" {
+        // We can throw this out, log it and return early.
+        event!(Level::INFO, "no automations are set up for action `{}` yet", event.action);
+        return Ok(HttpResponseAccepted("ok".to_string()));
+    }
+
+    let sha = event.pull_request.head.sha.to_string();
+    let check = check_configs(&api_context.db, &api_context.github, &sha).await;
+    post_config_check(&api_context.github, event.pull_request.number.try_into().unwrap(), &sha, &check).await;
+
+    event!(Level::INFO, "checked the configs for pull request {}: {}", event.pull_request.number, check.summary());
+    Ok(HttpResponseAccepted("ok".to_string()))
+}
+
 /// Handle the `repository` event for all repos.
 #[instrument(skip(api_context))]
 #[inline]
@@ -2058,3 +2587,132 @@ async fn handle_repository_event(api_context: Arc<Context>, event: GitHubWebhook
 
     Ok(HttpResponseAccepted("ok".to_string()))
 }
+
+/// The base URL of our SCIM endpoint, for the locations of the resources. Set
+/// `SCIM_BASE_URL` to the public URL, ie. `https://webhooky.oxide.computer/scim/v2`.
+fn scim_base_url() -> String {
+    env::var("SCIM_BASE_URL").unwrap_or_else(|_| "/scim/v2".to_string())
+}
+
+/// Make sure a SCIM request has the bearer token in `SCIM_BEARER_TOKEN`.
+#[instrument]
+#[inline]
+async fn verify_scim_request(rqctx: &Arc<RequestContext>) -> Result<(), HttpError> {
+    let headers = rqctx.request.lock().await.headers().clone();
+    let token = env::var("SCIM_BEARER_TOKEN").unwrap_or_default();
+    if token.is_empty() || !constant_time_eq(get_header(&headers, "Authorization").as_bytes(), format!("Bearer {}", token).as_bytes()) {
+        event!(Level::WARN, "scim request has an invalid bearer token");
+        return Err(HttpError::for_client_error(None, http::StatusCode::UNAUTHORIZED, "invalid bearer token".to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct ScimListParams {
+    #[serde(default)]
+    filter: Option<String>,
+    #[serde(default, rename = "startIndex")]
+    start_index: Option<usize>,
+    #[serde(default)]
+    count: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct ScimPathParams {
+    id: String,
+}
+
+/// Filter and page SCIM resources for a list request.
+#[instrument(skip(resources))]
+#[inline]
+fn scim_list(resources: Vec<serde_json::Value>, params: ScimListParams) -> Result<serde_json::Value, HttpError> {
+    let resources = match params.filter {
+        Some(f) if !f.is_empty() => {
+            let (attribute, value) = parse_scim_filter(&f).map_err(|e| HttpError::for_bad_request(Some("invalidFilter".to_string()), e))?;
+            resources.into_iter().filter(|r| scim_filter_matches(r, &attribute, &value)).collect()
+        }
+        _ => resources,
+    };
+
+    Ok(scim_list_response(resources, params.start_index.unwrap_or(1), params.count.unwrap_or(SCIM_MAX_COUNT)))
+}
+
+/** List the people in our directory for SCIM clients, people who were offboarded are deactivated. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Users",
+}]
+#[instrument(skip(query_args))]
+#[inline]
+async fn list_scim_users(rqctx: Arc<RequestContext>, query_args: Query<ScimListParams>) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    verify_scim_request(&rqctx).await?;
+    let api_context = Context::from_rqctx(&rqctx);
+
+    let users = get_scim_users(&api_context.db, &scim_base_url());
+    Ok(HttpResponseOk(scim_list(users, query_args.into_inner())?))
+}
+
+/** Get a person in our directory for SCIM clients by their username. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Users/{id}",
+}]
+#[instrument(skip(path_params))]
+#[inline]
+async fn get_scim_user(rqctx: Arc<RequestContext>, path_params: Path<ScimPathParams>) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    verify_scim_request(&rqctx).await?;
+    let api_context = Context::from_rqctx(&rqctx);
+
+    let id = path_params.into_inner().id;
+    match get_scim_users(&api_context.db, &scim_base_url()).into_iter().find(|u| u["id"] == id.as_str()) {
+        Some(u) => Ok(HttpResponseOk(u)),
+        None => Err(HttpError::for_not_found(None, format!("user `{}` does not exist", id))),
+    }
+}
+
+/** List the groups in our configs and their members for SCIM clients. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Groups",
+}]
+#[instrument(skip(query_args))]
+#[inline]
+async fn list_scim_groups(rqctx: Arc<RequestContext>, query_args: Query<ScimListParams>) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    verify_scim_request(&rqctx).await?;
+    let api_context = Context::from_rqctx(&rqctx);
+
+    let groups = get_scim_groups(&api_context.db, &scim_base_url());
+    Ok(HttpResponseOk(scim_list(groups, query_args.into_inner())?))
+}
+
+/** Get a group in our configs for SCIM clients by its name. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Groups/{id}",
+}]
+#[instrument(skip(path_params))]
+#[inline]
+async fn get_scim_group(rqctx: Arc<RequestContext>, path_params: Path<ScimPathParams>) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    verify_scim_request(&rqctx).await?;
+    let api_context = Context::from_rqctx(&rqctx);
+
+    let id = path_params.into_inner().id;
+    match get_scim_groups(&api_context.db, &scim_base_url()).into_iter().find(|g| g["id"] == id.as_str()) {
+        Some(g) => Ok(HttpResponseOk(g)),
+        None => Err(HttpError::for_not_found(None, format!("group `{}` does not exist", id))),
+    }
+}
+
+/** Tell SCIM clients what our SCIM endpoint supports. */
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/ServiceProviderConfig",
+}]
+#[instrument]
+#[inline]
+async fn get_scim_service_provider_config(rqctx: Arc<RequestContext>) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    verify_scim_request(&rqctx).await?;
+
+    Ok(HttpResponseOk(scim_service_provider_config()))
+}